@@ -0,0 +1,174 @@
+//! Commlib: AppArgs
+//! 命令行参数，在 service attach 之前解析并写入 G_CONF
+
+use commlib_sys::{GroupId, NodeId, ZoneId};
+
+/// 解析后的命令行参数
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AppArgs {
+    pub config: String,         // 配置文件地址
+    pub node_id: NodeId,        // 启动节点
+    pub log_level: Option<u32>, // 日志等级
+    pub log_path: String,       // 日志目录
+    pub name: String,           // 服务器名称（日志文件名）
+    pub api: String,            // node api 地址
+    pub zone: ZoneId,           // 区服id
+    pub group: GroupId,         // 服务器组（平台）
+    pub version: String,        // 版本号
+    pub job_params: String,     // 测试用例所需的工作参数字符串
+    pub dry_run: bool,          // 只校验配置，不启动 service
+
+    pub defines: hashbrown::HashMap<String, String>, // 应用自定义参数 -D key=value
+}
+
+impl AppArgs {
+    /// 支持的参数列表
+    pub fn command() -> clap::Command {
+        clap::Command::new("myprog")
+            .author("nneessh<nneessh@gmail.com>")
+            .about("app-helper::conf")
+            .arg(clap::arg!(-c --config <FILE> "配置文件地址").value_parser(clap::value_parser!(String)).required(false).default_value(""))
+            .arg(clap::arg!(-n --"node-id" <VALUE> "启动节点").alias("nodeid").value_parser(clap::value_parser!(NodeId)).required(false).default_value("0"))
+            .arg(clap::arg!(-l --"log-level" <LEVEL> "日志等级: critical|error|warn|info|debug|trace 或数字").alias("loglevel").value_parser(parse_log_level).required(false))
+            .arg(clap::arg!(-p --"log-path" <DIR> "日志目录").value_parser(clap::value_parser!(String)).required(false).default_value(""))
+            .arg(clap::arg!(-a --api <VALUE> "node api 地址").value_parser(clap::value_parser!(String)).required(false).default_value(""))
+            .arg(clap::arg!(-s --name <STRING> "服务器名称").alias("servername").value_parser(clap::value_parser!(String)).required(false).default_value(""))
+            .arg(clap::arg!(-z --zone <VALUE> "区服id").value_parser(clap::value_parser!(ZoneId)).required(false).default_value("0"))
+            .arg(clap::arg!(-g --group <VALUE> "服务器组（平台）").value_parser(clap::value_parser!(GroupId)).required(false).default_value("0"))
+            .arg(clap::arg!(-v --version <VALUE> "版本号").value_parser(clap::value_parser!(String)).required(false).default_value(""))
+            .arg(clap::arg!(-j --"job-params" <VALUE> "测试用例所需的工作参数字符串，用引号包围起来").value_parser(clap::value_parser!(String)).required(false).default_value(""))
+            .arg(clap::arg!(--"dry-run" "只校验配置并报告错误，不启动 service").action(clap::ArgAction::SetTrue))
+            .arg(clap::arg!(-D --define <KEY_VALUE> "应用自定义参数 key=value，可重复").value_parser(parse_define).action(clap::ArgAction::Append).required(false))
+    }
+
+    /// 解析命令行参数，未知参数返回带用法说明的错误
+    pub fn try_parse_from(arg_vec: &Vec<std::ffi::OsString>) -> Result<AppArgs, clap::Error> {
+        let matches = Self::command().try_get_matches_from(arg_vec)?;
+
+        let defines = matches
+            .get_many::<(String, String)>("define")
+            .map(|values| values.cloned().collect())
+            .unwrap_or_default();
+
+        Ok(AppArgs {
+            config: matches
+                .get_one::<String>("config")
+                .unwrap()
+                .trim()
+                .to_owned(),
+            node_id: *matches.get_one::<NodeId>("node-id").unwrap(),
+            log_level: matches.get_one::<u32>("log-level").copied(),
+            log_path: matches.get_one::<String>("log-path").unwrap().to_owned(),
+            name: matches.get_one::<String>("name").unwrap().to_owned(),
+            api: matches.get_one::<String>("api").unwrap().to_owned(),
+            zone: *matches.get_one::<ZoneId>("zone").unwrap(),
+            group: *matches.get_one::<GroupId>("group").unwrap(),
+            version: matches.get_one::<String>("version").unwrap().to_owned(),
+            job_params: matches.get_one::<String>("job-params").unwrap().to_owned(),
+            dry_run: matches.get_flag("dry-run"),
+            defines,
+        })
+    }
+
+    /// 解析命令行参数，出错时打印用法说明并退出
+    pub fn parse_from(arg_vec: &Vec<std::ffi::OsString>) -> AppArgs {
+        Self::try_parse_from(arg_vec).unwrap_or_else(|err| err.exit())
+    }
+
+    /// 应用自定义参数
+    pub fn get_define(&self, key: &str) -> Option<&str> {
+        self.defines.get(key).map(|s| s.as_str())
+    }
+
+    /// 应用自定义参数
+    pub fn get_define_typed<T>(&self, key: &str) -> Option<T>
+    where
+        T: std::str::FromStr,
+    {
+        self.get_define(key)?.trim().parse().ok()
+    }
+}
+
+fn parse_log_level(s: &str) -> Result<u32, String> {
+    let level = match s.trim().to_ascii_lowercase().as_str() {
+        "critical" => spdlog::Level::Critical,
+        "error" => spdlog::Level::Error,
+        "warn" => spdlog::Level::Warn,
+        "info" => spdlog::Level::Info,
+        "debug" => spdlog::Level::Debug,
+        "trace" => spdlog::Level::Trace,
+        other => {
+            return other
+                .parse::<u32>()
+                .map_err(|_| format!("invalid log level: {}", s))
+        }
+    };
+    Ok(level as u32)
+}
+
+fn parse_define(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_owned(), value.to_owned()))
+        }
+        _ => Err(format!("expect key=value, got: {}", s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(v: &[&str]) -> Vec<std::ffi::OsString> {
+        v.iter().map(std::ffi::OsString::from).collect()
+    }
+
+    #[test]
+    fn parse_args() {
+        let parsed = AppArgs::try_parse_from(&args(&[
+            "test",
+            "--node-id",
+            "1001",
+            "--config",
+            "res/test.xml",
+            "--log-level",
+            "debug",
+            "--log-path",
+            "logs/test",
+            "--name",
+            "gw",
+            "-D",
+            "remote=127.0.0.1:7001",
+            "--define",
+            "robots=20",
+        ]))
+        .unwrap();
+
+        assert_eq!(parsed.node_id, 1001);
+        assert_eq!(parsed.config, "res/test.xml");
+        assert_eq!(parsed.log_level, Some(spdlog::Level::Debug as u32));
+        assert_eq!(parsed.log_path, "logs/test");
+        assert_eq!(parsed.name, "gw");
+        assert_eq!(parsed.get_define("remote"), Some("127.0.0.1:7001"));
+        assert_eq!(parsed.get_define_typed::<u32>("robots"), Some(20));
+        assert!(!parsed.dry_run);
+
+        // 旧参数名仍然可用
+        let parsed =
+            AppArgs::try_parse_from(&args(&["test", "--nodeid", "7", "--loglevel", "2"])).unwrap();
+        assert_eq!((parsed.node_id, parsed.log_level), (7, Some(2)));
+
+        let parsed = AppArgs::try_parse_from(&args(&["test", "--dry-run"])).unwrap();
+        assert!(parsed.dry_run);
+    }
+
+    #[test]
+    fn unknown_flag_shows_usage() {
+        let err = AppArgs::try_parse_from(&args(&["test", "--node", "1"])).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::UnknownArgument);
+        assert!(err.render().to_string().contains("Usage"));
+
+        assert!(AppArgs::try_parse_from(&args(&["test", "-D", "robots"])).is_err());
+        assert!(AppArgs::try_parse_from(&args(&["test", "--log-level", "loud"])).is_err());
+    }
+}
@@ -1,11 +1,60 @@
+use parking_lot::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
 use commlib_sys::*;
 
 use crate::with_conf_mut;
 
+/// 配置校验函数，返回 Ok(警告) 或 Err(错误)
+pub type ConfigValidatorFn = dyn Fn() -> Result<Vec<String>, Vec<String>> + Send + Sync;
+
 /// App: 应用框架RwLock<
 pub struct App {
     app_name: String,
-    services: Vec<ServiceWrapper>,
+    services: Arc<RwLock<Vec<ServiceWrapper>>>,
+    dependencies: Vec<(u64, u64)>, // (dependent, dependency)
+    stop_timeout: std::time::Duration,
+    main_srv_id: Option<u64>, // init 启动的主 service，关闭时整个 App 退出
+    stop_requested: Arc<AtomicBool>, // AppHandle::stop
+
+    arg_vec: Vec<std::ffi::OsString>,
+    srv_name: String,
+    env_prefix: Option<String>,
+    dry_run: bool, // --dry-run: 只校验配置，不启动 service
+    config_validators: Vec<(String, Box<ConfigValidatorFn>)>,
+}
+
+/// App 句柄：run() 开始之后仍可在其他线程中追加 service
+#[derive(Clone)]
+pub struct AppHandle {
+    app_name: String,
+    services: Arc<RwLock<Vec<ServiceWrapper>>>,
+    stop_requested: Arc<AtomicBool>,
+}
+
+impl AppHandle {
+    /// 启动并追加 service，run() 的退出检查会包含该 service；id 重复时返回 Err 且不启动
+    pub fn attach_late<C, I>(
+        &self,
+        creator: C,
+        initializer: I,
+    ) -> Result<&'static dyn ServiceRs, String>
+    where
+        C: FnOnce() -> &'static dyn ServiceRs,
+        I: FnOnce() + Send + Sync + 'static,
+    {
+        log::info!("App({}) attach late ...", self.app_name);
+        attach_service(&self.services, creator, initializer)
+    }
+
+    /// 通知 run() 按顺序关闭所有 service 后返回，可在信号回调等任意线程中调用
+    pub fn stop(&self) {
+        log::info!("App({}) stop requested ...", self.app_name);
+        let _quit = G_EXIT_CV.0.lock();
+        self.stop_requested.store(true, Ordering::Release);
+        G_EXIT_CV.1.notify_all();
+    }
 }
 
 impl App {
@@ -13,10 +62,26 @@ impl App {
     pub fn new(arg_vec: &Vec<std::ffi::OsString>, app_name: &str) -> Self {
         let mut app = Self {
             app_name: app_name.to_owned(),
-            services: Vec::default(),
+            services: Arc::new(RwLock::new(Vec::default())),
+            dependencies: Vec::default(),
+            stop_timeout: std::time::Duration::from_secs(10),
+            main_srv_id: None,
+            stop_requested: Arc::new(AtomicBool::new(false)),
+
+            arg_vec: arg_vec.clone(),
+            srv_name: app_name.to_owned(),
+            env_prefix: None,
+            dry_run: false,
+            config_validators: Vec::default(),
         };
         app.config(arg_vec, app_name);
 
+        // dry-run 不启动任何 service（包括网络）
+        if app.dry_run {
+            log::info!("App({}) dry-run ...", app.app_name);
+            return app;
+        }
+
         // attach default services -- signal
         app.attach(
             || G_SERVICE_SIGNAL.as_ref(),
@@ -36,28 +101,149 @@ impl App {
         app
     }
 
+    /// 用前缀为 prefix 的环境变量覆盖各节点的 xml 配置（见 conf::apply_env_override）,
+    /// 之后 attach 的 service 在 conf() 之前拿到覆盖后的 xml，通过 with_conf_mut! 初始化的配置随之生效
+    pub fn with_env_override(mut self, prefix: &str) -> Self {
+        self.env_prefix = Some(prefix.to_owned());
+        let lookup = |name: &str| std::env::var(name).ok();
+        with_conf_mut!(crate::G_CONF, cfg_mut, {
+            for (node_id, xml_node) in cfg_mut.local_xml_nodes.iter_mut() {
+                for name in crate::conf::apply_env_override(xml_node, prefix, &lookup) {
+                    log::info!(
+                        "App({}) node {} xml config overridden by env {}",
+                        self.app_name,
+                        node_id,
+                        name
+                    );
+                }
+            }
+        });
+        self
+    }
+
     /// App init
     pub fn init<C, I>(&mut self, creator: C, initializer: I)
     where
         C: FnOnce() -> &'static dyn ServiceRs,
         I: FnOnce() + Send + Sync + 'static,
     {
+        if self.dry_run {
+            log::info!("App({}) dry-run, skip startup", self.app_name);
+            return;
+        }
+
         log::info!("App({}) startup ...", self.app_name);
-        self.attach(creator, initializer);
+        if let Some(srv) = self.attach(creator, initializer) {
+            self.main_srv_id = Some(srv.get_handle().id());
+        }
+    }
+
+    /// 添加配置校验（如配置表的 ConfigTable::validate），validate_config 时按添加顺序执行
+    pub fn add_config_validator<F>(&mut self, name: &str, f: F)
+    where
+        F: Fn() -> Result<Vec<String>, Vec<String>> + Send + Sync + 'static,
+    {
+        self.config_validators.push((name.to_owned(), Box::new(f)));
+    }
+
+    /// 校验所有配置，不启动 service 也不做任何网络操作: 返回 Ok(警告) 或 Err(错误)
+    pub fn validate_config(&self) -> Result<Vec<String>, Vec<String>> {
+        let (warnings, errors) = self.check_config();
+        if errors.is_empty() {
+            Ok(warnings)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// 用于 run() 之后追加 service 的句柄
+    pub fn handle(&self) -> AppHandle {
+        AppHandle {
+            app_name: self.app_name.clone(),
+            services: self.services.clone(),
+            stop_requested: self.stop_requested.clone(),
+        }
+    }
+
+    /// 启动并追加 service，可在 run() 之前使用；run() 之后请使用 AppHandle::attach_late
+    pub fn attach_late<C, I>(
+        &self,
+        creator: C,
+        initializer: I,
+    ) -> Result<&'static dyn ServiceRs, String>
+    where
+        C: FnOnce() -> &'static dyn ServiceRs,
+        I: FnOnce() + Send + Sync + 'static,
+    {
+        self.handle().attach_late(creator, initializer)
+    }
+
+    /// 声明 dependent 依赖 dependency（service id）：关闭时 dependent 先于 dependency 关闭。
+    /// 依赖成环时拒绝该依赖并返回 Err（含成环路径），已声明的依赖不变
+    pub fn declare_dependency(&mut self, dependent: u64, dependency: u64) -> Result<(), String> {
+        if let Some(path) = dependency_path(&self.dependencies, dependency, dependent) {
+            let cycle: Vec<String> = std::iter::once(dependent)
+                .chain(path)
+                .map(|id| id.to_string())
+                .collect();
+            let err = std::format!("dependency cycle: {}", cycle.join(" -> "));
+            log::error!(
+                "App({}) declare_dependency({} -> {}) failed!!! {}",
+                self.app_name,
+                dependent,
+                dependency,
+                err
+            );
+            return Err(err);
+        }
+        if !self.dependencies.contains(&(dependent, dependency)) {
+            self.dependencies.push((dependent, dependency));
+        }
+        Ok(())
+    }
+
+    /// 每个 service 关闭的等待超时时间
+    pub fn set_stop_timeout(&mut self, timeout: std::time::Duration) {
+        self.stop_timeout = timeout;
+    }
+
+    /// 按依赖关系（dependent 先关闭）和 stop_priority（相同则 attach 逆序）依次关闭 service 并等待线程结束,
+    /// 超时的 service 记录日志后跳过，不会阻塞退出
+    pub fn shutdown(&self) {
+        log::info!("App({}) shutdown ...", self.app_name);
+        let srvs = self.service_list();
+        let timed_out = stop_services_with_deps(&srvs, &self.dependencies, self.stop_timeout);
+        if !timed_out.is_empty() {
+            log::error!(
+                "App({}) shutdown with timed out services: {:?}",
+                self.app_name,
+                timed_out
+            );
+        }
     }
 
-    /// App  等待直至服务关闭
+    /// App  等待直至服务关闭; dry-run 时校验配置后退出进程，有错误时退出码为 1.
+    /// AppHandle::stop 或主 service（init 启动）关闭时按顺序关闭其余 service；其他 service 单独关闭不影响运行
     pub fn run(self) {
+        if self.dry_run {
+            self.exit_dry_run();
+        }
+
         let cv = G_EXIT_CV.clone();
         let &(ref lock, ref cvar) = &*cv;
         loop {
             // wait quit signal
             let mut quit = lock.lock();
-            cvar.wait(&mut quit);
+            if !self.stop_requested.load(Ordering::Acquire) {
+                cvar.wait(&mut quit);
+            }
 
+            // 每次唤醒重新获取 service 列表，包含 run() 之后追加的 service
+            let srvs = self.service_list();
             let mut exitflag = true;
-            for w in &self.services {
-                let w_srv_handle = w.srv.get_handle();
+            let mut closing = self.stop_requested.load(Ordering::Acquire);
+            for srv in &srvs {
+                let w_srv_handle = srv.get_handle();
                 log::info!(
                     "App:run() wait close .. App={} ID={} state={:?}",
                     self.app_name,
@@ -66,80 +252,447 @@ impl App {
                 );
                 if NodeState::Closed as u32 != w_srv_handle.state() as u32 {
                     exitflag = false;
-                    break;
+                } else if Some(w_srv_handle.id()) == self.main_srv_id {
+                    closing = true;
                 }
             }
 
             if exitflag {
-                for w in &self.services {
-                    w.srv.join();
+                for srv in &srvs {
+                    srv.join();
                 }
                 break;
+            } else if closing {
+                // 请求退出或主 service 退出：先等待 Draining 的 service 执行完剩余任务，再按顺序关闭其余 service
+                drop(quit);
+                self.wait_draining();
+                self.shutdown();
+                break;
             }
         }
     }
 
-    fn config(&mut self, arg_vec: &Vec<std::ffi::OsString>, srv_name: &str) {
-        // init G_CONF
-        with_conf_mut!(crate::G_CONF, cfg_mut, {
-            cfg_mut.init(&arg_vec, srv_name);
-        });
+    // 返回 (警告, 错误)，每条以来源为前缀
+    fn check_config(&self) -> (Vec<String>, Vec<String>) {
+        let mut warnings = Vec::new();
+        let mut errors = Vec::new();
 
-        // init logger
-        let log_path = std::path::PathBuf::from("auto-legend");
-        init_logger(&log_path, "testlog", spdlog::Level::Info as u16, true);
+        // 在独立的 Conf 上重新初始化，不影响 G_CONF
+        let mut conf = crate::conf::Conf::new();
+        match conf.try_init(&self.arg_vec, &self.srv_name) {
+            Ok(()) => {
+                if let Some(prefix) = &self.env_prefix {
+                    let lookup = |name: &str| std::env::var(name).ok();
+                    for xml_node in conf.local_xml_nodes.values_mut() {
+                        crate::conf::apply_env_override(xml_node, prefix, &lookup);
+                    }
+                }
+                warnings.extend(
+                    conf.check_xml_nodes()
+                        .into_iter()
+                        .map(|w| std::format!("conf: {}", w)),
+                );
+            }
+            Err(err) => errors.push(std::format!("conf: {}", err)),
+        }
+
+        for (name, validator) in &self.config_validators {
+            match validator() {
+                Ok(w) => warnings.extend(w.into_iter().map(|w| std::format!("{}: {}", name, w))),
+                Err(e) => errors.extend(e.into_iter().map(|e| std::format!("{}: {}", name, e))),
+            }
+        }
+        (warnings, errors)
     }
 
-    fn add_service(services: &mut Vec<ServiceWrapper>, srv: &'static dyn ServiceRs) {
-        //
-        let id = srv.get_handle().id();
-        let name = srv.name();
-
-        // 是否已经存在相同 id 的 service ?
-        for w in &*services {
-            let w_srv_handle = w.srv.get_handle();
-            if w_srv_handle.id() == id {
-                log::error!("App::add_service({}) failed!!! ID={}", name, id);
-                return;
+    fn exit_dry_run(&self) -> ! {
+        let (warnings, errors) = self.check_config();
+        println!(
+            "App({}) dry-run: {} warning(s), {} error(s)",
+            self.app_name,
+            warnings.len(),
+            errors.len()
+        );
+        for w in &warnings {
+            println!("warning: {}", w);
+        }
+        for e in &errors {
+            println!("error: {}", e);
+        }
+        log::info!(
+            "App({}) dry-run over, warnings: {:?}, errors: {:?}",
+            self.app_name,
+            warnings,
+            errors
+        );
+        std::process::exit(if errors.is_empty() { 0 } else { 1 });
+    }
+
+    fn service_list(&self) -> Vec<&'static dyn ServiceRs> {
+        self.services.read().iter().map(|w| w.srv).collect()
+    }
+
+    fn wait_draining(&self) {
+        for srv in self.service_list() {
+            let w_srv_handle = srv.get_handle();
+            if NodeState::Draining != w_srv_handle.state() {
+                continue;
+            }
+
+            log::info!(
+                "App:run() wait draining .. App={} ID={}",
+                self.app_name,
+                w_srv_handle.id()
+            );
+            if !w_srv_handle.wait_closed(self.stop_timeout) {
+                log::error!(
+                    "App:run() wait draining timeout!!! App={} ID={} state={:?}",
+                    self.app_name,
+                    w_srv_handle.id(),
+                    w_srv_handle.state()
+                );
+            }
+        }
+    }
+
+    fn config(&mut self, arg_vec: &Vec<std::ffi::OsString>, srv_name: &str) {
+        // 只解析一次：参数错误或 --help 时由 clap 打印用法说明并退出
+        let args = crate::AppArgs::try_parse_from(arg_vec).unwrap_or_else(|err| err.exit());
+        self.dry_run = args.dry_run;
+
+        // init G_CONF: --dry-run 时配置错误由 validate_config 报告
+        let ret = with_conf_mut!(crate::G_CONF, cfg_mut, {
+            cfg_mut.try_init_with_args(args, srv_name)
+        });
+        if let Err(err) = ret {
+            if !self.dry_run {
+                eprintln!("App({}) config failed!!! error: {}", srv_name, err);
+                std::process::exit(1);
             }
         }
 
-        //
-        services.push(ServiceWrapper { srv });
-        log::info!("App::add_service({}) ok, ID={}", name, id);
+        // init logger: 命令行参数优先
+        let (log_path, log_name, log_level) = crate::with_conf!(crate::G_CONF, cfg, {
+            let log_path = if cfg.args.log_path.is_empty() {
+                std::path::PathBuf::from("auto-legend")
+            } else {
+                cfg.log.path.clone()
+            };
+            let log_level = cfg.args.log_level.unwrap_or(spdlog::Level::Info as u32);
+            (log_path, cfg.appname.clone(), log_level)
+        });
+        init_logger(&log_path, &log_name, log_level as u16, true);
+
+        // --name 覆盖 app name
+        self.app_name = log_name;
     }
 
-    fn attach<C, I>(&mut self, creator: C, initializer: I) -> &'static dyn ServiceRs
+    fn attach<C, I>(&mut self, creator: C, initializer: I) -> Option<&'static dyn ServiceRs>
     where
         C: FnOnce() -> &'static dyn ServiceRs,
         I: FnOnce() + Send + Sync + 'static,
     {
-        let srv = creator();
-
-        // attach xml node to custom service
-        crate::with_conf!(crate::G_CONF, cfg, {
-            let node_id = cfg.node_id;
-            if let Some(xml_node) = cfg.get_xml_node(node_id) {
-                let srv_type = xml_node.get_u64(vec!["srv"], 0);
+        match attach_service(&self.services, creator, initializer) {
+            Ok(srv) => Some(srv),
+            Err(err) => {
+                log::error!("App({}) attach failed!!! error: {}", self.app_name, err);
+                None
+            }
+        }
+    }
+}
 
-                // set xml config
-                srv.get_handle().set_xml_config(xml_node.clone());
-            } else {
-                log::error!("node {} xml config not found!!!", node_id);
+/// 沿依赖关系从 from 到 to 的路径（含两端），不存在时返回 None
+fn dependency_path(deps: &[(u64, u64)], from: u64, to: u64) -> Option<Vec<u64>> {
+    if from == to {
+        return Some(vec![from]);
+    }
+    let mut visited = vec![from];
+    let mut stack = vec![vec![from]];
+    while let Some(path) = stack.pop() {
+        let last = *path.last().unwrap();
+        for (dependent, dependency) in deps {
+            if *dependent != last || visited.contains(dependency) {
+                continue;
             }
-        });
+            let mut next = path.clone();
+            next.push(*dependency);
+            if *dependency == to {
+                return Some(next);
+            }
+            visited.push(*dependency);
+            stack.push(next);
+        }
+    }
+    None
+}
+
+/// 登记 service id（检查重复），然后启动 service 线程
+fn attach_service<C, I>(
+    services: &RwLock<Vec<ServiceWrapper>>,
+    creator: C,
+    initializer: I,
+) -> Result<&'static dyn ServiceRs, String>
+where
+    C: FnOnce() -> &'static dyn ServiceRs,
+    I: FnOnce() + Send + Sync + 'static,
+{
+    let srv = creator();
+    add_service(services, srv)?;
+
+    // attach xml node to custom service: 节点不存在时由 service 在 conf()/init 中报错
+    crate::with_conf!(crate::G_CONF, cfg, {
+        let node_id = cfg.node_id;
+        let xml_node = cfg.get_xml_node(node_id).cloned();
+        if xml_node.is_none() {
+            log::error!("node {} xml config not found!!!", node_id);
+        }
+        srv.get_handle().set_xml_config(node_id, xml_node);
+    });
+
+    //
+    srv.conf();
+
+    //
+    let ready_pair = start_service(srv, srv.name(), initializer);
+    if !proc_service_ready(srv, ready_pair) {
+        // 启动失败，撤销登记
+        let id = srv.get_handle().id();
+        services.write().retain(|w| w.srv.get_handle().id() != id);
+        return Err(std::format!(
+            "start service({}) failed!!! ID={}",
+            srv.name(),
+            id
+        ));
+    }
+
+    //
+    Ok(srv)
+}
+
+fn add_service(
+    services: &RwLock<Vec<ServiceWrapper>>,
+    srv: &'static dyn ServiceRs,
+) -> Result<(), String> {
+    //
+    let id = srv.get_handle().id();
+    let name = srv.name();
+
+    // 是否已经存在相同 id 的 service ? 检查和登记在同一个写锁内完成
+    let mut services_mut = services.write();
+    for w in &*services_mut {
+        let w_srv_handle = w.srv.get_handle();
+        if w_srv_handle.id() == id {
+            log::error!("App::add_service({}) failed!!! ID={}", name, id);
+            return Err(std::format!("service({}) ID={} already exists", name, id));
+        }
+    }
+
+    //
+    services_mut.push(ServiceWrapper { srv });
+    log::info!("App::add_service({}) ok, ID={}", name, id);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    struct TestService {
+        name: String,
+        handle: ServiceHandle,
+    }
+
+    impl ServiceRs for TestService {
+        fn name(&self) -> &str {
+            &self.name
+        }
 
-        //
-        srv.conf();
+        fn get_handle(&self) -> &ServiceHandle {
+            &self.handle
+        }
+
+        fn conf(&self) {}
+
+        fn run_in_service(&self, cb: Box<dyn FnOnce() + Send + Sync>) {
+            self.handle.run_in_service(cb);
+        }
+
+        fn is_in_service_thread(&self) -> bool {
+            self.handle.is_in_service_thread()
+        }
+
+        fn join(&self) {
+            self.handle.join_service();
+        }
+    }
+
+    fn leak(id: u64, name: &str) -> &'static dyn ServiceRs {
+        Box::leak(Box::new(TestService {
+            name: name.to_owned(),
+            handle: ServiceHandle::new(id, NodeState::Idle),
+        }))
+    }
 
-        //
-        let ready_pair = start_service(srv, srv.name(), initializer);
-        proc_service_ready(srv, ready_pair);
+    #[test]
+    fn attach_late_after_run() {
+        let mut app = bare_app();
+        let first = leak(9001, "first");
+        app.attach(move || first, || {});
+        let handle = app.handle();
+        let runner = std::thread::spawn(move || app.run());
 
-        // add server to app
-        Self::add_service(&mut self.services, srv);
+        // 在其他线程中追加 service
+        let second = leak(9002, "second");
+        let handle2 = handle.clone();
+        std::thread::spawn(move || handle2.attach_late(move || second, || {}))
+            .join()
+            .unwrap()
+            .unwrap();
 
-        //
-        srv
+        // 重复 id 被拒绝，且不会启动
+        let dup = leak(9002, "dup");
+        assert!(handle.attach_late(move || dup, || {}).is_err());
+        assert_eq!(dup.get_handle().tid(), 0);
+
+        // 通知退出直至 run() 返回
+        first.get_handle().quit_service();
+        second.get_handle().quit_service();
+        wait_run_exit(runner);
+
+        for srv in [first, second] {
+            assert_eq!(srv.get_handle().state(), NodeState::Closed);
+            assert!(srv.get_handle().join_handle_opt.read().is_none());
+        }
+    }
+
+    // 通知退出直至 run() 返回
+    fn wait_run_exit(runner: std::thread::JoinHandle<()>) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !runner.is_finished() {
+            assert!(Instant::now() < deadline, "App::run() did not exit");
+            G_EXIT_CV.1.notify_all();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        runner.join().unwrap();
+    }
+
+    #[test]
+    fn shutdown_only_when_main_service_closes() {
+        let mut app = bare_app();
+        let side = leak(9011, "side");
+        let main = leak(9012, "main");
+        app.attach(move || side, || {});
+        app.init(move || main, || {});
+        let runner = std::thread::spawn(move || app.run());
+
+        // 其他 service 单独关闭，App 继续运行
+        side.get_handle().quit_service();
+        for _ in 0..10 {
+            G_EXIT_CV.1.notify_all();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(!runner.is_finished());
+        assert_eq!(main.get_handle().state(), NodeState::Run);
+
+        // 主 service 关闭，App 退出
+        main.get_handle().quit_service();
+        wait_run_exit(runner);
+        assert_eq!(main.get_handle().state(), NodeState::Closed);
+    }
+
+    #[test]
+    fn stop_shuts_down_running_services() {
+        let mut app = bare_app();
+        let main = leak(9021, "main");
+        let other = leak(9022, "other");
+        app.init(move || main, || {});
+        app.attach(move || other, || {});
+        let handle = app.handle();
+        let runner = std::thread::spawn(move || app.run());
+
+        handle.stop();
+        wait_run_exit(runner);
+        for srv in [main, other] {
+            assert_eq!(srv.get_handle().state(), NodeState::Closed);
+        }
+    }
+
+    fn bare_app() -> App {
+        App {
+            app_name: "test".to_owned(),
+            services: Arc::new(RwLock::new(Vec::new())),
+            dependencies: Vec::new(),
+            stop_timeout: Duration::from_secs(1),
+            main_srv_id: None,
+            stop_requested: Arc::new(AtomicBool::new(false)),
+
+            arg_vec: Vec::new(),
+            srv_name: "test".to_owned(),
+            env_prefix: None,
+            dry_run: false,
+            config_validators: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn validate_config() {
+        // 配置文件不存在
+        let mut app = bare_app();
+        app.arg_vec = ["test", "--dry-run", "--config", "missing/dragon.xml"]
+            .iter()
+            .map(std::ffi::OsString::from)
+            .collect();
+        app.add_config_validator("role", || Ok(vec!["role 3 without skill".to_owned()]));
+        app.add_config_validator("item", || Err(vec!["item 7: count < 0".to_owned()]));
+
+        let errors = app.validate_config().unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(
+            errors[0].starts_with("conf: read config file"),
+            "{}",
+            errors[0]
+        );
+        assert_eq!(errors[1], "item: item 7: count < 0");
+
+        // 只有警告
+        let etcfile = std::env::temp_dir().join(format!("app_dry_run_{}.xml", std::process::id()));
+        std::fs::write(&etcfile, r#"<config><node id="999" name="test"/></config>"#).unwrap();
+        app.arg_vec = ["test", "--config", etcfile.to_str().unwrap()]
+            .iter()
+            .map(std::ffi::OsString::from)
+            .collect();
+        app.config_validators.pop();
+        let warnings = app.validate_config().unwrap();
+        assert!(warnings.contains(&"role: role 3 without skill".to_owned()));
+        assert!(warnings.contains(&"conf: zone not set".to_owned()));
+        std::fs::remove_file(&etcfile).unwrap();
+    }
+
+    #[test]
+    fn declare_dependency_accepts_dag() {
+        let mut app = bare_app();
+        app.declare_dependency(3, 1).unwrap();
+        app.declare_dependency(2, 3).unwrap();
+        app.declare_dependency(2, 1).unwrap();
+        app.declare_dependency(2, 1).unwrap();
+        assert_eq!(app.dependencies, vec![(3, 1), (2, 3), (2, 1)]);
+    }
+
+    #[test]
+    fn declare_dependency_rejects_cycle() {
+        let mut app = bare_app();
+        app.declare_dependency(3, 1).unwrap();
+        app.declare_dependency(2, 3).unwrap();
+        assert_eq!(
+            app.declare_dependency(1, 2),
+            Err("dependency cycle: 1 -> 2 -> 3 -> 1".to_owned())
+        );
+        assert_eq!(
+            app.declare_dependency(1, 1),
+            Err("dependency cycle: 1 -> 1".to_owned())
+        );
+        assert_eq!(app.dependencies, vec![(3, 1), (2, 3)]);
     }
 }
@@ -1,32 +1,60 @@
 //! Commlib: Conf
 use commlib_sys::*;
 
+use crate::AppArgs;
+
 pub const TEST_NODE: NodeId = 999;
 
-/// use thread local unsafe cell for conf -- mut
+/// 修改全局配置 SharedConf<T>，兼容旧的 thread local 写法.
+/// 与旧写法的 borrow_mut 一样，body 中再次 with_conf_mut! 同一个配置会 panic（SharedConf::update 返回 ConfError::Reentrant）；
+/// body 中 with_conf! 读到的是修改前的配置
 #[macro_export]
 macro_rules! with_conf_mut {
     ($t:path, $c:ident, $body:block) => {
-        $t.with(|v| {
-            paste::paste! {
-                let $c = unsafe { &mut *v.get() };
-                $body
-            }
-        })
+        match $t.update(|$c| $body) {
+            Ok(r) => r,
+            Err(err) => std::panic!("with_conf_mut!({}) failed: {}", stringify!($t), err),
+        }
     };
 }
 
-/// use thread local unsafe cell for conf
+/// 读取全局配置 SharedConf<T>，兼容旧的 thread local 写法
 #[macro_export]
 macro_rules! with_conf {
-    ($t:path, $c:ident, $body:block) => {
-        $t.with(|v| {
-            paste::paste! {
-                let $c = unsafe { &*v.get() };
-                $body
+    ($t:path, $c:ident, $body:block) => {{
+        let guard = $t.get();
+        let $c = &**guard;
+        $body
+    }};
+}
+
+/// 用环境变量覆盖 xml 配置: 变量名为 {PREFIX}_{PATH}, PATH 为节点路径以 '_' 连接后大写（键名中的 '.' 换成 '_'）,
+/// 如 CLISRV_REMOTE_PORT 覆盖 <remote port="..."/>; 同名子节点只覆盖第一个. 返回生效的变量名
+pub fn apply_env_override<F>(xml: &mut XmlReader, prefix: &str, lookup: &F) -> Vec<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    fn walk<F>(node: &mut XmlReader, name: &str, lookup: &F, applied: &mut Vec<String>)
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        let keys: Vec<String> = node.child_keys().cloned().collect();
+        for key in keys {
+            let env_name = std::format!("{}_{}", name, key.replace('.', "_").to_uppercase());
+            if let Some(child) = node.get_child_mut(vec![key.as_str()]) {
+                if let Some(value) = lookup(&env_name) {
+                    child.value = value;
+                    applied.push(env_name.clone());
+                }
+                walk(child, &env_name, lookup, applied);
             }
-        })
-    };
+        }
+    }
+
+    let mut applied = Vec::new();
+    walk(xml, &prefix.to_uppercase(), lookup, &mut applied);
+    applied.sort();
+    applied
 }
 
 /// 获取当前执行环境，正式环境目录结构
@@ -49,15 +77,17 @@ pub fn get_run_env() -> Result<String, String> {
 }
 
 #[allow(dead_code)]
+#[derive(Clone)]
 pub struct Log {
-    level: u32,                 // log级别
-    path: std::path::PathBuf,   // 日志路径
-    bipath: std::path::PathBuf, // BI日志路径
-    console: bool,              // 是否输出到控制台
-    async_queue: u32,           // 异步队列长度
+    pub level: u32,                 // log级别
+    pub path: std::path::PathBuf,   // 日志路径
+    pub bipath: std::path::PathBuf, // BI日志路径
+    pub console: bool,              // 是否输出到控制台
+    pub async_queue: u32,           // 异步队列长度
 }
 
 #[allow(dead_code)]
+#[derive(Clone)]
 pub struct WebUrl {
     pub api_addr: String,
     pub player_id_addr: String, // 用来获取新玩家 pid
@@ -86,8 +116,10 @@ pub struct WebUrl {
 }
 
 #[allow(dead_code)]
+#[derive(Clone)]
 pub struct Conf {
     pub job_params_: String, // 测试用例所需的工作参数字符串，用引号包围起来
+    pub args: AppArgs,       // 命令行参数
 
     pub appname: String,
     pub etcfile: std::ffi::OsString, // 配置文件名称
@@ -118,6 +150,7 @@ impl Conf {
     pub fn new() -> Conf {
         Conf {
             job_params_: "".to_owned(),
+            args: AppArgs::default(),
 
             appname: "server".to_owned(),
             etcfile: std::ffi::OsString::default(),
@@ -171,8 +204,27 @@ impl Conf {
         }
     }
 
-    ///
+    /// 初始化，配置错误时 panic
     pub fn init(&mut self, arg_vec: &Vec<std::ffi::OsString>, srv_name: &str) {
+        if let Err(err) = self.try_init(arg_vec, srv_name) {
+            // commlib exit
+            std::panic!("{}", err);
+        }
+    }
+
+    /// 初始化，配置错误时返回 Err（不 panic）
+    pub fn try_init(
+        &mut self,
+        arg_vec: &Vec<std::ffi::OsString>,
+        srv_name: &str,
+    ) -> Result<(), String> {
+        // 解析命令行参数
+        let args = AppArgs::try_parse_from(arg_vec).map_err(|err| err.to_string())?;
+        self.try_init_with_args(args, srv_name)
+    }
+
+    /// 使用已解析的命令行参数初始化，配置错误时返回 Err（不 panic）
+    pub fn try_init_with_args(&mut self, args: AppArgs, srv_name: &str) -> Result<(), String> {
         // 读取一下当前的执行环境
         let env_r = get_run_env();
         match env_r {
@@ -180,20 +232,8 @@ impl Conf {
             Err(_err) => {}
         }
 
-        // 解析命令行参数
-        let matches = clap::Command::new("myprog")
-            .author("nneessh<nneessh@gmail.com>")
-            .about("app-helper::conf")
-            .arg(clap::arg!(-c --config <FILE> "配置文件地址").value_parser(clap::value_parser!(String)).required(false).default_value(""))
-            .arg(clap::arg!(-n --nodeid <VALUE> "启动节点").value_parser(clap::value_parser!(NodeId)).required(false).default_value("0"))
-            .arg(clap::arg!(-l --loglevel <VALUE> "日志等级").value_parser(clap::value_parser!(u32)).required(false).default_value("0"))
-            .arg(clap::arg!(-a --api <VALUE> "node api 地址").value_parser(clap::value_parser!(String)).required(false).default_value(""))
-            .arg(clap::arg!(-s --servername <STRING> "服务器名称").value_parser(clap::value_parser!(String)).required(false).default_value(""))
-            .arg(clap::arg!(-z --zone <VALUE> "区服id").value_parser(clap::value_parser!(ZoneId)).required(false).default_value("0"))
-            .arg(clap::arg!(-g --group <VALUE> "服务器组（平台）").value_parser(clap::value_parser!(GroupId)).required(false).default_value("0"))
-            .arg(clap::arg!(-v --version <VALUE> "版本号").value_parser(clap::value_parser!(String)).required(false).default_value(""))
-            .arg(clap::arg!(-j --"job-params" <VALUE> "测试用例所需的工作参数字符串，用引号包围起来").value_parser(clap::value_parser!(String)).required(false).default_value(""))
-            .get_matches_from(arg_vec);
+        self.args = args;
+        let args = self.args.clone();
 
         // 启动目录
         self.workdir = std::env::current_dir().unwrap();
@@ -202,11 +242,9 @@ impl Conf {
         self.command = std::env::current_exe().unwrap();
 
         // 配置文件位置，先从参数获取，再从默认位置
-        let etcfile = matches.get_one::<String>("config").unwrap().to_owned();
-
         //self.etcfile
-        if !etcfile.is_empty() {
-            self.etcfile = std::ffi::OsString::from(etcfile.trim());
+        if !args.config.is_empty() {
+            self.etcfile = std::ffi::OsString::from(&args.config);
         } else {
             const ETCFILE_DEFAULT: &str = "res/dragon.xml";
             const DRAGON_XML_CFG_ENV: &str = "DRAGON_XML_CFG";
@@ -224,40 +262,41 @@ impl Conf {
         }
 
         //
-        self.job_params_ = matches.get_one::<String>("job-params").unwrap().to_owned();
+        self.job_params_ = args.job_params.clone();
 
         //
-        let loglevel = matches.get_one::<u32>("loglevel").unwrap();
-        if *loglevel > 0 {
-            self.log.level = *loglevel;
+        if let Some(loglevel) = args.log_level {
+            self.log.level = loglevel;
+        }
+        if !args.log_path.is_empty() {
+            self.log.path = std::path::PathBuf::from(&args.log_path);
         }
 
         //
-        self.url.api_addr = matches.get_one::<String>("api").unwrap().to_owned();
+        self.url.api_addr = args.api.clone();
 
         //
-        self.node_id = *matches.get_one::<NodeId>("nodeid").unwrap();
-        self.zone_id = *matches.get_one::<ZoneId>("zone").unwrap();
-        self.group_id = *matches.get_one::<GroupId>("group").unwrap();
+        self.node_id = args.node_id;
+        self.zone_id = args.zone;
+        self.group_id = args.group;
 
         // 设置 appname (等价于设置 log file name)
-        let server_name = matches.get_one::<String>("servername").unwrap();
-        if !server_name.is_empty() {
-            self.appname = server_name.to_owned();
+        if !args.name.is_empty() {
+            self.appname = args.name.clone();
         } else {
             self.appname = srv_name.to_owned();
         }
 
         //
-        let ver = matches.get_one::<String>("version").unwrap();
-        if !ver.is_empty() {
-            self.version = ver.to_owned();
+        if !args.version.is_empty() {
+            self.version = args.version.clone();
         }
 
         // 从 etcfile(xml 格式) 中读取配置信息
         if !self.etcfile.is_empty() {
-            let config_xml = XmlReader::read_file(std::path::Path::new(&self.etcfile)).unwrap();
-            self.read_config_from_xml(&config_xml, srv_name);
+            let config_xml = XmlReader::read_file(std::path::Path::new(&self.etcfile))
+                .map_err(|err| format!("read config file({:?}) error: {}", self.etcfile, err))?;
+            self.read_config_from_xml(&config_xml, srv_name)?;
         }
 
         // node_id must match xml_node
@@ -276,19 +315,40 @@ impl Conf {
 
                 // can't find xml node
                 if !found {
-                    // commlib exit
-                    std::panic!("node xml error");
-                    //std::process::exit(0);
+                    return Err("node xml error".to_owned());
                 }
             }
         } else {
-            // commlib exit
-            std::panic!("null node");
-            //std::process::exit(0);
+            return Err("null node".to_owned());
         }
 
         // 保证 includes 包含自己
         self.cross_zones.insert(self.zone_id);
+        Ok(())
+    }
+
+    /// 检查初始化后的配置，返回警告（不影响启动）
+    pub fn check_xml_nodes(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.zone_id == 0 {
+            warnings.push("zone not set".to_owned());
+        }
+        if self.version.is_empty() {
+            warnings.push("version not set".to_owned());
+        }
+
+        let mut node_ids: Vec<&NodeId> = self.local_xml_nodes.keys().collect();
+        node_ids.sort();
+        for node_id in node_ids {
+            let xml_node = &self.local_xml_nodes[node_id];
+            if *node_id == 0 {
+                warnings.push("node without id".to_owned());
+            }
+            if xml_node.get_string(vec!["name"], "").is_empty() {
+                warnings.push(std::format!("node {} without name", node_id));
+            }
+        }
+        warnings
     }
 
     ///
@@ -311,7 +371,11 @@ impl Conf {
     }
 
     ///
-    fn read_config_from_xml(&mut self, config_xml: &XmlReader, srv_name: &str) {
+    fn read_config_from_xml(
+        &mut self,
+        config_xml: &XmlReader,
+        srv_name: &str,
+    ) -> Result<(), String> {
         // use command line first
         if self.zone_id == 0 {
             self.zone_id = config_xml.get::<ZoneId>(vec!["zone"], 0);
@@ -343,8 +407,7 @@ impl Conf {
         if srv_name.is_empty() {
             if TEST_NODE != self.node_id {
                 // TEST NODE
-                std::panic!("TEST NODE ID must be {}", TEST_NODE);
-                //std::process::exit(0);
+                return Err(std::format!("TEST NODE ID must be {}", TEST_NODE));
             }
         } else {
             // node id must match xml_node
@@ -361,5 +424,116 @@ impl Conf {
                 }
             }
         }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_override() {
+        let mut xml = XmlReader::read_content(
+            r#"<node id="1" name="clisrv" port="7000">
+                <remote name="gate" addr="127.0.0.1" port="8000"/>
+                <remote name="db" addr="127.0.0.2" port="8001"/>
+                <log><path>log</path></log>
+            </node>"#,
+        )
+        .unwrap();
+
+        let env: hashbrown::HashMap<&str, &str> = [
+            ("CLISRV_REMOTE_PORT", "9090"),
+            ("CLISRV_LOG_PATH", "/var/log/cli"),
+            ("CLISRV_PORT", "7100"),
+            ("OTHER_PORT", "1"),
+        ]
+        .into_iter()
+        .collect();
+        let lookup = |name: &str| env.get(name).map(|v| v.to_string());
+
+        let applied = apply_env_override(&mut xml, "clisrv", &lookup);
+        assert_eq!(
+            applied,
+            vec!["CLISRV_LOG_PATH", "CLISRV_PORT", "CLISRV_REMOTE_PORT"]
+        );
+        assert_eq!(xml.get_u64(vec!["port"], 0), 7100);
+        assert_eq!(xml.get_string(vec!["log", "path"], ""), "/var/log/cli");
+
+        // 同名子节点只覆盖第一个
+        let remotes = xml.get_children(vec!["remote"]).unwrap();
+        assert_eq!(remotes[0].get_u64(vec!["port"], 0), 9090);
+        assert_eq!(remotes[1].get_u64(vec!["port"], 0), 8001);
+        assert_eq!(xml.get_string(vec!["id"], ""), "1");
+    }
+
+    lazy_static::lazy_static! {
+        static ref G_TEST_PORT: crate::SharedConf<u16> = crate::SharedConf::new(7000);
+    }
+
+    #[test]
+    #[should_panic(expected = "conf update is not reentrant")]
+    fn nested_with_conf_mut_panics() {
+        with_conf_mut!(G_TEST_PORT, port, {
+            // 修改在 body 返回后才可见
+            *port = 7001;
+            with_conf!(G_TEST_PORT, old, { assert_eq!(*old, 7000) });
+            with_conf_mut!(G_TEST_PORT, port2, { *port2 = 7002 });
+        });
+    }
+
+    #[test]
+    fn try_init_reports_errors() {
+        let dir = std::env::temp_dir().join(format!("app_conf_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let etcfile = dir.join("dragon.xml");
+        std::fs::write(
+            &etcfile,
+            r#"<config zone="1" group="1">
+                <node id="1000" name="clisrv"><port>20001</port></node>
+                <node id="1001"><port>20002</port></node>
+            </config>"#,
+        )
+        .unwrap();
+        let args = |v: &[&str]| -> Vec<std::ffi::OsString> {
+            v.iter().map(std::ffi::OsString::from).collect()
+        };
+
+        let mut conf = Conf::new();
+        let ret = conf.try_init(
+            &args(&["test", "--config", etcfile.to_str().unwrap()]),
+            "clisrv",
+        );
+        assert_eq!(ret, Ok(()));
+        assert_eq!(conf.node_id, 1000);
+        assert_eq!(
+            conf.check_xml_nodes(),
+            vec!["version not set", "node 1001 without name"]
+        );
+
+        // 节点不存在
+        let mut conf = Conf::new();
+        let ret = conf.try_init(
+            &args(&["test", "--config", etcfile.to_str().unwrap(), "-n", "7"]),
+            "clisrv",
+        );
+        assert_eq!(ret, Err("node xml error".to_owned()));
+
+        // 配置文件不存在
+        let mut conf = Conf::new();
+        let missing = dir.join("missing.xml");
+        let ret = conf.try_init(
+            &args(&["test", "--config", missing.to_str().unwrap()]),
+            "clisrv",
+        );
+        assert!(ret.unwrap_err().contains("missing.xml"));
+
+        // 未知参数返回错误，不退出进程
+        let mut conf = Conf::new();
+        let ret = conf.try_init(&args(&["test", "--no-such-arg"]), "clisrv");
+        assert!(ret.unwrap_err().contains("--no-such-arg"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -1,6 +1,5 @@
 use parking_lot::Mutex;
 
-use std::cell::UnsafeCell;
 use std::sync::atomic::AtomicBool;
 
 #[allow(dead_code)]
@@ -8,6 +7,6 @@ static INIT: AtomicBool = AtomicBool::new(false);
 #[allow(dead_code)]
 static INIT_LOCK: Mutex<()> = Mutex::new(());
 
-thread_local! {
-    pub static G_CONF: UnsafeCell<crate::conf::Conf> = UnsafeCell::new(crate::conf::Conf::new());
+lazy_static::lazy_static! {
+    pub static ref G_CONF: crate::SharedConf<crate::conf::Conf> = crate::SharedConf::new(crate::conf::Conf::new());
 }
@@ -1,5 +1,9 @@
 //! AppHelper: app, conf
 
+///
+pub mod app_args;
+pub use app_args::*;
+
 ///
 pub mod conf;
 
@@ -7,6 +11,10 @@ pub mod conf;
 pub mod globals;
 pub use globals::*;
 
+///
+pub mod shared_conf;
+pub use shared_conf::*;
+
 ///
 pub mod startup;
 pub use startup::*;
@@ -0,0 +1,192 @@
+//! Commlib: SharedConf<T>
+//! 读多写少的全局配置：任意线程无锁读取快照，init/reload 时整体替换
+
+use arc_swap::{ArcSwap, Guard};
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::thread::ThreadId;
+
+/// 修改配置失败原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfError {
+    Reentrant, // 在 update 回调中再次 update 同一个配置
+}
+
+impl std::fmt::Display for ConfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConfError::Reentrant => write!(f, "conf update is not reentrant"),
+        }
+    }
+}
+
+impl std::error::Error for ConfError {}
+
+/// 全局配置
+pub struct SharedConf<T> {
+    inner: ArcSwap<T>,
+    write_lock: Mutex<()>,
+    writer: Mutex<Option<ThreadId>>, // 正在 update 的线程，用于检测重入
+}
+
+impl<T> SharedConf<T> {
+    ///
+    pub fn new(value: T) -> Self {
+        Self {
+            inner: ArcSwap::from_pointee(value),
+            write_lock: Mutex::new(()),
+            writer: Mutex::new(None),
+        }
+    }
+
+    /// 读取当前配置快照，持有期间不受并发 update 影响
+    #[inline(always)]
+    pub fn get(&self) -> Guard<Arc<T>> {
+        self.inner.load()
+    }
+
+    /// 读取当前配置（可长期持有）
+    #[inline(always)]
+    pub fn load_full(&self) -> Arc<T> {
+        self.inner.load_full()
+    }
+
+    /// 整体替换配置
+    pub fn store(&self, value: T) {
+        let _lock = self.write_lock.lock();
+        self.inner.store(Arc::new(value));
+    }
+}
+
+impl<T: Clone> SharedConf<T> {
+    /// 修改配置：在副本上修改后整体替换，多个写者串行执行.
+    /// 回调中再次 update 同一个配置返回 ConfError::Reentrant（而不是死锁）；
+    /// 回调中 get 读到的是修改前的快照，回调返回后修改才可见
+    pub fn update<F, R>(&self, f: F) -> Result<R, ConfError>
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let tid = std::thread::current().id();
+        if *self.writer.lock() == Some(tid) {
+            return Err(ConfError::Reentrant);
+        }
+
+        let _lock = self.write_lock.lock();
+        let _writer = WriterGuard::new(&self.writer, tid);
+        let mut value = T::clone(&self.inner.load());
+        let r = f(&mut value);
+        self.inner.store(Arc::new(value));
+        Ok(r)
+    }
+}
+
+/// 记录正在 update 的线程，回调 panic 时同样清除
+struct WriterGuard<'a> {
+    writer: &'a Mutex<Option<ThreadId>>,
+}
+
+impl<'a> WriterGuard<'a> {
+    fn new(writer: &'a Mutex<Option<ThreadId>>, tid: ThreadId) -> Self {
+        (*writer.lock()) = Some(tid);
+        Self { writer }
+    }
+}
+
+impl Drop for WriterGuard<'_> {
+    fn drop(&mut self) {
+        (*self.writer.lock()) = None;
+    }
+}
+
+impl<T: Default> Default for SharedConf<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct NodeAddr {
+        addr: String,
+        port: u16,
+    }
+
+    #[test]
+    fn init_visible_from_worker_thread() {
+        let conf: Arc<SharedConf<NodeAddr>> = Arc::new(SharedConf::default());
+
+        // service 线程 init
+        conf.update(|cfg| {
+            cfg.addr = "127.0.0.1".to_owned();
+            cfg.port = 7001;
+        })
+        .unwrap();
+
+        let conf2 = conf.clone();
+        let raddr = std::thread::spawn(move || {
+            let cfg = conf2.get();
+            format!("{}:{}", cfg.addr, cfg.port)
+        })
+        .join()
+        .unwrap();
+        assert_eq!(raddr, "127.0.0.1:7001");
+    }
+
+    #[test]
+    fn snapshot_while_updating() {
+        let conf = SharedConf::new(NodeAddr::default());
+
+        let snapshot = conf.get();
+        let port = conf
+            .update(|cfg| {
+                // 回调中再次读取不会死锁
+                cfg.port = conf.get().port + 1;
+                cfg.port
+            })
+            .unwrap();
+        assert_eq!(port, 1);
+        assert_eq!(snapshot.port, 0);
+        assert_eq!(conf.get().port, 1);
+    }
+
+    #[test]
+    fn nested_update_is_rejected() {
+        let conf = SharedConf::new(NodeAddr::default());
+
+        let nested = conf
+            .update(|cfg| {
+                cfg.port = 7001;
+                // 回调中读到的是修改前的快照
+                assert_eq!(conf.get().port, 0);
+                conf.update(|cfg| cfg.port = 7002)
+            })
+            .unwrap();
+        assert_eq!(nested, Err(ConfError::Reentrant));
+        assert_eq!(conf.get().port, 7001);
+
+        // 回调 panic 后仍可再次 update
+        let conf = Arc::new(conf);
+        let conf2 = conf.clone();
+        assert!(
+            std::thread::spawn(move || conf2.update(|_| panic!("update failed")))
+                .join()
+                .is_err()
+        );
+        assert_eq!(conf.update(|cfg| cfg.port), Ok(7001));
+
+        // 其他线程的 update 等待当前写者完成，不视为重入
+        let conf2 = conf.clone();
+        let port = conf
+            .update(|cfg| {
+                cfg.port = 7003;
+                std::thread::spawn(move || conf2.update(|cfg| cfg.port))
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(port, Ok(7003));
+    }
+}
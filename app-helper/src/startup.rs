@@ -1,13 +1,174 @@
 //! Commlib: Startup
 
 use parking_lot::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{Arc, OnceLock, Weak};
+use std::time::{Duration, Instant};
 
-/// 任务步骤回调函数
+use commlib_sys::{ServiceRs, G_SERVICE_SIGNAL};
+
+/// 并行组中的任务步骤回调函数，返回 false 表示失败（组内步骤不能挂起）
 pub type StepAction = dyn FnMut() -> bool + Send + Sync + 'static;
 
+/// 任务步骤回调函数
+pub type SyncStepAction = dyn FnMut() -> StepResult + Send + Sync + 'static;
+
+/// 带超时的任务步骤回调函数，在独立线程中执行
+pub type TimedStepAction = dyn FnOnce() -> StepResult + Send + 'static;
+
+/// 回滚函数，之后的步骤失败时按添加的逆序执行
+pub type RollbackAction = dyn FnOnce() + Send + 'static;
+
+/// 异步任务步骤回调函数，通过 StepToken 通知完成
+pub type AsyncStepAction = dyn FnOnce(StepToken) + Send + Sync + 'static;
+
+/// 进度回调 (step_index, step_name, status)，持有 startup 锁时调用，回调中不能再操作 startup
+pub type ProgressAction = dyn Fn(usize, &str, StepStatus) + Send + Sync + 'static;
+
+/// 步骤状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepStatus {
+    Started,         // 开始执行，每次重试都会通知
+    Succeeded,       // 执行成功
+    FailedWillRetry, // 执行失败，retry_interval 之后重试
+    FailedFatal,     // 执行失败且不再重试，startup 挂起
+}
+
+/// 同步步骤的执行结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepResult {
+    Succeeded, // 继续执行之后的步骤
+    Suspend,   // 挂起，由 resume() 继续，不视为失败
+    Failed,    // 失败，按 options 重试，重试次数用尽则回滚并挂起
+}
+
+/// 返回 bool 的步骤：true 继续执行，false 挂起等待 resume()
+impl From<bool> for StepResult {
+    fn from(ok: bool) -> Self {
+        if ok {
+            StepResult::Succeeded
+        } else {
+            StepResult::Suspend
+        }
+    }
+}
+
+/// 步骤选项
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StepOptions {
+    pub max_retries: u32,         // 失败后最多重试次数，0 表示不重试
+    pub retry_interval: Duration, // 重试间隔，由 retry service 的定时器调度，不阻塞线程
+}
+
+impl StepOptions {
+    ///
+    pub fn with_retries(max_retries: u32, retry_interval: Duration) -> Self {
+        Self {
+            max_retries,
+            retry_interval,
+        }
+    }
+}
+
+// 单次执行步骤的结果
+enum StepOutcome {
+    Succeeded,
+    Suspended,      // 步骤返回挂起，等待 resume()
+    Pending,        // 异步步骤等待 token.complete()
+    Failed(String), // 失败的步骤（并行组中为组内失败的步骤）
+}
+
+const STEP_PENDING: u8 = 0;
+const STEP_SUCCESS: u8 = 1;
+const STEP_FAILED: u8 = 2;
+
+struct StepTokenInner {
+    startup: Weak<Mutex<StartupHandle>>,
+    executing: Arc<AtomicBool>,
+    startup_name: String,
+    desc: String,
+    index: usize,
+    state: AtomicU8,
+}
+
+/// 异步步骤完成令牌，可以 clone 到回调中，只有第一次 complete 生效
+#[derive(Clone)]
+pub struct StepToken {
+    inner: Arc<StepTokenInner>,
+}
+
+impl StepToken {
+    /// 完成异步步骤：成功时在当前线程中继续执行后续步骤，失败时挂起
+    pub fn complete(&self, success: bool) {
+        let inner = &self.inner;
+        let state = if success { STEP_SUCCESS } else { STEP_FAILED };
+        if inner
+            .state
+            .compare_exchange(STEP_PENDING, state, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            log::warn!(
+                "startup[{}]: async task({}) already completed.",
+                inner.startup_name,
+                inner.desc
+            );
+            return;
+        }
+
+        if !success {
+            log::error!(
+                "startup[{}]: async task({}) failed at index({}).",
+                inner.startup_name,
+                inner.desc,
+                inner.index
+            );
+        }
+
+        // 在步骤回调中同步完成，由 exec_tasks 继续执行
+        if inner.executing.load(Ordering::SeqCst) {
+            return;
+        }
+
+        if let Some(startup) = inner.startup.upgrade() {
+            let mut handle = startup.lock();
+            if handle.suspending && handle.index == inner.index {
+                if success {
+                    handle.complete_step();
+                    handle.exec_tasks();
+                } else {
+                    handle.step_failed(inner.desc.clone());
+                }
+            }
+        }
+    }
+
+    ///
+    pub fn is_completed(&self) -> bool {
+        STEP_PENDING != self.inner.state.load(Ordering::SeqCst)
+    }
+
+    fn state(&self) -> u8 {
+        self.inner.state.load(Ordering::SeqCst)
+    }
+}
+
+enum StartupAction {
+    Sync(Box<SyncStepAction>),
+    SyncWithTimeout(Arc<Mutex<Box<SyncStepAction>>>, Duration),
+    Timed(Option<Box<TimedStepAction>>, Duration),
+    Parallel(Vec<(String, Box<StepAction>)>),
+    Async(Option<Box<AsyncStepAction>>, Option<Duration>),
+}
+
 struct StartupTask {
     desc: String, // 每个步骤加一个描述方便差错
-    action: Box<StepAction>,
+    action: StartupAction,
+    rollback: Option<Box<RollbackAction>>,
+
+    options: StepOptions,
+    retries: u32,                // 已重试次数
+    started_at: Option<Instant>, // 第一次执行的时间
+    elapsed: Option<Duration>,   // 成功或最终失败时记录，包括重试等待的时间
 }
 
 struct StartupHandle {
@@ -15,16 +176,36 @@ struct StartupHandle {
     tasks: Vec<StartupTask>,
     index: usize,
     suspending: bool,
+    failed: Arc<OnceLock<String>>, // 超时、失败的步骤，resume 时重置
+    rollbacks: Vec<(String, Box<RollbackAction>)>, // 已完成步骤的回滚函数
+    rollback_disabled: bool,
+
+    progress: Option<Box<ProgressAction>>,
+    retry_service: Option<Arc<dyn ServiceRs>>, // None 时使用 signal service 的定时器
+    retry_scheduled: bool,
+
+    this: Weak<Mutex<StartupHandle>>,
+    executing: Arc<AtomicBool>,
 }
 
 impl StartupHandle {
     ///
-    pub(crate) fn new(name: &str) -> StartupHandle {
+    pub(crate) fn new(name: &str, this: Weak<Mutex<StartupHandle>>) -> StartupHandle {
         StartupHandle {
             name: name.to_owned(),
             tasks: Vec::new(),
             index: 0,
             suspending: false,
+            failed: Arc::new(OnceLock::new()),
+            rollbacks: Vec::new(),
+            rollback_disabled: false,
+
+            progress: None,
+            retry_service: None,
+            retry_scheduled: false,
+
+            this,
+            executing: Arc::new(AtomicBool::new(false)),
         }
     }
 
@@ -36,18 +217,36 @@ impl StartupHandle {
             return;
         }
 
-        while self.index < task_count && self.exec_step() {
-            self.index += 1;
+        while self.index < task_count {
+            match self.exec_step() {
+                StepOutcome::Succeeded => {
+                    self.complete_step();
 
-            if self.index < task_count {
-                let task = &self.tasks[self.index];
-                log::info!(
-                    "startup[{}]: next task({}) index({}) ... ... tail_index={}",
-                    self.name,
-                    task.desc,
-                    self.index,
-                    task_count - 1
-                );
+                    if self.index < task_count {
+                        let task = &self.tasks[self.index];
+                        log::info!(
+                            "startup[{}]: next task({}) index({}) ... ... tail_index={}",
+                            self.name,
+                            task.desc,
+                            self.index,
+                            task_count - 1
+                        );
+                    }
+                }
+                StepOutcome::Suspended => {
+                    log::info!(
+                        "startup[{}]: task({}) suspended at index({}), wait resume ...",
+                        self.name,
+                        self.tasks[self.index].desc,
+                        self.index
+                    );
+                    break;
+                }
+                StepOutcome::Pending => break,
+                StepOutcome::Failed(desc) => {
+                    self.step_failed(desc);
+                    break;
+                }
             }
         }
 
@@ -67,53 +266,458 @@ impl StartupHandle {
         }
     }
 
-    fn exec_step(&mut self) -> bool {
+    fn exec_step(&mut self) -> StepOutcome {
         let task_count = self.tasks.len();
         if 0 == task_count {
             log::info!("startup[{}]: no task.", self.name);
-            return true;
+            return StepOutcome::Succeeded;
         }
 
         if self.index >= task_count {
             log::info!("startup[{}]: all task are over.", self.name);
-            return true;
+            return StepOutcome::Succeeded;
         }
 
         let task = &mut self.tasks[self.index];
         log::info!(
-            "startup[{}]: exec task({}) at index({}) ... ... tail_index={}",
+            "startup[{}]: exec task({}) at index({}) ... ... tail_index={} retries={}",
             self.name,
             task.desc,
             self.index,
-            task_count - 1
+            task_count - 1,
+            task.retries
         );
+        if task.started_at.is_none() {
+            task.started_at = Some(Instant::now());
+        }
+        self.notify(StepStatus::Started);
 
         // exec
-        (task.action)()
+        let task = &mut self.tasks[self.index];
+        let desc = task.desc.clone();
+        let result = match &mut task.action {
+            StartupAction::Sync(action) => (action)(),
+            StartupAction::SyncWithTimeout(action, timeout) => {
+                let action = action.clone();
+                let timeout = *timeout;
+                self.exec_with_timeout(desc.clone(), timeout, move || (*action.lock())())
+            }
+            StartupAction::Timed(action_opt, timeout) => {
+                let action = match action_opt.take() {
+                    Some(action) => action,
+                    None => {
+                        log::error!(
+                            "startup[{}]: timed task({}) can't exec more than one times!!!",
+                            self.name,
+                            task.desc
+                        );
+                        return StepOutcome::Failed(desc);
+                    }
+                };
+                let timeout = *timeout;
+                self.exec_with_timeout(desc.clone(), timeout, action)
+            }
+            StartupAction::Parallel(steps) => {
+                let results: Vec<(String, bool)> = std::thread::scope(|scope| {
+                    let joins: Vec<_> = steps
+                        .iter_mut()
+                        .map(|(desc, action)| {
+                            let desc: &str = desc;
+                            (desc, scope.spawn(move || (action)()))
+                        })
+                        .collect();
+                    joins
+                        .into_iter()
+                        .map(|(desc, join)| (desc.to_owned(), join.join().unwrap_or(false)))
+                        .collect()
+                });
+
+                // 任一步骤失败则整组失败并挂起
+                match results.into_iter().find(|(_, ok)| !*ok) {
+                    Some((failed_desc, _)) => {
+                        log::error!(
+                            "startup[{}]: parallel task({}) failed in group({})!!!",
+                            self.name,
+                            failed_desc,
+                            task.desc
+                        );
+                        return StepOutcome::Failed(failed_desc);
+                    }
+                    None => StepResult::Succeeded,
+                }
+            }
+            StartupAction::Async(action_opt, timeout_opt) => {
+                let action = match action_opt.take() {
+                    Some(action) => action,
+                    None => {
+                        log::error!(
+                            "startup[{}]: async task({}) can't exec more than one times!!!",
+                            self.name,
+                            task.desc
+                        );
+                        return StepOutcome::Failed(desc);
+                    }
+                };
+
+                let token = StepToken {
+                    inner: Arc::new(StepTokenInner {
+                        startup: self.this.clone(),
+                        executing: self.executing.clone(),
+                        startup_name: self.name.clone(),
+                        desc: task.desc.clone(),
+                        index: self.index,
+                        state: AtomicU8::new(STEP_PENDING),
+                    }),
+                };
+
+                if let Some(timeout) = *timeout_opt {
+                    let token = token.clone();
+                    std::thread::Builder::new()
+                        .name("startup_timeout".to_owned())
+                        .spawn(move || {
+                            std::thread::sleep(timeout);
+                            if !token.is_completed() {
+                                log::error!(
+                                    "startup[{}]: async task({}) timeout after {:?}!!!",
+                                    token.inner.startup_name,
+                                    token.inner.desc,
+                                    timeout
+                                );
+                                token.complete(false);
+                            }
+                        })
+                        .unwrap();
+                }
+
+                self.executing.store(true, Ordering::SeqCst);
+                (action)(token.clone());
+                self.executing.store(false, Ordering::SeqCst);
+
+                // 未完成时挂起，由 token.complete() 继续
+                match token.state() {
+                    STEP_SUCCESS => StepResult::Succeeded,
+                    STEP_FAILED => StepResult::Failed,
+                    _ => return StepOutcome::Pending,
+                }
+            }
+        };
+
+        match result {
+            StepResult::Succeeded => StepOutcome::Succeeded,
+            StepResult::Suspend => StepOutcome::Suspended,
+            StepResult::Failed => StepOutcome::Failed(desc),
+        }
+    }
+}
+
+impl StartupHandle {
+    // 当前步骤成功
+    fn complete_step(&mut self) {
+        let elapsed = self.finish_timing();
+        log::info!(
+            "startup[{}]: task({}) succeeded at index({}), elapsed {:?}.",
+            self.name,
+            self.tasks[self.index].desc,
+            self.index,
+            elapsed
+        );
+        self.notify(StepStatus::Succeeded);
+        self.advance();
+    }
+
+    // 当前步骤失败：还有重试次数时在 retry_interval 之后重试，否则挂起
+    fn step_failed(&mut self, desc: String) {
+        self.suspending = true;
+
+        let task = &mut self.tasks[self.index];
+        if task.retries < task.options.max_retries {
+            task.retries += 1;
+            let retry_interval = task.options.retry_interval;
+            log::warn!(
+                "startup[{}]: task({}) failed at index({}), retry {}/{} after {:?} ...",
+                self.name,
+                desc,
+                self.index,
+                task.retries,
+                task.options.max_retries,
+                retry_interval
+            );
+            self.notify(StepStatus::FailedWillRetry);
+            self.schedule_retry(retry_interval);
+        } else {
+            let elapsed = self.finish_timing();
+            log::error!(
+                "startup[{}]: task({}) failed at index({}), elapsed {:?}!!!",
+                self.name,
+                desc,
+                self.index,
+                elapsed
+            );
+            self.notify(StepStatus::FailedFatal);
+            self.fail(desc);
+        }
+    }
+
+    fn schedule_retry(&mut self, retry_interval: Duration) {
+        self.retry_scheduled = true;
+
+        let this = self.this.clone();
+        let index = self.index;
+        let retry = move || {
+            if let Some(startup) = this.upgrade() {
+                let mut handle = startup.lock();
+                if handle.retry_scheduled && handle.suspending && handle.index == index {
+                    handle.retry_scheduled = false;
+                    handle.exec_tasks();
+                }
+            }
+        };
+
+        match &self.retry_service {
+            Some(srv) => {
+                srv.get_handle().schedule(retry_interval, retry);
+            }
+            None => {
+                G_SERVICE_SIGNAL
+                    .get_handle()
+                    .schedule(retry_interval, retry);
+            }
+        }
+    }
+
+    // 记录当前步骤的耗时（从第一次执行开始）
+    fn finish_timing(&mut self) -> Duration {
+        let task = &mut self.tasks[self.index];
+        let elapsed = task
+            .started_at
+            .map_or(Duration::ZERO, |started_at| started_at.elapsed());
+        task.elapsed = Some(elapsed);
+        elapsed
+    }
+
+    fn notify(&self, status: StepStatus) {
+        if let Some(progress) = &self.progress {
+            (progress)(self.index, &self.tasks[self.index].desc, status);
+        }
+    }
+
+    // 当前步骤完成，记录其回滚函数
+    fn advance(&mut self) {
+        let task = &mut self.tasks[self.index];
+        if let Some(rollback) = task.rollback.take() {
+            self.rollbacks.push((task.desc.clone(), rollback));
+        }
+        self.index += 1;
+    }
+
+    // 步骤失败，按逆序执行已完成步骤的回滚函数
+    fn fail(&mut self, desc: String) {
+        if let Err(desc) = self.failed.set(desc) {
+            log::warn!("startup[{}]: task({}) already failed.", self.name, desc);
+        }
+        if self.rollback_disabled {
+            if !self.rollbacks.is_empty() {
+                log::warn!(
+                    "startup[{}]: rollback disabled, skip {} rollback(s).",
+                    self.name,
+                    self.rollbacks.len()
+                );
+            }
+            return;
+        }
+
+        while let Some((desc, rollback)) = self.rollbacks.pop() {
+            log::info!("startup[{}]: rollback task({}) ...", self.name, desc);
+            (rollback)();
+        }
+    }
+
+    // 在独立线程中执行步骤，超时未返回视为失败并挂起（执行线程无法终止，任其结束）
+    fn exec_with_timeout<F>(&self, desc: String, timeout: Duration, action: F) -> StepResult
+    where
+        F: FnOnce() -> StepResult + Send + 'static,
+    {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let spawn_ret = std::thread::Builder::new()
+            .name("startup_step".to_owned())
+            .spawn(move || {
+                // 超时后接收端已关闭，忽略发送错误
+                tx.send((action)()).ok();
+            });
+        if let Err(err) = spawn_ret {
+            log::error!(
+                "startup[{}]: timed task({}) spawn thread failed!!! error: {}",
+                self.name,
+                desc,
+                err
+            );
+            return StepResult::Failed;
+        }
+
+        match rx.recv_timeout(timeout) {
+            Ok(ret) => ret,
+            Err(_) => {
+                log::error!(
+                    "startup[{}]: timed task({}) timeout after {:?}!!!",
+                    self.name,
+                    desc,
+                    timeout
+                );
+                StepResult::Failed
+            }
+        }
     }
 }
 
 /// 启动步骤
 pub struct Startup {
-    handle: Mutex<StartupHandle>,
+    handle: Arc<Mutex<StartupHandle>>,
+    default_timeout: Option<Duration>,
+    failed: Arc<OnceLock<String>>, // 与 handle 共享，failed_step 不需要加锁
 }
 
 impl Startup {
     /// Constructor
     pub fn new(name: &str) -> Startup {
+        let handle: Arc<Mutex<StartupHandle>> =
+            Arc::new_cyclic(|this| Mutex::new(StartupHandle::new(name, this.clone())));
+        let failed = handle.lock().failed.clone();
         Startup {
-            handle: Mutex::new(StartupHandle::new(name)),
+            handle,
+            default_timeout: None,
+            failed,
+        }
+    }
+
+    /// 之后 add_step 添加的步骤的默认超时，None 表示不限时（在当前线程中执行）
+    pub fn set_default_timeout(&mut self, timeout: Option<Duration>) {
+        self.default_timeout = timeout;
+    }
+
+    /// 添加启动步骤，设置了默认超时时在独立线程中执行；返回 false 时挂起，由 resume() 继续
+    pub fn add_step<F, R>(&mut self, desc: &str, action: F)
+    where
+        F: FnMut() -> R + Send + Sync + 'static,
+        R: Into<StepResult>,
+    {
+        self.add_step_with_options(desc, StepOptions::default(), action);
+    }
+
+    /// 添加启动步骤，返回 StepResult::Failed（或超时）后按 options 重试，重试次数用尽则挂起
+    pub fn add_step_with_options<F, R>(&mut self, desc: &str, options: StepOptions, mut action: F)
+    where
+        F: FnMut() -> R + Send + Sync + 'static,
+        R: Into<StepResult>,
+    {
+        let action: Box<SyncStepAction> = Box::new(move || action().into());
+        match self.default_timeout {
+            Some(timeout) => self.add_task(
+                desc,
+                StartupAction::SyncWithTimeout(Arc::new(Mutex::new(action)), timeout),
+            ),
+            None => self.add_task(desc, StartupAction::Sync(action)),
         }
+        let mut handle = self.handle.lock();
+        if let Some(task) = handle.tasks.last_mut() {
+            task.options = options;
+        }
+    }
+
+    /// 进度回调 (step_index, step_name, status)
+    pub fn set_on_progress<F>(&mut self, f: F)
+    where
+        F: Fn(usize, &str, StepStatus) + Send + Sync + 'static,
+    {
+        let mut handle = self.handle.lock();
+        handle.progress = Some(Box::new(f));
+    }
+
+    /// 使用 srv 的定时器调度重试，重试在 srv 线程中执行；没有设置时使用 signal service 的定时器
+    pub fn set_retry_service<T>(&mut self, srv: &Arc<T>)
+    where
+        T: ServiceRs + 'static,
+    {
+        let mut handle = self.handle.lock();
+        handle.retry_service = Some(srv.clone());
+    }
+
+    /// 添加带超时的启动步骤，在独立线程中执行，超时未返回视为失败并挂起
+    pub fn add_step_with_timeout<F, R>(&mut self, desc: &str, timeout: Duration, action: F)
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Into<StepResult>,
+    {
+        let action: Box<TimedStepAction> = Box::new(move || action().into());
+        self.add_task(desc, StartupAction::Timed(Some(action), timeout));
     }
 
-    /// 添加启动步骤
-    pub fn add_step<F>(&mut self, desc: &str, action: F)
+    /// 添加异步启动步骤，exec 在该步骤挂起直至 token.complete(true)
+    pub fn add_async_step<F>(&mut self, desc: &str, action: F)
     where
-        F: FnMut() -> bool + Send + Sync + 'static,
+        F: FnOnce(StepToken) + Send + Sync + 'static,
     {
+        self.add_task(desc, StartupAction::Async(Some(Box::new(action)), None));
+    }
+
+    /// 添加异步启动步骤，超时未完成视为失败
+    pub fn add_async_step_with_timeout<F>(&mut self, desc: &str, timeout: Duration, action: F)
+    where
+        F: FnOnce(StepToken) + Send + Sync + 'static,
+    {
+        self.add_task(
+            desc,
+            StartupAction::Async(Some(Box::new(action)), Some(timeout)),
+        );
+    }
+
+    /// 添加并行步骤组，组内步骤同时执行，全部成功后才继续执行之后的步骤
+    pub fn add_parallel_group(&mut self, steps: Vec<(&str, Box<StepAction>)>) -> &mut Self {
+        let desc = steps
+            .iter()
+            .map(|(desc, _)| *desc)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let steps = steps
+            .into_iter()
+            .map(|(desc, action)| (desc.to_owned(), action))
+            .collect();
+        self.add_task(
+            std::format!("parallel({})", desc).as_str(),
+            StartupAction::Parallel(steps),
+        );
+        self
+    }
+
+    /// 添加带回滚的启动步骤，之后的步骤失败时执行 rollback 释放本步骤申请的资源
+    pub fn add_step_with_rollback<F, R, B>(&mut self, desc: &str, action: F, rollback: B)
+    where
+        F: FnMut() -> R + Send + Sync + 'static,
+        R: Into<StepResult>,
+        B: FnOnce() + Send + 'static,
+    {
+        self.add_step(desc, action);
+        let mut handle = self.handle.lock();
+        if let Some(task) = handle.tasks.last_mut() {
+            task.rollback = Some(Box::new(rollback));
+        }
+    }
+
+    /// 失败时不执行回滚
+    pub fn disable_rollback(&mut self) {
+        let mut handle = self.handle.lock();
+        handle.rollback_disabled = true;
+    }
+
+    fn add_task(&mut self, desc: &str, action: StartupAction) {
         let task = StartupTask {
             desc: desc.to_owned(),
-            action: Box::new(action),
+            action,
+            rollback: None,
+
+            options: StepOptions::default(),
+            retries: 0,
+            started_at: None,
+            elapsed: None,
         };
         let mut handle = self.handle.lock();
         handle.tasks.push(task)
@@ -125,12 +729,435 @@ impl Startup {
         handle.exec_tasks();
     }
 
-    /// 挂起返回，继续执行启动步骤，注意避免死循环
+    /// 挂起返回，跳过当前步骤继续执行启动步骤（记录其回滚函数），注意避免死循环
     pub fn resume(&mut self) {
         let mut handle = self.handle.lock();
+        handle.retry_scheduled = false;
+        if let Some(desc) = handle.failed.get() {
+            log::warn!(
+                "startup[{}]: resume after task({}) failed.",
+                handle.name,
+                desc
+            );
+        }
+        self.failed = Arc::new(OnceLock::new());
+        handle.failed = self.failed.clone();
+
         if handle.suspending {
-            handle.index += 1;
+            handle.advance();
         }
         handle.exec_tasks();
     }
+
+    /// 最终失败（重试次数用尽）的步骤，并行组中为组内失败的步骤，resume 后清除
+    pub fn failed_step(&self) -> Option<&str> {
+        self.failed.get().map(String::as_str)
+    }
+
+    /// 已结束（成功或最终失败）的步骤及其耗时，按执行顺序
+    pub fn step_timings(&self) -> Vec<(String, Duration)> {
+        let handle = self.handle.lock();
+        handle
+            .tasks
+            .iter()
+            .filter_map(|task| task.elapsed.map(|elapsed| (task.desc.clone(), elapsed)))
+            .collect()
+    }
+
+    /// 是否所有步骤都已执行完毕
+    pub fn is_over(&self) -> bool {
+        let handle = self.handle.lock();
+        handle.index >= handle.tasks.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use commlib_sys::{proc_service_ready, start_service, ServiceSignalRs};
+
+    #[test]
+    fn async_step_continues_on_complete() {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let token_slot: Arc<Mutex<Option<StepToken>>> = Arc::new(Mutex::new(None));
+
+        let mut startup = Startup::new("test");
+        let o = order.clone();
+        startup.add_step("sync", move || {
+            o.lock().push("sync");
+            true
+        });
+        let o = order.clone();
+        startup.add_async_step("inline", move |token| {
+            o.lock().push("inline");
+            token.complete(true);
+        });
+        let o = order.clone();
+        let slot = token_slot.clone();
+        startup.add_async_step("handshake", move |token| {
+            o.lock().push("handshake");
+            *slot.lock() = Some(token);
+        });
+        let o = order.clone();
+        startup.add_step("after", move || {
+            o.lock().push("after");
+            true
+        });
+
+        startup.exec();
+        assert_eq!(*order.lock(), vec!["sync", "inline", "handshake"]);
+        assert!(!startup.is_over());
+
+        // 在其它线程中完成
+        let token = token_slot.lock().take().unwrap();
+        std::thread::spawn(move || token.complete(true))
+            .join()
+            .unwrap();
+        assert_eq!(*order.lock(), vec!["sync", "inline", "handshake", "after"]);
+        assert!(startup.is_over());
+    }
+
+    #[test]
+    fn async_step_timeout() {
+        let ran = Arc::new(AtomicBool::new(false));
+
+        let mut startup = Startup::new("test");
+        startup.add_async_step_with_timeout("never", Duration::from_millis(10), |_token| {});
+        let r = ran.clone();
+        startup.add_step("after", move || {
+            r.store(true, Ordering::SeqCst);
+            true
+        });
+
+        startup.exec();
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!ran.load(Ordering::SeqCst));
+        assert!(!startup.is_over());
+
+        // 旧的 resume 流程仍然可用
+        startup.resume();
+        assert!(ran.load(Ordering::SeqCst));
+        assert!(startup.is_over());
+    }
+
+    #[test]
+    fn timed_step() {
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let mut startup = Startup::new("test");
+        let o = order.clone();
+        startup.add_step_with_timeout("fast", Duration::from_secs(5), move || {
+            o.lock().push("fast");
+            true
+        });
+        let o = order.clone();
+        startup.add_step_with_timeout("hang", Duration::from_millis(20), move || {
+            std::thread::sleep(Duration::from_millis(300));
+            o.lock().push("hang");
+            true
+        });
+
+        // 默认超时作用于之后的 add_step
+        startup.set_default_timeout(Some(Duration::from_millis(20)));
+        let o = order.clone();
+        startup.add_step("slow", move || {
+            std::thread::sleep(Duration::from_millis(300));
+            o.lock().push("slow");
+            true
+        });
+        startup.set_default_timeout(None);
+        let o = order.clone();
+        startup.add_step("after", move || {
+            o.lock().push("after");
+            true
+        });
+
+        startup.exec();
+        assert_eq!(*order.lock(), vec!["fast"]);
+        assert_eq!(startup.failed_step(), Some("hang"));
+        assert!(!startup.is_over());
+
+        startup.resume();
+        assert_eq!(startup.failed_step(), Some("slow"));
+        assert!(!startup.is_over());
+
+        startup.resume();
+        assert_eq!(startup.failed_step(), None);
+        assert!(startup.is_over());
+        assert_eq!(order.lock()[..2], ["fast", "after"]);
+    }
+
+    #[test]
+    fn parallel_group() {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let barrier = Arc::new(std::sync::Barrier::new(3));
+
+        let mut steps: Vec<(&str, Box<StepAction>)> = Vec::new();
+        for name in ["a", "b", "c"] {
+            let o = order.clone();
+            let b = barrier.clone();
+            steps.push((
+                name,
+                Box::new(move || {
+                    // 三个步骤同时执行才能通过 barrier
+                    b.wait();
+                    o.lock().push(name);
+                    true
+                }),
+            ));
+        }
+
+        let mut startup = Startup::new("test");
+        let o = order.clone();
+        startup
+            .add_parallel_group(steps)
+            .add_step("after", move || {
+                o.lock().push("after");
+                true
+            });
+        startup.exec();
+        assert!(startup.is_over());
+        let mut order = order.lock().clone();
+        assert_eq!(order.pop(), Some("after"));
+        order.sort();
+        assert_eq!(order, vec!["a", "b", "c"]);
+
+        // 任一步骤失败则挂起
+        let ran = Arc::new(AtomicBool::new(false));
+        let mut startup = Startup::new("test");
+        let r = ran.clone();
+        startup
+            .add_parallel_group(vec![
+                ("ok", Box::new(|| true) as Box<StepAction>),
+                ("bad", Box::new(|| false) as Box<StepAction>),
+            ])
+            .add_step("after", move || {
+                r.store(true, Ordering::SeqCst);
+                true
+            });
+        startup.exec();
+        assert!(!ran.load(Ordering::SeqCst));
+        assert_eq!(startup.failed_step(), Some("bad"));
+        assert!(!startup.is_over());
+    }
+
+    #[test]
+    fn rollback_on_failure() {
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let build = |disable: bool| {
+            let mut startup = Startup::new("test");
+            if disable {
+                startup.disable_rollback();
+            }
+            for name in ["a", "b"] {
+                let o = order.clone();
+                startup.add_step_with_rollback(
+                    name,
+                    || true,
+                    move || o.lock().push(std::format!("rollback {}", name)),
+                );
+            }
+            startup.add_async_step("fail", |token| token.complete(false));
+            let o = order.clone();
+            startup.add_step_with_rollback(
+                "never",
+                || true,
+                move || o.lock().push("rollback never".to_owned()),
+            );
+            startup
+        };
+
+        let mut startup = build(false);
+        startup.exec();
+        assert_eq!(startup.failed_step(), Some("fail"));
+        assert_eq!(*order.lock(), vec!["rollback b", "rollback a"]);
+
+        // 回滚只执行一次
+        startup.resume();
+        assert!(startup.is_over());
+        assert_eq!(order.lock().len(), 2);
+
+        order.lock().clear();
+        let mut startup = build(true);
+        startup.exec();
+        assert_eq!(startup.failed_step(), Some("fail"));
+        assert!(order.lock().is_empty());
+    }
+
+    fn recorder(startup: &mut Startup) -> Arc<Mutex<Vec<(usize, String, StepStatus)>>> {
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let p = progress.clone();
+        startup.set_on_progress(move |index, desc, status| {
+            p.lock().push((index, desc.to_owned(), status));
+        });
+        progress
+    }
+
+    // 运行中的 service，用于调度重试
+    fn retry_service(id: u64) -> &'static Arc<ServiceSignalRs> {
+        let srv: &'static Arc<ServiceSignalRs> =
+            Box::leak(Box::new(Arc::new(ServiceSignalRs::new(id))));
+        let ready_pair = start_service(srv.as_ref(), "startup_retry", || {});
+        assert!(proc_service_ready(srv.as_ref(), ready_pair));
+        srv
+    }
+
+    fn wait_until<F: Fn() -> bool>(f: F) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !f() {
+            assert!(Instant::now() < deadline, "wait timeout");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn retry_then_succeed() {
+        let srv = retry_service(9201);
+        let mut startup = Startup::new("test");
+        startup.set_retry_service(srv);
+        let progress = recorder(&mut startup);
+
+        let attempts = Arc::new(AtomicU8::new(0));
+        let a = attempts.clone();
+        startup.add_step_with_options(
+            "connect",
+            StepOptions::with_retries(3, Duration::from_millis(10)),
+            move || {
+                if a.fetch_add(1, Ordering::SeqCst) >= 2 {
+                    StepResult::Succeeded
+                } else {
+                    StepResult::Failed
+                }
+            },
+        );
+        startup.add_step("after", || true);
+
+        // 重试不阻塞 exec
+        startup.exec();
+        assert!(!startup.is_over());
+        wait_until(|| startup.is_over());
+
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        assert_eq!(startup.failed_step(), None);
+        use StepStatus::*;
+        let statuses: Vec<(usize, StepStatus)> = progress
+            .lock()
+            .iter()
+            .map(|(index, _, status)| (*index, *status))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                (0, Started),
+                (0, FailedWillRetry),
+                (0, Started),
+                (0, FailedWillRetry),
+                (0, Started),
+                (0, Succeeded),
+                (1, Started),
+                (1, Succeeded),
+            ]
+        );
+
+        // 耗时包括重试等待的时间
+        let timings = startup.step_timings();
+        assert_eq!(timings.len(), 2);
+        assert_eq!(timings[0].0, "connect");
+        assert!(timings[0].1 >= Duration::from_millis(20));
+        srv.get_handle().quit_service();
+    }
+
+    #[test]
+    fn fatal_failure_stops() {
+        let srv = retry_service(9202);
+        let mut startup = Startup::new("test");
+        startup.set_retry_service(srv);
+        let progress = recorder(&mut startup);
+
+        let ran = Arc::new(AtomicBool::new(false));
+        startup.add_step("ok", || true);
+        startup.add_step_with_options("bad", StepOptions::with_retries(1, Duration::ZERO), || {
+            StepResult::Failed
+        });
+        let r = ran.clone();
+        startup.add_step("after", move || {
+            r.store(true, Ordering::SeqCst);
+            true
+        });
+
+        startup.exec();
+        wait_until(|| startup.failed_step().is_some());
+        assert_eq!(startup.failed_step(), Some("bad"));
+        assert!(!ran.load(Ordering::SeqCst));
+        assert!(!startup.is_over());
+        assert_eq!(
+            progress.lock().last(),
+            Some(&(1, "bad".to_owned(), StepStatus::FailedFatal))
+        );
+        assert_eq!(
+            progress
+                .lock()
+                .iter()
+                .filter(|(_, _, status)| *status == StepStatus::Started)
+                .count(),
+            3
+        );
+        let timings = startup.step_timings();
+        assert_eq!(
+            timings
+                .iter()
+                .map(|(desc, _)| desc.as_str())
+                .collect::<Vec<_>>(),
+            vec!["ok", "bad"]
+        );
+
+        srv.get_handle().quit_service();
+
+        // 不重试的步骤失败也记录为 failed_step
+        let mut startup = Startup::new("test");
+        startup.add_step("bad", || StepResult::Failed);
+        startup.exec();
+        assert_eq!(startup.failed_step(), Some("bad"));
+        startup.resume();
+        assert_eq!(startup.failed_step(), None);
+        assert!(startup.is_over());
+    }
+
+    #[test]
+    fn false_suspends_until_resume() {
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let mut startup = Startup::new("test");
+        let progress = recorder(&mut startup);
+        let o = order.clone();
+        startup.add_step_with_rollback("load", || false, move || o.lock().push("rollback load"));
+        let o = order.clone();
+        startup.add_step_with_rollback(
+            "bad",
+            || StepResult::Failed,
+            move || o.lock().push("rollback bad"),
+        );
+        startup.add_step("fail", || StepResult::Failed);
+
+        // 返回 false 只是挂起：不视为失败，不回滚
+        startup.exec();
+        assert!(!startup.is_over());
+        assert_eq!(startup.failed_step(), None);
+        assert!(order.lock().is_empty());
+        assert!(progress
+            .lock()
+            .iter()
+            .all(|(_, _, status)| *status != StepStatus::FailedFatal));
+
+        // resume 继续执行，"bad" 失败时回滚 "load"
+        startup.resume();
+        assert_eq!(startup.failed_step(), Some("bad"));
+        assert_eq!(*order.lock(), vec!["rollback load"]);
+
+        // 跳过失败的步骤时同样记录其回滚函数
+        startup.resume();
+        assert_eq!(startup.failed_step(), Some("fail"));
+        assert_eq!(*order.lock(), vec!["rollback load", "rollback bad"]);
+    }
 }
@@ -12,7 +12,8 @@
 use std::sync::Arc;
 
 use commlib_sys::listen_tcp_addr;
-use commlib_sys::{ConnId, NetPacketGuard, NodeState, ServiceRs};
+use commlib_sys::service_net::TcpConn;
+use commlib_sys::{ConnId, NetPacketGuard, NodeState, PacketType, ServiceRs};
 use commlib_sys::{G_SERVICE_NET, G_SERVICE_SIGNAL};
 
 use app_helper::Startup;
@@ -66,8 +67,9 @@ pub fn startup_network_listen(srv: &Arc<TestService>) -> bool {
     // TODO: let thread_num: u32 = 1;
     // TODO: let connection_limit: u32 = 0; // 0=no limit
 
-    let conn_fn = |hd: ConnId| {
-        log::info!("[hd={}] conn_fn", hd);
+    let conn_fn = |conn: Arc<TcpConn>| {
+        let hd = conn.hd;
+        log::info!("[hd={}] conn_fn from {}", hd, conn.remote_addr());
 
         //
         G_MAIN.with(|g| {
@@ -99,6 +101,7 @@ pub fn startup_network_listen(srv: &Arc<TestService>) -> bool {
             srv,
             cfg.my.addr.clone(),
             cfg.my.port,
+            PacketType::Server,
             conn_fn,
             pkt_fn,
             close_fn,
@@ -121,10 +124,20 @@ fn test_service_init(srv: &Arc<TestService>) -> bool {
     });
     log::info!("\nTest init ...\n");
 
-    //
-    app_helper::with_conf_mut!(G_TEST_CONF, cfg, { cfg.init(handle.xml_config()) });
+    // 没有节点配置时启动失败
+    let xr = match handle.xml_config() {
+        Ok(xr) => xr,
+        Err(err) => {
+            log::error!("test service init failed!!! error: {}", err);
+            return false;
+        }
+    };
+    app_helper::with_conf_mut!(G_TEST_CONF, cfg, { cfg.init(&xr) });
 
     //
-    handle.set_state(NodeState::Start);
+    if let Err(err) = handle.set_state(NodeState::Start) {
+        log::error!("test service set state failed!!! error: {}", err);
+        return false;
+    }
     true
 }
@@ -2,17 +2,16 @@
 //! TestConf
 //!
 
-use parking_lot::RwLock;
-use std::cell::UnsafeCell;
-
+use app_helper::SharedConf;
 use commlib_sys::{NodeConf, XmlReader};
 
-thread_local! {
+lazy_static::lazy_static! {
     ///
-    pub static G_TEST_CONF: UnsafeCell<TestConf> = UnsafeCell::new(TestConf::new());
+    pub static ref G_TEST_CONF: SharedConf<TestConf> = SharedConf::new(TestConf::new());
 }
 
 ///
+#[derive(Clone)]
 pub struct TestConf {
     pub my: NodeConf,
 }
@@ -26,10 +25,9 @@ impl TestConf {
     }
 
     ///
-    pub fn init(&mut self, xr: &RwLock<XmlReader>) {
-        let xr = xr.read();
-        self.my.id = xr.get_u64(vec!["id"], 0);
-        self.my.addr = xr.get_string(vec!["addr"], "");
-        self.my.port = xr.get_u64(vec!["port"], 0) as u16;
+    pub fn init(&mut self, xr: &XmlReader) {
+        self.my.id = xr.get_u64_by_path("id", 0);
+        self.my.addr = xr.get_by_path("addr", "");
+        self.my.port = xr.get_typed_by_path::<u16>("port", 0);
     }
 }
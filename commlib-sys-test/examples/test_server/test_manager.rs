@@ -42,5 +42,7 @@ fn send_encrypt_token(proxy: &NetProxy, hd: ConnId) {
     };
 
     // send encrypt key
-    proxy.send_proto(hd, proto::EnumMsgType::EncryptToken as CmdId, &msg);
+    if let Err(err) = proxy.send_proto_hd(hd, proto::EnumMsgType::EncryptToken as CmdId, &msg) {
+        log::error!("[hd={}] send encrypt token failed!!! error: {}", hd, err);
+    }
 }
@@ -11,7 +11,7 @@
 
 use app_helper::Startup;
 use commlib_sys::service_net::TcpConn;
-use commlib_sys::{connect_to_tcp_server, data_schema, G_SERVICE_NET};
+use commlib_sys::{connect_to_tcp_server_ex, data_schema, G_SERVICE_NET};
 use commlib_sys::{ConnId, NetPacketGuard, ServiceRs};
 use std::sync::Arc;
 
@@ -22,6 +22,11 @@ use crate::config::role_table::{self, RoleTable};
 use crate::config_manager::ConfigManager;
 use crate::config_table::ConfigCid;
 
+lazy_static::lazy_static! {
+    /// 配置表，任意线程可读
+    pub static ref G_DATA_SCHEMA: Arc<data_schema::DataSchemaHandle> = Arc::new(data_schema::DataSchemaHandle::new());
+}
+
 thread_local! {
     ///
     pub static G_APP_STARTUP: std::cell::RefCell<Startup> = {
@@ -42,6 +47,16 @@ pub fn resume(srv: &Arc<CliService>) {
 
 ///
 pub fn exec(srv: &Arc<CliService>) {
+    // 没有节点配置时直接退出
+    match srv.get_handle().xml_config() {
+        Ok(xr) => app_helper::with_conf_mut!(G_CLI_CONF, cfg, { cfg.init(&xr) }),
+        Err(err) => {
+            log::error!("cli service init failed!!! error: {}", err);
+            srv.get_handle().quit_service();
+            return;
+        }
+    }
+
     // pre-startup, main manager init
     G_MAIN.with(|g| {
         let mut main_manager = g.borrow_mut();
@@ -52,6 +67,8 @@ pub fn exec(srv: &Arc<CliService>) {
     let srv2 = srv.clone();
     G_APP_STARTUP.with(|g| {
         let mut startup = g.borrow_mut();
+        // 失败重试由 cli service 的定时器调度
+        startup.set_retry_service(srv);
         //配置文件读取
         startup.add_step("load config table", move || do_load_config_data(&srv2));
         //
@@ -81,40 +98,37 @@ pub fn do_connect_to_test_server(srv: &Arc<CliService>) -> bool {
 
     let conn_fn = |conn: Arc<TcpConn>| {
         let hd = conn.hd;
-        log::info!("[hd={}] conn_fn", hd);
+        log::info!("[hd={}] conn_fn to {}", hd, conn.remote_addr());
 
         //
         G_MAIN.with(|g| {
             let mut cli_manager = g.borrow_mut();
 
             let push_encrypt_token = false;
-            cli_manager
-                .proxy
-                .on_incomming_conn(conn.as_ref(), push_encrypt_token);
+            cli_manager.proxy.on_incomming_conn(hd, push_encrypt_token);
         });
     };
 
-    let pkt_fn = |conn: Arc<TcpConn>, pkt: NetPacketGuard| {
-        let hd = conn.hd;
+    let pkt_fn = |hd: ConnId, pkt: NetPacketGuard| {
         log::info!("[hd={}] msg_fn", hd);
 
         G_MAIN.with(|g| {
             let mut main_manager = g.borrow_mut();
-            main_manager.proxy.on_net_packet(conn.as_ref(), pkt);
+            main_manager.proxy.on_net_packet(hd, pkt);
         });
     };
 
-    let close_fn = |hd: ConnId| {
-        log::info!("[hd={}] close_fn", hd);
+    let close_fn = |conn: Arc<TcpConn>| {
+        log::info!("[hd={}] close_fn", conn.hd);
 
         G_MAIN.with(|g| {
             let mut main_manager = g.borrow_mut();
-            main_manager.proxy.on_hd_lost(hd);
+            main_manager.proxy.on_conn_lost(conn.as_ref());
         });
     };
 
     //
-    let hd_opt = connect_to_tcp_server(
+    let hd_opt = connect_to_tcp_server_ex(
         srv,
         "cli",
         raddr.as_str(),
@@ -128,30 +142,37 @@ pub fn do_connect_to_test_server(srv: &Arc<CliService>) -> bool {
     hd_opt.is_some()
 }
 pub fn do_load_config_data(srv: &Arc<CliService>) -> bool {
-    let callback = Box::new(|ds: Box<data_schema::DataSchema>| {
-        // 在闭包函数内处理加载完成后的操作
+    let srv2 = srv.clone();
+    let callback = Box::new(move |ds: Arc<data_schema::DataSchema>| {
+        // 在闭包函数内处理加载完成后的操作, 运行于 service 线程
         println!("Data schema loaded with");
         // 处理 data_schema 对象
-        let mut binding = ConfigManager::get_instance();
-        let mut g = binding.lock().unwrap();
-        let data = &mut *g;
-        data.reload_all(ds);
+        {
+            let binding = ConfigManager::get_instance();
+            let mut g = binding.lock().unwrap();
+            let data = &mut *g;
+            data.reload_all(ds);
+        }
+
+        // 获取配置项映射
+        let role_opt = ConfigManager::get_instance()
+            .lock()
+            .unwrap()
+            .get_table_as::<RoleTable>(ConfigCid::Cid_Role);
+        if let Some(role_binding) = role_opt {
+            let role_ac = role_binding.read().unwrap();
+            let role_cc = &*role_ac;
+            let config_map = role_cc.get_role_configs();
+            for (key, value) in config_map {
+                println!("Key: {}, Name: {}, ID: {}", key, value.name, value.id);
+            }
+        }
+
+        // 加载完成，继续启动步骤
+        resume(&srv2);
     });
-    data_schema::load_data_schema_from_xml(srv, "data\\", callback);
-
-    // 获取配置项映射
-    let binding = ConfigManager::get_instance();
-    let mut ac = binding.lock().unwrap();
-    let cc = &mut *ac;
-    //let ct = cc.get_config_table(ConfigCid::Cid_Role);
-
-    let role_binding = RoleTable::get_instance();
-    let mut role_ac = role_binding.lock().unwrap();
-    let role_cc = &mut *role_ac;
-    let config_map = role_cc.get_role_configs();
-    for (key, value) in config_map {
-        println!("Key: {}, Name: {}, ID: {}", key, value.name, value.id);
-    }
+    data_schema::load_data_schema_from_xml(srv, &G_DATA_SCHEMA, "data", true, callback);
 
-    true
+    // 挂起，等待加载线程完成
+    false
 }
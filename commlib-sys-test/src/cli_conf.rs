@@ -2,19 +2,19 @@
 //! CliConf
 //!
 
-use parking_lot::RwLock;
-use std::cell::UnsafeCell;
+use app_helper::SharedConf;
+use commlib_sys::{NodeConf, XmlReader, NODE_CONF_DEFAULT_NAME};
 
-use commlib_sys::{NodeConf, XmlReader};
-
-thread_local! {
+lazy_static::lazy_static! {
     ///
-    pub static G_CLI_CONF: UnsafeCell<CliConf> = UnsafeCell::new(CliConf::new());
+    pub static ref G_CLI_CONF: SharedConf<CliConf> = SharedConf::new(CliConf::new());
 }
 
 ///
+#[derive(Clone)]
 pub struct CliConf {
     pub remote: NodeConf,
+    pub remotes: hashbrown::HashMap<String, NodeConf>, // <remote name="gate" addr="..." port="..."/>
 }
 
 impl CliConf {
@@ -22,14 +22,31 @@ impl CliConf {
     pub fn new() -> CliConf {
         CliConf {
             remote: NodeConf::new(),
+            remotes: hashbrown::HashMap::new(),
         }
     }
 
     ///
-    pub fn init(&mut self, xr: &RwLock<XmlReader>) {
-        let xr = xr.read();
-        self.remote.id = xr.get_u64(vec!["id"], 0);
-        self.remote.addr = xr.get_string(vec!["addr"], "");
-        self.remote.port = xr.get_u64(vec!["port"], 0) as u16;
+    pub fn init(&mut self, xr: &XmlReader) {
+        self.remote.id = xr.get_u64_by_path("id", 0);
+        self.remote.addr = xr.get_by_path("addr", "");
+        self.remote.port = xr.get_typed_by_path::<u16>("port", 0);
+
+        self.remotes = NodeConf::read_named_list(xr, "remote");
+
+        // 兼容旧配置：顶层的 addr/port 作为 "default"
+        if !self.remote.addr.is_empty() && !self.remotes.contains_key(NODE_CONF_DEFAULT_NAME) {
+            self.remotes
+                .insert(NODE_CONF_DEFAULT_NAME.to_owned(), self.remote.clone());
+        } else if let Some(remote) = self.remotes.get(NODE_CONF_DEFAULT_NAME) {
+            if self.remote.addr.is_empty() {
+                self.remote = remote.clone();
+            }
+        }
+    }
+
+    /// 按名字查找远端节点，未命名的节点为 "default"
+    pub fn remote(&self, name: &str) -> Option<&NodeConf> {
+        self.remotes.get(name)
     }
 }
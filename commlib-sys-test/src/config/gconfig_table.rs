@@ -1,15 +1,14 @@
 use commlib_sys::data_schema::DataSchema;
 use commlib_sys::ServiceRs;
 
+use crate::config_manager::ConfigManager;
 use crate::config_table::ConfigCid;
 use crate::config_table::ConfigTable;
+use std::any::Any;
 use std::cmp::Eq;
 use std::cmp::PartialEq;
 use std::sync::Arc;
-use std::sync::Mutex;
-lazy_static::lazy_static! {
-     pub static ref G_CONFIG: Arc<Mutex<GConfigTable>> = Arc::new(Mutex::new(GConfigTable::new()));
-}
+use std::sync::RwLock;
 impl ConfigTable for GConfigTable {
     fn get_cid(&self) -> ConfigCid {
         ConfigCid::Cid_Game
@@ -21,18 +20,65 @@ impl ConfigTable for GConfigTable {
         return v;
     }
     //加载配置
-    fn load(&mut self, ds: Box<DataSchema>) -> bool {
+    fn load(&mut self, ds: Arc<DataSchema>) -> bool {
         if let Some(table) = ds.get_table("gconfig") {
-            for (i, v) in table.rows.iter().enumerate() {
-                //   if let Some(name) = table.get_value::<String>(i, "NormalMissionCount") {
-                //      log::info!("name:{:?}", name);
-                //   }
+            for row in table.iter_rows() {
+                let mut entry = GConfigEntry::default();
+                if let Some(index) = row.get_typed::<u32>("index") {
+                    entry.index = index;
+                }
+                if let Some(name) = row.get_typed::<String>("name") {
+                    entry.name = name;
+                }
+                if let Some(value) = row.get_typed::<String>("value") {
+                    entry.value = value;
+                }
+                self.entries.push(entry);
             }
             return true;
         }
         return false;
     }
-    fn clear(&mut self) {}
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        let mut names = std::collections::HashSet::new();
+        for entry in &self.entries {
+            if entry.index == 0 {
+                errors.push(format!("gconfig name({}): index is 0", entry.name));
+            }
+            if entry.name.is_empty() {
+                errors.push(format!("gconfig index({}): name is empty", entry.index));
+            } else if !names.insert(entry.name.as_str()) {
+                errors.push(format!("gconfig name({}): duplicated", entry.name));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn snapshot(&mut self) -> Option<Box<dyn Any + Send>> {
+        Some(Box::new(std::mem::take(&mut self.entries)))
+    }
+
+    fn rollback(&mut self, snapshot: Box<dyn Any + Send>) {
+        if let Ok(entries) = snapshot.downcast::<Vec<GConfigEntry>>() {
+            self.entries = *entries;
+        }
+    }
+}
+///
+#[derive(Debug, Default, Clone, Eq, PartialEq, Hash)]
+pub struct GConfigEntry {
+    pub index: u32,
+    pub name: String,
+    pub value: String,
 }
 ///
 #[derive(Debug, Eq, PartialEq, Hash)]
@@ -40,6 +86,7 @@ pub struct GConfigTable {
     pub id: u32,
     pub a1: u32,
     pub s1: String,
+    pub entries: Vec<GConfigEntry>,
 }
 impl GConfigTable {
     pub fn new() -> Self {
@@ -47,9 +94,15 @@ impl GConfigTable {
             id: 0,
             a1: 0,
             s1: String::new(),
+            entries: Vec::new(),
         }
     }
-    pub fn get_instance() -> Arc<Mutex<GConfigTable>> {
-        G_CONFIG.clone()
+    #[deprecated(note = "use ConfigManager::get_table_as")]
+    pub fn get_instance() -> Arc<RwLock<GConfigTable>> {
+        ConfigManager::get_instance()
+            .lock()
+            .unwrap()
+            .get_table_as::<GConfigTable>(ConfigCid::Cid_Game)
+            .expect("GConfigTable not registered")
     }
 }
@@ -1,14 +1,13 @@
 use commlib_sys::data_schema::DataSchema;
 use hashbrown::HashMap;
+use std::any::Any;
 use std::cmp::Eq;
 use std::cmp::PartialEq;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, RwLock};
 
+use crate::config_manager::ConfigManager;
 use crate::config_table::ConfigCid;
 use crate::config_table::ConfigTable;
-lazy_static::lazy_static! {
-     pub static ref ROLE_CONFIG: Arc<Mutex<RoleTable>> = Arc::new(Mutex::new(RoleTable::new()));
-}
 impl ConfigTable for RoleTable {
     fn get_cid(&self) -> ConfigCid {
         ConfigCid::Cid_Role
@@ -21,16 +20,16 @@ impl ConfigTable for RoleTable {
     }
 
     //加载配置
-    fn load(&mut self, ds: Box<DataSchema>) -> bool {
+    fn load(&mut self, ds: Arc<DataSchema>) -> bool {
         if let Some(table) = ds.get_table("roletable") {
-            for (i, v) in table.rows.iter().enumerate() {
+            for row in table.iter_rows() {
                 let mut conf = RoleConfig::new();
-                if let Some(id) = table.get_value::<u32>(i, "id") {
+                if let Some(id) = row.get_typed::<u32>("id") {
                     conf.id = id;
                     log::info!("id:{:?}", id);
                 }
 
-                if let Some(name) = table.get_value::<String>(i, "name") {
+                if let Some(name) = row.get_typed::<String>("name") {
                     conf.name = name;
                 }
                 self.datas.insert(conf.id, conf);
@@ -40,6 +39,33 @@ impl ConfigTable for RoleTable {
         return false;
     }
     fn clear(&mut self) {}
+
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        for (key, conf) in &self.datas {
+            if conf.id == 0 {
+                errors.push(format!("roletable row(key={}): id is 0", key));
+            }
+            if conf.name.is_empty() {
+                errors.push(format!("roletable id({}): name is empty", conf.id));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn snapshot(&mut self) -> Option<Box<dyn Any + Send>> {
+        Some(Box::new(std::mem::take(&mut self.datas)))
+    }
+
+    fn rollback(&mut self, snapshot: Box<dyn Any + Send>) {
+        if let Ok(datas) = snapshot.downcast::<HashMap<u32, RoleConfig>>() {
+            self.datas = *datas;
+        }
+    }
 }
 ///
 #[derive(Debug, Eq, PartialEq, Hash)]
@@ -67,8 +93,13 @@ impl RoleTable {
             datas: HashMap::new(),
         }
     }
-    pub fn get_instance() -> Arc<Mutex<RoleTable>> {
-        ROLE_CONFIG.clone()
+    #[deprecated(note = "use ConfigManager::get_table_as")]
+    pub fn get_instance() -> Arc<RwLock<RoleTable>> {
+        ConfigManager::get_instance()
+            .lock()
+            .unwrap()
+            .get_table_as::<RoleTable>(ConfigCid::Cid_Role)
+            .expect("RoleTable not registered")
     }
     pub fn get_role_config(&self, id: u32) -> Option<&RoleConfig> {
         self.datas.get(&id)
@@ -1,62 +1,499 @@
 use commlib_sys::data_schema::DataSchema;
 use hashbrown::{HashMap, HashSet};
+use std::any::Any;
 use std::sync::{Arc, Mutex, RwLock};
 
 use crate::{
     config::{GConfigTable, RoleTable},
     config_table::{ConfigCid, ConfigTable},
 };
+
+/// 配置重新加载完成的通知
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfigChangeEvent {
+    pub cid: ConfigCid,
+    pub timestamp: std::time::SystemTime,
+}
+
+/// 配置变化回调
+pub type ConfigChangeFn = Box<dyn Fn(ConfigChangeEvent) + Send + Sync>;
+
+type ConfigSubscriber = Arc<dyn Fn(ConfigChangeEvent) + Send + Sync>;
+
 lazy_static::lazy_static! {
      pub static ref CONFIG_MANAGER: Arc<Mutex<ConfigManager>> = Arc::new(Mutex::new(ConfigManager::new()));
 }
 pub struct ConfigManager {
-    config_tables: HashMap<ConfigCid, Arc<Mutex<dyn ConfigTable>>>,
+    config_tables: HashMap<ConfigCid, Arc<RwLock<dyn ConfigTable + Send + Sync>>>,
+    typed_tables: HashMap<ConfigCid, Box<dyn Any + Send + Sync>>, // 注册时的 Arc<RwLock<T>>, 供 get_table_as 取回
+    subscribers: HashMap<ConfigCid, Vec<ConfigSubscriber>>,
+    all_subscribers: Vec<ConfigSubscriber>,
+    versions: HashMap<ConfigCid, u64>, // 每次重新加载成功 +1
 }
-unsafe impl Sync for ConfigManager {}
 impl ConfigManager {
     pub fn new() -> Self {
         ConfigManager {
             config_tables: HashMap::new(),
+            typed_tables: HashMap::new(),
+            subscribers: HashMap::new(),
+            all_subscribers: Vec::new(),
+            versions: HashMap::new(),
         }
     }
     pub fn get_instance() -> Arc<Mutex<ConfigManager>> {
         CONFIG_MANAGER.clone()
     }
-    pub fn register(&mut self, config: Arc<Mutex<dyn ConfigTable>>) {
-        let mut ac = config.lock().unwrap();
-        self.config_tables.insert(ac.get_cid(), config.clone());
+    /// 注册配置，配置必须是 Send + Sync（由 ConfigTable 的 trait 约束保证，见 ConfigTable 的文档测试）
+    pub fn register<T: ConfigTable>(&mut self, config: Arc<RwLock<T>>) {
+        let cid = config.read().unwrap().get_cid();
+        if self.config_tables.contains_key(&cid) {
+            log::warn!("[config.cid ={:?}] registered again, replace old", cid);
+        }
+        self.config_tables.insert(cid, config.clone());
+        self.typed_tables.insert(cid, Box::new(config));
+    }
+
+    /// 按具体类型取回注册的配置, cid 未注册或类型不符时返回 None
+    pub fn get_table_as<T: ConfigTable + 'static>(&self, cid: ConfigCid) -> Option<Arc<RwLock<T>>> {
+        self.typed_tables
+            .get(&cid)?
+            .downcast_ref::<Arc<RwLock<T>>>()
+            .cloned()
     }
 
     pub fn init(&mut self) {
-        self.register(GConfigTable::get_instance());
-        self.register(RoleTable::get_instance());
+        self.register(Arc::new(RwLock::new(GConfigTable::new())));
+        self.register(Arc::new(RwLock::new(RoleTable::new())));
+    }
+
+    /// 订阅 cid 对应配置的重新加载
+    pub fn subscribe(&mut self, cid: ConfigCid, cb: ConfigChangeFn) {
+        self.subscribers.entry(cid).or_default().push(Arc::from(cb));
+    }
+
+    /// 订阅所有配置的重新加载
+    pub fn subscribe_all(&mut self, cb: ConfigChangeFn) {
+        self.all_subscribers.push(Arc::from(cb));
+    }
+
+    /// 配置版本号, 未加载过为 0
+    pub fn config_version(&self, cid: ConfigCid) -> u64 {
+        self.versions.get(&cid).copied().unwrap_or(0)
     }
 
-    pub fn reload_tables(&mut self, ds: Box<DataSchema>, tables: HashSet<String>) {
-        let mut reloads: HashSet<Box<dyn ConfigTable>> = HashSet::new();
-        for config in tables {
-            //   if let Some(it) = self.config_tables.get(&config) {
-            //todo
-            //  reloads.insert(Box::new(it));
-            //   }
+    /// 所有已加载过的配置的版本号
+    pub fn all_versions(&self) -> HashMap<ConfigCid, u64> {
+        self.versions.clone()
+    }
+
+    // 先更新版本号再通知, 回调中读到的是新版本
+    fn mark_changed(&mut self, cids: &[ConfigCid]) {
+        for cid in cids {
+            *self.versions.entry(*cid).or_insert(0) += 1;
         }
-        for mut t in reloads {
-            t.clear();
-            t.load(ds.clone());
+        self.notify_changed(cids);
+    }
+
+    // 调用时不持有配置的锁, 回调中可以读取配置
+    fn notify_changed(&self, cids: &[ConfigCid]) {
+        let timestamp = std::time::SystemTime::now();
+        for cid in cids {
+            // 快照, 避免回调中修改订阅者
+            let mut callbacks: Vec<ConfigSubscriber> =
+                self.subscribers.get(cid).cloned().unwrap_or_default();
+            callbacks.extend(self.all_subscribers.iter().cloned());
+
+            let ev = ConfigChangeEvent {
+                cid: *cid,
+                timestamp,
+            };
+            for cb in callbacks {
+                cb(ev);
+            }
         }
     }
-    pub fn reload_all(&mut self, ds: Box<DataSchema>) -> bool {
-        for (_, config) in &mut self.config_tables {
-            let mut ac = config.lock().unwrap();
-            ac.clear();
+
+    /// 只重新加载关注了变化表的配置, 返回加载失败的配置
+    pub fn reload_tables(
+        &mut self,
+        ds: Arc<DataSchema>,
+        tables: HashSet<String>,
+    ) -> Result<(), Vec<ConfigCid>> {
+        let mut failed = Vec::new();
+        let mut changed = Vec::new();
+        for (cid, config) in &self.config_tables {
+            let mut ac = config.write().unwrap();
+            if !ac.get_cared_table().iter().any(|t| tables.contains(t)) {
+                continue;
+            }
+            if load_and_validate(*cid, &mut *ac, &ds) {
+                changed.push(*cid);
+            } else {
+                failed.push(*cid);
+            }
         }
-        for (_, config) in &mut self.config_tables {
-            let mut ac = config.lock().unwrap();
-            if !ac.load(ds.clone()) {
-                log::error!("[config.cid ={:?}] load err", ac.get_cid());
-                //  return false;
+        self.mark_changed(&changed);
+
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(failed)
+        }
+    }
+    /// 只重新加载 cid 对应的配置, 返回加载结果
+    pub fn reload_table(&mut self, cid: ConfigCid, ds: &Arc<DataSchema>) -> bool {
+        let config = match self.config_tables.get(&cid) {
+            Some(config) => config,
+            None => {
+                log::error!("[config.cid ={:?}] not registered", cid);
+                return false;
+            }
+        };
+
+        {
+            let mut ac = config.write().unwrap();
+            if !load_and_validate(cid, &mut *ac, ds) {
+                return false;
             }
         }
+        self.mark_changed(&[cid]);
+        true
+    }
+
+    /// 按变化的文件重新加载, 文件名（不含目录和扩展名）即表名
+    pub fn reload_tables_for_files(
+        &mut self,
+        changed_files: &[&str],
+        ds: &Arc<DataSchema>,
+    ) -> Result<(), Vec<ConfigCid>> {
+        let tables = changed_files
+            .iter()
+            .filter_map(|file| std::path::Path::new(file).file_stem())
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .collect();
+        self.reload_tables(Arc::clone(ds), tables)
+    }
+
+    pub fn reload_all(&mut self, ds: Arc<DataSchema>) -> bool {
+        let mut changed = Vec::new();
+        for (cid, config) in &mut self.config_tables {
+            let mut ac = config.write().unwrap();
+            if load_and_validate(*cid, &mut *ac, &ds) {
+                changed.push(*cid);
+            }
+        }
+        self.mark_changed(&changed);
         return true;
     }
+
+    /// 加载并校验所有配置（不记录版本, 不通知）, 返回所有错误, 用于启动前检查配置
+    pub fn validate_all(&mut self, ds: Arc<DataSchema>) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        for (cid, config) in &self.config_tables {
+            let mut ac = config.write().unwrap();
+            ac.clear();
+            if !ac.load(Arc::clone(&ds)) {
+                errors.push(format!("[config.cid ={:?}] load err", cid));
+            } else if let Err(errs) = ac.validate() {
+                errors.extend(
+                    errs.into_iter()
+                        .map(|err| format!("[config.cid ={:?}] {}", cid, err)),
+                );
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+// 加载并校验, 失败时恢复加载前的数据
+fn load_and_validate(cid: ConfigCid, ac: &mut dyn ConfigTable, ds: &Arc<DataSchema>) -> bool {
+    let snapshot = ac.snapshot();
+    ac.clear();
+
+    let ok = if !ac.load(Arc::clone(ds)) {
+        log::error!("[config.cid ={:?}] load err", cid);
+        false
+    } else if let Err(errors) = ac.validate() {
+        log::error!("[config.cid ={:?}] validate err: {:?}", cid, errors);
+        false
+    } else {
+        true
+    };
+
+    if !ok {
+        if let Some(snapshot) = snapshot {
+            ac.clear();
+            ac.rollback(snapshot);
+        }
+    }
+    ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FakeTable {
+        cid: ConfigCid,
+        cared: &'static str,
+        loads: usize,
+        ok: bool,
+    }
+
+    impl ConfigTable for FakeTable {
+        fn get_cid(&self) -> ConfigCid {
+            self.cid
+        }
+        fn get_cared_table(&self) -> Vec<String> {
+            vec![self.cared.to_owned()]
+        }
+        fn load(&mut self, _ds: Arc<DataSchema>) -> bool {
+            self.loads += 1;
+            self.ok
+        }
+        fn clear(&mut self) {}
+    }
+
+    fn fake(cid: ConfigCid, cared: &'static str, ok: bool) -> Arc<RwLock<FakeTable>> {
+        Arc::new(RwLock::new(FakeTable {
+            cid,
+            cared,
+            loads: 0,
+            ok,
+        }))
+    }
+
+    #[test]
+    fn manager_is_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ConfigManager>();
+    }
+
+    #[test]
+    fn reload_only_cared_tables() {
+        let role = fake(ConfigCid::Cid_Role, "role", true);
+        let game = fake(ConfigCid::Cid_Game, "game", false);
+
+        let mut mgr = ConfigManager::new();
+        mgr.register(role.clone());
+        mgr.register(game.clone());
+
+        let changed: HashSet<String> = ["role".to_owned()].into_iter().collect();
+        assert!(mgr
+            .reload_tables(Arc::new(DataSchema::new()), changed)
+            .is_ok());
+        assert_eq!(role.read().unwrap().loads, 1);
+        assert_eq!(game.read().unwrap().loads, 0);
+
+        let changed: HashSet<String> = ["game".to_owned()].into_iter().collect();
+        assert_eq!(
+            mgr.reload_tables(Arc::new(DataSchema::new()), changed),
+            Err(vec![ConfigCid::Cid_Game])
+        );
+        assert_eq!(role.read().unwrap().loads, 1);
+    }
+
+    #[test]
+    fn change_notification() {
+        let role = fake(ConfigCid::Cid_Role, "role", true);
+        let game = fake(ConfigCid::Cid_Game, "game", false);
+
+        let mut mgr = ConfigManager::new();
+        mgr.register(role.clone());
+        mgr.register(game.clone());
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let ev = events.clone();
+        let r = role.clone();
+        mgr.subscribe(
+            ConfigCid::Cid_Role,
+            Box::new(move |e| {
+                // 回调时配置未被锁住
+                assert!(r.try_write().is_ok());
+                ev.lock().unwrap().push(("role", e.cid));
+            }),
+        );
+        let ev = events.clone();
+        mgr.subscribe(
+            ConfigCid::Cid_Game,
+            Box::new(move |e| ev.lock().unwrap().push(("game", e.cid))),
+        );
+        let ev = events.clone();
+        mgr.subscribe_all(Box::new(move |e| ev.lock().unwrap().push(("all", e.cid))));
+
+        let ds = Arc::new(DataSchema::new());
+        assert!(mgr.reload_table(ConfigCid::Cid_Role, &ds));
+        assert!(!mgr.reload_table(ConfigCid::Cid_Game, &ds)); // 加载失败不通知
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![("role", ConfigCid::Cid_Role), ("all", ConfigCid::Cid_Role)]
+        );
+
+        events.lock().unwrap().clear();
+        mgr.reload_all(ds);
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![("role", ConfigCid::Cid_Role), ("all", ConfigCid::Cid_Role)]
+        );
+    }
+
+    #[test]
+    fn config_versions() {
+        let role = fake(ConfigCid::Cid_Role, "role", true);
+        let game = fake(ConfigCid::Cid_Game, "game", false);
+
+        let mut mgr = ConfigManager::new();
+        mgr.register(role.clone());
+        mgr.register(game.clone());
+        assert_eq!(mgr.config_version(ConfigCid::Cid_Role), 0);
+        assert!(mgr.all_versions().is_empty());
+
+        let ds = Arc::new(DataSchema::new());
+        mgr.reload_all(ds.clone());
+        assert!(mgr.reload_table(ConfigCid::Cid_Role, &ds));
+        assert!(!mgr.reload_table(ConfigCid::Cid_Game, &ds));
+
+        assert_eq!(mgr.config_version(ConfigCid::Cid_Role), 2);
+        assert_eq!(mgr.config_version(ConfigCid::Cid_Game), 0);
+        assert_eq!(
+            mgr.all_versions(),
+            [(ConfigCid::Cid_Role, 2)].into_iter().collect()
+        );
+    }
+
+    #[test]
+    fn validate_failure_keeps_previous_data() {
+        use crate::config::role_table::RoleConfig;
+        use commlib_sys::data_schema::DataTable;
+
+        let role = Arc::new(RwLock::new(RoleTable::new()));
+        let mut mgr = ConfigManager::new();
+        mgr.register(role.clone());
+
+        let mut good = RoleConfig::new();
+        good.id = 1;
+        good.name = "role1".to_owned();
+        role.write().unwrap().datas.insert(1, good);
+
+        // 加载成功但 name 为空, 校验失败
+        let mut table = DataTable::new(
+            "roletable".to_owned(),
+            vec!["id".to_owned(), "name".to_owned()],
+        );
+        table.set_data(vec![vec!["2".to_owned(), String::new()]]);
+        let mut ds = DataSchema::new();
+        ds.tables.insert("roletable".to_owned(), table);
+
+        assert!(!mgr.reload_table(ConfigCid::Cid_Role, &Arc::new(ds)));
+        let role = role.read().unwrap();
+        assert_eq!(role.datas.len(), 1);
+        assert_eq!(role.get_role_config(1).unwrap().name, "role1");
+        assert_eq!(mgr.config_version(ConfigCid::Cid_Role), 0);
+    }
+
+    #[test]
+    fn validate_all_collects_errors() {
+        use commlib_sys::data_schema::DataTable;
+
+        let mut mgr = ConfigManager::new();
+        mgr.register(Arc::new(RwLock::new(RoleTable::new())));
+        mgr.register(fake(ConfigCid(1001), "shop", false));
+
+        let mut table = DataTable::new(
+            "roletable".to_owned(),
+            vec!["id".to_owned(), "name".to_owned()],
+        );
+        table.set_data(vec![vec!["2".to_owned(), String::new()]]);
+        let mut ds = DataSchema::new();
+        ds.tables.insert("roletable".to_owned(), table);
+
+        let mut errors = mgr.validate_all(Arc::new(ds)).unwrap_err();
+        errors.sort();
+        assert_eq!(errors.len(), 2);
+        assert!(
+            errors[1].starts_with("[config.cid =ConfigCid(1)] roletable"),
+            "{}",
+            errors[1]
+        );
+        assert_eq!(errors[0], "[config.cid =ConfigCid(1001)] load err");
+        assert_eq!(mgr.config_version(ConfigCid::Cid_Role), 0);
+    }
+
+    #[test]
+    fn typed_retrieval() {
+        // 下游自定义的 cid 和配置类型
+        const CID_SHOP: ConfigCid = ConfigCid(1001);
+        struct ShopTable {
+            items: Vec<u32>,
+        }
+        impl ConfigTable for ShopTable {
+            fn get_cid(&self) -> ConfigCid {
+                CID_SHOP
+            }
+            fn get_cared_table(&self) -> Vec<String> {
+                vec!["shop".to_owned()]
+            }
+            fn load(&mut self, _ds: Arc<DataSchema>) -> bool {
+                self.items.push(1);
+                true
+            }
+            fn clear(&mut self) {
+                self.items.clear();
+            }
+        }
+
+        let mut mgr = ConfigManager::new();
+        mgr.register(Arc::new(RwLock::new(ShopTable { items: Vec::new() })));
+        mgr.register(fake(ConfigCid::Cid_Role, "role", true));
+        assert!(mgr.reload_table(CID_SHOP, &Arc::new(DataSchema::new())));
+
+        let shop = mgr.get_table_as::<ShopTable>(CID_SHOP).unwrap();
+        assert_eq!(shop.read().unwrap().items, vec![1]);
+        assert_eq!(mgr.config_version(CID_SHOP), 1);
+
+        // 类型不符或未注册
+        assert!(mgr.get_table_as::<FakeTable>(CID_SHOP).is_none());
+        assert!(mgr.get_table_as::<ShopTable>(ConfigCid::Cid_Role).is_none());
+        assert!(mgr.get_table_as::<ShopTable>(ConfigCid(1002)).is_none());
+        assert_eq!(
+            mgr.get_table_as::<FakeTable>(ConfigCid::Cid_Role)
+                .unwrap()
+                .read()
+                .unwrap()
+                .cared,
+            "role"
+        );
+    }
+
+    #[test]
+    fn reload_single_table() {
+        let role = fake(ConfigCid::Cid_Role, "role", true);
+        let game = fake(ConfigCid::Cid_Game, "game", false);
+
+        let mut mgr = ConfigManager::new();
+        mgr.register(role.clone());
+        let ds = Arc::new(DataSchema::new());
+        assert!(mgr.reload_table(ConfigCid::Cid_Role, &ds));
+        assert!(!mgr.reload_table(ConfigCid::Cid_Game, &ds)); // 未注册
+
+        mgr.register(game.clone());
+        assert!(!mgr.reload_table(ConfigCid::Cid_Game, &ds));
+        assert_eq!(
+            (role.read().unwrap().loads, game.read().unwrap().loads),
+            (1, 1)
+        );
+
+        assert!(mgr
+            .reload_tables_for_files(&["data/role.xml", "data/item.xml"], &ds)
+            .is_ok());
+        assert_eq!(
+            (role.read().unwrap().loads, game.read().unwrap().loads),
+            (2, 1)
+        );
+    }
 }
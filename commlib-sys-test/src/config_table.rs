@@ -5,24 +5,79 @@ use std::cmp::PartialEq;
 use std::fmt::Debug;
 use std::hash::Hash;
 use std::hash::Hasher;
-use std::sync::{Arc, Mutex};
-#[derive(Eq, Hash, PartialEq, std::fmt::Debug)]
-pub enum ConfigCid {
-    Cid_Role = 1,
-    Cid_Game = 2,
+use std::sync::{Arc, RwLock};
+/// 配置 id, 下游 crate 可以用 ConfigCid(n) 定义自己的配置, 不需要修改这里
+#[derive(Eq, Hash, PartialEq, Clone, Copy, std::fmt::Debug)]
+pub struct ConfigCid(pub u32);
+
+#[allow(non_upper_case_globals)]
+impl ConfigCid {
+    pub const Cid_Role: ConfigCid = ConfigCid(1);
+    pub const Cid_Game: ConfigCid = ConfigCid(2);
 }
 
+/// 配置表接口，要求 Send + Sync：ConfigManager 跨线程持有并重新加载配置
+///
+/// ```
+/// # use std::sync::{Arc, Mutex};
+/// # use commlib_sys::data_schema::DataSchema;
+/// # use commlib_sys_test::config_table::{ConfigCid, ConfigTable};
+/// struct CountTable(Arc<Mutex<u32>>);
+/// impl ConfigTable for CountTable {
+///     fn get_cid(&self) -> ConfigCid {
+///         ConfigCid(100)
+///     }
+///     fn get_cared_table(&self) -> Vec<String> {
+///         Vec::new()
+///     }
+///     fn load(&mut self, _ds: Arc<DataSchema>) -> bool {
+///         true
+///     }
+///     fn clear(&mut self) {}
+/// }
+/// ```
+///
+/// 同样的实现换成 Rc（不是 Send + Sync）无法编译，因此不能注册到 ConfigManager:
+///
+/// ```compile_fail,E0277
+/// # use std::rc::Rc;
+/// # use std::sync::Arc;
+/// # use commlib_sys::data_schema::DataSchema;
+/// # use commlib_sys_test::config_table::{ConfigCid, ConfigTable};
+/// struct CountTable(Rc<u32>);
+/// impl ConfigTable for CountTable {
+///     fn get_cid(&self) -> ConfigCid {
+///         ConfigCid(100)
+///     }
+///     fn get_cared_table(&self) -> Vec<String> {
+///         Vec::new()
+///     }
+///     fn load(&mut self, _ds: Arc<DataSchema>) -> bool {
+///         true
+///     }
+///     fn clear(&mut self) {}
+/// }
+/// ```
 pub trait ConfigTable: Send + Sync + Any {
     fn get_cid(&self) -> ConfigCid;
     //关注的table
     fn get_cared_table(&self) -> Vec<String>;
     //加载配置
-    fn load(&mut self, ds: Box<DataSchema>) -> bool;
+    fn load(&mut self, ds: Arc<DataSchema>) -> bool;
     fn clear(&mut self);
+    //加载后的数据校验, 返回所有错误
+    fn validate(&self) -> Result<(), Vec<String>> {
+        Ok(())
+    }
+    //重新加载前取出当前数据, 加载或校验失败时用 rollback 恢复
+    fn snapshot(&mut self) -> Option<Box<dyn Any + Send>> {
+        None
+    }
+    fn rollback(&mut self, _snapshot: Box<dyn Any + Send>) {}
 }
 
 #[derive(Clone)]
-pub struct WrappedConfigTable(pub Arc<Mutex<Box<dyn ConfigTable + Send>>>);
+pub struct WrappedConfigTable(pub Arc<RwLock<Box<dyn ConfigTable + Send + Sync>>>);
 
 impl PartialEq for WrappedConfigTable {
     fn eq(&self, other: &Self) -> bool {
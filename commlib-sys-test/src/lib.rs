@@ -0,0 +1,5 @@
+//!
+//! commlib-sys-test: 需要在 lib target 中做文档测试的部分（配置表接口），可执行程序经 `commlib_sys_test::` 引用
+//!
+
+pub mod config_table;
@@ -1,4 +1,6 @@
 use app_helper::App;
+use commlib_sys::data_schema;
+use std::sync::Arc;
 
 pub mod proto {
     include!("../protos/out/proto.rs");
@@ -12,7 +14,7 @@ mod cli_manager;
 //配置文件
 mod config;
 mod config_manager;
-mod config_table;
+use commlib_sys_test::config_table;
 fn main() {
     // panic hook
     std::panic::set_hook(Box::new(|panic_info| {
@@ -32,7 +34,19 @@ fn main() {
 
     //
     let arg_vec: Vec<std::ffi::OsString> = std::env::args_os().collect();
-    let mut app = App::new(&arg_vec, "clisrv");
+    let mut app = App::new(&arg_vec, "clisrv").with_env_override("CLISRV");
+
+    // --dry-run: 校验配置表
+    app.add_config_validator("config table", || {
+        let handle = Arc::new(data_schema::DataSchemaHandle::new());
+        let loader =
+            data_schema::DataSchemaLoader::from_xml_dir(&handle, "data", true, Box::new(|_| {}));
+        let ds = loader.read_schema()?;
+
+        let mut manager = config_manager::ConfigManager::new();
+        manager.init();
+        manager.validate_all(Arc::new(ds)).map(|_| Vec::new())
+    });
     app.init(
         || cli_service::G_CLI_SERVICE.as_ref(),
         || {
@@ -7,7 +7,13 @@ pub mod ffi_sig {
         type SignalCallback = crate::SignalCallback;
 
         #[namespace = "commlib"]
-        fn init_signal_handlers(cb1: SignalCallback, cb2: SignalCallback, cb3: SignalCallback);
+        fn init_signal_handlers(
+            cb_int: SignalCallback,
+            cb_term: SignalCallback,
+            cb_hup: SignalCallback,
+            cb_usr1: SignalCallback,
+            cb_usr2: SignalCallback,
+        );
 
         #[namespace = "commlib"]
         fn new_abc();
@@ -4,7 +4,7 @@
 
 use std::cell::UnsafeCell;
 use std::ops::AddAssign;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use crate::hash_wheel_timer::{self, ClosureTimer, TimerReturn::Reschedule};
 use crate::ServiceRs;
@@ -139,6 +139,16 @@ impl Clock {
         }));
     }
 
+    /// 距离下一个定时器需要处理的时间，没有定时器时返回 None
+    pub fn next_timeout() -> Option<Duration> {
+        with_tls!(G_CLOCK, clock, {
+            clock.wheel_timer.next_timeout().map(|d| {
+                let elapsed = clock.last_time.elapsed().unwrap_or_default();
+                d.saturating_sub(elapsed)
+            })
+        })
+    }
+
     /// 更新计时器 tick
     pub fn update() {
         with_tls_mut!(G_CLOCK, clock, {
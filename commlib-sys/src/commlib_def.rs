@@ -27,6 +27,7 @@ pub enum SpecialZone {
 
 ///
 #[repr(C)]
+#[derive(Clone)]
 pub struct NodeConf {
     pub id: NodeId,   // 节点 id
     pub addr: String, // 节点 ip
@@ -44,8 +45,38 @@ impl NodeConf {
             index: 0,
         }
     }
+
+    ///
+    pub fn from_xml(&mut self, xr: &XmlReader) {
+        self.id = xr.get_u64(vec!["id"], 0);
+        self.addr = xr.get_string(vec!["addr"], "");
+        self.port = xr.get_u64(vec!["port"], 0) as u16;
+    }
+
+    /// 读取 xr 下所有名为 tag 的子节点，按 name 属性建表，未命名的节点视为 "default"
+    pub fn read_named_list(xr: &XmlReader, tag: &str) -> hashbrown::HashMap<String, NodeConf> {
+        let mut confs = hashbrown::HashMap::new();
+        if let Some(children) = xr.get_children(vec![tag]) {
+            for child in children {
+                let mut name = child.get_string(vec!["name"], "");
+                if name.is_empty() {
+                    name = NODE_CONF_DEFAULT_NAME.to_owned();
+                }
+
+                let mut conf = NodeConf::new();
+                conf.from_xml(child);
+                if confs.insert(name.clone(), conf).is_some() {
+                    log::error!("duplicate {} name: {}", tag, name);
+                }
+            }
+        }
+        confs
+    }
 }
 
+/// 未命名的节点配置
+pub const NODE_CONF_DEFAULT_NAME: &str = "default";
+
 /// 节点配置
 pub const NODE_INDEX_MAX: usize = 16;
 pub const NODE_ID_MIN: usize = 1000;
@@ -150,3 +181,30 @@ impl DbAddr {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_remotes() {
+        let xr = XmlReader::read_content(
+            r#"<cli>
+                <remote name="gate" addr="127.0.0.1" port="7001"/>
+                <remote name="db" addr="10.0.0.2" port="7002" id="3"/>
+                <remote addr="localhost" port="7000"/>
+            </cli>"#,
+        )
+        .unwrap();
+
+        let remotes = NodeConf::read_named_list(&xr, "remote");
+        assert_eq!(remotes.len(), 3);
+        assert_eq!(remotes["gate"].addr, "127.0.0.1");
+        assert_eq!(remotes["gate"].port, 7001);
+        assert_eq!(remotes["db"].id, 3);
+        assert_eq!(remotes[NODE_CONF_DEFAULT_NAME].port, 7000);
+        assert!(remotes.get("world").is_none());
+
+        assert!(NodeConf::read_named_list(&xr, "local").is_empty());
+    }
+}
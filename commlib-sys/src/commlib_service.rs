@@ -3,7 +3,7 @@
 //!
 
 use bytemuck::NoUninit;
-use parking_lot::RwLock;
+use parking_lot::{MappedRwLockReadGuard, RwLock, RwLockReadGuard};
 use std::sync::Arc;
 use std::thread::JoinHandle;
 
@@ -11,16 +11,22 @@ use atomic::{Atomic, Ordering};
 use crossbeam::channel;
 use spdlog::get_current_tid;
 
-use super::G_EXIT_CV;
-use super::{Clock, PinkySwear, StopWatch, XmlReader};
+use super::hash_wheel_timer::TimerId;
+use super::service_call::CallHandlerTable;
+use super::service_channel::{ServiceChannel, ServiceReceiver, ServiceSender};
+use super::service_timer::{ServiceTimerId, ServiceTimers};
+use super::{Clock, NodeId, PinkySwear, StopWatch, XmlReader};
+use super::{G_EXIT_CV, G_PERIODIC_TIMER};
 
-#[derive(Debug, PartialEq, PartialOrd, Copy, Clone, NoUninit)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Copy, Clone, NoUninit)]
 #[repr(u8)]
 pub enum NodeState {
     Idle = 0,  // 空闲
     Init,      // 初始化
     Start,     // 启动中
     Run,       // 正在运行
+    Paused,    // 暂停：不执行任务，保留队列
+    Draining,  // 排空：不再接收新任务，执行完队列中剩余任务后关闭
     Finishing, // 等待完成
     Finish,    // 已完成，等待关闭
     Closing,   // 关闭中
@@ -28,28 +34,103 @@ pub enum NodeState {
     NodeLost,  // 节点丢失（world 管理节点用）
 }
 
+impl NodeState {
+    /// 是否允许从 self 切换到 to（相同状态总是允许）
+    pub fn can_transition_to(self, to: NodeState) -> bool {
+        use NodeState::*;
+        if self == to {
+            return true;
+        }
+        match self {
+            Idle | Init | Start => !matches!(to, Paused | Draining) && to > self,
+            Run => to > Run,
+            Paused => matches!(to, Run | Draining | Closing | Closed | NodeLost),
+            Draining => matches!(to, Closing | Closed | NodeLost),
+            Finishing | Finish | Closing => to > self,
+            Closed => false,
+            NodeLost => matches!(to, Run | Closing | Closed),
+        }
+    }
+}
+
 pub type ServiceFuncType = dyn FnOnce() + Send + Sync; // Note: tait object is always 'static, no need add 'static here
 
-/// Service handle
-pub struct ServiceHandle {
+/// 任务队列过载回调，在投递任务的线程中执行
+pub type ServiceOverloadFn = Arc<dyn Fn(&ServiceStats) + Send + Sync>;
+
+/// 投递任务失败原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceError {
+    QueueFull { depth: usize, limit: usize }, // 队列中等待执行的任务数已达上限
+    Draining,                                 // service 正在排空或已关闭，不再接收新任务
+    InvalidTransition { from: NodeState, to: NodeState }, // 非法的状态切换
+    XmlConfigNotFound { node_id: NodeId },    // 没有提供节点的 xml 配置
+}
+
+impl std::fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ServiceError::QueueFull { depth, limit } => {
+                write!(f, "service queue full: depth={} limit={}", depth, limit)
+            }
+            ServiceError::Draining => write!(f, "service is draining"),
+            ServiceError::InvalidTransition { from, to } => {
+                write!(f, "invalid state transition: {:?} -> {:?}", from, to)
+            }
+            ServiceError::XmlConfigNotFound { node_id } => {
+                write!(f, "node {} xml config not found", node_id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ServiceError {}
+
+/// Service 运行统计
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ServiceStats {
+    pub id: u64,
+    pub state: NodeState,
+    pub pending_tasks: usize,
+    pub processed_tasks: u64,
+    pub idle_ratio: f64,
+    pub wake_count: u64,
+}
+
+/// Service 任务队列：状态、队列及其统计，由 ServiceHandle 持有，
+/// 定时器、消息通道等可以 clone 后长期持有（不需要 &'static ServiceHandle）
+pub struct ServiceQueue {
     pub id: u64,
     pub state: Atomic<NodeState>,
 
     pub tx: channel::Sender<Box<ServiceFuncType>>,
     pub rx: channel::Receiver<Box<ServiceFuncType>>,
-
-    pub clock: Clock,
-
-    pub xml_config: RwLock<XmlReader>,
+    held: parking_lot::Mutex<std::collections::VecDeque<Box<ServiceFuncType>>>, // 暂停期间从队列取出的任务，resume 后先执行
 
     //
     pub tid: Atomic<u64>,
-    pub join_handle_opt: RwLock<Option<JoinHandle<()>>>,
+
+    // 空闲/忙碌时间统计（微秒）和唤醒次数
+    pub idle_us: Atomic<u64>,
+    pub busy_us: Atomic<u64>,
+    pub wake_count: Atomic<u64>,
+
+    // 任务队列统计及过载告警
+    pub processed_tasks: Atomic<u64>,
+    pub overload_threshold: Atomic<usize>,
+    pub overload_interval_ms: Atomic<u64>,
+    pub overload_last_warn_ms: Atomic<u64>, // 距 created 的毫秒数 + 1，0 表示未告警过
+    pub overload_fn: RwLock<Option<ServiceOverloadFn>>,
+
+    // 有界投递的队列容量（0 表示不限制）及历史最大队列深度
+    pub queue_capacity: Atomic<usize>,
+    pub queue_depth_watermark: Atomic<usize>,
+
+    created: std::time::Instant,
 }
 
-impl ServiceHandle {
-    ///
-    pub fn new(id: u64, state: NodeState) -> ServiceHandle {
+impl ServiceQueue {
+    fn new(id: u64, state: NodeState) -> ServiceQueue {
         let (tx, rx) = channel::unbounded::<Box<ServiceFuncType>>();
 
         Self {
@@ -58,13 +139,24 @@ impl ServiceHandle {
 
             tx,
             rx,
+            held: parking_lot::Mutex::new(std::collections::VecDeque::new()),
 
-            clock: Clock::new(),
+            tid: Atomic::new(0_u64),
 
-            xml_config: RwLock::new(XmlReader::new()),
+            idle_us: Atomic::new(0_u64),
+            busy_us: Atomic::new(0_u64),
+            wake_count: Atomic::new(0_u64),
 
-            tid: Atomic::new(0_u64),
-            join_handle_opt: RwLock::new(None),
+            processed_tasks: Atomic::new(0_u64),
+            overload_threshold: Atomic::new(0_usize),
+            overload_interval_ms: Atomic::new(10_000_u64),
+            overload_last_warn_ms: Atomic::new(0_u64),
+            overload_fn: RwLock::new(None),
+
+            queue_capacity: Atomic::new(0_usize),
+            queue_depth_watermark: Atomic::new(0_usize),
+
+            created: std::time::Instant::now(),
         }
     }
 
@@ -82,26 +174,287 @@ impl ServiceHandle {
 
     ///
     #[inline(always)]
-    pub fn set_state(&self, state: NodeState) {
-        self.state.store(state, Ordering::Relaxed);
+    pub fn tid(&self) -> u64 {
+        self.tid.load(Ordering::Relaxed)
     }
 
+    /// 当前代码是否运行于 service 线程中
+    #[inline(always)]
+    pub fn is_in_service_thread(&self) -> bool {
+        let tid = get_current_tid();
+        self.tid() == tid
+    }
+
+    /// 在 service 线程中执行回调任务
+    #[inline(always)]
+    pub fn run_in_service(&self, cb: Box<dyn FnOnce() + Send + Sync>) {
+        if self.is_in_service_thread() {
+            cb();
+        } else {
+            self.post(cb);
+        }
+    }
+
+    /// 总是投递到队列，即使在 service 线程中也不直接执行（调用方可能正持有任务需要的状态）
     ///
+    /// 与 run_in_service 相同：排空时丢弃其他线程投递的任务，并更新队列水位和过载统计
+    pub fn post(&self, cb: Box<dyn FnOnce() + Send + Sync>) {
+        if self.is_draining() && !self.is_in_service_thread() {
+            log::error!("service ID={} is draining, task dropped!!!", self.id);
+            return;
+        }
+        self.tx.send(cb).unwrap();
+        self.update_watermark();
+        self.check_overload();
+    }
+
+    /// 正在排空，不再接收其他线程投递的新任务
+    #[inline(always)]
+    pub fn is_draining(&self) -> bool {
+        NodeState::Draining == self.state()
+    }
+
+    /// 当前队列深度（等待执行的任务数）
+    #[inline(always)]
+    pub fn queue_depth(&self) -> usize {
+        self.rx.len() + self.held.lock().len()
+    }
+
     #[inline(always)]
-    pub fn xml_config(&self) -> &RwLock<XmlReader> {
-        &self.xml_config
+    fn update_watermark(&self) {
+        self.queue_depth_watermark
+            .fetch_max(self.queue_depth(), Ordering::Relaxed);
+    }
+
+    /// 队列中等待执行的任务数
+    #[inline(always)]
+    pub fn pending_tasks(&self) -> usize {
+        self.queue_depth()
+    }
+
+    /// 已执行的任务数
+    #[inline(always)]
+    pub fn processed_tasks(&self) -> u64 {
+        self.processed_tasks.load(Ordering::Relaxed)
+    }
+
+    fn check_overload(&self) {
+        let threshold = self.overload_threshold.load(Ordering::Relaxed);
+        if 0 == threshold {
+            return;
+        }
+        let pending = self.pending_tasks();
+        if pending <= threshold {
+            return;
+        }
+
+        // 限频：抢到告警时间窗口的线程负责告警
+        let now = self.created.elapsed().as_millis() as u64 + 1;
+        let last = self.overload_last_warn_ms.load(Ordering::Relaxed);
+        let interval = self.overload_interval_ms.load(Ordering::Relaxed);
+        if last > 0 && now < last + interval {
+            return;
+        }
+        if self
+            .overload_last_warn_ms
+            .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return;
+        }
+
+        let stats = self.stats();
+        log::warn!(
+            "service ID={} overload!!! pending_tasks={} threshold={} processed_tasks={}",
+            self.id,
+            stats.pending_tasks,
+            threshold,
+            stats.processed_tasks
+        );
+        let overload_fn = self.overload_fn.read().clone();
+        if let Some(f) = overload_fn {
+            f(&stats);
+        }
+    }
+
+    /// 运行统计
+    pub fn stats(&self) -> ServiceStats {
+        ServiceStats {
+            id: self.id,
+            state: self.state(),
+            pending_tasks: self.pending_tasks(),
+            processed_tasks: self.processed_tasks(),
+            idle_ratio: self.idle_ratio(),
+            wake_count: self.wake_count(),
+        }
+    }
+
+    /// 空闲时间占比
+    pub fn idle_ratio(&self) -> f64 {
+        let idle = self.idle_us.load(Ordering::Relaxed);
+        let busy = self.busy_us.load(Ordering::Relaxed);
+        if idle + busy == 0 {
+            0_f64
+        } else {
+            idle as f64 / (idle + busy) as f64
+        }
     }
 
     ///
-    pub fn set_xml_config(&self, xml_config: XmlReader) {
-        let mut xml_config_mut = self.xml_config.write();
-        (*xml_config_mut) = xml_config;
+    #[inline(always)]
+    pub fn wake_count(&self) -> u64 {
+        self.wake_count.load(Ordering::Relaxed)
+    }
+}
+
+/// Service handle
+pub struct ServiceHandle {
+    queue: Arc<ServiceQueue>, // 状态、任务队列及其统计，见 ServiceQueue
+
+    pub clock: Clock,
+
+    // service 内置定时器，回调在 service 线程中执行
+    pub timers: ServiceTimers,
+
+    pub xml_config: RwLock<Option<XmlReader>>, // None 表示没有提供配置
+    pub xml_node_id: Atomic<NodeId>,
+
+    //
+    pub join_handle_opt: RwLock<Option<JoinHandle<()>>>,
+
+    // 看门狗心跳：service 线程每次唤醒时更新（距 created 的毫秒数）
+    pub heartbeat_ms: Atomic<u64>,
+
+    // 关闭时是否执行完队列中剩余任务，false 则直接丢弃
+    pub drain_on_shutdown: Atomic<bool>,
+
+    // call_service 处理函数
+    pub call_handlers: CallHandlerTable,
+}
+
+/// ServiceHandle 可以直接使用 ServiceQueue 的字段和方法
+impl std::ops::Deref for ServiceHandle {
+    type Target = ServiceQueue;
+
+    #[inline(always)]
+    fn deref(&self) -> &ServiceQueue {
+        &self.queue
     }
+}
 
+impl ServiceHandle {
     ///
+    pub fn new(id: u64, state: NodeState) -> ServiceHandle {
+        Self {
+            queue: Arc::new(ServiceQueue::new(id, state)),
+
+            clock: Clock::new(),
+
+            timers: ServiceTimers::new(),
+
+            xml_config: RwLock::new(None),
+            xml_node_id: Atomic::new(0),
+
+            join_handle_opt: RwLock::new(None),
+
+            heartbeat_ms: Atomic::new(0_u64),
+
+            drain_on_shutdown: Atomic::new(true),
+
+            call_handlers: CallHandlerTable::new(),
+        }
+    }
+
+    /// 任务队列，可以在其他线程中长期持有并投递任务
     #[inline(always)]
-    pub fn tid(&self) -> u64 {
-        self.tid.load(Ordering::Relaxed)
+    pub fn queue(&self) -> Arc<ServiceQueue> {
+        self.queue.clone()
+    }
+
+    /// 切换状态，非法切换（如 Closed -> Run）返回 Err 且状态不变
+    pub fn set_state(&self, state: NodeState) -> Result<(), ServiceError> {
+        let mut from = self.state();
+        loop {
+            if !from.can_transition_to(state) {
+                log::error!(
+                    "service ID={} invalid state transition: {:?} -> {:?}!!!",
+                    self.id,
+                    from,
+                    state
+                );
+                return Err(ServiceError::InvalidTransition { from, to: state });
+            }
+            match self
+                .state
+                .compare_exchange(from, state, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return Ok(()),
+                Err(current) => from = current,
+            }
+        }
+    }
+
+    /// 暂停：service 线程不再执行任务，队列中的任务保留到 resume 之后执行
+    pub fn pause(&self) -> Result<(), ServiceError> {
+        self.switch_state(NodeState::Run, NodeState::Paused)
+    }
+
+    /// 从 Paused 恢复运行
+    pub fn resume(&self) -> Result<(), ServiceError> {
+        self.switch_state(NodeState::Paused, NodeState::Run)?;
+
+        // 唤醒可能阻塞的 service 线程
+        if !self.is_in_service_thread() {
+            self.tx.send(Box::new(|| {})).ok();
+        }
+        Ok(())
+    }
+
+    /// 排空：不再接收其他线程投递的新任务，执行完队列中剩余任务后进入 Closed
+    pub fn drain(&self) -> Result<(), ServiceError> {
+        self.set_state(NodeState::Draining)?;
+
+        // 唤醒可能阻塞的 service 线程
+        if !self.is_in_service_thread() {
+            self.tx.send(Box::new(|| {})).ok();
+        }
+        Ok(())
+    }
+
+    fn switch_state(&self, from: NodeState, to: NodeState) -> Result<(), ServiceError> {
+        self.state
+            .compare_exchange(from, to, Ordering::AcqRel, Ordering::Acquire)
+            .map(|_| ())
+            .map_err(|current| ServiceError::InvalidTransition { from: current, to })
+    }
+
+    /// 只由框架在线程退出等确定合法的场合调用
+    #[inline(always)]
+    fn force_state(&self, state: NodeState) {
+        self.state.store(state, Ordering::Relaxed);
+    }
+
+    /// 节点的 xml 配置，没有提供时返回 ServiceError::XmlConfigNotFound
+    pub fn xml_config(&self) -> Result<MappedRwLockReadGuard<'_, XmlReader>, ServiceError> {
+        RwLockReadGuard::try_map(self.xml_config.read(), |xml_config| xml_config.as_ref()).map_err(
+            |_| ServiceError::XmlConfigNotFound {
+                node_id: self.xml_node_id.load(Ordering::Relaxed),
+            },
+        )
+    }
+
+    /// 设置节点 node_id 的 xml 配置，节点不存在时为 None
+    pub fn set_xml_config(&self, node_id: NodeId, xml_config: Option<XmlReader>) {
+        self.xml_node_id.store(node_id, Ordering::Relaxed);
+        let mut xml_config_mut = self.xml_config.write();
+        (*xml_config_mut) = xml_config;
+    }
+
+    /// 直接提供 xml 配置（如单元测试中不读取配置文件），节点 id 取自配置中的 id
+    pub fn with_xml_config(self, xml_config: XmlReader) -> Self {
+        let node_id = xml_config.get_u64(vec!["id"], 0);
+        self.set_xml_config(node_id, Some(xml_config));
+        self
     }
 
     ///
@@ -109,27 +462,257 @@ impl ServiceHandle {
         self.tid.store(tid, Ordering::Relaxed);
     }
 
-    /// 在 service 线程中执行回调任务
-    #[inline(always)]
-    pub fn run_in_service(&self, cb: Box<dyn FnOnce() + Send + Sync>) {
+    /// 在 service 线程中执行回调任务，队列深度达到 max_depth（或 queue_capacity，取较小者）时拒绝投递
+    pub fn run_in_service_bounded(
+        &self,
+        cb: Box<dyn FnOnce() + Send + Sync>,
+        max_depth: usize,
+    ) -> Result<(), ServiceError> {
         if self.is_in_service_thread() {
             cb();
-        } else {
-            self.tx.send(cb).unwrap();
+            return Ok(());
+        }
+        if self.is_draining() {
+            return Err(ServiceError::Draining);
+        }
+
+        let limit = match self.queue_capacity() {
+            0 => max_depth,
+            capacity => std::cmp::min(capacity, max_depth),
+        };
+        let depth = self.queue_depth();
+        if depth >= limit {
+            log::warn!(
+                "service ID={} queue full!!! depth={} limit={}",
+                self.id,
+                depth,
+                limit
+            );
+            return Err(ServiceError::QueueFull { depth, limit });
         }
+
+        self.tx.send(cb).unwrap();
+        self.update_watermark();
+        self.check_overload();
+        Ok(())
     }
 
-    /// 当前代码是否运行于 service 线程中
+    /// 设置有界投递的队列容量（0 表示不限制），只对 run_in_service_bounded 生效
+    pub fn set_queue_capacity(&self, capacity: usize) {
+        self.queue_capacity.store(capacity, Ordering::Relaxed);
+    }
+
+    ///
     #[inline(always)]
-    pub fn is_in_service_thread(&self) -> bool {
-        let tid = get_current_tid();
-        self.tid() == tid
+    pub fn queue_capacity(&self) -> usize {
+        self.queue_capacity.load(Ordering::Relaxed)
+    }
+
+    /// 历史最大队列深度，用于诊断
+    #[inline(always)]
+    pub fn queue_depth_watermark(&self) -> usize {
+        self.queue_depth_watermark.load(Ordering::Relaxed)
+    }
+
+    /// 执行队列中至多 max 个任务（暂停后不再执行），返回执行的任务数
+    pub fn dispatch_tasks(&self, max: usize) -> usize {
+        let mut count = 0_usize;
+
+        // 暂停期间取出的任务先于队列中的任务
+        while count < max && NodeState::Paused != self.state() {
+            let cb_opt = self.held.lock().pop_front();
+            match cb_opt {
+                Some(cb) => {
+                    self.run_task(cb);
+                    count += 1;
+                }
+                None => break,
+            }
+        }
+
+        while count < max && NodeState::Paused != self.state() {
+            match self.rx.try_recv() {
+                Ok(cb) => {
+                    log::debug!("Dequeued item ID={}", self.id);
+                    self.run_task(cb);
+                    count += 1;
+                }
+                Err(_) => break,
+            }
+        }
+        count
+    }
+
+    /// delay 之后在 service 线程中执行一次 cb，返回 service 内唯一的定时器 id
+    pub fn schedule<F>(&self, delay: std::time::Duration, cb: F) -> ServiceTimerId
+    where
+        F: FnMut() + Send + Sync + 'static,
+    {
+        let id = self.timers.schedule(delay, cb);
+        self.wake_for_timer();
+        id
+    }
+
+    /// initial 之后在 service 线程中第一次执行 cb，之后每隔 interval 执行一次，直到 cancel_timer
+    pub fn schedule_periodic<F>(
+        &self,
+        initial: std::time::Duration,
+        interval: std::time::Duration,
+        cb: F,
+    ) -> ServiceTimerId
+    where
+        F: FnMut() + Send + Sync + 'static,
+    {
+        let id = self.timers.schedule_periodic(initial, interval, cb);
+        self.wake_for_timer();
+        id
+    }
+
+    /// 固定步长的 tick：每隔 step 在 service 线程中执行一次 cb(帧序号，从 1 开始)，
+    /// 作为定时器订阅，空闲时不轮询；用 cancel_timer 停止
+    pub fn start_tick<F>(&self, step: std::time::Duration, mut cb: F) -> ServiceTimerId
+    where
+        F: FnMut(u64) + Send + Sync + 'static,
+    {
+        let mut frame = 0_u64;
+        self.schedule_periodic(step, step, move || {
+            frame += 1;
+            cb(frame);
+        })
+    }
+
+    /// 取消定时器，可以在定时器回调中调用，返回定时器是否存在
+    pub fn cancel_timer(&self, id: ServiceTimerId) -> bool {
+        self.timers.cancel(id)
+    }
+
+    // 其他线程添加定时器时唤醒空闲等待中的 service，重新计算等待时间
+    fn wake_for_timer(&self) {
+        if self.tid() != 0 && !self.is_in_service_thread() && self.rx.is_empty() {
+            let _ = self.tx.send(Box::new(|| {}));
+        }
+    }
+
+    /// 丢弃队列中等待执行的任务（不执行），返回丢弃的任务数
+    pub fn cancel_pending_tasks(&self) -> usize {
+        let held = std::mem::take(&mut *self.held.lock());
+        let mut count = held.len();
+        drop(held);
+        while let Ok(cb) = self.rx.try_recv() {
+            drop(cb);
+            count += 1;
+        }
+        if count > 0 {
+            log::warn!("service ID={} cancel {} pending tasks", self.id, count);
+        }
+        count
+    }
+
+    /// true（默认）: 关闭时执行完队列中剩余任务; false: 关闭时调用 cancel_pending_tasks 丢弃剩余任务
+    pub fn set_drain_on_shutdown(&self, drain: bool) {
+        self.drain_on_shutdown.store(drain, Ordering::Relaxed);
+    }
+
+    ///
+    #[inline(always)]
+    pub fn drain_on_shutdown(&self) -> bool {
+        self.drain_on_shutdown.load(Ordering::Relaxed)
+    }
+
+    #[inline(always)]
+    fn run_task(&self, cb: Box<ServiceFuncType>) {
+        cb();
+        self.processed_tasks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 设置过载阈值（0 表示关闭），超过时每 interval 最多告警一次
+    pub fn set_overload_threshold(&self, threshold: usize, interval: std::time::Duration) {
+        self.overload_interval_ms
+            .store(interval.as_millis() as u64, Ordering::Relaxed);
+        self.overload_threshold.store(threshold, Ordering::Relaxed);
+    }
+
+    /// 设置过载回调
+    pub fn set_overload_callback<F>(&self, f: F)
+    where
+        F: Fn(&ServiceStats) + Send + Sync + 'static,
+    {
+        let mut overload_fn_mut = self.overload_fn.write();
+        (*overload_fn_mut) = Some(Arc::new(f));
+    }
+
+    /// 注册 call_service 处理函数，在本 service 线程中执行，相同 (Req, Resp) 重复注册时覆盖旧的
+    pub fn register_call_handler<Req, Resp, F>(&self, f: F)
+    where
+        Req: Send + 'static,
+        Resp: Send + 'static,
+        F: Fn(Req) -> Resp + Send + Sync + 'static,
+    {
+        self.call_handlers.register(f);
+    }
+
+    #[inline(always)]
+    fn heartbeat(&self) {
+        self.heartbeat_ms
+            .store(self.created.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// 距离 service 线程上次唤醒的时间
+    pub fn heartbeat_age(&self) -> std::time::Duration {
+        let now = self.created.elapsed().as_millis() as u64;
+        std::time::Duration::from_millis(
+            now.saturating_sub(self.heartbeat_ms.load(Ordering::Relaxed)),
+        )
+    }
+
+    /// 看门狗检查：心跳超过 threshold 且有应当处理的任务或已到期的定时器才算卡住，
+    /// 空闲阻塞（队列为空、定时器未到期）和暂停不算
+    pub fn is_stalled(&self, threshold: std::time::Duration) -> bool {
+        if NodeState::Paused == self.state() || self.heartbeat_age() <= threshold {
+            return false;
+        }
+        self.pending_tasks() > 0 || Some(std::time::Duration::ZERO) == self.timers.next_timeout()
     }
 
     /// 发送 close 信号
     pub fn quit_service(&self) {
         if self.state() < NodeState::Closed {
-            self.set_state(NodeState::Closed);
+            self.force_state(NodeState::Closed);
+
+            // 唤醒可能阻塞在队列上的 service 线程
+            if !self.is_in_service_thread() {
+                self.tx.send(Box::new(|| {})).ok();
+            }
+        }
+    }
+
+    /// 请求关闭：处理完队列中剩余任务后关闭
+    pub fn stop_service(&self) {
+        if self.state() < NodeState::Closing {
+            self.force_state(NodeState::Closing);
+
+            // 唤醒可能阻塞在队列上的 service 线程
+            if !self.is_in_service_thread() {
+                self.tx.send(Box::new(|| {})).ok();
+            }
+        }
+    }
+
+    /// 等待 service 进入 Closed 状态，超时返回 false
+    pub fn wait_closed(&self, timeout: std::time::Duration) -> bool {
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            let closed = NodeState::Closed == self.state() && {
+                let join_handle_opt = self.join_handle_opt.read();
+                join_handle_opt.as_ref().map_or(true, |h| h.is_finished())
+            };
+            if closed {
+                return true;
+            }
+            if std::time::Instant::now() >= deadline {
+                return false;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
         }
     }
 
@@ -161,6 +744,214 @@ pub trait ServiceRs: Send + Sync {
 
     /// 等待线程结束
     fn join(&self);
+
+    /// 关闭顺序优先级，数值大的先关闭；相同优先级按 attach 逆序关闭
+    fn stop_priority(&self) -> i32 {
+        0
+    }
+
+    /// 关闭 hook：默认处理完队列中剩余任务后关闭
+    fn stop(&self) {
+        self.get_handle().stop_service();
+    }
+
+    /// 每隔 period 在 service 线程中执行一次 cb（首次在 period 之后），由共用的定时器线程经 run_in_service 投递，
+    /// service 离开 Run/Paused（排空或关闭）后自动取消
+    fn run_every(
+        &self,
+        period: std::time::Duration,
+        cb: Box<dyn Fn() + Send + Sync + 'static>,
+    ) -> PeriodicTaskHandle {
+        PeriodicTaskHandle::start(self.get_handle().queue(), period, Arc::from(cb))
+    }
+
+    /// 创建发往本 service 的类型化消息通道，receiver 设置的 handler 在本 service 线程中执行
+    fn make_channel<T>(&self) -> (ServiceSender<T>, ServiceReceiver<T>)
+    where
+        Self: Sized,
+        T: Send + 'static,
+    {
+        ServiceChannel::make(self.get_handle())
+    }
+}
+
+struct PeriodicTask {
+    queue: Arc<ServiceQueue>,
+    cb: Arc<dyn Fn() + Send + Sync>,
+    timer_id: parking_lot::Mutex<Option<TimerId>>, // None 表示已取消
+    generation: std::sync::atomic::AtomicU64,      // 取消或修改周期时 +1，已投递的旧任务不再执行
+}
+
+impl PeriodicTask {
+    fn cancel(&self) {
+        let mut timer_id = self.timer_id.lock();
+        if let Some(id) = timer_id.take() {
+            self.generation
+                .fetch_add(1, std::sync::atomic::Ordering::AcqRel);
+            G_PERIODIC_TIMER.cancel(id);
+        }
+    }
+}
+
+/// run_every 返回的句柄，clone 后操作的是同一个任务
+#[derive(Clone)]
+pub struct PeriodicTaskHandle {
+    task: Arc<PeriodicTask>,
+}
+
+impl PeriodicTaskHandle {
+    fn start(
+        queue: Arc<ServiceQueue>,
+        period: std::time::Duration,
+        cb: Arc<dyn Fn() + Send + Sync>,
+    ) -> Self {
+        let task = Arc::new(PeriodicTask {
+            queue,
+            cb,
+            timer_id: parking_lot::Mutex::new(None),
+            generation: std::sync::atomic::AtomicU64::new(0),
+        });
+
+        let mut timer_id = task.timer_id.lock();
+        *timer_id = Some(Self::schedule(&task, period));
+        drop(timer_id);
+        Self { task }
+    }
+
+    fn schedule(task: &Arc<PeriodicTask>, period: std::time::Duration) -> TimerId {
+        let period = std::cmp::max(period, std::time::Duration::from_millis(1));
+        let generation = task.generation.load(std::sync::atomic::Ordering::Acquire);
+        let task2 = task.clone();
+        G_PERIODIC_TIMER.schedule_periodic(period, period, move || {
+            // service 已排空或关闭，不再投递
+            let state = task2.queue.state();
+            if state >= NodeState::Draining {
+                log::info!(
+                    "service ID={} state={:?}, cancel run_every task",
+                    task2.queue.id(),
+                    state
+                );
+                task2.cancel();
+                return;
+            }
+
+            let task3 = task2.clone();
+            task2.queue.run_in_service(Box::new(move || {
+                if task3.generation.load(std::sync::atomic::Ordering::Acquire) == generation {
+                    (task3.cb)();
+                }
+            }));
+        })
+    }
+
+    /// 取消后不再执行，已投递到队列中的任务也会被跳过
+    pub fn cancel(&self) {
+        self.task.cancel();
+    }
+
+    ///
+    pub fn is_cancelled(&self) -> bool {
+        self.task.timer_id.lock().is_none()
+    }
+
+    /// 修改周期，从现在开始重新计时；已取消的任务不会重新启动
+    pub fn reset_period(&self, period: std::time::Duration) {
+        let mut timer_id = self.task.timer_id.lock();
+        if let Some(id) = timer_id.take() {
+            self.task
+                .generation
+                .fetch_add(1, std::sync::atomic::Ordering::AcqRel);
+            G_PERIODIC_TIMER.cancel(id);
+            *timer_id = Some(Self::schedule(&self.task, period));
+        }
+    }
+}
+
+/// 按关闭顺序依次 stop 并等待每个 service 关闭（services 为 attach 顺序）,
+/// 超时的 service 记录日志后跳过 join，不阻塞退出。返回超时的 service id
+pub fn stop_services(
+    services: &[&'static dyn ServiceRs],
+    timeout: std::time::Duration,
+) -> Vec<u64> {
+    stop_services_with_deps(services, &[], timeout)
+}
+
+/// 关闭顺序：stop_priority 大的先关闭，相同优先级按 attach 逆序；
+/// 依赖关系 (dependent, dependency) 优先：dependent 全部关闭之后才关闭 dependency
+pub fn shutdown_order(
+    services: &[&'static dyn ServiceRs],
+    deps: &[(u64, u64)],
+) -> Vec<&'static dyn ServiceRs> {
+    let mut pending: Vec<&'static dyn ServiceRs> = services.iter().rev().copied().collect();
+    pending.sort_by_key(|srv| std::cmp::Reverse(srv.stop_priority()));
+
+    let mut ordered = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        // 第一个没有未关闭 dependent 的 service
+        let pos = pending.iter().position(|srv| {
+            let id = srv.get_handle().id();
+            !deps.iter().any(|(dependent, dependency)| {
+                *dependency == id
+                    && pending
+                        .iter()
+                        .any(|other| other.get_handle().id() == *dependent)
+            })
+        });
+        match pos {
+            Some(pos) => ordered.push(pending.remove(pos)),
+            None => {
+                // 依赖有环（声明时应已检查），剩余的按默认顺序关闭
+                log::error!("shutdown order: dependency cycle detected!!!");
+                ordered.append(&mut pending);
+            }
+        }
+    }
+    ordered
+}
+
+/// 同 stop_services，关闭顺序见 shutdown_order
+pub fn stop_services_with_deps(
+    services: &[&'static dyn ServiceRs],
+    deps: &[(u64, u64)],
+    timeout: std::time::Duration,
+) -> Vec<u64> {
+    let ordered = shutdown_order(services, deps);
+
+    let mut timed_out = Vec::new();
+    for srv in ordered {
+        let handle = srv.get_handle();
+        log::info!(
+            "stop service({}) ID={} state={:?} ...",
+            srv.name(),
+            handle.id(),
+            handle.state()
+        );
+        if NodeState::Draining == handle.state() {
+            // 排空中的 service 自行执行完剩余任务后关闭，只需等待
+            log::info!(
+                "stop service({}) ID={} is draining, wait ...",
+                srv.name(),
+                handle.id()
+            );
+        } else {
+            srv.stop();
+        }
+
+        if handle.wait_closed(timeout) {
+            srv.join();
+            log::info!("stop service({}) ID={} ok.", srv.name(), handle.id());
+        } else {
+            log::error!(
+                "stop service({}) ID={} timeout after {:?}!!! last state={:?}",
+                srv.name(),
+                handle.id(),
+                timeout,
+                handle.state()
+            );
+            timed_out.push(handle.id());
+        }
+    }
+    timed_out
 }
 
 /// 启动 service 线程，service 需要使用 Arc 包装，否则无法跨线程 move
@@ -208,7 +999,12 @@ where
             // exit
             {
                 // mark closed
-                handle.set_state(NodeState::Closed);
+                handle.force_state(NodeState::Closed);
+
+                // 退出过程中新投递的任务：不需要执行完的在线程退出前丢弃
+                if !handle.drain_on_shutdown() {
+                    handle.cancel_pending_tasks();
+                }
 
                 // notify exit
                 (&*exit_cv).1.notify_all();
@@ -254,13 +1050,34 @@ fn run_service(srv: &'static dyn ServiceRs, service_name: &str) {
     // loop until "NodeState::Closed"
     let mut sw = StopWatch::new();
     loop {
+        handle.heartbeat();
+
+        // paused: 不执行任务，阻塞在队列上，取出的任务保留到 resume 之后执行
+        // （resume/stop/quit 都会投递唤醒任务）
+        if NodeState::Paused == handle.state() {
+            let idle_sw = StopWatch::new();
+            let ret = handle.rx.recv();
+            handle
+                .idle_us
+                .fetch_add(idle_sw.elapsed_us(), Ordering::Relaxed);
+            handle.wake_count.fetch_add(1, Ordering::Relaxed);
+
+            if let Ok(cb) = ret {
+                handle.held.lock().push_back(cb);
+            }
+            continue;
+        }
+
         // check run
         let run = {
             let state = handle.state();
             if NodeState::Closed == state {
                 false
+            } else if NodeState::Draining == state {
+                // 排空：执行完剩余任务后关闭
+                !handle.rx.is_empty()
             } else if NodeState::Closing == state {
-                if handle.rx.is_empty() {
+                if !handle.drain_on_shutdown() || handle.rx.is_empty() {
                     false
                 } else {
                     log::debug!("[{}] rx length={}", service_name, handle.rx.len());
@@ -273,28 +1090,29 @@ fn run_service(srv: &'static dyn ServiceRs, service_name: &str) {
 
         // run or quit ?
         if !run {
+            // 退出前处理剩余任务（quit_service 直接进入 Closed 时同样处理）：
+            // drain_on_shutdown 时执行完，否则丢弃
+            if handle.drain_on_shutdown() {
+                while handle.dispatch_tasks(4096) > 0 {}
+            } else {
+                handle.cancel_pending_tasks();
+            }
+
             // mark service quit
             handle.quit_service();
             break;
         } else {
+            let busy_sw = StopWatch::new();
+
             // update clock
             Clock::update();
+            handle.timers.update();
 
             // dispatch cb -- process async tasks
-            let mut count = 4096_i32;
-            while count > 0 && !handle.rx.is_empty() {
-                match handle.rx.try_recv() {
-                    Ok(cb) => {
-                        log::info!("Dequeued item ID={}", handle.id);
-                        println!("Dequeued item ID={}", handle.id);
-                        cb();
-                        count -= 1;
-                    }
-                    Err(err) => {
-                        log::error!("service receive cb error: {:?}", err);
-                    }
-                }
-            }
+            handle.dispatch_tasks(4096);
+            handle
+                .busy_us
+                .fetch_add(busy_sw.elapsed_us(), Ordering::Relaxed);
 
             // sleep by cost
             let cost = sw.elapsed_and_reset();
@@ -305,11 +1123,662 @@ fn run_service(srv: &'static dyn ServiceRs, service_name: &str) {
                     handle.id,
                     cost
                 );*/
-            } else {
-                // sleep
-                const SLEEP_MS: std::time::Duration = std::time::Duration::from_millis(1);
-                std::thread::sleep(SLEEP_MS);
+            } else if handle.rx.is_empty() {
+                // 空闲：阻塞等待任务到达或最近的定时器到期，不再轮询；没有定时器时一直阻塞，
+                // stop/quit/resume/drain 及其他线程添加定时器时都会投递唤醒任务
+                let wait = [Clock::next_timeout(), handle.timers.next_timeout()]
+                    .into_iter()
+                    .flatten()
+                    .min();
+
+                let idle_sw = StopWatch::new();
+                let ret = match wait {
+                    Some(wait) => handle.rx.recv_timeout(wait).ok(),
+                    None => handle.rx.recv().ok(),
+                };
+                handle
+                    .idle_us
+                    .fetch_add(idle_sw.elapsed_us(), Ordering::Relaxed);
+                handle.wake_count.fetch_add(1, Ordering::Relaxed);
+                handle.heartbeat();
+
+                if let Some(cb) = ret {
+                    if NodeState::Paused == handle.state() {
+                        handle.held.lock().push_back(cb);
+                    } else {
+                        Clock::update();
+                        handle.run_task(cb);
+                    }
+                }
+                handle.timers.update();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::time::Duration;
+
+    struct FakeService {
+        name: String,
+        handle: ServiceHandle,
+        priority: i32,
+        closes: bool,
+        stopped: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl ServiceRs for FakeService {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn get_handle(&self) -> &ServiceHandle {
+            &self.handle
+        }
+
+        fn conf(&self) {}
+
+        fn run_in_service(&self, cb: Box<dyn FnOnce() + Send + Sync>) {
+            cb();
+        }
+
+        fn is_in_service_thread(&self) -> bool {
+            true
+        }
+
+        fn join(&self) {}
+
+        fn stop_priority(&self) -> i32 {
+            self.priority
+        }
+
+        fn stop(&self) {
+            self.stopped.lock().push(self.name.clone());
+            if self.closes {
+                if let Err(err) = self.handle.set_state(NodeState::Closed) {
+                    log::error!("fake service {} close failed: {}", self.name, err);
+                }
+            }
+        }
+    }
+
+    fn fake(
+        id: u64,
+        name: &str,
+        priority: i32,
+        closes: bool,
+        stopped: &Arc<Mutex<Vec<String>>>,
+    ) -> &'static dyn ServiceRs {
+        Box::leak(Box::new(FakeService {
+            name: name.to_owned(),
+            handle: ServiceHandle::new(id, NodeState::Run),
+            priority,
+            closes,
+            stopped: stopped.clone(),
+        }))
+    }
+
+    #[test]
+    fn pending_tasks_on_paused_service() {
+        // 没有 service 线程：任务只入队不执行
+        let handle = ServiceHandle::new(1, NodeState::Run);
+        let overloads = Arc::new(Mutex::new(Vec::new()));
+        let overloads_clone = overloads.clone();
+        handle.set_overload_threshold(5000, Duration::from_secs(60));
+        handle.set_overload_callback(move |stats| {
+            overloads_clone.lock().push(stats.pending_tasks);
+        });
+
+        let counter = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        for _ in 0..10_000 {
+            let counter = counter.clone();
+            handle.run_in_service(Box::new(move || {
+                counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            }));
+        }
+
+        let stats = handle.stats();
+        assert_eq!(stats.pending_tasks, 10_000);
+        assert_eq!(stats.processed_tasks, 0);
+        assert_eq!(*overloads.lock(), vec![5001]);
+
+        assert_eq!(handle.dispatch_tasks(4096), 4096);
+        assert_eq!(handle.dispatch_tasks(usize::MAX), 10_000 - 4096);
+        assert_eq!(handle.pending_tasks(), 0);
+        assert_eq!(handle.processed_tasks(), 10_000);
+        assert_eq!(counter.load(std::sync::atomic::Ordering::Relaxed), 10_000);
+    }
+
+    #[test]
+    fn bounded_queue_rejects_when_full() {
+        let handle = ServiceHandle::new(1, NodeState::Run);
+        for _ in 0..3 {
+            handle.run_in_service_bounded(Box::new(|| {}), 3).unwrap();
+        }
+        assert_eq!(handle.queue_depth(), 3);
+        assert_eq!(
+            handle.run_in_service_bounded(Box::new(|| {}), 3),
+            Err(ServiceError::QueueFull { depth: 3, limit: 3 })
+        );
+
+        // queue_capacity 更小时以 queue_capacity 为准
+        handle.set_queue_capacity(2);
+        assert_eq!(
+            handle.run_in_service_bounded(Box::new(|| {}), 100),
+            Err(ServiceError::QueueFull { depth: 3, limit: 2 })
+        );
+
+        assert_eq!(handle.dispatch_tasks(usize::MAX), 3);
+        assert_eq!(handle.queue_depth(), 0);
+        assert!(handle.run_in_service_bounded(Box::new(|| {}), 100).is_ok());
+        assert_eq!(handle.queue_depth_watermark(), 3);
+    }
+
+    #[test]
+    fn post_always_queues() {
+        let handle = ServiceHandle::new(1, NodeState::Run);
+        handle.set_tid(get_current_tid());
+
+        let counter = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter2 = counter.clone();
+        handle.post(Box::new(move || {
+            counter2.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }));
+        assert_eq!(counter.load(std::sync::atomic::Ordering::Relaxed), 0);
+        assert_eq!(handle.queue_depth_watermark(), 1);
+
+        // service 线程自身在排空期间投递的任务仍然保留
+        handle.drain().unwrap();
+        handle.post(Box::new(|| {}));
+        assert_eq!(handle.queue_depth(), 2);
+
+        // 其他线程投递的任务被丢弃
+        handle.set_tid(0);
+        handle.post(Box::new(|| {}));
+        assert_eq!(handle.queue_depth(), 2);
+
+        assert_eq!(handle.dispatch_tasks(usize::MAX), 2);
+        assert_eq!(counter.load(std::sync::atomic::Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn timers_on_handle() {
+        let handle: &'static ServiceHandle =
+            Box::leak(Box::new(ServiceHandle::new(1, NodeState::Run)));
+        let fired = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+        let fired2 = fired.clone();
+        let periodic =
+            handle.schedule_periodic(Duration::ZERO, Duration::from_millis(10), move || {
+                fired2.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            });
+        handle.schedule(Duration::from_millis(5), move || {
+            assert!(handle.cancel_timer(periodic));
+        });
+
+        // 没有 service 线程时不投递唤醒任务
+        assert_eq!(handle.pending_tasks(), 0);
+        std::thread::sleep(Duration::from_millis(20));
+        handle.timers.update();
+        assert_eq!(fired.load(std::sync::atomic::Ordering::Relaxed), 1);
+        assert!(handle.timers.is_empty());
+        assert!(!handle.cancel_timer(periodic));
+    }
+
+    #[test]
+    fn cancel_pending_tasks_discards_without_running() {
+        let handle = ServiceHandle::new(1, NodeState::Run);
+        let counter = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        for _ in 0..5 {
+            let counter = counter.clone();
+            handle.run_in_service(Box::new(move || {
+                counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            }));
+        }
+
+        assert_eq!(handle.cancel_pending_tasks(), 5);
+        assert_eq!(handle.pending_tasks(), 0);
+        assert_eq!(handle.dispatch_tasks(usize::MAX), 0);
+        assert_eq!(counter.load(std::sync::atomic::Ordering::Relaxed), 0);
+        assert_eq!(handle.cancel_pending_tasks(), 0);
+    }
+
+    #[test]
+    fn state_transitions() {
+        let handle = ServiceHandle::new(1, NodeState::Idle);
+        assert!(handle.pause().is_err());
+        assert_eq!(handle.set_state(NodeState::Run), Ok(()));
+
+        handle.pause().unwrap();
+        assert_eq!(handle.state(), NodeState::Paused);
+        assert!(handle.pause().is_err());
+        handle.resume().unwrap();
+        assert_eq!(handle.state(), NodeState::Run);
+        assert!(handle.resume().is_err());
+
+        handle.drain().unwrap();
+        assert_eq!(
+            handle.set_state(NodeState::Run),
+            Err(ServiceError::InvalidTransition {
+                from: NodeState::Draining,
+                to: NodeState::Run
+            })
+        );
+        assert_eq!(
+            handle.run_in_service_bounded(Box::new(|| {}), 10),
+            Err(ServiceError::Draining)
+        );
+
+        assert_eq!(handle.set_state(NodeState::Closed), Ok(()));
+        assert!(handle.set_state(NodeState::Closing).is_err());
+        assert_eq!(handle.state(), NodeState::Closed);
+    }
+
+    fn count_dispatched(handle: &ServiceHandle, wait: Duration) {
+        let deadline = std::time::Instant::now() + wait;
+        while std::time::Instant::now() < deadline {
+            handle.dispatch_tasks(usize::MAX);
+            std::thread::sleep(Duration::from_millis(2));
+        }
+    }
+
+    #[test]
+    fn run_every_cancel_and_reset() {
+        let stopped = Arc::new(Mutex::new(Vec::new()));
+        let srv = fake(1, "timer", 0, true, &stopped);
+        let counter = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter2 = counter.clone();
+        let task = srv.run_every(
+            Duration::from_millis(10),
+            Box::new(move || {
+                counter2.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            }),
+        );
+
+        count_dispatched(srv.get_handle(), Duration::from_millis(120));
+        let n = counter.load(std::sync::atomic::Ordering::Relaxed);
+        assert!(n >= 3, "ran {} times", n);
+
+        // 放慢周期
+        task.reset_period(Duration::from_secs(60));
+        counter.store(0, std::sync::atomic::Ordering::Relaxed);
+        count_dispatched(srv.get_handle(), Duration::from_millis(60));
+        assert_eq!(counter.load(std::sync::atomic::Ordering::Relaxed), 0);
+
+        task.reset_period(Duration::from_millis(5));
+        count_dispatched(srv.get_handle(), Duration::from_millis(60));
+        assert!(counter.load(std::sync::atomic::Ordering::Relaxed) > 0);
+
+        // 取消前已投递但未执行的任务也被跳过
+        std::thread::sleep(Duration::from_millis(20));
+        task.cancel();
+        assert!(task.is_cancelled());
+        counter.store(0, std::sync::atomic::Ordering::Relaxed);
+        count_dispatched(srv.get_handle(), Duration::from_millis(40));
+        assert_eq!(counter.load(std::sync::atomic::Ordering::Relaxed), 0);
+
+        // 已取消不会重新启动
+        task.reset_period(Duration::from_millis(5));
+        assert!(task.is_cancelled());
+    }
+
+    // 有 service 线程的 service
+    struct LoopService {
+        handle: ServiceHandle,
+    }
+
+    impl ServiceRs for LoopService {
+        fn name(&self) -> &str {
+            "loop"
+        }
+
+        fn get_handle(&self) -> &ServiceHandle {
+            &self.handle
+        }
+
+        fn conf(&self) {}
+
+        fn run_in_service(&self, cb: Box<dyn FnOnce() + Send + Sync>) {
+            self.handle.run_in_service(cb);
+        }
+
+        fn is_in_service_thread(&self) -> bool {
+            self.handle.is_in_service_thread()
+        }
+
+        fn join(&self) {
+            self.handle.join_service();
+        }
+    }
+
+    fn start_loop(id: u64) -> &'static LoopService {
+        let srv: &'static LoopService = Box::leak(Box::new(LoopService {
+            handle: ServiceHandle::new(id, NodeState::Run),
+        }));
+        let ready_pair = start_service(srv, "loop", || {});
+        assert!(proc_service_ready(srv, ready_pair));
+        srv
+    }
+
+    fn quit_loop(srv: &'static LoopService) {
+        srv.handle.quit_service();
+        assert!(srv.handle.wait_closed(Duration::from_secs(5)));
+        srv.join();
+    }
+
+    #[test]
+    fn idle_service_wakes_only_for_timers() {
+        let srv = start_loop(101);
+        let handle = &srv.handle;
+        std::thread::sleep(Duration::from_millis(20));
+
+        // 没有任务和定时器：一直阻塞，不唤醒
+        let wakes = handle.wake_count();
+        std::thread::sleep(Duration::from_millis(150));
+        assert_eq!(handle.wake_count(), wakes);
+        assert!(handle.idle_ratio() > 0.9);
+
+        // 定时器：添加时唤醒一次重新计算等待时间，到期时唤醒
+        let (tx, rx) = std::sync::mpsc::channel();
+        let tx = Mutex::new(tx);
+        let start = std::time::Instant::now();
+        handle.schedule(Duration::from_millis(50), move || {
+            tx.lock().send(start.elapsed()).unwrap();
+        });
+        let fired_at = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert!(fired_at >= Duration::from_millis(50), "{:?}", fired_at);
+        assert!(fired_at < Duration::from_millis(50 + 30), "{:?}", fired_at);
+
+        std::thread::sleep(Duration::from_millis(100));
+        let timer_wakes = handle.wake_count() - wakes;
+        assert!((2..=4).contains(&timer_wakes), "woke {} times", timer_wakes);
+        quit_loop(srv);
+    }
+
+    #[test]
+    fn task_arrival_interrupts_idle_block() {
+        let srv = start_loop(102);
+        std::thread::sleep(Duration::from_millis(50));
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let tx = Mutex::new(tx);
+        let start = std::time::Instant::now();
+        srv.run_in_service(Box::new(move || {
+            tx.lock().send(start.elapsed()).unwrap();
+        }));
+        let latency = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert!(latency < Duration::from_millis(20), "{:?}", latency);
+        quit_loop(srv);
+    }
+
+    #[test]
+    fn watchdog_tolerates_idle_block() {
+        let srv = start_loop(103);
+        let handle = &srv.handle;
+        std::thread::sleep(Duration::from_millis(100));
+
+        // 空闲阻塞：心跳很久没有更新，但没有应当处理的任务
+        assert!(handle.heartbeat_age() >= Duration::from_millis(50));
+        assert!(!handle.is_stalled(Duration::from_millis(20)));
+
+        // 卡在任务中且有任务等待：报警
+        srv.run_in_service(Box::new(|| {
+            std::thread::sleep(Duration::from_millis(150));
+        }));
+        srv.run_in_service(Box::new(|| {}));
+        std::thread::sleep(Duration::from_millis(80));
+        assert!(handle.is_stalled(Duration::from_millis(20)));
+
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(!handle.is_stalled(Duration::from_millis(20)));
+        quit_loop(srv);
+    }
+
+    #[test]
+    fn busy_service_runs_tasks_in_order() {
+        let srv = start_loop(104);
+        let handle = &srv.handle;
+        let order = Arc::new(Mutex::new(Vec::new()));
+        for i in 0..10_000 {
+            let order = order.clone();
+            srv.run_in_service(Box::new(move || {
+                order.lock().push(i);
+            }));
+        }
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while handle.processed_tasks() < 10_000 {
+            assert!(std::time::Instant::now() < deadline, "wait timeout");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(*order.lock(), (0..10_000).collect::<Vec<_>>());
+        assert!(handle.wake_count() <= 10_000);
+        quit_loop(srv);
+    }
+
+    #[test]
+    fn tick_loop_subscribes_as_timer() {
+        let srv = start_loop(105);
+        let handle = &srv.handle;
+        std::thread::sleep(Duration::from_millis(20));
+        let wakes = handle.wake_count();
+
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let frames2 = frames.clone();
+        let id = handle.start_tick(Duration::from_millis(10), move |frame| {
+            frames2.lock().push(frame);
+        });
+        std::thread::sleep(Duration::from_millis(105));
+        assert!(handle.cancel_timer(id));
+
+        let frames = frames.lock().clone();
+        assert!(frames.len() >= 5, "{:?}", frames);
+        assert_eq!(frames, (1..=frames.len() as u64).collect::<Vec<_>>());
+
+        // 每帧只唤醒常数次，没有空转
+        let woke = handle.wake_count() - wakes;
+        assert!(woke <= 2 * frames.len() as u64 + 2, "woke {} times", woke);
+        quit_loop(srv);
+    }
+
+    #[test]
+    fn paused_service_blocks_and_keeps_queue() {
+        let srv = start_loop(106);
+        let handle = &srv.handle;
+        std::thread::sleep(Duration::from_millis(20));
+        handle.pause().unwrap();
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        for i in 0..3 {
+            let order = order.clone();
+            srv.run_in_service(Box::new(move || {
+                order.lock().push(i);
+            }));
+        }
+
+        // 暂停时不执行也不空转，任务仍计入队列
+        let wakes = handle.wake_count();
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(order.lock().is_empty());
+        assert_eq!(handle.pending_tasks(), 3);
+        assert!(handle.wake_count() - wakes <= 3);
+        assert!(!handle.is_stalled(Duration::from_millis(20)));
+
+        handle.resume().unwrap();
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while order.lock().len() < 3 {
+            assert!(std::time::Instant::now() < deadline, "wait timeout");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(*order.lock(), vec![0, 1, 2]);
+        quit_loop(srv);
+    }
+
+    #[test]
+    fn quit_drains_or_cancels_pending_tasks() {
+        for (id, drain) in [(107, true), (108, false)] {
+            let srv = start_loop(id);
+            let handle = &srv.handle;
+            handle.set_drain_on_shutdown(drain);
+            std::thread::sleep(Duration::from_millis(20));
+
+            // 暂停后投递，保证 quit 时任务还在队列中
+            handle.pause().unwrap();
+            let counter = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+            for _ in 0..5 {
+                let counter = counter.clone();
+                srv.run_in_service(Box::new(move || {
+                    counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                }));
             }
+            assert_eq!(handle.pending_tasks(), 5);
+
+            // quit_service 直接进入 Closed
+            quit_loop(srv);
+            let expected = if drain { 5 } else { 0 };
+            assert_eq!(
+                counter.load(std::sync::atomic::Ordering::Relaxed),
+                expected,
+                "drain_on_shutdown={}",
+                drain
+            );
+            assert_eq!(handle.pending_tasks(), 0);
         }
     }
+
+    #[test]
+    fn run_every_stops_when_service_closes() {
+        let stopped = Arc::new(Mutex::new(Vec::new()));
+        let srv = fake(2, "closing", 0, true, &stopped);
+        let handle = srv.get_handle();
+        let counter = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter2 = counter.clone();
+        let task = srv.run_every(
+            Duration::from_millis(5),
+            Box::new(move || {
+                counter2.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            }),
+        );
+
+        // 经 run_in_service 投递：计入队列水位
+        std::thread::sleep(Duration::from_millis(40));
+        assert!(handle.queue_depth_watermark() >= 2);
+        handle.dispatch_tasks(usize::MAX);
+        assert!(counter.load(std::sync::atomic::Ordering::Relaxed) > 0);
+
+        // 排空后不再投递，定时器自行取消
+        handle.drain().unwrap();
+        std::thread::sleep(Duration::from_millis(40));
+        assert!(task.is_cancelled());
+        handle.cancel_pending_tasks();
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(handle.pending_tasks(), 0);
+    }
+
+    #[test]
+    fn run_every_and_channel_on_borrowed_service() {
+        // 不需要 &'static：定时器和通道持有 clone 的任务队列
+        let stopped = Arc::new(Mutex::new(Vec::new()));
+        let srv = FakeService {
+            name: "local".to_owned(),
+            handle: ServiceHandle::new(3, NodeState::Run),
+            priority: 0,
+            closes: true,
+            stopped,
+        };
+        let queue = srv.get_handle().queue();
+
+        let counter = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter2 = counter.clone();
+        let task = srv.run_every(
+            Duration::from_millis(5),
+            Box::new(move || {
+                counter2.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            }),
+        );
+        let (sender, receiver) = srv.make_channel::<u32>();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received2 = received.clone();
+        receiver.set_handler(move |msg| received2.lock().push(msg));
+        sender.send(7);
+
+        count_dispatched(srv.get_handle(), Duration::from_millis(40));
+        assert!(counter.load(std::sync::atomic::Ordering::Relaxed) > 0);
+        assert_eq!(*received.lock(), vec![7]);
+
+        // service 释放后定时器仍可安全投递，直至取消
+        drop(srv);
+        std::thread::sleep(Duration::from_millis(20));
+        task.cancel();
+        assert!(task.is_cancelled());
+        sender.send(8);
+        assert!(queue.pending_tasks() > 0);
+    }
+
+    #[test]
+    fn stop_in_reverse_attach_order() {
+        let stopped = Arc::new(Mutex::new(Vec::new()));
+        let net = fake(1, "net", 0, true, &stopped);
+        let game = fake(2, "game", 0, true, &stopped);
+
+        let timed_out = stop_services(&[net, game], Duration::from_millis(100));
+        assert!(timed_out.is_empty());
+        assert_eq!(*stopped.lock(), vec!["game", "net"]);
+    }
+
+    #[test]
+    fn dependents_stop_before_dependencies() {
+        let stopped = Arc::new(Mutex::new(Vec::new()));
+        let db = fake(1, "db", 10, true, &stopped);
+        let net = fake(2, "net", 0, true, &stopped);
+        let game = fake(3, "game", 0, true, &stopped);
+        let log = fake(4, "log", 0, true, &stopped);
+
+        // game 依赖 db，net 依赖 game：db 优先级虽高也要等 game 关闭之后
+        let deps = [(3, 1), (2, 3)];
+        let order: Vec<&str> = shutdown_order(&[db, net, game, log], &deps)
+            .iter()
+            .map(|srv| srv.name())
+            .collect();
+        assert_eq!(order, vec!["log", "net", "game", "db"]);
+
+        let timed_out =
+            stop_services_with_deps(&[db, net, game, log], &deps, Duration::from_millis(100));
+        assert!(timed_out.is_empty());
+        assert_eq!(*stopped.lock(), vec!["log", "net", "game", "db"]);
+    }
+
+    #[test]
+    fn priority_first_and_timeout_does_not_hang() {
+        let stopped = Arc::new(Mutex::new(Vec::new()));
+        let net = fake(1, "net", 10, true, &stopped);
+        let stuck = fake(2, "stuck", 0, false, &stopped);
+        let game = fake(3, "game", 0, true, &stopped);
+
+        let timed_out = stop_services(&[net, stuck, game], Duration::from_millis(20));
+        assert_eq!(timed_out, vec![2]);
+        assert_eq!(*stopped.lock(), vec!["net", "game", "stuck"]);
+    }
+
+    #[test]
+    fn xml_config_optional() {
+        let handle = ServiceHandle::new(1, NodeState::Idle);
+        assert_eq!(
+            handle.xml_config().err(),
+            Some(ServiceError::XmlConfigNotFound { node_id: 0 })
+        );
+
+        // 节点不存在
+        handle.set_xml_config(1001, None);
+        let err = handle.xml_config().err().unwrap();
+        assert_eq!(err.to_string(), "node 1001 xml config not found");
+
+        let xr = XmlReader::read_content(r#"<node id="1002"><port>20001</port></node>"#).unwrap();
+        let handle = ServiceHandle::new(2, NodeState::Idle).with_xml_config(xr);
+        assert_eq!(handle.xml_node_id.load(Ordering::Relaxed), 1002);
+        assert_eq!(handle.xml_config().unwrap().get_u64(vec!["port"], 0), 20001);
+    }
 }
@@ -1,9 +1,131 @@
 use crate::{xmlreader, ServiceRs, XmlReader};
+use arc_swap::{ArcSwap, Guard};
 use std::ops::Deref;
-use std::sync::{Arc, Mutex, RwLock};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI32, AtomicU32, Ordering};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
 use std::{collections::HashMap, str::FromStr};
 use std::{fs, thread};
 
+mod csv;
+pub use csv::{read_csv_string, read_csv_table};
+
+#[cfg(feature = "serde")]
+mod json;
+#[cfg(feature = "serde")]
+pub use json::{read_data_table_json, JSON_FIELD_SCAN_ROWS};
+
+/// 列类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Int,
+    UInt,
+    Float,
+    String,
+    Bool,
+}
+
+impl ColumnType {
+    /// 由类型名构造（xml cell 的 type 属性）
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "int" | "i32" | "i64" => Some(ColumnType::Int),
+            "uint" | "u32" | "u64" => Some(ColumnType::UInt),
+            "float" | "f32" | "f64" | "double" => Some(ColumnType::Float),
+            "string" | "str" => Some(ColumnType::String),
+            "bool" => Some(ColumnType::Bool),
+            _ => None,
+        }
+    }
+
+    fn parse(&self, raw: &str) -> Option<CellValue> {
+        if raw.is_empty() {
+            return Some(CellValue::Empty);
+        }
+        match self {
+            ColumnType::Int => raw.trim().parse::<i64>().ok().map(CellValue::Int),
+            ColumnType::UInt => raw.trim().parse::<u64>().ok().map(CellValue::UInt),
+            ColumnType::Float => raw.trim().parse::<f64>().ok().map(CellValue::Float),
+            ColumnType::String => Some(CellValue::Text),
+            ColumnType::Bool => match raw.trim().to_ascii_lowercase().as_str() {
+                "1" | "true" => Some(CellValue::Bool(true)),
+                "0" | "false" => Some(CellValue::Bool(false)),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// 预解析的单元格数据，字符串仍然使用 rows 中的原始数据
+#[derive(Debug, Clone, PartialEq)]
+pub enum CellValue {
+    Empty,
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Bool(bool),
+    Text,
+}
+
+/// 单元格类型校验错误
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaError {
+    pub table: String,
+    pub row: usize,
+    pub column: String,
+    pub value: String,
+    pub expected: ColumnType,
+}
+
+impl std::fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "table({}) row({}) column({}): \"{}\" is not {:?}",
+            self.table, self.row, self.column, self.value, self.expected
+        )
+    }
+}
+
+/// DataTable 读取错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataTableError {
+    RowOutOfRange {
+        row: usize,
+        len: usize,
+    },
+    UnknownColumn(String),
+    ShortRow {
+        row: usize,
+        column: String,
+        len: usize,
+    },
+    SchemaMismatch {
+        table: String,
+    },
+}
+
+impl std::fmt::Display for DataTableError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DataTableError::RowOutOfRange { row, len } => {
+                write!(f, "row({}) out of range, len={}", row, len)
+            }
+            DataTableError::UnknownColumn(column) => write!(f, "unknown column({})", column),
+            DataTableError::ShortRow { row, column, len } => {
+                write!(
+                    f,
+                    "row({}) has only {} cells, column({}) missing",
+                    row, len, column
+                )
+            }
+            DataTableError::SchemaMismatch { table } => {
+                write!(f, "table({}) fields mismatch", table)
+            }
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct DataTable {
     pub name: String,
@@ -11,6 +133,14 @@ pub struct DataTable {
     pub rows: Vec<Vec<String>>,
     pub field_index: HashMap<String, usize>,
     pub rows_by_pk: HashMap<String, usize>,
+
+    // 二级索引: 列名 -> (单元格值 -> 行号列表)
+    pub secondary_indices: HashMap<String, HashMap<String, Vec<usize>>>,
+
+    // 可选的列类型声明及预解析结果
+    pub column_types: Vec<(String, ColumnType)>,
+    pub cells: Vec<Vec<CellValue>>,
+    pub schema_errors: Vec<SchemaError>,
 }
 
 impl DataTable {
@@ -21,8 +151,20 @@ impl DataTable {
             rows: Vec::new(),
             field_index: HashMap::new(),
             rows_by_pk: HashMap::new(),
+
+            secondary_indices: HashMap::new(),
+
+            column_types: Vec::new(),
+            cells: Vec::new(),
+            schema_errors: Vec::new(),
         }
     }
+
+    /// 声明列类型，在 set_data 时校验并预解析
+    pub fn set_column_types(&mut self, column_types: Vec<(String, ColumnType)>) {
+        self.column_types = column_types;
+    }
+
     pub fn set_data(&mut self, data: Vec<Vec<String>>) {
         // 使用第一个字段作为参考字段
         let reference_field = &self.fields[0];
@@ -49,33 +191,214 @@ impl DataTable {
             for (row_index, row) in self.rows.iter().enumerate() {
                 // 获取当前行的主键值（使用参考字段的值作为主键）
                 let key = row.get(reference_field_index).cloned().unwrap_or_default();
-                // 将主键值和行索引插入主键索引中
-                self.rows_by_pk.insert(key, row_index);
+                // 将主键值和行索引插入主键索引中，重复主键以后出现的行为准
+                if let Some(old_index) = self.rows_by_pk.insert(key, row_index) {
+                    log::warn!(
+                        "table({}) duplicate primary key({}) at row {} and row {}",
+                        self.name,
+                        row.get(reference_field_index).map_or("", |s| s.as_str()),
+                        old_index,
+                        row_index
+                    );
+                }
             }
         }
+
+        // 数据变化后重建已有的二级索引
+        let columns: Vec<String> = self.secondary_indices.keys().cloned().collect();
+        self.secondary_indices.clear();
+        for column in columns {
+            if let Err(err) = self.build_index(&column) {
+                log::error!("table({}) rebuild index failed: {}", self.name, err);
+            }
+        }
+
+        //
+        self.parse_cells();
     }
-    pub fn get(&self, row: usize, column: &str) -> String {
-        let mut index_row = 0;
 
-        if row < self.rows.len() && row > 0 {
-            index_row = row;
+    /// 为 column 列建立二级索引（允许重复值），末尾留空的单元格按空字符串索引
+    pub fn build_index(&mut self, column: &str) -> Result<(), DataTableError> {
+        let col_index = match self.field_index.get(column) {
+            Some(col_index) => *col_index,
+            None => return Err(DataTableError::UnknownColumn(column.to_owned())),
+        };
+
+        let mut index: HashMap<String, Vec<usize>> = HashMap::new();
+        for (row_index, row) in self.rows.iter().enumerate() {
+            let key = row.get(col_index).cloned().unwrap_or_default();
+            index.entry(key).or_default().push(row_index);
         }
-        let data = &self.rows[index_row];
-        let it = self.field_index.get(column).copied().unwrap_or(0);
+        self.secondary_indices.insert(column.to_owned(), index);
+        Ok(())
+    }
 
-        if data.len() < self.field_index.len() {
-            panic!("data.len < self.field_index.len");
+    /// 按二级索引查找单元格文本等于 value 的行号，未建立索引时返回空
+    pub fn rows_by(&self, column: &str, value: &str) -> &[usize] {
+        match self.secondary_indices.get(column) {
+            Some(index) => index.get(value).map(|rows| rows.as_slice()).unwrap_or(&[]),
+            None => {
+                log::error!("table({}) column({}) is not indexed", self.name, column);
+                &[]
+            }
         }
-        if it < data.len() {
-            if let Some(tmp) = data.get(it) {
-                return tmp.clone();
-            } else {
-                return String::new();
+    }
+
+    /// 查找单元格解析后等于 value 的行号（升序），有二级索引时按索引键比较，否则逐行扫描
+    pub fn get_rows_where<T>(&self, column: &str, value: T) -> Vec<usize>
+    where
+        T: FromStr + PartialEq,
+    {
+        let matches = |cell: &str| cell.parse::<T>().map_or(false, |v| v == value);
+
+        if let Some(index) = self.secondary_indices.get(column) {
+            let mut rows: Vec<usize> = index
+                .iter()
+                .filter(|(key, _)| matches(key.as_str()))
+                .flat_map(|(_, rows)| rows.iter().copied())
+                .collect();
+            rows.sort_unstable();
+            return rows;
+        }
+
+        match self.field_index.get(column) {
+            Some(col_index) => self
+                .rows
+                .iter()
+                .enumerate()
+                .filter(|(_, row)| matches(row.get(*col_index).map_or("", |s| s.as_str())))
+                .map(|(row_index, _)| row_index)
+                .collect(),
+            None => {
+                log::error!("table({}) unknown column({})", self.name, column);
+                Vec::new()
             }
-        } else {
-            panic!(" it < data.len()");
         }
     }
+
+    // 按列类型校验并预解析，收集全部错误
+    fn parse_cells(&mut self) {
+        self.cells.clear();
+        self.schema_errors.clear();
+        if self.column_types.is_empty() {
+            return;
+        }
+
+        let types: Vec<Option<ColumnType>> = self
+            .fields
+            .iter()
+            .map(|field| {
+                self.column_types
+                    .iter()
+                    .find(|(name, _)| name == field)
+                    .map(|(_, column_type)| *column_type)
+            })
+            .collect();
+
+        for (row_index, row) in self.rows.iter().enumerate() {
+            let mut row_cells = Vec::with_capacity(types.len());
+            for (col_index, column_type_opt) in types.iter().enumerate() {
+                let raw = row.get(col_index).map(|s| s.as_str()).unwrap_or("");
+                let cell = match column_type_opt {
+                    Some(column_type) => match column_type.parse(raw) {
+                        Some(cell) => cell,
+                        None => {
+                            self.schema_errors.push(SchemaError {
+                                table: self.name.clone(),
+                                row: row_index,
+                                column: self.fields[col_index].clone(),
+                                value: raw.to_owned(),
+                                expected: *column_type,
+                            });
+                            CellValue::Empty
+                        }
+                    },
+                    None => CellValue::Text,
+                };
+                row_cells.push(cell);
+            }
+            self.cells.push(row_cells);
+        }
+    }
+
+    fn get_cell(&self, row: usize, column: &str) -> Option<&CellValue> {
+        let col_index = self.field_index.get(column)?;
+        self.cells.get(row)?.get(*col_index)
+    }
+
+    /// 读取整数，已声明列类型时不再解析字符串
+    pub fn get_int(&self, row: usize, column: &str) -> Option<i64> {
+        if self.cells.is_empty() {
+            return self.get_value::<i64>(row, column);
+        }
+        match self.get_cell(row, column)? {
+            CellValue::Int(v) => Some(*v),
+            CellValue::UInt(v) => i64::try_from(*v).ok(),
+            _ => None,
+        }
+    }
+
+    /// 读取浮点数
+    pub fn get_f64(&self, row: usize, column: &str) -> Option<f64> {
+        if self.cells.is_empty() {
+            return self.get_value::<f64>(row, column);
+        }
+        match self.get_cell(row, column)? {
+            CellValue::Float(v) => Some(*v),
+            CellValue::Int(v) => Some(*v as f64),
+            CellValue::UInt(v) => Some(*v as f64),
+            _ => None,
+        }
+    }
+
+    /// 读取布尔值
+    pub fn get_bool(&self, row: usize, column: &str) -> Option<bool> {
+        if self.cells.is_empty() {
+            return match self.get(row, column).trim() {
+                "1" | "true" => Some(true),
+                "0" | "false" => Some(false),
+                _ => None,
+            };
+        }
+        match self.get_cell(row, column)? {
+            CellValue::Bool(v) => Some(*v),
+            _ => None,
+        }
+    }
+    /// 读取单元格字符串，出错时返回空字符串
+    pub fn get(&self, row: usize, column: &str) -> String {
+        match self.try_get(row, column) {
+            Ok(value) => value,
+            Err(DataTableError::ShortRow { .. }) => String::new(), // 策划经常留空末尾单元格
+            Err(err) => {
+                log::error!("table({}) get error: {}", self.name, err);
+                String::new()
+            }
+        }
+    }
+
+    /// 读取单元格字符串
+    pub fn try_get(&self, row: usize, column: &str) -> Result<String, DataTableError> {
+        let data = self.rows.get(row).ok_or(DataTableError::RowOutOfRange {
+            row,
+            len: self.rows.len(),
+        })?;
+        let col_index = self
+            .field_index
+            .get(column)
+            .copied()
+            .ok_or_else(|| DataTableError::UnknownColumn(column.to_owned()))?;
+
+        match data.get(col_index) {
+            Some(value) => Ok(value.clone()),
+            None => Err(DataTableError::ShortRow {
+                row,
+                column: column.to_owned(),
+                len: data.len(),
+            }),
+        }
+    }
+
     //获取不同类型字段
     pub fn get_value<T>(&self, row: usize, column: &str) -> Option<T>
     where
@@ -92,7 +415,148 @@ impl DataTable {
     fn get_row_by_key(&self, key: &str) -> Option<usize> {
         self.rows_by_pk.get(key).copied()
     }
+
+    /// 以 self 为旧版本，按主键比较 other 中新增、删除和修改的行
+    pub fn diff<'a>(&'a self, other: &'a DataTable) -> Result<DataTableDiff<'a>, DataTableError> {
+        if self.fields != other.fields {
+            return Err(DataTableError::SchemaMismatch {
+                table: self.name.clone(),
+            });
+        }
+
+        let mut diff = DataTableDiff::default();
+        for (index, new_row) in other.rows.iter().enumerate() {
+            let pk = new_row.first().map(|s| s.as_str()).unwrap_or("");
+            if other.rows_by_pk.get(pk) != Some(&index) {
+                continue; // 重复主键只比较最后一行
+            }
+            match self.get_row_by_key(pk) {
+                Some(old_index) => {
+                    let old_row = &self.rows[old_index];
+                    if old_row != new_row {
+                        diff.modified.push((old_row, new_row));
+                    }
+                }
+                None => diff.added.push(new_row),
+            }
+        }
+        for (index, old_row) in self.rows.iter().enumerate() {
+            let pk = old_row.first().map(|s| s.as_str()).unwrap_or("");
+            if self.rows_by_pk.get(pk) == Some(&index) && !other.rows_by_pk.contains_key(pk) {
+                diff.removed.push(old_row);
+            }
+        }
+        Ok(diff)
+    }
+
+    /// 按行顺序遍历
+    pub fn iter_rows(&self) -> DataTableRowIter<'_> {
+        DataTableRowIter {
+            table: self,
+            next: 0,
+        }
+    }
+
+    /// 按主键字符串排序遍历
+    pub fn rows_by_pk_iter(&self) -> impl Iterator<Item = (&str, DataTableRow<'_>)> {
+        let mut keys: Vec<(&str, usize)> = self
+            .rows_by_pk
+            .iter()
+            .map(|(key, index)| (key.as_str(), *index))
+            .collect();
+        keys.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        keys.into_iter()
+            .map(move |(key, index)| (key, DataTableRow { table: self, index }))
+    }
+}
+
+/// 两个版本 DataTable 的差异
+#[derive(Debug, Default, PartialEq)]
+pub struct DataTableDiff<'a> {
+    pub added: Vec<&'a Vec<String>>,
+    pub removed: Vec<&'a Vec<String>>,
+    pub modified: Vec<(&'a Vec<String>, &'a Vec<String>)>, // (old, new)
+}
+
+impl DataTableDiff<'_> {
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// DataTable 中的一行
+#[derive(Debug, Clone, Copy)]
+pub struct DataTableRow<'a> {
+    table: &'a DataTable,
+    index: usize,
+}
+
+impl<'a> DataTableRow<'a> {
+    /// 行号
+    #[inline(always)]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// 主键（第一列）
+    pub fn pk(&self) -> &'a str {
+        self.table.rows[self.index]
+            .first()
+            .map(|s| s.as_str())
+            .unwrap_or("")
+    }
+
+    /// 读取单元格字符串，未知列或末尾留空时返回 None
+    pub fn get_str(&self, col: &str) -> Option<&'a str> {
+        let col_index = self.table.field_index.get(col)?;
+        self.table.rows[self.index]
+            .get(*col_index)
+            .map(|s| s.as_str())
+    }
+
+    /// 读取并解析单元格，空字符串返回 None
+    pub fn get_typed<T>(&self, col: &str) -> Option<T>
+    where
+        T: FromStr,
+    {
+        let data = self.get_str(col)?;
+        if data.is_empty() {
+            return None;
+        }
+        data.parse().ok()
+    }
 }
+
+/// DataTable 行迭代器
+pub struct DataTableRowIter<'a> {
+    table: &'a DataTable,
+    next: usize,
+}
+
+impl<'a> Iterator for DataTableRowIter<'a> {
+    type Item = DataTableRow<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next < self.table.rows.len() {
+            let row = DataTableRow {
+                table: self.table,
+                index: self.next,
+            };
+            self.next += 1;
+            Some(row)
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.table.rows.len() - self.next;
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for DataTableRowIter<'_> {}
 #[derive(Debug, Clone)]
 pub struct DataSchema {
     pub tables: HashMap<String, DataTable>,
@@ -114,113 +578,1098 @@ impl DataSchema {
     }
 }
 
-struct DataSchemaLoader {
+/// DataSchema 只读快照
+pub type DataSchemaRef = Guard<Arc<DataSchema>>;
+
+/// 可原子替换的 DataSchema: 读取不加锁, 热加载时整体替换
+pub struct DataSchemaHandle {
+    inner: ArcSwap<DataSchema>,
+}
+
+impl DataSchemaHandle {
+    ///
+    pub fn new() -> Self {
+        Self {
+            inner: ArcSwap::from_pointee(DataSchema::new()),
+        }
+    }
+
+    /// 当前 DataSchema 快照，持有期间不受 swap 影响
+    #[inline(always)]
+    pub fn get(&self) -> DataSchemaRef {
+        self.inner.load()
+    }
+
+    /// 当前 DataSchema（可长期持有）
+    #[inline(always)]
+    pub fn load_full(&self) -> Arc<DataSchema> {
+        self.inner.load_full()
+    }
+
+    /// 替换为 new, 返回旧的 DataSchema
+    pub fn swap(&self, new: DataSchema) -> Arc<DataSchema> {
+        self.inner.swap(Arc::new(new))
+    }
+
+    /// 替换为 new, 返回旧的 DataSchema
+    pub fn swap_arc(&self, new: Arc<DataSchema>) -> Arc<DataSchema> {
+        self.inner.swap(new)
+    }
+}
+
+impl Default for DataSchemaHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 加载完成回调, 在 service 线程中执行
+pub type DataSchemaLoadFn = Box<dyn FnMut(Arc<DataSchema>) + Send + Sync>;
+
+/// 加载进度, 加载线程与 LoadHandle 共享
+#[derive(Default)]
+struct LoadProgress {
+    total: AtomicU32,
+    count: AtomicU32,
+    ec: AtomicI32,
+    done: Mutex<bool>,
+    cv: Condvar,
+}
+
+impl LoadProgress {
+    fn finish(&self) {
+        let mut done = self.done.lock().unwrap();
+        *done = true;
+        self.cv.notify_all();
+    }
+}
+
+/// 保证每条退出路径都调用 finish: 加载线程 panic、回调 panic 或 service 不再执行回调时,
+/// drop 时记录一次错误（ec + 1）后 finish，LoadHandle::wait 不会一直阻塞
+struct LoadFinishGuard {
+    progress: Arc<LoadProgress>,
+    completed: bool,
+}
+
+impl LoadFinishGuard {
+    fn new(progress: &Arc<LoadProgress>) -> Self {
+        Self {
+            progress: progress.clone(),
+            completed: false,
+        }
+    }
+
+    /// 回调已执行完毕
+    fn complete(mut self) {
+        self.completed = true;
+    }
+}
+
+impl Drop for LoadFinishGuard {
+    fn drop(&mut self) {
+        if !self.completed {
+            log::error!("data schema load aborted!!! loader panicked or callback not run");
+            self.progress.ec.fetch_add(1, Ordering::AcqRel);
+        }
+        self.progress.finish();
+    }
+}
+
+/// 异步加载句柄，可查询进度或阻塞等待
+#[derive(Clone)]
+pub struct LoadHandle {
+    progress: Arc<LoadProgress>,
+}
+
+impl LoadHandle {
+    /// 加载完成且回调已在 service 线程执行
+    pub fn is_done(&self) -> bool {
+        *self.progress.done.lock().unwrap()
+    }
+
+    /// 阻塞等待加载完成且回调执行完毕
+    ///
+    /// 回调在目标 service 线程中执行，不要在该线程中调用，否则死锁
+    pub fn wait(&self) {
+        let mut done = self.progress.done.lock().unwrap();
+        while !*done {
+            done = self.progress.cv.wait(done).unwrap();
+        }
+    }
+
+    /// 需要加载的文件数
+    pub fn total(&self) -> u32 {
+        self.progress.total.load(Ordering::Acquire)
+    }
+
+    /// 已处理的文件数
+    pub fn count(&self) -> u32 {
+        self.progress.count.load(Ordering::Acquire)
+    }
+
+    /// 加载失败的文件数
+    pub fn ec(&self) -> i32 {
+        self.progress.ec.load(Ordering::Acquire)
+    }
+}
+
+/// 配置表文件格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TableFormat {
+    Xml,
+    Csv,
+}
+
+/// xml 目录中可以混合放置的配置表格式
+#[cfg(feature = "serde")]
+const XML_DIR_EXTS: &[&str] = &["xml", "xlsx", "json"];
+#[cfg(not(feature = "serde"))]
+const XML_DIR_EXTS: &[&str] = &["xml", "xlsx"];
+
+impl TableFormat {
+    fn exts(&self) -> &'static [&'static str] {
+        match self {
+            TableFormat::Xml => XML_DIR_EXTS,
+            TableFormat::Csv => &["csv"],
+        }
+    }
+
+    // 按扩展名选择读取方式
+    fn read_table(&self, path: &Path) -> Result<DataTable, String> {
+        let ext = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+        match (self, ext.as_str()) {
+            (TableFormat::Xml, "xlsx") => crate::xlsx_reader::read_data_table(path, None),
+            #[cfg(feature = "serde")]
+            (TableFormat::Xml, "json") => read_data_table_json(path),
+            (TableFormat::Xml, _) => XmlReader::read_data_table(path),
+            (TableFormat::Csv, _) => read_csv_table(path),
+        }
+    }
+}
+
+/// 配置表加载器
+pub struct DataSchemaLoader {
     myid: i32,
-    dc: Arc<Mutex<DataSchema>>, //多线程读写
-    cb: Box<dyn FnMut(Box<DataSchema>) + Send + Sync>,
-    need_load_tables: Vec<String>,
-    xml_path: String,
+    format: TableFormat,
+    parallel: bool,
+    manifest: Option<HashMap<String, String>>, // 文件名 -> sha256 hex
+    dc: DataSchema,
+    handle: Arc<DataSchemaHandle>,
+    cb: DataSchemaLoadFn,
+    need_load_tables: Vec<PathBuf>,
+    xml_path: PathBuf,
     xml_data: String,
-    count: u32,
-    ec: i32,
+    progress: Arc<LoadProgress>, // count, ec
     tables: HashMap<String, bool>,
     pks: HashMap<String, String>,
 }
 
 impl DataSchemaLoader {
     pub fn new() -> Self {
-        fn default_closure() -> Box<dyn FnMut(Box<DataSchema>) + Send + Sync> {
+        fn default_closure() -> DataSchemaLoadFn {
             Box::new(|_| {})
         }
         DataSchemaLoader {
             myid: 0,
-            dc: Arc::new(Mutex::new(DataSchema::new())),
+            format: TableFormat::Xml,
+            parallel: false,
+            manifest: None,
+            dc: DataSchema::new(),
+            handle: Arc::new(DataSchemaHandle::new()),
             need_load_tables: Vec::new(),
-            count: 0,
-            ec: 0,
+            progress: Arc::new(LoadProgress::default()),
             tables: HashMap::new(),
             pks: HashMap::new(),
             cb: default_closure(),
-            xml_path: String::new(),
+            xml_path: PathBuf::new(),
             xml_data: String::new(),
         }
     }
 
-    pub fn do_load_xml<T>(&mut self, srv: &Arc<T>)
+    /// 读取 path 目录下的所有 xml（及 xlsx、json）配置表, recursive 为 true 时包含子目录
+    pub fn from_xml_dir(
+        handle: &Arc<DataSchemaHandle>,
+        path: impl AsRef<Path>,
+        recursive: bool,
+        cb: DataSchemaLoadFn,
+    ) -> Self {
+        Self::from_dir(handle, TableFormat::Xml, path.as_ref(), recursive, cb)
+    }
+
+    /// 读取 path 目录下的所有 csv 配置表, 表名为不含扩展名的文件名
+    pub fn from_csv_dir(
+        handle: &Arc<DataSchemaHandle>,
+        path: impl AsRef<Path>,
+        recursive: bool,
+        cb: DataSchemaLoadFn,
+    ) -> Self {
+        Self::from_dir(handle, TableFormat::Csv, path.as_ref(), recursive, cb)
+    }
+
+    fn from_dir(
+        handle: &Arc<DataSchemaHandle>,
+        format: TableFormat,
+        path: &Path,
+        recursive: bool,
+        cb: DataSchemaLoadFn,
+    ) -> Self {
+        let mut loader = DataSchemaLoader::new();
+        loader.format = format;
+        loader.handle = handle.clone();
+        loader.cb = cb;
+        loader.xml_path = path.to_path_buf();
+        loader.need_load_tables = get_table_files(path, recursive, format.exts());
+        loader
+    }
+
+    /// 多线程并行解析配置表，回调仍然通过 srv.run_in_service 在 service 线程中执行
+    pub fn set_parallel(mut self, parallel: bool) -> Self {
+        self.parallel = parallel;
+        self
+    }
+
+    /// 设置校验清单: json 对象 {"文件名": "sha256 hex"}, 文件名为相对于配置目录的路径,
+    /// 加载时内容与清单不一致（或不在清单中）的表视为加载失败
+    pub fn set_manifest_file(&mut self, path: &str) -> Result<(), String> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| format!("read manifest file({}) error: {}.", path, err))?;
+        let manifest: HashMap<String, String> = serde_json::from_str(&content)
+            .map_err(|err| format!("parse manifest file({}) error: {}.", path, err))?;
+        self.manifest = Some(
+            manifest
+                .into_iter()
+                .map(|(name, digest)| (name, digest.trim().to_ascii_lowercase()))
+                .collect(),
+        );
+        Ok(())
+    }
+
+    /// 只校验不加载: 全部通过时返回文件列表, 否则返回错误列表
+    pub fn verify_only(&self) -> Result<Vec<String>, Vec<String>> {
+        if self.manifest.is_none() {
+            return Err(vec!["manifest file not set".to_owned()]);
+        }
+
+        let mut ok_list = Vec::new();
+        let mut err_list = Vec::new();
+        for v in &self.need_load_tables {
+            match self.verify_file(v) {
+                Ok(()) => ok_list.push(manifest_key(v)),
+                Err(err) => err_list.push(err),
+            }
+        }
+
+        if err_list.is_empty() {
+            Ok(ok_list)
+        } else {
+            Err(err_list)
+        }
+    }
+
+    /// 在当前线程中读取所有配置表（不替换 handle，不回调）, 有表读取失败或 schema 错误时返回错误列表
+    pub fn read_schema(&self) -> Result<DataSchema, Vec<String>> {
+        let mut ds = DataSchema::new();
+        let mut err_list = Vec::new();
+        for (file_path, dt) in self.read_tables() {
+            match dt {
+                Ok(content) => {
+                    for err in &content.schema_errors {
+                        err_list.push(format!("data table({:?}) schema error: {}", file_path, err));
+                    }
+                    ds.tables.insert(content.name.clone(), content);
+                }
+                Err(err) => {
+                    err_list.push(format!("load data table({:?}) failed: {}", file_path, err))
+                }
+            }
+        }
+
+        if err_list.is_empty() {
+            Ok(ds)
+        } else {
+            Err(err_list)
+        }
+    }
+
+    // 未设置清单时直接通过
+    fn verify_file(&self, v: &Path) -> Result<(), String> {
+        use sha2::Digest;
+
+        let manifest = match &self.manifest {
+            Some(manifest) => manifest,
+            None => return Ok(()),
+        };
+
+        let key = manifest_key(v);
+        let expected = manifest
+            .get(&key)
+            .ok_or_else(|| format!("file({}) not found in manifest", key))?;
+
+        let file_path = self.xml_path.join(v);
+        let bytes = fs::read(&file_path)
+            .map_err(|err| format!("read file({:?}) error: {}.", file_path, err))?;
+        let digest = hex::encode(sha2::Sha256::digest(&bytes));
+        if digest != *expected {
+            return Err(format!(
+                "file({}) sha256 mismatch: expected {}, got {} ({} bytes)",
+                key,
+                expected,
+                digest,
+                bytes.len()
+            ));
+        }
+        Ok(())
+    }
+
+    // 按 need_load_tables 的顺序返回每个文件的解析结果
+    fn read_tables(&self) -> Vec<(PathBuf, Result<DataTable, String>)> {
+        let read = |v: &PathBuf| {
+            let file_path = self.xml_path.join(v);
+            let dt = self
+                .verify_file(v)
+                .and_then(|_| self.format.read_table(&file_path));
+            self.progress.count.fetch_add(1, Ordering::AcqRel);
+            (file_path, dt)
+        };
+
+        if self.parallel {
+            use rayon::prelude::*;
+            self.need_load_tables.par_iter().map(read).collect()
+        } else {
+            self.need_load_tables.iter().map(read).collect()
+        }
+    }
+
+    pub fn do_load<T>(&mut self, srv: &Arc<T>)
     where
         T: ServiceRs + 'static,
     {
-        for v in &self.need_load_tables {
-            let file_path = format!("{}{}", self.xml_path, v);
-
-            let dt = XmlReader::read_data_table(&file_path);
+        self.progress
+            .total
+            .store(self.need_load_tables.len() as u32, Ordering::Release);
+        let guard = LoadFinishGuard::new(&self.progress);
+        for (file_path, dt) in self.read_tables() {
             match dt {
                 Ok(content) => {
+                    for err in &content.schema_errors {
+                        log::error!("data schema error: {}", err);
+                    }
+
                     let key = &content.name;
-                    let value = &content.fields[0];
+                    let value = match content.fields.first() {
+                        Some(value) => value,
+                        None => {
+                            log::error!("load data table({:?}) failed: no fields", file_path);
+                            self.progress.ec.fetch_add(1, Ordering::AcqRel);
+                            continue;
+                        }
+                    };
                     self.pks.insert(key.to_string(), value.to_string());
-                    // 写数据锁，可以同时被多个线程获取
-                    let mut write_lock = self.dc.lock().unwrap();
-                    write_lock.tables.insert(key.to_string(), content.clone());
+                    self.dc.tables.insert(key.to_string(), content);
                     self.tables.insert(key.to_string(), true);
                 }
-                Err(_err) => {
+                Err(err) => {
+                    log::error!("load data table({:?}) failed: {}", file_path, err);
+                    self.progress.ec.fetch_add(1, Ordering::AcqRel);
                     continue;
                 }
             }
         }
-        let data_schema = self.dc.lock().unwrap();
-        let db = Box::new(data_schema.clone());
-        let cbb = self.cb.as_mut();
-        let f = (*cbb)(db);
-        srv.run_in_service(Box::new(move || f));
+
+        // 在 service 线程中替换并回调
+        let db = Arc::new(std::mem::replace(&mut self.dc, DataSchema::new()));
+        let handle = self.handle.clone();
+        let mut cb = std::mem::replace(&mut self.cb, Box::new(|_| {}));
+        srv.run_in_service(Box::new(move || {
+            handle.swap_arc(db.clone());
+            cb(db);
+            guard.complete();
+        }));
     }
 
-    pub fn load_xml<T>(&mut self, srv: &Arc<T>)
+    /// 在独立的加载线程中读取所有配置表
+    pub fn load<T>(self, srv: &Arc<T>) -> LoadHandle
     where
         T: ServiceRs + 'static,
     {
-        let thread_handle = thread::scope(|s| {
-            self.do_load_xml(srv);
-        });
+        let handle = LoadHandle {
+            progress: self.progress.clone(),
+        };
+
+        // 在启动加载线程之前设置，返回后即可查询
+        self.progress
+            .total
+            .store(self.need_load_tables.len() as u32, Ordering::Release);
+
+        let srv = srv.clone();
+        let mut loader = self;
+        thread::Builder::new()
+            .name("data_schema_loader".to_owned())
+            .spawn(move || loader.do_load(&srv))
+            .unwrap();
+        handle
     }
 }
 
-fn get_just_current_file(cate_dir: &str) -> Vec<String> {
-    let path = std::env::current_dir().unwrap().join(cate_dir);
-
-    let mut file_list = Vec::new();
+/// 校验清单中的文件名, 统一使用 '/' 分隔
+fn manifest_key(rel_path: &Path) -> String {
+    rel_path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
 
-    if let Ok(entries) = fs::read_dir(path) {
-        for entry in entries {
-            if let Ok(entry) = entry {
-                if let Ok(file_type) = entry.file_type() {
-                    if file_type.is_file() {
-                        if let Some(file_name) = entry.file_name().to_str() {
-                            file_list.push(file_name.to_string());
-                        }
+/// 收集目录下扩展名在 exts 中的文件（忽略大小写）, 返回相对于 dir 的路径
+fn get_table_files(dir: &Path, recursive: bool, exts: &[&str]) -> Vec<PathBuf> {
+    fn walk(root: &Path, sub: &Path, recursive: bool, exts: &[&str], file_list: &mut Vec<PathBuf>) {
+        let entries = match fs::read_dir(root.join(sub)) {
+            Ok(entries) => entries,
+            Err(err) => {
+                log::error!("read dir({:?}) failed: {}", root.join(sub), err);
+                return;
+            }
+        };
+        for entry in entries.flatten() {
+            let rel_path = sub.join(entry.file_name());
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => {
+                    if recursive {
+                        walk(root, &rel_path, recursive, exts, file_list);
                     }
                 }
+                Ok(file_type) if file_type.is_file() => {
+                    let matched = rel_path.extension().map_or(false, |e| {
+                        exts.iter().any(|ext| e.eq_ignore_ascii_case(ext))
+                    });
+                    if matched {
+                        file_list.push(rel_path);
+                    } else {
+                        log::debug!("skip non-{:?} file: {:?}", exts, root.join(&rel_path));
+                    }
+                }
+                _ => {}
             }
         }
     }
 
+    let mut file_list = Vec::new();
+    walk(dir, Path::new(""), recursive, exts, &mut file_list);
+    file_list.sort();
     file_list
 }
+/// 在加载线程中读取 path 目录下的所有 xml（及 xlsx）配置表, recursive 为 true 时包含子目录,
+/// 加载完成后在 srv 线程中替换 handle 并回调
 pub fn load_data_schema_from_xml<T>(
     srv: &Arc<T>,
-    path: &str,
-    cb: Box<dyn FnMut(Box<DataSchema>) + Send + Sync>,
-) where
+    handle: &Arc<DataSchemaHandle>,
+    path: impl AsRef<Path>,
+    recursive: bool,
+    cb: DataSchemaLoadFn,
+) -> LoadHandle
+where
     T: ServiceRs + 'static,
 {
-    let mut loader = DataSchemaLoader::new();
-    loader.cb = cb;
-    loader.dc = Arc::new(Mutex::new(DataSchema::new()));
-    loader.xml_path = path.to_string();
-    let allfiles = get_just_current_file(path);
-    for i in allfiles {
-        loader.need_load_tables.push(i.clone());
-    }
-    loader.load_xml(srv);
+    DataSchemaLoader::from_xml_dir(handle, path, recursive, cb).load(srv)
+}
+
+/// 在加载线程中读取 path 目录下的所有 csv 配置表, 表名为不含扩展名的文件名
+pub fn load_data_schema_from_csv<T>(
+    srv: &Arc<T>,
+    handle: &Arc<DataSchemaHandle>,
+    path: impl AsRef<Path>,
+    recursive: bool,
+    cb: DataSchemaLoadFn,
+) -> LoadHandle
+where
+    T: ServiceRs + 'static,
+{
+    DataSchemaLoader::from_csv_dir(handle, path, recursive, cb).load(srv)
+}
+
+/// 配置表内容摘要, 用于热加载时比较表是否变化
+fn table_content_hash(dt: &DataTable) -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    dt.fields.hash(&mut hasher);
+    dt.rows.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// 配置热加载回调: 参数为变化的表组成的 DataSchema, 以及变化(含删除)的表名
+pub type DataSchemaReloadFn =
+    Arc<dyn Fn(Box<DataSchema>, hashbrown::HashSet<String>) + Send + Sync>;
+
+/// 配置热加载: 按需重新读取目录下所有 xml（及 xlsx/json）, 与上次加载结果比较, 只通知变化的表
+pub struct DataSchemaWatcher {
+    xml_path: PathBuf,
+    recursive: bool,
+    hashes: HashMap<String, u64>,
+    files: HashMap<PathBuf, String>, // 文件 -> 上次读取成功的表名
+    cb: DataSchemaReloadFn,
+}
+
+impl DataSchemaWatcher {
+    ///
+    pub fn new(path: impl AsRef<Path>, recursive: bool, cb: DataSchemaReloadFn) -> Self {
+        DataSchemaWatcher {
+            xml_path: path.as_ref().to_path_buf(),
+            recursive,
+            hashes: HashMap::new(),
+            files: HashMap::new(),
+            cb,
+        }
+    }
+
+    /// 重新加载并在 srv 线程中回调, 没有变化时不回调
+    pub fn reload<T>(&mut self, srv: &Arc<T>) -> hashbrown::HashSet<String>
+    where
+        T: ServiceRs + 'static,
+    {
+        let (ds, changed) = self.load_changed();
+        if !changed.is_empty() {
+            let cb = self.cb.clone();
+            let tables = changed.clone();
+            srv.run_in_service(Box::new(move || cb(Box::new(ds), tables)));
+        }
+        changed
+    }
+
+    /// 重新读取所有配置表, 返回变化的表及变化(含删除)的表名
+    ///
+    /// 读取失败的文件沿用上次的结果, 既不视为变化也不视为删除
+    fn load_changed(&mut self) -> (DataSchema, hashbrown::HashSet<String>) {
+        let mut ds = DataSchema::new();
+        let mut changed = hashbrown::HashSet::new();
+        let mut hashes = HashMap::new();
+        let mut files = HashMap::new();
+
+        let format = TableFormat::Xml;
+        for file in get_table_files(&self.xml_path, self.recursive, format.exts()) {
+            let file_path = self.xml_path.join(&file);
+            match format.read_table(&file_path) {
+                Ok(dt) => {
+                    files.insert(file, dt.name.clone());
+                    for err in &dt.schema_errors {
+                        log::error!("data schema error: {}", err);
+                    }
+
+                    let hash = table_content_hash(&dt);
+                    if self.hashes.get(&dt.name) != Some(&hash) {
+                        changed.insert(dt.name.clone());
+                        hashes.insert(dt.name.clone(), hash);
+                        ds.tables.insert(dt.name.clone(), dt);
+                    } else {
+                        hashes.insert(dt.name.clone(), hash);
+                    }
+                }
+                Err(err) => {
+                    log::error!("reload data table({:?}) failed: {}", file_path, err);
+                    if let Some(name) = self.files.get(&file) {
+                        if let Some(hash) = self.hashes.get(name) {
+                            hashes.insert(name.clone(), *hash);
+                        }
+                        files.insert(file, name.clone());
+                    }
+                }
+            }
+        }
+
+        // 被删除的表
+        for name in self.hashes.keys() {
+            if !hashes.contains_key(name) {
+                changed.insert(name.clone());
+            }
+        }
+
+        self.hashes = hashes;
+        self.files = files;
+        (ds, changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_table() -> DataTable {
+        let mut dt = DataTable::new(
+            "role".to_owned(),
+            vec![
+                "id".to_owned(),
+                "lv".to_owned(),
+                "rate".to_owned(),
+                "open".to_owned(),
+            ],
+        );
+        dt.set_column_types(vec![
+            ("id".to_owned(), ColumnType::UInt),
+            ("lv".to_owned(), ColumnType::Int),
+            ("rate".to_owned(), ColumnType::Float),
+            ("open".to_owned(), ColumnType::Bool),
+        ]);
+        dt
+    }
+
+    fn row(cells: &[&str]) -> Vec<String> {
+        cells.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn typed_columns() {
+        let mut dt = make_table();
+        dt.set_data(vec![
+            row(&["1", "-3", "0.5", "true"]),
+            row(&["2", "7", "", "0"]),
+        ]);
+
+        assert!(dt.schema_errors.is_empty());
+        assert_eq!(dt.get_int(0, "id"), Some(1));
+        assert_eq!(dt.get_int(0, "lv"), Some(-3));
+        assert_eq!(dt.get_f64(0, "rate"), Some(0.5));
+        assert_eq!(dt.get_f64(1, "rate"), None);
+        assert_eq!(dt.get_bool(0, "open"), Some(true));
+        assert_eq!(dt.get_bool(1, "open"), Some(false));
+    }
+
+    #[test]
+    fn collect_all_schema_errors() {
+        let mut dt = make_table();
+        dt.set_data(vec![
+            row(&["1", "x", "0.5", "yes"]),
+            row(&["-2", "7", "abc", "1"]),
+        ]);
+
+        let errors: Vec<(usize, &str)> = dt
+            .schema_errors
+            .iter()
+            .map(|e| (e.row, e.column.as_str()))
+            .collect();
+        assert_eq!(errors, vec![(0, "lv"), (0, "open"), (1, "id"), (1, "rate")]);
+        assert_eq!(dt.schema_errors[0].table, "role");
+    }
+
+    #[test]
+    fn short_row() {
+        let mut dt = DataTable::new(
+            "role".to_owned(),
+            vec![
+                "id".to_owned(),
+                "name".to_owned(),
+                "caste".to_owned(),
+                "lv".to_owned(),
+            ],
+        );
+        dt.set_data(vec![row(&["1", "role1", "3", "1"]), row(&["2", "role2"])]);
+
+        assert_eq!(dt.try_get(1, "name"), Ok("role2".to_owned()));
+        assert_eq!(
+            dt.try_get(1, "lv"),
+            Err(DataTableError::ShortRow {
+                row: 1,
+                column: "lv".to_owned(),
+                len: 2
+            })
+        );
+        assert_eq!(dt.get(1, "caste"), "");
+        assert_eq!(dt.get(0, "lv"), "1");
+
+        // 越界行不再返回第 0 行
+        assert_eq!(
+            dt.try_get(2, "id"),
+            Err(DataTableError::RowOutOfRange { row: 2, len: 2 })
+        );
+        assert_eq!(dt.get(5, "id"), "");
+        assert_eq!(
+            dt.try_get(0, "hp"),
+            Err(DataTableError::UnknownColumn("hp".to_owned()))
+        );
+    }
+    #[test]
+    fn iter_rows() {
+        let mut dt = DataTable::new(
+            "role".to_owned(),
+            vec!["id".to_owned(), "name".to_owned(), "lv".to_owned()],
+        );
+        dt.set_data(vec![
+            row(&["3", "role3", "5"]),
+            row(&["1", "role1", ""]),
+            row(&["2", "role2"]),
+        ]);
+
+        let rows: Vec<(&str, Option<&str>, Option<u32>)> = dt
+            .iter_rows()
+            .map(|r| (r.pk(), r.get_str("name"), r.get_typed::<u32>("lv")))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("3", Some("role3"), Some(5)),
+                ("1", Some("role1"), None),
+                ("2", Some("role2"), None),
+            ]
+        );
+        assert_eq!(dt.iter_rows().len(), 3);
+        assert_eq!(dt.iter_rows().next().unwrap().get_str("hp"), None);
+
+        let sorted: Vec<(&str, usize)> = dt
+            .rows_by_pk_iter()
+            .map(|(pk, r)| (pk, r.index()))
+            .collect();
+        assert_eq!(sorted, vec![("1", 1), ("2", 2), ("3", 0)]);
+    }
+
+    #[test]
+    fn secondary_index() {
+        let mut dt = DataTable::new(
+            "role".to_owned(),
+            vec!["id".to_owned(), "config_id".to_owned()],
+        );
+        dt.set_data(vec![
+            row(&["1", "42"]),
+            row(&["2", "7"]),
+            row(&["3", "42"]),
+            row(&["4"]),
+        ]);
+
+        // 未建立索引时逐行扫描
+        assert_eq!(dt.get_rows_where("config_id", 42_u32), vec![0, 2]);
+        assert!(dt.get_rows_where("hp", 42_u32).is_empty());
+
+        assert_eq!(
+            dt.build_index("hp"),
+            Err(DataTableError::UnknownColumn("hp".to_owned()))
+        );
+        assert_eq!(dt.build_index("config_id"), Ok(()));
+        assert_eq!(dt.rows_by("config_id", "42"), &[0, 2]);
+        assert_eq!(dt.rows_by("config_id", ""), &[3]);
+        assert!(dt.rows_by("config_id", "8").is_empty());
+        assert!(dt.rows_by("id", "1").is_empty());
+        assert_eq!(dt.get_rows_where("config_id", 42_u32), vec![0, 2]);
+        assert_eq!(dt.get_rows_where("config_id", 7_i64), vec![1]);
+
+        // set_data 后自动重建
+        dt.set_data(vec![row(&["5", "7"]), row(&["6", "42"])]);
+        assert_eq!(dt.rows_by("config_id", "42"), &[1]);
+        assert_eq!(dt.rows_by("config_id", "7"), &[0]);
+        assert_eq!(dt.get_rows_where("config_id", 42_u32), vec![1]);
+    }
+
+    #[test]
+    fn diff() {
+        let fields = vec!["id".to_owned(), "lv".to_owned()];
+        let mut old = DataTable::new("role".to_owned(), fields.clone());
+        old.set_data(vec![row(&["1", "1"]), row(&["2", "2"]), row(&["3", "3"])]);
+        let mut new = DataTable::new("role".to_owned(), fields);
+        new.set_data(vec![row(&["3", "3"]), row(&["2", "5"]), row(&["4", "4"])]);
+
+        assert!(old.diff(&old).unwrap().is_empty());
+
+        let diff = old.diff(&new).unwrap();
+        assert!(!diff.is_empty());
+        assert_eq!(diff.added, vec![&row(&["4", "4"])]);
+        assert_eq!(diff.removed, vec![&row(&["1", "1"])]);
+        assert_eq!(diff.modified, vec![(&row(&["2", "2"]), &row(&["2", "5"]))]);
+
+        let other = DataTable::new("role".to_owned(), vec!["id".to_owned()]);
+        assert_eq!(
+            old.diff(&other),
+            Err(DataTableError::SchemaMismatch {
+                table: "role".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn watcher_reports_changed_tables() {
+        fn write_table(dir: &std::path::Path, name: &str, lv: &str) {
+            let xml = format!(
+                "<{name}><data><cell name=\"id\">1</cell><cell name=\"lv\">{lv}</cell></data></{name}>"
+            );
+            fs::write(dir.join(format!("{name}.xml")), xml).unwrap();
+        }
+
+        let dir = std::env::temp_dir().join(format!("data_schema_watcher_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        write_table(&dir, "role", "1");
+        write_table(&dir, "item", "1");
+
+        let mut watcher = DataSchemaWatcher::new(&dir, false, Arc::new(|_, _| {}));
+
+        let (ds, changed) = watcher.load_changed();
+        assert_eq!(changed.len(), 2);
+        assert_eq!(ds.tables.len(), 2);
+
+        let (ds, changed) = watcher.load_changed();
+        assert!(changed.is_empty());
+        assert!(ds.tables.is_empty());
+
+        write_table(&dir, "role", "2");
+        let (ds, changed) = watcher.load_changed();
+        assert_eq!(changed.len(), 1);
+        assert!(changed.contains("role"));
+        assert_eq!(ds.get_table("role").unwrap().get(0, "lv"), "2");
+        assert!(ds.get_table("item").is_none());
+
+        // 读取失败时沿用上次结果, 不报告为删除
+        fs::write(dir.join("item.xml"), "<item><data>").unwrap();
+        let (_, changed) = watcher.load_changed();
+        assert!(changed.is_empty());
+
+        write_table(&dir, "item", "1");
+        let (_, changed) = watcher.load_changed();
+        assert!(changed.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn schema_handle_swap() {
+        let handle = DataSchemaHandle::new();
+        let old = handle.get();
+        assert!(old.get_table("role").is_none());
+
+        let mut ds = DataSchema::new();
+        ds.tables.insert(
+            "role".to_owned(),
+            DataTable::new("role".to_owned(), vec!["id".to_owned()]),
+        );
+        let prev = handle.swap(ds);
+        assert!(prev.tables.is_empty());
+
+        // 旧快照不受影响
+        assert!(old.get_table("role").is_none());
+        assert!(handle.get().get_table("role").is_some());
+
+        let reader = {
+            let ds = handle.load_full();
+            thread::spawn(move || ds.get_table("role").is_some())
+        };
+        assert!(reader.join().unwrap());
+    }
+
+    #[test]
+    fn parallel_read_keeps_order() {
+        let dir = std::env::temp_dir().join(format!("data_schema_parallel_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for i in 0..16 {
+            fs::write(
+                dir.join(format!("t{:02}.csv", i)),
+                format!("id,v\n1,{}\n", i),
+            )
+            .unwrap();
+        }
+
+        let handle = Arc::new(DataSchemaHandle::new());
+        let sequential = DataSchemaLoader::from_csv_dir(&handle, &dir, false, Box::new(|_| {}));
+        let parallel = DataSchemaLoader::from_csv_dir(&handle, &dir, false, Box::new(|_| {}))
+            .set_parallel(true);
+
+        let names = |loader: &DataSchemaLoader| -> Vec<(PathBuf, String)> {
+            loader
+                .read_tables()
+                .into_iter()
+                .map(|(path, dt)| (path, dt.unwrap().get(0, "v")))
+                .collect()
+        };
+        let expected = names(&sequential);
+        assert_eq!(expected.len(), 16);
+        assert_eq!(names(&parallel), expected);
+        assert_eq!(parallel.progress.count.load(Ordering::Acquire), 16);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn xml_dir_mixes_xlsx() {
+        let dir = std::env::temp_dir().join(format!("data_schema_xlsx_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::copy(
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/role.xlsx"),
+            dir.join("role.xlsx"),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("item.xml"),
+            r#"<item><data><cell name="id">7</cell><cell name="count">3</cell></data></item>"#,
+        )
+        .unwrap();
+        fs::write(dir.join("notes.txt"), "skip").unwrap();
+
+        let handle = Arc::new(DataSchemaHandle::new());
+        let loader = DataSchemaLoader::from_xml_dir(&handle, &dir, false, Box::new(|_| {}));
+        let tables: Vec<(String, String)> = loader
+            .read_tables()
+            .into_iter()
+            .map(|(_, dt)| {
+                let dt = dt.unwrap();
+                (dt.name.clone(), dt.get(0, "id"))
+            })
+            .collect();
+        assert_eq!(
+            tables,
+            vec![
+                ("item".to_owned(), "7".to_owned()),
+                ("role".to_owned(), "1".to_owned())
+            ]
+        );
+        let ds = loader.read_schema().unwrap();
+        assert_eq!(ds.get_table("role").unwrap().get(0, "lv"), "5");
+
+        fs::write(dir.join("broken.xml"), "<broken><data>").unwrap();
+        let loader = DataSchemaLoader::from_xml_dir(&handle, &dir, false, Box::new(|_| {}));
+        let errors = loader.read_schema().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("broken.xml"), "{}", errors[0]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn manifest_verify() {
+        let dir = std::env::temp_dir().join(format!("data_schema_manifest_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("good.csv"), "id,v\n1,1\n").unwrap();
+        fs::write(dir.join("bad.csv"), "id,v\n1,").unwrap(); // 复制不完整
+        fs::write(
+            dir.join("manifest.json"),
+            r#"{
+                "good.csv": "147e0064c2bc8d39851c107073c082eb2de0d4329fee793442e81acef4f0a3c2",
+                "bad.csv": "147E0064C2BC8D39851C107073C082EB2DE0D4329FEE793442E81ACEF4F0A3C2"
+            }"#,
+        )
+        .unwrap();
+
+        let handle = Arc::new(DataSchemaHandle::new());
+        let mut loader = DataSchemaLoader::from_csv_dir(&handle, &dir, false, Box::new(|_| {}));
+        assert!(loader.verify_only().is_err());
+        assert!(loader.set_manifest_file("no_such_manifest.json").is_err());
+        loader
+            .set_manifest_file(dir.join("manifest.json").to_str().unwrap())
+            .unwrap();
+
+        let errors = loader.verify_only().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("bad.csv") && errors[0].contains("mismatch"));
+
+        let results = loader.read_tables();
+        assert!(results[0].1.is_err()); // bad.csv
+        assert_eq!(results[1].1.as_ref().unwrap().get(0, "v"), "1");
+
+        fs::remove_file(dir.join("bad.csv")).unwrap();
+        let loader = {
+            let mut loader = DataSchemaLoader::from_csv_dir(&handle, &dir, false, Box::new(|_| {}));
+            loader
+                .set_manifest_file(dir.join("manifest.json").to_str().unwrap())
+                .unwrap();
+            loader
+        };
+        assert_eq!(loader.verify_only().unwrap(), vec!["good.csv".to_owned()]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn load_handle_wait() {
+        let handle = LoadHandle {
+            progress: Arc::new(LoadProgress::default()),
+        };
+        assert!(!handle.is_done());
+
+        let progress = handle.progress.clone();
+        let t = thread::spawn(move || {
+            progress.total.store(2, Ordering::Release);
+            progress.count.store(2, Ordering::Release);
+            progress.ec.store(1, Ordering::Release);
+            progress.finish();
+        });
+        handle.wait();
+        t.join().unwrap();
+
+        assert!(handle.is_done());
+        assert_eq!((handle.total(), handle.count(), handle.ec()), (2, 2, 1));
+    }
+
+    #[test]
+    fn load_finishes_when_callback_fails() {
+        use crate::{NodeState, ServiceNetRs};
+
+        let dir = std::env::temp_dir().join(format!("data_schema_finish_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("role.csv"), "id,lv\n1,1\n").unwrap();
+        let handle = Arc::new(DataSchemaHandle::new());
+        let running_srv = || {
+            let srv = Arc::new(ServiceNetRs::new(0));
+            assert_eq!(srv.get_handle().set_state(NodeState::Run), Ok(()));
+            srv
+        };
+
+        // service 排空中，回调被丢弃
+        let srv = running_srv();
+        assert_eq!(srv.get_handle().set_state(NodeState::Draining), Ok(()));
+        let load =
+            DataSchemaLoader::from_csv_dir(&handle, &dir, false, Box::new(|_| {})).load(&srv);
+        assert_eq!(load.total(), 1);
+        load.wait();
+        assert_eq!((load.count(), load.ec()), (1, 1));
+        assert!(handle.load_full().get_table("role").is_none());
+
+        // 回调 panic
+        let srv = running_srv();
+        let load = DataSchemaLoader::from_csv_dir(
+            &handle,
+            &dir,
+            false,
+            Box::new(|_| panic!("callback failed")),
+        )
+        .load(&srv);
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while srv.get_handle().queue_depth() == 0 {
+            assert!(std::time::Instant::now() < deadline, "loader timeout");
+            thread::sleep(std::time::Duration::from_millis(5));
+        }
+        assert!(!load.is_done());
+        let dispatch = std::panic::AssertUnwindSafe(|| srv.get_handle().dispatch_tasks(usize::MAX));
+        assert!(std::panic::catch_unwind(dispatch).is_err());
+        load.wait();
+        assert_eq!(load.ec(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn xml_files_flat_and_recursive() {
+        let dir = std::env::temp_dir().join(format!("data_schema_files_{}", std::process::id()));
+        fs::create_dir_all(dir.join("quest").join("daily")).unwrap();
+        let xml = |name: &str| format!("<{name}><data><cell name=\"id\">1</cell></data></{name}>");
+        fs::write(dir.join("role.xml"), xml("role")).unwrap();
+        fs::write(dir.join("role.xlsx"), b"PK\x03\x04").unwrap();
+        fs::write(dir.join("quest").join("quest.XML"), xml("quest")).unwrap();
+        fs::write(
+            dir.join("quest").join("daily").join("daily.xml"),
+            xml("daily"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            get_table_files(&dir, false, &["xml"]),
+            vec![PathBuf::from("role.xml")]
+        );
+        assert_eq!(
+            get_table_files(&dir, true, &["xml"]),
+            vec![
+                Path::new("quest").join("daily").join("daily.xml"),
+                Path::new("quest").join("quest.XML"),
+                PathBuf::from("role.xml"),
+            ]
+        );
+
+        let mut watcher = DataSchemaWatcher::new(&dir, true, Arc::new(|_, _| {}));
+        let (ds, changed) = watcher.load_changed();
+        assert_eq!(changed.len(), 3);
+        assert!(ds.get_table("daily").is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,143 @@
+//! Commlib: DataTable csv
+//! 第一行为字段名，其余为数据行；支持双引号包围的字段、"" 转义及字段内的逗号和换行
+
+use std::path::Path;
+
+use super::{get_table_files, DataSchema, DataTable};
+
+/// 解析 csv 内容为行列表
+pub(crate) fn parse_csv(content: &str) -> Result<Vec<Vec<String>>, String> {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content); // BOM
+
+    let mut rows = Vec::new();
+    let mut row: Vec<String> = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut line = 1_usize;
+
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                '\n' => {
+                    line += 1;
+                    field.push(c);
+                }
+                _ => field.push(c),
+            }
+            continue;
+        }
+
+        match c {
+            '"' if field.is_empty() => in_quotes = true,
+            ',' => row.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                line += 1;
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            _ => field.push(c),
+        }
+    }
+
+    if in_quotes {
+        return Err(format!("unterminated quoted field at line {}", line));
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    // 跳过空行
+    rows.retain(|row| !(row.len() == 1 && row[0].is_empty()));
+    Ok(rows)
+}
+
+/// 读取 csv 文件，表名为不含扩展名的文件名
+pub fn read_csv_table(path: impl AsRef<Path>) -> Result<DataTable, String> {
+    let path = path.as_ref();
+    let name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let content = std::fs::read_to_string(path)
+        .map_err(|err| format!("read csv file({:?}) error: {}.", path, err))?;
+    read_csv_string(&name, &content).map_err(|err| format!("csv file({:?}): {}", path, err))
+}
+
+/// 解析 csv 内容
+pub fn read_csv_string(name: &str, content: &str) -> Result<DataTable, String> {
+    let mut rows = parse_csv(content)?.into_iter();
+    let fields: Vec<String> = match rows.next() {
+        Some(header) => header.into_iter().map(|s| s.trim().to_owned()).collect(),
+        None => return Err("empty csv, header not found".to_owned()),
+    };
+    if fields.iter().any(|field| field.is_empty()) {
+        return Err("empty field name in header".to_owned());
+    }
+
+    let mut dt = DataTable::new(name.to_owned(), fields);
+    dt.set_data(rows.collect());
+    Ok(dt)
+}
+
+impl DataSchema {
+    /// 读取目录下所有 csv 文件，每个文件一张表
+    pub fn load_csv_dir(path: &str) -> Result<DataSchema, String> {
+        let dir = Path::new(path);
+        if !dir.is_dir() {
+            return Err(format!("csv dir({:?}) not found", dir));
+        }
+
+        let mut ds = DataSchema::new();
+        for file in get_table_files(dir, false, &["csv"]) {
+            let dt = read_csv_table(dir.join(file))?;
+            ds.tables.insert(dt.name.clone(), dt);
+        }
+        Ok(ds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_fields() {
+        let content = "\u{feff}id,name,desc\r\n1,warrior,\"big, strong\"\r\n2,\"mage\",\"say \"\"hi\"\"\nnext line\"\n\n3,,\n";
+        let dt = read_csv_string("role", content).unwrap();
+
+        assert_eq!(dt.fields, vec!["id", "name", "desc"]);
+        assert_eq!(dt.rows.len(), 3);
+        assert_eq!(dt.get(0, "desc"), "big, strong");
+        assert_eq!(dt.get(1, "name"), "mage");
+        assert_eq!(dt.get(1, "desc"), "say \"hi\"\nnext line");
+        assert_eq!(dt.get(2, "name"), "");
+        assert_eq!(dt.rows_by_pk.get("3"), Some(&2));
+
+        assert!(parse_csv("id\n\"1").is_err());
+        assert!(read_csv_string("role", "").is_err());
+    }
+
+    #[test]
+    fn load_dir() {
+        let dir = std::env::temp_dir().join(format!("data_schema_csv_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("role.csv"), "id,lv\n1,5\n").unwrap();
+        std::fs::write(dir.join("item.CSV"), "id,count\n7,\"1,000\"\n").unwrap();
+        std::fs::write(dir.join("role.xml"), "<role/>").unwrap();
+
+        let ds = DataSchema::load_csv_dir(dir.to_str().unwrap()).unwrap();
+        assert_eq!(ds.tables.len(), 2);
+        assert_eq!(ds.get_table("role").unwrap().get(0, "lv"), "5");
+        assert_eq!(ds.get_table("item").unwrap().get(0, "count"), "1,000");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
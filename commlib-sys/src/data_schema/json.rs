@@ -0,0 +1,458 @@
+//! Commlib: DataTable json
+//! DataTable 序列化为对象数组 [{"id":"1","name":"warrior"}, ...]，DataSchema 序列化为 {表名: DataTable}
+//! json 配置表文件: 对象数组，或 {"name": "...", "rows": [...]}
+
+use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+
+use super::{DataSchema, DataTable};
+
+impl DataTable {
+    /// 序列化为 json 对象数组
+    pub fn to_json_string(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+
+    /// 由 json 对象数组构造，字段顺序以首次出现为准
+    pub fn from_json_str(name: &str, json: &str) -> Result<DataTable, String> {
+        let mut dt: DataTable = serde_json::from_str(json)
+            .map_err(|err| format!("table({}) parse json failed: {}", name, err))?;
+        dt.name = name.to_owned();
+        Ok(dt)
+    }
+}
+
+/// json 配置表文件只取前 JSON_FIELD_SCAN_ROWS 行的字段，之后的行中新出现的字段忽略
+pub const JSON_FIELD_SCAN_ROWS: usize = 100;
+
+/// 读取 json 配置表文件，未指定 name 时表名为不含扩展名的文件名
+pub fn read_data_table_json(path: impl AsRef<Path>) -> Result<DataTable, String> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path)
+        .map_err(|err| format!("read json file({:?}) error: {}.", path, err))?;
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    read_data_table_json_str(&stem, &content, JSON_FIELD_SCAN_ROWS)
+        .map_err(|err| format!("json file({:?}): {}", path, err))
+}
+
+fn read_data_table_json_str(
+    default_name: &str,
+    content: &str,
+    scan_rows: usize,
+) -> Result<DataTable, String> {
+    let file: JsonTableFile = serde_json::from_str(content).map_err(|err| err.to_string())?;
+    let name = file.name.unwrap_or_else(|| default_name.to_owned());
+    let dt = rows_to_table(name, file.rows, scan_rows);
+    if dt.fields.is_empty() {
+        return Err("no field found".to_owned());
+    }
+    Ok(dt)
+}
+
+/// 由前 scan_rows 行的字段（按首次出现的顺序）构造 DataTable，缺少的字段补空
+fn rows_to_table(name: String, rows: Vec<JsonRowOwned>, scan_rows: usize) -> DataTable {
+    let mut fields: Vec<String> = Vec::new();
+    let mut field_index: HashMap<String, usize> = HashMap::new();
+    for row in rows.iter().take(scan_rows) {
+        for (field, _) in &row.0 {
+            if !field_index.contains_key(field) {
+                field_index.insert(field.clone(), fields.len());
+                fields.push(field.clone());
+            }
+        }
+    }
+
+    let mut ignored: HashSet<String> = HashSet::new();
+    let rows: Vec<Vec<String>> = rows
+        .into_iter()
+        .map(|row| {
+            let mut data = vec![String::new(); fields.len()];
+            for (field, value) in row.0 {
+                match field_index.get(&field) {
+                    Some(index) => data[*index] = value,
+                    None => {
+                        ignored.insert(field);
+                    }
+                }
+            }
+            data
+        })
+        .collect();
+    if !ignored.is_empty() {
+        log::warn!(
+            "table({}) fields not found in first {} rows are ignored: {:?}",
+            name,
+            scan_rows,
+            ignored
+        );
+    }
+
+    let mut dt = DataTable::new(name, fields);
+    if !dt.fields.is_empty() {
+        dt.set_data(rows);
+    }
+    dt
+}
+
+struct JsonRow<'a> {
+    fields: &'a [String],
+    row: &'a [String],
+}
+
+impl Serialize for JsonRow<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.fields.len()))?;
+        for (index, field) in self.fields.iter().enumerate() {
+            let value = self.row.get(index).map(|s| s.as_str()).unwrap_or("");
+            map.serialize_entry(field, value)?;
+        }
+        map.end()
+    }
+}
+
+impl Serialize for DataTable {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.rows.len()))?;
+        for row in &self.rows {
+            seq.serialize_element(&JsonRow {
+                fields: &self.fields,
+                row,
+            })?;
+        }
+        seq.end()
+    }
+}
+
+/// 单元格：接受字符串、数字和布尔值，统一保存为字符串；嵌套的对象和数组保存为紧凑的 json 字符串
+struct JsonCell(String);
+
+impl<'de> Deserialize<'de> for JsonCell {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct CellVisitor;
+
+        impl<'de> Visitor<'de> for CellVisitor {
+            type Value = JsonCell;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a string, number, bool, object or array")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<JsonCell, E> {
+                Ok(JsonCell(v.to_owned()))
+            }
+
+            fn visit_string<E: serde::de::Error>(self, v: String) -> Result<JsonCell, E> {
+                Ok(JsonCell(v))
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<JsonCell, E> {
+                Ok(JsonCell(v.to_string()))
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<JsonCell, E> {
+                Ok(JsonCell(v.to_string()))
+            }
+
+            fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<JsonCell, E> {
+                Ok(JsonCell(v.to_string()))
+            }
+
+            fn visit_bool<E: serde::de::Error>(self, v: bool) -> Result<JsonCell, E> {
+                Ok(JsonCell(v.to_string()))
+            }
+
+            fn visit_unit<E: serde::de::Error>(self) -> Result<JsonCell, E> {
+                Ok(JsonCell(String::new()))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, access: A) -> Result<JsonCell, A::Error> {
+                let value = serde_json::Value::deserialize(
+                    serde::de::value::SeqAccessDeserializer::new(access),
+                )?;
+                Ok(JsonCell(value.to_string()))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, access: A) -> Result<JsonCell, A::Error> {
+                let value = serde_json::Value::deserialize(
+                    serde::de::value::MapAccessDeserializer::new(access),
+                )?;
+                Ok(JsonCell(value.to_string()))
+            }
+        }
+
+        deserializer.deserialize_any(CellVisitor)
+    }
+}
+
+/// 单行：保持 key 的出现顺序
+struct JsonRowOwned(Vec<(String, String)>);
+
+impl<'de> Deserialize<'de> for JsonRowOwned {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct RowVisitor;
+
+        impl<'de> Visitor<'de> for RowVisitor {
+            type Value = JsonRowOwned;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a row object keyed by field names")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<JsonRowOwned, A::Error> {
+                let mut cells = Vec::with_capacity(access.size_hint().unwrap_or(0));
+                while let Some((field, cell)) = access.next_entry::<String, JsonCell>()? {
+                    cells.push((field, cell.0));
+                }
+                Ok(JsonRowOwned(cells))
+            }
+        }
+
+        deserializer.deserialize_map(RowVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for DataTable {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TableVisitor;
+
+        impl<'de> Visitor<'de> for TableVisitor {
+            type Value = DataTable;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an array of row objects")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut access: A) -> Result<DataTable, A::Error> {
+                let mut rows = Vec::new();
+                while let Some(row) = access.next_element::<JsonRowOwned>()? {
+                    rows.push(row);
+                }
+
+                // 后出现的字段在前面的行中补空
+                Ok(rows_to_table(String::new(), rows, usize::MAX))
+            }
+        }
+
+        deserializer.deserialize_seq(TableVisitor)
+    }
+}
+
+/// json 配置表文件
+struct JsonTableFile {
+    name: Option<String>,
+    rows: Vec<JsonRowOwned>,
+}
+
+impl<'de> Deserialize<'de> for JsonTableFile {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FileVisitor;
+
+        impl<'de> Visitor<'de> for FileVisitor {
+            type Value = JsonTableFile;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(r#"an array of row objects or {"name": "...", "rows": [...]}"#)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(
+                self,
+                mut access: A,
+            ) -> Result<JsonTableFile, A::Error> {
+                let mut rows = Vec::new();
+                while let Some(row) = access.next_element::<JsonRowOwned>()? {
+                    rows.push(row);
+                }
+                Ok(JsonTableFile { name: None, rows })
+            }
+
+            fn visit_map<A: MapAccess<'de>>(
+                self,
+                mut access: A,
+            ) -> Result<JsonTableFile, A::Error> {
+                use serde::de::Error;
+
+                let mut name = None;
+                let mut rows = None;
+                while let Some(key) = access.next_key::<String>()? {
+                    match key.as_str() {
+                        "name" => name = Some(access.next_value::<String>()?),
+                        "rows" => rows = Some(access.next_value::<Vec<JsonRowOwned>>()?),
+                        other => return Err(A::Error::unknown_field(other, &["name", "rows"])),
+                    }
+                }
+                match rows {
+                    Some(rows) => Ok(JsonTableFile { name, rows }),
+                    None => Err(A::Error::missing_field("rows")),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(FileVisitor)
+    }
+}
+
+impl Serialize for DataSchema {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut names: Vec<&String> = self.tables.keys().collect();
+        names.sort();
+
+        let mut map = serializer.serialize_map(Some(names.len()))?;
+        for name in names {
+            map.serialize_entry(name, &self.tables[name])?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for DataSchema {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let tables = HashMap::<String, DataTable>::deserialize(deserializer)?;
+        let mut ds = DataSchema::new();
+        for (name, mut dt) in tables {
+            dt.name = name.clone();
+            ds.tables.insert(name, dt);
+        }
+        Ok(ds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_round_trip() {
+        let json = r#"[{"id":"1","name":"warrior"},{"id":2,"name":"mage","lv":3}]"#;
+        let dt = DataTable::from_json_str("role", json).unwrap();
+        assert_eq!(dt.name, "role");
+        assert_eq!(dt.fields, vec!["id", "name", "lv"]);
+        assert_eq!(dt.get(1, "id"), "2");
+        assert_eq!(dt.get(0, "lv"), "");
+        assert_eq!(dt.rows_by_pk.get("2"), Some(&1));
+
+        let out = dt.to_json_string().unwrap();
+        assert_eq!(
+            out,
+            r#"[{"id":"1","name":"warrior","lv":""},{"id":"2","name":"mage","lv":"3"}]"#
+        );
+        assert_eq!(
+            DataTable::from_json_str("role", &out).unwrap().rows,
+            dt.rows
+        );
+
+        assert!(DataTable::from_json_str("role", r#"{"id":"1"}"#).is_err());
+        assert!(DataTable::from_json_str("role", "[]")
+            .unwrap()
+            .fields
+            .is_empty());
+    }
+
+    #[test]
+    fn schema_json() {
+        let mut ds = DataSchema::new();
+        ds.tables.insert(
+            "role".to_owned(),
+            DataTable::from_json_str("role", r#"[{"id":"1"}]"#).unwrap(),
+        );
+
+        let out = serde_json::to_string(&ds).unwrap();
+        assert_eq!(out, r#"{"role":[{"id":"1"}]}"#);
+
+        let ds2: DataSchema = serde_json::from_str(&out).unwrap();
+        assert_eq!(ds2.get_table("role").unwrap().name, "role");
+    }
+
+    #[test]
+    fn table_file() {
+        // 异构的行，嵌套对象/数组保存为 json 字符串
+        let json = r#"[
+            {"id": 1, "name": "warrior", "skills": [1, 2]},
+            {"id": 2, "lv": 3.5, "extra": {"a": 1}},
+            {"name": "mage", "id": 3, "hidden": true}
+        ]"#;
+        let dt = read_data_table_json_str("role", json, 2).unwrap();
+        assert_eq!(dt.name, "role");
+        assert_eq!(dt.fields, vec!["id", "name", "skills", "lv", "extra"]);
+        assert_eq!(dt.get(0, "skills"), "[1,2]");
+        assert_eq!(dt.get(1, "extra"), r#"{"a":1}"#);
+        assert_eq!(dt.get(1, "name"), "");
+        assert_eq!(dt.get(2, "name"), "mage");
+        assert_eq!(dt.get_value::<f64>(1, "lv"), Some(3.5));
+        assert_eq!(dt.get_value::<u32>(2, "id"), Some(3));
+        assert_eq!(dt.rows_by_pk.get("3"), Some(&2));
+
+        // 第 3 行才出现的字段被忽略
+        assert!(!dt.fields.contains(&"hidden".to_owned()));
+        assert_eq!(dt.rows[2].len(), dt.fields.len());
+
+        let dt = read_data_table_json_str(
+            "file_stem",
+            r#"{"rows": [{"id": "7"}], "name": "item"}"#,
+            JSON_FIELD_SCAN_ROWS,
+        )
+        .unwrap();
+        assert_eq!(dt.name, "item");
+        assert_eq!(dt.get(0, "id"), "7");
+    }
+
+    #[test]
+    fn not_a_table() {
+        for json in [
+            "42",
+            r#""text""#,
+            "[]",
+            "[1, 2]",
+            "[[1]]",
+            r#"{"id": 1}"#,
+            r#"{"name": "role"}"#,
+            r#"{"name": 1, "rows": []}"#,
+            r#"{"rows": {"id": 1}}"#,
+            "[{\"id\": 1}",
+        ] {
+            assert!(
+                read_data_table_json_str("t", json, JSON_FIELD_SCAN_ROWS).is_err(),
+                "{}",
+                json
+            );
+        }
+    }
+
+    #[test]
+    fn xml_dir_mixes_json() {
+        use crate::data_schema::{DataSchemaHandle, DataSchemaLoader};
+        use std::sync::Arc;
+
+        let dir = std::env::temp_dir().join(format!("data_schema_json_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("role.json"), r#"[{"id": 1, "lv": 5}]"#).unwrap();
+        std::fs::write(
+            dir.join("item.xml"),
+            r#"<item><data><cell name="id">7</cell></data></item>"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("bad.json"), r#"{"version": 1}"#).unwrap();
+
+        let handle = Arc::new(DataSchemaHandle::new());
+        let loader = DataSchemaLoader::from_xml_dir(&handle, &dir, false, Box::new(|_| {}));
+        let results = loader.read_tables();
+        assert_eq!(results.len(), 3);
+        let result = |name: &str| {
+            results
+                .iter()
+                .find(|(path, _)| path.file_name().unwrap() == name)
+                .map(|(_, dt)| dt.clone())
+                .unwrap()
+        };
+        assert!(result("bad.json").is_err());
+        assert_eq!(result("item.xml").unwrap().get(0, "id"), "7");
+        assert_eq!(result("role.json").unwrap().get(0, "lv"), "5");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -10,5 +10,9 @@ lazy_static::lazy_static! {
     pub static ref G_SERVICE_SIGNAL: Arc<crate::ServiceSignalRs> =  Arc::new(crate::ServiceSignalRs::new(SERVICE_ID_SIG));
     pub static ref G_SERVICE_NET: Arc<crate::ServiceNetRs> =  Arc::new(crate::ServiceNetRs::new(SERVICE_ID_NET));
 
+    // ServiceRs::run_every 共用的定时器线程
+    pub static ref G_PERIODIC_TIMER: crate::hash_wheel_timer::thread_timer::ClosureTimerWithThread =
+        crate::hash_wheel_timer::thread_timer::ClosureTimerWithThread::for_closures();
+
     pub static ref G_EXIT_CV: Arc<(Mutex<bool>, Condvar)> = Arc::new((Mutex::new(false), Condvar::new()));
 }
@@ -50,6 +50,7 @@
 
 use std::{hash::Hash, time::Duration};
 
+pub mod calendar;
 pub mod simulation;
 pub mod wheel_timer;
 pub mod wheels;
@@ -61,6 +62,9 @@ pub use self::timers::*;
 #[cfg(feature = "thread-timer")]
 pub mod thread_timer;
 
+#[cfg(feature = "async-timer")]
+pub mod async_timer;
+
 #[cfg(feature = "uuid-extras")]
 mod uuid_extras;
 #[cfg(feature = "uuid-extras")]
@@ -75,8 +79,43 @@ pub enum TimerError<EntryType> {
     Expired(EntryType),
 }
 
+/// The outstanding entries of a timer wheel, for checkpointing and restoring it
+///
+/// Obtained via [snapshot](wheels::cancellable::QuadWheelWithOverflow::snapshot) and
+/// turned back into a wheel with [restore](wheels::cancellable::QuadWheelWithOverflow::restore).
+/// With the `serde` feature it can be serialized.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimerSnapshot<EntryType> {
+    /// The position of the wheel in its cycle, in ticks
+    pub tick_offset: u64,
+    /// Every outstanding entry with the time remaining until it expires
+    pub entries: Vec<(EntryType, Duration)>,
+}
+
+/// Counters describing the activity of a timer wheel
+///
+/// Obtained via [stats](wheels::cancellable::QuadWheelWithOverflow::stats) or
+/// [snapshot_stats](wheels::cancellable::QuadWheelWithOverflow::snapshot_stats).
+/// It is a plain `Copy` value, so a snapshot can be freely sent to other threads for inspection.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TimerStats {
+    /// Number of successful insertions (including periodic reschedules)
+    pub inserts: u64,
+    /// Number of successful cancellations
+    pub cancels: u64,
+    /// Number of entries returned as expired
+    pub fires: u64,
+    /// Number of single ticks
+    pub ticks: u64,
+    /// Number of ticks skipped over via `skip`
+    pub skips: u64,
+    /// Number of entries moved from the overflow list into the wheel
+    pub overflows_promoted: u64,
+}
+
 /// A simple implementation of a timer entry that only stores its own unique id and the original delay
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct IdOnlyTimerEntry<I> {
     /// The unique identifier part of the entry
     pub id: I,
@@ -109,6 +148,273 @@ where
     }
 }
 
+/// A simple implementation of a periodic timer entry that only stores its id, the initial delay and the period
+#[derive(Debug, Clone)]
+pub struct IdOnlyPeriodicTimerEntry<I> {
+    /// The unique identifier part of the entry
+    pub id: I,
+    /// The delay until the first expiry
+    pub delay: Duration,
+    /// The delay between subsequent expiries
+    pub period: Duration,
+}
+impl<I> IdOnlyPeriodicTimerEntry<I> {
+    /// Create a new periodic timer entry from the id, the initial delay and the period
+    pub fn new(id: I, delay: Duration, period: Duration) -> Self {
+        IdOnlyPeriodicTimerEntry { id, delay, period }
+    }
+}
+impl<I> CancellableTimerEntry for IdOnlyPeriodicTimerEntry<I>
+where
+    I: Hash + Clone + Eq + std::fmt::Debug,
+{
+    type Id = I;
+
+    fn id(&self) -> &Self::Id {
+        &self.id
+    }
+
+    fn period(&self) -> Option<Duration> {
+        Some(self.period)
+    }
+}
+
+impl<I> TimerEntryWithDelay for IdOnlyPeriodicTimerEntry<I>
+where
+    I: Hash + Clone + Eq + std::fmt::Debug,
+{
+    fn delay(&self) -> Duration {
+        self.delay
+    }
+}
+
+/// A timer entry that carries a payload along with its id and delay
+///
+/// The payload comes back with the expired entry, so there is no need
+/// for a separate map from id to the work that should happen on expiry.
+#[derive(Debug, Clone)]
+pub struct PayloadTimerEntry<I, P> {
+    /// The unique identifier part of the entry
+    pub id: I,
+    /// The delay that this entry is to be schedulled with (i.e., expire after)
+    pub delay: Duration,
+    /// The data associated with the timeout
+    pub payload: P,
+}
+impl<I, P> PayloadTimerEntry<I, P> {
+    /// Create a new timer entry from the id, the delay after which it should expire and its payload
+    pub fn new(id: I, delay: Duration, payload: P) -> Self {
+        PayloadTimerEntry { id, delay, payload }
+    }
+
+    /// A reference to the payload
+    pub fn payload(&self) -> &P {
+        &self.payload
+    }
+
+    /// Produce an entry with the same id and delay, but with `f` applied to the payload
+    pub fn map_payload<Q, F>(self, f: F) -> PayloadTimerEntry<I, Q>
+    where
+        F: FnOnce(P) -> Q,
+    {
+        PayloadTimerEntry {
+            id: self.id,
+            delay: self.delay,
+            payload: f(self.payload),
+        }
+    }
+}
+impl<I, P> CancellableTimerEntry for PayloadTimerEntry<I, P>
+where
+    I: Hash + Clone + Eq + std::fmt::Debug,
+    P: std::fmt::Debug,
+{
+    type Id = I;
+
+    fn id(&self) -> &Self::Id {
+        &self.id
+    }
+}
+
+impl<I, P> TimerEntryWithDelay for PayloadTimerEntry<I, P>
+where
+    I: Hash + Clone + Eq + std::fmt::Debug,
+    P: std::fmt::Debug,
+{
+    fn delay(&self) -> Duration {
+        self.delay
+    }
+}
+
+/// A wrapper that delays the inner entry by an extra random amount of up to `jitter`
+///
+/// Use it to spread out large numbers of timers that would otherwise expire in the same tick.
+/// The extra delay is drawn once at construction with millisecond granularity,
+/// so [delay](TimerEntryWithDelay::delay) is stable across calls.
+#[derive(Debug, Clone)]
+pub struct JitteredTimerEntry<E> {
+    /// The wrapped entry
+    pub inner: E,
+    /// The maximum extra delay
+    pub jitter: Duration,
+    offset: Duration,
+}
+impl<E> JitteredTimerEntry<E> {
+    /// Wrap `inner` with a random extra delay of up to `jitter`
+    pub fn new(inner: E, jitter: Duration) -> Self {
+        Self::with_seed(inner, jitter, rand::random())
+    }
+
+    /// Like [new](JitteredTimerEntry::new), but draws the extra delay from a generator seeded with `seed`
+    pub fn with_seed(inner: E, jitter: Duration, seed: u64) -> Self {
+        use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+        let mut rng = SmallRng::seed_from_u64(seed);
+        let offset = Duration::from_millis(rng.gen_range(0..=jitter.as_millis() as u64));
+        JitteredTimerEntry {
+            inner,
+            jitter,
+            offset,
+        }
+    }
+
+    /// The extra delay that was drawn for this entry
+    pub fn offset(&self) -> Duration {
+        self.offset
+    }
+}
+impl<E> CancellableTimerEntry for JitteredTimerEntry<E>
+where
+    E: CancellableTimerEntry,
+{
+    type Id = E::Id;
+
+    fn id(&self) -> &Self::Id {
+        self.inner.id()
+    }
+
+    fn period(&self) -> Option<Duration> {
+        self.inner.period()
+    }
+}
+
+impl<E> TimerEntryWithDelay for JitteredTimerEntry<E>
+where
+    E: TimerEntryWithDelay,
+{
+    fn delay(&self) -> Duration {
+        self.inner.delay() + self.offset
+    }
+}
+
+impl<I> IdOnlyTimerEntry<I> {
+    /// Create a new timer entry that expires after `delay` plus a random extra delay of up to `jitter_max`
+    pub fn with_jitter(id: I, delay: Duration, jitter_max: Duration) -> JitteredTimerEntry<Self> {
+        JitteredTimerEntry::new(IdOnlyTimerEntry::new(id, delay), jitter_max)
+    }
+}
+
+/// An automatically generated timer id, unique within the process
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TimerId(u64);
+impl TimerId {
+    /// Generate a new, never before used id
+    pub fn next() -> Self {
+        static NEXT_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1);
+        TimerId(NEXT_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed))
+    }
+
+    /// The raw value of the id
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+enum ClosureTimerAction {
+    Once(parking_lot::Mutex<Option<Box<dyn FnOnce() + Send>>>),
+    Periodic(Duration, parking_lot::Mutex<Box<dyn FnMut() + Send>>),
+}
+
+/// A timer entry that carries its own closure, for fire-and-forget callbacks
+///
+/// The id is generated on construction. It can be inserted directly into a
+/// [cancellable wheel](wheels::cancellable::QuadWheelWithOverflow);
+/// call [run](ClosureTimerEntry::run) on every entry the wheel returns.
+/// Periodic entries are rescheduled by the wheel itself.
+pub struct ClosureTimerEntry {
+    id: TimerId,
+    delay: Duration,
+    action: ClosureTimerAction,
+}
+impl ClosureTimerEntry {
+    /// Create an entry that runs `action` once after `delay`
+    pub fn once<F>(delay: Duration, action: F) -> Self
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        ClosureTimerEntry {
+            id: TimerId::next(),
+            delay,
+            action: ClosureTimerAction::Once(parking_lot::Mutex::new(Some(Box::new(action)))),
+        }
+    }
+
+    /// Create an entry that runs `action` after `delay` and then every `period`
+    pub fn periodic<F>(delay: Duration, period: Duration, action: F) -> Self
+    where
+        F: FnMut() + Send + 'static,
+    {
+        ClosureTimerEntry {
+            id: TimerId::next(),
+            delay,
+            action: ClosureTimerAction::Periodic(period, parking_lot::Mutex::new(Box::new(action))),
+        }
+    }
+
+    /// Run the closure, a one-shot closure only runs the first time
+    pub fn run(&self) {
+        match &self.action {
+            ClosureTimerAction::Once(action) => {
+                if let Some(action) = action.lock().take() {
+                    action();
+                }
+            }
+            ClosureTimerAction::Periodic(_, action) => (action.lock())(),
+        }
+    }
+}
+impl std::fmt::Debug for ClosureTimerEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "ClosureTimerEntry(id={:?}, delay={:?}, period={:?}, action=<function>)",
+            self.id,
+            self.delay,
+            self.period()
+        )
+    }
+}
+impl CancellableTimerEntry for ClosureTimerEntry {
+    type Id = TimerId;
+
+    fn id(&self) -> &Self::Id {
+        &self.id
+    }
+
+    fn period(&self) -> Option<Duration> {
+        match &self.action {
+            ClosureTimerAction::Once(_) => None,
+            ClosureTimerAction::Periodic(period, _) => Some(*period),
+        }
+    }
+}
+
+impl TimerEntryWithDelay for ClosureTimerEntry {
+    fn delay(&self) -> Duration {
+        self.delay
+    }
+}
+
 /// A module with some convenince functions for writing timer tests
 #[cfg(test)]
 pub mod test_helpers {
@@ -0,0 +1,211 @@
+//! This module provides a `Future`-based timer for async services.
+//!
+//! Ticks are driven by a [tokio interval](tokio::time::interval) on a shared
+//! [cancellable wheel](crate::wheels::cancellable::QuadWheelWithOverflow), so no dedicated thread is needed.
+//! Fired ids are delivered through an unbounded channel, and can also be awaited individually
+//! via [TimerFuture](TimerFuture).
+//!
+//! # Example
+//! ```
+//! # use std::sync::{Arc, Mutex};
+//! # use std::time::Duration;
+//! use commlib::hash_wheel_timer::async_timer::*;
+//! use commlib::hash_wheel_timer::wheels::cancellable::QuadWheelWithOverflow;
+//!
+//! # tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap().block_on(async {
+//! let wheel = Arc::new(Mutex::new(QuadWheelWithOverflow::new()));
+//! let timer = AsyncTimerHandle::start(wheel, Duration::from_millis(1));
+//! let fut = timer
+//!     .timeout(1u64, Duration::from_millis(10))
+//!     .expect("Could not schedule timeout!");
+//! assert_eq!(fut.await, Some(1u64));
+//! # });
+//! ```
+
+use super::wheels::cancellable::QuadWheelWithOverflow;
+use super::{IdOnlyTimerEntry, TimerError};
+
+use std::{
+    convert::Infallible,
+    fmt::Debug,
+    future::Future,
+    hash::Hash,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tokio::sync::{mpsc, oneshot};
+
+/// The wheel type shared between the handle and the tick driver
+pub type SharedWheel<I> = Arc<Mutex<QuadWheelWithOverflow<IdOnlyTimerEntry<I>>>>;
+
+type Waiters<I> = Arc<Mutex<hashbrown::HashMap<I, oneshot::Sender<I>>>>;
+
+/// A future that resolves once the timeout with the given id fires
+///
+/// Resolves to `None` if the timeout was cancelled or the driver stopped.
+pub struct TimerFuture<I> {
+    rx: oneshot::Receiver<I>,
+}
+
+impl<I> Future for TimerFuture<I> {
+    type Output = Option<I>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.rx).poll(cx).map(|res| res.ok())
+    }
+}
+
+/// Handle to a wheel driven by a tokio task
+///
+/// Dropping the handle stops the driver task.
+pub struct AsyncTimerHandle<I>
+where
+    I: Hash + Clone + Eq + Debug + Send + Sync + 'static,
+{
+    wheel: SharedWheel<I>,
+    waiters: Waiters<I>,
+    fired_rx: Option<mpsc::UnboundedReceiver<I>>,
+    driver: tokio::task::JoinHandle<()>,
+}
+
+impl<I> AsyncTimerHandle<I>
+where
+    I: Hash + Clone + Eq + Debug + Send + Sync + 'static,
+{
+    /// Spawn the tick driver for `wheel` on the current tokio runtime
+    ///
+    /// The wheel is advanced by the real elapsed time every `tick_interval`.
+    pub fn start(wheel: SharedWheel<I>, tick_interval: Duration) -> Self {
+        let (fired_tx, fired_rx) = mpsc::unbounded_channel();
+        let waiters: Waiters<I> = Arc::new(Mutex::new(hashbrown::HashMap::new()));
+
+        let driver_wheel = wheel.clone();
+        let driver_waiters = waiters.clone();
+        let driver = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(tick_interval);
+            let start = Instant::now();
+            let mut last_ms = 0u128;
+            loop {
+                interval.tick().await;
+                let now_ms = start.elapsed().as_millis();
+                let ticks = (now_ms - last_ms) as u32;
+                last_ms = now_ms;
+                if ticks == 0 {
+                    continue;
+                }
+
+                let fired = driver_wheel.lock().unwrap().tick_n(ticks);
+                for e in fired {
+                    let id = e.id.clone();
+                    if let Some(tx) = driver_waiters.lock().unwrap().remove(&id) {
+                        tx.send(id.clone()).ok();
+                    }
+                    // nobody listening is fine
+                    fired_tx.send(id).ok();
+                }
+            }
+        });
+
+        AsyncTimerHandle {
+            wheel,
+            waiters,
+            fired_rx: Some(fired_rx),
+            driver,
+        }
+    }
+
+    /// Schedule a timeout with the given `id` to fire after `delay`
+    pub fn schedule(&self, id: I, delay: Duration) -> Result<(), TimerError<IdOnlyTimerEntry<I>>> {
+        self.wheel
+            .lock()
+            .unwrap()
+            .insert(IdOnlyTimerEntry::new(id, delay))
+    }
+
+    /// Schedule a timeout and return a future that resolves when it fires
+    pub fn timeout(
+        &self,
+        id: I,
+        delay: Duration,
+    ) -> Result<TimerFuture<I>, TimerError<IdOnlyTimerEntry<I>>> {
+        let (tx, rx) = oneshot::channel();
+        self.waiters.lock().unwrap().insert(id.clone(), tx);
+        match self.schedule(id.clone(), delay) {
+            Ok(()) => Ok(TimerFuture { rx }),
+            Err(err) => {
+                self.waiters.lock().unwrap().remove(&id);
+                Err(err)
+            }
+        }
+    }
+
+    /// Cancel the timeout with the given `id`
+    ///
+    /// A pending [TimerFuture](TimerFuture) for it resolves to `None`.
+    pub fn cancel(&self, id: &I) -> Result<(), TimerError<Infallible>> {
+        self.waiters.lock().unwrap().remove(id);
+        self.wheel.lock().unwrap().cancel(id)
+    }
+
+    /// Take the receiver of all fired ids
+    ///
+    /// Returns `None` if it has already been taken.
+    pub fn take_fired_receiver(&mut self) -> Option<mpsc::UnboundedReceiver<I>> {
+        self.fired_rx.take()
+    }
+}
+
+impl<I> Drop for AsyncTimerHandle<I>
+where
+    I: Hash + Clone + Eq + Debug + Send + Sync + 'static,
+{
+    fn drop(&mut self) {
+        self.driver.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_wheel() -> SharedWheel<u64> {
+        Arc::new(Mutex::new(QuadWheelWithOverflow::new()))
+    }
+
+    #[tokio::test]
+    async fn await_timeout() {
+        let timer = AsyncTimerHandle::start(new_wheel(), Duration::from_millis(1));
+        let fut = timer
+            .timeout(1, Duration::from_millis(10))
+            .expect("Could not schedule timeout!");
+        assert_eq!(fut.await, Some(1));
+    }
+
+    #[tokio::test]
+    async fn cancelled_timeout_resolves_none() {
+        let timer = AsyncTimerHandle::start(new_wheel(), Duration::from_millis(1));
+        let fut = timer
+            .timeout(1, Duration::from_millis(50))
+            .expect("Could not schedule timeout!");
+        timer.cancel(&1).expect("Could not cancel timeout!");
+        assert_eq!(fut.await, None);
+    }
+
+    #[tokio::test]
+    async fn fired_channel_in_order() {
+        let mut timer = AsyncTimerHandle::start(new_wheel(), Duration::from_millis(1));
+        let mut rx = timer.take_fired_receiver().unwrap();
+        for id in [30u64, 10, 20] {
+            timer
+                .schedule(id, Duration::from_millis(id))
+                .expect("Could not schedule timeout!");
+        }
+        let mut ids = vec![];
+        for _ in 0..3 {
+            ids.push(rx.recv().await.unwrap());
+        }
+        assert_eq!(ids, vec![10, 20, 30]);
+    }
+}
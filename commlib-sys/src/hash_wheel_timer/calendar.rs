@@ -0,0 +1,522 @@
+//! Calendar schedules ("every day at 05:00", "every Monday at 00:00") on top of the
+//! cancellable [QuadWheelWithOverflow](super::wheels::cancellable::QuadWheelWithOverflow).
+//!
+//! The wheel itself only knows relative delays. A [CalendarScheduler] computes the delay
+//! until the next firing from the wall clock, inserts it into the wheel and, every time an
+//! entry fires, recomputes the following firing from the wall clock again. So no drift is
+//! accumulated, and jumps of the server clock (e.g. an NTP correction) are handled as follows:
+//!
+//! - Clock moved backwards: an entry that the wheel returns before its wall clock target
+//!   is simply re-armed for the remaining time, its action is not run early.
+//! - Clock moved forwards: every entry whose target has been passed fires once,
+//!   missed repetitions are not replayed.
+//!
+//! # Example
+//! ```
+//! use commlib::hash_wheel_timer::calendar::*;
+//!
+//! let mut scheduler = CalendarScheduler::new(chrono::FixedOffset::east_opt(8 * 3600).unwrap());
+//! scheduler.schedule(1u64, CalendarSpec::daily(5, 0, 0), |id| {
+//!     println!("daily reset, id={}", id);
+//! });
+//! scheduler.update();
+//! ```
+
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, TimeZone, Utc, Weekday};
+use std::{
+    convert::Infallible,
+    fmt::Debug,
+    hash::Hash,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use super::wheels::{cancellable::QuadWheelWithOverflow, Skip};
+use super::{CancellableTimerEntry, TimerError};
+
+/// A source of wall clock time
+///
+/// Implement this to drive a [CalendarScheduler] from a mocked clock in tests.
+pub trait WallClock: Send + Sync {
+    /// The current wall clock time
+    fn now(&self) -> SystemTime;
+}
+
+/// The system wall clock
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemWallClock;
+
+impl WallClock for SystemWallClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// When a calendar entry fires
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CalendarSpec {
+    /// Every day at the given time of day
+    Daily {
+        /// Hour of the day, `0..24`
+        hour: u32,
+        /// Minute of the hour, `0..60`
+        minute: u32,
+        /// Second of the minute, `0..60`
+        second: u32,
+    },
+    /// Every week on `weekday` at the given time of day
+    Weekly {
+        /// The day of the week
+        weekday: Weekday,
+        /// Hour of the day, `0..24`
+        hour: u32,
+        /// Minute of the hour, `0..60`
+        minute: u32,
+        /// Second of the minute, `0..60`
+        second: u32,
+    },
+    /// Every `period`, at `anchor + n * period`
+    Interval {
+        /// A point in time the firings are aligned to
+        anchor: SystemTime,
+        /// The time between two firings, must not be zero
+        period: Duration,
+    },
+}
+
+impl CalendarSpec {
+    /// Every day at `hour:minute:second`
+    pub fn daily(hour: u32, minute: u32, second: u32) -> Self {
+        CalendarSpec::Daily {
+            hour,
+            minute,
+            second,
+        }
+    }
+
+    /// Every week on `weekday` at `hour:minute:second`
+    pub fn weekly(weekday: Weekday, hour: u32, minute: u32, second: u32) -> Self {
+        CalendarSpec::Weekly {
+            weekday,
+            hour,
+            minute,
+            second,
+        }
+    }
+
+    /// Every `period`, aligned to `anchor`
+    pub fn interval(anchor: SystemTime, period: Duration) -> Self {
+        CalendarSpec::Interval { anchor, period }
+    }
+
+    /// The first firing strictly after `now`, with times of day interpreted in `offset`
+    ///
+    /// Returns `None` if the spec is invalid (e.g. `hour >= 24` or a zero `period`).
+    pub fn next_after(&self, now: SystemTime, offset: FixedOffset) -> Option<SystemTime> {
+        match *self {
+            CalendarSpec::Daily {
+                hour,
+                minute,
+                second,
+            } => {
+                let today = local_date(now, offset);
+                let first = at_time(today, hour, minute, second, offset)?;
+                if first > now {
+                    Some(first)
+                } else {
+                    at_time(today.succ_opt()?, hour, minute, second, offset)
+                }
+            }
+            CalendarSpec::Weekly {
+                weekday,
+                hour,
+                minute,
+                second,
+            } => {
+                let today = local_date(now, offset);
+                let days_ahead = (7 + weekday.num_days_from_monday()
+                    - today.weekday().num_days_from_monday())
+                    % 7;
+                let day = today + chrono::Duration::days(days_ahead as i64);
+                let first = at_time(day, hour, minute, second, offset)?;
+                if first > now {
+                    Some(first)
+                } else {
+                    at_time(
+                        day + chrono::Duration::days(7),
+                        hour,
+                        minute,
+                        second,
+                        offset,
+                    )
+                }
+            }
+            CalendarSpec::Interval { anchor, period } => {
+                if period.is_zero() {
+                    return None;
+                }
+                match now.duration_since(anchor) {
+                    Ok(since) => {
+                        let n = since.as_nanos() / period.as_nanos() + 1;
+                        let nanos = u64::try_from(n * period.as_nanos()).ok()?;
+                        anchor.checked_add(Duration::from_nanos(nanos))
+                    }
+                    Err(_) => {
+                        // anchor is in the future: fire at the last `anchor - n * period` after now
+                        let until = anchor.duration_since(now).ok()?;
+                        let n = (until.as_nanos() - 1) / period.as_nanos();
+                        let nanos = u64::try_from(n * period.as_nanos()).ok()?;
+                        anchor.checked_sub(Duration::from_nanos(nanos))
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn local_date(t: SystemTime, offset: FixedOffset) -> NaiveDate {
+    DateTime::<Utc>::from(t).with_timezone(&offset).date_naive()
+}
+
+fn at_time(
+    day: NaiveDate,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    offset: FixedOffset,
+) -> Option<SystemTime> {
+    let local = day.and_hms_opt(hour, minute, second)?;
+    let t = offset.from_local_datetime(&local).single()?;
+    Some(SystemTime::from(t))
+}
+
+/// Action run every time a calendar entry fires
+pub type CalendarAction<I> = Box<dyn FnMut(I) + Send + Sync + 'static>;
+
+struct CalendarEntry<I> {
+    id: I,
+    spec: CalendarSpec,
+    target: SystemTime,
+    action: CalendarAction<I>,
+}
+
+impl<I> Debug for CalendarEntry<I>
+where
+    I: Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CalendarEntry")
+            .field("id", &self.id)
+            .field("spec", &self.spec)
+            .field("target", &self.target)
+            .finish()
+    }
+}
+
+impl<I> CancellableTimerEntry for CalendarEntry<I>
+where
+    I: Hash + Clone + Eq + Debug,
+{
+    type Id = I;
+
+    fn id(&self) -> &Self::Id {
+        &self.id
+    }
+}
+
+/// A timer for calendar schedules, re-arming every entry from the wall clock after each firing
+///
+/// Like the [WheelTimer](super::wheel_timer::WheelTimer) it doesn't run its own thread,
+/// call [update](CalendarScheduler::update) regularly to fire expired entries.
+pub struct CalendarScheduler<I>
+where
+    I: Hash + Clone + Eq + Debug + Send + Sync,
+{
+    offset: FixedOffset,
+    clock: Box<dyn WallClock>,
+    wheel: QuadWheelWithOverflow<CalendarEntry<I>>,
+    last_update: SystemTime,
+}
+
+impl<I> CalendarScheduler<I>
+where
+    I: Hash + Clone + Eq + Debug + Send + Sync,
+{
+    /// Create a scheduler on the system clock, with times of day interpreted in `offset`
+    pub fn new(offset: FixedOffset) -> Self {
+        Self::with_clock(offset, SystemWallClock)
+    }
+
+    /// Create a scheduler on a custom wall clock
+    pub fn with_clock<C>(offset: FixedOffset, clock: C) -> Self
+    where
+        C: WallClock + 'static,
+    {
+        let last_update = clock.now();
+        CalendarScheduler {
+            offset,
+            clock: Box::new(clock),
+            wheel: QuadWheelWithOverflow::new(),
+            last_update,
+        }
+    }
+
+    /// The time zone times of day are interpreted in
+    pub fn offset(&self) -> FixedOffset {
+        self.offset
+    }
+
+    /// Schedule `action` to run according to `spec` until it is cancelled
+    ///
+    /// An existing entry with the same `id` is replaced.
+    /// Returns the time of the first firing, or `None` if `spec` is invalid.
+    pub fn schedule<F>(&mut self, id: I, spec: CalendarSpec, action: F) -> Option<SystemTime>
+    where
+        F: FnMut(I) + Send + Sync + 'static,
+    {
+        let now = self.clock.now();
+        let target = spec.next_after(now, self.offset)?;
+        let entry = CalendarEntry {
+            id,
+            spec,
+            target,
+            action: Box::new(action),
+        };
+        self.arm(entry, now);
+        Some(target)
+    }
+
+    /// Cancel the entry with the given `id`
+    pub fn cancel(&mut self, id: &I) -> Result<(), TimerError<Infallible>> {
+        self.wheel.cancel(id)
+    }
+
+    /// Number of scheduled entries
+    pub fn pending_count(&self) -> usize {
+        self.wheel.pending_count()
+    }
+
+    /// `true` if no entries are scheduled
+    pub fn is_empty(&self) -> bool {
+        self.wheel.is_empty()
+    }
+
+    /// Time until the scheduler next needs to be updated, `None` if no entries are scheduled
+    pub fn next_timeout(&self) -> Option<Duration> {
+        match self.wheel.can_skip() {
+            Skip::Empty => None,
+            Skip::None => Some(Duration::from_millis(1)),
+            Skip::Millis(ms) => Some(Duration::from_millis(ms as u64 + 1)),
+        }
+    }
+
+    /// Advance to the current wall clock time, run the actions of all entries that are due
+    /// and re-arm them
+    ///
+    /// Returns the number of actions that were run.
+    pub fn update(&mut self) -> usize {
+        let now = self.clock.now();
+        let elapsed_ms = match now.duration_since(self.last_update) {
+            Ok(elapsed) => elapsed.as_millis() as u64,
+            Err(_) => {
+                // clock moved backwards: the wheel stands still, early entries are re-armed
+                self.last_update = now;
+                0
+            }
+        };
+        self.last_update += Duration::from_millis(elapsed_ms);
+
+        let mut expired = Vec::new();
+        let mut remaining = elapsed_ms;
+        while remaining > 0 && !self.wheel.is_empty() {
+            let n = std::cmp::min(remaining, u32::MAX as u64) as u32;
+            expired.extend(self.wheel.tick_n(n));
+            remaining -= n as u64;
+        }
+
+        let mut fired = 0;
+        for e in expired {
+            let mut entry = Arc::try_unwrap(e).expect("shouldn't hold on to these refs anywhere");
+            if now >= entry.target {
+                (entry.action)(entry.id.clone());
+                fired += 1;
+
+                // recompute from the wall clock rather than adding a period
+                match entry.spec.next_after(now, self.offset) {
+                    Some(target) => entry.target = target,
+                    None => continue,
+                }
+            }
+            self.arm(entry, now);
+        }
+        fired
+    }
+
+    fn arm(&mut self, entry: CalendarEntry<I>, now: SystemTime) {
+        // round up, so the wheel never returns an entry before its target
+        let delay = entry.target.duration_since(now).unwrap_or_default();
+        let delay_ms = std::cmp::max(1, (delay.as_nanos() + 999_999) / 1_000_000) as u64;
+        match self
+            .wheel
+            .insert_ref_with_delay(Arc::new(entry), Duration::from_millis(delay_ms))
+        {
+            Ok(_) => (), // ok
+            Err(f) => panic!("Could not insert calendar entry! {:?}", f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+
+    #[derive(Clone)]
+    struct MockClock(Arc<Mutex<SystemTime>>);
+
+    impl MockClock {
+        fn set(&self, t: SystemTime) {
+            *self.0.lock() = t;
+        }
+
+        fn advance(&self, d: Duration) {
+            *self.0.lock() += d;
+        }
+    }
+
+    impl WallClock for MockClock {
+        fn now(&self) -> SystemTime {
+            *self.0.lock()
+        }
+    }
+
+    fn utc8() -> FixedOffset {
+        FixedOffset::east_opt(8 * 3600).unwrap()
+    }
+
+    // local time in utc+8
+    fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32, s: u32) -> SystemTime {
+        SystemTime::from(utc8().with_ymd_and_hms(y, mo, d, h, mi, s).unwrap())
+    }
+
+    #[test]
+    fn next_after() {
+        // 2024-01-01 is a Monday
+        let now = at(2024, 1, 1, 4, 0, 0);
+        let daily = CalendarSpec::daily(5, 0, 0);
+        assert_eq!(daily.next_after(now, utc8()), Some(at(2024, 1, 1, 5, 0, 0)));
+        assert_eq!(
+            daily.next_after(at(2024, 1, 1, 5, 0, 0), utc8()),
+            Some(at(2024, 1, 2, 5, 0, 0))
+        );
+        assert_eq!(CalendarSpec::daily(24, 0, 0).next_after(now, utc8()), None);
+
+        let monday = CalendarSpec::weekly(Weekday::Mon, 0, 0, 0);
+        assert_eq!(
+            monday.next_after(now, utc8()),
+            Some(at(2024, 1, 8, 0, 0, 0))
+        );
+        let friday = CalendarSpec::weekly(Weekday::Fri, 12, 0, 0);
+        assert_eq!(
+            friday.next_after(now, utc8()),
+            Some(at(2024, 1, 5, 12, 0, 0))
+        );
+
+        let anchor = at(2024, 1, 1, 0, 0, 0);
+        let hourly = CalendarSpec::interval(anchor, Duration::from_secs(3600));
+        assert_eq!(
+            hourly.next_after(now, utc8()),
+            Some(at(2024, 1, 1, 5, 0, 0))
+        );
+        assert_eq!(
+            hourly.next_after(at(2024, 1, 1, 4, 30, 0), utc8()),
+            Some(at(2024, 1, 1, 5, 0, 0))
+        );
+        assert_eq!(
+            hourly.next_after(at(2023, 12, 31, 21, 30, 0), utc8()),
+            Some(at(2023, 12, 31, 22, 0, 0))
+        );
+        assert_eq!(
+            CalendarSpec::interval(anchor, Duration::ZERO).next_after(now, utc8()),
+            None
+        );
+    }
+
+    #[test]
+    fn daily_rearms_from_wall_clock() {
+        let clock = MockClock(Arc::new(Mutex::new(at(2024, 1, 1, 4, 0, 0))));
+        let mut scheduler = CalendarScheduler::with_clock(utc8(), clock.clone());
+
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let (f, c) = (fired.clone(), clock.clone());
+        let first = scheduler.schedule(1u64, CalendarSpec::daily(5, 0, 0), move |id| {
+            f.lock().push((id, c.now()));
+        });
+        assert_eq!(first, Some(at(2024, 1, 1, 5, 0, 0)));
+
+        clock.advance(Duration::from_secs(3599));
+        assert_eq!(scheduler.update(), 0);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(scheduler.update(), 1);
+        assert_eq!(*fired.lock(), vec![(1, at(2024, 1, 1, 5, 0, 0))]);
+
+        // NTP correction: back by one hour, the wheel fires early but the action waits
+        clock.set(at(2024, 1, 2, 4, 30, 0));
+        assert_eq!(scheduler.update(), 0);
+        clock.set(at(2024, 1, 2, 3, 30, 0));
+        assert_eq!(scheduler.update(), 0);
+        clock.set(at(2024, 1, 2, 4, 0, 0));
+        assert_eq!(scheduler.update(), 0);
+        assert_eq!(scheduler.pending_count(), 1);
+        clock.set(at(2024, 1, 2, 4, 59, 59));
+        assert_eq!(scheduler.update(), 0);
+        clock.set(at(2024, 1, 2, 5, 0, 0));
+        assert_eq!(scheduler.update(), 1);
+
+        // forward jump over several days fires only once
+        clock.set(at(2024, 1, 5, 6, 0, 0));
+        assert_eq!(scheduler.update(), 1);
+        assert_eq!(fired.lock().last(), Some(&(1, at(2024, 1, 5, 6, 0, 0))));
+        clock.set(at(2024, 1, 6, 5, 0, 0));
+        assert_eq!(scheduler.update(), 1);
+        assert_eq!(fired.lock().len(), 4);
+        assert_eq!(scheduler.pending_count(), 1);
+    }
+
+    #[test]
+    fn cancel_by_id() {
+        let clock = MockClock(Arc::new(Mutex::new(at(2024, 1, 1, 0, 0, 0))));
+        let mut scheduler = CalendarScheduler::with_clock(utc8(), clock.clone());
+
+        let count = Arc::new(Mutex::new(0));
+        let c = count.clone();
+        scheduler.schedule(
+            "weekly",
+            CalendarSpec::weekly(Weekday::Mon, 0, 0, 0),
+            move |_| *c.lock() += 1,
+        );
+        let c = count.clone();
+        scheduler.schedule(
+            "interval",
+            CalendarSpec::interval(at(2024, 1, 1, 0, 0, 0), Duration::from_secs(60)),
+            move |_| *c.lock() += 1,
+        );
+        assert!(scheduler
+            .schedule("bad", CalendarSpec::daily(0, 60, 0), |_| {})
+            .is_none());
+        assert_eq!(scheduler.pending_count(), 2);
+
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(scheduler.update(), 1);
+
+        scheduler.cancel(&"interval").unwrap();
+        assert!(scheduler.cancel(&"interval").is_err());
+        clock.advance(Duration::from_secs(600));
+        assert_eq!(scheduler.update(), 0);
+        assert_eq!(*count.lock(), 1);
+
+        clock.set(at(2024, 1, 8, 0, 0, 0));
+        assert_eq!(scheduler.update(), 1);
+        assert_eq!(*count.lock(), 2);
+    }
+}
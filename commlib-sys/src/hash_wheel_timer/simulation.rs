@@ -39,9 +39,16 @@
 //! let guard = barrier.lock().unwrap();
 //! assert_eq!(*guard, true);
 //! ```
+//!
+//! # Determinism
+//! Entries that expire in the same tick are fired in a deterministic order, so a simulation
+//! with the same inserts always produces the same execution trace. By default this is the order
+//! in which the entries were inserted (a rescheduled periodic entry counts as inserted when it is
+//! rescheduled). With [set_tiebreak](SimulationTimer::set_tiebreak) they can be ordered by id instead.
 use super::wheels::{cancellable::*, *};
 use super::*;
 use std::{
+    cmp::Ordering,
     fmt::Debug,
     hash::Hash,
     time::{Duration, SystemTime},
@@ -55,8 +62,15 @@ where
     O: OneshotState<Id = I> + Send + Sync,
     P: PeriodicState<Id = I> + Send + Sync,
 {
-    OneShot { state: O },
-    Periodic { period: Duration, state: P },
+    OneShot {
+        seq: u64,
+        state: O,
+    },
+    Periodic {
+        seq: u64,
+        period: Duration,
+        state: P,
+    },
 }
 
 impl<I, O, P> SimulationEntry<I, O, P>
@@ -65,15 +79,24 @@ where
     O: OneshotState<Id = I> + Debug + Send + Sync,
     P: PeriodicState<Id = I> + Debug + Send + Sync,
 {
-    fn execute(self) -> Option<(Self, Duration)> {
+    // the insertion sequence number, used to order entries expiring in the same tick
+    fn seq(&self) -> u64 {
+        match self {
+            SimulationEntry::OneShot { seq, .. } => *seq,
+            SimulationEntry::Periodic { seq, .. } => *seq,
+        }
+    }
+
+    fn execute(self, next_seq: u64) -> Option<(Self, Duration)> {
         match self {
-            SimulationEntry::OneShot { state } => {
+            SimulationEntry::OneShot { state, .. } => {
                 state.trigger();
                 None
             }
-            SimulationEntry::Periodic { period, state } => match state.trigger() {
+            SimulationEntry::Periodic { period, state, .. } => match state.trigger() {
                 TimerReturn::Reschedule(new_state) => {
                     let new_entry = SimulationEntry::Periodic {
+                        seq: next_seq,
                         period,
                         state: new_state,
                     };
@@ -86,10 +109,11 @@ where
 
     fn execute_unique_ref(
         unique_ref: std::sync::Arc<Self>,
+        next_seq: u64,
     ) -> Option<(std::sync::Arc<Self>, Duration)> {
         let unique = std::sync::Arc::try_unwrap(unique_ref)
             .expect("shouldn't hold on to these refs anywhere");
-        unique.execute().map(|t| {
+        unique.execute(next_seq).map(|t| {
             let (new_unique, delay) = t;
             (std::sync::Arc::new(new_unique), delay)
         })
@@ -112,6 +136,15 @@ where
     }
 }
 
+/// How entries that expire in the same tick are ordered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TieBreak {
+    /// In the order they were inserted (the default)
+    InsertionOrder,
+    /// By id, entries with equal ids in insertion order
+    ById,
+}
+
 /// A timer implementation that used virtual time
 ///
 /// Time is simply advanced until the next event is scheduled.
@@ -123,6 +156,8 @@ where
 {
     time: u128,
     timer: QuadWheelWithOverflow<SimulationEntry<I, O, P>>,
+    next_seq: u64,
+    id_order: Option<fn(&I, &I) -> Ordering>, // `Some` for TieBreak::ById
 }
 
 impl<I, O, P> SimulationTimer<I, O, P>
@@ -136,6 +171,8 @@ where
         SimulationTimer {
             time: 0u128,
             timer: QuadWheelWithOverflow::new(),
+            next_seq: 0,
+            id_order: None,
         }
     }
 
@@ -148,6 +185,8 @@ where
         SimulationTimer {
             time: tms,
             timer: QuadWheelWithOverflow::new(),
+            next_seq: 0,
+            id_order: None,
         }
     }
 
@@ -156,6 +195,15 @@ where
         self.time
     }
 
+    /// How entries that expire in the same tick are ordered
+    pub fn tiebreak(&self) -> TieBreak {
+        if self.id_order.is_some() {
+            TieBreak::ById
+        } else {
+            TieBreak::InsertionOrder
+        }
+    }
+
     /// Advance the virtual time
     #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> SimulationStep {
@@ -166,9 +214,7 @@ where
                     let res = self.timer.tick();
                     self.time += 1u128;
                     if !res.is_empty() {
-                        for e in res {
-                            self.trigger_entry(e);
-                        }
+                        self.trigger_expired(res);
                         return SimulationStep::Ok;
                     }
                 }
@@ -178,9 +224,7 @@ where
                     let res = self.timer.tick();
                     self.time += 1u128;
                     if !res.is_empty() {
-                        for e in res {
-                            self.trigger_entry(e);
-                        }
+                        self.trigger_expired(res);
                         return SimulationStep::Ok;
                     }
                 }
@@ -188,8 +232,27 @@ where
         }
     }
 
+    fn trigger_expired(&mut self, mut res: Vec<std::sync::Arc<SimulationEntry<I, O, P>>>) {
+        match self.id_order {
+            Some(id_order) => {
+                res.sort_by(|a, b| id_order(a.id(), b.id()).then(a.seq().cmp(&b.seq())))
+            }
+            None => res.sort_by_key(|e| e.seq()),
+        }
+        for e in res {
+            self.trigger_entry(e);
+        }
+    }
+
+    fn take_seq(&mut self) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        seq
+    }
+
     fn trigger_entry(&mut self, e: std::sync::Arc<SimulationEntry<I, O, P>>) {
-        if let Some((new_e, delay)) = SimulationEntry::execute_unique_ref(e) {
+        let next_seq = self.take_seq();
+        if let Some((new_e, delay)) = SimulationEntry::execute_unique_ref(e, next_seq) {
             match self.timer.insert_ref_with_delay(new_e, delay) {
                 Ok(_) => (), // ok
                 Err(TimerError::Expired(e)) => panic!(
@@ -202,6 +265,23 @@ where
     }
 }
 
+impl<I, O, P> SimulationTimer<I, O, P>
+where
+    I: Hash + Clone + Eq + Ord + Debug + Send + Sync,
+    O: OneshotState<Id = I> + Debug + Send + Sync,
+    P: PeriodicState<Id = I> + Debug + Send + Sync,
+{
+    /// Choose how entries that expire in the same tick are ordered
+    ///
+    /// Ordering by id requires the id type to implement [Ord].
+    pub fn set_tiebreak(&mut self, tiebreak: TieBreak) {
+        self.id_order = match tiebreak {
+            TieBreak::InsertionOrder => None,
+            TieBreak::ById => Some(<I as Ord>::cmp),
+        };
+    }
+}
+
 impl<I, O, P> Default for SimulationTimer<I, O, P>
 where
     I: Hash + Clone + Eq + Debug + Send + Sync,
@@ -256,14 +336,17 @@ where
     type PeriodicState = P;
 
     fn schedule_once(&mut self, timeout: Duration, state: Self::OneshotState) {
-        let e = SimulationEntry::OneShot { state };
+        let e = SimulationEntry::OneShot {
+            seq: self.take_seq(),
+            state,
+        };
         match self
             .timer
             .insert_ref_with_delay(std::sync::Arc::new(e), timeout)
         {
             Ok(_) => (), // ok
             Err(TimerError::Expired(e)) => {
-                if SimulationEntry::execute_unique_ref(e).is_none() {
+                if SimulationEntry::execute_unique_ref(e, 0).is_none() {
                     // do nothing
                 } else {
                     // clearly a OneShot
@@ -275,14 +358,19 @@ where
     }
 
     fn schedule_periodic(&mut self, delay: Duration, period: Duration, state: Self::PeriodicState) {
-        let e = SimulationEntry::Periodic { period, state };
+        let e = SimulationEntry::Periodic {
+            seq: self.take_seq(),
+            period,
+            state,
+        };
         match self
             .timer
             .insert_ref_with_delay(std::sync::Arc::new(e), delay)
         {
             Ok(_) => (), // ok
             Err(TimerError::Expired(e)) => {
-                if let Some((new_e, delay)) = SimulationEntry::execute_unique_ref(e) {
+                let next_seq = self.take_seq();
+                if let Some((new_e, delay)) = SimulationEntry::execute_unique_ref(e, next_seq) {
                     match self.timer.insert_ref_with_delay(new_e, delay) {
                         Ok(_) => (), // ok
                         Err(TimerError::Expired(e)) => panic!(
@@ -380,4 +468,51 @@ mod tests {
             assert!(*guard);
         }
     }
+
+    fn run_trace(tiebreak: TieBreak) -> Vec<u64> {
+        let trace = Arc::new(Mutex::new(Vec::new()));
+        let mut timer = SimulationTimer::for_closures();
+        timer.set_tiebreak(tiebreak);
+        assert_eq!(timer.tiebreak(), tiebreak);
+
+        // a: 300ms 后到期，需要从外层 wheel 降级到底层
+        let t = trace.clone();
+        timer.schedule_action_once(1000u64, Duration::from_millis(300), move |id| {
+            t.lock().push(id)
+        });
+        for i in 0..100u64 {
+            let t = trace.clone();
+            let id = (i * 37) % 100;
+            timer.schedule_action_once(id, Duration::from_millis(100), move |id| t.lock().push(id));
+        }
+        assert!(matches!(timer.next(), SimulationStep::Ok));
+        assert_eq!(timer.current_time(), 100);
+
+        // b: 在 100ms 时插入，与 a 在同一个 tick 到期
+        let t = trace.clone();
+        timer.schedule_action_once(999u64, Duration::from_millis(200), move |id| {
+            t.lock().push(id)
+        });
+        while let SimulationStep::Ok = timer.next() {}
+
+        drop(timer);
+        Arc::try_unwrap(trace).unwrap().into_inner()
+    }
+
+    #[test]
+    fn same_tick_order_is_deterministic() {
+        let first = run_trace(TieBreak::InsertionOrder);
+        assert_eq!(first, run_trace(TieBreak::InsertionOrder));
+
+        let mut expected: Vec<u64> = (0..100u64).map(|i| (i * 37) % 100).collect();
+        expected.extend([1000, 999]);
+        assert_eq!(first, expected);
+
+        let by_id = run_trace(TieBreak::ById);
+        assert_eq!(by_id, run_trace(TieBreak::ById));
+
+        let mut expected: Vec<u64> = (0..100u64).collect();
+        expected.extend([999, 1000]);
+        assert_eq!(by_id, expected);
+    }
 }
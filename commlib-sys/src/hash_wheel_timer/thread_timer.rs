@@ -57,9 +57,20 @@ where
 {
     Schedule(TimerEntry<I, O, P>),
     Cancel(I),
+    Pause,
+    Resume(ResumeMode),
     Stop,
 }
 
+/// How a paused timer treats the time that passed while it was paused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResumeMode {
+    /// Drop the paused time, all outstanding timeouts are pushed back by the pause duration
+    Skip,
+    /// Replay the missed ticks, firing every timeout that expired during the pause
+    CatchUp,
+}
+
 /// A reference to a thread timer
 ///
 /// This is used to schedule events on the timer from other threads.
@@ -110,6 +121,30 @@ where
     }
 }
 
+impl<I, O, P> TimerRef<I, O, P>
+where
+    I: Hash + Clone + Eq + Send + Sync,
+    O: OneshotState<Id = I> + Send + Sync,
+    P: PeriodicState<Id = I> + Send + Sync,
+{
+    /// Stop advancing the timer
+    ///
+    /// New timeouts can still be scheduled or cancelled while paused,
+    /// but none will fire until [resume](TimerRef::resume) is called.
+    pub fn pause(&self) {
+        self.work_queue
+            .send(TimerMsg::Pause)
+            .unwrap_or_else(|e| eprintln!("Could not send Pause msg: {:?}", e));
+    }
+
+    /// Continue advancing the timer, handling the paused time according to `mode`
+    pub fn resume(&self, mode: ResumeMode) {
+        self.work_queue
+            .send(TimerMsg::Resume(mode))
+            .unwrap_or_else(|e| eprintln!("Could not send Resume msg: {:?}", e));
+    }
+}
+
 /// A timer implementation that uses its own thread
 ///
 /// This struct acts as a main handle for the timer and its thread.
@@ -157,6 +192,20 @@ where
         }
     }
 
+    /// Stop advancing the timer, see [TimerRef::pause](TimerRef::pause)
+    pub fn pause(&self) {
+        self.work_queue
+            .send(TimerMsg::Pause)
+            .unwrap_or_else(|e| eprintln!("Could not send Pause msg: {:?}", e));
+    }
+
+    /// Continue advancing the timer, see [TimerRef::resume](TimerRef::resume)
+    pub fn resume(&self, mode: ResumeMode) {
+        self.work_queue
+            .send(TimerMsg::Resume(mode))
+            .unwrap_or_else(|e| eprintln!("Could not send Resume msg: {:?}", e));
+    }
+
     /// Shut this timer down
     ///
     /// In particular, this method waits for the timer's thread to be
@@ -204,6 +253,105 @@ impl
     }
 }
 
+/// A thread timer with generated [TimerId]s and plain closures
+pub type ClosureTimerWithThread =
+    TimerWithThread<TimerId, OneShotClosureState<TimerId>, PeriodicClosureState<TimerId>>;
+
+/// A reference to a [ClosureTimerWithThread]
+pub type ClosureTimerRef =
+    TimerRef<TimerId, OneShotClosureState<TimerId>, PeriodicClosureState<TimerId>>;
+
+impl ClosureTimerWithThread {
+    /// Shorthand for creating a timer instance using generated ids and closure state
+    pub fn for_closures() -> Self {
+        Self::new().expect("timer")
+    }
+
+    /// Run `action` once after `delay`, see [ClosureTimerRef::schedule_once]
+    pub fn schedule_once<F>(&self, delay: Duration, action: F) -> TimerId
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.timer_ref().schedule_once(delay, action)
+    }
+
+    /// Run `action` after `initial` and then every `period`, see [ClosureTimerRef::schedule_periodic]
+    pub fn schedule_periodic<F>(&self, initial: Duration, period: Duration, action: F) -> TimerId
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.timer_ref().schedule_periodic(initial, period, action)
+    }
+
+    /// Cancel the timeout with the given `id`, see [ClosureTimerRef::cancel_id]
+    pub fn cancel(&self, id: TimerId) {
+        self.timer_ref().cancel_id(id)
+    }
+}
+
+impl ClosureTimerRef {
+    /// Run `action` once after `delay`
+    ///
+    /// Returns the generated id, which can be used to cancel the timeout.
+    pub fn schedule_once<F>(&self, delay: Duration, action: F) -> TimerId
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let id = TimerId::next();
+        let action = parking_lot::Mutex::new(Some(action));
+        let state = OneShotClosureState::new(id, move |_| {
+            if let Some(action) = action.lock().take() {
+                action();
+            }
+        });
+        self.send_schedule(TimerEntry::OneShot {
+            timeout: delay,
+            state,
+        });
+        id
+    }
+
+    /// Run `action` after `initial` and then every `period` until it is cancelled
+    ///
+    /// Returns the generated id, which can be used to cancel the timeout.
+    pub fn schedule_periodic<F>(&self, initial: Duration, period: Duration, action: F) -> TimerId
+    where
+        F: FnMut() + Send + 'static,
+    {
+        let id = TimerId::next();
+        let action = parking_lot::Mutex::new(action);
+        let state = PeriodicClosureState::new(id, move |_| {
+            (action.lock())();
+            TimerReturn::Reschedule(())
+        });
+        self.send_schedule(TimerEntry::Periodic {
+            delay: initial,
+            period,
+            state,
+        });
+        id
+    }
+
+    /// Cancel the timeout with the given `id`
+    ///
+    /// The closure never runs once the timer thread has handled the cancellation,
+    /// which happens before any tick that follows it.
+    pub fn cancel_id(&self, id: TimerId) {
+        self.work_queue
+            .send(TimerMsg::Cancel(id))
+            .unwrap_or_else(|e| eprintln!("Could not send Cancel msg: {:?}", e));
+    }
+
+    fn send_schedule(
+        &self,
+        e: TimerEntry<TimerId, OneShotClosureState<TimerId>, PeriodicClosureState<TimerId>>,
+    ) {
+        self.work_queue
+            .send(TimerMsg::Schedule(e))
+            .unwrap_or_else(|e| eprintln!("Could not send Schedule msg: {:?}", e));
+    }
+}
+
 /// Errors that can occur when stopping the timer thread
 #[derive(Debug)]
 pub enum ThreadTimerError<I, O, P>
@@ -313,6 +461,7 @@ where
     timer: QuadWheelWithOverflow<ThreadTimerEntry<I, O, P>>,
     work_queue: channel::Receiver<TimerMsg<I, O, P>>,
     running: bool,
+    paused: bool,
     start: Instant,
     last_check: u128,
 }
@@ -328,6 +477,7 @@ where
             timer: QuadWheelWithOverflow::new(),
             work_queue,
             running: true,
+            paused: false,
             start: Instant::now(),
             last_check: 0u128,
         }
@@ -335,6 +485,17 @@ where
 
     fn run(mut self) {
         while self.running {
+            if self.paused {
+                // only handle messages, the wheel doesn't move
+                match self.work_queue.recv() {
+                    Ok(msg) => self.handle_msg(msg),
+                    Err(channel::RecvError) => {
+                        panic!("Timer work_queue unexpectedly shut down!")
+                    }
+                }
+                continue;
+            }
+
             let elap = self.elapsed();
             if elap > 0 {
                 for _ in 0..elap {
@@ -446,6 +607,24 @@ where
                     Err(f) => panic!("Could not insert timer entry! {:?}", f),
                 }
             }
+            TimerMsg::Pause => {
+                if !self.paused {
+                    // fire everything that is due up to now
+                    let elap = self.elapsed();
+                    self.catch_up(elap);
+                    self.paused = true;
+                }
+            }
+            TimerMsg::Resume(mode) => {
+                if self.paused {
+                    self.paused = false;
+                    let elap = self.elapsed();
+                    match mode {
+                        ResumeMode::Skip => (), // all timeouts are relative, so just forget the paused time
+                        ResumeMode::CatchUp => self.catch_up(elap),
+                    }
+                }
+            }
             TimerMsg::Cancel(ref id) => match self.timer.cancel(id) {
                 Ok(_) => (),                     // ok
                 Err(TimerError::NotFound) => (), // also ok, might have been triggered already
@@ -467,6 +646,25 @@ where
         } // otherwise: timer is not rescheduled
     }
 
+    // advance by `ticks`, skipping empty stretches
+    fn catch_up(&mut self, ticks: u128) {
+        let mut remaining = ticks;
+        while remaining > 0 {
+            match self.timer.can_skip() {
+                Skip::Empty => break,
+                Skip::Millis(ms) => {
+                    let n = std::cmp::min(ms as u128, remaining);
+                    self.timer.skip(n as u32);
+                    remaining -= n;
+                }
+                Skip::None => {
+                    self.tick();
+                    remaining -= 1;
+                }
+            }
+        }
+    }
+
     #[inline(always)]
     fn tick(&mut self) {
         let res = self.timer.tick();
@@ -570,4 +768,82 @@ mod tests {
             assert!(*guard);
         }
     }
+
+    #[test]
+    fn pause_and_catch_up() {
+        use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+        let timer_core = TimerWithThread::for_uuid_closures();
+        let mut timer = timer_core.timer_ref();
+        let fired = Arc::new(AtomicUsize::new(0));
+
+        timer_core.pause();
+        let fired2 = fired.clone();
+        timer.schedule_action_once(Uuid::new_v4(), Duration::from_millis(5), move |_| {
+            fired2.fetch_add(1, AtomicOrdering::SeqCst);
+        });
+        let cancelled_id = Uuid::new_v4();
+        let fired3 = fired.clone();
+        timer.schedule_action_once(cancelled_id, Duration::from_millis(5), move |_| {
+            fired3.fetch_add(1, AtomicOrdering::SeqCst);
+        });
+        timer.cancel(&cancelled_id);
+
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(fired.load(AtomicOrdering::SeqCst), 0);
+
+        timer_core.resume(ResumeMode::CatchUp);
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(fired.load(AtomicOrdering::SeqCst), 1);
+
+        timer_core
+            .shutdown()
+            .expect("Timer didn't shutdown properly!");
+    }
+
+    #[test]
+    fn closure_timer_cancel_half() {
+        use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+        let timer_core = ClosureTimerWithThread::for_closures();
+        let timer = timer_core.timer_ref();
+        let counter = Arc::new(AtomicUsize::new(0));
+
+        let mut ids = Vec::with_capacity(1000);
+        for i in 0..1000u64 {
+            let c = counter.clone();
+            ids.push(
+                timer.schedule_once(Duration::from_millis(100 + i % 50), move || {
+                    c.fetch_add(1, AtomicOrdering::SeqCst);
+                }),
+            );
+        }
+        for id in ids.iter().skip(1).step_by(2) {
+            timer.cancel_id(*id);
+        }
+
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let t = ticks.clone();
+        let periodic = timer_core.schedule_periodic(
+            Duration::from_millis(10),
+            Duration::from_millis(10),
+            move || {
+                t.fetch_add(1, AtomicOrdering::SeqCst);
+            },
+        );
+
+        thread::sleep(Duration::from_millis(300));
+        assert_eq!(counter.load(AtomicOrdering::SeqCst), 500);
+
+        timer_core.cancel(periodic);
+        thread::sleep(Duration::from_millis(20));
+        let fired = ticks.load(AtomicOrdering::SeqCst);
+        assert!(fired > 0);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(ticks.load(AtomicOrdering::SeqCst), fired);
+
+        timer_core
+            .shutdown()
+            .expect("Timer didn't shutdown properly!");
+    }
 }
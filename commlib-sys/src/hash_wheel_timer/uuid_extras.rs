@@ -1,4 +1,4 @@
-use super::IdOnlyTimerEntry;
+use super::{IdOnlyTimerEntry, PayloadTimerEntry};
 use std::time::Duration;
 use uuid::Uuid;
 
@@ -13,3 +13,15 @@ impl UuidOnlyTimerEntry {
         Self::new(Uuid::new_v4(), delay)
     }
 }
+
+/// A shorthand for payload timer entries that use [Uuid](uuid::Uuid) as their id type
+pub type UuidPayloadTimerEntry<P> = PayloadTimerEntry<Uuid, P>;
+
+impl<P> UuidPayloadTimerEntry<P> {
+    /// Produce an entry with a random [Uuid](uuid::Uuid), the given `delay` and `payload`
+    ///
+    /// Uses `Uuid::new_v4()` internally.
+    pub fn with_random_id(delay: Duration, payload: P) -> Self {
+        Self::new(Uuid::new_v4(), delay, payload)
+    }
+}
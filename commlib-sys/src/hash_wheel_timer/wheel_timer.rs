@@ -150,6 +150,18 @@ where
         self.time
     }
 
+    /// Time until the wheel next needs to be updated, `None` if no timers are scheduled
+    ///
+    /// This is a lower bound: when entries are still sitting in an outer wheel the returned
+    /// value is the next cascade point rather than the actual expiry.
+    pub fn next_timeout(&self) -> Option<Duration> {
+        match self.timer.can_skip() {
+            Skip::Empty => None,
+            Skip::None => Some(Duration::from_millis(1)),
+            Skip::Millis(ms) => Some(Duration::from_millis(ms as u64 + 1)),
+        }
+    }
+
     /// Update by ms
     pub fn update(&mut self, d: std::time::Duration) {
         let expired_vec = self.collect_expired(d);
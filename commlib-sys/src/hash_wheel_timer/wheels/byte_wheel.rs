@@ -79,11 +79,33 @@ impl<EntryType, RestType> ByteWheel<EntryType, RestType> {
         }
     }
 
+    /// Iterate over all entries together with the index of the slot they are in
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &WheelEntry<EntryType, RestType>)> {
+        self.slots.iter().enumerate().flat_map(|(index, slot)| {
+            slot.iter()
+                .flat_map(move |l| l.iter().map(move |we| (index as u8, we)))
+        })
+    }
+
     /// True if the number of entries is 0
     pub fn is_empty(&self) -> bool {
         self.count == 0
     }
 
+    /// Remove and return all entries in every slot, leaving the wheel empty
+    ///
+    /// The current slot index is left untouched.
+    pub fn drain(&mut self) -> WheelEntryList<EntryType, RestType> {
+        let mut res = Vec::with_capacity(self.count as usize);
+        for slot in self.slots.iter_mut() {
+            if let Some(mut l) = slot.take() {
+                res.append(&mut l);
+            }
+        }
+        self.count = 0;
+        res
+    }
+
     /// Move the wheel by one tick and return all entries in the current slot together with the index of the next slot
     pub fn tick(&mut self) -> (Option<WheelEntryList<EntryType, RestType>>, u8) {
         self.current = self.current.wrapping_add(1u8);
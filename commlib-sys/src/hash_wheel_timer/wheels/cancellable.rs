@@ -33,6 +33,21 @@
 //! assert_eq!(res.len(), 0);
 //! ```
 //!
+//! Entries can also carry a payload, which is handed back directly with the expired entry.
+//! ```
+//! # use std::time::Duration;
+//! use commlib::hash_wheel_timer::*;
+//! use commlib::hash_wheel_timer::wheels::cancellable::*;
+//!
+//! let mut timer = QuadWheelWithOverflow::new();
+//! timer
+//!     .insert(PayloadTimerEntry::new(1u64, Duration::from_millis(1), "respawn".to_string()))
+//!     .expect("Could not insert timer entry!");
+//! let res = timer.tick();
+//! assert_eq!(res.len(), 1);
+//! assert_eq!(res[0].payload(), "respawn");
+//! ```
+//!
 //! More advanced examples can be found in the sources for the [SimulationTimer](crate::simulation::SimulationTimer)
 //! and the [TimerWithThread](crate::thread_timer::TimerWithThread).
 
@@ -54,8 +69,24 @@ pub trait CancellableTimerEntry: Debug {
 
     /// Returns the unique id of the outstanding timeout
     fn id(&self) -> &Self::Id;
+
+    /// Returns the period after which the entry should fire again, if it is periodic
+    ///
+    /// Periodic entries are rescheduled automatically by [tick](QuadWheelWithOverflow::tick)
+    /// until they are cancelled. Periods shorter than one tick are rounded up to one tick.
+    fn period(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Returns `true` if the entry should be re-inserted by the wheel after it expires
+    fn is_periodic(&self) -> bool {
+        self.period().is_some()
+    }
 }
 
+/// The default length of a single tick, which is also the smallest period a periodic entry can be rescheduled with
+const MIN_PERIOD: Duration = Duration::from_millis(1);
+
 /// A pruner implementation for [Weak](std::sync::Weak) references
 ///
 /// Keeps values that can still be upgraded.
@@ -67,19 +98,32 @@ pub fn rc_prune<E>(e: &std::sync::Weak<E>) -> PruneDecision {
     }
 }
 
+// Wheel slots hold a weak reference plus the generation of the insertion that put it there,
+// so a stale slot left behind by `reschedule` can't fire an entry that has moved.
+type SlotRef<EntryType> = (std::sync::Weak<EntryType>, u64);
+
+fn slot_prune<E>(e: &SlotRef<E>) -> PruneDecision {
+    rc_prune(&e.0)
+}
+
 /// An implementation of four-level byte-sized wheel
 ///
 /// Any value scheduled so far off that it doesn't fit into the wheel
 /// is stored in an overflow `Vec` and added to the wheel, once time as advanced enough
 /// that it actually fits.
-/// In this design the maximum schedule duration for the wheel itself is [`u32::MAX`](std::u32::MAX) units (typically ms),
+/// In this design the maximum schedule duration for the wheel itself is 2^40 - 1 units (typically ms),
 /// everything else goes into the overflow `Vec`.
 pub struct QuadWheelWithOverflow<EntryType>
 where
     EntryType: CancellableTimerEntry + Send + Sync,
 {
-    wheel: BasicQuadWheelWithOverflow<std::sync::Weak<EntryType>>,
-    timers: hashbrown::HashMap<EntryType::Id, std::sync::Arc<EntryType>>,
+    wheel: BasicQuadWheelWithOverflow<SlotRef<EntryType>>,
+    // entry, generation of its current slot, absolute tick at which it expires
+    timers: hashbrown::HashMap<EntryType::Id, (std::sync::Arc<EntryType>, u64, u64)>,
+    next_generation: u64,
+    current_tick: u64,
+    stats: TimerStats,
+    resolution: Duration,
 }
 
 impl<EntryType> QuadWheelWithOverflow<EntryType>
@@ -106,6 +150,27 @@ where
         let delay = e.delay();
         self.insert_ref_with_delay(e, delay)
     }
+
+    /// Insert many timeouts at once
+    ///
+    /// Capacity for the lookup table is reserved up front.
+    /// Entries that could not be inserted are returned, in input order,
+    /// so the result is empty if all entries were scheduled.
+    pub fn insert_batch(
+        &mut self,
+        entries: impl IntoIterator<Item = EntryType>,
+    ) -> Vec<TimerError<EntryType>> {
+        let entries = entries.into_iter();
+        self.timers.reserve(entries.size_hint().0);
+
+        let mut errors = Vec::new();
+        for e in entries {
+            if let Err(err) = self.insert(e) {
+                errors.push(err);
+            }
+        }
+        errors
+    }
 }
 
 impl<EntryType> QuadWheelWithOverflow<EntryType>
@@ -114,9 +179,35 @@ where
 {
     /// Create a new wheel
     pub fn new() -> Self {
+        Self::with_resolution(MIN_PERIOD)
+    }
+
+    /// Create a new wheel where a single tick is `resolution` long
+    ///
+    /// Delays and periods are converted to ticks, rounding down.
+    pub(crate) fn with_resolution(resolution: Duration) -> Self {
         QuadWheelWithOverflow {
-            wheel: BasicQuadWheelWithOverflow::new(rc_prune::<EntryType>),
+            wheel: BasicQuadWheelWithOverflow::new(slot_prune::<EntryType>),
             timers: hashbrown::HashMap::new(),
+            next_generation: 0,
+            current_tick: 0,
+            stats: TimerStats::default(),
+            resolution,
+        }
+    }
+
+    // the basic wheel counts in ms, so express the number of ticks as ms
+    fn to_wheel_delay(&self, delay: Duration) -> Duration {
+        let ticks = delay.as_nanos() / self.resolution.as_nanos();
+        Duration::from_millis(ticks as u64)
+    }
+
+    fn from_wheel_delay(&self, wheel_delay: Duration) -> Duration {
+        if self.resolution == MIN_PERIOD {
+            wheel_delay
+        } else {
+            let nanos = wheel_delay.as_millis() * self.resolution.as_nanos();
+            Duration::from_nanos(nanos as u64)
         }
     }
 
@@ -127,10 +218,19 @@ where
         delay: Duration,
     ) -> Result<(), TimerError<std::sync::Arc<EntryType>>> {
         let weak_e = std::sync::Arc::downgrade(&e);
+        let generation = self.next_generation;
 
-        match self.wheel.insert_with_delay(weak_e, delay) {
+        let wheel_delay = self.to_wheel_delay(delay);
+        match self
+            .wheel
+            .insert_with_delay((weak_e, generation), wheel_delay)
+        {
             Ok(_) => {
-                self.timers.insert(e.id().clone(), e);
+                self.next_generation = generation.wrapping_add(1);
+                let target_tick = self.current_tick + wheel_delay.as_millis() as u64;
+                self.timers
+                    .insert(e.id().clone(), (e, generation, target_tick));
+                self.stats.inserts += 1;
                 Ok(())
             }
             Err(TimerError::Expired(_weak_e)) => Err(TimerError::Expired(e)),
@@ -147,39 +247,142 @@ where
         // Simply remove it from the lookup table
         // This will prevent the Weak pointer in the wheels from upgrading later
         match self.timers.remove_entry(id) {
-            Some(_) => Ok(()),
+            Some(_) => {
+                self.stats.cancels += 1;
+                Ok(())
+            }
             None => Err(TimerError::NotFound),
         }
     }
 
-    fn take_timer(
+    /// Cancel all outstanding timeouts for which `predicate` returns `true`
+    ///
+    /// Like [cancel](QuadWheelWithOverflow::cancel) this only touches the lookup table.
+    /// Returns the number of cancelled entries.
+    pub fn cancel_where<F>(&mut self, predicate: F) -> usize
+    where
+        F: Fn(&EntryType) -> bool,
+    {
+        let before = self.timers.len();
+        self.timers
+            .retain(|_id, (e, _generation, _target)| !predicate(e));
+        let cancelled = before - self.timers.len();
+        self.stats.cancels += cancelled as u64;
+        cancelled
+    }
+
+    /// Move the outstanding timeout with the given `id` so it expires `new_delay` ticks from now
+    ///
+    /// The entry keeps its id. The old slot is invalidated by the new insertion's generation,
+    /// and is dropped lazily once the wheel reaches it.
+    pub fn reschedule(
         &mut self,
-        weak_e: std::sync::Weak<EntryType>,
-    ) -> Option<std::sync::Arc<EntryType>> {
+        id: &EntryType::Id,
+        new_delay: Duration,
+    ) -> Result<(), TimerError<Infallible>> {
+        let e = match self.timers.remove(id) {
+            Some((e, _generation, _target)) => e,
+            None => return Err(TimerError::NotFound),
+        };
+        match self.insert_ref_with_delay(e, new_delay) {
+            Ok(()) => Ok(()),
+            Err(TimerError::Expired(e)) => {
+                // a zero delay fires on the next tick rather than being lost
+                let one_tick = self.resolution;
+                self.insert_ref_with_delay(e, one_tick)
+                    .map_err(|_| TimerError::NotFound)
+            }
+            Err(TimerError::NotFound) => Err(TimerError::NotFound),
+        }
+    }
+
+    fn take_timer(&mut self, slot: SlotRef<EntryType>) -> Option<std::sync::Arc<EntryType>> {
+        let (weak_e, generation) = slot;
         match weak_e.upgrade() {
             Some(rc_e) => {
-                match self.timers.remove_entry(rc_e.id()) {
-                    Some(rc_e2) => drop(rc_e2), // ok
-                    None => {
-                        // Perhaps it was removed via cancel(), and the underlying
-                        // Arc is still alive through some other reference
-                        return None;
-                    }
+                let is_current = matches!(
+                    self.timers.get(rc_e.id()),
+                    Some((_, current, _)) if *current == generation
+                );
+                if !is_current {
+                    // Perhaps it was removed via cancel(), or moved via reschedule(),
+                    // and the underlying Arc is still alive through some other reference
+                    return None;
                 }
+                self.timers.remove(rc_e.id());
                 Some(rc_e)
             }
             None => None,
         }
     }
 
+    fn reschedule_periodic(&mut self, e: std::sync::Arc<EntryType>, period: Duration) {
+        let period = if period < self.resolution {
+            self.resolution
+        } else {
+            period
+        };
+        match self.insert_ref_with_delay(e, period) {
+            Ok(()) => (),
+            Err(f) => unreachable!("Periodic entry could not be rescheduled: {:?}", f),
+        }
+    }
+
     /// Move the wheel forward by a single unit (ms)
     ///
     /// Returns a list of all timers that expire during this tick.
+    /// Periodic entries are rescheduled with their period before being returned.
     pub fn tick(&mut self) -> Vec<std::sync::Arc<EntryType>> {
-        let res = self.wheel.tick();
-        res.into_iter()
-            .flat_map(|weak_e| self.take_timer(weak_e))
-            .collect()
+        let mut expired: Vec<std::sync::Arc<EntryType>> = Vec::new();
+        self.tick_into(&mut expired);
+        expired
+    }
+
+    /// Move the wheel forward by `ticks` units (ms)
+    ///
+    /// Returns all timers that expire during these ticks, in the same order
+    /// individual [tick](QuadWheelWithOverflow::tick) calls would have produced them.
+    /// Empty stretches are skipped rather than iterated.
+    pub fn tick_n(&mut self, ticks: u32) -> Vec<std::sync::Arc<EntryType>> {
+        let mut expired: Vec<std::sync::Arc<EntryType>> = Vec::new();
+        let mut remaining = ticks;
+        while remaining > 0 {
+            match self.can_skip() {
+                Skip::Empty => {
+                    self.skip(remaining);
+                    break;
+                }
+                Skip::Millis(ms) => {
+                    let n = std::cmp::min(ms, remaining);
+                    self.skip(n);
+                    remaining -= n;
+                }
+                Skip::None => {
+                    self.tick_into(&mut expired);
+                    remaining -= 1;
+                }
+            }
+        }
+        expired
+    }
+
+    fn tick_into(&mut self, expired: &mut Vec<std::sync::Arc<EntryType>>) {
+        let start = expired.len();
+        self.current_tick += 1;
+        for slot in self.wheel.tick() {
+            if let Some(e) = self.take_timer(slot) {
+                expired.push(e);
+            }
+        }
+        self.stats.ticks += 1;
+        self.stats.fires += (expired.len() - start) as u64;
+        self.stats.overflows_promoted = self.wheel.overflows_promoted();
+        for e in expired[start..].iter() {
+            if e.is_periodic() {
+                let period = e.period().unwrap_or(self.resolution);
+                self.reschedule_periodic(e.clone(), period);
+            }
+        }
     }
 
     /// Skip a certain `amount` of units (ms)
@@ -189,12 +392,110 @@ where
     /// valid with [can_skip](QuadWheelWithOverflow::can_skip)!
     pub fn skip(&mut self, amount: u32) {
         self.wheel.skip(amount);
+        self.current_tick += amount as u64;
+        self.stats.skips += amount as u64;
     }
 
     /// Determine if and how many ticks can be skipped
     pub fn can_skip(&self) -> Skip {
         self.wheel.can_skip()
     }
+
+    /// Remove and return all outstanding entries without ticking
+    ///
+    /// The returned entries are in unspecified order and the wheel is empty afterwards.
+    pub fn drain(&mut self) -> Vec<std::sync::Arc<EntryType>> {
+        // drop the weak references in the slots first, they are only handles to `timers`
+        drop(self.wheel.drain());
+        self.timers.drain().map(|(_id, (e, _, _))| e).collect()
+    }
+
+    /// Capture all outstanding entries with the time remaining until they expire
+    ///
+    /// Cancelled entries and stale slots left behind by `reschedule` are not included.
+    pub fn snapshot(&self) -> TimerSnapshot<EntryType>
+    where
+        EntryType: Clone,
+    {
+        let mut entries = Vec::with_capacity(self.timers.len());
+        for ((weak_e, generation), wheel_delay) in self.wheel.remaining_delays() {
+            if let Some(e) = weak_e.upgrade() {
+                let is_current = matches!(
+                    self.timers.get(e.id()),
+                    Some((_, current, _)) if current == generation
+                );
+                if is_current {
+                    entries.push((EntryType::clone(&e), self.from_wheel_delay(wheel_delay)));
+                }
+            }
+        }
+        TimerSnapshot {
+            tick_offset: self.wheel.current_time_in_long_cycle(),
+            entries,
+        }
+    }
+
+    /// Build a new wheel from a [snapshot](QuadWheelWithOverflow::snapshot)
+    ///
+    /// The wheel is moved to the same position, and every entry is inserted with its remaining delay.
+    /// Entries with less than a tick remaining expire on the first tick.
+    pub fn restore(snapshot: TimerSnapshot<EntryType>) -> Self {
+        let mut wheel = Self::new();
+        wheel.current_tick = snapshot.tick_offset;
+        let mut offset = snapshot.tick_offset;
+        while offset > 0 {
+            let n = std::cmp::min(offset, u32::MAX as u64) as u32;
+            wheel.wheel.skip(n);
+            offset -= n as u64;
+        }
+        for (e, delay) in snapshot.entries {
+            let delay = std::cmp::max(delay, wheel.resolution);
+            if let Err(err) = wheel.insert_ref_with_delay(std::sync::Arc::new(e), delay) {
+                log::error!("restore timer entry failed: {:?}", err);
+            }
+        }
+        wheel
+    }
+
+    /// Activity counters of this wheel
+    pub fn stats(&self) -> &TimerStats {
+        &self.stats
+    }
+
+    /// An owned copy of the activity counters, e.g. for handing to another thread
+    pub fn snapshot_stats(&self) -> TimerStats {
+        self.stats
+    }
+
+    /// Reset all activity counters to zero
+    pub fn reset_stats(&mut self) {
+        self.stats = TimerStats::default();
+        self.wheel.reset_overflows_promoted();
+    }
+
+    /// Time left until the outstanding timeout with the given `id` expires
+    ///
+    /// Returns `None` if the id was never scheduled, has been cancelled or has already fired.
+    /// A periodic entry reports the time until its next expiry.
+    pub fn remaining(&self, id: &EntryType::Id) -> Option<Duration> {
+        self.timers.get(id).map(|(_e, _generation, target_tick)| {
+            let ticks = target_tick.saturating_sub(self.current_tick);
+            self.from_wheel_delay(Duration::from_millis(ticks))
+        })
+    }
+
+    /// The number of outstanding (not yet expired or cancelled) entries
+    pub fn pending_count(&self) -> usize {
+        self.timers.len()
+    }
+
+    /// `true` if there are no outstanding entries
+    ///
+    /// Unlike [can_skip](QuadWheelWithOverflow::can_skip) this does not wait for
+    /// cancelled entries to be pruned from the wheel slots.
+    pub fn is_empty(&self) -> bool {
+        self.timers.is_empty()
+    }
 }
 
 impl<EntryType> Default for QuadWheelWithOverflow<EntryType>
@@ -258,6 +559,22 @@ mod uuid_tests {
         assert_eq!(res.len(), 0);
     }
 
+    #[test]
+    fn payload_schedule() {
+        use crate::hash_wheel_timer::UuidPayloadTimerEntry;
+
+        let mut timer = QuadWheelWithOverflow::new();
+        let entry =
+            UuidPayloadTimerEntry::with_random_id(Duration::from_millis(2), "uuid".to_string());
+        let id = entry.id;
+        timer.insert(entry).expect("Could not insert timer entry!");
+        assert!(timer.tick().is_empty());
+        let res = timer.tick();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].id(), &id);
+        assert_eq!(res[0].payload(), "uuid");
+    }
+
     #[test]
     fn single_ms_reschedule() {
         let mut timer = QuadWheelWithOverflow::new();
@@ -279,6 +596,26 @@ mod uuid_tests {
         }
     }
 
+    #[test]
+    fn single_ms_periodic() {
+        let mut timer = QuadWheelWithOverflow::new();
+        let id = Uuid::new_v4();
+        let entry = IdOnlyPeriodicTimerEntry {
+            id,
+            delay: Duration::from_millis(1),
+            period: Duration::from_millis(1),
+        };
+
+        timer.insert(entry).expect("Could not insert timer entry!");
+        for _ in 0..1000 {
+            let res = timer.tick();
+            assert_eq!(res.len(), 1);
+            assert_eq!(res[0].id(), &id);
+        }
+        timer.cancel(&id).expect("Entry could not be cancelled!");
+        assert_eq!(timer.tick().len(), 0);
+    }
+
     #[test]
     fn increasing_schedule_no_overflow() {
         let mut timer = QuadWheelWithOverflow::new();
@@ -398,6 +735,45 @@ mod uuid_tests {
 mod u64_tests {
     use super::*;
 
+    #[test]
+    fn closure_entries() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let counter = std::sync::Arc::new(AtomicUsize::new(0));
+        let mut timer = QuadWheelWithOverflow::new();
+        let mut ids = Vec::new();
+        for i in 0..1000u64 {
+            let c = counter.clone();
+            let e = ClosureTimerEntry::once(Duration::from_millis(1 + i % 300), move || {
+                c.fetch_add(1, Ordering::SeqCst);
+            });
+            ids.push(*e.id());
+            timer.insert(e).expect("Could not insert timer entry!");
+        }
+        for id in ids.iter().step_by(2) {
+            timer.cancel(id).expect("Entry could not be cancelled!");
+        }
+
+        let c = counter.clone();
+        let periodic = ClosureTimerEntry::periodic(
+            Duration::from_millis(100),
+            Duration::from_millis(100),
+            move || {
+                c.fetch_add(1000, Ordering::SeqCst);
+            },
+        );
+        timer
+            .insert(periodic)
+            .expect("Could not insert timer entry!");
+
+        for _ in 0..350 {
+            for e in timer.tick() {
+                e.run();
+            }
+        }
+        assert_eq!(counter.load(Ordering::SeqCst), 500 + 3 * 1000);
+    }
+
     #[test]
     fn single_schedule_fail() {
         let mut timer = QuadWheelWithOverflow::new();
@@ -443,6 +819,32 @@ mod u64_tests {
         assert_eq!(res.len(), 0);
     }
 
+    #[test]
+    fn payload_schedule() {
+        let mut timer = QuadWheelWithOverflow::new();
+        for (id, ms) in [(1u64, 3u64), (2, 1), (3, 3), (4, 300)] {
+            let entry = PayloadTimerEntry::new(id, Duration::from_millis(ms), id)
+                .map_payload(|id| std::format!("payload-{}", id));
+            timer.insert(entry).expect("Could not insert timer entry!");
+        }
+        timer.cancel(&3).expect("Entry could not be cancelled!");
+
+        let mut fired = vec![];
+        for tick in 1..=300u64 {
+            for e in timer.tick() {
+                fired.push((tick, e.payload().clone()));
+            }
+        }
+        assert_eq!(
+            fired,
+            vec![
+                (1, "payload-2".to_string()),
+                (3, "payload-1".to_string()),
+                (300, "payload-4".to_string()),
+            ]
+        );
+    }
+
     #[test]
     fn cancel_and_drain() {
         let mut timer = QuadWheelWithOverflow::new();
@@ -613,4 +1015,455 @@ mod u64_tests {
         }
         assert_eq!(timer.can_skip(), Skip::Empty);
     }
+
+    #[test]
+    fn single_ms_periodic() {
+        let mut timer = QuadWheelWithOverflow::new();
+        let id = 1u64;
+        let entry = IdOnlyPeriodicTimerEntry {
+            id,
+            delay: Duration::from_millis(1),
+            period: Duration::from_millis(1),
+        };
+
+        timer.insert(entry).expect("Could not insert timer entry!");
+        for _ in 0..1000 {
+            let res = timer.tick();
+            assert_eq!(res.len(), 1);
+            assert_eq!(res[0].id(), &id);
+        }
+    }
+
+    #[test]
+    fn periodic_cancel_while_in_wheel() {
+        let mut timer = QuadWheelWithOverflow::new();
+        let id = 1u64;
+        timer
+            .insert(IdOnlyPeriodicTimerEntry {
+                id,
+                delay: Duration::from_millis(1),
+                period: Duration::from_millis(5),
+            })
+            .expect("Could not insert timer entry!");
+        assert_eq!(timer.tick().len(), 1);
+
+        // entry now sits in the wheel for its next firing
+        timer.cancel(&id).expect("Entry could not be cancelled!");
+        for _ in 0..20 {
+            assert_eq!(timer.tick().len(), 0);
+        }
+        assert_eq!(timer.can_skip(), Skip::Empty);
+    }
+
+    #[test]
+    fn periodic_sub_ms_rounds_up() {
+        let mut timer = QuadWheelWithOverflow::new();
+        let id = 1u64;
+        timer
+            .insert(IdOnlyPeriodicTimerEntry {
+                id,
+                delay: Duration::from_millis(1),
+                period: Duration::from_micros(100),
+            })
+            .expect("Could not insert timer entry!");
+        for _ in 0..10 {
+            assert_eq!(timer.tick().len(), 1);
+        }
+    }
+
+    #[test]
+    fn periodic_overflow() {
+        let mut timer = QuadWheelWithOverflow::new();
+        let id = 1u64;
+        let period: u64 = (u32::MAX as u64) + 10;
+        timer
+            .insert(IdOnlyPeriodicTimerEntry {
+                id,
+                delay: Duration::from_millis(1),
+                period: Duration::from_millis(period),
+            })
+            .expect("Could not insert timer entry!");
+        assert_eq!(timer.tick().len(), 1);
+
+        let mut millis = 1u64;
+        let mut fired = 0;
+        while fired < 2 {
+            match timer.can_skip() {
+                Skip::Empty => panic!("Timer ran empty at millis={}!", millis),
+                Skip::Millis(skip) => {
+                    timer.skip(skip);
+                    millis += skip as u64;
+                }
+                Skip::None => (),
+            }
+            let res = timer.tick();
+            millis += 1;
+            if !res.is_empty() {
+                fired += 1;
+                assert_eq!(millis, 1 + fired * period);
+            }
+        }
+    }
+
+    #[test]
+    fn periodic_fires_once_per_period() {
+        let mut timer = QuadWheelWithOverflow::new();
+        let id = 1u64;
+        timer
+            .insert(IdOnlyPeriodicTimerEntry::new(
+                id,
+                Duration::from_millis(1),
+                Duration::from_millis(3),
+            ))
+            .expect("Could not insert timer entry!");
+
+        let mut fired_at = vec![];
+        for millis in 1..=10 {
+            let res = timer.tick();
+            assert!(res.len() <= 1);
+            if !res.is_empty() {
+                assert!(res[0].is_periodic());
+                fired_at.push(millis);
+            }
+        }
+        assert_eq!(fired_at, vec![1, 4, 7, 10]);
+
+        // cancelling permanently suppresses re-insertion
+        timer.cancel(&id).expect("Entry could not be cancelled!");
+        for _ in 0..10 {
+            assert_eq!(timer.tick().len(), 0);
+        }
+        assert!(timer.cancel(&id).is_err());
+    }
+
+    #[test]
+    fn pending_count_tracks_live_entries() {
+        let mut timer = QuadWheelWithOverflow::new();
+        assert!(timer.is_empty());
+        for id in 1..=3u64 {
+            timer
+                .insert(IdOnlyTimerEntry::new(id, Duration::from_millis(id * 2)))
+                .expect("Could not insert timer entry!");
+        }
+        assert_eq!(timer.pending_count(), 3);
+
+        timer.cancel(&2).expect("Entry could not be cancelled!");
+        assert_eq!(timer.pending_count(), 2);
+        assert_ne!(timer.can_skip(), Skip::Empty);
+
+        timer.tick();
+        timer.tick();
+        assert_eq!(timer.pending_count(), 1);
+        for _ in 0..4 {
+            timer.tick();
+        }
+        assert!(timer.is_empty());
+    }
+
+    #[test]
+    fn tick_n_preserves_order() {
+        let mut timer = QuadWheelWithOverflow::new();
+        for id in [7u64, 1, 3] {
+            timer
+                .insert(IdOnlyTimerEntry::new(id, Duration::from_millis(id)))
+                .expect("Could not insert timer entry!");
+        }
+        let res = timer.tick_n(10);
+        let ids: Vec<u64> = res.iter().map(|e| *e.id()).collect();
+        assert_eq!(ids, vec![1, 3, 7]);
+        assert!(timer.is_empty());
+    }
+
+    #[test]
+    fn tick_n_periodic() {
+        let mut timer = QuadWheelWithOverflow::new();
+        timer
+            .insert(IdOnlyPeriodicTimerEntry::new(
+                1u64,
+                Duration::from_millis(2),
+                Duration::from_millis(2),
+            ))
+            .expect("Could not insert timer entry!");
+        assert_eq!(timer.tick_n(10).len(), 5);
+        assert_eq!(timer.pending_count(), 1);
+    }
+
+    #[test]
+    fn drain_returns_live_entries() {
+        let mut timer = QuadWheelWithOverflow::new();
+        for id in 1..=4u64 {
+            timer
+                .insert(IdOnlyTimerEntry::new(id, Duration::from_millis(id << 10)))
+                .expect("Could not insert timer entry!");
+        }
+        timer.cancel(&3).expect("Entry could not be cancelled!");
+
+        let mut ids: Vec<u64> = timer.drain().iter().map(|e| *e.id()).collect();
+        ids.sort();
+        assert_eq!(ids, vec![1, 2, 4]);
+        assert!(timer.is_empty());
+        assert_eq!(timer.can_skip(), Skip::Empty);
+    }
+
+    #[test]
+    fn reschedule_moves_deadline() {
+        let mut timer = QuadWheelWithOverflow::new();
+        let id = 1u64;
+        timer
+            .insert(IdOnlyTimerEntry::new(id, Duration::from_millis(3)))
+            .expect("Could not insert timer entry!");
+        timer.tick();
+        timer
+            .reschedule(&id, Duration::from_millis(5))
+            .expect("Entry could not be rescheduled!");
+        assert_eq!(timer.pending_count(), 1);
+
+        let mut fired_at = vec![];
+        for millis in 2..=10 {
+            let res = timer.tick();
+            for e in res {
+                assert_eq!(e.id(), &id);
+                fired_at.push(millis);
+            }
+        }
+        assert_eq!(fired_at, vec![6]);
+        assert!(timer.reschedule(&id, Duration::from_millis(1)).is_err());
+    }
+
+    #[test]
+    fn reschedule_shared_periodic() {
+        let mut timer = QuadWheelWithOverflow::new();
+        let id = 1u64;
+        timer
+            .insert(IdOnlyPeriodicTimerEntry::new(
+                id,
+                Duration::from_millis(1),
+                Duration::from_millis(2),
+            ))
+            .expect("Could not insert timer entry!");
+
+        // keep the returned Arc alive, so the stale slot can still upgrade
+        let held = timer.tick();
+        assert_eq!(held.len(), 1);
+        timer
+            .reschedule(&id, Duration::from_millis(5))
+            .expect("Entry could not be rescheduled!");
+
+        let mut fired_at = vec![];
+        for millis in 2..=6 {
+            if !timer.tick().is_empty() {
+                fired_at.push(millis);
+            }
+        }
+        assert_eq!(fired_at, vec![6]);
+    }
+
+    #[test]
+    fn stats_counters() {
+        let mut timer = QuadWheelWithOverflow::new();
+        for id in 1..=3u64 {
+            timer
+                .insert(IdOnlyTimerEntry::new(id, Duration::from_millis(id)))
+                .expect("Could not insert timer entry!");
+        }
+        timer.cancel(&2).expect("Entry could not be cancelled!");
+        for _ in 0..3 {
+            timer.tick();
+        }
+        timer.skip(10);
+
+        let stats = timer.snapshot_stats();
+        assert_eq!(stats.inserts, 3);
+        assert_eq!(stats.cancels, 1);
+        assert_eq!(stats.fires, 2);
+        assert_eq!(stats.ticks, 3);
+        assert_eq!(stats.skips, 10);
+        assert_eq!(stats.overflows_promoted, 0);
+
+        timer.reset_stats();
+        assert_eq!(timer.stats(), &TimerStats::default());
+    }
+
+    #[test]
+    fn stats_overflow_promoted() {
+        let mut timer = QuadWheelWithOverflow::new();
+        timer
+            .insert(IdOnlyTimerEntry::new(1u64, Duration::from_millis(1 << 41)))
+            .expect("Could not insert timer entry!");
+        while timer.stats().fires == 0 {
+            match timer.can_skip() {
+                Skip::Millis(ms) => timer.skip(ms),
+                Skip::None => (),
+                Skip::Empty => panic!("Timer ran empty!"),
+            }
+            timer.tick();
+        }
+        assert!(timer.stats().overflows_promoted >= 1);
+    }
+
+    #[test]
+    fn insert_batch_collects_failures() {
+        let mut timer = QuadWheelWithOverflow::new();
+        let entries = (0..10u64).map(|id| IdOnlyTimerEntry {
+            id,
+            delay: Duration::from_millis(id % 5),
+        });
+        let errors = timer.insert_batch(entries);
+        let failed: Vec<u64> = errors
+            .iter()
+            .map(|err| match err {
+                TimerError::Expired(e) => e.id,
+                TimerError::NotFound => panic!("Unexpected error {:?}", err),
+            })
+            .collect();
+        assert_eq!(failed, vec![0, 5]);
+        assert_eq!(timer.pending_count(), 8);
+        assert_eq!(timer.tick_n(4).len(), 8);
+    }
+    #[test]
+    fn cancel_where_by_owner() {
+        let mut timer = QuadWheelWithOverflow::new();
+        for id in 0..10u64 {
+            timer
+                .insert(IdOnlyTimerEntry {
+                    id,
+                    delay: Duration::from_millis(5),
+                })
+                .expect("Could not insert timer entry!");
+        }
+        assert_eq!(timer.cancel_where(|e| e.id % 2 == 0), 5);
+        assert_eq!(timer.cancel_where(|e| e.id % 2 == 0), 0);
+        assert_eq!(timer.stats().cancels, 5);
+
+        let mut fired: Vec<u64> = timer.tick_n(5).iter().map(|e| e.id).collect();
+        fired.sort();
+        assert_eq!(fired, vec![1, 3, 5, 7, 9]);
+    }
+    #[test]
+    fn snapshot_restore() {
+        let mut timer = QuadWheelWithOverflow::new();
+        timer.skip(1000);
+        let delays = [3u64, 300, 70_000, 20_000_000, (1 << 33) + 5, (1 << 41) + 9];
+        for (id, ms) in delays.iter().enumerate() {
+            timer
+                .insert(IdOnlyTimerEntry::new(id as u64, Duration::from_millis(*ms)))
+                .expect("Could not insert timer entry!");
+        }
+        timer
+            .insert(IdOnlyTimerEntry::new(99, Duration::from_millis(10)))
+            .expect("Could not insert timer entry!");
+        timer.cancel(&99).expect("Entry could not be cancelled!");
+        timer.tick();
+
+        let snapshot = timer.snapshot();
+        assert_eq!(snapshot.tick_offset, 1001);
+        let mut remaining: Vec<(u64, Duration)> =
+            snapshot.entries.iter().map(|(e, d)| (e.id, *d)).collect();
+        remaining.sort();
+        let expected: Vec<(u64, Duration)> = delays
+            .iter()
+            .enumerate()
+            .map(|(id, ms)| (id as u64, Duration::from_millis(ms - 1)))
+            .collect();
+        assert_eq!(remaining, expected);
+
+        let mut restored = QuadWheelWithOverflow::restore(snapshot);
+        assert_eq!(restored.pending_count(), delays.len());
+        let fired: Vec<u64> = restored.tick_n(2).iter().map(|e| e.id).collect();
+        assert_eq!(fired, vec![0]);
+    }
+    #[test]
+    fn jittered_entries_spread_out() {
+        let delay = Duration::from_millis(30);
+        let jitter = Duration::from_millis(20);
+
+        let a = JitteredTimerEntry::with_seed(IdOnlyTimerEntry::new(1u64, delay), jitter, 7);
+        let b = JitteredTimerEntry::with_seed(IdOnlyTimerEntry::new(1u64, delay), jitter, 7);
+        assert_eq!(a.delay(), b.delay());
+        assert_eq!(a.delay(), a.delay());
+
+        let mut timer = QuadWheelWithOverflow::new();
+        for id in 0..100u64 {
+            let e = JitteredTimerEntry::with_seed(IdOnlyTimerEntry::new(id, delay), jitter, id);
+            assert!(e.delay() >= delay && e.delay() <= delay + jitter);
+            timer.insert(e).expect("Could not insert timer entry!");
+        }
+        assert!(timer.tick_n(29).is_empty());
+        let mut fire_ticks = std::collections::BTreeSet::new();
+        for tick in 30..=50u64 {
+            if !timer.tick().is_empty() {
+                fire_ticks.insert(tick);
+            }
+        }
+        assert!(timer.is_empty());
+        assert!(fire_ticks.len() > 1);
+
+        let e = IdOnlyTimerEntry::with_jitter(1u64, delay, Duration::ZERO);
+        assert_eq!(e.delay(), delay);
+    }
+    #[test]
+    fn remaining_time() {
+        let mut timer = QuadWheelWithOverflow::new();
+        timer
+            .insert(IdOnlyTimerEntry::new(1u64, Duration::from_millis(500)))
+            .expect("Could not insert timer entry!");
+        assert_eq!(timer.remaining(&1), Some(Duration::from_millis(500)));
+        assert_eq!(timer.remaining(&2), None);
+
+        for _ in 0..200 {
+            assert!(timer.tick().is_empty());
+        }
+        assert_eq!(timer.remaining(&1), Some(Duration::from_millis(300)));
+
+        // inserted later, counted from the current position
+        timer
+            .insert(IdOnlyTimerEntry::new(2u64, Duration::from_millis(500)))
+            .expect("Could not insert timer entry!");
+        assert_eq!(timer.remaining(&2), Some(Duration::from_millis(500)));
+
+        let skipped = match timer.can_skip() {
+            Skip::Millis(ms) => ms as u64,
+            res => panic!("Unexpected skip {:?}", res),
+        };
+        assert!(skipped > 0);
+        timer.skip(skipped as u32);
+        assert_eq!(
+            timer.remaining(&1),
+            Some(Duration::from_millis(300 - skipped))
+        );
+        assert_eq!(
+            timer.remaining(&2),
+            Some(Duration::from_millis(500 - skipped))
+        );
+
+        timer.cancel(&2).expect("Entry could not be cancelled!");
+        assert_eq!(timer.remaining(&2), None);
+
+        assert!(timer.tick_n(299 - skipped as u32).is_empty());
+        assert_eq!(timer.remaining(&1), Some(Duration::from_millis(1)));
+        assert_eq!(timer.tick().len(), 1);
+        assert_eq!(timer.remaining(&1), None);
+    }
+    #[test]
+    fn remaining_time_periodic_and_rescheduled() {
+        let mut timer = QuadWheelWithOverflow::new();
+        timer
+            .insert(IdOnlyPeriodicTimerEntry {
+                id: 1u64,
+                delay: Duration::from_millis(10),
+                period: Duration::from_millis(50),
+            })
+            .expect("Could not insert timer entry!");
+
+        // after firing, a periodic entry reports the time to its next expiry
+        assert_eq!(timer.tick_n(10).len(), 1);
+        assert_eq!(timer.remaining(&1), Some(Duration::from_millis(50)));
+        assert_eq!(timer.tick_n(20).len(), 0);
+        assert_eq!(timer.remaining(&1), Some(Duration::from_millis(30)));
+
+        timer
+            .reschedule(&1, Duration::from_millis(5))
+            .expect("Entry could not be rescheduled!");
+        assert_eq!(timer.remaining(&1), Some(Duration::from_millis(5)));
+    }
 }
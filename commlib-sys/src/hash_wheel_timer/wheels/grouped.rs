@@ -0,0 +1,222 @@
+//! A [cancellable wheel](crate::wheels::cancellable) that can associate entries with a group,
+//! so that all timers of e.g. a player or a room can be cancelled in one call.
+//!
+//! # Examples
+//! ```
+//! # use std::time::Duration;
+//! use commlib::hash_wheel_timer::*;
+//! use commlib::hash_wheel_timer::wheels::grouped::*;
+//!
+//! let mut timer = GroupedCancellableWheel::new();
+//! let room = 7u32;
+//! for id in 1..=3u64 {
+//!     timer
+//!         .insert_in_group(IdOnlyTimerEntry::new(id, Duration::from_millis(5)), room)
+//!         .expect("Could not insert timer entry!");
+//! }
+//! assert_eq!(timer.cancel_group(&room), 3);
+//! assert!(timer.tick_n(5).is_empty());
+//! ```
+
+use super::wheels::cancellable::{CancellableTimerEntry, QuadWheelWithOverflow};
+use super::*;
+
+/// A cancellable wheel with a side-index from group ids to the ids of their entries
+pub struct GroupedCancellableWheel<EntryType, GroupId>
+where
+    EntryType: CancellableTimerEntry + Send + Sync,
+    GroupId: Hash + Clone + Eq,
+{
+    wheel: QuadWheelWithOverflow<EntryType>,
+    groups: hashbrown::HashMap<GroupId, Vec<EntryType::Id>>,
+    group_of: hashbrown::HashMap<EntryType::Id, GroupId>,
+}
+
+impl<EntryType, GroupId> GroupedCancellableWheel<EntryType, GroupId>
+where
+    EntryType: TimerEntryWithDelay + CancellableTimerEntry + Send + Sync,
+    GroupId: Hash + Clone + Eq,
+{
+    /// Insert a new timeout that doesn't belong to any group
+    pub fn insert(&mut self, e: EntryType) -> Result<(), TimerError<EntryType>> {
+        let id = e.id().clone();
+        self.wheel.insert(e)?;
+        self.unlink(&id);
+        Ok(())
+    }
+
+    /// Insert a new timeout as a member of `group`
+    ///
+    /// If an entry with the same id was already in a group, it's moved to `group`.
+    pub fn insert_in_group(
+        &mut self,
+        e: EntryType,
+        group: GroupId,
+    ) -> Result<(), TimerError<EntryType>> {
+        let id = e.id().clone();
+        self.wheel.insert(e)?;
+        self.unlink(&id);
+        self.groups
+            .entry(group.clone())
+            .or_default()
+            .push(id.clone());
+        self.group_of.insert(id, group);
+        Ok(())
+    }
+}
+
+impl<EntryType, GroupId> GroupedCancellableWheel<EntryType, GroupId>
+where
+    EntryType: CancellableTimerEntry + Send + Sync,
+    GroupId: Hash + Clone + Eq,
+{
+    /// Create a new wheel
+    pub fn new() -> Self {
+        GroupedCancellableWheel {
+            wheel: QuadWheelWithOverflow::new(),
+            groups: hashbrown::HashMap::new(),
+            group_of: hashbrown::HashMap::new(),
+        }
+    }
+
+    fn unlink(&mut self, id: &EntryType::Id) {
+        if let Some(group) = self.group_of.remove(id) {
+            if let Some(members) = self.groups.get_mut(&group) {
+                members.retain(|member| member != id);
+                if members.is_empty() {
+                    self.groups.remove(&group);
+                }
+            }
+        }
+    }
+
+    /// Cancel the timeout with the given `id`, removing it from its group
+    pub fn cancel(&mut self, id: &EntryType::Id) -> Result<(), TimerError<Infallible>> {
+        self.unlink(id);
+        self.wheel.cancel(id)
+    }
+
+    /// Cancel every outstanding timeout in `group`
+    ///
+    /// Returns the number of cancelled entries.
+    pub fn cancel_group(&mut self, group: &GroupId) -> usize {
+        let members = match self.groups.remove(group) {
+            Some(members) => members,
+            None => return 0,
+        };
+        let mut cancelled = 0;
+        for id in members {
+            self.group_of.remove(&id);
+            if self.wheel.cancel(&id).is_ok() {
+                cancelled += 1;
+            }
+        }
+        cancelled
+    }
+
+    /// The number of outstanding entries in `group`
+    pub fn group_len(&self, group: &GroupId) -> usize {
+        self.groups.get(group).map_or(0, |members| members.len())
+    }
+
+    /// The group the entry with the given `id` belongs to, if any
+    pub fn group_of(&self, id: &EntryType::Id) -> Option<&GroupId> {
+        self.group_of.get(id)
+    }
+
+    // fired one-shot entries are gone from the wheel, so drop them from the index too
+    fn unlink_fired(&mut self, fired: &[std::sync::Arc<EntryType>]) {
+        for e in fired {
+            if !e.is_periodic() {
+                self.unlink(e.id());
+            }
+        }
+    }
+
+    /// Move the wheel forward by a single unit (ms)
+    pub fn tick(&mut self) -> Vec<std::sync::Arc<EntryType>> {
+        let fired = self.wheel.tick();
+        self.unlink_fired(&fired);
+        fired
+    }
+
+    /// Move the wheel forward by `ticks` units (ms)
+    pub fn tick_n(&mut self, ticks: u32) -> Vec<std::sync::Arc<EntryType>> {
+        let fired = self.wheel.tick_n(ticks);
+        self.unlink_fired(&fired);
+        fired
+    }
+
+    /// Skip a certain `amount` of units (ms)
+    ///
+    /// Only use this after determining that it's actually
+    /// valid with [can_skip](GroupedCancellableWheel::can_skip)!
+    pub fn skip(&mut self, amount: u32) {
+        self.wheel.skip(amount);
+    }
+
+    /// Determine if and how many ticks can be skipped
+    pub fn can_skip(&self) -> Skip {
+        self.wheel.can_skip()
+    }
+
+    /// The inner cancellable wheel
+    pub fn wheel(&self) -> &QuadWheelWithOverflow<EntryType> {
+        &self.wheel
+    }
+}
+
+impl<EntryType, GroupId> Default for GroupedCancellableWheel<EntryType, GroupId>
+where
+    EntryType: CancellableTimerEntry + Send + Sync,
+    GroupId: Hash + Clone + Eq,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: u64, ms: u64) -> IdOnlyTimerEntry<u64> {
+        IdOnlyTimerEntry::new(id, Duration::from_millis(ms))
+    }
+
+    #[test]
+    fn cancel_group_only_hits_members() {
+        let mut timer = GroupedCancellableWheel::new();
+        timer.insert_in_group(entry(1, 3), "a").unwrap();
+        timer.insert_in_group(entry(2, 3), "a").unwrap();
+        timer.insert_in_group(entry(3, 3), "b").unwrap();
+        timer.insert(entry(4, 3)).unwrap();
+
+        assert_eq!(timer.cancel_group(&"a"), 2);
+        assert_eq!(timer.cancel_group(&"a"), 0);
+
+        let mut fired: Vec<u64> = timer.tick_n(3).iter().map(|e| e.id).collect();
+        fired.sort();
+        assert_eq!(fired, vec![3, 4]);
+        // fired entries left their group
+        assert_eq!(timer.group_len(&"b"), 0);
+    }
+
+    #[test]
+    fn cancel_single_updates_group() {
+        let mut timer = GroupedCancellableWheel::new();
+        timer.insert_in_group(entry(1, 3), 10u32).unwrap();
+        timer.insert_in_group(entry(2, 3), 10u32).unwrap();
+
+        timer.cancel(&1).expect("Entry could not be cancelled!");
+        assert_eq!(timer.group_len(&10), 1);
+        assert_eq!(timer.group_of(&1), None);
+
+        // re-inserting moves the entry between groups
+        timer.insert_in_group(entry(2, 5), 20u32).unwrap();
+        assert_eq!(timer.group_len(&10), 0);
+        assert_eq!(timer.group_of(&2), Some(&20));
+        assert_eq!(timer.cancel_group(&20), 1);
+        assert!(timer.tick_n(5).is_empty());
+    }
+}
@@ -0,0 +1,320 @@
+//! A variant of the [quad wheel](crate::wheels::quad_wheel) with a base tick of 100µs.
+//!
+//! Delays are converted to ticks of [TICK](TICK) length (rounding down) before being handed
+//! to the underlying millisecond wheel, so all the level and overflow logic is shared.
+//! Skip amounts are reported as [MicroSkip](MicroSkip) in µs, to keep them apart from the ms based [Skip](Skip).
+//!
+//! # Examples
+//! ```
+//! # use std::time::Duration;
+//! use commlib::hash_wheel_timer::*;
+//! use commlib::hash_wheel_timer::wheels::micro_quad_wheel::*;
+//!
+//! let mut timer = MicroQuadWheelWithOverflow::default();
+//! timer
+//!     .insert(IdOnlyTimerEntry {
+//!         id: 1u64,
+//!         delay: Duration::from_micros(300),
+//!     })
+//!     .expect("Could not insert timer entry!");
+//! assert!(timer.tick_n(2).is_empty());
+//! assert_eq!(timer.tick().len(), 1);
+//! ```
+
+use super::wheels::cancellable::{
+    CancellableTimerEntry, QuadWheelWithOverflow as CancellableQuadWheelWithOverflow,
+};
+use super::wheels::quad_wheel::{no_prune, PruneDecision, QuadWheelWithOverflow};
+use super::*;
+
+/// The length of a single tick of the micro wheels
+pub const TICK: Duration = Duration::from_micros(100);
+
+const TICK_MICROS: u64 = 100;
+
+/// Result of a [can_skip](MicroQuadWheelWithOverflow::can_skip) invocation on a micro wheel
+#[derive(PartialEq, Debug)]
+pub enum MicroSkip {
+    /// The wheel is completely empty, so there's no point in skipping
+    Empty,
+    /// It's possible to skip up to the provided number of µs (always a multiple of the tick)
+    Micros(u64),
+    /// Nothing can be skipped, as the next tick has expiring timers
+    None,
+}
+
+impl From<Skip> for MicroSkip {
+    fn from(skip: Skip) -> Self {
+        match skip {
+            Skip::Empty => MicroSkip::Empty,
+            Skip::Millis(ticks) => MicroSkip::Micros(ticks as u64 * TICK_MICROS),
+            Skip::None => MicroSkip::None,
+        }
+    }
+}
+
+// the underlying wheel counts in ms, so express the number of ticks as ms
+fn to_wheel_delay(delay: Duration) -> Duration {
+    let ticks = delay.as_micros() / TICK_MICROS as u128;
+    Duration::from_millis(ticks as u64)
+}
+
+// the underlying wheels skip at most u32::MAX ticks at a time
+fn skip_micros(micros: u64, mut skip_ticks: impl FnMut(u32)) {
+    let mut ticks = micros / TICK_MICROS;
+    while ticks > 0 {
+        let n = std::cmp::min(ticks, u32::MAX as u64) as u32;
+        skip_ticks(n);
+        ticks -= n as u64;
+    }
+}
+
+/// A four-level hierarchical wheel with overflow and a tick of 100µs
+pub struct MicroQuadWheelWithOverflow<EntryType>
+where
+    EntryType: Debug + Send + Sync,
+{
+    wheel: QuadWheelWithOverflow<EntryType>,
+}
+
+impl<EntryType> Default for MicroQuadWheelWithOverflow<EntryType>
+where
+    EntryType: Debug + Send + Sync,
+{
+    fn default() -> Self {
+        MicroQuadWheelWithOverflow::new(no_prune::<EntryType>)
+    }
+}
+
+impl<EntryType> MicroQuadWheelWithOverflow<EntryType>
+where
+    EntryType: TimerEntryWithDelay + Send + Sync,
+{
+    /// Insert a new timeout into the wheel
+    pub fn insert(&mut self, e: EntryType) -> Result<(), TimerError<EntryType>> {
+        let delay = e.delay();
+        self.insert_with_delay(e, delay)
+    }
+}
+
+impl<EntryType> MicroQuadWheelWithOverflow<EntryType>
+where
+    EntryType: Debug + Send + Sync,
+{
+    /// Create a new wheel
+    pub fn new(pruner: fn(&EntryType) -> PruneDecision) -> Self {
+        MicroQuadWheelWithOverflow {
+            wheel: QuadWheelWithOverflow::new(pruner),
+        }
+    }
+
+    /// Insert a new timeout into the wheel to be returned after `delay`
+    ///
+    /// Delays shorter than a single [TICK](TICK) are rejected as expired.
+    pub fn insert_with_delay(
+        &mut self,
+        e: EntryType,
+        delay: Duration,
+    ) -> Result<(), TimerError<EntryType>> {
+        self.wheel.insert_with_delay(e, to_wheel_delay(delay))
+    }
+
+    /// Move the wheel forward by a single tick (100µs)
+    ///
+    /// Returns a list of all timers that expire during this tick.
+    pub fn tick(&mut self) -> Vec<EntryType> {
+        self.wheel.tick()
+    }
+
+    /// Move the wheel forward by `ticks` ticks (100µs each)
+    pub fn tick_n(&mut self, ticks: u32) -> Vec<EntryType> {
+        self.wheel.tick_n(ticks)
+    }
+
+    /// Skip a certain amount of µs, rounded down to whole ticks
+    ///
+    /// Only use this after determining that it's actually
+    /// valid with [can_skip](MicroQuadWheelWithOverflow::can_skip)!
+    pub fn skip(&mut self, micros: u64) {
+        skip_micros(micros, |ticks| self.wheel.skip(ticks));
+    }
+
+    /// Determine if and how many µs can be skipped
+    pub fn can_skip(&self) -> MicroSkip {
+        self.wheel.can_skip().into()
+    }
+
+    /// Remove and return all entries without ticking
+    pub fn drain(&mut self) -> Vec<EntryType> {
+        self.wheel.drain()
+    }
+}
+
+/// The cancellable counterpart of [MicroQuadWheelWithOverflow](MicroQuadWheelWithOverflow)
+///
+/// Periods of periodic entries are converted to ticks as well.
+pub struct CancellableMicroQuadWheelWithOverflow<EntryType>
+where
+    EntryType: CancellableTimerEntry + Send + Sync,
+{
+    wheel: CancellableQuadWheelWithOverflow<EntryType>,
+}
+
+impl<EntryType> Default for CancellableMicroQuadWheelWithOverflow<EntryType>
+where
+    EntryType: CancellableTimerEntry + Send + Sync,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<EntryType> CancellableMicroQuadWheelWithOverflow<EntryType>
+where
+    EntryType: TimerEntryWithDelay + CancellableTimerEntry + Send + Sync,
+{
+    /// Insert a new timeout into the wheel
+    pub fn insert(&mut self, e: EntryType) -> Result<(), TimerError<EntryType>> {
+        self.wheel.insert(e)
+    }
+
+    /// Insert a new timeout into the wheel
+    pub fn insert_ref(
+        &mut self,
+        e: std::sync::Arc<EntryType>,
+    ) -> Result<(), TimerError<std::sync::Arc<EntryType>>> {
+        self.wheel.insert_ref(e)
+    }
+}
+
+impl<EntryType> CancellableMicroQuadWheelWithOverflow<EntryType>
+where
+    EntryType: CancellableTimerEntry + Send + Sync,
+{
+    /// Create a new wheel
+    pub fn new() -> Self {
+        CancellableMicroQuadWheelWithOverflow {
+            wheel: CancellableQuadWheelWithOverflow::with_resolution(TICK),
+        }
+    }
+
+    /// Insert a new timeout into the wheel to be returned after `delay`
+    pub fn insert_ref_with_delay(
+        &mut self,
+        e: std::sync::Arc<EntryType>,
+        delay: Duration,
+    ) -> Result<(), TimerError<std::sync::Arc<EntryType>>> {
+        self.wheel.insert_ref_with_delay(e, delay)
+    }
+
+    /// Cancel the timeout with the given `id`
+    pub fn cancel(&mut self, id: &EntryType::Id) -> Result<(), TimerError<Infallible>> {
+        self.wheel.cancel(id)
+    }
+
+    /// Move the outstanding timeout with the given `id` so it expires `new_delay` from now
+    pub fn reschedule(
+        &mut self,
+        id: &EntryType::Id,
+        new_delay: Duration,
+    ) -> Result<(), TimerError<Infallible>> {
+        self.wheel.reschedule(id, new_delay)
+    }
+
+    /// Move the wheel forward by a single tick (100µs)
+    pub fn tick(&mut self) -> Vec<std::sync::Arc<EntryType>> {
+        self.wheel.tick()
+    }
+
+    /// Move the wheel forward by `ticks` ticks (100µs each)
+    pub fn tick_n(&mut self, ticks: u32) -> Vec<std::sync::Arc<EntryType>> {
+        self.wheel.tick_n(ticks)
+    }
+
+    /// Skip a certain amount of µs, rounded down to whole ticks
+    ///
+    /// Only use this after determining that it's actually
+    /// valid with [can_skip](CancellableMicroQuadWheelWithOverflow::can_skip)!
+    pub fn skip(&mut self, micros: u64) {
+        skip_micros(micros, |ticks| self.wheel.skip(ticks));
+    }
+
+    /// Determine if and how many µs can be skipped
+    pub fn can_skip(&self) -> MicroSkip {
+        self.wheel.can_skip().into()
+    }
+
+    /// Remove and return all outstanding entries without ticking
+    pub fn drain(&mut self) -> Vec<std::sync::Arc<EntryType>> {
+        self.wheel.drain()
+    }
+
+    /// The number of outstanding (not yet expired or cancelled) entries
+    pub fn pending_count(&self) -> usize {
+        self.wheel.pending_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sub_ms_schedule() {
+        let mut timer = MicroQuadWheelWithOverflow::default();
+        for (id, us) in [(1u64, 250u64), (2, 100), (3, 1500)] {
+            timer
+                .insert(IdOnlyTimerEntry::new(id, Duration::from_micros(us)))
+                .expect("Could not insert timer entry!");
+        }
+        assert!(timer
+            .insert(IdOnlyTimerEntry::new(4u64, Duration::from_micros(99)))
+            .is_err());
+
+        let mut fired = vec![];
+        for tick in 1..=15u64 {
+            for e in timer.tick() {
+                fired.push((e.id, tick));
+            }
+        }
+        assert_eq!(fired, vec![(2, 1), (1, 2), (3, 15)]);
+    }
+
+    #[test]
+    fn skip_in_micros() {
+        let mut timer = MicroQuadWheelWithOverflow::default();
+        assert_eq!(timer.can_skip(), MicroSkip::Empty);
+        timer
+            .insert(IdOnlyTimerEntry::new(1u64, Duration::from_millis(30)))
+            .expect("Could not insert timer entry!");
+        // 300 ticks, the first 255 of them are empty
+        assert_eq!(timer.can_skip(), MicroSkip::Micros(25500));
+        timer.skip(25500);
+        assert!(timer.tick_n(44).is_empty());
+        assert_eq!(timer.tick().len(), 1);
+    }
+
+    #[test]
+    fn cancellable_periodic() {
+        let mut timer = CancellableMicroQuadWheelWithOverflow::new();
+        timer
+            .insert(IdOnlyPeriodicTimerEntry::new(
+                1u64,
+                Duration::from_micros(200),
+                Duration::from_micros(500),
+            ))
+            .expect("Could not insert timer entry!");
+        timer
+            .insert(IdOnlyTimerEntry::new(2u64, Duration::from_micros(300)))
+            .expect("Could not insert timer entry!");
+        timer.cancel(&2).expect("Entry could not be cancelled!");
+
+        let mut fired = vec![];
+        for tick in 1..=12u64 {
+            for e in timer.tick() {
+                fired.push((*e.id(), tick));
+            }
+        }
+        assert_eq!(fired, vec![(1, 2), (1, 7), (1, 12)]);
+    }
+}
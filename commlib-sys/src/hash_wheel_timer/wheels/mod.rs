@@ -5,6 +5,8 @@ use std::{cmp::Eq, convert::Infallible, fmt::Debug, hash::Hash, mem, time::Durat
 
 pub mod byte_wheel;
 pub mod cancellable;
+pub mod grouped;
+pub mod micro_quad_wheel;
 pub mod quad_wheel;
 
 /// Result of a [can_skip](quad_wheel::QuadWheelWithOverflow::can_skip) invocation
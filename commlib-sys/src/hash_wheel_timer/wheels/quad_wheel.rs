@@ -2,10 +2,12 @@
 //!
 //! Combining four [byte wheels](crate::wheels::byte_wheel) we get a hierachical timer
 //! that can represent timeouts up to [`u32::MAX`](std::u32::MAX) time units into the future.
+//! A fifth byte wheel, whose slots each span 2^32 time units, extends this to 2^40 time units
+//! (about 34 years at ms resolution), so long-lived leases never touch the overflow list.
 //!
 //! In order to support timeouts of up to [`u64::MAX`](std::u64::MAX) time units,
 //! this implementation also keeps an overflow list, which stores all timers that didn't fit
-//! into any slot in the four wheels. Additions into this list happens in (amortised) constant time
+//! into any slot in the five wheels. Additions into this list happens in (amortised) constant time
 //! but movement from the list into the timer array is linear in the number of overflow items.
 //!
 //! Our design assumes that the vast majority of timers are going be scheduled less than
@@ -97,7 +99,7 @@ pub fn no_prune<E>(_e: &E) -> PruneDecision {
 /// Any value scheduled so far off that it doesn't fit into the wheel
 /// is stored in an overflow `Vec` and added to the wheel, once time as advanced enough
 /// that it actually fits.
-/// In this design the maximum schedule duration for the wheel itself is [`u32::MAX`](std::u32::MAX) units (typically ms),
+/// In this design the maximum schedule duration for the wheel itself is 2^40 - 1 units (typically ms),
 /// everything else goes into the overflow `Vec`.
 pub struct QuadWheelWithOverflow<EntryType>
 where
@@ -107,11 +109,14 @@ where
     secondary: Box<ByteWheel<EntryType, [u8; 1]>>,
     tertiary: Box<ByteWheel<EntryType, [u8; 2]>>,
     quarternary: Box<ByteWheel<EntryType, [u8; 3]>>,
+    quinary: Box<ByteWheel<EntryType, [u8; 4]>>,
     overflow: Vec<OverflowEntry<EntryType>>,
     pruner: fn(&EntryType) -> PruneDecision,
+    overflows_promoted: u64,
 }
 
-const MAX_SCHEDULE_DUR: Duration = Duration::from_millis(u32::MAX as u64);
+const MAX_SCHEDULE_DUR: Duration = Duration::from_millis((1 << 40) - 1);
+const LONG_CYCLE_LENGTH: u64 = 1 << 40; // 2^40
 const CYCLE_LENGTH: u64 = 1 << 32; // 2^32
 const PRIMARY_LENGTH: u32 = 1 << 8; // 2^8
 const SECONDARY_LENGTH: u32 = 1 << 16; // 2^16
@@ -148,14 +153,16 @@ where
             secondary: Box::new(ByteWheel::new()),
             tertiary: Box::new(ByteWheel::new()),
             quarternary: Box::new(ByteWheel::new()),
+            quinary: Box::new(ByteWheel::new()),
             overflow: Vec::new(),
             pruner,
+            overflows_promoted: 0,
         }
     }
 
     /// Described how many ticks are left before the timer has wrapped around completely
     pub fn remaining_time_in_cycle(&self) -> u64 {
-        CYCLE_LENGTH - (self.current_time_in_cycle() as u64)
+        LONG_CYCLE_LENGTH - self.current_time_in_long_cycle()
     }
 
     /// Produces a 32-bit timestamp including the current index of every wheel
@@ -169,6 +176,11 @@ where
         u32::from_be(unsafe { mem::transmute(time_bytes) })
     }
 
+    /// Produces a 40-bit timestamp including the current index of every wheel, the fifth one included
+    pub fn current_time_in_long_cycle(&self) -> u64 {
+        ((self.quinary.current() as u64) << 32) | (self.current_time_in_cycle() as u64)
+    }
+
     /// Insert a new timeout into the wheel to be returned after `delay` ticks
     pub fn insert_with_delay(
         &mut self,
@@ -182,40 +194,49 @@ where
             self.overflow.push(overflow_e);
             Ok(())
         } else {
-            let delay = {
-                let s = (delay.as_secs() * 1000) as u32;
-                let ms = delay.subsec_millis();
-                s + ms
-            };
-            let current_time = self.current_time_in_cycle();
-            let absolute_time = delay.wrapping_add(current_time);
-            let absolute_bytes: [u8; 4] = absolute_time.to_be_bytes();
+            let delay = delay.as_secs() * 1000 + delay.subsec_millis() as u64;
+            let current_time = self.current_time_in_long_cycle();
+            let absolute_time = (delay + current_time) & (LONG_CYCLE_LENGTH - 1);
+            let absolute_bytes: [u8; 8] = absolute_time.to_be_bytes();
             let zero_time = absolute_time ^ current_time; // a-b%2
-            let zero_bytes: [u8; 4] = zero_time.to_be_bytes();
-            match zero_bytes {
-                [0, 0, 0, 0] => Err(TimerError::Expired(e)),
-                [0, 0, 0, _] => {
-                    self.primary.insert(absolute_bytes[3], e, []);
+            let zero_bytes: [u8; 8] = zero_time.to_be_bytes();
+            match zero_bytes[3..] {
+                [0, 0, 0, 0, 0] => Err(TimerError::Expired(e)),
+                [0, 0, 0, 0, _] => {
+                    self.primary.insert(absolute_bytes[7], e, []);
                     Ok(())
                 }
-                [0, 0, _, _] => {
+                [0, 0, 0, _, _] => {
                     self.secondary
-                        .insert(absolute_bytes[2], e, [absolute_bytes[3]]);
+                        .insert(absolute_bytes[6], e, [absolute_bytes[7]]);
                     Ok(())
                 }
-                [0, _, _, _] => {
+                [0, 0, _, _, _] => {
                     self.tertiary.insert(
-                        absolute_bytes[1],
+                        absolute_bytes[5],
                         e,
-                        [absolute_bytes[2], absolute_bytes[3]],
+                        [absolute_bytes[6], absolute_bytes[7]],
                     );
                     Ok(())
                 }
-                [_, _, _, _] => {
+                [0, _, _, _, _] => {
                     self.quarternary.insert(
-                        absolute_bytes[0],
+                        absolute_bytes[4],
+                        e,
+                        [absolute_bytes[5], absolute_bytes[6], absolute_bytes[7]],
+                    );
+                    Ok(())
+                }
+                _ => {
+                    self.quinary.insert(
+                        absolute_bytes[3],
                         e,
-                        [absolute_bytes[1], absolute_bytes[2], absolute_bytes[3]],
+                        [
+                            absolute_bytes[4],
+                            absolute_bytes[5],
+                            absolute_bytes[6],
+                            absolute_bytes[7],
+                        ],
                     );
                     Ok(())
                 }
@@ -228,6 +249,39 @@ where
     /// Returns a list of all timers that expire during this tick.
     pub fn tick(&mut self) -> Vec<EntryType> {
         let mut res: Vec<EntryType> = Vec::new();
+        self.tick_into(&mut res);
+        res
+    }
+
+    /// Move the wheel forward by `ticks` units (ms)
+    ///
+    /// Returns all timers that expire during these ticks, in the same order
+    /// individual [tick](QuadWheelWithOverflow::tick) calls would have produced them.
+    /// Empty stretches are skipped rather than iterated.
+    pub fn tick_n(&mut self, ticks: u32) -> Vec<EntryType> {
+        let mut res: Vec<EntryType> = Vec::new();
+        let mut remaining = ticks;
+        while remaining > 0 {
+            match self.can_skip() {
+                Skip::Empty => {
+                    self.skip(remaining);
+                    break;
+                }
+                Skip::Millis(ms) => {
+                    let n = std::cmp::min(ms, remaining);
+                    self.skip(n);
+                    remaining -= n;
+                }
+                Skip::None => {
+                    self.tick_into(&mut res);
+                    remaining -= 1;
+                }
+            }
+        }
+        res
+    }
+
+    fn tick_into(&mut self, res: &mut Vec<EntryType>) {
         // primary
         let (move0_opt, current0) = self.primary.tick();
         if let Some(move0) = move0_opt {
@@ -299,19 +353,49 @@ where
                         }
                     }
                     if current3 == 0u8 {
-                        // overflow list
-                        if !self.overflow.is_empty() {
-                            let mut ol = Vec::with_capacity(self.overflow.len() / 2); // assume that about half are going to be scheduled now
-                            mem::swap(&mut self.overflow, &mut ol);
-                            for overflow_e in ol {
-                                if (self.pruner)(&overflow_e.entry).should_keep() {
-                                    match self.insert_with_delay(
-                                        overflow_e.entry,
-                                        overflow_e.remaining_delay,
-                                    ) {
-                                        Ok(()) => (), // ignore
-                                        Err(TimerError::Expired(e)) => res.push(e),
-                                        Err(f) => panic!("Unexpected error during insert: {:?}", f),
+                        // quinary
+                        let (move4_opt, current4) = self.quinary.tick();
+                        if let Some(move4) = move4_opt {
+                            for we in move4 {
+                                if (self.pruner)(&we.entry).should_keep() {
+                                    match we.rest {
+                                        [0, 0, 0, 0] => {
+                                            res.push(we.entry);
+                                        }
+                                        [0, 0, 0, b0] => {
+                                            self.primary.insert(b0, we.entry, []);
+                                        }
+                                        [0, 0, b1, b0] => {
+                                            self.secondary.insert(b1, we.entry, [b0]);
+                                        }
+                                        [0, b2, b1, b0] => {
+                                            self.tertiary.insert(b2, we.entry, [b1, b0]);
+                                        }
+                                        [b3, b2, b1, b0] => {
+                                            self.quarternary.insert(b3, we.entry, [b2, b1, b0]);
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                        if current4 == 0u8 {
+                            // overflow list
+                            if !self.overflow.is_empty() {
+                                let mut ol = Vec::with_capacity(self.overflow.len() / 2); // assume that about half are going to be scheduled now
+                                mem::swap(&mut self.overflow, &mut ol);
+                                for overflow_e in ol {
+                                    if (self.pruner)(&overflow_e.entry).should_keep() {
+                                        self.overflows_promoted += 1;
+                                        match self.insert_with_delay(
+                                            overflow_e.entry,
+                                            overflow_e.remaining_delay,
+                                        ) {
+                                            Ok(()) => (), // ignore
+                                            Err(TimerError::Expired(e)) => res.push(e),
+                                            Err(f) => {
+                                                panic!("Unexpected error during insert: {:?}", f)
+                                            }
+                                        }
                                     }
                                 }
                             }
@@ -320,6 +404,104 @@ where
                 }
             }
         }
+    }
+
+    /// Number of entries moved from the overflow list into the wheel so far
+    pub fn overflows_promoted(&self) -> u64 {
+        self.overflows_promoted
+    }
+
+    /// Reset the [overflows_promoted](QuadWheelWithOverflow::overflows_promoted) counter
+    pub fn reset_overflows_promoted(&mut self) {
+        self.overflows_promoted = 0;
+    }
+
+    /// Remove and return all entries from every wheel level and the overflow list
+    ///
+    /// Entries rejected by the pruner are dropped. The returned entries are in unspecified order
+    /// and the wheel is empty afterwards.
+    pub fn drain(&mut self) -> Vec<EntryType> {
+        let mut res: Vec<EntryType> = Vec::new();
+        let pruner = self.pruner;
+        let mut keep = |e: EntryType| {
+            if pruner(&e).should_keep() {
+                res.push(e);
+            }
+        };
+        self.primary
+            .drain()
+            .into_iter()
+            .for_each(|we| keep(we.entry));
+        self.secondary
+            .drain()
+            .into_iter()
+            .for_each(|we| keep(we.entry));
+        self.tertiary
+            .drain()
+            .into_iter()
+            .for_each(|we| keep(we.entry));
+        self.quarternary
+            .drain()
+            .into_iter()
+            .for_each(|we| keep(we.entry));
+        self.quinary
+            .drain()
+            .into_iter()
+            .for_each(|we| keep(we.entry));
+        mem::take(&mut self.overflow)
+            .into_iter()
+            .for_each(|oe| keep(oe.entry));
+        res
+    }
+
+    /// All entries with the number of ticks (as ms) remaining until they expire
+    ///
+    /// Entries rejected by the pruner are still included. The order is unspecified.
+    pub fn remaining_delays(&self) -> Vec<(&EntryType, Duration)> {
+        let current = self.current_time_in_long_cycle();
+        let mut res: Vec<(&EntryType, Duration)> = Vec::new();
+        {
+            // below the fifth level an entry shares all higher bytes with the current time
+            let mut push = |e, level: u32, low_bits: u64| {
+                let high_bits = current & !((1u64 << (8 * (level + 1))) - 1);
+                let ticks = (high_bits | low_bits).saturating_sub(current);
+                res.push((e, Duration::from_millis(ticks)));
+            };
+            for (p, we) in self.primary.iter() {
+                push(&we.entry, 0, p as u64);
+            }
+            for (p, we) in self.secondary.iter() {
+                push(
+                    &we.entry,
+                    1,
+                    u64::from_be_bytes([0, 0, 0, 0, 0, 0, p, we.rest[0]]),
+                );
+            }
+            for (p, we) in self.tertiary.iter() {
+                let [b1, b0] = we.rest;
+                push(&we.entry, 2, u64::from_be_bytes([0, 0, 0, 0, 0, p, b1, b0]));
+            }
+            for (p, we) in self.quarternary.iter() {
+                let [b2, b1, b0] = we.rest;
+                push(
+                    &we.entry,
+                    3,
+                    u64::from_be_bytes([0, 0, 0, 0, p, b2, b1, b0]),
+                );
+            }
+        }
+        // the fifth level may wrap around the full cycle
+        for (p, we) in self.quinary.iter() {
+            let [b3, b2, b1, b0] = we.rest;
+            let target = u64::from_be_bytes([0, 0, 0, p, b3, b2, b1, b0]);
+            let ticks = (target + LONG_CYCLE_LENGTH - current) & (LONG_CYCLE_LENGTH - 1);
+            res.push((&we.entry, Duration::from_millis(ticks)));
+        }
+        // overflow entries are re-inserted with their remaining delay once the cycle wraps
+        let remaining_in_cycle = Duration::from_millis(self.remaining_time_in_cycle());
+        for oe in self.overflow.iter() {
+            res.push((&oe.entry, remaining_in_cycle + oe.remaining_delay));
+        }
         res
     }
 
@@ -329,12 +511,14 @@ where
     /// Only use this after determining that it's actually
     /// valid with [can_skip](QuadWheelWithOverflow::can_skip)!
     pub fn skip(&mut self, amount: u32) {
-        let new_time = self.current_time_in_cycle().wrapping_add(amount);
-        let new_time_bytes: [u8; 4] = new_time.to_be_bytes();
-        self.primary.advance(new_time_bytes[3]);
-        self.secondary.advance(new_time_bytes[2]);
-        self.tertiary.advance(new_time_bytes[1]);
-        self.quarternary.advance(new_time_bytes[0]);
+        let new_time =
+            (self.current_time_in_long_cycle() + amount as u64) & (LONG_CYCLE_LENGTH - 1);
+        let new_time_bytes: [u8; 8] = new_time.to_be_bytes();
+        self.primary.advance(new_time_bytes[7]);
+        self.secondary.advance(new_time_bytes[6]);
+        self.tertiary.advance(new_time_bytes[5]);
+        self.quarternary.advance(new_time_bytes[4]);
+        self.quinary.advance(new_time_bytes[3]);
     }
 
     /// Determine if and how many ticks can be skipped
@@ -343,10 +527,16 @@ where
             if self.secondary.is_empty() {
                 if self.tertiary.is_empty() {
                     if self.quarternary.is_empty() {
-                        if self.overflow.is_empty() {
-                            Skip::Empty
+                        if self.quinary.is_empty() {
+                            if self.overflow.is_empty() {
+                                Skip::Empty
+                            } else {
+                                let rem = self.remaining_time_in_cycle() - 1u64;
+                                Skip::from_millis(std::cmp::min(rem, u32::MAX as u64) as u32)
+                            }
                         } else {
-                            Skip::from_millis((self.remaining_time_in_cycle() - 1u64) as u32)
+                            let rem = CYCLE_LENGTH - (self.current_time_in_cycle() as u64);
+                            Skip::from_millis((rem - 1u64) as u32)
                         }
                     } else {
                         let tertiary_current =
@@ -708,4 +898,81 @@ mod u64_tests {
         }
         assert_eq!(timer.can_skip(), Skip::Empty);
     }
+
+    #[test]
+    fn tick_n_preserves_order() {
+        let mut timer = QuadWheelWithOverflow::default();
+        for id in [7u64, 1, 3] {
+            timer
+                .insert(IdOnlyTimerEntry::new(id, Duration::from_millis(id)))
+                .expect("Could not insert timer entry!");
+        }
+        let res = timer.tick_n(10);
+        let ids: Vec<u64> = res.iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![1, 3, 7]);
+        assert_eq!(timer.current_time_in_cycle(), 10);
+        assert_eq!(timer.can_skip(), Skip::Empty);
+    }
+
+    #[test]
+    fn drain_all_levels() {
+        let mut timer = QuadWheelWithOverflow::default();
+        for (id, delay) in [
+            (1u64, 5u64),
+            (2, 300),
+            (3, 70_000),
+            (4, 20_000_000),
+            (5, 1 << 33),
+        ] {
+            timer
+                .insert(IdOnlyTimerEntry::new(id, Duration::from_millis(delay)))
+                .expect("Could not insert timer entry!");
+        }
+        let mut ids: Vec<u64> = timer.drain().iter().map(|e| e.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![1, 2, 3, 4, 5]);
+        assert_eq!(timer.can_skip(), Skip::Empty);
+    }
+    fn run_until_fired(timer: &mut QuadWheelWithOverflow<IdOnlyTimerEntry<u64>>) -> u64 {
+        let mut millis = 0u64;
+        loop {
+            match timer.can_skip() {
+                Skip::Empty => panic!("Timer ran empty at millis={}!", millis),
+                Skip::Millis(ms) => {
+                    timer.skip(ms);
+                    millis += ms as u64;
+                }
+                Skip::None => (),
+            }
+            let res = timer.tick();
+            millis += 1;
+            if !res.is_empty() {
+                return millis;
+            }
+        }
+    }
+
+    #[test]
+    fn fifth_level_schedule() {
+        // roughly six months
+        let delay: u64 = 180 * 24 * 3600 * 1000;
+        let mut timer = QuadWheelWithOverflow::default();
+        timer.skip(12345);
+        timer
+            .insert(IdOnlyTimerEntry::new(1u64, Duration::from_millis(delay)))
+            .expect("Could not insert timer entry!");
+        assert_eq!(run_until_fired(&mut timer), delay);
+        assert_eq!(timer.overflows_promoted(), 0);
+    }
+
+    #[test]
+    fn beyond_fifth_level_overflows() {
+        let delay: u64 = (1 << 40) + 77;
+        let mut timer = QuadWheelWithOverflow::default();
+        timer
+            .insert(IdOnlyTimerEntry::new(1u64, Duration::from_millis(delay)))
+            .expect("Could not insert timer entry!");
+        assert_eq!(run_until_fired(&mut timer), delay);
+        assert_eq!(timer.overflows_promoted(), 1);
+    }
 }
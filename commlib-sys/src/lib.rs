@@ -36,6 +36,18 @@ pub use commlib_log::*;
 pub mod commlib_service;
 pub use commlib_service::*;
 
+/// service 之间的请求/应答
+pub mod service_call;
+pub use service_call::{call_service, CallError, CallHandlerTable};
+
+/// 发往 service 的类型化消息通道
+pub mod service_channel;
+pub use service_channel::{ServiceChannel, ServiceReceiver, ServiceSender};
+
+/// service 内置定时器
+pub mod service_timer;
+pub use service_timer::{ServiceTimerId, ServiceTimers};
+
 ///
 pub mod clock;
 pub use clock::*;
@@ -47,6 +59,9 @@ pub mod hash_wheel_timer;
 pub mod xmlreader;
 pub use xmlreader::XmlReader;
 
+/// xlsx 配置表
+pub mod xlsx_reader;
+
 ///
 pub mod service_signal;
 pub use service_signal::ServiceSignalRs;
@@ -54,12 +69,19 @@ pub use service_signal::ServiceSignalRs;
 ///
 pub mod service_net;
 pub use service_net::{
-    connect_to_tcp_server, create_tcp_client, listen_tcp_addr, start_network, stop_network,
+    connect_to_tcp_server, connect_to_tcp_server_ex, connect_to_tcp_server_with_limit,
+    connect_to_tcp_server_with_reconnect, connect_to_kcp_server, connect_to_udp_server,
+    create_tcp_client, create_tcp_client_ex, listen_kcp_addr, listen_tcp_addr, listen_tcp_addr_ex,
+    listen_tcp_addr_with_limit, listen_tcp_addr_with_max_conns, listen_udp_addr, start_network,
+    stop_network,
 };
 pub use service_net::{
-    CmdId, ConnId, NetPacket, NetPacketGuard, NetProxy, PacketType, ServiceNetRs, TcpClient,
-    TcpHandler, TcpListenerId, TcpServer,
+    CmdId, ConnId, ListenerHandle, NetPacket, NetPacketGuard, NetPacketGuardExt, NetProxy,
+    PacketBytes, PacketType, RateLimit, RateLimitPolicy, ReconnectPolicy, SendQueueLimit,
+    ServiceNetRs, TcpClient, TcpHandler, TcpListenerId, TcpServer,
 };
+pub use service_net::{Encryptor, EncryptorFactory, SendError, XorEncryptor};
+pub use service_net::{KcpConfig, KcpError};
 pub use service_net::{ENCRYPT_KEY_LEN, ENCRYPT_MAX_LEN};
 
 /// 全局变量
@@ -71,4 +93,8 @@ pub mod commlib_def;
 pub use commlib_def::*;
 ///
 pub mod data_schema;
-pub use data_schema::DataTable;
+pub use data_schema::{
+    CellValue, ColumnType, DataSchemaHandle, DataSchemaLoadFn, DataSchemaLoader, DataSchemaRef,
+    DataSchemaReloadFn, DataSchemaWatcher, DataTable, DataTableDiff, DataTableError, DataTableRow,
+    DataTableRowIter, LoadHandle, SchemaError,
+};
@@ -0,0 +1,299 @@
+//!
+//! Common Library: service call -- service 之间的请求/应答
+//!
+//! 目标 service 通过 ServiceHandle::register_call_handler 注册处理函数,
+//! 调用方使用 call_service 投递请求，应答在调用方 service 线程中回调
+//!
+
+use parking_lot::{Mutex, RwLock};
+use std::any::{Any, TypeId};
+use std::sync::Arc;
+
+use super::hash_wheel_timer::TimerId;
+use super::{ServiceRs, G_PERIODIC_TIMER};
+
+/// call_service 失败原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallError {
+    NoHandler, // 目标 service 没有注册该 (Req, Resp) 的处理函数
+    Timeout,   // 超时未收到应答
+}
+
+impl std::fmt::Display for CallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CallError::NoHandler => write!(f, "call handler not found"),
+            CallError::Timeout => write!(f, "call timeout"),
+        }
+    }
+}
+
+impl std::error::Error for CallError {}
+
+type CallHandlerFn<Req, Resp> = Box<dyn Fn(Req) -> Resp + Send + Sync>;
+
+/// call 处理函数表，按 (Req, Resp) 类型查找
+#[derive(Default)]
+pub struct CallHandlerTable {
+    handlers: RwLock<hashbrown::HashMap<(TypeId, TypeId), Arc<dyn Any + Send + Sync>>>,
+}
+
+impl CallHandlerTable {
+    ///
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 注册处理函数，相同 (Req, Resp) 重复注册时覆盖旧的
+    pub fn register<Req, Resp, F>(&self, f: F)
+    where
+        Req: Send + 'static,
+        Resp: Send + 'static,
+        F: Fn(Req) -> Resp + Send + Sync + 'static,
+    {
+        let handler: CallHandlerFn<Req, Resp> = Box::new(f);
+        let key = (TypeId::of::<Req>(), TypeId::of::<Resp>());
+        self.handlers.write().insert(key, Arc::new(handler));
+    }
+
+    ///
+    pub fn unregister<Req, Resp>(&self) -> bool
+    where
+        Req: Send + 'static,
+        Resp: Send + 'static,
+    {
+        let key = (TypeId::of::<Req>(), TypeId::of::<Resp>());
+        self.handlers.write().remove(&key).is_some()
+    }
+
+    /// 在当前线程中执行处理函数，未注册时返回 CallError::NoHandler
+    pub fn call<Req, Resp>(&self, req: Req) -> Result<Resp, CallError>
+    where
+        Req: Send + 'static,
+        Resp: Send + 'static,
+    {
+        let key = (TypeId::of::<Req>(), TypeId::of::<Resp>());
+        let handler_opt = self.handlers.read().get(&key).cloned();
+        match handler_opt.and_then(|h| h.downcast::<CallHandlerFn<Req, Resp>>().ok()) {
+            Some(handler) => Ok((handler)(req)),
+            None => Err(CallError::NoHandler),
+        }
+    }
+}
+
+type ReplyFn<Resp> = Box<dyn FnOnce(Result<Resp, CallError>) + Send>;
+
+/// 一次 call 的应答状态：应答和超时谁先到达谁负责回调，另一方忽略
+struct PendingReply<Resp> {
+    on_reply: Mutex<Option<ReplyFn<Resp>>>,
+    timer_id: Mutex<Option<TimerId>>,
+}
+
+impl<Resp> PendingReply<Resp>
+where
+    Resp: Send + 'static,
+{
+    fn reply<C>(&self, caller: &Arc<C>, result: Result<Resp, CallError>)
+    where
+        C: ServiceRs + 'static,
+    {
+        let on_reply = match self.on_reply.lock().take() {
+            Some(f) => f,
+            None => return, // 已超时（或已应答）
+        };
+        if let Some(id) = self.timer_id.lock().take() {
+            G_PERIODIC_TIMER.cancel(id);
+        }
+
+        // 回到调用方 service 线程中执行（Mutex 包装以满足 Sync）
+        let reply = Mutex::new(Some((on_reply, result)));
+        caller.run_in_service(Box::new(move || {
+            if let Some((on_reply, result)) = reply.lock().take() {
+                on_reply(result);
+            }
+        }));
+    }
+}
+
+/// 向 target 发起请求，target 线程中执行注册的处理函数，on_reply 在 caller 线程中执行,
+/// timeout 为 None 时一直等待应答
+pub fn call_service<C, T, Req, Resp, F>(
+    caller: &Arc<C>,
+    target: &Arc<T>,
+    req: Req,
+    timeout: Option<std::time::Duration>,
+    on_reply: F,
+) where
+    C: ServiceRs + 'static,
+    T: ServiceRs + 'static,
+    Req: Send + 'static,
+    Resp: Send + 'static,
+    F: FnOnce(Result<Resp, CallError>) + Send + 'static,
+{
+    let pending = Arc::new(PendingReply::<Resp> {
+        on_reply: Mutex::new(Some(Box::new(on_reply))),
+        timer_id: Mutex::new(None),
+    });
+
+    // 超时
+    if let Some(timeout) = timeout {
+        let pending2 = pending.clone();
+        let caller2 = caller.clone();
+        let mut timer_id = pending.timer_id.lock();
+        *timer_id = Some(G_PERIODIC_TIMER.schedule_once(timeout, move || {
+            log::warn!("call_service timeout!!! caller={}", caller2.name());
+            pending2.reply(&caller2, Err(CallError::Timeout));
+        }));
+    }
+
+    // 请求
+    let caller2 = caller.clone();
+    let target2 = target.clone();
+    let req = Mutex::new(Some(req));
+    target.run_in_service(Box::new(move || {
+        if let Some(req) = req.lock().take() {
+            let result = target2.get_handle().call_handlers.call::<Req, Resp>(req);
+            if let Err(err) = &result {
+                log::error!(
+                    "call_service failed!!! caller={} target={} error: {}",
+                    caller2.name(),
+                    target2.name(),
+                    err
+                );
+            }
+            pending.reply(&caller2, result);
+        }
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{proc_service_ready, start_service, NodeState, ServiceHandle};
+    use spdlog::get_current_tid;
+    use std::time::Duration;
+
+    struct TestService {
+        name: String,
+        handle: ServiceHandle,
+    }
+
+    impl ServiceRs for TestService {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn get_handle(&self) -> &ServiceHandle {
+            &self.handle
+        }
+
+        fn conf(&self) {}
+
+        fn run_in_service(&self, cb: Box<dyn FnOnce() + Send + Sync>) {
+            self.handle.run_in_service(cb);
+        }
+
+        fn is_in_service_thread(&self) -> bool {
+            self.handle.is_in_service_thread()
+        }
+
+        fn join(&self) {
+            self.handle.join_service();
+        }
+    }
+
+    fn spawn_service(id: u64, name: &str) -> &'static Arc<TestService> {
+        let srv: &'static Arc<TestService> = Box::leak(Box::new(Arc::new(TestService {
+            name: name.to_owned(),
+            handle: ServiceHandle::new(id, NodeState::Idle),
+        })));
+        let ready_pair = start_service(srv.as_ref(), name, || {});
+        assert!(proc_service_ready(srv.as_ref(), ready_pair));
+        srv
+    }
+
+    fn wait_until<F: Fn() -> bool>(f: F) {
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while !f() {
+            assert!(std::time::Instant::now() < deadline, "wait timeout");
+            std::thread::sleep(Duration::from_millis(2));
+        }
+    }
+
+    #[test]
+    fn replies_land_on_caller_thread() {
+        let caller = spawn_service(2001, "caller");
+        let target = spawn_service(2002, "target");
+
+        let target_tid = target.get_handle().tid();
+        target
+            .get_handle()
+            .register_call_handler(move |x: u32| (x * 2, get_current_tid()));
+
+        const N: u32 = 300;
+        let replies = Arc::new(Mutex::new(Vec::new()));
+        let replies2 = replies.clone();
+        caller.run_in_service(Box::new(move || {
+            for i in 0..N {
+                let replies3 = replies2.clone();
+                call_service(
+                    caller,
+                    target,
+                    i,
+                    Some(Duration::from_secs(5)),
+                    move |ret: Result<(u32, u64), CallError>| {
+                        let (doubled, handler_tid) = ret.unwrap();
+                        assert_eq!(doubled, i * 2);
+                        replies3.lock().push((handler_tid, get_current_tid()));
+                    },
+                );
+            }
+        }));
+
+        wait_until(|| replies.lock().len() == N as usize);
+        let caller_tid = caller.get_handle().tid();
+        for (handler_tid, reply_tid) in replies.lock().iter() {
+            assert_eq!(*handler_tid, target_tid);
+            assert_eq!(*reply_tid, caller_tid);
+        }
+
+        // 未注册的 (Req, Resp)
+        let no_handler = Arc::new(Mutex::new(None));
+        let no_handler2 = no_handler.clone();
+        call_service(
+            caller,
+            target,
+            "ping".to_owned(),
+            None,
+            move |ret: Result<u32, _>| {
+                *no_handler2.lock() = Some(ret);
+            },
+        );
+        wait_until(|| no_handler.lock().is_some());
+        assert_eq!(no_handler.lock().take(), Some(Err(CallError::NoHandler)));
+
+        // 超时：只回调一次，迟到的应答被忽略
+        target.get_handle().register_call_handler(|ms: u64| {
+            std::thread::sleep(Duration::from_millis(ms));
+            ms
+        });
+        let timeouts = Arc::new(Mutex::new(Vec::new()));
+        let timeouts2 = timeouts.clone();
+        call_service(
+            caller,
+            target,
+            200_u64,
+            Some(Duration::from_millis(20)),
+            move |ret: Result<u64, CallError>| {
+                timeouts2.lock().push(ret);
+            },
+        );
+        std::thread::sleep(Duration::from_millis(400));
+        assert_eq!(*timeouts.lock(), vec![Err(CallError::Timeout)]);
+
+        for srv in [caller, target] {
+            srv.get_handle().quit_service();
+            srv.join();
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//!
+//! Common Library: service channel -- 发往 service 的类型化消息通道
+//!
+//! ServiceSender 可在任意线程发送，ServiceReceiver 设置 handler 后在目标 service 线程中逐条处理;
+//! 未设置 handler 时消息保留在通道中，可直接 try_recv（便于不启动 service 线程的单元测试）
+//!
+
+use crossbeam::channel;
+use parking_lot::RwLock;
+use std::sync::Arc;
+
+use super::{ServiceHandle, ServiceQueue};
+
+type MessageHandlerFn<T> = Arc<dyn Fn(T) + Send + Sync>;
+
+/// 通道共享状态
+pub struct ServiceChannel<T: Send + 'static> {
+    tx: channel::Sender<T>,
+    rx: channel::Receiver<T>,
+    handler: RwLock<Option<MessageHandlerFn<T>>>,
+    srv: Arc<ServiceQueue>, // 目标 service 的任务队列
+}
+
+impl<T: Send + 'static> ServiceChannel<T> {
+    /// 创建发往 handle 所属 service 的通道
+    pub fn make(handle: &ServiceHandle) -> (ServiceSender<T>, ServiceReceiver<T>) {
+        let (tx, rx) = channel::unbounded::<T>();
+        let chan = Arc::new(Self {
+            tx,
+            rx,
+            handler: RwLock::new(None),
+            srv: handle.queue(),
+        });
+        (
+            ServiceSender { chan: chan.clone() },
+            ServiceReceiver { chan },
+        )
+    }
+
+    /// 把通道中的消息逐条交给 handler，返回处理的消息数；没有 handler 时不处理
+    fn pump(&self) -> usize {
+        let handler = match self.handler.read().clone() {
+            Some(f) => f,
+            None => return 0,
+        };
+
+        let mut count = 0_usize;
+        while let Ok(msg) = self.rx.try_recv() {
+            (handler)(msg);
+            count += 1;
+        }
+        count
+    }
+
+    /// 经 run_in_service 通知目标 service 处理：在 service 线程中直接处理，排空中的 service 不再接收
+    fn post_pump(self: &Arc<Self>) {
+        let chan = self.clone();
+        self.srv.run_in_service(Box::new(move || {
+            chan.pump();
+        }));
+    }
+}
+
+/// 发送端，clone 后发往同一个通道
+pub struct ServiceSender<T: Send + 'static> {
+    chan: Arc<ServiceChannel<T>>,
+}
+
+impl<T: Send + 'static> Clone for ServiceSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            chan: self.chan.clone(),
+        }
+    }
+}
+
+impl<T: Send + 'static> ServiceSender<T> {
+    /// 消息入队，并通知目标 service 线程处理
+    pub fn send(&self, msg: T) {
+        // chan 持有 rx，发送不会失败
+        self.chan.tx.send(msg).ok();
+        self.chan.post_pump();
+    }
+}
+
+/// 接收端
+pub struct ServiceReceiver<T: Send + 'static> {
+    chan: Arc<ServiceChannel<T>>,
+}
+
+impl<T: Send + 'static> ServiceReceiver<T> {
+    /// 设置消息处理函数（在目标 service 线程中执行），已在通道中的消息也会被处理
+    pub fn set_handler<F>(&self, f: F)
+    where
+        F: Fn(T) + Send + Sync + 'static,
+    {
+        {
+            let mut handler_mut = self.chan.handler.write();
+            (*handler_mut) = Some(Arc::new(f));
+        }
+        if !self.chan.rx.is_empty() {
+            self.chan.post_pump();
+        }
+    }
+
+    /// 直接取出一条消息（不经过 handler）
+    #[inline(always)]
+    pub fn try_recv(&self) -> Option<T> {
+        self.chan.rx.try_recv().ok()
+    }
+
+    /// 通道中等待处理的消息数
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.chan.rx.len()
+    }
+
+    ///
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.chan.rx.is_empty()
+    }
+
+    /// 在当前线程中把通道中的消息交给 handler，返回处理的消息数
+    pub fn pump(&self) -> usize {
+        self.chan.pump()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NodeState;
+    use parking_lot::Mutex;
+
+    #[test]
+    fn messages_run_in_service_queue() {
+        // 没有 service 线程：消息在 dispatch_tasks 时处理
+        let handle = ServiceHandle::new(1, NodeState::Run);
+        let (sender, receiver) = ServiceChannel::<u32>::make(&handle);
+
+        // 没有 handler 时消息保留在通道中
+        sender.send(1);
+        handle.dispatch_tasks(usize::MAX);
+        assert_eq!(receiver.len(), 1);
+        assert_eq!(receiver.try_recv(), Some(1));
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received2 = received.clone();
+        receiver.set_handler(move |msg| received2.lock().push(msg));
+
+        let sender2 = sender.clone();
+        std::thread::spawn(move || {
+            for i in 0..100 {
+                sender2.send(i);
+            }
+        })
+        .join()
+        .unwrap();
+        assert!(received.lock().is_empty());
+
+        handle.dispatch_tasks(usize::MAX);
+        assert_eq!(*received.lock(), (0..100).collect::<Vec<_>>());
+        assert!(receiver.is_empty());
+
+        // 排空中的 service 不再接收 pump 任务，消息留在通道中
+        assert_eq!(handle.set_state(NodeState::Draining), Ok(()));
+        sender.send(100);
+        assert_eq!(handle.queue_depth(), 0);
+        assert_eq!(receiver.len(), 1);
+    }
+}
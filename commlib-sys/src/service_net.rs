@@ -16,13 +16,17 @@ pub mod net_packet_pool;
 pub use net_packet_pool::{take_large_packet, take_packet, take_small_packet};
 pub use net_packet_pool::{NetPacketGuard, NetPacketPool};
 
+///
+pub mod packet_bytes;
+pub use packet_bytes::{NetPacketGuardExt, PacketBytes};
+
 ///
 pub mod packet_receiver;
-pub use packet_receiver::PacketReceiver;
+pub use packet_receiver::{PacketReceiver, MAX_PACKET_SIZE};
 
 ///
 pub mod conn_id;
-pub use conn_id::ConnId;
+pub use conn_id::{ConnId, ConnIdAllocator, ConnIdError};
 
 ///
 pub mod tcp_listener_id;
@@ -30,12 +34,53 @@ pub use tcp_listener_id::TcpListenerId;
 
 ///
 pub mod net_proxy;
-pub use net_proxy::NetProxy;
+pub use net_proxy::{ConnStats, NetProxy, PanicPolicy, SendError, DEFAULT_MAX_PENDING_SENDS};
+
+///
+pub mod encryptor;
+pub use encryptor::{
+    EncryptSession, Encryptor, EncryptorFactory, XorEncryptor, ENCRYPT_HANDSHAKE_CMD,
+};
+
+///
+pub mod compression;
+pub use compression::{
+    CompressionMiddleware, CompressionSession, COMPRESSION_ACCEPT_CMD, COMPRESSION_ADVERTISE_CMD,
+    DEFAULT_COMPRESSION_THRESHOLD,
+};
+
+///
+pub mod close_reason;
+pub use close_reason::CloseReason;
 
 ///
 pub mod tcp_handler;
 pub use tcp_handler::TcpHandler;
 
+///
+pub mod send_queue;
+pub use send_queue::{HighWatermarkFn, SendQueueEvent, SendQueueLimit, SendQueueState};
+
+///
+pub mod rate_limit;
+pub use rate_limit::{
+    dispatch_rate_limited, RateAdmit, RateLimit, RateLimitPolicy, RateLimitState, RateLimitStats,
+};
+
+///
+pub mod ws_framer;
+pub use ws_framer::{
+    encode_frame, handle_ws_message, parse_upgrade_request, ws_accept_key, WsDeframe, WsFramer,
+    WsOpcode,
+};
+
+///
+pub mod heartbeat;
+pub use heartbeat::{
+    handle_heartbeat, make_heartbeat_packet, peek_heartbeat, start_heartbeat, Heartbeat,
+    HeartbeatConfig, HEARTBEAT_PING_CMD, HEARTBEAT_PONG_CMD,
+};
+
 ///
 pub mod tcp_conn;
 pub use tcp_conn::TcpConn;
@@ -44,10 +89,29 @@ pub use tcp_conn::TcpConn;
 pub mod tcp_server;
 pub use tcp_server::TcpServer;
 
+///
+pub mod listener_handle;
+pub use listener_handle::ListenerHandle;
+
 ///
 pub mod tcp_client;
 pub use tcp_client::TcpClient;
 
+///
+pub mod reconnect_policy;
+pub use reconnect_policy::ReconnectPolicy;
+
+///
+pub mod kcp;
+pub use kcp::{kcp_clock_ms, kcp_peek_conv, Kcp, KcpConfig, KcpError, KCP_OVERHEAD};
+
+///
+pub mod udp_conn;
+pub use udp_conn::{
+    close_udp_conn, handle_udp_message_event, on_udp_message, schedule_udp_idle_check,
+    schedule_udp_kcp_update, update_udp_kcp, UdpConn, UdpServer, UdpSessions,
+};
+
 ///
 pub mod server_status;
 pub use server_status::ServerStatus;
@@ -67,6 +131,16 @@ pub use service_net_impl::*;
 pub mod network_impl;
 pub use network_impl::*;
 
+///
+pub mod session_migration;
+pub use session_migration::{
+    decode_forwarded, encode_forwarded, is_migration_cmd, restore_session, serialize_session,
+    MigratableComponent, MigratableSession, MigrationError, MigrationMsg, OwnershipToken,
+    PacketDisposition, RouteUpdate, SessionGate, SessionMigrator, SessionRegistry,
+    MIGRATION_ABORT_CMD, MIGRATION_COMMITTED_CMD, MIGRATION_COMMIT_CMD, MIGRATION_OFFER_CMD,
+    MIGRATION_READY_CMD, MIGRATION_ROUTE_ACK_CMD, MIGRATION_ROUTE_CMD,
+};
+
 ///
 pub mod connect_to_server_helper;
 pub use connect_to_server_helper::*;
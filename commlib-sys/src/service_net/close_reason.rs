@@ -0,0 +1,17 @@
+use bytemuck::NoUninit;
+
+/// 连接关闭原因
+#[derive(Debug, PartialEq, Eq, Copy, Clone, NoUninit)]
+#[repr(u8)]
+pub enum CloseReason {
+    None = 0,          // 未关闭
+    Normal,            // 正常关闭（本端或对端）
+    HandlerPanic,      // 包处理函数 panic，仅关闭该连接
+    IdleTimeout,       // 超时未收到数据（udp session）
+    SendQueueOverflow, // 待发送数据超过 hard limit（对端不读取）
+    HandshakeFailed,   // 加密连接握手前收到非握手包
+    PacketTooLarge,    // 收到的包长度超过 max_packet_size
+    RateLimited,       // 收包速率超过限制（RateLimitPolicy::CloseConn 或缓存满）
+    DecompressFailed,  // 协商压缩后收到无法解压的包体
+    DeadLink,          // kcp 分片重传次数达到上限（对端不可达）
+}
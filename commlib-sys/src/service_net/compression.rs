@@ -0,0 +1,175 @@
+//! Commlib: CompressionMiddleware
+//! NetProxy 的包体压缩：协商之后超过阈值的包体用 lz4 压缩（压缩后更大则不压缩），并在包头中标记.
+//! 协商：server 在连接建立后发送 COMPRESSION_ADVERTISE_CMD，启用了压缩的 client 回复 COMPRESSION_ACCEPT_CMD，
+//! server 收到后同样回复 COMPRESSION_ACCEPT_CMD. 每个方向在本端的协商包发出之后才发送压缩包，
+//! 对端收到该协商包之后才接受压缩包，未协商时收到压缩包视为错误
+//!
+//! 线路格式：压缩标记为包头 4 字节前导长度字段的最高位（PKT_COMPRESSED_FLAG），长度字段的其余位为
+//! 压缩后的包长度，包头长度不变. 压缩包体为 4 字节原始长度 + lz4 数据. 加密在压缩之后进行.
+//! 只有发送包头为 4 字节长度字段的 PacketType（Server、Client 发往客户端）会发送压缩包，
+//! 客户端发往服务器的 2 字节长度字段和 WS 包头没有标记位，这些方向总是不压缩
+
+use super::{CmdId, ConnId};
+
+/// 压缩协商：server 通知支持压缩（包体为空）
+pub const COMPRESSION_ADVERTISE_CMD: CmdId = 0xFFFC;
+
+/// 压缩协商：client 选择启用压缩，server 确认（包体为空）
+pub const COMPRESSION_ACCEPT_CMD: CmdId = 0xFFFB;
+
+/// 默认压缩阈值：包体超过该长度才压缩
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 512;
+
+/// 连接的压缩协商状态
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressionSession {
+    pub advertised: bool, // server 已发送 advertise
+    pub send: bool,       // 可以发送压缩包
+    pub recv: bool,       // 可以接收压缩包
+}
+
+/// 包体压缩：记录每条连接的协商状态，发送时压缩，接收时解压
+pub struct CompressionMiddleware {
+    threshold: usize,
+    sessions: hashbrown::HashMap<ConnId, CompressionSession>,
+}
+
+impl CompressionMiddleware {
+    /// threshold: 包体超过该长度才压缩
+    pub fn new(threshold: usize) -> Self {
+        Self {
+            threshold,
+            sessions: hashbrown::HashMap::new(),
+        }
+    }
+
+    ///
+    #[inline(always)]
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    ///
+    pub fn session(&self, hd: ConnId) -> CompressionSession {
+        self.sessions.get(&hd).copied().unwrap_or_default()
+    }
+
+    ///
+    pub fn session_mut(&mut self, hd: ConnId) -> &mut CompressionSession {
+        self.sessions.entry(hd).or_default()
+    }
+
+    /// 连接断开，清除协商状态
+    pub fn remove(&mut self, hd: ConnId) {
+        self.sessions.remove(&hd);
+    }
+
+    /// 发送方向已启用且压缩后更小时返回 (压缩的包体, 比原包体节省的字节数)，否则返回 None（发送原包体）
+    pub fn compress(&self, hd: ConnId, body: &[u8]) -> Option<(Vec<u8>, usize)> {
+        if !self.session(hd).send {
+            return None;
+        }
+        let data = compress_body(body, self.threshold)?;
+        let saved = body.len() - data.len();
+        Some((data, saved))
+    }
+
+    /// 解压包头标记为压缩的包体，接收方向没有启用时返回错误
+    pub fn decompress(&self, hd: ConnId, data: &[u8], max_size: usize) -> Result<Vec<u8>, String> {
+        if !self.session(hd).recv {
+            return Err("compressed packet before negotiation".to_owned());
+        }
+        decompress_body(data, max_size)
+    }
+}
+
+impl Default for CompressionMiddleware {
+    fn default() -> Self {
+        Self::new(DEFAULT_COMPRESSION_THRESHOLD)
+    }
+}
+
+/// 压缩包体（4 字节原始长度 + lz4 数据），不超过 threshold 或压缩后不更小时返回 None
+pub fn compress_body(body: &[u8], threshold: usize) -> Option<Vec<u8>> {
+    if body.len() <= threshold {
+        return None;
+    }
+    let compressed = lz4_flex::compress_prepend_size(body);
+    if compressed.len() < body.len() {
+        Some(compressed)
+    } else {
+        None
+    }
+}
+
+/// 解压 compress_body 的结果，解压后的长度超过 max_size 返回错误
+pub fn decompress_body(data: &[u8], max_size: usize) -> Result<Vec<u8>, String> {
+    // 先检查原始长度，避免按伪造的长度分配内存
+    if data.len() < 4 {
+        return Err("lz4 body too short".to_owned());
+    }
+    let size = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    if size > max_size {
+        return Err(format!("decompressed size {} > max {}", size, max_size));
+    }
+    lz4_flex::decompress_size_prepended(data).map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compress_round_trip() {
+        let large = b"world snapshot ".repeat(100);
+        let data = compress_body(&large, DEFAULT_COMPRESSION_THRESHOLD).unwrap();
+        assert!(data.len() < large.len() / 4);
+        assert_eq!(decompress_body(&data, 4096).unwrap(), large);
+
+        // 不超过阈值、压缩后更大的包体不压缩
+        assert!(compress_body(b"hello", DEFAULT_COMPRESSION_THRESHOLD).is_none());
+
+        let mut seed = 0x2545_F491_u32;
+        let random: Vec<u8> = (0..1024)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as u8
+            })
+            .collect();
+        assert!(compress_body(&random, 16).is_none());
+    }
+
+    #[test]
+    fn decompress_rejects_invalid() {
+        let large = vec![7_u8; 8192];
+        let data = compress_body(&large, DEFAULT_COMPRESSION_THRESHOLD).unwrap();
+        assert!(decompress_body(&data, 4096).is_err());
+        assert!(decompress_body(&data, 8192).is_ok());
+
+        assert!(decompress_body(b"", 4096).is_err());
+        assert!(decompress_body(b"ab", 4096).is_err());
+        assert!(decompress_body(b"\x10\x00\x00\x00\xff", 4096).is_err());
+    }
+
+    #[test]
+    fn per_conn_session() {
+        let mut compression = CompressionMiddleware::default();
+        let hd = ConnId::from(1);
+        let body = vec![1_u8; 1024];
+        assert!(compression.compress(hd, &body).is_none());
+        assert!(compression.decompress(hd, &body, 4096).is_err());
+
+        compression.session_mut(hd).send = true;
+        let (data, saved) = compression.compress(hd, &body).unwrap();
+        assert_eq!(saved, body.len() - data.len());
+        assert!(compression.compress(ConnId::from(2), &body).is_none());
+
+        compression.session_mut(hd).recv = true;
+        assert_eq!(compression.decompress(hd, &data, 4096), Ok(body));
+
+        compression.remove(hd);
+        assert_eq!(compression.session(hd), CompressionSession::default());
+    }
+}
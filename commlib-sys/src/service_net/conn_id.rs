@@ -1,30 +1,84 @@
 use bytemuck::NoUninit;
 use std::net::SocketAddr;
 
-use crate::ServiceNetRs;
+use crate::{ServiceNetRs, ServiceRs};
 
-use super::PacketType;
+use super::{handle_close_conn_event, CloseReason, PacketType};
 
-/// Connection id
-#[derive(Copy, Clone, PartialEq, Eq, std::hash::Hash, NoUninit)]
+/// Connection id：低 32 位为槽位 index，高 32 位为 generation。
+/// 连接关闭后 generation 递增，持有旧 hd 的回调不会误发到复用槽位的新连接
+#[derive(Debug, Copy, Clone, PartialEq, Eq, std::hash::Hash, NoUninit)]
 #[repr(C)]
 pub struct ConnId {
-    pub id: usize,
-    // TODO: add self as payload to EndPoint
+    pub id: u64,
 }
 
+/// ConnId 查找错误
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConnIdError {
+    StaleConnId(ConnId), // 槽位已被新连接复用（或已关闭）
+    NotFound(ConnId),
+}
+
+impl std::fmt::Display for ConnIdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConnIdError::StaleConnId(hd) => write!(f, "[hd={}] stale conn id", hd),
+            ConnIdError::NotFound(hd) => write!(f, "[hd={}] conn not found", hd),
+        }
+    }
+}
+
+impl std::error::Error for ConnIdError {}
+
 impl ConnId {
     ///
     #[inline(always)]
-    pub fn send(&self, srv_net: &ServiceNetRs, data: &[u8]) {
+    pub fn new(index: u32, generation: u32) -> Self {
+        Self {
+            id: ((generation as u64) << 32) | index as u64,
+        }
+    }
+
+    /// 迁移用：直接使用原始数值（不经过分配表，generation 为数值的高 32 位）
+    #[inline(always)]
+    pub fn from_raw(raw: usize) -> Self {
+        Self { id: raw as u64 }
+    }
+
+    ///
+    #[inline(always)]
+    pub fn raw(&self) -> u64 {
+        self.id
+    }
+
+    ///
+    #[inline(always)]
+    pub fn index(&self) -> u32 {
+        self.id as u32
+    }
+
+    ///
+    #[inline(always)]
+    pub fn generation(&self) -> u32 {
+        (self.id >> 32) as u32
+    }
+
+    ///
+    #[inline(always)]
+    pub fn send(&self, srv_net: &ServiceNetRs, data: &[u8]) -> Result<(), ConnIdError> {
         let hd = *self;
 
         // 在当前线程中加 read 锁取出 conn，以便尽快发送
-        let conn_opt = srv_net.get_conn(hd);
-        if let Some(conn) = conn_opt {
-            conn.send(data);
-        } else {
-            log::error!("[hd={}] send failed!!!", hd);
+        match srv_net.lookup_conn(hd) {
+            Ok(conn) => {
+                conn.send(data);
+                Ok(())
+            }
+            Err(err) => {
+                log::error!("[hd={}] send failed!!! error: {}", hd, err);
+                Err(err)
+            }
         }
     }
 
@@ -53,12 +107,47 @@ impl ConnId {
             log::error!("[hd={}] change pakcet type failed!!!", hd);
         }
     }
+
+    /// 关闭连接并触发 close_fn（local remove 不会产生断开事件）
+    pub fn close_with_reason(
+        &self,
+        srv_net: &ServiceNetRs,
+        reason: CloseReason,
+    ) -> Result<(), ConnIdError> {
+        let hd = *self;
+
+        // 在当前线程中加 read 锁取出 conn
+        match srv_net.lookup_conn(hd) {
+            Ok(conn) => {
+                conn.close_with_reason(reason);
+
+                // 总是投递到 srv_net 队列：调用方（如 NetProxy 分发包时）可能正持有 close_fn 需要的状态
+                let srv_net = conn.srv_net.clone();
+                conn.srv_net.get_handle().post(Box::new(move || {
+                    if let Some(conn) = srv_net.get_conn(hd) {
+                        handle_close_conn_event(srv_net.as_ref(), &conn);
+                    }
+                }));
+                Ok(())
+            }
+            Err(err) => {
+                log::error!(
+                    "[hd={}] close failed!!! reason={:?} error: {}",
+                    hd,
+                    reason,
+                    err
+                );
+                Err(err)
+            }
+        }
+    }
 }
 
+/// 同 ConnId::from_raw
 impl From<usize> for ConnId {
     #[inline(always)]
     fn from(raw: usize) -> Self {
-        Self { id: raw }
+        Self::from_raw(raw)
     }
 }
 
@@ -67,8 +156,157 @@ impl std::fmt::Display for ConnId {
     // 这个 trait 要求 `fmt` 使用与下面的函数完全一致的函数签名
     #[inline(always)]
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        // 仅将 self 的第一个元素写入到给定的输出流 `f`。返回 `fmt:Result`，此
-        // 结果表明操作成功或失败。注意 `write!` 的用法和 `println!` 很相似。
-        write!(f, "{}", self.id)
+        // index#generation
+        write!(f, "{}#{}", self.index(), self.generation())
+    }
+}
+
+struct ConnSlot {
+    generation: u32,
+    raw_id: Option<usize>, // None 表示空闲
+}
+
+/// ConnId 分配表：message io 的 resource id 映射到 (index, generation)，槽位复用时 generation 递增
+pub struct ConnIdAllocator {
+    slots: Vec<ConnSlot>,
+    free: Vec<u32>,
+    by_raw: hashbrown::HashMap<usize, ConnId>,
+}
+
+impl ConnIdAllocator {
+    ///
+    pub fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            by_raw: hashbrown::HashMap::new(),
+        }
+    }
+
+    /// 为 resource id 分配 ConnId，已分配则返回原值
+    pub fn alloc(&mut self, raw_id: usize) -> ConnId {
+        if let Some(hd) = self.by_raw.get(&raw_id) {
+            return *hd;
+        }
+
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                // generation 从 1 开始，与 from_raw 的小数值区分
+                self.slots.push(ConnSlot {
+                    generation: 1,
+                    raw_id: None,
+                });
+                (self.slots.len() - 1) as u32
+            }
+        };
+        let slot = &mut self.slots[index as usize];
+        slot.raw_id = Some(raw_id);
+
+        let hd = ConnId::new(index, slot.generation);
+        self.by_raw.insert(raw_id, hd);
+        hd
+    }
+
+    ///
+    #[inline(always)]
+    pub fn resolve(&self, raw_id: usize) -> Option<ConnId> {
+        self.by_raw.get(&raw_id).copied()
+    }
+
+    /// 检查 hd 是否仍有效，返回对应的 resource id
+    pub fn raw_id(&self, hd: ConnId) -> Result<usize, ConnIdError> {
+        match self.slots.get(hd.index() as usize) {
+            Some(slot) => match slot.raw_id {
+                Some(raw_id) if slot.generation == hd.generation() => Ok(raw_id),
+                _ if hd.generation() != 0 => Err(ConnIdError::StaleConnId(hd)),
+                _ => Err(ConnIdError::NotFound(hd)),
+            },
+            None => Err(ConnIdError::NotFound(hd)),
+        }
+    }
+
+    /// 连接关闭：generation 递增，槽位回收
+    pub fn release(&mut self, hd: ConnId) -> bool {
+        let raw_id = match self.raw_id(hd) {
+            Ok(raw_id) => raw_id,
+            Err(_) => return false,
+        };
+        self.by_raw.remove(&raw_id);
+
+        let slot = &mut self.slots[hd.index() as usize];
+        slot.raw_id = None;
+        slot.generation = slot.generation.wrapping_add(1).max(1);
+        self.free.push(hd.index());
+        true
+    }
+
+    /// 使用中的 ConnId 数量
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.by_raw.len()
+    }
+
+    ///
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.by_raw.is_empty()
+    }
+}
+
+impl Default for ConnIdAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generation_bumps_on_release() {
+        let mut ids = ConnIdAllocator::new();
+        let a = ids.alloc(100);
+        assert_eq!(ids.alloc(100), a);
+        assert_eq!(ids.raw_id(a), Ok(100));
+        assert_eq!(format!("{}", a), "0#1");
+
+        assert!(ids.release(a));
+        assert!(!ids.release(a));
+        assert_eq!(ids.resolve(100), None);
+
+        // 槽位复用，旧 hd 失效
+        let b = ids.alloc(200);
+        assert_eq!(b.index(), a.index());
+        assert_ne!(b, a);
+        assert_eq!(ids.raw_id(b), Ok(200));
+        assert_eq!(ids.raw_id(a), Err(ConnIdError::StaleConnId(a)));
+        assert_eq!(
+            ids.raw_id(ConnId::new(7, 1)),
+            Err(ConnIdError::NotFound(ConnId::new(7, 1)))
+        );
+    }
+
+    #[test]
+    fn send_with_stale_hd_fails() {
+        let srv_net = ServiceNetRs::new(0);
+        let old_hd = srv_net.alloc_conn_id(100);
+        srv_net.remove_conn(old_hd);
+
+        // 新连接复用槽位
+        let new_hd = srv_net.alloc_conn_id(101);
+        assert_eq!(new_hd.index(), old_hd.index());
+        assert_eq!(srv_net.resolve_conn_id(101), Some(new_hd));
+
+        assert_eq!(
+            old_hd.send(&srv_net, b"late"),
+            Err(ConnIdError::StaleConnId(old_hd))
+        );
+        assert_eq!(
+            old_hd.close_with_reason(&srv_net, CloseReason::Normal),
+            Err(ConnIdError::StaleConnId(old_hd))
+        );
+        assert!(srv_net.lookup_conn(old_hd).is_err());
     }
 }
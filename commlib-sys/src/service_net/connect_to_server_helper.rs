@@ -2,10 +2,11 @@ use std::sync::Arc;
 
 use crate::{ServiceNetRs, ServiceRs};
 
-use super::create_tcp_client;
-use super::{ConnId, NetPacketGuard};
+use super::{create_tcp_client_with_limit, create_tcp_client_with_reconnect};
+use super::{ConnId, NetPacketGuard, ReconnectPolicy, SendQueueLimit, TcpClient, TcpConn};
 
-///
+/// 对端推送加密握手时，在 conn_fn 中调用 NetProxy::on_outgoing_conn(hd, true)，
+/// 握手包由 NetProxy::on_net_packet 消费，之后的包体自动解密再交给处理函数
 pub fn connect_to_tcp_server<T, C, P, S>(
     srv: &Arc<T>,
     name: &str,
@@ -17,12 +18,55 @@ pub fn connect_to_tcp_server<T, C, P, S>(
 ) -> Option<ConnId>
 where
     T: ServiceRs + 'static,
-    C: Fn(ConnId) + Send + Sync + 'static,
+    C: Fn(Arc<TcpConn>) + Send + Sync + 'static,
     P: Fn(ConnId, NetPacketGuard) + Send + Sync + 'static,
     S: Fn(ConnId) + Send + Sync + 'static,
+{
+    let close_fn = move |conn: Arc<TcpConn>| close_fn(conn.hd);
+    connect_to_tcp_server_ex(srv, name, raddr, conn_fn, pkt_fn, close_fn, srv_net)
+}
+
+/// close_fn 参数为已关闭的 conn，仍可读取连接信息
+pub fn connect_to_tcp_server_ex<T, C, P, S>(
+    srv: &Arc<T>,
+    name: &str,
+    raddr: &str,
+    conn_fn: C,
+    pkt_fn: P,
+    close_fn: S,
+    srv_net: &Arc<ServiceNetRs>,
+) -> Option<ConnId>
+where
+    T: ServiceRs + 'static,
+    C: Fn(Arc<TcpConn>) + Send + Sync + 'static,
+    P: Fn(ConnId, NetPacketGuard) + Send + Sync + 'static,
+    S: Fn(Arc<TcpConn>) + Send + Sync + 'static,
+{
+    let limit = SendQueueLimit::default();
+    connect_to_tcp_server_with_limit(srv, name, raddr, conn_fn, pkt_fn, close_fn, limit, srv_net)
+}
+
+/// conn 使用 send_limit 限制发送队列，超过 hard limit 时以 CloseReason::SendQueueOverflow 关闭
+pub fn connect_to_tcp_server_with_limit<T, C, P, S>(
+    srv: &Arc<T>,
+    name: &str,
+    raddr: &str,
+    conn_fn: C,
+    pkt_fn: P,
+    close_fn: S,
+    send_limit: SendQueueLimit,
+    srv_net: &Arc<ServiceNetRs>,
+) -> Option<ConnId>
+where
+    T: ServiceRs + 'static,
+    C: Fn(Arc<TcpConn>) + Send + Sync + 'static,
+    P: Fn(ConnId, NetPacketGuard) + Send + Sync + 'static,
+    S: Fn(Arc<TcpConn>) + Send + Sync + 'static,
 {
     //
-    let cli = create_tcp_client(srv, name, raddr, conn_fn, pkt_fn, close_fn, srv_net);
+    let cli = create_tcp_client_with_limit(
+        srv, name, raddr, conn_fn, pkt_fn, close_fn, send_limit, srv_net,
+    );
     log::info!(
         "[connect_to_tcp_server] start connect to {} -- id<{}> ... ",
         cli.id,
@@ -48,3 +92,50 @@ where
         }
     }
 }
+
+/// 按 policy 断线重连，返回 client 便于查询重连次数或手动关闭
+/// close_fn 第二个参数为 final：重连次数用尽时为 true，之后不再重连
+pub fn connect_to_tcp_server_with_reconnect<T, C, P, S>(
+    srv: &Arc<T>,
+    name: &str,
+    raddr: &str,
+    conn_fn: C,
+    pkt_fn: P,
+    close_fn: S,
+    policy: ReconnectPolicy,
+    srv_net: &Arc<ServiceNetRs>,
+) -> Arc<TcpClient>
+where
+    T: ServiceRs + 'static,
+    C: Fn(Arc<TcpConn>) + Send + Sync + 'static,
+    P: Fn(ConnId, NetPacketGuard) + Send + Sync + 'static,
+    S: Fn(ConnId, bool) + Send + Sync + 'static,
+{
+    let cli = create_tcp_client_with_reconnect(
+        srv, name, raddr, conn_fn, pkt_fn, close_fn, policy, srv_net,
+    );
+    log::info!(
+        "[connect_to_tcp_server_with_reconnect] start connect to {} -- id<{}> ... ",
+        raddr,
+        cli.id
+    );
+
+    // 连接失败时由 policy 驱动重连
+    match cli.connect() {
+        Ok(hd) => {
+            log::info!(
+                "[connect_to_tcp_server_with_reconnect][hd={}] client added to service net.",
+                hd
+            );
+        }
+        Err(err) => {
+            log::error!(
+                "[connect_to_tcp_server_with_reconnect] connect failed!!! error: {}",
+                err
+            );
+        }
+    }
+
+    //
+    cli
+}
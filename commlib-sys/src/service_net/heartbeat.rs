@@ -0,0 +1,229 @@
+//! Commlib: Heartbeat
+//! tcp 连接心跳：设置了 heartbeat_interval 的一端（通常是 connector）定时发送 ping，
+//! 对端收到后自动回复 pong；超过 idle_timeout 没有收到任何数据则以 CloseReason::IdleTimeout 关闭连接.
+//! ping/pong 只用于服务器内部包（PacketType::Server），在 srv_net 中处理，不会交给 pkt_fn
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::{Clock, ServiceNetRs};
+
+use super::net_packet::get_packet_header_size;
+use super::udp_conn::IdleTracker;
+use super::{handle_close_conn_event, take_small_packet};
+use super::{CloseReason, CmdId, ConnId, NetPacket, NetPacketGuard, PacketType, TcpConn};
+
+/// 心跳 ping 协议号（保留）
+pub const HEARTBEAT_PING_CMD: CmdId = 0xFFFE;
+
+/// 心跳 pong 协议号（保留）
+pub const HEARTBEAT_PONG_CMD: CmdId = 0xFFFD;
+
+/// 心跳配置，默认不发送心跳也不检查空闲
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeartbeatConfig {
+    pub heartbeat_interval: Option<Duration>, // 发送 ping 的间隔
+    pub idle_timeout: Option<Duration>,       // 超过该时间没有收到数据则关闭连接
+}
+
+impl HeartbeatConfig {
+    ///
+    pub fn new(heartbeat_interval: Duration, idle_timeout: Duration) -> Self {
+        Self {
+            heartbeat_interval: Some(heartbeat_interval),
+            idle_timeout: Some(idle_timeout),
+        }
+    }
+
+    ///
+    #[inline(always)]
+    pub fn is_disabled(&self) -> bool {
+        self.heartbeat_interval.is_none() && self.idle_timeout.is_none()
+    }
+
+    /// 记录 conn 最后收到数据的时间，没有 idle_timeout 时永不超时
+    pub fn idle_tracker(&self) -> IdleTracker {
+        IdleTracker::new(self.idle_timeout.unwrap_or(Duration::MAX))
+    }
+}
+
+/// 心跳包类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Heartbeat {
+    Ping,
+    Pong,
+}
+
+impl Heartbeat {
+    ///
+    #[inline(always)]
+    pub fn cmd(&self) -> CmdId {
+        match self {
+            Heartbeat::Ping => HEARTBEAT_PING_CMD,
+            Heartbeat::Pong => HEARTBEAT_PONG_CMD,
+        }
+    }
+}
+
+/// 查看收到的完整包（尚未 decode）是否为心跳包，不消耗数据
+pub fn peek_heartbeat(pkt: &NetPacket) -> Option<Heartbeat> {
+    if pkt.packet_type() != PacketType::Server {
+        return None;
+    }
+
+    // 心跳包没有包体: leading(4) + cmd(2)
+    let data = pkt.peek();
+    let header_size = get_packet_header_size(PacketType::Server);
+    if data.len() != header_size {
+        return None;
+    }
+    match u16::from_be_bytes([data[4], data[5]]) {
+        HEARTBEAT_PING_CMD => Some(Heartbeat::Ping),
+        HEARTBEAT_PONG_CMD => Some(Heartbeat::Pong),
+        _ => None,
+    }
+}
+
+/// 生成已编码的心跳包
+pub fn make_heartbeat_packet(heartbeat: Heartbeat) -> NetPacketGuard {
+    let mut pkt = take_small_packet();
+    pkt.set_type(PacketType::Server);
+    pkt.set_cmd(heartbeat.cmd());
+    pkt.set_body(&[]);
+    pkt.encode_packet(ConnId::from_raw(0), &hashbrown::HashMap::new());
+    pkt
+}
+
+/// 处理收到的心跳包：ping 回复 pong，pong 只用于刷新活跃时间（在 srv_net 中运行）
+pub fn handle_heartbeat(conn: &Arc<TcpConn>, heartbeat: Heartbeat) {
+    log::trace!("[hd={}] recv heartbeat {:?}", conn.hd, heartbeat);
+    if heartbeat == Heartbeat::Ping {
+        let pkt = make_heartbeat_packet(Heartbeat::Pong);
+        conn.send(pkt.peek());
+    }
+}
+
+/// 按 conn 的心跳配置启动定时器，定时器复用 srv_net 的 timer，conn 移除后自动停止（在 srv_net 中运行）
+pub fn start_heartbeat(srv_net: &Arc<ServiceNetRs>, conn: &Arc<TcpConn>) {
+    if let Some(interval) = conn.heartbeat.heartbeat_interval {
+        schedule_heartbeat_ping(srv_net, conn.hd, interval);
+    }
+    if let Some(idle_timeout) = conn.heartbeat.idle_timeout {
+        schedule_tcp_idle_check(srv_net, conn.hd, idle_timeout);
+    }
+}
+
+fn schedule_heartbeat_ping(srv_net: &Arc<ServiceNetRs>, hd: ConnId, interval: Duration) {
+    let srv_net2 = srv_net.clone();
+    let delay_ms = std::cmp::max(1, interval.as_millis() as u64);
+    Clock::set_timeout(srv_net.as_ref(), delay_ms, move || {
+        if let Some(conn) = srv_net2.get_conn(hd) {
+            if conn.closed.load(atomic::Ordering::Relaxed) {
+                return;
+            }
+
+            // 加密的客户端包没有心跳
+            if conn.packet_type() == PacketType::Server {
+                let pkt = make_heartbeat_packet(Heartbeat::Ping);
+                conn.send(pkt.peek());
+            }
+            schedule_heartbeat_ping(&srv_net2, hd, interval);
+        }
+    });
+}
+
+fn schedule_tcp_idle_check(srv_net: &Arc<ServiceNetRs>, hd: ConnId, delay: Duration) {
+    let srv_net2 = srv_net.clone();
+    let delay_ms = std::cmp::max(1, delay.as_millis() as u64);
+    Clock::set_timeout(srv_net.as_ref(), delay_ms, move || {
+        if let Some(conn) = srv_net2.get_conn(hd) {
+            match conn.idle.remaining(Instant::now()) {
+                Some(remaining) => {
+                    schedule_tcp_idle_check(&srv_net2, hd, remaining);
+                }
+                None => {
+                    log::info!(
+                        "[hd={}] tcp conn idle for {:?}, close it",
+                        hd,
+                        conn.idle.timeout()
+                    );
+                    conn.close_with_reason(CloseReason::IdleTimeout);
+                    handle_close_conn_event(srv_net2.as_ref(), &conn);
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service_net::packet_receiver::PacketResult;
+    use crate::service_net::{PacketReceiver, MAX_PACKET_SIZE};
+
+    fn receive(data: &[u8]) -> NetPacketGuard {
+        let mut pkt = take_small_packet();
+        pkt.set_type(PacketType::Server);
+        let receiver = PacketReceiver::with_max_packet_size(pkt, MAX_PACKET_SIZE);
+        match receiver.read(data.as_ptr(), data.len()) {
+            PacketResult::Ready((pkt, consumed)) => {
+                assert_eq!(consumed, data.len());
+                pkt
+            }
+            _ => panic!("packet not ready"),
+        }
+    }
+
+    #[test]
+    fn heartbeat_packets() {
+        assert!(HeartbeatConfig::default().is_disabled());
+        let config = HeartbeatConfig::new(Duration::from_secs(5), Duration::from_secs(15));
+        assert!(!config.is_disabled());
+
+        for heartbeat in [Heartbeat::Ping, Heartbeat::Pong] {
+            let pkt = make_heartbeat_packet(heartbeat);
+            assert_eq!(pkt.peek().len(), 6);
+
+            let pkt = receive(pkt.peek());
+            assert_eq!(peek_heartbeat(&pkt), Some(heartbeat));
+        }
+
+        // 普通包、带包体的保留协议号、客户端包都不是心跳包
+        let mut pkt = take_small_packet();
+        pkt.set_type(PacketType::Server);
+        pkt.set_cmd(1001);
+        pkt.set_body(&[]);
+        pkt.encode_packet(ConnId::from_raw(0), &hashbrown::HashMap::new());
+        assert_eq!(peek_heartbeat(&receive(pkt.peek())), None);
+
+        let mut pkt = take_small_packet();
+        pkt.set_type(PacketType::Server);
+        pkt.set_cmd(HEARTBEAT_PING_CMD);
+        pkt.set_body(b"ping");
+        pkt.encode_packet(ConnId::from_raw(0), &hashbrown::HashMap::new());
+        assert_eq!(peek_heartbeat(&receive(pkt.peek())), None);
+
+        let mut pkt = receive(make_heartbeat_packet(Heartbeat::Ping).peek());
+        pkt.set_type(PacketType::Client);
+        assert_eq!(peek_heartbeat(&pkt), None);
+    }
+
+    #[test]
+    fn idle_tracker() {
+        // 没有 idle_timeout 时永不超时
+        let idle = HeartbeatConfig::default().idle_tracker();
+        let now = Instant::now();
+        assert!(idle.remaining(now + Duration::from_secs(86400)).is_some());
+
+        let config = HeartbeatConfig {
+            heartbeat_interval: None,
+            idle_timeout: Some(Duration::from_millis(100)),
+        };
+        let idle = config.idle_tracker();
+        let start = idle.last_active();
+        idle.touch(start + Duration::from_millis(50));
+        assert_eq!(idle.last_active(), start + Duration::from_millis(50));
+        assert!(idle.remaining(start + Duration::from_millis(120)).is_some());
+        assert!(idle.remaining(start + Duration::from_millis(150)).is_none());
+    }
+}
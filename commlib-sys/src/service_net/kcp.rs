@@ -0,0 +1,891 @@
+//! Commlib: Kcp
+//! ikcp 的 rust 实现：在不可靠的数据报之上提供确认、重传、排序和流控.
+//! 本模块只是协议控制块，不涉及 socket 和线程：
+//!   input  -- 收到的数据报交给 kcp 解析（ack / push / 窗口探测）
+//!   send   -- 上层消息按 mss 分片进入发送队列
+//!   recv   -- 取出按序重组好的完整消息
+//!   update -- 由定时器按 interval 驱动，flush 时通过 output 回调发出数据报
+//! 报文格式与 ikcp 一致（24 字节小端头部），可以与 C 版本的 ikcp 互通.
+
+use std::collections::VecDeque;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+const KCP_RTO_NDL: u32 = 30; // no delay min rto
+const KCP_RTO_MIN: u32 = 100; // normal min rto
+const KCP_RTO_DEF: u32 = 200;
+const KCP_RTO_MAX: u32 = 60000;
+const KCP_CMD_PUSH: u8 = 81; // cmd: push data
+const KCP_CMD_ACK: u8 = 82; // cmd: ack
+const KCP_CMD_WASK: u8 = 83; // cmd: window probe (ask)
+const KCP_CMD_WINS: u8 = 84; // cmd: window size (tell)
+const KCP_ASK_SEND: u32 = 1; // need to send KCP_CMD_WASK
+const KCP_ASK_TELL: u32 = 2; // need to send KCP_CMD_WINS
+const KCP_WND_SND: u16 = 32;
+const KCP_WND_RCV: u16 = 128; // must >= max fragment size
+const KCP_MTU_DEF: usize = 1400;
+const KCP_INTERVAL: u32 = 100;
+const KCP_DEADLINK: u32 = 20;
+const KCP_THRESH_INIT: u32 = 2;
+const KCP_THRESH_MIN: u32 = 2;
+const KCP_PROBE_INIT: u32 = 7000; // 7 secs to probe window size
+const KCP_PROBE_LIMIT: u32 = 120000; // up to 120 secs to probe window
+const KCP_FASTACK_LIMIT: u32 = 5; // max times to trigger fastack
+
+/// kcp 报文头长度
+pub const KCP_OVERHEAD: usize = 24;
+
+/// kcp 使用的毫秒时钟（进程内单调，u32 回绕）
+pub fn kcp_clock_ms() -> u32 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_millis() as u32
+}
+
+/// 从数据报中读取 conv，长度不足一个报文头时返回 None
+#[inline(always)]
+pub fn kcp_peek_conv(data: &[u8]) -> Option<u32> {
+    if data.len() < KCP_OVERHEAD {
+        None
+    } else {
+        Some(u32::from_le_bytes([data[0], data[1], data[2], data[3]]))
+    }
+}
+
+#[inline(always)]
+fn timediff(later: u32, earlier: u32) -> i32 {
+    later.wrapping_sub(earlier) as i32
+}
+
+/// Kcp 错误
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum KcpError {
+    ConvMismatch(u32), // 报文 conv 与本端不一致
+    Truncated,         // 报文头或数据长度不完整
+    UnknownCmd(u8),    // 未知的报文命令
+    TooLarge(usize),   // 消息分片数超过接收窗口
+}
+
+impl std::fmt::Display for KcpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ConvMismatch(conv) => write!(f, "conv mismatch: {}", conv),
+            Self::Truncated => write!(f, "truncated segment"),
+            Self::UnknownCmd(cmd) => write!(f, "unknown cmd: {}", cmd),
+            Self::TooLarge(len) => write!(f, "message too large: {}", len),
+        }
+    }
+}
+
+impl std::error::Error for KcpError {}
+
+/// Kcp 参数，对应 ikcp_nodelay / ikcp_wndsize / ikcp_setmtu
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct KcpConfig {
+    pub nodelay: bool, // 最小 rto 使用 30ms，超时后 rto 按 1.5 倍而不是 2 倍增长
+    pub interval: u32, // update 间隔（毫秒）
+    pub resend: u32,   // 快速重传：被跳过 resend 次 ack 后立即重传，0 表示关闭
+    pub nocwnd: bool,  // 关闭拥塞控制
+    pub snd_wnd: u16,  // 发送窗口（包）
+    pub rcv_wnd: u16,  // 接收窗口（包），不小于 KCP_WND_RCV
+    pub mtu: usize,    // 单个数据报的最大长度
+}
+
+impl KcpConfig {
+    /// ikcp 推荐的极速模式：nodelay, 10ms, 2 次快速重传, 无拥塞控制
+    pub fn fast() -> Self {
+        Self {
+            nodelay: true,
+            interval: 10,
+            resend: 2,
+            nocwnd: true,
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for KcpConfig {
+    /// 与 ikcp_create 的默认参数一致
+    fn default() -> Self {
+        Self {
+            nodelay: false,
+            interval: KCP_INTERVAL,
+            resend: 0,
+            nocwnd: false,
+            snd_wnd: KCP_WND_SND,
+            rcv_wnd: KCP_WND_RCV,
+            mtu: KCP_MTU_DEF,
+        }
+    }
+}
+
+#[derive(Default)]
+struct KcpSegment {
+    conv: u32,
+    cmd: u8,
+    frg: u8,
+    wnd: u16,
+    ts: u32,
+    sn: u32,
+    una: u32,
+    resendts: u32,
+    rto: u32,
+    fastack: u32,
+    xmit: u32,
+    data: Vec<u8>,
+}
+
+impl KcpSegment {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.conv.to_le_bytes());
+        buf.push(self.cmd);
+        buf.push(self.frg);
+        buf.extend_from_slice(&self.wnd.to_le_bytes());
+        buf.extend_from_slice(&self.ts.to_le_bytes());
+        buf.extend_from_slice(&self.sn.to_le_bytes());
+        buf.extend_from_slice(&self.una.to_le_bytes());
+        buf.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&self.data);
+    }
+}
+
+/// 数据报输出回调
+pub type KcpOutputFn = Box<dyn FnMut(&[u8]) + Send>;
+
+/// Kcp 控制块
+pub struct Kcp {
+    conv: u32,
+    mtu: usize,
+    mss: usize,
+    dead: bool,
+
+    snd_una: u32,
+    snd_nxt: u32,
+    rcv_nxt: u32,
+
+    ssthresh: u32,
+    rx_rttval: i32,
+    rx_srtt: i32,
+    rx_rto: u32,
+    rx_minrto: u32,
+
+    snd_wnd: u32,
+    rcv_wnd: u32,
+    rmt_wnd: u32,
+    cwnd: u32,
+    incr: u32,
+    probe: u32,
+
+    current: u32,
+    interval: u32,
+    ts_flush: u32,
+    xmit: u32,
+    nodelay: bool,
+    updated: bool,
+    ts_probe: u32,
+    probe_wait: u32,
+    dead_link: u32,
+
+    fastresend: u32,
+    fastlimit: u32,
+    nocwnd: bool,
+
+    snd_queue: VecDeque<KcpSegment>,
+    rcv_queue: VecDeque<KcpSegment>,
+    snd_buf: VecDeque<KcpSegment>,
+    rcv_buf: VecDeque<KcpSegment>,
+    acklist: Vec<(u32, u32)>, // (sn, ts)
+
+    output: KcpOutputFn,
+}
+
+impl Kcp {
+    /// 通信双方的 conv 必须一致
+    pub fn new(conv: u32, config: &KcpConfig, output: KcpOutputFn) -> Self {
+        let mtu = std::cmp::max(config.mtu, KCP_OVERHEAD + 1);
+        let mut kcp = Self {
+            conv,
+            mtu,
+            mss: mtu - KCP_OVERHEAD,
+            dead: false,
+
+            snd_una: 0,
+            snd_nxt: 0,
+            rcv_nxt: 0,
+
+            ssthresh: KCP_THRESH_INIT,
+            rx_rttval: 0,
+            rx_srtt: 0,
+            rx_rto: KCP_RTO_DEF,
+            rx_minrto: KCP_RTO_MIN,
+
+            snd_wnd: std::cmp::max(config.snd_wnd, 1) as u32,
+            rcv_wnd: std::cmp::max(config.rcv_wnd, KCP_WND_RCV) as u32,
+            rmt_wnd: KCP_WND_RCV as u32,
+            cwnd: 0,
+            incr: 0,
+            probe: 0,
+
+            current: 0,
+            interval: KCP_INTERVAL,
+            ts_flush: KCP_INTERVAL,
+            xmit: 0,
+            nodelay: false,
+            updated: false,
+            ts_probe: 0,
+            probe_wait: 0,
+            dead_link: KCP_DEADLINK,
+
+            fastresend: 0,
+            fastlimit: KCP_FASTACK_LIMIT,
+            nocwnd: false,
+
+            snd_queue: VecDeque::new(),
+            rcv_queue: VecDeque::new(),
+            snd_buf: VecDeque::new(),
+            rcv_buf: VecDeque::new(),
+            acklist: Vec::new(),
+
+            output,
+        };
+        kcp.set_nodelay(
+            config.nodelay,
+            config.interval,
+            config.resend,
+            config.nocwnd,
+        );
+        kcp
+    }
+
+    /// ikcp_nodelay
+    pub fn set_nodelay(&mut self, nodelay: bool, interval: u32, resend: u32, nocwnd: bool) {
+        self.nodelay = nodelay;
+        self.rx_minrto = if nodelay { KCP_RTO_NDL } else { KCP_RTO_MIN };
+        self.interval = interval.clamp(10, 5000);
+        self.fastresend = resend;
+        self.nocwnd = nocwnd;
+    }
+
+    /// 会话 id
+    #[inline(always)]
+    pub fn conv(&self) -> u32 {
+        self.conv
+    }
+
+    /// update 间隔（毫秒）
+    #[inline(always)]
+    pub fn interval(&self) -> u32 {
+        self.interval
+    }
+
+    /// 单个分片的最大数据长度
+    #[inline(always)]
+    pub fn mss(&self) -> usize {
+        self.mss
+    }
+
+    /// 某个分片重传次数达到 dead_link，链路视为断开
+    #[inline(always)]
+    pub fn is_dead(&self) -> bool {
+        self.dead
+    }
+
+    /// 等待发送（含未确认）的分片数
+    #[inline(always)]
+    pub fn wait_snd(&self) -> usize {
+        self.snd_buf.len() + self.snd_queue.len()
+    }
+
+    /// 上层消息按 mss 分片进入发送队列，分片数不能超过接收窗口
+    pub fn send(&mut self, data: &[u8]) -> Result<(), KcpError> {
+        let count = std::cmp::max(1, data.len().div_ceil(self.mss));
+        if count >= KCP_WND_RCV as usize {
+            return Err(KcpError::TooLarge(data.len()));
+        }
+
+        for (i, chunk) in data.chunks(self.mss).enumerate() {
+            self.snd_queue.push_back(KcpSegment {
+                frg: (count - i - 1) as u8,
+                data: chunk.to_vec(),
+                ..KcpSegment::default()
+            });
+        }
+        if data.is_empty() {
+            self.snd_queue.push_back(KcpSegment::default());
+        }
+        Ok(())
+    }
+
+    /// 下一条完整消息的长度，消息还没有到齐时返回 None
+    pub fn peek_size(&self) -> Option<usize> {
+        let front = self.rcv_queue.front()?;
+        if front.frg == 0 {
+            return Some(front.data.len());
+        }
+        if self.rcv_queue.len() < front.frg as usize + 1 {
+            return None;
+        }
+
+        let mut len = 0;
+        for seg in &self.rcv_queue {
+            len += seg.data.len();
+            if seg.frg == 0 {
+                break;
+            }
+        }
+        Some(len)
+    }
+
+    /// 取出一条按序重组好的完整消息
+    pub fn recv(&mut self) -> Option<Vec<u8>> {
+        let size = self.peek_size()?;
+        let recover = self.rcv_queue.len() >= self.rcv_wnd as usize;
+
+        let mut msg = Vec::with_capacity(size);
+        while let Some(seg) = self.rcv_queue.pop_front() {
+            msg.extend_from_slice(&seg.data);
+            if seg.frg == 0 {
+                break;
+            }
+        }
+
+        self.move_rcv_buf();
+
+        // 接收窗口从满变为有空余，主动告知对端
+        if recover && self.rcv_queue.len() < self.rcv_wnd as usize {
+            self.probe |= KCP_ASK_TELL;
+        }
+        Some(msg)
+    }
+
+    /// 收到的数据报交给 kcp 解析
+    pub fn input(&mut self, mut data: &[u8]) -> Result<(), KcpError> {
+        if data.len() < KCP_OVERHEAD {
+            return Err(KcpError::Truncated);
+        }
+
+        let prev_una = self.snd_una;
+        let mut max_ack: Option<(u32, u32)> = None;
+
+        while data.len() >= KCP_OVERHEAD {
+            let read_u32 = |pos: usize| {
+                u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+            };
+            let conv = read_u32(0);
+            let cmd = data[4];
+            let frg = data[5];
+            let wnd = u16::from_le_bytes([data[6], data[7]]);
+            let ts = read_u32(8);
+            let sn = read_u32(12);
+            let una = read_u32(16);
+            let len = read_u32(20) as usize;
+
+            if conv != self.conv {
+                return Err(KcpError::ConvMismatch(conv));
+            }
+            if data.len() - KCP_OVERHEAD < len {
+                return Err(KcpError::Truncated);
+            }
+            if !matches!(
+                cmd,
+                KCP_CMD_PUSH | KCP_CMD_ACK | KCP_CMD_WASK | KCP_CMD_WINS
+            ) {
+                return Err(KcpError::UnknownCmd(cmd));
+            }
+
+            self.rmt_wnd = wnd as u32;
+            self.parse_una(una);
+            self.shrink_buf();
+
+            match cmd {
+                KCP_CMD_ACK => {
+                    if timediff(self.current, ts) >= 0 {
+                        self.update_ack(timediff(self.current, ts));
+                    }
+                    self.parse_ack(sn);
+                    self.shrink_buf();
+                    match max_ack {
+                        Some((max_sn, _)) if timediff(sn, max_sn) <= 0 => {}
+                        _ => max_ack = Some((sn, ts)),
+                    }
+                }
+                KCP_CMD_PUSH if timediff(sn, self.rcv_nxt.wrapping_add(self.rcv_wnd)) < 0 => {
+                    self.acklist.push((sn, ts));
+                    if timediff(sn, self.rcv_nxt) >= 0 {
+                        self.parse_data(KcpSegment {
+                            conv,
+                            cmd,
+                            frg,
+                            wnd,
+                            ts,
+                            sn,
+                            una,
+                            data: data[KCP_OVERHEAD..KCP_OVERHEAD + len].to_vec(),
+                            ..KcpSegment::default()
+                        });
+                    }
+                }
+                KCP_CMD_WASK => {
+                    // 对端请求告知窗口大小
+                    self.probe |= KCP_ASK_TELL;
+                }
+                _ => {
+                    // KCP_CMD_WINS: 窗口大小已在上面更新
+                    // 超出接收窗口的 KCP_CMD_PUSH 不确认，等对端重传
+                }
+            }
+
+            data = &data[KCP_OVERHEAD + len..];
+        }
+
+        if let Some((sn, _ts)) = max_ack {
+            self.parse_fastack(sn);
+        }
+
+        // 拥塞窗口增长
+        if timediff(self.snd_una, prev_una) > 0 && self.cwnd < self.rmt_wnd {
+            let mss = self.mss as u32;
+            if self.cwnd < self.ssthresh {
+                self.cwnd += 1;
+                self.incr += mss;
+            } else {
+                if self.incr < mss {
+                    self.incr = mss;
+                }
+                self.incr += (mss * mss) / self.incr + (mss / 16);
+                if (self.cwnd + 1) * mss <= self.incr {
+                    self.cwnd = self.incr.div_ceil(mss);
+                }
+            }
+            if self.cwnd > self.rmt_wnd {
+                self.cwnd = self.rmt_wnd;
+                self.incr = self.rmt_wnd * mss;
+            }
+        }
+        Ok(())
+    }
+
+    /// 由定时器驱动，current 为 kcp_clock_ms，到达 flush 时间时发出数据报
+    pub fn update(&mut self, current: u32) {
+        self.current = current;
+        if !self.updated {
+            self.updated = true;
+            self.ts_flush = current;
+        }
+
+        let mut slap = timediff(current, self.ts_flush);
+        if !(-10000..10000).contains(&slap) {
+            self.ts_flush = current;
+            slap = 0;
+        }
+
+        if slap >= 0 {
+            self.ts_flush = self.ts_flush.wrapping_add(self.interval);
+            if timediff(current, self.ts_flush) >= 0 {
+                self.ts_flush = current.wrapping_add(self.interval);
+            }
+            self.flush();
+        }
+    }
+
+    /// 发出 ack、窗口探测和待发送/待重传的分片
+    pub fn flush(&mut self) {
+        if !self.updated {
+            return;
+        }
+
+        let current = self.current;
+        let mtu = self.mtu;
+        let output = &mut self.output;
+        let mut buffer = Vec::with_capacity(mtu);
+        let mut emit = |buffer: &mut Vec<u8>, need: usize| {
+            if !buffer.is_empty() && buffer.len() + need > mtu {
+                (output)(buffer);
+                buffer.clear();
+            }
+        };
+
+        let wnd = if self.rcv_queue.len() < self.rcv_wnd as usize {
+            (self.rcv_wnd as usize - self.rcv_queue.len()) as u16
+        } else {
+            0
+        };
+        let mut seg = KcpSegment {
+            conv: self.conv,
+            cmd: KCP_CMD_ACK,
+            wnd,
+            una: self.rcv_nxt,
+            ..KcpSegment::default()
+        };
+
+        // ack
+        for (sn, ts) in self.acklist.drain(..) {
+            emit(&mut buffer, KCP_OVERHEAD);
+            seg.sn = sn;
+            seg.ts = ts;
+            seg.encode(&mut buffer);
+        }
+
+        // 对端窗口为 0 时定期探测
+        if self.rmt_wnd == 0 {
+            if self.probe_wait == 0 {
+                self.probe_wait = KCP_PROBE_INIT;
+                self.ts_probe = current.wrapping_add(self.probe_wait);
+            } else if timediff(current, self.ts_probe) >= 0 {
+                self.probe_wait = std::cmp::max(self.probe_wait, KCP_PROBE_INIT);
+                self.probe_wait += self.probe_wait / 2;
+                self.probe_wait = std::cmp::min(self.probe_wait, KCP_PROBE_LIMIT);
+                self.ts_probe = current.wrapping_add(self.probe_wait);
+                self.probe |= KCP_ASK_SEND;
+            }
+        } else {
+            self.ts_probe = 0;
+            self.probe_wait = 0;
+        }
+
+        seg.sn = 0;
+        seg.ts = 0;
+        if self.probe & KCP_ASK_SEND != 0 {
+            seg.cmd = KCP_CMD_WASK;
+            emit(&mut buffer, KCP_OVERHEAD);
+            seg.encode(&mut buffer);
+        }
+        if self.probe & KCP_ASK_TELL != 0 {
+            seg.cmd = KCP_CMD_WINS;
+            emit(&mut buffer, KCP_OVERHEAD);
+            seg.encode(&mut buffer);
+        }
+        self.probe = 0;
+
+        // 发送窗口内的分片从 snd_queue 移入 snd_buf
+        let mut cwnd = std::cmp::min(self.snd_wnd, self.rmt_wnd);
+        if !self.nocwnd {
+            cwnd = std::cmp::min(self.cwnd, cwnd);
+        }
+        while timediff(self.snd_nxt, self.snd_una.wrapping_add(cwnd)) < 0 {
+            let Some(mut newseg) = self.snd_queue.pop_front() else {
+                break;
+            };
+            newseg.conv = self.conv;
+            newseg.cmd = KCP_CMD_PUSH;
+            newseg.wnd = wnd;
+            newseg.ts = current;
+            newseg.sn = self.snd_nxt;
+            newseg.una = self.rcv_nxt;
+            newseg.resendts = current;
+            newseg.rto = self.rx_rto;
+            newseg.fastack = 0;
+            newseg.xmit = 0;
+            self.snd_nxt = self.snd_nxt.wrapping_add(1);
+            self.snd_buf.push_back(newseg);
+        }
+
+        let resent = if self.fastresend > 0 {
+            self.fastresend
+        } else {
+            u32::MAX
+        };
+        let rtomin = if self.nodelay { 0 } else { self.rx_rto >> 3 };
+
+        let mut change = false;
+        let mut lost = false;
+        for segment in self.snd_buf.iter_mut() {
+            let mut needsend = false;
+            if segment.xmit == 0 {
+                needsend = true;
+                segment.xmit += 1;
+                segment.rto = self.rx_rto;
+                segment.resendts = current.wrapping_add(segment.rto + rtomin);
+            } else if timediff(current, segment.resendts) >= 0 {
+                // 超时重传
+                needsend = true;
+                segment.xmit += 1;
+                self.xmit += 1;
+                if self.nodelay {
+                    segment.rto += segment.rto / 2;
+                } else {
+                    segment.rto += std::cmp::max(segment.rto, self.rx_rto);
+                }
+                segment.resendts = current.wrapping_add(segment.rto);
+                lost = true;
+            } else if segment.fastack >= resent
+                && (segment.xmit <= self.fastlimit || self.fastlimit == 0)
+            {
+                // 快速重传
+                needsend = true;
+                segment.xmit += 1;
+                segment.fastack = 0;
+                segment.resendts = current.wrapping_add(segment.rto);
+                change = true;
+            }
+
+            if needsend {
+                segment.ts = current;
+                segment.wnd = wnd;
+                segment.una = self.rcv_nxt;
+                emit(&mut buffer, KCP_OVERHEAD + segment.data.len());
+                segment.encode(&mut buffer);
+                if segment.xmit >= self.dead_link {
+                    self.dead = true;
+                }
+            }
+        }
+
+        if !buffer.is_empty() {
+            (output)(&buffer);
+        }
+
+        // 更新慢启动阈值和拥塞窗口
+        let mss = self.mss as u32;
+        if change {
+            let inflight = self.snd_nxt.wrapping_sub(self.snd_una);
+            self.ssthresh = std::cmp::max(inflight / 2, KCP_THRESH_MIN);
+            self.cwnd = self.ssthresh + resent;
+            self.incr = self.cwnd.saturating_mul(mss);
+        }
+        if lost {
+            self.ssthresh = std::cmp::max(self.cwnd / 2, KCP_THRESH_MIN);
+            self.cwnd = 1;
+            self.incr = mss;
+        }
+        if self.cwnd < 1 {
+            self.cwnd = 1;
+            self.incr = mss;
+        }
+    }
+
+    fn update_ack(&mut self, rtt: i32) {
+        if self.rx_srtt == 0 {
+            self.rx_srtt = rtt;
+            self.rx_rttval = rtt / 2;
+        } else {
+            let delta = (rtt - self.rx_srtt).abs();
+            self.rx_rttval = (3 * self.rx_rttval + delta) / 4;
+            self.rx_srtt = std::cmp::max(1, (7 * self.rx_srtt + rtt) / 8);
+        }
+        let rto = self.rx_srtt as u32 + std::cmp::max(self.interval, 4 * self.rx_rttval as u32);
+        self.rx_rto = rto.clamp(self.rx_minrto, KCP_RTO_MAX);
+    }
+
+    fn shrink_buf(&mut self) {
+        self.snd_una = match self.snd_buf.front() {
+            Some(seg) => seg.sn,
+            None => self.snd_nxt,
+        };
+    }
+
+    fn parse_ack(&mut self, sn: u32) {
+        if timediff(sn, self.snd_una) < 0 || timediff(sn, self.snd_nxt) >= 0 {
+            return;
+        }
+        if let Some(pos) = self.snd_buf.iter().position(|seg| seg.sn == sn) {
+            self.snd_buf.remove(pos);
+        }
+    }
+
+    fn parse_una(&mut self, una: u32) {
+        while let Some(seg) = self.snd_buf.front() {
+            if timediff(una, seg.sn) > 0 {
+                self.snd_buf.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn parse_fastack(&mut self, sn: u32) {
+        if timediff(sn, self.snd_una) < 0 || timediff(sn, self.snd_nxt) >= 0 {
+            return;
+        }
+        for seg in self.snd_buf.iter_mut() {
+            if timediff(sn, seg.sn) < 0 {
+                break;
+            } else if sn != seg.sn {
+                seg.fastack += 1;
+            }
+        }
+    }
+
+    fn parse_data(&mut self, newseg: KcpSegment) {
+        let sn = newseg.sn;
+        if timediff(sn, self.rcv_nxt.wrapping_add(self.rcv_wnd)) >= 0
+            || timediff(sn, self.rcv_nxt) < 0
+        {
+            return;
+        }
+
+        // 从后往前找插入位置，重复的分片丢弃
+        let mut pos = self.rcv_buf.len();
+        let mut repeat = false;
+        for (i, seg) in self.rcv_buf.iter().enumerate().rev() {
+            if seg.sn == sn {
+                repeat = true;
+                break;
+            }
+            if timediff(sn, seg.sn) > 0 {
+                break;
+            }
+            pos = i;
+        }
+        if !repeat {
+            self.rcv_buf.insert(pos, newseg);
+        }
+
+        self.move_rcv_buf();
+    }
+
+    fn move_rcv_buf(&mut self) {
+        while let Some(seg) = self.rcv_buf.front() {
+            if seg.sn == self.rcv_nxt && self.rcv_queue.len() < self.rcv_wnd as usize {
+                let seg = self.rcv_buf.pop_front().unwrap();
+                self.rcv_queue.push_back(seg);
+                self.rcv_nxt = self.rcv_nxt.wrapping_add(1);
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    type Wire = Arc<parking_lot::Mutex<Vec<Vec<u8>>>>;
+
+    fn make_kcp(conv: u32, config: &KcpConfig) -> (Kcp, Wire) {
+        let wire: Wire = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let wire2 = wire.clone();
+        let kcp = Kcp::new(
+            conv,
+            config,
+            Box::new(move |data: &[u8]| wire2.lock().push(data.to_vec())),
+        );
+        (kcp, wire)
+    }
+
+    /// 在内存链路上推进两端，drop(n) 为 true 的数据报被丢弃
+    fn pump(
+        a: &mut Kcp,
+        a_wire: &Wire,
+        b: &mut Kcp,
+        b_wire: &Wire,
+        now: &mut u32,
+        drop: &mut dyn FnMut(usize) -> bool,
+    ) {
+        let mut n = 0;
+        *now += 10;
+        a.update(*now);
+        b.update(*now);
+        for data in std::mem::take(&mut *a_wire.lock()) {
+            n += 1;
+            if !drop(n) {
+                b.input(&data).unwrap();
+            }
+        }
+        for data in std::mem::take(&mut *b_wire.lock()) {
+            n += 1;
+            if !drop(n) {
+                a.input(&data).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn reliable_in_order_over_lossy_link() {
+        let config = KcpConfig::fast();
+        let (mut a, a_wire) = make_kcp(7, &config);
+        let (mut b, b_wire) = make_kcp(7, &config);
+
+        for seq in 0..200_u32 {
+            a.send(&seq.to_le_bytes()).unwrap();
+        }
+
+        // 每 3 个数据报丢 1 个
+        let mut now = 0;
+        let mut received = Vec::new();
+        for _ in 0..2000 {
+            pump(&mut a, &a_wire, &mut b, &b_wire, &mut now, &mut |n| {
+                n % 3 == 0
+            });
+            while let Some(msg) = b.recv() {
+                received.push(u32::from_le_bytes(msg.try_into().unwrap()));
+            }
+            if received.len() == 200 && a.wait_snd() == 0 {
+                break;
+            }
+        }
+        assert_eq!(received, (0..200).collect::<Vec<_>>());
+        assert_eq!(a.wait_snd(), 0);
+        assert!(!a.is_dead());
+    }
+
+    #[test]
+    fn large_message_fragments_and_reassembles() {
+        let config = KcpConfig::default();
+        let (mut a, a_wire) = make_kcp(1, &config);
+        let (mut b, b_wire) = make_kcp(1, &config);
+
+        let msg: Vec<u8> = (0..a.mss() * 5 + 17).map(|i| i as u8).collect();
+        a.send(&msg).unwrap();
+        a.send(&[]).unwrap();
+
+        // 默认参数有拥塞控制，慢启动需要多轮往返
+        let mut now = 0;
+        for _ in 0..100 {
+            pump(&mut a, &a_wire, &mut b, &b_wire, &mut now, &mut |_| false);
+            if a.wait_snd() == 0 {
+                break;
+            }
+        }
+        assert_eq!(a.wait_snd(), 0);
+        assert_eq!(b.peek_size(), Some(msg.len()));
+        assert_eq!(b.recv(), Some(msg));
+        assert_eq!(b.recv(), Some(Vec::new()));
+        assert_eq!(b.recv(), None);
+
+        // 分片数超过接收窗口
+        let too_large = vec![0_u8; a.mss() * KCP_WND_RCV as usize];
+        assert_eq!(a.send(&too_large), Err(KcpError::TooLarge(too_large.len())));
+    }
+
+    #[test]
+    fn input_rejects_bad_segments() {
+        let config = KcpConfig::fast();
+        let (mut a, a_wire) = make_kcp(1, &config);
+        let (mut b, _) = make_kcp(2, &config);
+
+        a.send(b"hello").unwrap();
+        a.update(0);
+        let data = a_wire.lock().pop().unwrap();
+        assert_eq!(kcp_peek_conv(&data), Some(1));
+        assert_eq!(b.input(&data), Err(KcpError::ConvMismatch(1)));
+        assert_eq!(b.input(&data[..KCP_OVERHEAD - 1]), Err(KcpError::Truncated));
+        assert_eq!(kcp_peek_conv(&data[..KCP_OVERHEAD - 1]), None);
+
+        let (mut c, _) = make_kcp(1, &config);
+        assert_eq!(c.input(&data[..data.len() - 1]), Err(KcpError::Truncated));
+        let mut bad_cmd = data.clone();
+        bad_cmd[4] = 0;
+        assert_eq!(c.input(&bad_cmd), Err(KcpError::UnknownCmd(0)));
+        assert!(c.input(&data).is_ok());
+        assert_eq!(c.recv(), Some(b"hello".to_vec()));
+    }
+
+    #[test]
+    fn dead_link_after_max_retransmits() {
+        let config = KcpConfig::fast();
+        let (mut a, a_wire) = make_kcp(1, &config);
+        a.send(b"lost").unwrap();
+
+        // 对端没有任何回应
+        let mut now = 0;
+        while !a.is_dead() {
+            now += 10;
+            a.update(now);
+            a_wire.lock().clear();
+            assert!(now < 10_000_000, "never dead");
+        }
+        assert_eq!(a.wait_snd(), 1);
+    }
+}
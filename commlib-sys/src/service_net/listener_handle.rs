@@ -0,0 +1,278 @@
+//! Commlib: ListenerHandle
+//! listen_tcp_addr 返回的 listener id 的操作句柄：暂停/恢复 accept，遍历或关闭 accept 的连接.
+//! 所有操作都在 srv_net 线程中执行，结果返回给调用方
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::{PinkySwear, ServiceNetRs, ServiceRs};
+
+use super::{handle_close_conn_event, CloseReason, TcpConn, TcpListenerId, TcpServer};
+
+/// Tcp listener handle
+#[derive(Clone)]
+pub struct ListenerHandle {
+    id: TcpListenerId,
+    srv_net: Arc<ServiceNetRs>,
+}
+
+impl ListenerHandle {
+    ///
+    pub fn new(srv_net: &Arc<ServiceNetRs>, id: TcpListenerId) -> Self {
+        Self {
+            id,
+            srv_net: srv_net.clone(),
+        }
+    }
+
+    ///
+    #[inline(always)]
+    pub fn id(&self) -> TcpListenerId {
+        self.id
+    }
+
+    /// 停止 accept，新的连接被拒绝，已建立的连接不受影响（如维护模式）
+    pub fn stop_accept(&self) -> bool {
+        let id = self.id;
+        self.run_in_net(move |srv_net| {
+            with_tcp_server(srv_net, id, |tcp_server| tcp_server.stop_accept()).unwrap_or(false)
+        })
+    }
+
+    /// 恢复 accept，监听之前绑定的地址
+    pub fn resume_accept(&self) -> bool {
+        let id = self.id;
+        self.run_in_net(move |srv_net| {
+            with_tcp_server(srv_net, id, |tcp_server| tcp_server.resume_accept()).unwrap_or(false)
+        })
+    }
+
+    ///
+    pub fn is_accepting(&self) -> bool {
+        let id = self.id;
+        self.run_in_net(move |srv_net| {
+            with_tcp_server(srv_net, id, |tcp_server| tcp_server.status().is_running())
+                .unwrap_or(false)
+        })
+    }
+
+    /// 实际绑定的地址（listen 的端口为 0 时可以由此取得分配的端口）
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        let id = self.id;
+        self.run_in_net(move |srv_net| {
+            with_tcp_server(srv_net, id, |tcp_server| tcp_server.local_addr).flatten()
+        })
+    }
+
+    /// 关闭所有 accept 的连接，每个连接触发一次 close_fn，返回关闭的连接数
+    pub fn close_all(&self, reason: CloseReason) -> usize {
+        let id = self.id;
+        self.run_in_net(move |srv_net| {
+            let conns = srv_net.listener_conns(id);
+            for conn in &conns {
+                conn.close_with_reason(reason);
+                handle_close_conn_event(srv_net, conn);
+            }
+            log::info!(
+                "[listener_id={}] close all {} conns, reason: {:?}",
+                id,
+                conns.len(),
+                reason
+            );
+            conns.len()
+        })
+    }
+
+    /// 当前 accept 的连接数
+    pub fn conn_count(&self) -> usize {
+        let id = self.id;
+        self.run_in_net(move |srv_net| srv_net.listener_conns(id).len())
+    }
+
+    /// 遍历当前 accept 的连接：连接列表在 srv_net 中取得，f 在调用方线程中执行
+    pub fn for_each_conn<F>(&self, mut f: F)
+    where
+        F: FnMut(&Arc<TcpConn>),
+    {
+        let id = self.id;
+        let conns = self.run_in_net(move |srv_net| srv_net.listener_conns(id));
+        for conn in &conns {
+            f(conn);
+        }
+    }
+
+    // 在 srv_net 线程中执行并等待结果，已在 srv_net 线程中时直接执行
+    fn run_in_net<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&Arc<ServiceNetRs>) -> R + Send + Sync + 'static,
+        R: Send + 'static,
+    {
+        if self.srv_net.is_in_service_thread() {
+            return f(&self.srv_net);
+        }
+
+        let (promise, pinky) = PinkySwear::<R>::new();
+        let srv_net = self.srv_net.clone();
+        self.srv_net.run_in_service(Box::new(move || {
+            pinky.swear(f(&srv_net));
+        }));
+        promise.wait()
+    }
+}
+
+fn with_tcp_server<F, R>(srv_net: &ServiceNetRs, id: TcpListenerId, f: F) -> Option<R>
+where
+    F: FnOnce(&mut TcpServer) -> R,
+{
+    let mut tcp_server_vec_mut = srv_net.tcp_server_vec.write();
+    match tcp_server_vec_mut
+        .iter_mut()
+        .find(|tcp_server| tcp_server.id == id)
+    {
+        Some(tcp_server) => Some(f(tcp_server)),
+        None => {
+            log::error!("[listener_id={}] tcp server not found!!!", id);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{listen_tcp_addr, listen_tcp_addr_with_max_conns, PacketType};
+    use crate::{proc_service_ready, start_network, start_service, ConnId};
+    use parking_lot::Mutex;
+    use std::io::Read;
+    use std::net::TcpStream;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+
+    fn wait_until<F: Fn() -> bool>(f: F) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !f() {
+            assert!(Instant::now() < deadline, "wait timeout");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn stop_accept_keeps_conns() {
+        let srv_net: &'static Arc<ServiceNetRs> =
+            Box::leak(Box::new(Arc::new(ServiceNetRs::new(4001))));
+        let ready_pair = start_service(srv_net.as_ref(), "test_net", || {});
+        assert!(proc_service_ready(srv_net.as_ref(), ready_pair));
+        start_network(srv_net);
+
+        let closed = Arc::new(AtomicUsize::new(0));
+        let closed2 = closed.clone();
+        let id = listen_tcp_addr(
+            srv_net,
+            "127.0.0.1".to_owned(),
+            0,
+            PacketType::Server,
+            |_conn: Arc<TcpConn>| {},
+            |_hd, _pkt| {},
+            move |_hd: ConnId| {
+                closed2.fetch_add(1, Ordering::Relaxed);
+            },
+            srv_net,
+        );
+        let listener = ListenerHandle::new(srv_net, id);
+        let addr = listener.local_addr().unwrap();
+        assert!(listener.is_accepting());
+
+        let clients: Vec<TcpStream> = (0..3).map(|_| TcpStream::connect(addr).unwrap()).collect();
+        wait_until(|| listener.conn_count() == 3);
+
+        // 停止 accept 后新连接被拒绝，已有连接保留
+        assert!(listener.stop_accept());
+        assert!(!listener.stop_accept());
+        assert!(!listener.is_accepting());
+        assert!(TcpStream::connect(addr).is_err());
+        assert_eq!(listener.conn_count(), 3);
+
+        let mut hds = Vec::new();
+        let mut remote_addrs = Vec::new();
+        listener.for_each_conn(|conn| {
+            hds.push(conn.hd);
+            remote_addrs.push(conn.remote_addr());
+            assert_eq!(conn.local_addr(), addr);
+            assert!(conn.connected_at() <= Instant::now());
+        });
+        assert_eq!(hds.len(), 3);
+
+        // 对端地址即客户端 socket 的本端地址
+        remote_addrs.sort();
+        let mut client_addrs: Vec<_> = clients.iter().map(|c| c.local_addr().unwrap()).collect();
+        client_addrs.sort();
+        assert_eq!(remote_addrs, client_addrs);
+
+        assert_eq!(listener.close_all(CloseReason::Normal), 3);
+        wait_until(|| closed.load(Ordering::Relaxed) == 3);
+        assert_eq!(listener.conn_count(), 0);
+        drop(clients);
+    }
+
+    #[test]
+    fn max_conns_rejects() {
+        let srv_net: &'static Arc<ServiceNetRs> =
+            Box::leak(Box::new(Arc::new(ServiceNetRs::new(4003))));
+        let ready_pair = start_service(srv_net.as_ref(), "test_net", || {});
+        assert!(proc_service_ready(srv_net.as_ref(), ready_pair));
+        start_network(srv_net);
+
+        let rejected = Arc::new(Mutex::new(Vec::new()));
+        let rejected2 = rejected.clone();
+        let opened = Arc::new(AtomicUsize::new(0));
+        let opened2 = opened.clone();
+        let closed = Arc::new(AtomicUsize::new(0));
+        let closed2 = closed.clone();
+        let id = listen_tcp_addr_with_max_conns(
+            srv_net,
+            "127.0.0.1".to_owned(),
+            0,
+            2,
+            move |addr: SocketAddr| {
+                rejected2.lock().push(addr);
+            },
+            move |conn: Arc<TcpConn>| {
+                // conn_fn 中即可读取对端地址
+                assert!(conn.remote_addr().ip().is_loopback());
+                opened2.fetch_add(1, Ordering::Relaxed);
+            },
+            |_hd, _pkt| {},
+            move |_hd: ConnId| {
+                closed2.fetch_add(1, Ordering::Relaxed);
+            },
+            srv_net,
+        );
+        let listener = ListenerHandle::new(srv_net, id);
+        let addr = listener.local_addr().unwrap();
+
+        let first = TcpStream::connect(addr).unwrap();
+        let second = TcpStream::connect(addr).unwrap();
+        wait_until(|| opened.load(Ordering::Relaxed) == 2);
+
+        // 达到上限：第三个连接被 accept 后立即关闭
+        let mut third = TcpStream::connect(addr).unwrap();
+        third
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut buf = [0_u8; 8];
+        assert!(matches!(third.read(&mut buf), Ok(0) | Err(_)));
+        wait_until(|| rejected.lock().len() == 1);
+        assert_eq!(rejected.lock()[0], third.local_addr().unwrap());
+        assert_eq!(opened.load(Ordering::Relaxed), 2);
+        assert_eq!(listener.conn_count(), 2);
+
+        // 关闭一个连接后名额释放
+        drop(first);
+        wait_until(|| closed.load(Ordering::Relaxed) == 1);
+        let _fourth = TcpStream::connect(addr).unwrap();
+        wait_until(|| opened.load(Ordering::Relaxed) == 3);
+        assert_eq!(rejected.lock().len(), 1);
+        assert_eq!(listener.conn_count(), 2);
+        drop(second);
+    }
+}
@@ -27,6 +27,9 @@ const PKT_LEADING_SIZE: usize = 4;
 /// 2字节包体前导长度字段(来自客户端)
 const FROM_CLIENT_PKT_LEADING_SIZE: usize = 2;
 
+/// 4字节前导长度字段的最高位：包体为压缩格式（2字节长度字段和 WS 包头没有标记位）
+pub const PKT_COMPRESSED_FLAG: u32 = 0x8000_0000;
+
 /// 4字节包体前导长度字段 + 2字节协议号
 ///     leading(pkt_full_len)(4) + cmd(2)
 const SERVER_INNER_HEADER_SIZE: usize = PKT_LEADING_SIZE + 2;
@@ -47,6 +50,10 @@ const TO_CLIENT_HEADER_SIZE_WS: usize = 2;
 ///     client_no(1) + cmd(2)
 const FROM_CLIENT_HEADER_SIZE_WS: usize = 3;
 
+/// 2字节协议号: websocket 消息（收发相同）
+///     cmd(2)
+const WEBSOCKET_HEADER_SIZE: usize = 2;
+
 ///
 pub struct EncryptData {
     pub no_list: LinkedList<i8>, // 缓存的包序号列表
@@ -64,6 +71,8 @@ pub enum PacketType {
 
     ClientWs = 3, // 处理客户端包（WS）：收包解密，发包不加密
     RobotWs = 4,  // 模拟客户端包（WS）：发包加密，收包不需要解密
+
+    Websocket = 5, // tcp 上的 websocket（RFC 6455 分帧）：消息内容为 cmd(2) + 包体，不加密
 }
 
 ///
@@ -91,6 +100,7 @@ pub struct NetPacket {
     ///
     body_size: usize, // 包体纯数据长度，不包含包头（包头：包体前导长度字段，协议号，包序号等）
     cmd: CmdId,
+    compressed: bool, // 包头压缩标记
     client: ClientHead,
 
     buffer: Buffer, // 包体数据缓冲区
@@ -106,6 +116,7 @@ impl NetPacket {
 
             body_size: 0,
             cmd: 0,
+            compressed: false,
             client: ClientHead { no: 0 },
 
             buffer: Buffer::new(initial_size, BUFFER_RESERVED_PREPEND_SIZE),
@@ -123,6 +134,7 @@ impl NetPacket {
     pub fn release(&mut self) {
         self.body_size = 0;
         self.cmd = 0;
+        self.compressed = false;
         self.set_client_no(0);
         self.buffer.reset();
     }
@@ -160,6 +172,12 @@ impl NetPacket {
         self.size_type = size_type;
     }
 
+    ///
+    #[inline(always)]
+    pub fn packet_type(&self) -> PacketType {
+        self.packet_type
+    }
+
     ///
     #[inline(always)]
     pub fn set_type(&mut self, packet_type: PacketType) {
@@ -173,7 +191,7 @@ impl NetPacket {
         // 客户端包 2 字节包头，其他都是 4 字节包头
         match self.packet_type {
             PacketType::Client => self.buffer.peek_u16() as usize,
-            _ => self.buffer.peek_u32() as usize,
+            _ => (self.buffer.peek_u32() & !PKT_COMPRESSED_FLAG) as usize,
         }
     }
 
@@ -213,6 +231,18 @@ impl NetPacket {
         self.cmd = cmd;
     }
 
+    /// 包头中的压缩标记：包体为压缩格式
+    #[inline(always)]
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    /// 设置包头压缩标记，encode_packet 时写入 4 字节前导长度字段的最高位
+    #[inline(always)]
+    pub fn set_compressed(&mut self, compressed: bool) {
+        self.compressed = compressed;
+    }
+
     /// 包序号, MUTS be less than 128
     #[inline(always)]
     pub fn client_no(&self) -> i8 {
@@ -231,6 +261,20 @@ impl NetPacket {
         self.buffer.peek()
     }
 
+    /// 包体数据（decode_packet 之后），直接借用 buffer 不复制
+    #[inline(always)]
+    pub fn body(&self) -> &[u8] {
+        let data = self.buffer.peek();
+        &data[..std::cmp::min(self.body_size, data.len())]
+    }
+
+    /// 包体数据（可修改），用于包体加解密：encode_packet 之前或 decode_packet 之后调用
+    #[inline(always)]
+    pub fn body_mut(&mut self) -> &mut [u8] {
+        let len = std::cmp::min(self.body_size, self.buffer.length());
+        unsafe { std::slice::from_raw_parts_mut(self.buffer.data_mut(), len) }
+    }
+
     /// 内部消耗掉 buffer 数据，供给外部使用
     #[inline(always)]
     pub fn consume(&mut self) -> &[u8] {
@@ -314,6 +358,7 @@ impl NetPacket {
             PacketType::ClientWs | PacketType::RobotWs => {
                 self.buffer.length() >= FROM_CLIENT_HEADER_SIZE_WS
             }
+            PacketType::Websocket => self.buffer.length() >= WEBSOCKET_HEADER_SIZE,
         }
     }
 
@@ -419,6 +464,19 @@ impl NetPacket {
                 self.read_robot_ws_packet();
                 true
             }
+
+            PacketType::Websocket => {
+                if !self.check_packet() {
+                    log::error!(
+                        "[decode_packet::PacketType::Websocket] received data from [hd={}] error: check packet failed!!!",
+                        hd
+                    );
+                    false
+                } else {
+                    self.read_websocket_packet();
+                    true
+                }
+            }
         }
     }
 
@@ -487,9 +545,31 @@ impl NetPacket {
                 self.write_client_ws_packet();
                 true
             }
+
+            PacketType::Websocket => {
+                self.write_websocket_packet();
+                true
+            }
         }
     }
 
+    #[inline(always)]
+    fn compressed_flag(&self) -> u32 {
+        if self.compressed {
+            PKT_COMPRESSED_FLAG
+        } else {
+            0
+        }
+    }
+
+    // 读取 4 字节前导长度字段，取出压缩标记
+    #[inline(always)]
+    fn read_leading_u32(&mut self) -> usize {
+        let leading = self.buffer.read_u32();
+        self.compressed = leading & PKT_COMPRESSED_FLAG != 0;
+        (leading & !PKT_COMPRESSED_FLAG) as usize
+    }
+
     /* **** server **** */
     #[inline(always)]
     fn write_server_packet(&mut self) {
@@ -497,17 +577,18 @@ impl NetPacket {
         // 2 字节 cmd
         self.buffer.prepend_u16(self.cmd);
 
-        // 4 字节包长度
+        // 4 字节包长度（最高位为压缩标记）
         let size = SERVER_INNER_HEADER_SIZE + self.body_size;
-        self.buffer.prepend_u32(size as u32);
+        self.buffer
+            .prepend_u32(size as u32 | self.compressed_flag());
     }
 
     #[inline(always)]
     fn read_server_packet(&mut self) {
         // MUST only one packet in buffer
 
-        // 4 字节长度
-        let pkt_full_len = self.buffer.read_u32() as usize;
+        // 4 字节长度（最高位为压缩标记）
+        let pkt_full_len = self.read_leading_u32();
         self.body_size = pkt_full_len - SERVER_INNER_HEADER_SIZE;
 
         // 2 字节 cmd
@@ -521,9 +602,10 @@ impl NetPacket {
         // 2 字节 cmd
         self.buffer.prepend_u16(self.cmd);
 
-        // 4 字节包长度
+        // 4 字节包长度（最高位为压缩标记）
         let size = TO_CLIENT_HEADER_SIZE + self.body_size;
-        self.buffer.prepend_u32(size as u32);
+        self.buffer
+            .prepend_u32(size as u32 | self.compressed_flag());
     }
 
     #[inline(always)]
@@ -577,8 +659,8 @@ impl NetPacket {
     fn read_robot_packet(&mut self) {
         // MUST only one packet in buffer
 
-        // 4 字节长度
-        let pkt_full_len = self.buffer.read_u32() as usize;
+        // 4 字节长度（最高位为压缩标记）
+        let pkt_full_len = self.read_leading_u32();
         self.body_size = pkt_full_len - TO_CLIENT_HEADER_SIZE;
 
         // 2 字节 cmd
@@ -645,6 +727,25 @@ impl NetPacket {
         // 2 字节 cmd
         self.cmd = self.buffer.read_u16();
     }
+
+    /* **** websocket **** */
+    #[inline(always)]
+    fn write_websocket_packet(&mut self) {
+        // ws 帧头由 WsFramer 在发送时添加
+        // 2 字节 cmd
+        self.buffer.prepend_u16(self.cmd);
+    }
+
+    #[inline(always)]
+    fn read_websocket_packet(&mut self) {
+        // MUST only one message in buffer
+
+        //
+        self.body_size = self.buffer_raw_len() - WEBSOCKET_HEADER_SIZE;
+
+        // 2 字节 cmd
+        self.cmd = self.buffer.read_u16();
+    }
 }
 
 #[inline(always)]
@@ -656,6 +757,38 @@ pub fn get_packet_leading_field_size(packet_type: PacketType) -> usize {
     }
 }
 
+/// 发送的包头是否有压缩标记位（4 字节前导长度字段）
+#[inline(always)]
+pub fn can_send_compressed(packet_type: PacketType) -> bool {
+    matches!(packet_type, PacketType::Server | PacketType::Client)
+}
+
+/// 包头长度（含前导长度字段），按发送方向计算
+#[inline(always)]
+pub fn get_packet_header_size(packet_type: PacketType) -> usize {
+    match packet_type {
+        PacketType::Server => SERVER_INNER_HEADER_SIZE,
+        PacketType::Client => TO_CLIENT_HEADER_SIZE,
+        PacketType::Robot => FROM_CLIENT_HEADER_SIZE,
+        PacketType::ClientWs => TO_CLIENT_HEADER_SIZE_WS,
+        PacketType::RobotWs => FROM_CLIENT_HEADER_SIZE_WS,
+        PacketType::Websocket => WEBSOCKET_HEADER_SIZE,
+    }
+}
+
+/// 包头长度（含前导长度字段），按接收方向计算
+#[inline(always)]
+pub fn get_recv_packet_header_size(packet_type: PacketType) -> usize {
+    match packet_type {
+        PacketType::Server => SERVER_INNER_HEADER_SIZE,
+        PacketType::Client => FROM_CLIENT_HEADER_SIZE,
+        PacketType::Robot => TO_CLIENT_HEADER_SIZE,
+        PacketType::ClientWs => FROM_CLIENT_HEADER_SIZE_WS,
+        PacketType::RobotWs => TO_CLIENT_HEADER_SIZE_WS,
+        PacketType::Websocket => WEBSOCKET_HEADER_SIZE,
+    }
+}
+
 ///
 #[inline(always)]
 pub fn write_prost_message<M>(msg: &M, mut buf: &mut [u8]) -> bool
@@ -1,13 +1,26 @@
 use bytes::BytesMut;
+use std::any::Any;
 use std::cell::RefCell;
-use std::collections::LinkedList;
+use std::collections::{LinkedList, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
 use std::rc::Rc;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
 
-use crate::{Base64, ServiceNetRs};
+use crate::{Base64, ServiceNetRs, ServiceRs};
 
+use super::encryptor::HANDSHAKE_KEY_LEN;
+use super::net_packet::{can_send_compressed, get_packet_header_size};
 use super::take_packet;
-use super::{CmdId, ConnId, EncryptData, NetPacketGuard, PacketType};
+use super::{
+    CloseReason, CmdId, ConnId, ConnIdError, EncryptData, NetPacketGuard, PacketType,
+    ReconnectPolicy, SendQueueEvent, TcpClient, TcpConn,
+};
+use super::{CompressionMiddleware, COMPRESSION_ACCEPT_CMD, COMPRESSION_ADVERTISE_CMD};
+use super::{EncryptSession, EncryptorFactory, ENCRYPT_HANDSHAKE_CMD, MAX_PACKET_SIZE};
+
+/// 客户端模式重连期间默认最多缓存的包数
+pub const DEFAULT_MAX_PENDING_SENDS: usize = 4096;
 
 ///
 pub struct CrossRoutInfo {
@@ -20,17 +33,276 @@ pub struct CrossRoutInfo {
 ///
 pub type EncryptTokenHander = Box<dyn Fn(&NetProxy, ConnId) + Send + Sync>;
 pub type PacketHander = Box<dyn Fn(&NetProxy, ConnId, CmdId, &[u8]) + Send + Sync>;
+pub type PanicAlertHander = Box<dyn Fn(CmdId, u64) + Send + Sync>;
+pub type ReconnectExhaustedHander = Box<dyn Fn(&NetProxy, ConnId) + Send + Sync>;
+pub type SendQueueHighWaterHander = Box<dyn Fn(&NetProxy, ConnId, usize) + Send + Sync>;
+pub type UserDataInitHander = Box<dyn Fn(&NetProxy, &TcpConn) + Send + Sync>;
+pub type ConnLostHander =
+    Box<dyn Fn(&NetProxy, ConnId, Option<Arc<dyn Any + Send + Sync>>) + Send + Sync>;
+
+/// 包处理函数 panic 升级策略：window 时间内同一 cmd panic 超过 threshold 次，禁用该 cmd 的处理函数
+#[derive(Debug, Copy, Clone)]
+pub struct PanicPolicy {
+    pub threshold: usize,
+    pub window: Duration,
+}
+
+impl Default for PanicPolicy {
+    fn default() -> Self {
+        Self {
+            threshold: 5,
+            window: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Default)]
+struct CmdPanicStat {
+    total: u64,
+    recent: VecDeque<Instant>,
+    disabled: bool,
+}
+
+/// 按 cmd 统计处理函数 panic 次数
+pub struct HandlerPanicGuard {
+    policy: PanicPolicy,
+    stats: hashbrown::HashMap<CmdId, CmdPanicStat>,
+}
+
+impl HandlerPanicGuard {
+    ///
+    pub fn new(policy: PanicPolicy) -> Self {
+        Self {
+            policy,
+            stats: hashbrown::HashMap::new(),
+        }
+    }
+
+    ///
+    #[inline(always)]
+    pub fn policy(&self) -> PanicPolicy {
+        self.policy
+    }
+
+    ///
+    pub fn set_policy(&mut self, policy: PanicPolicy) {
+        self.policy = policy;
+    }
+
+    ///
+    pub fn is_disabled(&self, cmd: CmdId) -> bool {
+        self.stats.get(&cmd).map_or(false, |stat| stat.disabled)
+    }
+
+    /// panic 总次数
+    pub fn panic_count(&self, cmd: CmdId) -> u64 {
+        self.stats.get(&cmd).map_or(0, |stat| stat.total)
+    }
+
+    /// 重新启用 cmd（如热更修复后），窗口计数清零
+    pub fn enable(&mut self, cmd: CmdId) {
+        if let Some(stat) = self.stats.get_mut(&cmd) {
+            stat.disabled = false;
+            stat.recent.clear();
+        }
+    }
+
+    /// 记录一次 panic，返回 true 表示本次触发了升级（cmd 被禁用）
+    pub fn record(&mut self, cmd: CmdId, now: Instant) -> bool {
+        let policy = self.policy;
+        let stat = self.stats.entry(cmd).or_default();
+        stat.total += 1;
+
+        // 滑出窗口的记录
+        while let Some(front) = stat.recent.front() {
+            if now.duration_since(*front) > policy.window {
+                stat.recent.pop_front();
+            } else {
+                break;
+            }
+        }
+        stat.recent.push_back(now);
+
+        if !stat.disabled && stat.recent.len() > policy.threshold {
+            stat.disabled = true;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// 每条连接的发送队列（包数）：conn 不可写时缓存已编码的包，可写后按顺序发送
+pub struct ConnSendQueues {
+    capacity: usize,   // 每条连接最多缓存的包数，0 表示不缓存直接发送
+    high_water: usize, // 缓存包数达到 high water 时通知上层，0 表示不通知
+    queues: hashbrown::HashMap<ConnId, VecDeque<NetPacketGuard>>,
+}
+
+impl ConnSendQueues {
+    ///
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            high_water: 0,
+            queues: hashbrown::HashMap::new(),
+        }
+    }
+
+    ///
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    ///
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+    }
+
+    ///
+    pub fn set_high_water(&mut self, high_water: usize) {
+        self.high_water = high_water;
+    }
+
+    /// 缓存的包数
+    pub fn depth(&self, hd: ConnId) -> usize {
+        self.queues.get(&hd).map_or(0, VecDeque::len)
+    }
+
+    /// 入队，队列已满时丢弃 pkt 并返回 Overflow
+    pub fn push(&mut self, hd: ConnId, pkt: NetPacketGuard) -> SendQueueEvent {
+        let queue = self.queues.entry(hd).or_default();
+        if queue.len() >= self.capacity {
+            return SendQueueEvent::Overflow(queue.len());
+        }
+
+        queue.push_back(pkt);
+        let depth = queue.len();
+        if self.high_water > 0 && depth == self.high_water {
+            SendQueueEvent::HighWatermark(depth)
+        } else {
+            SendQueueEvent::Queued
+        }
+    }
+
+    ///
+    pub fn pop(&mut self, hd: ConnId) -> Option<NetPacketGuard> {
+        let queue = self.queues.get_mut(&hd)?;
+        let pkt = queue.pop_front();
+        if queue.is_empty() {
+            self.queues.remove(&hd);
+        }
+        pkt
+    }
+
+    /// 连接断开时丢弃缓存的包，返回丢弃的包数
+    pub fn remove(&mut self, hd: ConnId) -> usize {
+        self.queues.remove(&hd).map_or(0, |queue| queue.len())
+    }
+
+    /// 有缓存包的连接
+    pub fn conns(&self) -> Vec<ConnId> {
+        self.queues.keys().copied().collect()
+    }
+}
+
+/// 发送错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SendError {
+    TooLarge { len: usize, max: usize }, // 编码后包长度（含包头）超过上限
+    EncodeFailed(ConnId),                // 包头编码失败（如加密数据不存在）
+    Conn(ConnIdError),                   // hd 失效或不存在
+    NotClient,                           // 没有通过 connect_to_server 进入客户端模式
+    QueueFull,                           // 连接发送队列已满
+}
+
+impl std::fmt::Display for SendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SendError::TooLarge { len, max } => {
+                write!(f, "packet too large: len={} max={}", len, max)
+            }
+            SendError::EncodeFailed(hd) => write!(f, "[hd={}] encode packet failed", hd),
+            SendError::Conn(err) => write!(f, "{}", err),
+            SendError::NotClient => write!(f, "proxy not in client mode"),
+            SendError::QueueFull => write!(f, "send queue full"),
+        }
+    }
+}
+
+impl std::error::Error for SendError {}
+
+/// 连接收发统计
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnStats {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    pub connected_at: Instant,
+    pub last_recv_at: Instant, // 没有收到过数据时等于 connected_at
+    pub bytes_saved_by_compression: u64, // 发送的包体压缩节省的字节数
+}
+
+impl ConnStats {
+    ///
+    pub fn new(connected_at: Instant) -> Self {
+        Self {
+            bytes_sent: 0,
+            bytes_received: 0,
+            packets_sent: 0,
+            packets_received: 0,
+            connected_at,
+            last_recv_at: connected_at,
+            bytes_saved_by_compression: 0,
+        }
+    }
+}
 
 ///
 pub struct NetProxy {
     packet_type: PacketType, // 通信 packet 类型
     srv_net: Arc<ServiceNetRs>,
+    max_packet_size: usize, // 发送包长度上限（含包头）
 
     hd_encrypt_table: hashbrown::HashMap<ConnId, RefCell<EncryptData>>, // 每条连接的包序号和密钥（客户端连接才需要保存）
     encrypt_token_handler: EncryptTokenHander,
 
+    encryptor_factory: Option<EncryptorFactory>, // 设置后使用内置握手和包体加密
+    hd_session_table: hashbrown::HashMap<ConnId, RefCell<EncryptSession>>,
+
+    compression: Option<CompressionMiddleware>, // 设置后与对端协商包体压缩
+
     default_handler: PacketHander,
     handlers: hashbrown::HashMap<CmdId, Rc<PacketHander>>,
+
+    panic_guard: HandlerPanicGuard,
+    panic_alert_handler: PanicAlertHander,
+    trace_seq: u64, // 分发序号，作为 trace id 写入日志
+
+    // 客户端模式：断线后由 TcpClient 按 reconnect_policy 重连，重连期间发往服务器的包先缓存
+    reconnect_policy: ReconnectPolicy,
+    client_mode: bool,
+    client_hd: Option<ConnId>, // 当前连接，断线或未连上时为 None
+    pending_sends: RefCell<VecDeque<(CmdId, Vec<u8>)>>,
+    max_pending_sends: usize, // 缓存包数上限，0 表示不限制
+    reconnect_exhausted_handler: ReconnectExhaustedHander,
+
+    // 每条连接的发送队列，conn 不可写时缓存
+    send_queues: RefCell<ConnSendQueues>,
+    send_queue_high_water_handler: SendQueueHighWaterHander,
+
+    // 连接分组（如房间），广播时包只编码一次
+    groups: hashbrown::HashMap<u64, hashbrown::HashSet<ConnId>>,
+
+    // 每条连接的收发统计，发送接口是 &self，用 RefCell
+    conn_stats: RefCell<hashbrown::HashMap<ConnId, ConnStats>>,
+
+    // 连接关联数据：on_incomming_conn 时设置，on_hd_lost 时交给 conn_lost_handler 后清除
+    hd_conn_table: hashbrown::HashMap<ConnId, Weak<TcpConn>>,
+    user_data_init_handler: Option<UserDataInitHander>,
+    conn_lost_handler: ConnLostHander,
 }
 
 impl NetProxy {
@@ -39,12 +311,164 @@ impl NetProxy {
         NetProxy {
             packet_type,
             srv_net: srv_net.clone(),
+            max_packet_size: MAX_PACKET_SIZE,
 
             hd_encrypt_table: hashbrown::HashMap::new(),
             encrypt_token_handler: Box::new(|_1, _2| {}),
 
+            encryptor_factory: None,
+            hd_session_table: hashbrown::HashMap::new(),
+
+            compression: None,
+
             default_handler: Box::new(|_1, _2, _3, _4| {}),
             handlers: hashbrown::HashMap::new(),
+
+            panic_guard: HandlerPanicGuard::new(PanicPolicy::default()),
+            panic_alert_handler: Box::new(|_1, _2| {}),
+            trace_seq: 0,
+
+            reconnect_policy: ReconnectPolicy::default(),
+            client_mode: false,
+            client_hd: None,
+            pending_sends: RefCell::new(VecDeque::new()),
+            max_pending_sends: DEFAULT_MAX_PENDING_SENDS,
+            reconnect_exhausted_handler: Box::new(|_1, _2| {}),
+
+            send_queues: RefCell::new(ConnSendQueues::new(0)),
+            send_queue_high_water_handler: Box::new(|_1, _2, _3| {}),
+
+            groups: hashbrown::HashMap::new(),
+
+            conn_stats: RefCell::new(hashbrown::HashMap::new()),
+
+            hd_conn_table: hashbrown::HashMap::new(),
+            user_data_init_handler: None,
+            conn_lost_handler: Box::new(|_1, _2, _3| {}),
+        }
+    }
+
+    /// 客户端模式：连接 raddr，断线后按 reconnect policy 在 service net 定时器中重连（指数退避）.
+    /// conn_fn 中调用 on_outgoing_conn，close_fn(hd, exhausted) 中调用 on_hd_lost,
+    /// exhausted 为 true 时（重连次数用尽）再调用 on_reconnect_exhausted
+    pub fn connect_to_server<T, C, P, S>(
+        &mut self,
+        srv: &Arc<T>,
+        name: &str,
+        raddr: &str,
+        conn_fn: C,
+        pkt_fn: P,
+        close_fn: S,
+    ) -> Arc<TcpClient>
+    where
+        T: ServiceRs + 'static,
+        C: Fn(Arc<TcpConn>) + Send + Sync + 'static,
+        P: Fn(ConnId, NetPacketGuard) + Send + Sync + 'static,
+        S: Fn(ConnId, bool) + Send + Sync + 'static,
+    {
+        self.client_mode = true;
+        self.client_hd = None;
+        super::connect_to_tcp_server_with_reconnect(
+            srv,
+            name,
+            raddr,
+            conn_fn,
+            pkt_fn,
+            close_fn,
+            self.reconnect_policy,
+            &self.srv_net,
+        )
+    }
+
+    /// 客户端模式的重连策略，在 connect_to_server 之前设置
+    pub fn set_reconnect_policy(&mut self, policy: ReconnectPolicy) {
+        self.reconnect_policy = policy;
+    }
+
+    /// 客户端模式未连上时最多缓存的包数，超过时 send_proto_to_server 返回 SendError::QueueFull;
+    /// 0 表示不限制
+    pub fn set_max_pending_sends(&mut self, limit: usize) {
+        self.max_pending_sends = limit;
+    }
+
+    /// 重连次数用尽时回调 (proxy, 最后一个 hd)
+    pub fn set_reconnect_exhausted_handler<F>(&mut self, f: F)
+    where
+        F: Fn(&NetProxy, ConnId) + Send + Sync + 'static,
+    {
+        self.reconnect_exhausted_handler = Box::new(f);
+    }
+
+    /// 重连次数用尽：丢弃缓存的包，退出客户端模式
+    pub fn on_reconnect_exhausted(&mut self, hd: ConnId) {
+        let dropped = self.pending_sends.borrow_mut().drain(..).count();
+        log::error!(
+            "[hd={}] reconnect exhausted!!! policy={:?}, drop {} pending packets",
+            hd,
+            self.reconnect_policy,
+            dropped
+        );
+        self.client_mode = false;
+        self.client_hd = None;
+        (self.reconnect_exhausted_handler)(self, hd);
+    }
+
+    /// 客户端模式：发送到服务器，未连上（或握手未完成）时缓存，连上后按顺序发送；
+    /// 缓存已满时返回 SendError::QueueFull
+    pub fn send_proto_to_server<M>(&self, cmd: CmdId, msg: &M) -> Result<(), SendError>
+    where
+        M: prost::Message,
+    {
+        if !self.client_mode {
+            return Err(SendError::NotClient);
+        }
+
+        match self.client_hd {
+            Some(hd) if self.is_client_ready(hd) => self.send_proto_hd(hd, cmd, msg),
+            _ => {
+                let depth = self.pending_sends.borrow().len();
+                if self.max_pending_sends > 0 && depth >= self.max_pending_sends {
+                    log::warn!(
+                        "pending sends full!!! cmd={} depth={} limit={}",
+                        cmd,
+                        depth,
+                        self.max_pending_sends
+                    );
+                    return Err(SendError::QueueFull);
+                }
+
+                let body = self.build_body(cmd, msg)?;
+                self.pending_sends
+                    .borrow_mut()
+                    .push_back((cmd, body.body().to_vec()));
+                Ok(())
+            }
+        }
+    }
+
+    /// 客户端模式下等待发送的包数
+    pub fn pending_send_count(&self) -> usize {
+        self.pending_sends.borrow().len()
+    }
+
+    // 不加密或握手已完成
+    fn is_client_ready(&self, hd: ConnId) -> bool {
+        self.hd_session_table
+            .get(&hd)
+            .map_or(true, |session| session.borrow().is_established())
+    }
+
+    fn flush_pending_sends(&self, hd: ConnId) {
+        let pending: Vec<(CmdId, Vec<u8>)> = self.pending_sends.borrow_mut().drain(..).collect();
+        if pending.is_empty() {
+            return;
+        }
+
+        log::info!("[hd={}] flush {} pending packets", hd, pending.len());
+        for (cmd, body) in pending {
+            if let Err(err) = self.send_raw(hd, cmd, &body) {
+                log::error!("[hd={}] flush cmd={} failed!!! error: {}", hd, cmd, err);
+            }
         }
     }
 
@@ -58,105 +482,1676 @@ impl NetProxy {
             packet_type
         );
         hd.set_packet_type(self.srv_net.as_ref(), packet_type);
+        self.conn_stats
+            .borrow_mut()
+            .insert(hd, ConnStats::new(Instant::now()));
+
+        // 设置连接关联数据
+        if let Some(conn) = self.srv_net.get_conn(hd) {
+            if let Some(init) = &self.user_data_init_handler {
+                (init)(self, &conn);
+            }
+            self.hd_conn_table.insert(hd, Arc::downgrade(&conn));
+        }
 
         //
         if push_encrypt_token {
-            // 发送 EncryptToken
-            (self.encrypt_token_handler)(self, hd);
+            if self.encryptor_factory.is_some() {
+                // 内置握手：发送密钥，之后包体加密
+                if let Some(mut pkt) = self.start_handshake(hd) {
+                    let slice = pkt.consume();
+                    if hd.send(self.srv_net.as_ref(), slice).is_ok() {
+                        self.record_sent(hd, slice.len());
+                    }
+                }
+            } else {
+                // 发送 EncryptToken
+                (self.encrypt_token_handler)(self, hd);
+            }
         }
-    }
 
-    ///
-    pub fn on_net_packet(&mut self, hd: ConnId, mut pkt: NetPacketGuard) {
-        if pkt.decode_packet(hd, &mut self.hd_encrypt_table) {
-            let cmd = pkt.cmd();
-            if let Some(handler) = self.handlers.get(&cmd) {
-                let h = handler.clone();
-                let slice = pkt.consume();
-                (h)(self, hd, cmd, slice);
-            } else {
-                // no-handler(trans), use default handler
-                let slice = pkt.consume();
-                (self.default_handler)(self, hd, cmd, slice);
+        // 通知对端支持压缩，在握手包之后发送
+        if let Some(mut pkt) = self.start_compression(hd) {
+            let slice = pkt.consume();
+            if hd.send(self.srv_net.as_ref(), slice).is_ok() {
+                self.record_sent(hd, slice.len());
             }
         }
     }
 
-    ///
-    #[inline(always)]
-    pub fn packet_type(&self) -> PacketType {
-        self.packet_type
+    /// 主动连接建立，expect_encrypt_token 为 true 时等待对端的握手包，握手完成前收到的其他包视为非法
+    pub fn on_outgoing_conn(&mut self, hd: ConnId, expect_encrypt_token: bool) {
+        log::info!(
+            "[hd={}] on_outgoing_conn packet_type={:?} expect_encrypt_token={}",
+            hd,
+            self.packet_type,
+            expect_encrypt_token
+        );
+        if self.client_mode {
+            self.client_hd = Some(hd);
+        }
+        self.conn_stats
+            .borrow_mut()
+            .insert(hd, ConnStats::new(Instant::now()));
+
+        if expect_encrypt_token {
+            if self.encryptor_factory.is_none() {
+                log::error!(
+                    "[hd={}] on_outgoing_conn failed!!! no encryptor factory!!!",
+                    hd
+                );
+                return;
+            }
+            self.hd_session_table
+                .insert(hd, RefCell::new(EncryptSession::Pending));
+        } else if self.client_hd == Some(hd) {
+            self.flush_pending_sends(hd);
+        }
     }
 
-    ///
-    pub fn set_encrypt_token_handler<F>(&mut self, f: F)
-    where
-        F: Fn(&NetProxy, ConnId) + Send + Sync + 'static,
-    {
-        self.encrypt_token_handler = Box::new(f);
+    /// 握手完成（密钥已交换）
+    pub fn is_handshake_done(&self, hd: ConnId) -> bool {
+        self.hd_session_table
+            .get(&hd)
+            .map_or(false, |session| session.borrow().is_established())
     }
 
-    ///
-    pub fn set_encrypt_key(&mut self, hd: ConnId, key: Vec<u8>) {
-        let encrypt_opt = self.hd_encrypt_table.get(&hd);
-        if let Some(encrypt) = encrypt_opt {
-            let old_encrypt = encrypt;
-            log::error!(
-                "set [hd={}] encrypt key error!!! already exists {}!!!",
-                hd,
-                Base64::encode(&old_encrypt.borrow().encrypt_key)
-            );
-            self.hd_encrypt_table.remove(&hd);
+    // 生成密钥并返回明文握手包，本端此后加密
+    fn start_handshake(&mut self, hd: ConnId) -> Option<NetPacketGuard> {
+        let factory = self.encryptor_factory.as_ref()?;
+        let key = crate::gen_password(HANDSHAKE_KEY_LEN).into_bytes();
+
+        let mut pkt = take_packet(key.len());
+        pkt.set_type(self.packet_type);
+        pkt.set_cmd(ENCRYPT_HANDSHAKE_CMD);
+        pkt.set_body(&key);
+        if !pkt.encode_packet(hd, &self.hd_encrypt_table) {
+            log::error!("[hd={}] encode handshake packet failed!!!", hd);
+            return None;
         }
 
-        //
-        log::info!("set [hd={}] encrypt key {}", hd, Base64::encode(&key));
-        self.hd_encrypt_table.insert(
-            hd,
-            RefCell::new(EncryptData {
-                no_list: LinkedList::new(),
-                encrypt_key: unsafe { String::from_utf8_unchecked(key) },
-            }),
-        );
+        let encryptor = factory(&key);
+        self.hd_session_table
+            .insert(hd, RefCell::new(EncryptSession::Established(encryptor)));
+        Some(pkt)
     }
 
-    /// 发送接口线程安全
-    #[inline(always)]
-    pub fn send_raw(&self, hd: ConnId, cmd: CmdId, slice: &[u8]) {
-        let mut pkt = take_packet(slice.len());
-        pkt.set_type(self.packet_type);
-        pkt.set_cmd(cmd);
-        pkt.set_body(slice);
+    // 返回压缩 advertise 包（server），未启用压缩时返回 None
+    fn start_compression(&mut self, hd: ConnId) -> Option<NetPacketGuard> {
+        self.compression.as_ref()?;
+        let pkt = self.build_control_packet(hd, COMPRESSION_ADVERTISE_CMD)?;
+        self.compression.as_mut()?.session_mut(hd).advertised = true;
+        Some(pkt)
+    }
 
-        //
-        self.send_packet(hd, pkt);
+    // 处理压缩协商包，返回需要回复的包. 回复的包在修改状态之前编码，总是未压缩格式
+    fn on_compression_packet(&mut self, hd: ConnId, cmd: CmdId) -> Option<NetPacketGuard> {
+        let session = match &self.compression {
+            Some(compression) => compression.session(hd),
+            None => {
+                log::info!("[hd={}] compression not enabled, ignore cmd={}", hd, cmd);
+                return None;
+            }
+        };
+
+        match cmd {
+            COMPRESSION_ADVERTISE_CMD if !session.advertised && !session.send => {
+                // client: 选择启用压缩，回复之后发送的包体使用压缩格式
+                let reply = self.build_control_packet(hd, COMPRESSION_ACCEPT_CMD)?;
+                self.compression.as_mut()?.session_mut(hd).send = true;
+                Some(reply)
+            }
+            COMPRESSION_ACCEPT_CMD if session.advertised && !session.recv => {
+                // server: 对端之后的包体为压缩格式，确认之后本端也使用压缩格式
+                let reply = self.build_control_packet(hd, COMPRESSION_ACCEPT_CMD)?;
+                let session = self.compression.as_mut()?.session_mut(hd);
+                session.recv = true;
+                session.send = true;
+                log::info!("[hd={}] compression negotiated", hd);
+                Some(reply)
+            }
+            COMPRESSION_ACCEPT_CMD if !session.advertised && session.send && !session.recv => {
+                // client: server 已确认
+                self.compression.as_mut()?.session_mut(hd).recv = true;
+                log::info!("[hd={}] compression negotiated", hd);
+                None
+            }
+            _ => {
+                log::warn!("[hd={}] unexpected compression cmd={}!!!", hd, cmd);
+                None
+            }
+        }
     }
 
-    //#[inline(always)]
-    pub fn send_proto<M>(&self, hd: ConnId, cmd: CmdId, msg: &M)
-    where
-        M: prost::Message,
-    {
-        //
-        let len = msg.encoded_len();
-        let mut pkt = take_packet(len);
+    // 包体为空的协议包（握手之后加密）
+    fn build_control_packet(&self, hd: ConnId, cmd: CmdId) -> Option<NetPacketGuard> {
+        let mut pkt = take_packet(0);
         pkt.set_type(self.packet_type);
         pkt.set_cmd(cmd);
-        pkt.set_msg(msg);
+        pkt.set_body(&[]);
+        if self.encode_for(hd, &mut pkt) {
+            Some(pkt)
+        } else {
+            log::error!("[hd={}] encode cmd={} failed!!!", hd, cmd);
+            None
+        }
+    }
 
-        //
-        self.send_packet(hd, pkt);
+    /// 双方都已启用压缩
+    pub fn is_compression_negotiated(&self, hd: ConnId) -> bool {
+        self.compression.as_ref().map_or(false, |compression| {
+            let session = compression.session(hd);
+            session.send && session.recv
+        })
+    }
+
+    /// 连接断开，清理该连接的加密数据
+    pub fn on_hd_lost(&mut self, hd: ConnId) {
+        log::info!("[hd={}] on_hd_lost", hd);
+
+        // close_fn 执行期间 conn 仍然存活，取出最后的关联数据
+        let user_data = self
+            .hd_conn_table
+            .remove(&hd)
+            .and_then(|conn| conn.upgrade())
+            .and_then(|conn| conn.take_user_data());
+        (self.conn_lost_handler)(self, hd, user_data);
+
+        self.hd_encrypt_table.remove(&hd);
+        self.hd_session_table.remove(&hd);
+        if let Some(compression) = self.compression.as_mut() {
+            compression.remove(hd);
+        }
+
+        let dropped = self.send_queues.borrow_mut().remove(hd);
+        if dropped > 0 {
+            log::warn!("[hd={}] drop {} queued packets", hd, dropped);
+        }
+
+        self.groups.retain(|_, members| {
+            members.remove(&hd);
+            !members.is_empty()
+        });
+
+        self.conn_stats.borrow_mut().remove(&hd);
+
+        // 客户端模式：等待 TcpClient 重连
+        if self.client_mode && self.client_hd == Some(hd) {
+            log::info!("[hd={}] client conn lost, wait reconnect ...", hd);
+            self.client_hd = None;
+        }
+    }
+
+    /// 连接断开，conn 已标记关闭但仍可读取连接信息
+    pub fn on_conn_lost(&mut self, conn: &TcpConn) {
+        log::info!(
+            "[hd={}] on_conn_lost close_reason={:?}",
+            conn.hd,
+            conn.close_reason()
+        );
+        self.on_hd_lost(conn.hd);
     }
 
-    //#[inline(always)]
-    fn send_packet(&self, hd: ConnId, mut pkt: NetPacketGuard) {
-        if pkt.encode_packet(hd, &self.hd_encrypt_table) {
+    ///
+    pub fn on_net_packet(&mut self, hd: ConnId, mut pkt: NetPacketGuard) {
+        self.record_recv(hd, pkt.peek().len(), Instant::now());
+        if pkt.decode_packet(hd, &mut self.hd_encrypt_table)
+            && self.open_packet(hd, &mut pkt)
+            && self.decompress_packet(hd, &mut pkt)
+        {
+            let cmd = pkt.cmd();
             let slice = pkt.consume();
-            log::info!("send: {:?}", slice);
-            hd.send(self.srv_net.as_ref(), slice);
+            self.dispatch(hd, cmd, slice);
+        }
+    }
+
+    // 解密包体，返回 false 表示不再分发（握手包或非法包）
+    fn open_packet(&mut self, hd: ConnId, pkt: &mut NetPacketGuard) -> bool {
+        let session = match self.hd_session_table.get(&hd) {
+            Some(session) => session,
+            None => return true, // 不加密
+        };
+        if let EncryptSession::Established(encryptor) = &mut *session.borrow_mut() {
+            encryptor.decrypt(pkt.body_mut());
+            return true;
+        }
+
+        // 等待握手包
+        if pkt.cmd() == ENCRYPT_HANDSHAKE_CMD && !pkt.body().is_empty() {
+            if let Some(factory) = self.encryptor_factory.as_ref() {
+                let encryptor = factory(pkt.body());
+                self.hd_session_table
+                    .insert(hd, RefCell::new(EncryptSession::Established(encryptor)));
+                log::info!("[hd={}] encrypt handshake done", hd);
+                if self.client_hd == Some(hd) {
+                    self.flush_pending_sends(hd);
+                }
+            }
+            false
         } else {
-            log::error!("[hd={}] send packet failed!!!", hd);
-            return;
+            log::error!(
+                "[hd={}] packet before handshake!!! cmd={}, close conn",
+                hd,
+                pkt.cmd()
+            );
+            let _ = hd.close_with_reason(self.srv_net.as_ref(), CloseReason::HandshakeFailed);
+            false
+        }
+    }
+
+    // 处理压缩协商包，解压包头标记为压缩的包体（已解密），返回 false 表示不再分发
+    fn decompress_packet(&mut self, hd: ConnId, pkt: &mut NetPacketGuard) -> bool {
+        let cmd = pkt.cmd();
+        let recv = self
+            .compression
+            .as_ref()
+            .map_or(false, |compression| compression.session(hd).recv);
+
+        // 协商包总是未压缩格式
+        if !recv && (cmd == COMPRESSION_ADVERTISE_CMD || cmd == COMPRESSION_ACCEPT_CMD) {
+            if let Some(mut reply) = self.on_compression_packet(hd, cmd) {
+                let slice = reply.consume();
+                if hd.send(self.srv_net.as_ref(), slice).is_ok() {
+                    self.record_sent(hd, slice.len());
+                }
+            }
+            return false;
+        }
+
+        if !pkt.is_compressed() {
+            return true;
+        }
+        let body = match &self.compression {
+            Some(compression) => compression.decompress(hd, pkt.body(), self.max_packet_size),
+            None => Err("compression not enabled".to_owned()),
+        };
+        match body {
+            Ok(body) => {
+                let mut plain = take_packet(body.len());
+                plain.set_type(pkt.packet_type());
+                plain.set_cmd(cmd);
+                plain.set_body(&body);
+                *pkt = plain;
+                true
+            }
+            Err(err) => {
+                log::error!(
+                    "[hd={}] decompress cmd={} failed!!! error: {}, close conn",
+                    hd,
+                    cmd,
+                    err
+                );
+                let _ = hd.close_with_reason(self.srv_net.as_ref(), CloseReason::DecompressFailed);
+                false
+            }
+        }
+    }
+
+    fn dispatch(&mut self, hd: ConnId, cmd: CmdId, slice: &[u8]) {
+        self.trace_seq += 1;
+        let trace_id = self.trace_seq;
+
+        // 已被禁用的 cmd 走 default handler
+        let handler_opt = if self.panic_guard.is_disabled(cmd) {
+            None
+        } else {
+            self.handlers.get(&cmd).cloned()
+        };
+
+        // AssertUnwindSafe: 处理函数拿到的 &NetProxy 仍可修改 send_queues / conn_stats / pending_sends 等 RefCell，
+        // panic 时 RefCell 借用随 unwind 释放，但处理函数可能只发出了一部分回包；
+        // on_handler_panic 会关闭该连接并丢弃它还没发出的缓存包，其它连接的状态不受影响。
+        // handler 自己捕获的状态以及连接上的 user data 没有办法复原，由 handler 自行保证一致
+        let proxy: &NetProxy = self;
+        let ret = if let Some(h) = handler_opt {
+            panic::catch_unwind(AssertUnwindSafe(|| (h)(proxy, hd, cmd, slice)))
+        } else {
+            // no-handler(trans), use default handler
+            panic::catch_unwind(AssertUnwindSafe(|| {
+                (proxy.default_handler)(proxy, hd, cmd, slice)
+            }))
+        };
+
+        if let Err(err) = ret {
+            self.on_handler_panic(hd, cmd, trace_id, err);
+        }
+    }
+
+    fn on_handler_panic(
+        &mut self,
+        hd: ConnId,
+        cmd: CmdId,
+        trace_id: u64,
+        err: Box<dyn Any + Send>,
+    ) {
+        let msg = if let Some(s) = err.downcast_ref::<&str>() {
+            s.to_string()
+        } else if let Some(s) = err.downcast_ref::<String>() {
+            s.clone()
+        } else {
+            "unknown".to_owned()
+        };
+
+        // backtrace 由 panic hook 输出
+        log::error!(
+            "[hd={}] handler panic!!! cmd={} trace_id={} count={} error: {}",
+            hd,
+            cmd,
+            trace_id,
+            self.panic_guard.panic_count(cmd) + 1,
+            msg
+        );
+
+        // 只关闭出错的连接，service 继续处理后续任务；handler 中途缓存的回包不再发送
+        let _ = hd.close_with_reason(self.srv_net.as_ref(), CloseReason::HandlerPanic);
+        let dropped = self.send_queues.borrow_mut().remove(hd);
+        if dropped > 0 {
+            log::warn!(
+                "[hd={}] drop {} queued packets after handler panic",
+                hd,
+                dropped
+            );
+        }
+
+        //
+        if self.panic_guard.record(cmd, Instant::now()) {
+            let count = self.panic_guard.panic_count(cmd);
+            log::error!(
+                "cmd={} handler disabled!!! panic count={} policy={:?}",
+                cmd,
+                count,
+                self.panic_guard.policy()
+            );
+            (self.panic_alert_handler)(cmd, count);
+        }
+    }
+
+    ///
+    pub fn set_packet_handler<F>(&mut self, cmd: CmdId, f: F)
+    where
+        F: Fn(&NetProxy, ConnId, CmdId, &[u8]) + Send + Sync + 'static,
+    {
+        self.handlers.insert(cmd, Rc::new(Box::new(f)));
+    }
+
+    ///
+    pub fn set_default_handler<F>(&mut self, f: F)
+    where
+        F: Fn(&NetProxy, ConnId, CmdId, &[u8]) + Send + Sync + 'static,
+    {
+        self.default_handler = Box::new(f);
+    }
+
+    ///
+    pub fn set_panic_policy(&mut self, policy: PanicPolicy) {
+        self.panic_guard.set_policy(policy);
+    }
+
+    /// cmd 被禁用时回调 (cmd, panic count)
+    pub fn set_panic_alert_handler<F>(&mut self, f: F)
+    where
+        F: Fn(CmdId, u64) + Send + Sync + 'static,
+    {
+        self.panic_alert_handler = Box::new(f);
+    }
+
+    ///
+    pub fn panic_count(&self, cmd: CmdId) -> u64 {
+        self.panic_guard.panic_count(cmd)
+    }
+
+    ///
+    pub fn is_cmd_disabled(&self, cmd: CmdId) -> bool {
+        self.panic_guard.is_disabled(cmd)
+    }
+
+    ///
+    pub fn enable_cmd(&mut self, cmd: CmdId) {
+        self.panic_guard.enable(cmd);
+    }
+
+    ///
+    #[inline(always)]
+    pub fn packet_type(&self) -> PacketType {
+        self.packet_type
+    }
+
+    ///
+    #[inline(always)]
+    pub fn max_packet_size(&self) -> usize {
+        self.max_packet_size
+    }
+
+    /// 发送包长度上限（含包头），不应超过接收端的 MAX_PACKET_SIZE
+    pub fn set_max_packet_size(&mut self, max_packet_size: usize) {
+        self.max_packet_size = max_packet_size;
+    }
+
+    ///
+    pub fn set_encrypt_token_handler<F>(&mut self, f: F)
+    where
+        F: Fn(&NetProxy, ConnId) + Send + Sync + 'static,
+    {
+        self.encrypt_token_handler = Box::new(f);
+    }
+
+    /// 启用内置加密握手，包体使用 factory 创建的 Encryptor 加解密
+    pub fn set_encryptor_factory(&mut self, factory: EncryptorFactory) {
+        self.encryptor_factory = Some(factory);
+    }
+
+    /// 启用包体压缩（lz4），协商完成的连接包体超过 threshold_bytes 时压缩，压缩标记在包头中.
+    /// server 在 on_incomming_conn 时通知对端，client 设置后才会选择启用，双方都需要设置；
+    /// 包头没有标记位的方向（客户端发往服务器、WS）总是不压缩
+    pub fn set_compression(&mut self, threshold_bytes: usize) {
+        self.compression = Some(CompressionMiddleware::new(threshold_bytes));
+    }
+
+    ///
+    pub fn set_encrypt_key(&mut self, hd: ConnId, key: Vec<u8>) {
+        let encrypt_opt = self.hd_encrypt_table.get(&hd);
+        if let Some(encrypt) = encrypt_opt {
+            let old_encrypt = encrypt;
+            log::error!(
+                "set [hd={}] encrypt key error!!! already exists {}!!!",
+                hd,
+                Base64::encode(&old_encrypt.borrow().encrypt_key)
+            );
+            self.hd_encrypt_table.remove(&hd);
+        }
+
+        //
+        log::info!("set [hd={}] encrypt key {}", hd, Base64::encode(&key));
+        self.hd_encrypt_table.insert(
+            hd,
+            RefCell::new(EncryptData {
+                no_list: LinkedList::new(),
+                encrypt_key: unsafe { String::from_utf8_unchecked(key) },
+            }),
+        );
+    }
+
+    /// 发送接口线程安全
+    #[inline(always)]
+    pub fn send_raw(&self, hd: ConnId, cmd: CmdId, slice: &[u8]) -> Result<(), SendError> {
+        let mut pkt = take_packet(slice.len());
+        pkt.set_type(self.packet_type);
+        pkt.set_cmd(cmd);
+        pkt.set_body(slice);
+
+        //
+        self.send_packet(hd, pkt)
+    }
+
+    /// 编码 msg 并发送到 conn，超过 max packet size 返回错误
+    pub fn send_proto<M>(&self, conn: &TcpConn, cmd: CmdId, msg: &M) -> Result<(), SendError>
+    where
+        M: prost::Message,
+    {
+        let pkt = self.build_body(cmd, msg)?;
+        self.send_packet(conn.hd, pkt)
+    }
+
+    /// 同 send_proto，只有 hd 时使用
+    pub fn send_proto_hd<M>(&self, hd: ConnId, cmd: CmdId, msg: &M) -> Result<(), SendError>
+    where
+        M: prost::Message,
+    {
+        let pkt = self.build_body(cmd, msg)?;
+        self.send_packet(hd, pkt)
+    }
+
+    /// 每条连接发送队列的容量（包数），0 表示不缓存直接发送
+    pub fn set_send_queue_capacity(&mut self, capacity: usize) {
+        self.send_queues.borrow_mut().set_capacity(capacity);
+    }
+
+    /// 连接发送队列中缓存的包数
+    pub fn send_queue_depth(&self, hd: ConnId) -> usize {
+        self.send_queues.borrow().depth(hd)
+    }
+
+    /// 连接发送队列缓存包数达到 threshold 时回调 (proxy, hd, depth)，上层可以据此限流
+    pub fn set_on_send_queue_high_water<F>(&mut self, threshold: usize, f: F)
+    where
+        F: Fn(&NetProxy, ConnId, usize) + Send + Sync + 'static,
+    {
+        self.send_queues.borrow_mut().set_high_water(threshold);
+        self.send_queue_high_water_handler = Box::new(f);
+    }
+
+    /// 连接可写时按顺序发送缓存的包，返回发送的包数
+    ///
+    /// conn 的待发送字节数降到 SendQueueLimit 的 high watermark 以下即为可写，
+    /// 由 service 在定时器（或 high watermark 回调之后）中调用
+    pub fn on_send_ready(&self, hd: ConnId) -> usize {
+        match self.srv_net.lookup_conn(hd) {
+            Ok(conn) => self.drain_send_queue(&conn),
+            Err(err) => {
+                let dropped = self.send_queues.borrow_mut().remove(hd);
+                log::error!(
+                    "[hd={}] drain send queue failed!!! error: {}, drop {} packets",
+                    hd,
+                    err,
+                    dropped
+                );
+                0
+            }
+        }
+    }
+
+    /// 对所有有缓存包的连接调用 on_send_ready，返回发送的包数
+    pub fn drain_send_queues(&self) -> usize {
+        let conns = self.send_queues.borrow().conns();
+        conns.into_iter().map(|hd| self.on_send_ready(hd)).sum()
+    }
+
+    fn drain_send_queue(&self, conn: &TcpConn) -> usize {
+        let mut count = 0_usize;
+        while conn.is_writable() {
+            let pkt_opt = self.send_queues.borrow_mut().pop(conn.hd);
+            match pkt_opt {
+                Some(pkt) => {
+                    self.send_to_conn(conn, pkt);
+                    count += 1;
+                }
+                None => break,
+            }
+        }
+        count
+    }
+
+    /// on_incomming_conn 时调用 f(proxy, conn)，用于设置 conn 的关联数据（如玩家 session）
+    pub fn set_user_data_init_handler<F>(&mut self, f: F)
+    where
+        F: Fn(&NetProxy, &TcpConn) + Send + Sync + 'static,
+    {
+        self.user_data_init_handler = Some(Box::new(f));
+    }
+
+    /// on_hd_lost 时回调 (proxy, hd, 最后的关联数据)，之后关联数据被清除
+    pub fn set_conn_lost_handler<F>(&mut self, f: F)
+    where
+        F: Fn(&NetProxy, ConnId, Option<Arc<dyn Any + Send + Sync>>) + Send + Sync + 'static,
+    {
+        self.conn_lost_handler = Box::new(f);
+    }
+
+    /// 连接的关联数据，连接不存在、没有设置或类型不符时返回 None
+    pub fn user_data<T>(&self, hd: ConnId) -> Option<Arc<T>>
+    where
+        T: Any + Send + Sync,
+    {
+        self.srv_net.get_conn(hd)?.user_data::<T>()
+    }
+
+    /// 连接收发统计快照，连接不存在（或已断开）返回 None
+    pub fn conn_stats(&self, hd: ConnId) -> Option<ConnStats> {
+        self.conn_stats.borrow().get(&hd).copied()
+    }
+
+    /// 超过 since 没有收到数据的连接
+    pub fn idle_connections(&self, since: Duration) -> Vec<ConnId> {
+        let now = Instant::now();
+        self.conn_stats
+            .borrow()
+            .iter()
+            .filter(|(_, stats)| now.saturating_duration_since(stats.last_recv_at) >= since)
+            .map(|(hd, _)| *hd)
+            .collect()
+    }
+
+    // 没有经过 on_incomming_conn/on_outgoing_conn 的连接以第一次收发的时间作为 connected_at
+    fn record_recv(&self, hd: ConnId, len: usize, now: Instant) {
+        let mut conn_stats = self.conn_stats.borrow_mut();
+        let stats = conn_stats.entry(hd).or_insert_with(|| ConnStats::new(now));
+        stats.bytes_received += len as u64;
+        stats.packets_received += 1;
+        stats.last_recv_at = now;
+    }
+
+    fn record_compressed(&self, hd: ConnId, saved: usize) {
+        let mut conn_stats = self.conn_stats.borrow_mut();
+        let stats = conn_stats
+            .entry(hd)
+            .or_insert_with(|| ConnStats::new(Instant::now()));
+        stats.bytes_saved_by_compression += saved as u64;
+    }
+
+    fn record_sent(&self, hd: ConnId, len: usize) {
+        let mut conn_stats = self.conn_stats.borrow_mut();
+        let stats = conn_stats
+            .entry(hd)
+            .or_insert_with(|| ConnStats::new(Instant::now()));
+        stats.bytes_sent += len as u64;
+        stats.packets_sent += 1;
+    }
+
+    /// 广播：msg 只编码一次，返回成功发送的连接数
+    ///
+    /// 不加密的包类型所有连接共用同一个包；Robot 类型每条连接的序号和密钥不同，只复用包体
+    pub fn send_to_all<M>(
+        &self,
+        conns: &[Arc<TcpConn>],
+        cmd: CmdId,
+        msg: &M,
+    ) -> Result<usize, SendError>
+    where
+        M: prost::Message,
+    {
+        let body = self.build_body(cmd, msg)?;
+        self.broadcast_body(conns, body)
+    }
+
+    /// 加入分组，已在分组中返回 false
+    pub fn add_to_group(&mut self, hd: ConnId, group_id: u64) -> bool {
+        self.groups.entry(group_id).or_default().insert(hd)
+    }
+
+    /// 退出分组，不在分组中返回 false
+    pub fn remove_from_group(&mut self, hd: ConnId, group_id: u64) -> bool {
+        match self.groups.get_mut(&group_id) {
+            Some(members) => {
+                let removed = members.remove(&hd);
+                if members.is_empty() {
+                    self.groups.remove(&group_id);
+                }
+                removed
+            }
+            None => false,
+        }
+    }
+
+    /// 分组中的连接数
+    pub fn group_size(&self, group_id: u64) -> usize {
+        self.groups
+            .get(&group_id)
+            .map_or(0, |members| members.len())
+    }
+
+    /// 广播到分组：pkt 为设置了 cmd 和包体、尚未编码的包，只编码一次，返回成功发送的连接数
+    pub fn broadcast_to_group(
+        &self,
+        group_id: u64,
+        mut pkt: NetPacketGuard,
+    ) -> Result<usize, SendError> {
+        let len = pkt.body().len() + get_packet_header_size(self.packet_type);
+        if len > self.max_packet_size {
+            log::error!(
+                "broadcast cmd={} to group {} failed!!! packet too large: len={} max={}",
+                pkt.cmd(),
+                group_id,
+                len,
+                self.max_packet_size
+            );
+            return Err(SendError::TooLarge {
+                len,
+                max: self.max_packet_size,
+            });
+        }
+
+        let conns: Vec<Arc<TcpConn>> = match self.groups.get(&group_id) {
+            Some(members) => members
+                .iter()
+                .filter_map(|hd| self.srv_net.get_conn(*hd))
+                .collect(),
+            None => Vec::new(),
+        };
+        pkt.set_type(self.packet_type);
+        self.broadcast_body(&conns, pkt)
+    }
+
+    /// 不加密的包类型所有连接共用同一个包；加密、压缩或需要排队时每条连接单独编码包头，只复用包体
+    fn broadcast_body(
+        &self,
+        conns: &[Arc<TcpConn>],
+        mut body: NetPacketGuard,
+    ) -> Result<usize, SendError> {
+        if conns.is_empty() {
+            return Ok(0);
+        }
+        let cmd = body.cmd();
+
+        // 加密连接每条连接的密钥和状态不同，只能复用包体
+        let per_conn = self.send_queues.borrow().capacity() > 0
+            || self.compression.is_some()
+            || matches!(self.packet_type, PacketType::Robot | PacketType::RobotWs)
+            || conns
+                .iter()
+                .any(|conn| self.hd_session_table.contains_key(&conn.hd));
+        if per_conn {
+            let mut count = 0_usize;
+            for conn in conns {
+                let mut pkt = take_packet(body.body().len());
+                pkt.set_type(self.packet_type);
+                pkt.set_cmd(cmd);
+                pkt.set_body(body.body());
+                match self.send_packet(conn.hd, pkt) {
+                    Ok(()) => count += 1,
+                    Err(err) => {
+                        log::error!("[hd={}] broadcast failed!!! error: {}", conn.hd, err);
+                    }
+                }
+            }
+            Ok(count)
+        } else {
+            // 不加密，包头与 hd 无关
+            body.encode_packet(conns[0].hd, &self.hd_encrypt_table);
+            let slice = body.consume();
+            for conn in conns {
+                conn.send(slice);
+                self.record_sent(conn.hd, slice.len());
+            }
+            Ok(conns.len())
+        }
+    }
+
+    /// 编码包体，包体缓冲区来自 packet pool
+    fn build_body<M>(&self, cmd: CmdId, msg: &M) -> Result<NetPacketGuard, SendError>
+    where
+        M: prost::Message,
+    {
+        // 压缩只在包体变小时使用，标记在包头中不占包体，按原包体检查即为发送长度的上限
+        let len = msg.encoded_len() + get_packet_header_size(self.packet_type);
+        if len > self.max_packet_size {
+            log::error!(
+                "send cmd={} failed!!! packet too large: len={} max={}",
+                cmd,
+                len,
+                self.max_packet_size
+            );
+            return Err(SendError::TooLarge {
+                len,
+                max: self.max_packet_size,
+            });
+        }
+
+        let mut pkt = take_packet(msg.encoded_len());
+        pkt.set_type(self.packet_type);
+        pkt.set_cmd(cmd);
+        pkt.set_msg(msg);
+        Ok(pkt)
+    }
+
+    /// 编码完整的包（包体 + 包头），可直接发送
+    #[cfg(test)]
+    fn build_packet<M>(&self, hd: ConnId, cmd: CmdId, msg: &M) -> Result<NetPacketGuard, SendError>
+    where
+        M: prost::Message,
+    {
+        let mut pkt = self.build_body(cmd, msg)?;
+        if self.encode_for(hd, &mut pkt) {
+            Ok(pkt)
+        } else {
+            log::error!("[hd={}] send packet failed!!!", hd);
+            Err(SendError::EncodeFailed(hd))
+        }
+    }
+
+    // 压缩、加密包体（协商、握手完成的连接）后编码包头
+    fn encode_for(&self, hd: ConnId, pkt: &mut NetPacketGuard) -> bool {
+        let compression = self
+            .compression
+            .as_ref()
+            .filter(|_| can_send_compressed(self.packet_type));
+        if let Some(compression) = compression {
+            if let Some((data, saved)) = compression.compress(hd, pkt.body()) {
+                let mut compressed = take_packet(data.len());
+                compressed.set_type(pkt.packet_type());
+                compressed.set_cmd(pkt.cmd());
+                compressed.set_compressed(true);
+                compressed.set_body(&data);
+                *pkt = compressed;
+                self.record_compressed(hd, saved);
+            }
+        }
+        if let Some(session) = self.hd_session_table.get(&hd) {
+            match &mut *session.borrow_mut() {
+                EncryptSession::Established(encryptor) => encryptor.encrypt(pkt.body_mut()),
+                EncryptSession::Pending => {
+                    log::error!("[hd={}] send before handshake!!!", hd);
+                    return false;
+                }
+            }
+        }
+        pkt.encode_packet(hd, &self.hd_encrypt_table)
+    }
+
+    #[inline(always)]
+    fn send_to_conn(&self, conn: &TcpConn, mut pkt: NetPacketGuard) {
+        let slice = pkt.consume();
+        conn.send(slice);
+        self.record_sent(conn.hd, slice.len());
+    }
+
+    /// 编码包头后发送；设置了发送队列容量时，conn 不可写（或已有缓存）的包先入队，
+    /// 队列已满返回 SendError::QueueFull
+    pub fn send_packet(&self, hd: ConnId, mut pkt: NetPacketGuard) -> Result<(), SendError> {
+        if !self.encode_for(hd, &mut pkt) {
+            log::error!("[hd={}] send packet failed!!!", hd);
+            return Err(SendError::EncodeFailed(hd));
+        }
+
+        if self.send_queues.borrow().capacity() == 0 {
+            let slice = pkt.consume();
+            hd.send(self.srv_net.as_ref(), slice)
+                .map_err(SendError::Conn)?;
+            self.record_sent(hd, slice.len());
+            return Ok(());
+        }
+
+        let conn = self.srv_net.lookup_conn(hd).map_err(|err| {
+            self.send_queues.borrow_mut().remove(hd);
+            SendError::Conn(err)
+        })?;
+
+        // 先发送之前缓存的包，保证顺序
+        self.drain_send_queue(&conn);
+        if 0 == self.send_queue_depth(hd) && conn.is_writable() {
+            self.send_to_conn(&conn, pkt);
+            return Ok(());
+        }
+
+        let event = self.send_queues.borrow_mut().push(hd, pkt);
+        match event {
+            SendQueueEvent::Queued => Ok(()),
+            SendQueueEvent::HighWatermark(depth) => {
+                log::warn!("[hd={}] send queue high water: {} packets", hd, depth);
+                (self.send_queue_high_water_handler)(self, hd, depth);
+                Ok(())
+            }
+            SendQueueEvent::Overflow(depth) => {
+                log::error!("[hd={}] send queue full: {} packets!!!", hd, depth);
+                Err(SendError::QueueFull)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service_net::XorEncryptor;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    fn new_proxy() -> NetProxy {
+        let srv_net = Arc::new(ServiceNetRs::new(0));
+        NetProxy::new(PacketType::Server, &srv_net)
+    }
+
+    #[test]
+    fn panic_drops_queued_packets_of_offending_conn() {
+        let mut proxy = new_proxy();
+        proxy.set_send_queue_capacity(8);
+        let (bad, good) = (ConnId::from(1), ConnId::from(2));
+        proxy.set_packet_handler(1, |proxy, hd, _, _| {
+            // 回包只缓存了一半
+            proxy.send_queues.borrow_mut().push(hd, take_packet(8));
+            panic!("bad packet");
+        });
+        proxy.send_queues.borrow_mut().push(good, take_packet(8));
+
+        proxy.dispatch(bad, 1, &[]);
+        assert_eq!(proxy.panic_count(1), 1);
+        assert_eq!(proxy.send_queue_depth(bad), 0);
+        assert_eq!(proxy.send_queue_depth(good), 1);
+    }
+
+    #[test]
+    fn panic_closes_only_offending_conn() {
+        use crate::ListenerHandle;
+        use crate::{listen_tcp_addr, proc_service_ready, start_network, start_service};
+        use std::io::{Read, Write};
+
+        let srv_net: &'static Arc<ServiceNetRs> =
+            Box::leak(Box::new(Arc::new(ServiceNetRs::new(4004))));
+        let ready_pair = start_service(srv_net.as_ref(), "test_net", || {});
+        assert!(proc_service_ready(srv_net.as_ref(), ready_pair));
+        start_network(srv_net);
+
+        let handled = Arc::new(AtomicU64::new(0));
+        let conns = Arc::new(parking_lot::Mutex::new(hashbrown::HashMap::new()));
+        let closed = Arc::new(parking_lot::Mutex::new(Vec::new()));
+
+        // proxy 在 srv_net 线程中创建和使用
+        let (handled2, conns2) = (handled.clone(), conns.clone());
+        let conn_fn = move |conn: Arc<TcpConn>| {
+            let hd = conn.hd;
+            conns2.lock().insert(hd, conn);
+            G_PROXY.with(|g| {
+                let mut proxy_opt = g.borrow_mut();
+                let proxy = proxy_opt.get_or_insert_with(|| {
+                    let mut proxy = NetProxy::new(PacketType::Server, srv_net);
+                    proxy.set_packet_handler(1, |_, _, _, _| panic!("bad packet"));
+                    let handled = handled2.clone();
+                    proxy.set_packet_handler(2, move |_, _, _, _| {
+                        handled.fetch_add(1, Ordering::Relaxed);
+                    });
+                    proxy
+                });
+                proxy.on_incomming_conn(hd, false);
+            });
+        };
+        let pkt_fn = |hd: ConnId, pkt: NetPacketGuard| {
+            G_PROXY.with(|g| g.borrow_mut().as_mut().unwrap().on_net_packet(hd, pkt));
+        };
+        let (conns3, closed2) = (conns.clone(), closed.clone());
+        let close_fn = move |hd: ConnId| {
+            let reason = conns3.lock().remove(&hd).unwrap().close_reason();
+            G_PROXY.with(|g| {
+                let mut proxy_opt = g.borrow_mut();
+                let proxy = proxy_opt.as_mut().unwrap();
+                closed2.lock().push((hd, reason, proxy.panic_count(1)));
+                proxy.on_hd_lost(hd);
+            });
+        };
+        let id = listen_tcp_addr(
+            srv_net,
+            "127.0.0.1".to_owned(),
+            0,
+            PacketType::Server,
+            conn_fn,
+            pkt_fn,
+            close_fn,
+            srv_net,
+        );
+        let addr = ListenerHandle::new(srv_net, id).local_addr().unwrap();
+
+        let wait_until = |f: &dyn Fn() -> bool| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while !f() {
+                assert!(Instant::now() < deadline, "wait timeout");
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        };
+        let wire = |cmd: CmdId| {
+            let ping = Ping {
+                seq: 1,
+                text: "hello".to_owned(),
+            };
+            new_proxy()
+                .build_packet(ConnId::from(0), cmd, &ping)
+                .unwrap()
+                .consume()
+                .to_vec()
+        };
+
+        let mut bad = std::net::TcpStream::connect(addr).unwrap();
+        let mut good = std::net::TcpStream::connect(addr).unwrap();
+        wait_until(&|| conns.lock().len() == 2);
+        let bad_hd = *conns
+            .lock()
+            .iter()
+            .find(|(_, conn)| conn.remote_addr() == bad.local_addr().unwrap())
+            .unwrap()
+            .0;
+
+        bad.write_all(&wire(2)).unwrap();
+        good.write_all(&wire(2)).unwrap();
+        wait_until(&|| handled.load(Ordering::Relaxed) == 2);
+
+        // 只关闭出错的连接，close_fn 带上 HandlerPanic
+        bad.write_all(&wire(1)).unwrap();
+        wait_until(&|| !closed.lock().is_empty());
+        assert_eq!(*closed.lock(), vec![(bad_hd, CloseReason::HandlerPanic, 1)]);
+        bad.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut buf = [0_u8; 8];
+        assert!(matches!(bad.read(&mut buf), Ok(0) | Err(_)));
+
+        // 其他连接继续正常处理
+        good.write_all(&wire(2)).unwrap();
+        wait_until(&|| handled.load(Ordering::Relaxed) == 3);
+        assert_eq!(closed.lock().len(), 1);
+        assert_eq!(conns.lock().len(), 1);
+    }
+
+    #[test]
+    fn escalation_disables_cmd_and_alerts_once() {
+        let mut proxy = new_proxy();
+        let alerts = Arc::new(AtomicU64::new(0));
+        let fallback = Arc::new(AtomicU64::new(0));
+
+        proxy.set_panic_policy(PanicPolicy {
+            threshold: 3,
+            window: Duration::from_secs(60),
+        });
+        proxy.set_packet_handler(1, |_, _, _, _| panic!("bad packet"));
+        let fallback2 = fallback.clone();
+        proxy.set_default_handler(move |_, _, _, _| {
+            fallback2.fetch_add(1, Ordering::Relaxed);
+        });
+        let alerts2 = alerts.clone();
+        proxy.set_panic_alert_handler(move |cmd, _count| {
+            assert_eq!(cmd, 1);
+            alerts2.fetch_add(1, Ordering::Relaxed);
+        });
+
+        for i in 0..3 {
+            proxy.dispatch(ConnId::from(i), 1, &[]);
+        }
+        assert!(!proxy.is_cmd_disabled(1));
+        assert_eq!(alerts.load(Ordering::Relaxed), 0);
+
+        // threshold exceeded
+        proxy.dispatch(ConnId::from(3), 1, &[]);
+        assert!(proxy.is_cmd_disabled(1));
+        assert_eq!(proxy.panic_count(1), 4);
+        assert_eq!(alerts.load(Ordering::Relaxed), 1);
+
+        // routed to fallback, no more panics or alerts
+        proxy.dispatch(ConnId::from(4), 1, &[]);
+        assert_eq!(fallback.load(Ordering::Relaxed), 1);
+        assert_eq!(proxy.panic_count(1), 4);
+        assert_eq!(alerts.load(Ordering::Relaxed), 1);
+
+        // re-enable, escalate again -> alert again
+        proxy.enable_cmd(1);
+        for i in 0..4 {
+            proxy.dispatch(ConnId::from(10 + i), 1, &[]);
+        }
+        assert!(proxy.is_cmd_disabled(1));
+        assert_eq!(alerts.load(Ordering::Relaxed), 2);
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct Ping {
+        #[prost(uint32, tag = "1")]
+        seq: u32,
+        #[prost(string, tag = "2")]
+        text: String,
+    }
+
+    #[test]
+    fn send_proto_loopback() {
+        let mut proxy = new_proxy();
+        let received = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let received2 = received.clone();
+        proxy.set_packet_handler(9, move |_, hd, cmd, slice| {
+            let msg = <Ping as prost::Message>::decode(slice).unwrap();
+            received2.lock().push((hd, cmd, msg));
+        });
+
+        // pkt_fn 跨线程投递收到的 pkt
+        let (tx, rx) = std::sync::mpsc::channel();
+        let tx = parking_lot::Mutex::new(tx);
+        let pkt_fn: Arc<dyn Fn(ConnId, NetPacketGuard) + Send + Sync> =
+            Arc::new(move |hd, pkt| tx.lock().send((hd, pkt)).unwrap());
+
+        let hd = ConnId::from(5);
+        let ping = Ping {
+            seq: 7,
+            text: "hello".to_owned(),
+        };
+        let mut out = proxy.build_packet(hd, 9, &ping).unwrap();
+        let wire = out.consume().to_vec();
+
+        // 模拟对端收包
+        let mut input = take_packet(wire.len());
+        input.set_type(PacketType::Server);
+        input.append_slice(&wire);
+        (pkt_fn)(hd, input);
+
+        let (hd2, pkt) = rx.recv().unwrap();
+        proxy.on_net_packet(hd2, pkt);
+
+        let received = received.lock();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0], (hd, 9, ping));
+    }
+
+    #[test]
+    fn send_proto_too_large() {
+        let mut proxy = new_proxy();
+        proxy.set_max_packet_size(16);
+
+        let ping = Ping {
+            seq: 1,
+            text: "x".repeat(32),
+        };
+        match proxy.build_packet(ConnId::from(1), 9, &ping) {
+            Err(SendError::TooLarge { len, max }) => {
+                assert_eq!(max, 16);
+                assert!(len > max);
+            }
+            _ => panic!("expect TooLarge"),
+        }
+
+        let small = Ping {
+            seq: 1,
+            text: String::new(),
+        };
+        assert!(proxy.build_packet(ConnId::from(1), 9, &small).is_ok());
+    }
+
+    // 模拟对端收包
+    fn recv_wire(proxy: &mut NetProxy, hd: ConnId, wire: &[u8]) {
+        let mut input = take_packet(wire.len());
+        input.set_type(proxy.packet_type());
+        input.append_slice(wire);
+        proxy.on_net_packet(hd, input);
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|w| w == needle)
+    }
+
+    fn encrypted_proxy(received: &Arc<parking_lot::Mutex<Vec<Ping>>>) -> NetProxy {
+        let mut proxy = new_proxy();
+        proxy.set_encryptor_factory(XorEncryptor::factory());
+        let received = received.clone();
+        proxy.set_packet_handler(9, move |_, _, _, slice| {
+            received
+                .lock()
+                .push(<Ping as prost::Message>::decode(slice).unwrap());
+        });
+        proxy
+    }
+
+    #[test]
+    fn encrypt_handshake_round_trip() {
+        let srv_received = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let cli_received = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let mut srv = encrypted_proxy(&srv_received);
+        let mut cli = encrypted_proxy(&cli_received);
+        let hd = ConnId::from(1);
+
+        // server 推送密钥
+        let mut handshake = srv.start_handshake(hd).unwrap();
+        let wire = handshake.consume().to_vec();
+        cli.on_outgoing_conn(hd, true);
+        assert!(!cli.is_handshake_done(hd));
+        recv_wire(&mut cli, hd, &wire);
+        assert!(cli.is_handshake_done(hd));
+        assert!(cli_received.lock().is_empty());
+
+        let ping = Ping {
+            seq: 42,
+            text: "plaintext secret message".to_owned(),
+        };
+        let plain = prost::Message::encode_to_vec(&ping);
+        for _ in 0..3 {
+            // server -> client
+            let wire = srv.build_packet(hd, 9, &ping).unwrap().consume().to_vec();
+            assert!(!contains(&wire, &plain));
+            assert!(!contains(&wire, ping.text.as_bytes()));
+            recv_wire(&mut cli, hd, &wire);
+
+            // client -> server
+            let wire = cli.build_packet(hd, 9, &ping).unwrap().consume().to_vec();
+            assert!(!contains(&wire, &plain));
+            recv_wire(&mut srv, hd, &wire);
+        }
+        assert_eq!(*cli_received.lock(), vec![ping.clone(); 3]);
+        assert_eq!(*srv_received.lock(), vec![ping; 3]);
+    }
+
+    #[test]
+    fn packet_before_handshake_rejected() {
+        let received = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let mut cli = encrypted_proxy(&received);
+        let plain_srv = new_proxy();
+        let hd = ConnId::from(2);
+        cli.on_outgoing_conn(hd, true);
+
+        let ping = Ping {
+            seq: 1,
+            text: "early".to_owned(),
+        };
+        let wire = plain_srv
+            .build_packet(hd, 9, &ping)
+            .unwrap()
+            .consume()
+            .to_vec();
+        recv_wire(&mut cli, hd, &wire);
+        recv_wire(&mut cli, hd, &wire);
+        assert!(received.lock().is_empty());
+        assert!(!cli.is_handshake_done(hd));
+
+        // 握手前不能发送
+        assert_eq!(
+            cli.build_packet(hd, 9, &ping).err(),
+            Some(SendError::EncodeFailed(hd))
+        );
+    }
+
+    fn compressed_proxy(received: &Arc<parking_lot::Mutex<Vec<Ping>>>, opt_in: bool) -> NetProxy {
+        let mut proxy = new_proxy();
+        if opt_in {
+            proxy.set_compression(crate::service_net::DEFAULT_COMPRESSION_THRESHOLD);
+        }
+        let received = received.clone();
+        proxy.set_packet_handler(9, move |_, _, _, slice| {
+            received
+                .lock()
+                .push(<Ping as prost::Message>::decode(slice).unwrap());
+        });
+        proxy
+    }
+
+    #[test]
+    fn compression_negotiated_round_trip() {
+        let srv_received = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let cli_received = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let mut srv = compressed_proxy(&srv_received, true);
+        let mut cli = compressed_proxy(&cli_received, true);
+        let hd = ConnId::from(1);
+
+        // server 通知支持压缩，client 回复启用，server 确认
+        let advertise = srv.start_compression(hd).unwrap();
+        assert_eq!(
+            advertise.peek().len(),
+            get_packet_header_size(PacketType::Server)
+        );
+        let accept = cli
+            .on_compression_packet(hd, COMPRESSION_ADVERTISE_CMD)
+            .unwrap();
+        assert_eq!(
+            accept.peek().len(),
+            get_packet_header_size(PacketType::Server)
+        );
+        assert!(!cli.is_compression_negotiated(hd));
+        let ack = srv
+            .on_compression_packet(hd, COMPRESSION_ACCEPT_CMD)
+            .unwrap();
+        assert_eq!(ack.peek().len(), get_packet_header_size(PacketType::Server));
+        assert!(srv.is_compression_negotiated(hd));
+        assert!(cli
+            .on_compression_packet(hd, COMPRESSION_ACCEPT_CMD)
+            .is_none());
+        assert!(cli.is_compression_negotiated(hd));
+
+        let large = Ping {
+            seq: 7,
+            text: "world snapshot ".repeat(300),
+        };
+        let small = Ping {
+            seq: 8,
+            text: "hi".to_owned(),
+        };
+        let plain = prost::Message::encode_to_vec(&large);
+        for _ in 0..2 {
+            // server -> client
+            let wire = srv.build_packet(hd, 9, &large).unwrap().consume().to_vec();
+            assert!(wire.len() < plain.len() / 4);
+            assert_ne!(wire[0] & 0x80, 0);
+            recv_wire(&mut cli, hd, &wire);
+
+            // client -> server，小包不压缩，包头没有压缩标记
+            let wire = cli.build_packet(hd, 9, &small).unwrap().consume().to_vec();
+            let small_len = prost::Message::encoded_len(&small);
+            assert_eq!(
+                wire.len(),
+                get_packet_header_size(PacketType::Server) + small_len
+            );
+            assert_eq!(wire[0] & 0x80, 0);
+            recv_wire(&mut srv, hd, &wire);
+        }
+        assert_eq!(*cli_received.lock(), vec![large.clone(); 2]);
+        assert_eq!(*srv_received.lock(), vec![small.clone(); 2]);
+
+        let saved = srv.conn_stats(hd).unwrap().bytes_saved_by_compression;
+        assert!(saved > plain.len() as u64);
+        assert_eq!(cli.conn_stats(hd).unwrap().bytes_saved_by_compression, 0);
+
+        // 未协商的连接收到压缩包不再分发
+        let wire = srv.build_packet(hd, 9, &large).unwrap().consume().to_vec();
+        let mut other = compressed_proxy(&cli_received, true);
+        recv_wire(&mut other, hd, &wire);
+        assert_eq!(cli_received.lock().len(), 2);
+
+        srv.on_hd_lost(hd);
+        assert!(!srv.is_compression_negotiated(hd));
+    }
+
+    #[test]
+    fn compression_flag_does_not_grow_max_size_packet() {
+        let received = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let mut srv = compressed_proxy(&received, true);
+        let mut cli = compressed_proxy(&received, true);
+        srv.set_max_packet_size(1024);
+        let hd = ConnId::from(1);
+        srv.compression.as_mut().unwrap().session_mut(hd).send = true;
+
+        // 不可压缩、刚好达到上限的包体
+        let mut seed = 0x2545_F491_u32;
+        let mut text = String::new();
+        let mut ping = Ping {
+            seq: 1,
+            text: String::new(),
+        };
+        while prost::Message::encoded_len(&ping) + get_packet_header_size(PacketType::Server) < 1024
+        {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            text.push((b'a' + (seed % 26) as u8) as char);
+            ping.text = text.clone();
+        }
+        let wire = srv.build_packet(hd, 9, &ping).unwrap().consume().to_vec();
+        assert_eq!(wire.len(), 1024);
+        assert_eq!(wire[0] & 0x80, 0);
+        recv_wire(&mut cli, hd, &wire);
+        assert_eq!(*received.lock(), vec![ping.clone()]);
+
+        ping.text.push('a');
+        assert!(matches!(
+            srv.build_packet(hd, 9, &ping),
+            Err(SendError::TooLarge {
+                len: 1025,
+                max: 1024
+            })
+        ));
+    }
+
+    #[test]
+    fn compression_opt_out() {
+        let srv_received = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let cli_received = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let mut srv = compressed_proxy(&srv_received, true);
+        let mut cli = compressed_proxy(&cli_received, false);
+        let hd = ConnId::from(1);
+
+        // client 没有启用压缩：advertise 不分发也不回复
+        let advertise = srv.start_compression(hd).unwrap().consume().to_vec();
+        recv_wire(&mut cli, hd, &advertise);
+        assert!(cli_received.lock().is_empty());
+        assert!(cli
+            .on_compression_packet(hd, COMPRESSION_ADVERTISE_CMD)
+            .is_none());
+
+        // 未协商的连接不压缩
+        let large = Ping {
+            seq: 7,
+            text: "world snapshot ".repeat(300),
+        };
+        let wire = srv.build_packet(hd, 9, &large).unwrap().consume().to_vec();
+        assert!(wire.len() > prost::Message::encoded_len(&large));
+        recv_wire(&mut cli, hd, &wire);
+        assert_eq!(*cli_received.lock(), vec![large]);
+
+        // 没有通知过的连接发来的 accept 被忽略
+        let other = ConnId::from(2);
+        assert!(srv
+            .on_compression_packet(other, COMPRESSION_ACCEPT_CMD)
+            .is_none());
+        assert!(!srv.is_compression_negotiated(other));
+    }
+
+    #[test]
+    fn panic_window_expires() {
+        let mut guard = HandlerPanicGuard::new(PanicPolicy {
+            threshold: 2,
+            window: Duration::from_millis(100),
+        });
+        let now = Instant::now();
+        assert!(!guard.record(7, now));
+        assert!(!guard.record(7, now));
+        assert!(!guard.record(7, now + Duration::from_millis(500)));
+        assert!(!guard.is_disabled(7));
+        assert!(!guard.record(7, now + Duration::from_millis(510)));
+        assert!(guard.record(7, now + Duration::from_millis(520)));
+        assert_eq!(guard.panic_count(7), 5);
+    }
+
+    #[test]
+    fn client_sends_buffered_until_reconnect() {
+        let mut proxy = new_proxy();
+        let ping = Ping {
+            seq: 1,
+            text: "hello".to_owned(),
+        };
+        assert_eq!(
+            proxy.send_proto_to_server(9, &ping),
+            Err(SendError::NotClient)
+        );
+
+        // 未连上时缓存
+        proxy.client_mode = true;
+        proxy.send_proto_to_server(9, &ping).unwrap();
+        assert_eq!(proxy.pending_send_count(), 1);
+
+        let hd1 = ConnId::from(1);
+        proxy.on_outgoing_conn(hd1, false);
+        assert_eq!(proxy.pending_send_count(), 0);
+
+        // 断线期间缓存，重连成功后发送
+        proxy.on_hd_lost(hd1);
+        proxy.send_proto_to_server(9, &ping).unwrap();
+        proxy.send_proto_to_server(10, &ping).unwrap();
+        assert_eq!(proxy.pending_send_count(), 2);
+        proxy.on_hd_lost(ConnId::from(7));
+        assert_eq!(proxy.pending_send_count(), 2);
+
+        let hd2 = ConnId::from(2);
+        proxy.on_outgoing_conn(hd2, false);
+        assert_eq!(proxy.client_hd, Some(hd2));
+        assert_eq!(proxy.pending_send_count(), 0);
+
+        // 缓存达到上限后拒绝，连上后恢复
+        proxy.set_max_pending_sends(2);
+        proxy.on_hd_lost(hd2);
+        proxy.send_proto_to_server(9, &ping).unwrap();
+        proxy.send_proto_to_server(9, &ping).unwrap();
+        assert_eq!(
+            proxy.send_proto_to_server(9, &ping),
+            Err(SendError::QueueFull)
+        );
+        assert_eq!(proxy.pending_send_count(), 2);
+        proxy.on_outgoing_conn(ConnId::from(3), false);
+        assert_eq!(proxy.pending_send_count(), 0);
+        proxy.on_hd_lost(ConnId::from(3));
+        proxy.send_proto_to_server(9, &ping).unwrap();
+        assert_eq!(proxy.pending_send_count(), 1);
+    }
+
+    #[test]
+    fn client_reconnect_exhausted() {
+        let received = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let mut proxy = encrypted_proxy(&received);
+        let exhausted = Arc::new(AtomicU64::new(0));
+        let exhausted2 = exhausted.clone();
+        proxy.set_reconnect_exhausted_handler(move |_, hd| {
+            exhausted2.store(hd.raw(), Ordering::Relaxed);
+        });
+        proxy.client_mode = true;
+
+        // 握手完成前缓存
+        let hd = ConnId::from(3);
+        let ping = Ping {
+            seq: 1,
+            text: "early".to_owned(),
+        };
+        proxy.on_outgoing_conn(hd, true);
+        proxy.send_proto_to_server(9, &ping).unwrap();
+        assert_eq!(proxy.pending_send_count(), 1);
+
+        proxy.on_hd_lost(hd);
+        proxy.on_reconnect_exhausted(hd);
+        assert_eq!(exhausted.load(Ordering::Relaxed), hd.raw());
+        assert_eq!(proxy.pending_send_count(), 0);
+        assert_eq!(
+            proxy.send_proto_to_server(9, &ping),
+            Err(SendError::NotClient)
+        );
+    }
+
+    #[test]
+    fn groups() {
+        let mut proxy = new_proxy();
+        let hd1 = ConnId::from(1);
+        let hd2 = ConnId::from(2);
+
+        assert!(proxy.add_to_group(hd1, 100));
+        assert!(!proxy.add_to_group(hd1, 100));
+        assert!(proxy.add_to_group(hd2, 100));
+        assert!(proxy.add_to_group(hd1, 200));
+        assert_eq!(proxy.group_size(100), 2);
+        assert_eq!(proxy.group_size(200), 1);
+
+        assert!(proxy.remove_from_group(hd2, 100));
+        assert!(!proxy.remove_from_group(hd2, 100));
+        assert!(!proxy.remove_from_group(hd2, 300));
+        assert_eq!(proxy.group_size(100), 1);
+
+        // 断线后从所有分组移除
+        proxy.on_hd_lost(hd1);
+        assert_eq!(proxy.group_size(100), 0);
+        assert_eq!(proxy.group_size(200), 0);
+        assert!(proxy.groups.is_empty());
+
+        // 分组中没有存活的连接
+        proxy.add_to_group(hd2, 100);
+        let mut pkt = take_packet(4);
+        pkt.set_cmd(9);
+        pkt.set_body(b"data");
+        assert_eq!(proxy.broadcast_to_group(100, pkt), Ok(0));
+
+        proxy.set_max_packet_size(8);
+        let mut pkt = take_packet(4);
+        pkt.set_cmd(9);
+        pkt.set_body(b"data");
+        assert_eq!(
+            proxy.broadcast_to_group(100, pkt),
+            Err(SendError::TooLarge { len: 10, max: 8 })
+        );
+    }
+
+    #[test]
+    fn conn_stats() {
+        let mut proxy = new_proxy();
+        let peer = new_proxy();
+        let hd = ConnId::from(1);
+        assert_eq!(proxy.conn_stats(hd), None);
+
+        proxy.on_outgoing_conn(hd, false);
+        let stats = proxy.conn_stats(hd).unwrap();
+        assert_eq!(stats.packets_received, 0);
+        assert_eq!(stats.last_recv_at, stats.connected_at);
+
+        let ping = Ping {
+            seq: 1,
+            text: "hello".to_owned(),
+        };
+        let wire = peer.build_packet(hd, 9, &ping).unwrap().consume().to_vec();
+        recv_wire(&mut proxy, hd, &wire);
+        recv_wire(&mut proxy, hd, &wire);
+        let stats = proxy.conn_stats(hd).unwrap();
+        assert_eq!(stats.packets_received, 2);
+        assert_eq!(stats.bytes_received, 2 * wire.len() as u64);
+        assert!(stats.last_recv_at >= stats.connected_at);
+
+        // hd 不存在，发送失败不计入
+        assert!(proxy.send_proto_hd(hd, 9, &ping).is_err());
+        assert_eq!(proxy.conn_stats(hd).unwrap().packets_sent, 0);
+
+        assert_eq!(proxy.idle_connections(Duration::ZERO), vec![hd]);
+        assert!(proxy.idle_connections(Duration::from_secs(60)).is_empty());
+
+        proxy.on_hd_lost(hd);
+        assert_eq!(proxy.conn_stats(hd), None);
+        assert!(proxy.idle_connections(Duration::ZERO).is_empty());
+    }
+
+    #[derive(Debug)]
+    struct Session {
+        name: String,
+    }
+
+    thread_local! {
+        static G_PROXY: RefCell<Option<NetProxy>> = RefCell::new(None);
+    }
+
+    #[test]
+    fn user_data_from_connect_to_packet_handler() {
+        use crate::ListenerHandle;
+        use crate::{listen_tcp_addr, proc_service_ready, start_network, start_service};
+        use std::io::Write;
+
+        let srv_net: &'static Arc<ServiceNetRs> =
+            Box::leak(Box::new(Arc::new(ServiceNetRs::new(4002))));
+        let ready_pair = start_service(srv_net.as_ref(), "test_net", || {});
+        assert!(proc_service_ready(srv_net.as_ref(), ready_pair));
+        start_network(srv_net);
+
+        let received = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let lost = Arc::new(parking_lot::Mutex::new(Vec::new()));
+
+        // proxy 在 srv_net 线程中创建和使用
+        let (received2, lost2) = (received.clone(), lost.clone());
+        let conn_fn = move |conn: Arc<TcpConn>| {
+            let hd = conn.hd;
+            G_PROXY.with(|g| {
+                let mut proxy_opt = g.borrow_mut();
+                let proxy = proxy_opt.get_or_insert_with(|| {
+                    let mut proxy = NetProxy::new(PacketType::Server, srv_net);
+                    proxy.set_user_data_init_handler(|_, conn| {
+                        conn.set_user_data(Arc::new(Session {
+                            name: std::format!("session-{}", conn.hd),
+                        }));
+                    });
+                    let received = received2.clone();
+                    proxy.set_packet_handler(9, move |proxy, hd, _, _| {
+                        // 类型不符返回 None
+                        assert!(proxy.user_data::<String>(hd).is_none());
+                        let session = proxy.user_data::<Session>(hd).unwrap();
+                        received.lock().push(session.name.clone());
+                    });
+                    let lost = lost2.clone();
+                    proxy.set_conn_lost_handler(move |_, hd, user_data| {
+                        let session = user_data.and_then(|data| data.downcast::<Session>().ok());
+                        lost.lock().push((hd, session));
+                    });
+                    proxy
+                });
+                proxy.on_incomming_conn(hd, false);
+            });
+        };
+        let pkt_fn = |hd: ConnId, pkt: NetPacketGuard| {
+            G_PROXY.with(|g| g.borrow_mut().as_mut().unwrap().on_net_packet(hd, pkt));
+        };
+        let close_fn = |hd: ConnId| {
+            G_PROXY.with(|g| g.borrow_mut().as_mut().unwrap().on_hd_lost(hd));
+        };
+        let id = listen_tcp_addr(
+            srv_net,
+            "127.0.0.1".to_owned(),
+            0,
+            PacketType::Server,
+            conn_fn,
+            pkt_fn,
+            close_fn,
+            srv_net,
+        );
+        let addr = ListenerHandle::new(srv_net, id).local_addr().unwrap();
+
+        let ping = Ping {
+            seq: 1,
+            text: "hello".to_owned(),
+        };
+        let wire = new_proxy()
+            .build_packet(ConnId::from(0), 9, &ping)
+            .unwrap()
+            .consume()
+            .to_vec();
+        let mut client = std::net::TcpStream::connect(addr).unwrap();
+        client.write_all(&wire).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while received.lock().is_empty() {
+            assert!(Instant::now() < deadline, "wait packet timeout");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        // 断开后关联数据交给 conn_lost_handler，conn 中已清除
+        drop(client);
+        while lost.lock().is_empty() {
+            assert!(Instant::now() < deadline, "wait close timeout");
+            std::thread::sleep(Duration::from_millis(5));
         }
+        let (hd, session) = lost.lock().pop().unwrap();
+        let session = session.unwrap();
+        assert_eq!(session.name, std::format!("session-{}", hd));
+        assert_eq!(*received.lock(), vec![session.name.clone()]);
+        assert_eq!(Arc::strong_count(&session), 1);
     }
 }
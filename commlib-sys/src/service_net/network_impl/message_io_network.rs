@@ -1,9 +1,10 @@
 use parking_lot::RwLock;
 use std::sync::Arc;
 
+use crate::service_net::on_udp_message;
 use crate::{ConnId, ServiceNetRs, TcpClient, TcpHandler, TcpListenerId, TcpServer};
 
-use message_io::network::{NetEvent, Transport};
+use message_io::network::{Endpoint, NetEvent, ResourceId, Transport};
 use message_io::node::{split, NodeHandler, NodeListener, NodeTask};
 
 /// message io
@@ -73,7 +74,7 @@ impl MessageIoNetwork {
             Ok((endpoint, sock_addr)) => {
                 //
                 let raw_id = endpoint.resource_id().raw();
-                let hd = ConnId::from(raw_id);
+                let hd = tcp_client.srv_net.alloc_conn_id(raw_id);
                 log::info!(
                     "[hd={}] client connected, raddr: {} sock_addr: {}",
                     hd,
@@ -81,9 +82,9 @@ impl MessageIoNetwork {
                     sock_addr
                 );
 
-                // call on_connected directly
+                // call on_connected directly: endpoint 为对端地址，sock_addr 为本端地址
                 let on_connected = self.tcp_handler.on_connected;
-                on_connected(tcp_client_ptr, hd, sock_addr.into());
+                on_connected(tcp_client_ptr, hd, endpoint.addr().into(), sock_addr.into());
 
                 //
                 Ok(hd)
@@ -99,6 +100,42 @@ impl MessageIoNetwork {
         }
     }
 
+    /// Bind udp socket at addr
+    pub fn listen_udp(&self, addr: &str) -> Result<(ResourceId, std::net::SocketAddr), String> {
+        match self.node_handler.network().listen(Transport::Udp, addr) {
+            Ok((id, sock_addr)) => {
+                log::info!("network udp listening at {}", sock_addr);
+                Ok((id, sock_addr))
+            }
+            Err(err) => {
+                log::error!(
+                    "network udp listening at {} failed!!! error {:?}",
+                    addr,
+                    err
+                );
+                Err(err.to_string())
+            }
+        }
+    }
+
+    /// Create udp socket connected to raddr
+    pub fn connect_udp(&self, raddr: &str) -> Result<Endpoint, String> {
+        match self.node_handler.network().connect(Transport::Udp, raddr) {
+            Ok((endpoint, sock_addr)) => {
+                log::info!("udp socket {} connected to raddr: {}", sock_addr, raddr);
+                Ok(endpoint)
+            }
+            Err(err) => {
+                log::error!(
+                    "Could not connect udp to raddr: {}!!! error: {}",
+                    raddr,
+                    err
+                );
+                Err(err.to_string())
+            }
+        }
+    }
+
     ///
     pub fn stop(&self) {
         self.node_handler.stop();
@@ -150,7 +187,9 @@ impl MessageIoNetwork {
                 NetEvent::Connected(endpoint, handshake) => {
                     // just log
                     let raw_id = endpoint.resource_id().raw();
-                    let hd = ConnId::from(raw_id);
+                    let hd = srv_net
+                        .resolve_conn_id(raw_id)
+                        .unwrap_or_else(|| ConnId::from_raw(raw_id));
                     log::info!(
                         "[hd={}] {} endpoint {} handshake={}.",
                         hd,
@@ -163,7 +202,7 @@ impl MessageIoNetwork {
                 NetEvent::Accepted(endpoint, id) => {
                     //
                     let raw_id = endpoint.resource_id().raw();
-                    let hd = ConnId::from(raw_id);
+                    let hd = srv_net.alloc_conn_id(raw_id);
                     let listener_id = TcpListenerId::from(id.raw());
                     log::info!(
                         "[hd={}] {} endpoint {} accepted, listener_id={}",
@@ -186,7 +225,17 @@ impl MessageIoNetwork {
                 NetEvent::Message(endpoint, input_data) => {
                     //
                     let raw_id = endpoint.resource_id().raw();
-                    let hd = ConnId::from(raw_id);
+                    if srv_net.is_udp_resource(raw_id) {
+                        on_udp_message(&srv_net, endpoint, input_data);
+                        return;
+                    }
+                    let hd = match srv_net.resolve_conn_id(raw_id) {
+                        Some(hd) => hd,
+                        None => {
+                            log::error!("[raw_id={}] message from unknown resource!!!", raw_id);
+                            return;
+                        }
+                    };
 
                     //
                     (on_message)(srv_net_ptr, hd, input_data.as_ptr(), input_data.len());
@@ -195,7 +244,17 @@ impl MessageIoNetwork {
                 NetEvent::Disconnected(endpoint) => {
                     //
                     let raw_id = endpoint.resource_id().raw();
-                    let hd = ConnId::from(raw_id);
+                    let hd = match srv_net.resolve_conn_id(raw_id) {
+                        Some(hd) => hd,
+                        None => {
+                            log::error!(
+                                "[raw_id={}] endpoint {} disconnected, unknown resource!!!",
+                                raw_id,
+                                endpoint
+                            );
+                            return;
+                        }
+                    };
                     log::info!("[hd={}] endpoint {} disconnected", hd, endpoint);
 
                     //
@@ -0,0 +1,247 @@
+//! Commlib: PacketBytes
+//! 引用计数的包体切片：持有 NetPacketGuard，所有切片释放后 packet 才归还内存池,
+//! 可以跨 service 线程传递而不复制包体
+
+use std::ops::{Deref, Range};
+use std::sync::Arc;
+
+use super::net_packet::get_recv_packet_header_size;
+use super::{CmdId, NetPacketGuard};
+
+/// 包体切片
+#[derive(Clone)]
+pub struct PacketBytes {
+    pkt: Arc<NetPacketGuard>,
+    range: Range<usize>,
+}
+
+impl PacketBytes {
+    ///
+    pub fn new(pkt: NetPacketGuard) -> Self {
+        let len = pkt.body().len();
+        Self {
+            pkt: Arc::new(pkt),
+            range: 0..len,
+        }
+    }
+
+    /// 协议号
+    #[inline(always)]
+    pub fn cmd(&self) -> CmdId {
+        self.pkt.cmd()
+    }
+
+    /// 子切片，共享同一个 packet
+    pub fn slice(&self, range: Range<usize>) -> Self {
+        assert!(range.start <= range.end && range.end <= self.range.len());
+        Self {
+            pkt: self.pkt.clone(),
+            range: (self.range.start + range.start)..(self.range.start + range.end),
+        }
+    }
+
+    /// 共享同一个 packet 的切片数量
+    #[inline(always)]
+    pub fn ref_count(&self) -> usize {
+        Arc::strong_count(&self.pkt)
+    }
+}
+
+impl Deref for PacketBytes {
+    type Target = [u8];
+
+    #[inline(always)]
+    fn deref(&self) -> &[u8] {
+        &self.pkt.body()[self.range.clone()]
+    }
+}
+
+impl AsRef<[u8]> for PacketBytes {
+    #[inline(always)]
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+/// NetPacketGuard 扩展
+///
+/// NetPacketGuard 是内存池 guard 的别名，不能直接添加方法，读取接口都在这里.
+/// 以下读取接口直接借用 buffer，不复制
+pub trait NetPacketGuardExt {
+    /// 拆出协议号和包体，包体不复制
+    fn split_body(self) -> (CmdId, PacketBytes);
+
+    /// buffer 中尚未读取的全部数据：收到的原始包含包头，decode 之后只剩包体
+    fn as_bytes(&self) -> &[u8];
+
+    /// 收到的原始包（decode 之前）跳过包头（前导长度、序号、协议号）之后的数据，
+    /// 加密的客户端包为密文. decode 之后请使用 body()
+    fn body_bytes(&self) -> &[u8];
+
+    /// PacketType 的数值；NetPacket::packet_type() 返回枚举，这里不能同名
+    fn packet_type_raw(&self) -> u16;
+
+    /// body_bytes() 的长度
+    fn payload_len(&self) -> usize;
+
+    /// 复制出数据（同 as_bytes()），packet 归还内存池
+    fn try_into_vec(self) -> Vec<u8>;
+}
+
+impl NetPacketGuardExt for NetPacketGuard {
+    #[inline(always)]
+    fn split_body(self) -> (CmdId, PacketBytes) {
+        let cmd = self.cmd();
+        (cmd, PacketBytes::new(self))
+    }
+
+    #[inline(always)]
+    fn as_bytes(&self) -> &[u8] {
+        self.peek()
+    }
+
+    #[inline(always)]
+    fn body_bytes(&self) -> &[u8] {
+        let data = self.peek();
+        let header_size = get_recv_packet_header_size(self.packet_type());
+        &data[std::cmp::min(header_size, data.len())..]
+    }
+
+    #[inline(always)]
+    fn packet_type_raw(&self) -> u16 {
+        self.packet_type() as u16
+    }
+
+    #[inline(always)]
+    fn payload_len(&self) -> usize {
+        self.body_bytes().len()
+    }
+
+    #[inline(always)]
+    fn try_into_vec(self) -> Vec<u8> {
+        self.peek().to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service_net::{take_small_packet, ConnId, PacketType};
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    struct CountingAlloc;
+
+    thread_local! {
+        static ALLOC_COUNT: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAlloc {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOC_COUNT.try_with(|c| c.set(c.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: CountingAlloc = CountingAlloc;
+
+    fn alloc_count() -> usize {
+        ALLOC_COUNT.with(|c| c.get())
+    }
+
+    fn encode(cmd: CmdId, body: &[u8], out: &mut Vec<u8>) {
+        let encrypt_table = hashbrown::HashMap::new();
+        let mut pkt = take_small_packet();
+        pkt.set_type(PacketType::Server);
+        pkt.set_cmd(cmd);
+        pkt.set_body(body);
+        assert!(pkt.encode_packet(ConnId::from(0), &encrypt_table));
+        out.clear();
+        out.extend_from_slice(pkt.peek());
+    }
+
+    fn decode(raw: &[u8]) -> NetPacketGuard {
+        let encrypt_table = hashbrown::HashMap::new();
+        let mut pkt = take_small_packet();
+        pkt.set_type(PacketType::Server);
+        pkt.append_slice(raw);
+        assert!(pkt.decode_packet(ConnId::from(0), &encrypt_table));
+        pkt
+    }
+
+    #[test]
+    fn split_body_shares_packet() {
+        let mut raw = Vec::new();
+        encode(7, b"hello world", &mut raw);
+
+        let pkt = decode(&raw);
+        assert_eq!(pkt.cmd(), 7);
+        assert_eq!(pkt.body(), b"hello world");
+
+        let (cmd, body) = pkt.split_body();
+        assert_eq!(cmd, 7);
+        assert_eq!(&body[..], b"hello world");
+
+        let world = body.slice(6..11);
+        assert_eq!(&world[..], b"world");
+        assert_eq!(world.ref_count(), 2);
+
+        // 跨线程传递
+        let handle = std::thread::spawn(move || world.to_vec());
+        assert_eq!(handle.join().unwrap(), b"world");
+        assert_eq!(body.ref_count(), 1);
+    }
+
+    #[test]
+    fn decode_without_per_packet_alloc() {
+        let mut raw = Vec::new();
+        encode(9, &[0xAB; 32], &mut raw);
+
+        // warm up: 内存池中准备好 packet
+        let mut sum = 0_usize;
+        sum += decode(&raw).body().len();
+
+        let before = alloc_count();
+        for _ in 0..10_000 {
+            let pkt = decode(&raw);
+            assert_eq!(pkt.cmd(), 9);
+            sum += pkt.body().iter().map(|b| *b as usize).sum::<usize>();
+        }
+        let allocs = alloc_count() - before;
+
+        assert_eq!(sum, 32 + 10_000 * 32 * 0xAB);
+        assert_eq!(allocs, 0, "decode allocated {} times", allocs);
+    }
+
+    #[test]
+    fn raw_packet_bytes_without_copy() {
+        let mut raw = Vec::new();
+        encode(11, b"snapshot", &mut raw);
+
+        let mut pkt = take_small_packet();
+        pkt.set_type(PacketType::Server);
+        pkt.append_slice(&raw);
+
+        let before = alloc_count();
+        assert_eq!(pkt.as_bytes(), &raw[..]);
+        assert_eq!(pkt.as_bytes().as_ptr(), pkt.peek().as_ptr());
+        assert_eq!(pkt.body_bytes(), b"snapshot");
+        assert_eq!(pkt.payload_len(), 8);
+        assert_eq!(pkt.packet_type_raw(), PacketType::Server as u16);
+        assert_eq!(alloc_count() - before, 0);
+
+        assert_eq!(pkt.try_into_vec(), raw);
+
+        // 不完整的包没有包体
+        let mut pkt = take_small_packet();
+        pkt.set_type(PacketType::Server);
+        pkt.append_slice(&raw[..3]);
+        assert!(pkt.body_bytes().is_empty());
+        assert_eq!(pkt.payload_len(), 0);
+    }
+}
@@ -4,12 +4,14 @@ use super::{
 };
 use super::{NetPacketGuard, PacketType};
 
-const MAX_PACKET_SIZE: usize = 1024 * 1024 * 20; // 20M
+/// 包体最大长度（含包头），超过则中止连接
+pub const MAX_PACKET_SIZE: usize = 1024 * 1024 * 20; // 20M
 
 /// Read result
 pub enum PacketResult {
     Ready((NetPacketGuard, usize)), // (pkt, consumed)
     Suspend(usize),                 // consumed
+    Overflow(usize),                // 包长度（含包头）超过 max_packet_size
     Abort(String),
 }
 
@@ -22,9 +24,11 @@ enum PacketReceiverState {
     Abort(usize),  // 中止（pkt_full_len）
 }
 
-///
+/// 按包体前导长度字段组包：包头和包体都可以跨多次 read 到达，大包使用 large pkt 累积
 pub struct PacketReceiver {
     pub leading_field_size: usize,
+    pub max_packet_size: usize,
+    packet_type: PacketType,
     pkt_opt: Option<NetPacketGuard>, // 使用 option 以便把内部 pkt 返回给外部使用
     state: PacketReceiverState,
 }
@@ -32,11 +36,19 @@ pub struct PacketReceiver {
 impl PacketReceiver {
     ///
     pub fn new(pkt: NetPacketGuard) -> PacketReceiver {
+        Self::with_max_packet_size(pkt, MAX_PACKET_SIZE)
+    }
+
+    /// max_packet_size: 包长度上限（含包头），超过则返回 PacketResult::Overflow
+    pub fn with_max_packet_size(pkt: NetPacketGuard, max_packet_size: usize) -> PacketReceiver {
         let leading_field_size = pkt.leading_field_size();
+        let packet_type = pkt.packet_type();
         let pkt_opt = Some(pkt);
 
         PacketReceiver {
             leading_field_size,
+            max_packet_size,
+            packet_type,
             pkt_opt,
             state: PacketReceiverState::Leading,
         }
@@ -49,11 +61,6 @@ impl PacketReceiver {
 
         let leading_field_size = self.leading_field_size;
 
-        let input = unsafe { std::slice::from_raw_parts(input_data, input_len) };
-        log::info!("input: {:?}", input);
-        let input_hex = hex::encode(input);
-        log::info!("input_hex: {}", input_hex);
-
         let pkt_opt_ptr_: *mut Option<NetPacketGuard> =
             &self.pkt_opt as *const Option<NetPacketGuard> as *mut Option<NetPacketGuard>;
         let state_ptr_ = &self.state as *const PacketReceiverState as *mut PacketReceiverState;
@@ -72,13 +79,17 @@ impl PacketReceiver {
                     if buffer_raw_len >= leading_field_size {
                         // 查看取包体前导长度
                         let pkt_full_len = pkt.peek_leading_field();
-                        if pkt_full_len > MAX_PACKET_SIZE {
+                        if pkt_full_len > self.max_packet_size {
                             // state: 中止
                             (*state) = PacketReceiverState::Abort(pkt_full_len);
+                        } else if pkt_full_len < leading_field_size {
+                            // 包长度不足以容纳前导长度字段，数据流已损坏
+                            log::error!("packet malformed!!! pkt_full_len={}", pkt_full_len);
+                            return PacketResult::Abort("malformed".to_owned());
                         } else {
                             // 检查 pkt 容量是否足够
                             let writable_bytes = pkt.buffer_writable_bytes();
-                            if writable_bytes < pkt_full_len {
+                            if writable_bytes < pkt_full_len - buffer_raw_len {
                                 // state: 进入扩展包体缓冲区处理，重新申请 large pkt
                                 (*state) = PacketReceiverState::Expand(pkt_full_len);
                             } else {
@@ -114,11 +125,13 @@ impl PacketReceiver {
                     let buffer_raw_len = old_pkt.buffer_raw_len();
 
                     //
-                    let ensure_bytes = buffer_raw_len + pkt_full_len;
+                    let ensure_bytes = std::cmp::max(buffer_raw_len, pkt_full_len);
                     let old_pkt_slice = old_pkt.peek();
 
-                    // old pkt 数据转移到 new pkt，使用 new pkt 继续解析
-                    let new_pkt = take_large_packet(ensure_bytes, old_pkt_slice);
+                    // old pkt 数据转移到 new pkt，使用 new pkt 继续解析（new pkt 需要沿用包类型）
+                    let mut new_pkt = take_large_packet(ensure_bytes, b"");
+                    new_pkt.set_type(self.packet_type);
+                    new_pkt.append_slice(old_pkt_slice);
                     (*pkt_opt) = Some(new_pkt);
 
                     // 进入包体数据处理
@@ -136,9 +149,8 @@ impl PacketReceiver {
                         // state: 完成当前 pkt 读取，转入完成处理
                         (*state) = PacketReceiverState::Complete;
                     } else {
-                        //
-                        assert!(buffer_raw_len + consumed <= pkt_full_len);
-                        let need_types = pkt_full_len - (buffer_raw_len + consumed);
+                        // 已追加的数据都在 pkt 中，只需补足剩余部分
+                        let need_types = pkt_full_len - buffer_raw_len;
                         let append_bytes = if remain >= need_types {
                             need_types
                         } else {
@@ -162,7 +174,8 @@ impl PacketReceiver {
 
                 PacketReceiverState::Complete => {
                     // 将完整的 pkt 返回到外部使用，内部补充新的 pkt
-                    let new_pkt = take_small_packet();
+                    let mut new_pkt = take_small_packet();
+                    new_pkt.set_type(self.packet_type);
                     let old_pkt = pkt_opt.take().unwrap();
                     (*pkt_opt) = Some(new_pkt);
 
@@ -174,10 +187,14 @@ impl PacketReceiver {
                 }
 
                 PacketReceiverState::Abort(pkt_full_len) => {
-                    log::error!("packet overflow!!! pkt_full_len={}", pkt_full_len);
+                    log::error!(
+                        "packet overflow!!! pkt_full_len={} max_packet_size={}",
+                        pkt_full_len,
+                        self.max_packet_size
+                    );
 
                     // 包长度越界
-                    return PacketResult::Abort("overflow".to_owned());
+                    return PacketResult::Overflow(pkt_full_len);
                 }
             }
         }
@@ -186,3 +203,112 @@ impl PacketReceiver {
         PacketResult::Suspend(consumed)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service_net::ConnId;
+
+    fn new_receiver(packet_type: PacketType, max_packet_size: usize) -> PacketReceiver {
+        let mut pkt = take_small_packet();
+        pkt.set_type(packet_type);
+        PacketReceiver::with_max_packet_size(pkt, max_packet_size)
+    }
+
+    /// 按 chunk 切分 input 喂给 receiver，返回收到的完整 pkt；Overflow 时返回 Err(pkt_full_len)
+    fn feed(
+        receiver: &PacketReceiver,
+        input: &[u8],
+        chunk: usize,
+    ) -> Result<Vec<NetPacketGuard>, usize> {
+        let mut pkts = Vec::new();
+        for part in input.chunks(chunk) {
+            let mut pos = 0_usize;
+            while pos < part.len() {
+                let ptr = unsafe { part.as_ptr().add(pos) };
+                match receiver.read(ptr, part.len() - pos) {
+                    PacketResult::Ready((pkt, consumed)) => {
+                        pkts.push(pkt);
+                        pos += consumed;
+                    }
+                    PacketResult::Suspend(consumed) => pos += consumed,
+                    PacketResult::Overflow(pkt_full_len) => return Err(pkt_full_len),
+                    PacketResult::Abort(err) => panic!("abort: {}", err),
+                }
+            }
+        }
+        Ok(pkts)
+    }
+
+    fn server_frame(cmd: u16, body: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(6 + body.len());
+        frame.extend_from_slice(&((6 + body.len()) as u32).to_be_bytes());
+        frame.extend_from_slice(&cmd.to_be_bytes());
+        frame.extend_from_slice(body);
+        frame
+    }
+
+    #[test]
+    fn large_server_packet_byte_by_byte() {
+        let body: Vec<u8> = (0..300 * 1024).map(|i| (i % 251) as u8).collect();
+        let frame = server_frame(1001, &body);
+        let receiver = new_receiver(PacketType::Server, MAX_PACKET_SIZE);
+
+        let mut pkts = feed(&receiver, &frame, 1).unwrap();
+        assert_eq!(pkts.len(), 1);
+
+        let mut pkt = pkts.pop().unwrap();
+        assert!(pkt.decode_packet(ConnId::from_raw(1), &hashbrown::HashMap::new()));
+        assert_eq!(pkt.cmd(), 1001);
+        assert_eq!(pkt.body(), &body[..]);
+    }
+
+    #[test]
+    fn client_packet_split_across_reads() {
+        // 客户端包：2字节长度 + 1字节序号 + 2字节协议号
+        let body: Vec<u8> = (0..60_000).map(|i| (i % 13) as u8).collect();
+        let mut frame = Vec::new();
+        frame.extend_from_slice(&((5 + body.len()) as u16).to_be_bytes());
+        frame.push(3);
+        frame.extend_from_slice(&2002_u16.to_be_bytes());
+        frame.extend_from_slice(&body);
+
+        // 两个包连续到达，读边界落在包头中间
+        let mut input = frame.clone();
+        input.extend_from_slice(&frame);
+        let receiver = new_receiver(PacketType::Client, MAX_PACKET_SIZE);
+
+        let pkts = feed(&receiver, &input, 4099).unwrap();
+        assert_eq!(pkts.len(), 2);
+        for pkt in &pkts {
+            assert_eq!(pkt.packet_type(), PacketType::Client);
+            assert_eq!(pkt.peek(), &frame[..]);
+        }
+    }
+
+    #[test]
+    fn many_small_packets_in_one_read() {
+        let mut input = Vec::new();
+        for i in 0..10_u16 {
+            input.extend_from_slice(&server_frame(i, &[i as u8; 3]));
+        }
+        let receiver = new_receiver(PacketType::Server, MAX_PACKET_SIZE);
+
+        let pkts = feed(&receiver, &input, input.len()).unwrap();
+        assert_eq!(pkts.len(), 10);
+        for (i, mut pkt) in pkts.into_iter().enumerate() {
+            assert!(pkt.decode_packet(ConnId::from_raw(1), &hashbrown::HashMap::new()));
+            assert_eq!(pkt.cmd(), i as u16);
+            assert_eq!(pkt.body(), &[i as u8; 3]);
+        }
+    }
+
+    #[test]
+    fn oversized_packet_overflows() {
+        let frame = server_frame(1, &[0_u8; 1024]);
+        let receiver = new_receiver(PacketType::Server, 1000);
+
+        // 包头到达即判定越界，不等待包体
+        assert_eq!(feed(&receiver, &frame[..4], 1).err(), Some(1030));
+    }
+}
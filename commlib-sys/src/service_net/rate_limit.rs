@@ -0,0 +1,493 @@
+//! Commlib: RateLimit
+//! TcpConn 收包限流：每秒包数/字节数两个令牌桶（容量为 1 秒的额度），在 pkt_fn 之前检查.
+//! 超过限制时按 RateLimitPolicy 丢弃、关闭连接或缓存后平滑投递. 默认不限制
+
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::{Clock, ServiceNetRs};
+
+use super::{handle_close_conn_event, CloseReason, ConnId, NetPacketGuard, TcpConn};
+
+/// 超过限制时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitPolicy {
+    DropSilently,                       // 丢弃超出的包
+    CloseConn,                          // 以 CloseReason::RateLimited 关闭连接
+    DeferToQueue { max_queued: usize }, // 缓存最多 max_queued 个包，按速率平滑投递，缓存满时关闭连接
+}
+
+/// 收包限流配置，0 表示该项不限制
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub packets_per_sec: u32,
+    pub bytes_per_sec: u32,
+    pub policy: RateLimitPolicy,
+}
+
+impl RateLimit {
+    ///
+    pub fn new(packets_per_sec: u32, bytes_per_sec: u32, policy: RateLimitPolicy) -> Self {
+        Self {
+            packets_per_sec,
+            bytes_per_sec,
+            policy,
+        }
+    }
+
+    /// 不限制时不检查令牌桶
+    #[inline(always)]
+    pub fn is_unlimited(&self) -> bool {
+        0 == self.packets_per_sec && 0 == self.bytes_per_sec
+    }
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self::new(0, 0, RateLimitPolicy::DropSilently)
+    }
+}
+
+/// 限流检查结果
+pub enum RateAdmit {
+    Deliver(NetPacketGuard),    // 交给 pkt_fn
+    Dropped,                    // 已丢弃
+    Deferred(Option<Duration>), // 已缓存，Some 表示需要在该时间后启动投递
+    Close,                      // 需要关闭连接
+}
+
+/// 限流统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimitStats {
+    pub violations: u64, // 超过限制的次数（包括被缓存的包）
+    pub dropped: u64,
+    pub deferred: u64,
+}
+
+struct RateBuckets {
+    packet_tokens: f64,
+    byte_tokens: f64,
+    last_refill: Option<Instant>,
+    queue: VecDeque<NetPacketGuard>,
+    drain_scheduled: bool,
+    last_warn: Option<Instant>,
+    suppressed_warns: u64,
+}
+
+/// TcpConn 的令牌桶和统计
+pub struct RateLimitState {
+    buckets: Mutex<RateBuckets>,
+    violations: AtomicU64,
+    dropped: AtomicU64,
+    deferred: AtomicU64,
+}
+
+impl RateLimitState {
+    ///
+    pub fn new() -> Self {
+        Self {
+            buckets: Mutex::new(RateBuckets {
+                packet_tokens: 0_f64,
+                byte_tokens: 0_f64,
+                last_refill: None,
+                queue: VecDeque::new(),
+                drain_scheduled: false,
+                last_warn: None,
+                suppressed_warns: 0,
+            }),
+            violations: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            deferred: AtomicU64::new(0),
+        }
+    }
+
+    /// 检查收到的包是否可以交给 pkt_fn
+    pub fn admit(&self, limit: &RateLimit, pkt: NetPacketGuard, now: Instant) -> RateAdmit {
+        if limit.is_unlimited() {
+            return RateAdmit::Deliver(pkt);
+        }
+
+        let mut buckets = self.buckets.lock();
+        buckets.refill(limit, now);
+
+        // 已有缓存的包时排在其后，保持顺序
+        let len = pkt.peek().len();
+        if buckets.queue.is_empty() && buckets.try_take(limit, len) {
+            return RateAdmit::Deliver(pkt);
+        }
+
+        self.violations.fetch_add(1, Ordering::Relaxed);
+        match limit.policy {
+            RateLimitPolicy::DropSilently => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                RateAdmit::Dropped
+            }
+            RateLimitPolicy::CloseConn => RateAdmit::Close,
+            RateLimitPolicy::DeferToQueue { max_queued } => {
+                if buckets.queue.len() >= max_queued {
+                    return RateAdmit::Close;
+                }
+                buckets.queue.push_back(pkt);
+                self.deferred.fetch_add(1, Ordering::Relaxed);
+
+                if buckets.drain_scheduled {
+                    RateAdmit::Deferred(None)
+                } else {
+                    buckets.drain_scheduled = true;
+                    RateAdmit::Deferred(buckets.next_ready_in(limit))
+                }
+            }
+        }
+    }
+
+    /// 取出缓存中已可投递的包，返回 (包, 距离下一次投递的时间)，缓存清空时为 None
+    pub fn drain(
+        &self,
+        limit: &RateLimit,
+        now: Instant,
+    ) -> (Vec<NetPacketGuard>, Option<Duration>) {
+        let mut buckets = self.buckets.lock();
+        buckets.refill(limit, now);
+
+        let mut ready = Vec::new();
+        while let Some(len) = buckets.queue.front().map(|pkt| pkt.peek().len()) {
+            if !buckets.try_take(limit, len) {
+                break;
+            }
+            ready.push(buckets.queue.pop_front().unwrap());
+        }
+
+        let next = buckets.next_ready_in(limit);
+        buckets.drain_scheduled = next.is_some();
+        (ready, next)
+    }
+
+    /// 缓存中等待投递的包数
+    pub fn queued(&self) -> usize {
+        self.buckets.lock().queue.len()
+    }
+
+    ///
+    pub fn stats(&self) -> RateLimitStats {
+        RateLimitStats {
+            violations: self.violations.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            deferred: self.deferred.load(Ordering::Relaxed),
+        }
+    }
+
+    /// 警告日志每秒最多一条，可以输出时返回期间被忽略的次数
+    pub fn warn_due(&self, now: Instant) -> Option<u64> {
+        let mut buckets = self.buckets.lock();
+        match buckets.last_warn {
+            Some(last) if now.saturating_duration_since(last) < Duration::from_secs(1) => {
+                buckets.suppressed_warns += 1;
+                None
+            }
+            _ => {
+                buckets.last_warn = Some(now);
+                Some(std::mem::take(&mut buckets.suppressed_warns))
+            }
+        }
+    }
+}
+
+impl Default for RateLimitState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateBuckets {
+    fn refill(&mut self, limit: &RateLimit, now: Instant) {
+        let elapsed = match self.last_refill {
+            Some(last) => now.saturating_duration_since(last).as_secs_f64(),
+            None => {
+                // 初始为满桶
+                self.packet_tokens = limit.packets_per_sec as f64;
+                self.byte_tokens = limit.bytes_per_sec as f64;
+                0_f64
+            }
+        };
+        self.last_refill = Some(now);
+
+        self.packet_tokens = f64::min(
+            limit.packets_per_sec as f64,
+            self.packet_tokens + elapsed * limit.packets_per_sec as f64,
+        );
+        self.byte_tokens = f64::min(
+            limit.bytes_per_sec as f64,
+            self.byte_tokens + elapsed * limit.bytes_per_sec as f64,
+        );
+    }
+
+    // 超过 1 秒额度的大包在满桶时也可以通过，之后的包等待令牌补足
+    fn byte_need(limit: &RateLimit, len: usize) -> f64 {
+        f64::min(len as f64, limit.bytes_per_sec as f64)
+    }
+
+    fn try_take(&mut self, limit: &RateLimit, len: usize) -> bool {
+        let packet_ok = 0 == limit.packets_per_sec || self.packet_tokens >= 1_f64;
+        let byte_ok = 0 == limit.bytes_per_sec || self.byte_tokens >= Self::byte_need(limit, len);
+        if !packet_ok || !byte_ok {
+            return false;
+        }
+
+        if limit.packets_per_sec > 0 {
+            self.packet_tokens -= 1_f64;
+        }
+        if limit.bytes_per_sec > 0 {
+            self.byte_tokens -= len as f64;
+        }
+        true
+    }
+
+    fn next_ready_in(&self, limit: &RateLimit) -> Option<Duration> {
+        let len = self.queue.front()?.peek().len();
+
+        let mut secs = 0_f64;
+        if limit.packets_per_sec > 0 {
+            let need = 1_f64 - self.packet_tokens;
+            secs = f64::max(secs, need / limit.packets_per_sec as f64);
+        }
+        if limit.bytes_per_sec > 0 {
+            let need = Self::byte_need(limit, len) - self.byte_tokens;
+            secs = f64::max(secs, need / limit.bytes_per_sec as f64);
+        }
+        Some(Duration::from_secs_f64(secs).max(Duration::from_millis(1)))
+    }
+}
+
+/// 按 conn 的限流配置投递收到的包，返回 false 表示连接已因限流关闭（在 srv_net 中运行）
+pub fn dispatch_rate_limited(
+    srv_net: &ServiceNetRs,
+    conn: &Arc<TcpConn>,
+    pkt: NetPacketGuard,
+) -> bool {
+    let now = Instant::now();
+    match conn.rate_state.admit(&conn.rate_limit, pkt, now) {
+        RateAdmit::Deliver(pkt) => {
+            conn.run_pkt_fn(pkt);
+            return true;
+        }
+        RateAdmit::Dropped => {}
+        RateAdmit::Deferred(delay_opt) => {
+            if let Some(delay) = delay_opt {
+                schedule_rate_limit_drain(&conn.srv_net, conn.hd, delay);
+            }
+        }
+        RateAdmit::Close => {
+            log::error!(
+                "[hd={}] peer {} exceeds rate limit {:?}, close it!!!",
+                conn.hd,
+                conn.remote_addr(),
+                conn.rate_limit
+            );
+            conn.close_with_reason(CloseReason::RateLimited);
+            handle_close_conn_event(srv_net, conn);
+            return false;
+        }
+    }
+
+    if let Some(suppressed) = conn.rate_state.warn_due(now) {
+        log::warn!(
+            "[hd={}] peer {} exceeds rate limit {:?}, stats: {:?}, {} warnings suppressed",
+            conn.hd,
+            conn.remote_addr(),
+            conn.rate_limit,
+            conn.rate_state.stats(),
+            suppressed
+        );
+    }
+    true
+}
+
+fn schedule_rate_limit_drain(srv_net: &Arc<ServiceNetRs>, hd: ConnId, delay: Duration) {
+    let srv_net2 = srv_net.clone();
+    let delay_ms = std::cmp::max(1, delay.as_millis() as u64);
+    Clock::set_timeout(srv_net.as_ref(), delay_ms, move || {
+        if let Some(conn) = srv_net2.get_conn(hd) {
+            if conn.closed.load(atomic::Ordering::Relaxed) {
+                return;
+            }
+
+            let (ready, next) = conn.rate_state.drain(&conn.rate_limit, Instant::now());
+            for pkt in ready {
+                conn.run_pkt_fn(pkt);
+            }
+            if let Some(delay) = next {
+                schedule_rate_limit_drain(&srv_net2, hd, delay);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service_net::packet_receiver::PacketResult;
+    use crate::service_net::{take_small_packet, PacketReceiver, PacketType, MAX_PACKET_SIZE};
+
+    // 10k 个包连续写入，经 PacketReceiver 解析
+    fn burst(count: usize) -> Vec<NetPacketGuard> {
+        let mut data = Vec::new();
+        for i in 0..count {
+            let mut pkt = take_small_packet();
+            pkt.set_type(PacketType::Server);
+            pkt.set_cmd(1001);
+            pkt.set_body(&(i as u32).to_be_bytes());
+            pkt.encode_packet(ConnId::from_raw(0), &hashbrown::HashMap::new());
+            data.extend_from_slice(pkt.peek());
+        }
+
+        let mut pkt = take_small_packet();
+        pkt.set_type(PacketType::Server);
+        let receiver = PacketReceiver::with_max_packet_size(pkt, MAX_PACKET_SIZE);
+
+        let mut pkts = Vec::new();
+        let mut pos = 0_usize;
+        while pos < data.len() {
+            let remain = &data[pos..];
+            match receiver.read(remain.as_ptr(), remain.len()) {
+                PacketResult::Ready((pkt, consumed)) => {
+                    pkts.push(pkt);
+                    pos += consumed;
+                }
+                PacketResult::Suspend(consumed) => pos += consumed,
+                _ => panic!("parse failed"),
+            }
+        }
+        assert_eq!(pkts.len(), count);
+        pkts
+    }
+
+    #[test]
+    fn unlimited_by_default() {
+        let limit = RateLimit::default();
+        assert!(limit.is_unlimited());
+
+        let state = RateLimitState::new();
+        let now = Instant::now();
+        for pkt in burst(10_000) {
+            assert!(matches!(
+                state.admit(&limit, pkt, now),
+                RateAdmit::Deliver(_)
+            ));
+        }
+        assert_eq!(state.stats(), RateLimitStats::default());
+    }
+
+    #[test]
+    fn drop_silently() {
+        let limit = RateLimit::new(100, 0, RateLimitPolicy::DropSilently);
+        let state = RateLimitState::new();
+        let now = Instant::now();
+
+        let mut delivered = 0;
+        for pkt in burst(10_000) {
+            match state.admit(&limit, pkt, now) {
+                RateAdmit::Deliver(_) => delivered += 1,
+                RateAdmit::Dropped => {}
+                _ => panic!("unexpected admit"),
+            }
+        }
+        assert_eq!(delivered, 100);
+        let stats = state.stats();
+        assert_eq!(stats.violations, 9_900);
+        assert_eq!(stats.dropped, 9_900);
+
+        // 半秒后补充 50 个令牌
+        let mut delivered = 0;
+        for pkt in burst(100) {
+            if let RateAdmit::Deliver(_) =
+                state.admit(&limit, pkt, now + Duration::from_millis(500))
+            {
+                delivered += 1;
+            }
+        }
+        assert_eq!(delivered, 50);
+    }
+
+    #[test]
+    fn close_conn_on_bytes() {
+        // 每个包 10 字节（包头 6 + 包体 4），1000 字节/秒即 100 个包
+        let limit = RateLimit::new(0, 1000, RateLimitPolicy::CloseConn);
+        let state = RateLimitState::new();
+        let now = Instant::now();
+
+        let mut pkts = burst(10_000).into_iter();
+        for pkt in pkts.by_ref().take(100) {
+            assert!(matches!(
+                state.admit(&limit, pkt, now),
+                RateAdmit::Deliver(_)
+            ));
+        }
+        assert!(matches!(
+            state.admit(&limit, pkts.next().unwrap(), now),
+            RateAdmit::Close
+        ));
+        assert_eq!(state.stats().violations, 1);
+    }
+
+    #[test]
+    fn defer_to_queue() {
+        let limit = RateLimit::new(100, 0, RateLimitPolicy::DeferToQueue { max_queued: 500 });
+        let state = RateLimitState::new();
+        let now = Instant::now();
+
+        let mut delivered = 0;
+        let mut scheduled = Vec::new();
+        let mut closed = false;
+        for pkt in burst(10_000) {
+            match state.admit(&limit, pkt, now) {
+                RateAdmit::Deliver(_) => delivered += 1,
+                RateAdmit::Deferred(delay) => scheduled.extend(delay),
+                RateAdmit::Close => {
+                    closed = true;
+                    break;
+                }
+                RateAdmit::Dropped => panic!("unexpected drop"),
+            }
+        }
+        assert_eq!(delivered, 100);
+        assert!(closed);
+        assert_eq!(state.queued(), 500);
+        assert_eq!(state.stats().deferred, 500);
+        assert_eq!(state.stats().violations, 501);
+
+        // 只在第一次缓存时启动投递，约 10ms 后有一个令牌
+        assert_eq!(scheduled.len(), 1);
+        assert!(scheduled[0] <= Duration::from_millis(10));
+
+        // 1 秒后按速率投递 100 个，顺序不变
+        let (ready, next) = state.drain(&limit, now + Duration::from_secs(1));
+        assert_eq!(ready.len(), 100);
+        assert_eq!(&ready[0].peek()[6..], &100_u32.to_be_bytes());
+        assert!(next.is_some());
+        assert_eq!(state.queued(), 400);
+
+        let (ready, next) = state.drain(&limit, now + Duration::from_secs(10));
+        assert_eq!(ready.len(), 100);
+        assert!(next.is_some());
+        for i in 0..3 {
+            let (ready, _) = state.drain(&limit, now + Duration::from_secs(11 + i));
+            assert_eq!(ready.len(), 100);
+        }
+        let (ready, next) = state.drain(&limit, now + Duration::from_secs(20));
+        assert!(ready.is_empty());
+        assert_eq!(next, None);
+    }
+
+    #[test]
+    fn warn_rate_limited() {
+        let state = RateLimitState::new();
+        let now = Instant::now();
+        assert_eq!(state.warn_due(now), Some(0));
+        assert_eq!(state.warn_due(now + Duration::from_millis(10)), None);
+        assert_eq!(state.warn_due(now + Duration::from_millis(500)), None);
+        assert_eq!(state.warn_due(now + Duration::from_secs(1)), Some(2));
+    }
+}
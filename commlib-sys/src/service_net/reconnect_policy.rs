@@ -0,0 +1,95 @@
+//! Commlib: ReconnectPolicy
+//! TcpClient 断线重连策略：指数退避，可限制最大重连次数
+
+use std::time::Duration;
+
+/// 断线重连策略
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReconnectPolicy {
+    /// 第一次重连前的等待时间
+    pub initial_delay: Duration,
+    /// 等待时间上限
+    pub max_delay: Duration,
+    /// 每次重连失败后等待时间的放大倍数
+    pub multiplier: f64,
+    /// 最大重连次数，0 表示不限次数
+    pub max_attempts: u32,
+}
+
+impl Default for ReconnectPolicy {
+    /// 与旧版行为一致：固定 5 秒，无限重连
+    fn default() -> Self {
+        Self::fixed(Duration::from_millis(5000))
+    }
+}
+
+impl ReconnectPolicy {
+    ///
+    pub fn new(
+        initial_delay: Duration,
+        max_delay: Duration,
+        multiplier: f64,
+        max_attempts: u32,
+    ) -> Self {
+        Self {
+            initial_delay,
+            max_delay,
+            multiplier,
+            max_attempts,
+        }
+    }
+
+    /// 固定间隔，无限重连
+    pub fn fixed(delay: Duration) -> Self {
+        Self::new(delay, delay, 1.0, 0)
+    }
+
+    /// 第 attempt 次重连（从 1 开始）前的等待时间
+    pub fn delay_for(&self, attempt: u32) -> Duration {
+        let exp = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let factor = self.multiplier.max(1.0).powi(exp);
+        let nanos = self.initial_delay.as_nanos() as f64 * factor;
+        if !nanos.is_finite() || nanos >= self.max_delay.as_nanos() as f64 {
+            self.max_delay.max(self.initial_delay)
+        } else {
+            Duration::from_nanos(nanos as u64)
+        }
+    }
+
+    /// 已经重连 attempts 次之后是否还允许继续重连
+    #[inline(always)]
+    pub fn is_exhausted(&self, attempts: u32) -> bool {
+        self.max_attempts > 0 && attempts >= self.max_attempts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_is_capped() {
+        let policy = ReconnectPolicy::new(
+            Duration::from_millis(100),
+            Duration::from_millis(1000),
+            2.0,
+            0,
+        );
+        assert_eq!(Duration::from_millis(100), policy.delay_for(1));
+        assert_eq!(Duration::from_millis(200), policy.delay_for(2));
+        assert_eq!(Duration::from_millis(800), policy.delay_for(4));
+        assert_eq!(Duration::from_millis(1000), policy.delay_for(5));
+        assert_eq!(Duration::from_millis(1000), policy.delay_for(u32::MAX));
+        assert!(!policy.is_exhausted(u32::MAX));
+    }
+
+    #[test]
+    fn max_attempts() {
+        let mut policy = ReconnectPolicy::default();
+        assert_eq!(Duration::from_millis(5000), policy.delay_for(10));
+
+        policy.max_attempts = 3;
+        assert!(!policy.is_exhausted(2));
+        assert!(policy.is_exhausted(3));
+    }
+}
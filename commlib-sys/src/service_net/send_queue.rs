@@ -0,0 +1,136 @@
+//! Commlib: SendQueue
+//! TcpConn 发送队列字节数统计：超过 high watermark 通知上层限流，超过 hard limit 关闭连接
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use super::TcpConn;
+
+/// 待发送字节数越过 high watermark 时回调 (conn, pending_bytes)
+pub type HighWatermarkFn = Arc<dyn Fn(Arc<TcpConn>, usize) + Send + Sync>;
+
+/// 发送队列限制，0 表示不限制
+#[derive(Clone, Default)]
+pub struct SendQueueLimit {
+    pub high_watermark: usize,
+    pub hard_limit: usize,
+    pub high_watermark_fn: Option<HighWatermarkFn>,
+}
+
+impl SendQueueLimit {
+    ///
+    pub fn new(high_watermark: usize, hard_limit: usize) -> Self {
+        Self {
+            high_watermark,
+            hard_limit,
+            high_watermark_fn: None,
+        }
+    }
+
+    /// 在 conn 所属 service 中回调
+    pub fn with_high_watermark_fn<F>(mut self, f: F) -> Self
+    where
+        F: Fn(Arc<TcpConn>, usize) + Send + Sync + 'static,
+    {
+        self.high_watermark_fn = Some(Arc::new(f));
+        self
+    }
+
+    /// 不限制时直接发送，不经过发送队列
+    #[inline(always)]
+    pub fn is_unlimited(&self) -> bool {
+        0 == self.high_watermark && 0 == self.hard_limit
+    }
+}
+
+///
+#[derive(Debug, PartialEq, Eq)]
+pub enum SendQueueEvent {
+    Queued,
+    HighWatermark(usize), // 本次入队越过 high watermark
+    Overflow(usize),      // 超过 hard limit，数据未入队
+}
+
+/// 待发送字节数
+#[derive(Default)]
+pub struct SendQueueState {
+    pending: AtomicUsize,
+}
+
+impl SendQueueState {
+    ///
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 入队 len 字节
+    pub fn push(&self, len: usize, limit: &SendQueueLimit) -> SendQueueEvent {
+        let mut cur = self.pending.load(Ordering::Relaxed);
+        loop {
+            let next = cur + len;
+            if limit.hard_limit > 0 && next > limit.hard_limit {
+                return SendQueueEvent::Overflow(next);
+            }
+            match self
+                .pending
+                .compare_exchange_weak(cur, next, Ordering::AcqRel, Ordering::Relaxed)
+            {
+                Ok(_) => {
+                    if limit.high_watermark > 0
+                        && cur < limit.high_watermark
+                        && next >= limit.high_watermark
+                    {
+                        return SendQueueEvent::HighWatermark(next);
+                    }
+                    return SendQueueEvent::Queued;
+                }
+                Err(actual) => cur = actual,
+            }
+        }
+    }
+
+    /// 已写入 socket 的 len 字节出队
+    #[inline(always)]
+    pub fn pop(&self, len: usize) {
+        self.pending.fetch_sub(len, Ordering::AcqRel);
+    }
+
+    ///
+    #[inline(always)]
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stalled_reader_crosses_watermark_then_overflows() {
+        let limit = SendQueueLimit::new(100, 250);
+        let queue = SendQueueState::new();
+
+        // 对端从不读取：只入队不出队
+        assert_eq!(queue.push(60, &limit), SendQueueEvent::Queued);
+        assert_eq!(queue.push(60, &limit), SendQueueEvent::HighWatermark(120));
+        assert_eq!(queue.push(60, &limit), SendQueueEvent::Queued);
+        assert_eq!(queue.pending(), 180);
+        assert_eq!(queue.push(80, &limit), SendQueueEvent::Overflow(260));
+        assert_eq!(queue.pending(), 180);
+
+        // 排空后再次越过 watermark 会再次通知
+        queue.pop(180);
+        assert_eq!(queue.pending(), 0);
+        assert_eq!(queue.push(100, &limit), SendQueueEvent::HighWatermark(100));
+    }
+
+    #[test]
+    fn unlimited() {
+        let limit = SendQueueLimit::default();
+        assert!(limit.is_unlimited());
+
+        let queue = SendQueueState::new();
+        assert_eq!(queue.push(usize::MAX / 2, &limit), SendQueueEvent::Queued);
+    }
+}
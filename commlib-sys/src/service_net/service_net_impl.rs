@@ -4,10 +4,14 @@ use std::sync::Arc;
 use crate::{Clock, NodeState, PinkySwear, ServiceHandle, ServiceRs};
 
 use super::MessageIoNetwork;
+use super::{dispatch_rate_limited, handle_heartbeat, peek_heartbeat, HeartbeatConfig, RateLimit};
+use super::{handle_ws_message, PacketType};
 use super::{
-    packet_receiver::PacketResult, ConnId, NetPacketGuard, TcpClient, TcpConn, TcpListenerId,
-    TcpServer,
+    packet_receiver::PacketResult, CloseReason, ConnId, ConnIdAllocator, ConnIdError,
+    NetPacketGuard, ReconnectPolicy, TcpClient, TcpConn, TcpListenerId, TcpServer,
 };
+use super::{schedule_udp_idle_check, schedule_udp_kcp_update, KcpConfig, SendQueueLimit};
+use super::{UdpConn, UdpServer, UdpSessions};
 
 /// ServiceNetRs
 pub struct ServiceNetRs {
@@ -15,9 +19,14 @@ pub struct ServiceNetRs {
 
     client_table: RwLock<hashbrown::HashMap<uuid::Uuid, Arc<TcpClient>>>, // TODO: remove lock?
     conn_table: RwLock<hashbrown::HashMap<ConnId, Arc<TcpConn>>>,         // TODO: remove lock?
+    conn_ids: RwLock<ConnIdAllocator>, // tcp resource id -> ConnId
 
     pub tcp_server_vec: RwLock<Vec<TcpServer>>, // TODO: remove lock?
 
+    // key: udp socket resource id
+    udp_server_table: RwLock<hashbrown::HashMap<usize, Arc<UdpServer>>>,
+    udp_conn_table: RwLock<hashbrown::HashMap<ConnId, Arc<UdpConn>>>,
+
     //
     inner_network: Arc<MessageIoNetwork>,
 }
@@ -31,8 +40,12 @@ impl ServiceNetRs {
             client_table: RwLock::new(hashbrown::HashMap::new()),
 
             conn_table: RwLock::new(hashbrown::HashMap::with_capacity(4096)),
+            conn_ids: RwLock::new(ConnIdAllocator::new()),
             tcp_server_vec: RwLock::new(Vec::new()),
 
+            udp_server_table: RwLock::new(hashbrown::HashMap::new()),
+            udp_conn_table: RwLock::new(hashbrown::HashMap::new()),
+
             //
             inner_network: Arc::new(MessageIoNetwork::new()),
         }
@@ -50,6 +63,33 @@ impl ServiceNetRs {
         }
     }
 
+    /// 区分 hd 已失效（槽位被复用）和不存在
+    pub fn lookup_conn(&self, hd: ConnId) -> Result<Arc<TcpConn>, ConnIdError> {
+        if let Some(conn) = self.conn_table.read().get(&hd) {
+            return Ok(conn.clone());
+        }
+        match self.conn_ids.read().raw_id(hd) {
+            Err(err) => Err(err),
+            Ok(_) => Err(ConnIdError::NotFound(hd)), // 已分配但尚未 insert_conn
+        }
+    }
+
+    /// 为 message io 的 tcp resource id 分配 ConnId
+    pub fn alloc_conn_id(&self, raw_id: usize) -> ConnId {
+        self.conn_ids.write().alloc(raw_id)
+    }
+
+    ///
+    #[inline(always)]
+    pub fn resolve_conn_id(&self, raw_id: usize) -> Option<ConnId> {
+        self.conn_ids.read().resolve(raw_id)
+    }
+
+    /// hd 对应的 message io resource id
+    pub fn conn_resource_id(&self, hd: ConnId) -> Result<usize, ConnIdError> {
+        self.conn_ids.read().raw_id(hd)
+    }
+
     /// Add conn
     //#[inline(always)]
     pub fn insert_conn(&self, hd: ConnId, conn: &Arc<TcpConn>) {
@@ -63,9 +103,93 @@ impl ServiceNetRs {
     pub fn remove_conn(&self, hd: ConnId) -> Option<Arc<TcpConn>> {
         let mut conn_table_mut = self.conn_table.write();
         log::info!("[hd={}] -------- service net remove_conn", hd);
+
+        // 连接关闭，之后旧 hd 的查找都返回 StaleConnId
+        self.conn_ids.write().release(hd);
         conn_table_mut.remove(&hd)
     }
 
+    /// 设置 listener accept 的 conn 接收包长度上限（含包头），只影响之后 accept 的 conn
+    pub fn set_listener_max_packet_size(
+        &self,
+        listener_id: TcpListenerId,
+        max_packet_size: usize,
+    ) -> bool {
+        let tcp_server_vec = self.tcp_server_vec.read();
+        for tcp_server in &*tcp_server_vec {
+            if tcp_server.id == listener_id {
+                tcp_server.set_max_packet_size(max_packet_size);
+                return true;
+            }
+        }
+        log::error!(
+            "set_listener_max_packet_size failed -- listener_id={} not found!!!",
+            listener_id
+        );
+        false
+    }
+
+    /// 设置 listener accept 的 conn 使用的心跳配置，只影响之后 accept 的 conn
+    pub fn set_listener_heartbeat(
+        &self,
+        listener_id: TcpListenerId,
+        heartbeat: HeartbeatConfig,
+    ) -> bool {
+        let tcp_server_vec = self.tcp_server_vec.read();
+        for tcp_server in &*tcp_server_vec {
+            if tcp_server.id == listener_id {
+                tcp_server.set_heartbeat(heartbeat);
+                return true;
+            }
+        }
+        log::error!(
+            "set_listener_heartbeat failed -- listener_id={} not found!!!",
+            listener_id
+        );
+        false
+    }
+
+    /// 设置 listener accept 的 conn 使用的收包限流配置，只影响之后 accept 的 conn
+    pub fn set_listener_rate_limit(
+        &self,
+        listener_id: TcpListenerId,
+        rate_limit: RateLimit,
+    ) -> bool {
+        let tcp_server_vec = self.tcp_server_vec.read();
+        for tcp_server in &*tcp_server_vec {
+            if tcp_server.id == listener_id {
+                tcp_server.set_rate_limit(rate_limit);
+                return true;
+            }
+        }
+        log::error!(
+            "set_listener_rate_limit failed -- listener_id={} not found!!!",
+            listener_id
+        );
+        false
+    }
+
+    /// listener accept 的连接关闭，释放连接名额
+    pub fn release_listener_conn(&self, listener_id: TcpListenerId) {
+        let tcp_server_vec = self.tcp_server_vec.read();
+        if let Some(tcp_server) = tcp_server_vec
+            .iter()
+            .find(|tcp_server| tcp_server.id == listener_id)
+        {
+            tcp_server.release_conn();
+        }
+    }
+
+    /// listener accept 的连接
+    pub fn listener_conns(&self, listener_id: TcpListenerId) -> Vec<Arc<TcpConn>> {
+        let conn_table = self.conn_table.read();
+        conn_table
+            .values()
+            .filter(|conn| conn.listener == Some(listener_id))
+            .cloned()
+            .collect()
+    }
+
     ///
     #[inline(always)]
     pub fn get_client(&self, id: &uuid::Uuid) -> Option<Arc<TcpClient>> {
@@ -94,6 +218,61 @@ impl ServiceNetRs {
         client_table_mut.remove(id)
     }
 
+    ///
+    #[inline(always)]
+    pub fn get_udp_server(&self, raw_id: usize) -> Option<Arc<UdpServer>> {
+        let udp_server_table = self.udp_server_table.read();
+        udp_server_table.get(&raw_id).cloned()
+    }
+
+    ///
+    #[inline(always)]
+    pub fn get_udp_conn(&self, hd: ConnId) -> Option<Arc<UdpConn>> {
+        let udp_conn_table = self.udp_conn_table.read();
+        udp_conn_table.get(&hd).cloned()
+    }
+
+    /// Add udp conn
+    pub fn insert_udp_conn(&self, hd: ConnId, conn: &Arc<UdpConn>) {
+        let mut udp_conn_table_mut = self.udp_conn_table.write();
+        log::info!("[hd={}] ++++++++ service net insert_udp_conn", hd);
+        udp_conn_table_mut.insert(hd, conn.clone());
+    }
+
+    /// Remove udp conn
+    pub fn remove_udp_conn(&self, hd: ConnId) -> Option<Arc<UdpConn>> {
+        let mut udp_conn_table_mut = self.udp_conn_table.write();
+        log::info!("[hd={}] -------- service net remove_udp_conn", hd);
+
+        // 与 tcp 连接相同，之后旧 hd 的查找都返回 StaleConnId
+        self.conn_ids.write().release(hd);
+        udp_conn_table_mut.remove(&hd)
+    }
+
+    /// message io 的 resource 是否属于 udp socket
+    #[inline(always)]
+    pub fn is_udp_resource(&self, raw_id: usize) -> bool {
+        if self.udp_server_table.read().contains_key(&raw_id) {
+            return true;
+        }
+
+        // 先取 udp_conn_table 的锁：connect_to_udp_server 持有写锁直到登记完成
+        let udp_conn_table = self.udp_conn_table.read();
+        match self.conn_ids.read().resolve(raw_id) {
+            Some(hd) => udp_conn_table.contains_key(&hd),
+            None => false,
+        }
+    }
+
+    /// Send over udp conn
+    pub fn send_udp(&self, hd: ConnId, data: &[u8]) {
+        if let Some(conn) = self.get_udp_conn(hd) {
+            conn.send(data);
+        } else {
+            log::error!("[hd={}] udp send failed -- hd not found!!!", hd);
+        }
+    }
+
     /// Send over tcp conn
     #[inline(always)]
     pub fn send(&self, hd: ConnId, data: &[u8]) {
@@ -171,11 +350,17 @@ pub fn stop_network(srv_net: &Arc<ServiceNetRs>) {
     srv_net.inner_network.stop();
 }
 
-/// Listen on [ip:port] over service net
+/// Listen on [ip:port] over service net, 返回的 listener id 可用 ListenerHandle 暂停 accept 或遍历连接
+///
+/// conn_fn 参数为新建立的 conn，可直接读取 remote_addr() 等连接信息.
+/// framing 为 accept 的 conn 的分帧方式：PacketType::Server 为服务器内部包，
+/// PacketType::Websocket 先完成 http upgrade 握手，之后按 RFC 6455 帧收发
+#[allow(clippy::too_many_arguments)]
 pub fn listen_tcp_addr<T, C, P, S>(
     srv: &Arc<T>,
     ip: String,
     port: u16,
+    framing: PacketType,
     conn_fn: C,
     pkt_fn: P,
     close_fn: S,
@@ -183,12 +368,122 @@ pub fn listen_tcp_addr<T, C, P, S>(
 ) -> TcpListenerId
 where
     T: ServiceRs + 'static,
-    C: Fn(ConnId) + Send + Sync + 'static,
+    C: Fn(Arc<TcpConn>) + Send + Sync + 'static,
     P: Fn(ConnId, NetPacketGuard) + Send + Sync + 'static,
     S: Fn(ConnId) + Send + Sync + 'static,
+{
+    log::info!(
+        "service net listen {}:{} framing: {:?}...",
+        ip,
+        port,
+        framing
+    );
+
+    listen_tcp_server(srv, ip, port, srv_net, move |tcp_server| {
+        tcp_server.set_connection_callback(conn_fn);
+        tcp_server.set_message_callback(pkt_fn);
+        tcp_server.set_close_callback(close_fn);
+        tcp_server.set_framing(framing);
+    })
+}
+
+/// Listen on [ip:port] over service net, close_fn receives the closed conn
+pub fn listen_tcp_addr_ex<T, C, P, S>(
+    srv: &Arc<T>,
+    ip: String,
+    port: u16,
+    conn_fn: C,
+    pkt_fn: P,
+    close_fn: S,
+    srv_net: &Arc<ServiceNetRs>,
+) -> TcpListenerId
+where
+    T: ServiceRs + 'static,
+    C: Fn(Arc<TcpConn>) + Send + Sync + 'static,
+    P: Fn(ConnId, NetPacketGuard) + Send + Sync + 'static,
+    S: Fn(Arc<TcpConn>) + Send + Sync + 'static,
+{
+    let limit = SendQueueLimit::default();
+    listen_tcp_addr_with_limit(srv, ip, port, conn_fn, pkt_fn, close_fn, limit, srv_net)
+}
+
+/// Listen on [ip:port] over service net, accept 的 conn 使用 send_limit 限制发送队列,
+/// 超过 hard limit 时以 CloseReason::SendQueueOverflow 关闭
+pub fn listen_tcp_addr_with_limit<T, C, P, S>(
+    srv: &Arc<T>,
+    ip: String,
+    port: u16,
+    conn_fn: C,
+    pkt_fn: P,
+    close_fn: S,
+    send_limit: SendQueueLimit,
+    srv_net: &Arc<ServiceNetRs>,
+) -> TcpListenerId
+where
+    T: ServiceRs + 'static,
+    C: Fn(Arc<TcpConn>) + Send + Sync + 'static,
+    P: Fn(ConnId, NetPacketGuard) + Send + Sync + 'static,
+    S: Fn(Arc<TcpConn>) + Send + Sync + 'static,
 {
     log::info!("service net listen {}:{}...", ip, port);
 
+    listen_tcp_server(srv, ip, port, srv_net, move |tcp_server| {
+        tcp_server.set_connection_callback(conn_fn);
+        tcp_server.set_message_callback(pkt_fn);
+        tcp_server.set_close_callback_ex(close_fn);
+        tcp_server.set_send_queue_limit(send_limit);
+    })
+}
+
+/// Listen on [ip:port] over service net, 同时存在的 accept 连接最多 max_conns 个（0 表示不限制）.
+/// 达到上限时新连接 accept 后立即关闭，关闭前以对端地址触发 reject_fn，不会触发 conn_fn/close_fn
+#[allow(clippy::too_many_arguments)]
+pub fn listen_tcp_addr_with_max_conns<T, R, C, P, S>(
+    srv: &Arc<T>,
+    ip: String,
+    port: u16,
+    max_conns: u32,
+    reject_fn: R,
+    conn_fn: C,
+    pkt_fn: P,
+    close_fn: S,
+    srv_net: &Arc<ServiceNetRs>,
+) -> TcpListenerId
+where
+    T: ServiceRs + 'static,
+    R: Fn(std::net::SocketAddr) + Send + Sync + 'static,
+    C: Fn(Arc<TcpConn>) + Send + Sync + 'static,
+    P: Fn(ConnId, NetPacketGuard) + Send + Sync + 'static,
+    S: Fn(ConnId) + Send + Sync + 'static,
+{
+    log::info!(
+        "service net listen {}:{} with max conns: {}...",
+        ip,
+        port,
+        max_conns
+    );
+
+    listen_tcp_server(srv, ip, port, srv_net, move |tcp_server| {
+        tcp_server.set_connection_callback(conn_fn);
+        tcp_server.set_message_callback(pkt_fn);
+        tcp_server.set_close_callback(close_fn);
+        tcp_server.set_reject_callback(reject_fn);
+        tcp_server.set_connection_limit(max_conns as usize);
+    })
+}
+
+// 在 srv_net 中创建 tcp server，由 setup 设置回调等，listen 后返回 listener id
+fn listen_tcp_server<T, F>(
+    srv: &Arc<T>,
+    ip: String,
+    port: u16,
+    srv_net: &Arc<ServiceNetRs>,
+    setup: F,
+) -> TcpListenerId
+where
+    T: ServiceRs + 'static,
+    F: FnOnce(&mut TcpServer) + Send + Sync + 'static,
+{
     let (promise, pinky) = PinkySwear::<TcpListenerId>::new();
 
     //
@@ -201,12 +496,11 @@ where
             TcpServer::new(&srv2, addr.as_str(), &srv_net2.inner_network, &srv_net2);
 
         //
-        tcp_server.set_connection_callback(conn_fn);
-        tcp_server.set_message_callback(pkt_fn);
-        tcp_server.set_close_callback(close_fn);
+        setup(&mut tcp_server);
 
         // listen
         tcp_server.listen();
+        tcp_server.id = tcp_server.listener_id;
 
         // add tcp server to serivce net
         {
@@ -227,6 +521,241 @@ where
     promise.wait()
 }
 
+/// Listen udp on [ip:port] over service net, 每个远端地址是一个 session,
+/// idle_timeout 内没有收到数据则触发 close_fn. 返回实际绑定的地址. 原始 UDP，不保证送达和顺序（见 udp_conn 模块说明）
+pub fn listen_udp_addr<T, C, P, S>(
+    srv: &Arc<T>,
+    ip: String,
+    port: u16,
+    conn_fn: C,
+    pkt_fn: P,
+    close_fn: S,
+    idle_timeout: std::time::Duration,
+    srv_net: &Arc<ServiceNetRs>,
+) -> Option<std::net::SocketAddr>
+where
+    T: ServiceRs + 'static,
+    C: Fn(ConnId) + Send + Sync + 'static,
+    P: Fn(ConnId, NetPacketGuard) + Send + Sync + 'static,
+    S: Fn(ConnId) + Send + Sync + 'static,
+{
+    listen_udp_impl(
+        srv,
+        ip,
+        port,
+        conn_fn,
+        pkt_fn,
+        close_fn,
+        idle_timeout,
+        None,
+        srv_net,
+    )
+}
+
+/// Listen kcp on [ip:port] over service net: 与 listen_udp_addr 相同，每个 session 叠加 kcp，
+/// 可靠有序. conv 取自客户端的第一个数据报
+#[allow(clippy::too_many_arguments)]
+pub fn listen_kcp_addr<T, C, P, S>(
+    srv: &Arc<T>,
+    ip: String,
+    port: u16,
+    conn_fn: C,
+    pkt_fn: P,
+    close_fn: S,
+    idle_timeout: std::time::Duration,
+    kcp_config: KcpConfig,
+    srv_net: &Arc<ServiceNetRs>,
+) -> Option<std::net::SocketAddr>
+where
+    T: ServiceRs + 'static,
+    C: Fn(ConnId) + Send + Sync + 'static,
+    P: Fn(ConnId, NetPacketGuard) + Send + Sync + 'static,
+    S: Fn(ConnId) + Send + Sync + 'static,
+{
+    listen_udp_impl(
+        srv,
+        ip,
+        port,
+        conn_fn,
+        pkt_fn,
+        close_fn,
+        idle_timeout,
+        Some(kcp_config),
+        srv_net,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn listen_udp_impl<T, C, P, S>(
+    srv: &Arc<T>,
+    ip: String,
+    port: u16,
+    conn_fn: C,
+    pkt_fn: P,
+    close_fn: S,
+    idle_timeout: std::time::Duration,
+    kcp: Option<KcpConfig>,
+    srv_net: &Arc<ServiceNetRs>,
+) -> Option<std::net::SocketAddr>
+where
+    T: ServiceRs + 'static,
+    C: Fn(ConnId) + Send + Sync + 'static,
+    P: Fn(ConnId, NetPacketGuard) + Send + Sync + 'static,
+    S: Fn(ConnId) + Send + Sync + 'static,
+{
+    let addr = std::format!("{}:{}", ip, port);
+    log::info!(
+        "service net listen {} {}...",
+        if kcp.is_some() { "kcp" } else { "udp" },
+        addr
+    );
+
+    // 持有写锁直到 udp server 登记完成，避免 message io 线程先收到数据
+    let mut udp_server_table_mut = srv_net.udp_server_table.write();
+    match srv_net.inner_network.listen_udp(addr.as_str()) {
+        Ok((listener, sock_addr)) => {
+            let srv: Arc<dyn ServiceRs> = srv.clone();
+            let udp_server = Arc::new(UdpServer {
+                addr: sock_addr,
+                listener,
+                idle_timeout,
+                netctrl: srv_net.inner_network.node_handler.clone(),
+                sessions: RwLock::new(UdpSessions::new()),
+                kcp,
+
+                srv,
+                conn_fn: Arc::new(conn_fn),
+                pkt_fn: Arc::new(pkt_fn),
+                close_fn: Arc::new(close_fn),
+            });
+            udp_server_table_mut.insert(listener.raw(), udp_server);
+            Some(sock_addr)
+        }
+        Err(err) => {
+            log::error!("service net listen udp {} failed!!! error: {}", addr, err);
+            None
+        }
+    }
+}
+
+/// Connect to udp [ip:port], conn_fn 立即触发, idle_timeout 内没有收到数据则触发 close_fn
+pub fn connect_to_udp_server<T, C, P, S>(
+    srv: &Arc<T>,
+    raddr: &str,
+    conn_fn: C,
+    pkt_fn: P,
+    close_fn: S,
+    idle_timeout: std::time::Duration,
+    srv_net: &Arc<ServiceNetRs>,
+) -> Option<ConnId>
+where
+    T: ServiceRs + 'static,
+    C: Fn(ConnId) + Send + Sync + 'static,
+    P: Fn(ConnId, NetPacketGuard) + Send + Sync + 'static,
+    S: Fn(ConnId) + Send + Sync + 'static,
+{
+    connect_to_udp_impl(
+        srv,
+        raddr,
+        conn_fn,
+        pkt_fn,
+        close_fn,
+        idle_timeout,
+        None,
+        srv_net,
+    )
+}
+
+/// Connect to kcp [ip:port]: 与 connect_to_udp_server 相同，conn 上叠加 kcp（随机 conv），
+/// 重传次数达到上限时以 CloseReason::DeadLink 触发 close_fn
+#[allow(clippy::too_many_arguments)]
+pub fn connect_to_kcp_server<T, C, P, S>(
+    srv: &Arc<T>,
+    raddr: &str,
+    conn_fn: C,
+    pkt_fn: P,
+    close_fn: S,
+    idle_timeout: std::time::Duration,
+    kcp_config: KcpConfig,
+    srv_net: &Arc<ServiceNetRs>,
+) -> Option<ConnId>
+where
+    T: ServiceRs + 'static,
+    C: Fn(ConnId) + Send + Sync + 'static,
+    P: Fn(ConnId, NetPacketGuard) + Send + Sync + 'static,
+    S: Fn(ConnId) + Send + Sync + 'static,
+{
+    connect_to_udp_impl(
+        srv,
+        raddr,
+        conn_fn,
+        pkt_fn,
+        close_fn,
+        idle_timeout,
+        Some(kcp_config),
+        srv_net,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn connect_to_udp_impl<T, C, P, S>(
+    srv: &Arc<T>,
+    raddr: &str,
+    conn_fn: C,
+    pkt_fn: P,
+    close_fn: S,
+    idle_timeout: std::time::Duration,
+    kcp: Option<KcpConfig>,
+    srv_net: &Arc<ServiceNetRs>,
+) -> Option<ConnId>
+where
+    T: ServiceRs + 'static,
+    C: Fn(ConnId) + Send + Sync + 'static,
+    P: Fn(ConnId, NetPacketGuard) + Send + Sync + 'static,
+    S: Fn(ConnId) + Send + Sync + 'static,
+{
+    log::info!("[connect_to_udp_server] start connect to {} ...", raddr);
+
+    // 持有写锁直到 udp conn 登记完成，避免 message io 线程先收到数据
+    let mut udp_conn_table_mut = srv_net.udp_conn_table.write();
+    match srv_net.inner_network.connect_udp(raddr) {
+        Ok(endpoint) => {
+            let hd = srv_net.alloc_conn_id(endpoint.resource_id().raw());
+            let srv: Arc<dyn ServiceRs> = srv.clone();
+            let conn = Arc::new(UdpConn::new(
+                hd,
+                endpoint,
+                None,
+                srv_net.inner_network.node_handler.clone(),
+                idle_timeout,
+                &srv,
+                srv_net,
+                Arc::new(conn_fn),
+                Arc::new(pkt_fn),
+                Arc::new(close_fn),
+                kcp.map(|config| (crate::rand_between(1, i32::MAX) as u32, config)),
+            ));
+            log::info!("[hd={}] ++++++++ service net insert_udp_conn", hd);
+            udp_conn_table_mut.insert(hd, conn.clone());
+            drop(udp_conn_table_mut);
+
+            //
+            let srv_net2 = srv_net.clone();
+            let conn2 = conn.clone();
+            srv_net.run_in_service(Box::new(move || {
+                schedule_udp_idle_check(&srv_net2, hd, idle_timeout);
+                schedule_udp_kcp_update(&srv_net2, &conn2);
+            }));
+            conn.run_conn_fn();
+            Some(hd)
+        }
+        Err(err) => {
+            log::error!("[connect_to_udp_server] connect failed!!! error: {}", err);
+            None
+        }
+    }
+}
+
 /// Create tcp client
 pub fn create_tcp_client<T, C, P, S>(
     srv: &Arc<T>,
@@ -239,11 +768,52 @@ pub fn create_tcp_client<T, C, P, S>(
 ) -> Arc<TcpClient>
 where
     T: ServiceRs + 'static,
-    C: Fn(ConnId) + Send + Sync + 'static,
+    C: Fn(Arc<TcpConn>) + Send + Sync + 'static,
     P: Fn(ConnId, NetPacketGuard) + Send + Sync + 'static,
     S: Fn(ConnId) + Send + Sync + 'static,
 {
-    let cli = Arc::new(TcpClient::new(
+    let close_fn = move |conn: Arc<TcpConn>| close_fn(conn.hd);
+    create_tcp_client_ex(srv, name, raddr, conn_fn, pkt_fn, close_fn, srv_net)
+}
+
+/// Create tcp client, close_fn receives the closed conn
+pub fn create_tcp_client_ex<T, C, P, S>(
+    srv: &Arc<T>,
+    name: &str,
+    raddr: &str,
+    conn_fn: C,
+    pkt_fn: P,
+    close_fn: S,
+    srv_net: &Arc<ServiceNetRs>,
+) -> Arc<TcpClient>
+where
+    T: ServiceRs + 'static,
+    C: Fn(Arc<TcpConn>) + Send + Sync + 'static,
+    P: Fn(ConnId, NetPacketGuard) + Send + Sync + 'static,
+    S: Fn(Arc<TcpConn>) + Send + Sync + 'static,
+{
+    let limit = SendQueueLimit::default();
+    create_tcp_client_with_limit(srv, name, raddr, conn_fn, pkt_fn, close_fn, limit, srv_net)
+}
+
+/// Create tcp client, conn 使用 send_limit 限制发送队列
+pub fn create_tcp_client_with_limit<T, C, P, S>(
+    srv: &Arc<T>,
+    name: &str,
+    raddr: &str,
+    conn_fn: C,
+    pkt_fn: P,
+    close_fn: S,
+    send_limit: SendQueueLimit,
+    srv_net: &Arc<ServiceNetRs>,
+) -> Arc<TcpClient>
+where
+    T: ServiceRs + 'static,
+    C: Fn(Arc<TcpConn>) + Send + Sync + 'static,
+    P: Fn(ConnId, NetPacketGuard) + Send + Sync + 'static,
+    S: Fn(Arc<TcpConn>) + Send + Sync + 'static,
+{
+    let mut cli = TcpClient::new(
         srv,
         name,
         raddr,
@@ -252,7 +822,52 @@ where
         pkt_fn,
         close_fn,
         srv_net,
-    ));
+    );
+    cli.set_send_queue_limit(send_limit);
+    let cli = Arc::new(cli);
+
+    // add client to srv_net
+    srv_net.insert_client(&cli.id, &cli);
+
+    //
+    cli
+}
+
+/// Create tcp client with reconnect policy, close_fn 第二个参数为 final（重连次数用尽）
+pub fn create_tcp_client_with_reconnect<T, C, P, S>(
+    srv: &Arc<T>,
+    name: &str,
+    raddr: &str,
+    conn_fn: C,
+    pkt_fn: P,
+    close_fn: S,
+    policy: ReconnectPolicy,
+    srv_net: &Arc<ServiceNetRs>,
+) -> Arc<TcpClient>
+where
+    T: ServiceRs + 'static,
+    C: Fn(Arc<TcpConn>) + Send + Sync + 'static,
+    P: Fn(ConnId, NetPacketGuard) + Send + Sync + 'static,
+    S: Fn(ConnId, bool) + Send + Sync + 'static,
+{
+    let close_fn = Arc::new(close_fn);
+    let close_fn2 = close_fn.clone();
+
+    let mut cli = TcpClient::new(
+        srv,
+        name,
+        raddr,
+        &srv_net.inner_network,
+        conn_fn,
+        pkt_fn,
+        move |conn: Arc<TcpConn>| (close_fn)(conn.hd, false),
+        srv_net,
+    );
+    cli.auto_reconnect = true;
+    cli.reconnect_policy = policy;
+    cli.set_reconnect_exhausted_callback(move |hd| (close_fn2)(hd, true));
+
+    let cli = Arc::new(cli);
 
     // add client to srv_net
     srv_net.insert_client(&cli.id, &cli);
@@ -269,7 +884,16 @@ pub fn handle_message_event(
 ) {
     assert!(srv_net.is_in_service_thread());
 
+    // 收到任何数据都刷新活跃时间
+    conn.idle.touch(std::time::Instant::now());
+
     let input = buffer_pkt.consume();
+
+    // websocket 连接按帧处理
+    if let Some(framer) = &conn.ws_framer {
+        handle_ws_message(srv_net, conn, framer, input);
+        return;
+    }
     let input_data = input.as_ptr();
     let input_len: usize = input.len();
 
@@ -280,14 +904,33 @@ pub fn handle_message_event(
         let len = input_len - pos;
         match conn.handle_read(ptr, len) {
             PacketResult::Ready((pkt, consumed)) => {
-                // 收到一个 pkt trigger pkt_fn
-                conn.run_pkt_fn(pkt);
+                // 收到一个 pkt trigger pkt_fn，心跳包不交给 pkt_fn
+                if let Some(heartbeat) = peek_heartbeat(&pkt) {
+                    handle_heartbeat(conn, heartbeat);
+                } else if !dispatch_rate_limited(srv_net, conn, pkt) {
+                    // 超过限流已关闭
+                    break;
+                }
                 pos += consumed;
             }
             PacketResult::Suspend(consumed) => {
                 // pkt 尚不完整,  continue
                 pos += consumed;
             }
+            PacketResult::Overflow(pkt_full_len) => {
+                log::error!(
+                    "[hd={}][on_message_cb] packet too large!!! pkt_full_len={}",
+                    conn.hd,
+                    pkt_full_len
+                );
+
+                // low level close
+                conn.close_with_reason(CloseReason::PacketTooLarge);
+
+                // handle close conn event
+                handle_close_conn_event(srv_net, &conn);
+                break;
+            }
             PacketResult::Abort(err) => {
                 log::error!("[on_message_cb] handle_read failed!!! error: {}", err);
 
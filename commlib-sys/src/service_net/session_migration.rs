@@ -0,0 +1,1995 @@
+//!
+//! Session migration: 玩家会话在节点间迁移
+//!
+//! 流程：source 逐个组件序列化会话 -> Offer -> target 恢复全部组件并暂存，回复 Ready -> source 停止处理，
+//! 向 gate 发送 RouteUpdate -> gate 更新 SessionRegistry 并在同一连接上回复 RouteAck（排在切换前转发给
+//! source 的包之后）-> source 发送 Commit（携带迁移期间缓存的包）-> target 成为权威节点，回复 Committed
+//! -> source 释放本地副本。
+//!
+//! 是否提交由 target 决定：收到 Commit 时暂存副本仍在则提交，否则回复 Abort。source 在收到
+//! Committed 之前一直保留副本，等待超时则重发 Commit（等待 RouteAck 超时则重发 RouteUpdate）；
+//! 收到 Abort 则重新成为权威节点并让 gate 切回路由。Ready 之前的超时直接回滚，source 仍为权威节点。
+//! 任一组件序列化或恢复失败都整体放弃，不会留下部分恢复的会话。
+//! epoch 作为所有权令牌随消息传递，任何时刻至多只有一个节点持有某个会话的权威副本，
+//! gate 的路由表按 (epoch, rollback) 只接受更新的路由。
+//!
+//! 迁移消息使用保留的 mesh cmd（MIGRATION_*_CMD），经节点间的 NetProxy 连接收发；
+//! 玩家的包由 SessionGate 按路由表转发到权威节点。
+//!
+
+use std::time::{Duration, Instant};
+
+use crate::{NodeId, PlayerId};
+
+use super::{CmdId, ConnId, NetProxy, SendError};
+
+/// 迁移协议号（保留）：Offer
+pub const MIGRATION_OFFER_CMD: CmdId = 0xFFFA;
+
+/// 迁移协议号（保留）：Ready
+pub const MIGRATION_READY_CMD: CmdId = 0xFFF9;
+
+/// 迁移协议号（保留）：Commit
+pub const MIGRATION_COMMIT_CMD: CmdId = 0xFFF8;
+
+/// 迁移协议号（保留）：Committed
+pub const MIGRATION_COMMITTED_CMD: CmdId = 0xFFF7;
+
+/// 迁移协议号（保留）：Abort
+pub const MIGRATION_ABORT_CMD: CmdId = 0xFFF6;
+
+/// 迁移协议号（保留）：节点 -> gate 切换路由
+pub const MIGRATION_ROUTE_CMD: CmdId = 0xFFF5;
+
+/// 迁移协议号（保留）：gate -> 节点 路由已切换
+pub const MIGRATION_ROUTE_ACK_CMD: CmdId = 0xFFF4;
+
+/// 会话中可迁移的组件（背包、任务等），各自序列化和恢复
+pub trait MigratableComponent {
+    /// 组件名，迁移数据按组件名对应，会话内唯一
+    fn name(&self) -> &'static str;
+
+    ///
+    fn serialize(&self) -> Result<Vec<u8>, String>;
+
+    /// 用迁移数据恢复组件状态，version 为 source 的 MigratableSession::VERSION
+    fn restore(&mut self, version: u32, data: &[u8]) -> Result<(), String>;
+}
+
+/// 应用层实现的可迁移会话：由若干组件组成
+pub trait MigratableSession: Sized {
+    /// 序列化格式版本
+    const VERSION: u32;
+
+    /// target 上创建待恢复的会话，全部组件恢复成功后才成为暂存副本
+    fn new_for_restore(pid: PlayerId) -> Self;
+
+    ///
+    fn components(&self) -> Vec<&dyn MigratableComponent>;
+
+    ///
+    fn components_mut(&mut self) -> Vec<&mut dyn MigratableComponent>;
+}
+
+/// 逐个组件序列化：count(4) + (name, data)*，任一组件失败返回错误
+pub fn serialize_session<S: MigratableSession>(session: &S) -> Result<Vec<u8>, String> {
+    let components = session.components();
+    let mut buf = Vec::with_capacity(64);
+    buf.extend_from_slice(&(components.len() as u32).to_le_bytes());
+    for component in components {
+        let data = component
+            .serialize()
+            .map_err(|err| format!("component {} serialize failed: {}", component.name(), err))?;
+        put_bytes(&mut buf, component.name().as_bytes());
+        put_bytes(&mut buf, &data);
+    }
+    Ok(buf)
+}
+
+/// 逐个组件恢复，组件缺失、多余、重复或任一组件恢复失败都返回错误，部分恢复的会话直接丢弃
+pub fn restore_session<S: MigratableSession>(
+    pid: PlayerId,
+    version: u32,
+    data: &[u8],
+) -> Result<S, String> {
+    let mut reader = WireReader { data, pos: 0 };
+    let count = reader.u32()?;
+    let mut parts = hashbrown::HashMap::new();
+    for _ in 0..count {
+        let name = std::str::from_utf8(reader.bytes()?).map_err(|err| err.to_string())?;
+        if parts.insert(name, reader.bytes()?).is_some() {
+            return Err(format!("duplicate component {}", name));
+        }
+    }
+    if reader.pos != data.len() {
+        return Err(format!("trailing {} bytes", data.len() - reader.pos));
+    }
+
+    let mut session = S::new_for_restore(pid);
+    for component in session.components_mut() {
+        let name = component.name();
+        let part = parts
+            .remove(name)
+            .ok_or_else(|| format!("component {} missing", name))?;
+        component
+            .restore(version, part)
+            .map_err(|err| format!("component {} restore failed: {}", name, err))?;
+    }
+    if let Some(name) = parts.keys().next() {
+        return Err(format!("unknown component {}", name));
+    }
+    Ok(session)
+}
+
+/// 所有权令牌
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct OwnershipToken {
+    pub pid: PlayerId,
+    pub epoch: u64,
+}
+
+/// 迁移协议消息
+#[derive(Debug, Clone, PartialEq)]
+pub enum MigrationMsg {
+    Offer {
+        token: OwnershipToken,
+        source: NodeId,
+        version: u32,
+        data: Vec<u8>,
+    },
+    Ready {
+        token: OwnershipToken,
+    },
+    Commit {
+        token: OwnershipToken,
+        packets: Vec<Vec<u8>>, // 迁移期间 source 收到的包，按到达顺序
+    },
+    Committed {
+        token: OwnershipToken,
+    },
+    Abort {
+        token: OwnershipToken,
+        reason: String,
+        packets: Vec<Vec<u8>>, // target 放弃时交还的包（路由切换后 target 收到的包）
+    },
+}
+
+impl MigrationMsg {
+    /// 消息对应的 mesh cmd
+    pub fn cmd(&self) -> CmdId {
+        match self {
+            MigrationMsg::Offer { .. } => MIGRATION_OFFER_CMD,
+            MigrationMsg::Ready { .. } => MIGRATION_READY_CMD,
+            MigrationMsg::Commit { .. } => MIGRATION_COMMIT_CMD,
+            MigrationMsg::Committed { .. } => MIGRATION_COMMITTED_CMD,
+            MigrationMsg::Abort { .. } => MIGRATION_ABORT_CMD,
+        }
+    }
+
+    ///
+    pub fn token(&self) -> OwnershipToken {
+        match self {
+            MigrationMsg::Offer { token, .. }
+            | MigrationMsg::Ready { token }
+            | MigrationMsg::Commit { token, .. }
+            | MigrationMsg::Committed { token }
+            | MigrationMsg::Abort { token, .. } => *token,
+        }
+    }
+
+    /// 编码包体：pid(8) + epoch(8) + 各消息字段，整数为小端，变长字段前加 4 字节长度
+    pub fn encode(&self) -> Vec<u8> {
+        let token = self.token();
+        let mut buf = Vec::with_capacity(64);
+        buf.extend_from_slice(&token.pid.to_le_bytes());
+        buf.extend_from_slice(&token.epoch.to_le_bytes());
+
+        match self {
+            MigrationMsg::Offer {
+                source,
+                version,
+                data,
+                ..
+            } => {
+                buf.extend_from_slice(&source.to_le_bytes());
+                buf.extend_from_slice(&version.to_le_bytes());
+                put_bytes(&mut buf, data);
+            }
+            MigrationMsg::Ready { .. } | MigrationMsg::Committed { .. } => {}
+            MigrationMsg::Commit { packets, .. } => {
+                put_packets(&mut buf, packets);
+            }
+            MigrationMsg::Abort {
+                reason, packets, ..
+            } => {
+                put_bytes(&mut buf, reason.as_bytes());
+                put_packets(&mut buf, packets);
+            }
+        }
+        buf
+    }
+
+    /// 经节点间的连接发送
+    pub fn send(&self, proxy: &NetProxy, hd: ConnId) -> Result<(), SendError> {
+        proxy.send_raw(hd, self.cmd(), &self.encode())
+    }
+
+    /// 解码包体，cmd 不是迁移协议号或包体不完整时返回错误
+    pub fn decode(cmd: CmdId, data: &[u8]) -> Result<Self, String> {
+        let mut reader = WireReader { data, pos: 0 };
+        let token = OwnershipToken {
+            pid: reader.u64()?,
+            epoch: reader.u64()?,
+        };
+
+        let msg = match cmd {
+            MIGRATION_OFFER_CMD => MigrationMsg::Offer {
+                token,
+                source: reader.u64()?,
+                version: reader.u32()?,
+                data: reader.bytes()?.to_vec(),
+            },
+            MIGRATION_READY_CMD => MigrationMsg::Ready { token },
+            MIGRATION_COMMIT_CMD => MigrationMsg::Commit {
+                token,
+                packets: reader.packets()?,
+            },
+            MIGRATION_COMMITTED_CMD => MigrationMsg::Committed { token },
+            MIGRATION_ABORT_CMD => MigrationMsg::Abort {
+                token,
+                reason: String::from_utf8(reader.bytes()?.to_vec())
+                    .map_err(|err| err.to_string())?,
+                packets: reader.packets()?,
+            },
+            _ => return Err(format!("not a migration cmd: {}", cmd)),
+        };
+
+        if reader.pos != data.len() {
+            return Err(format!(
+                "cmd={} trailing {} bytes",
+                cmd,
+                data.len() - reader.pos
+            ));
+        }
+        Ok(msg)
+    }
+}
+
+/// 是否为迁移协议号
+#[inline(always)]
+pub fn is_migration_cmd(cmd: CmdId) -> bool {
+    (MIGRATION_ROUTE_ACK_CMD..=MIGRATION_OFFER_CMD).contains(&cmd)
+}
+
+/// 路由切换：source 收到 Ready 后切到 target，回滚时切回 source
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct RouteUpdate {
+    pub token: OwnershipToken,
+    pub node: NodeId,   // 新的权威节点
+    pub rollback: bool, // 同一 epoch 内回滚优先于切换
+}
+
+impl RouteUpdate {
+    /// 编码包体：pid(8) + epoch(8) + node(8) + rollback(1)
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(25);
+        buf.extend_from_slice(&self.token.pid.to_le_bytes());
+        buf.extend_from_slice(&self.token.epoch.to_le_bytes());
+        buf.extend_from_slice(&self.node.to_le_bytes());
+        buf.push(self.rollback as u8);
+        buf
+    }
+
+    ///
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        let mut reader = WireReader { data, pos: 0 };
+        let update = Self {
+            token: OwnershipToken {
+                pid: reader.u64()?,
+                epoch: reader.u64()?,
+            },
+            node: reader.u64()?,
+            rollback: 0 != reader.take(1)?[0],
+        };
+        if reader.pos != data.len() {
+            return Err(format!(
+                "route update trailing {} bytes",
+                data.len() - reader.pos
+            ));
+        }
+        Ok(update)
+    }
+
+    /// 节点 -> gate
+    pub fn send(&self, proxy: &NetProxy, hd: ConnId) -> Result<(), SendError> {
+        proxy.send_raw(hd, MIGRATION_ROUTE_CMD, &self.encode())
+    }
+}
+
+/// gate 转发给节点的玩家包体：pid(8) + payload
+pub fn encode_forwarded(pid: PlayerId, payload: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(8 + payload.len());
+    buf.extend_from_slice(&pid.to_le_bytes());
+    buf.extend_from_slice(payload);
+    buf
+}
+
+///
+pub fn decode_forwarded(data: &[u8]) -> Result<(PlayerId, &[u8]), String> {
+    let mut reader = WireReader { data, pos: 0 };
+    let pid = reader.u64()?;
+    Ok((pid, &data[reader.pos..]))
+}
+
+struct RouteEntry {
+    node: NodeId,
+    epoch: u64,
+    rollback: bool,
+}
+
+/// 会话路由表：pid -> 权威节点，按 (epoch, rollback) 只接受更新的路由，
+/// 迟到或重复的 RouteUpdate 不会覆盖新路由
+#[derive(Default)]
+pub struct SessionRegistry {
+    routes: hashbrown::HashMap<PlayerId, RouteEntry>,
+}
+
+impl SessionRegistry {
+    ///
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 登录时登记，与 SessionMigrator::register 对应（epoch 从 0 开始）
+    pub fn register(&mut self, pid: PlayerId, node: NodeId) {
+        self.routes.insert(
+            pid,
+            RouteEntry {
+                node,
+                epoch: 0,
+                rollback: false,
+            },
+        );
+    }
+
+    ///
+    pub fn remove(&mut self, pid: PlayerId) {
+        self.routes.remove(&pid);
+    }
+
+    ///
+    pub fn owner(&self, pid: PlayerId) -> Option<NodeId> {
+        self.routes.get(&pid).map(|entry| entry.node)
+    }
+
+    /// 比当前路由新时更新，返回是否更新
+    pub fn update(&mut self, update: &RouteUpdate) -> bool {
+        match self.routes.get_mut(&update.token.pid) {
+            Some(entry)
+                if (update.token.epoch, update.rollback) > (entry.epoch, entry.rollback) =>
+            {
+                entry.node = update.node;
+                entry.epoch = update.token.epoch;
+                entry.rollback = update.rollback;
+                true
+            }
+            Some(_) => false,
+            None => {
+                log::error!(
+                    "[pid={}] route update for unknown session!!!",
+                    update.token.pid
+                );
+                false
+            }
+        }
+    }
+
+    ///
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+
+    ///
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+}
+
+/// gate 转发层：按 SessionRegistry 把玩家的包转发到权威节点，并处理节点发来的路由切换
+#[derive(Default)]
+pub struct SessionGate {
+    registry: SessionRegistry,
+    nodes: hashbrown::HashMap<NodeId, ConnId>, // 到各节点的连接
+}
+
+impl SessionGate {
+    ///
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 登记到节点的连接
+    pub fn add_node(&mut self, node: NodeId, hd: ConnId) {
+        self.nodes.insert(node, hd);
+    }
+
+    ///
+    pub fn remove_node(&mut self, node: NodeId) {
+        self.nodes.remove(&node);
+    }
+
+    ///
+    pub fn registry(&self) -> &SessionRegistry {
+        &self.registry
+    }
+
+    /// 玩家登录到 node
+    pub fn login(&mut self, pid: PlayerId, node: NodeId) {
+        self.registry.register(pid, node);
+    }
+
+    /// 转发玩家的包到权威节点（cmd 不变，包体前加 pid），返回转发到的节点
+    pub fn forward(
+        &self,
+        proxy: &NetProxy,
+        pid: PlayerId,
+        cmd: CmdId,
+        payload: &[u8],
+    ) -> Result<NodeId, MigrationError> {
+        let node = self
+            .registry
+            .owner(pid)
+            .ok_or(MigrationError::NotFound(pid))?;
+        let hd = *self.nodes.get(&node).ok_or(MigrationError::NoRoute(node))?;
+        proxy
+            .send_raw(hd, cmd, &encode_forwarded(pid, payload))
+            .map_err(MigrationError::Send)?;
+        Ok(node)
+    }
+
+    /// 节点发来的 MIGRATION_ROUTE_CMD：更新路由表，并在同一连接上回复 MIGRATION_ROUTE_ACK_CMD.
+    /// 回复排在此前转发给该节点的包之后，source 收到回复时已收齐切换前的包
+    pub fn on_route_update(
+        &mut self,
+        proxy: &NetProxy,
+        hd: ConnId,
+        body: &[u8],
+    ) -> Result<RouteUpdate, MigrationError> {
+        let update = RouteUpdate::decode(body).map_err(MigrationError::Decode)?;
+        if self.registry.update(&update) {
+            log::info!(
+                "[pid={}] route epoch={} switch to node={} rollback={}",
+                update.token.pid,
+                update.token.epoch,
+                update.node,
+                update.rollback
+            );
+        }
+
+        // 重复的 RouteUpdate 同样回复，由 source 按自身状态忽略
+        proxy
+            .send_raw(hd, MIGRATION_ROUTE_ACK_CMD, body)
+            .map_err(MigrationError::Send)?;
+        Ok(update)
+    }
+}
+
+fn put_bytes(buf: &mut Vec<u8>, data: &[u8]) {
+    buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buf.extend_from_slice(data);
+}
+
+fn put_packets(buf: &mut Vec<u8>, packets: &[Vec<u8>]) {
+    buf.extend_from_slice(&(packets.len() as u32).to_le_bytes());
+    for pkt in packets {
+        put_bytes(buf, pkt);
+    }
+}
+
+struct WireReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> WireReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.data.len() - self.pos < len {
+            return Err(format!(
+                "need {} bytes at {}, got {}",
+                len,
+                self.pos,
+                self.data.len() - self.pos
+            ));
+        }
+        let slice = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let slice = self.take(4)?;
+        Ok(u32::from_le_bytes([slice[0], slice[1], slice[2], slice[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let mut bytes = [0_u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], String> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    fn packets(&mut self) -> Result<Vec<Vec<u8>>, String> {
+        // 数量来自对端，不按它预分配
+        let count = self.u32()?;
+        let mut packets = Vec::new();
+        for _ in 0..count {
+            packets.push(self.bytes()?.to_vec());
+        }
+        Ok(packets)
+    }
+}
+
+/// 收到包时的处理方式
+#[derive(Debug, PartialEq, Eq)]
+pub enum PacketDisposition {
+    Handle,   // 本节点权威，直接处理
+    Buffered, // 迁移中，缓存后在迁移结束时交给权威节点处理
+    NotOwner, // 本节点不是权威节点
+}
+
+///
+#[derive(Debug, PartialEq)]
+pub enum MigrationError {
+    NotFound(PlayerId),
+    NotOwner(PlayerId),
+    AlreadyMigrating(PlayerId),
+    Serialize(String),
+    NoRoute(NodeId), // gate 没有到该节点的连接
+    Decode(String),  // 包体解码失败
+    Send(SendError), // 发送失败
+}
+
+struct OwnedSession<S> {
+    session: S,
+    epoch: u64,
+    authoritative: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+enum MigrationPhase {
+    Offered,    // 已发送 Offer，等待 Ready，source 仍为权威节点
+    Switching,  // 已发送 RouteUpdate，等待 gate 确认，source 不再处理，缓存 gate 切换前转发的包
+    Committing, // 已发送 Commit，等待 Committed，source 保留副本但不再处理
+}
+
+struct PendingMigration {
+    target: NodeId,
+    epoch: u64,
+    phase: MigrationPhase,
+    deadline: Instant,
+    packets: Vec<Vec<u8>>,
+}
+
+struct StagedSession<S> {
+    session: S,
+    source: NodeId,
+    epoch: u64,
+    deadline: Instant,
+    packets: Vec<Vec<u8>>, // 路由切换之后、Commit 之前收到的包
+}
+
+/// 单个节点上的会话迁移管理
+pub struct SessionMigrator<S: MigratableSession> {
+    node: NodeId,
+    timeout: Duration,
+
+    sessions: hashbrown::HashMap<PlayerId, OwnedSession<S>>,
+    pending: hashbrown::HashMap<PlayerId, PendingMigration>,
+    staged: hashbrown::HashMap<PlayerId, StagedSession<S>>,
+
+    route_fn: Box<dyn FnMut(RouteUpdate)>, // 发送 RouteUpdate 给 gate（或通知客户端）
+}
+
+impl<S: MigratableSession> SessionMigrator<S> {
+    ///
+    pub fn new<F>(node: NodeId, timeout: Duration, route_fn: F) -> Self
+    where
+        F: FnMut(RouteUpdate) + 'static,
+    {
+        Self {
+            node,
+            timeout,
+
+            sessions: hashbrown::HashMap::new(),
+            pending: hashbrown::HashMap::new(),
+            staged: hashbrown::HashMap::new(),
+
+            route_fn: Box::new(route_fn),
+        }
+    }
+
+    ///
+    #[inline(always)]
+    pub fn node(&self) -> NodeId {
+        self.node
+    }
+
+    /// 登录时注册为权威会话
+    pub fn register(&mut self, pid: PlayerId, session: S) {
+        self.sessions.insert(
+            pid,
+            OwnedSession {
+                session,
+                epoch: 0,
+                authoritative: true,
+            },
+        );
+    }
+
+    ///
+    pub fn get(&self, pid: PlayerId) -> Option<&S> {
+        self.sessions
+            .get(&pid)
+            .filter(|owned| owned.authoritative)
+            .map(|owned| &owned.session)
+    }
+
+    ///
+    pub fn is_authoritative(&self, pid: PlayerId) -> bool {
+        self.sessions
+            .get(&pid)
+            .map_or(false, |owned| owned.authoritative)
+    }
+
+    /// 本节点是否持有会话副本（权威或等待 Committed）
+    pub fn has_session(&self, pid: PlayerId) -> bool {
+        self.sessions.contains_key(&pid)
+    }
+
+    ///
+    pub fn is_migrating(&self, pid: PlayerId) -> bool {
+        self.pending.contains_key(&pid)
+    }
+
+    /// source: 发起迁移
+    pub fn begin_migration(
+        &mut self,
+        pid: PlayerId,
+        target: NodeId,
+        now: Instant,
+    ) -> Result<MigrationMsg, MigrationError> {
+        if self.pending.contains_key(&pid) {
+            return Err(MigrationError::AlreadyMigrating(pid));
+        }
+        let owned = self
+            .sessions
+            .get_mut(&pid)
+            .ok_or(MigrationError::NotFound(pid))?;
+        if !owned.authoritative {
+            return Err(MigrationError::NotOwner(pid));
+        }
+
+        // 任一组件序列化失败则整体放弃，epoch 不变
+        let data = serialize_session(&owned.session).map_err(MigrationError::Serialize)?;
+
+        // 每次发起迁移都占用新的 epoch，回滚后旧 epoch 的消息全部失效
+        owned.epoch += 1;
+        let epoch = owned.epoch;
+
+        self.pending.insert(
+            pid,
+            PendingMigration {
+                target,
+                epoch,
+                phase: MigrationPhase::Offered,
+                deadline: now + self.timeout,
+                packets: Vec::new(),
+            },
+        );
+
+        Ok(MigrationMsg::Offer {
+            token: OwnershipToken { pid, epoch },
+            source: self.node,
+            version: S::VERSION,
+            data,
+        })
+    }
+
+    /// 收到玩家包时调用
+    pub fn on_packet(&mut self, pid: PlayerId, pkt: &[u8]) -> PacketDisposition {
+        if let Some(pending) = self.pending.get_mut(&pid) {
+            if MigrationPhase::Committing != pending.phase {
+                pending.packets.push(pkt.to_vec());
+                return PacketDisposition::Buffered;
+            }
+            // Commit 已发出，gate 已确认切换到 target
+            PacketDisposition::NotOwner
+        } else if let Some(staged) = self.staged.get_mut(&pid) {
+            staged.packets.push(pkt.to_vec());
+            PacketDisposition::Buffered
+        } else if self.is_authoritative(pid) {
+            PacketDisposition::Handle
+        } else {
+            PacketDisposition::NotOwner
+        }
+    }
+
+    /// 处理迁移消息，返回需要回复给发送方的消息，以及需要在本节点按序处理的包
+    pub fn handle_msg(
+        &mut self,
+        msg: MigrationMsg,
+        now: Instant,
+    ) -> (Option<MigrationMsg>, Vec<Vec<u8>>) {
+        match msg {
+            MigrationMsg::Offer {
+                token,
+                source,
+                version,
+                data,
+            } => (
+                Some(self.on_offer(token, source, version, &data, now)),
+                Vec::new(),
+            ),
+            MigrationMsg::Ready { token } => (self.on_ready(token, now), Vec::new()),
+            MigrationMsg::Commit { token, packets } => self.on_commit(token, packets),
+            MigrationMsg::Committed { token } => {
+                self.on_committed(token);
+                (None, Vec::new())
+            }
+            MigrationMsg::Abort {
+                token,
+                reason,
+                packets,
+            } => (None, self.on_abort(token, &reason, packets)),
+        }
+    }
+
+    /// 检查超时，返回需要发送的 (对端节点, 消息)，以及回滚后需要在本节点按序处理的 (pid, 包)
+    #[allow(clippy::type_complexity)]
+    pub fn check_timeout(
+        &mut self,
+        now: Instant,
+    ) -> (Vec<(NodeId, MigrationMsg)>, Vec<(PlayerId, Vec<Vec<u8>>)>) {
+        let mut out = Vec::new();
+        let mut replay = Vec::new();
+
+        let expired: Vec<PlayerId> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| pending.deadline <= now)
+            .map(|(pid, _)| *pid)
+            .collect();
+        for pid in expired {
+            let pending = self.pending.get_mut(&pid).unwrap();
+            let token = OwnershipToken {
+                pid,
+                epoch: pending.epoch,
+            };
+            match pending.phase {
+                MigrationPhase::Offered => {
+                    // 未收到 Ready，回滚，source 仍为权威节点
+                    let pending = self.pending.remove(&pid).unwrap();
+                    log::error!(
+                        "[pid={}] migration to node={} timeout, rollback!!!",
+                        pid,
+                        pending.target
+                    );
+                    out.push((
+                        pending.target,
+                        MigrationMsg::Abort {
+                            token,
+                            reason: "source timeout".to_owned(),
+                            packets: Vec::new(),
+                        },
+                    ));
+                    if !pending.packets.is_empty() {
+                        replay.push((pid, pending.packets));
+                    }
+                }
+                MigrationPhase::Switching => {
+                    // 不知道 gate 是否已切换：重发 RouteUpdate，gate 按 epoch 去重并再次确认
+                    log::warn!(
+                        "[pid={}] migration to node={} wait route ack timeout, resend route update",
+                        pid,
+                        pending.target
+                    );
+                    pending.deadline = now + self.timeout;
+                    let target = pending.target;
+                    (self.route_fn)(RouteUpdate {
+                        token,
+                        node: target,
+                        rollback: false,
+                    });
+                }
+                MigrationPhase::Committing => {
+                    // 不知道 target 是否已提交，不能回滚：重发 Commit，由 target 决定提交或放弃
+                    log::warn!(
+                        "[pid={}] migration to node={} wait committed timeout, resend commit",
+                        pid,
+                        pending.target
+                    );
+                    pending.deadline = now + self.timeout;
+                    out.push((
+                        pending.target,
+                        MigrationMsg::Commit {
+                            token,
+                            packets: pending.packets.clone(),
+                        },
+                    ));
+                }
+            }
+        }
+
+        // target: 未收到 Commit，放弃暂存副本并通知 source 回滚（之后到达的 Commit 回复 Abort）
+        let expired: Vec<PlayerId> = self
+            .staged
+            .iter()
+            .filter(|(_, staged)| staged.deadline <= now)
+            .map(|(pid, _)| *pid)
+            .collect();
+        for pid in expired {
+            let staged = self.staged.remove(&pid).unwrap();
+            log::error!("[pid={}] staged session timeout, drop!!!", pid);
+            out.push((
+                staged.source,
+                MigrationMsg::Abort {
+                    token: OwnershipToken {
+                        pid,
+                        epoch: staged.epoch,
+                    },
+                    reason: "target timeout".to_owned(),
+                    packets: staged.packets,
+                },
+            ));
+        }
+
+        (out, replay)
+    }
+
+    fn on_offer(
+        &mut self,
+        token: OwnershipToken,
+        source: NodeId,
+        version: u32,
+        data: &[u8],
+        now: Instant,
+    ) -> MigrationMsg {
+        let pid = token.pid;
+
+        // epoch 不比本地新的 offer 一律拒绝
+        if let Some(owned) = self.sessions.get(&pid) {
+            if owned.authoritative || owned.epoch >= token.epoch {
+                return MigrationMsg::Abort {
+                    token,
+                    reason: format!("stale epoch from node={}", source),
+                    packets: Vec::new(),
+                };
+            }
+        }
+
+        match restore_session::<S>(pid, version, data) {
+            Ok(session) => {
+                self.staged.insert(
+                    pid,
+                    StagedSession {
+                        session,
+                        source,
+                        epoch: token.epoch,
+                        deadline: now + self.timeout,
+                        packets: Vec::new(),
+                    },
+                );
+                MigrationMsg::Ready { token }
+            }
+            Err(err) => {
+                log::error!("[pid={}] migration restore failed!!! {}", pid, err);
+                MigrationMsg::Abort {
+                    token,
+                    reason: err,
+                    packets: Vec::new(),
+                }
+            }
+        }
+    }
+
+    fn on_ready(&mut self, token: OwnershipToken, now: Instant) -> Option<MigrationMsg> {
+        let pid = token.pid;
+        let pending = match self.pending.get_mut(&pid) {
+            Some(pending) if pending.epoch == token.epoch => pending,
+            _ => {
+                // 已回滚或 epoch 不匹配
+                return Some(MigrationMsg::Abort {
+                    token,
+                    reason: "no pending migration".to_owned(),
+                    packets: Vec::new(),
+                });
+            }
+        };
+
+        match pending.phase {
+            MigrationPhase::Offered if pending.deadline > now => {}
+            MigrationPhase::Offered => {
+                // 已超时，等待 check_timeout 回滚
+                return None;
+            }
+            MigrationPhase::Switching => {
+                // 重复的 Ready，继续等待 gate 确认
+                return None;
+            }
+            MigrationPhase::Committing => {
+                // 重复的 Ready，重发 Commit
+                return Some(MigrationMsg::Commit {
+                    token,
+                    packets: pending.packets.clone(),
+                });
+            }
+        }
+
+        // 先停止处理再切换路由，保证不会同时有两个权威节点；副本保留到收到 Committed
+        pending.phase = MigrationPhase::Switching;
+        pending.deadline = now + self.timeout;
+        let target = pending.target;
+        if let Some(owned) = self.sessions.get_mut(&pid) {
+            owned.authoritative = false;
+        }
+        (self.route_fn)(RouteUpdate {
+            token,
+            node: target,
+            rollback: false,
+        });
+        None
+    }
+
+    /// source: gate 确认路由已切到 target（MIGRATION_ROUTE_ACK_CMD），返回发给 target 的 Commit.
+    /// gate 切换前转发的包都已缓存，Commit 中的包在 target 直接收到的包之前
+    pub fn on_route_ack(
+        &mut self,
+        update: RouteUpdate,
+        now: Instant,
+    ) -> Option<(NodeId, MigrationMsg)> {
+        let token = update.token;
+        let pending = match self.pending.get_mut(&token.pid) {
+            Some(pending)
+                if pending.epoch == token.epoch
+                    && pending.target == update.node
+                    && !update.rollback
+                    && MigrationPhase::Switching == pending.phase =>
+            {
+                pending
+            }
+            _ => {
+                // 重复的确认或回滚的确认
+                return None;
+            }
+        };
+
+        pending.phase = MigrationPhase::Committing;
+        pending.deadline = now + self.timeout;
+        Some((
+            pending.target,
+            MigrationMsg::Commit {
+                token,
+                packets: pending.packets.clone(),
+            },
+        ))
+    }
+
+    fn on_commit(
+        &mut self,
+        token: OwnershipToken,
+        mut packets: Vec<Vec<u8>>,
+    ) -> (Option<MigrationMsg>, Vec<Vec<u8>>) {
+        let pid = token.pid;
+        match self.staged.get(&pid) {
+            Some(staged) if staged.epoch == token.epoch => {
+                let staged = self.staged.remove(&pid).unwrap();
+                self.sessions.insert(
+                    pid,
+                    OwnedSession {
+                        session: staged.session,
+                        epoch: staged.epoch,
+                        authoritative: true,
+                    },
+                );
+
+                // source 转交的包在前，路由切换之后本节点收到的包在后
+                packets.extend(staged.packets);
+                (Some(MigrationMsg::Committed { token }), packets)
+            }
+            _ => {
+                if self.sessions.get(&pid).map_or(false, |owned| {
+                    owned.authoritative && owned.epoch == token.epoch
+                }) {
+                    // 重发的 Commit，已提交
+                    return (Some(MigrationMsg::Committed { token }), Vec::new());
+                }
+
+                log::error!("[pid={}] commit epoch={} mismatch!!!", pid, token.epoch);
+                (
+                    Some(MigrationMsg::Abort {
+                        token,
+                        reason: "no staged session".to_owned(),
+                        packets: Vec::new(),
+                    }),
+                    Vec::new(),
+                )
+            }
+        }
+    }
+
+    fn on_committed(&mut self, token: OwnershipToken) {
+        let pid = token.pid;
+        if self.pending.get(&pid).map_or(false, |pending| {
+            pending.epoch == token.epoch && MigrationPhase::Committing == pending.phase
+        }) {
+            let pending = self.pending.remove(&pid).unwrap();
+            self.sessions.remove(&pid);
+            log::info!(
+                "[pid={}] migration epoch={} to node={} committed",
+                pid,
+                token.epoch,
+                pending.target
+            );
+        }
+    }
+
+    fn on_abort(
+        &mut self,
+        token: OwnershipToken,
+        reason: &str,
+        packets: Vec<Vec<u8>>,
+    ) -> Vec<Vec<u8>> {
+        let pid = token.pid;
+        log::error!(
+            "[pid={}] migration epoch={} aborted: {}",
+            pid,
+            token.epoch,
+            reason
+        );
+
+        // target: source 已回滚
+        if self
+            .staged
+            .get(&pid)
+            .map_or(false, |staged| staged.epoch == token.epoch)
+        {
+            self.staged.remove(&pid);
+        }
+
+        // source: 重新成为权威节点，处理迁移期间缓存的包和 target 交还的包
+        if !self
+            .pending
+            .get(&pid)
+            .map_or(false, |pending| pending.epoch == token.epoch)
+        {
+            return Vec::new();
+        }
+        let mut pending = self.pending.remove(&pid).unwrap();
+        if MigrationPhase::Offered != pending.phase {
+            if let Some(owned) = self.sessions.get_mut(&pid) {
+                owned.authoritative = true;
+            }
+            (self.route_fn)(RouteUpdate {
+                token,
+                node: self.node,
+                rollback: true,
+            });
+        }
+        pending.packets.extend(packets);
+        pending.packets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{take_packet, PacketType};
+    use super::*;
+    use crate::{NetPacketGuard, ServiceNetRs, ServiceRs, TcpConn};
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    #[derive(Debug, PartialEq, Default)]
+    struct Level {
+        level: u32,
+        broken: bool, // 序列化失败
+    }
+
+    impl MigratableComponent for Level {
+        fn name(&self) -> &'static str {
+            "level"
+        }
+
+        fn serialize(&self) -> Result<Vec<u8>, String> {
+            if self.broken {
+                return Err("broken".to_owned());
+            }
+            Ok(self.level.to_le_bytes().to_vec())
+        }
+
+        fn restore(&mut self, version: u32, data: &[u8]) -> Result<(), String> {
+            if version != TestSession::VERSION {
+                return Err(format!("bad version {}", version));
+            }
+            self.level = u32::from_le_bytes(data.try_into().map_err(|_| "bad level")?);
+            Ok(())
+        }
+    }
+
+    #[derive(Debug, PartialEq, Default)]
+    struct Name(String);
+
+    impl MigratableComponent for Name {
+        fn name(&self) -> &'static str {
+            "name"
+        }
+
+        fn serialize(&self) -> Result<Vec<u8>, String> {
+            Ok(self.0.as_bytes().to_vec())
+        }
+
+        fn restore(&mut self, _version: u32, data: &[u8]) -> Result<(), String> {
+            let name = String::from_utf8(data.to_vec()).map_err(|e| e.to_string())?;
+            // target 上无法恢复的数据
+            if "unrestorable" == name {
+                return Err("unrestorable name".to_owned());
+            }
+            self.0 = name;
+            Ok(())
+        }
+    }
+
+    #[derive(Debug, PartialEq, Default)]
+    struct TestSession {
+        level: Level,
+        name: Name,
+    }
+
+    impl MigratableSession for TestSession {
+        const VERSION: u32 = 1;
+
+        fn new_for_restore(_pid: PlayerId) -> Self {
+            Self::default()
+        }
+
+        fn components(&self) -> Vec<&dyn MigratableComponent> {
+            vec![&self.level, &self.name]
+        }
+
+        fn components_mut(&mut self) -> Vec<&mut dyn MigratableComponent> {
+            vec![&mut self.level, &mut self.name]
+        }
+    }
+
+    fn session(name: &str) -> TestSession {
+        TestSession {
+            level: Level {
+                level: 3,
+                broken: false,
+            },
+            name: Name(name.to_owned()),
+        }
+    }
+
+    const GATE: NodeId = 0;
+
+    struct Node {
+        migrator: SessionMigrator<TestSession>,
+        routes: Rc<RefCell<Vec<RouteUpdate>>>, // route_fn 发出的 RouteUpdate
+        handled: Vec<Vec<u8>>,
+    }
+
+    // 两个进程内节点和 gate：消息按 cmd 编码后经 wire 传递，gate 按路由表转发玩家的包
+    struct Cluster {
+        nodes: Vec<Node>, // node id = index + 1
+        registry: SessionRegistry,
+        wire: VecDeque<(NodeId, NodeId, CmdId, Vec<u8>)>, // (from, to, cmd, body)
+        drop_cmds: Vec<CmdId>,                            // 依次丢弃的消息
+        now: Instant,
+    }
+
+    impl Cluster {
+        fn new() -> Self {
+            let nodes = (1..=2)
+                .map(|id| {
+                    let routes = Rc::new(RefCell::new(Vec::new()));
+                    let routes2 = routes.clone();
+                    Node {
+                        migrator: SessionMigrator::new(
+                            id,
+                            Duration::from_millis(100),
+                            move |update| routes2.borrow_mut().push(update),
+                        ),
+                        routes,
+                        handled: Vec::new(),
+                    }
+                })
+                .collect();
+            Self {
+                nodes,
+                registry: SessionRegistry::new(),
+                wire: VecDeque::new(),
+                drop_cmds: Vec::new(),
+                now: Instant::now(),
+            }
+        }
+
+        fn node(&mut self, id: NodeId) -> &mut Node {
+            &mut self.nodes[id as usize - 1]
+        }
+
+        fn login(&mut self, id: NodeId, pid: PlayerId, session: TestSession) {
+            self.node(id).migrator.register(pid, session);
+            self.registry.register(pid, id);
+        }
+
+        fn post(&mut self, from: NodeId, to: NodeId, msg: MigrationMsg) {
+            self.wire.push_back((from, to, msg.cmd(), msg.encode()));
+        }
+
+        // 节点发给 gate 的 RouteUpdate 进入 wire
+        fn flush_routes(&mut self, id: NodeId) {
+            let updates: Vec<_> = self.node(id).routes.borrow_mut().drain(..).collect();
+            for update in updates {
+                self.wire
+                    .push_back((id, GATE, MIGRATION_ROUTE_CMD, update.encode()));
+            }
+        }
+
+        fn client_send(&mut self, pid: PlayerId, pkt: &[u8]) -> PacketDisposition {
+            let id = self.registry.owner(pid).unwrap();
+            let node = self.node(id);
+            let disposition = node.migrator.on_packet(pid, pkt);
+            if PacketDisposition::Handle == disposition {
+                node.handled.push(pkt.to_vec());
+            }
+            disposition
+        }
+
+        // 投递一条消息，返回其 cmd
+        fn step(&mut self) -> Option<CmdId> {
+            let (from, to, cmd, body) = self.wire.pop_front()?;
+            if self.drop_cmds.first() == Some(&cmd) {
+                self.drop_cmds.remove(0);
+                return Some(cmd);
+            }
+
+            let now = self.now;
+            match cmd {
+                MIGRATION_ROUTE_CMD => {
+                    // gate 更新路由表并回复确认
+                    self.registry.update(&RouteUpdate::decode(&body).unwrap());
+                    self.wire
+                        .push_back((GATE, from, MIGRATION_ROUTE_ACK_CMD, body));
+                }
+                MIGRATION_ROUTE_ACK_CMD => {
+                    let update = RouteUpdate::decode(&body).unwrap();
+                    if let Some((target, commit)) = self.node(to).migrator.on_route_ack(update, now)
+                    {
+                        self.post(to, target, commit);
+                    }
+                }
+                _ => {
+                    let msg = MigrationMsg::decode(cmd, &body).unwrap();
+                    let node = self.node(to);
+                    let (reply, replay) = node.migrator.handle_msg(msg, now);
+                    node.handled.extend(replay);
+                    if let Some(reply) = reply {
+                        self.post(to, from, reply);
+                    }
+                    self.flush_routes(to);
+                }
+            }
+            Some(cmd)
+        }
+
+        fn run(&mut self) {
+            while self.step().is_some() {
+                self.assert_single_owner(7);
+            }
+        }
+
+        fn advance(&mut self, id: NodeId, delta: Duration) {
+            self.now += delta;
+            let now = self.now;
+            let node = self.node(id);
+            let (out, replay) = node.migrator.check_timeout(now);
+            for (_, pkts) in replay {
+                node.handled.extend(pkts);
+            }
+            for (to, msg) in out {
+                self.post(id, to, msg);
+            }
+            self.flush_routes(id);
+        }
+
+        fn begin(&mut self, from: NodeId, to: NodeId, pid: PlayerId) {
+            let now = self.now;
+            let offer = self
+                .node(from)
+                .migrator
+                .begin_migration(pid, to, now)
+                .unwrap();
+            self.post(from, to, offer);
+        }
+
+        fn assert_single_owner(&self, pid: PlayerId) {
+            let owners = self
+                .nodes
+                .iter()
+                .filter(|node| node.migrator.is_authoritative(pid))
+                .count();
+            assert!(owners <= 1, "double authority");
+        }
+    }
+
+    #[test]
+    fn migrate_preserves_session_and_packets() {
+        let mut cluster = Cluster::new();
+        cluster.login(1, 7, session("a"));
+        assert_eq!(cluster.client_send(7, b"p1"), PacketDisposition::Handle);
+
+        cluster.begin(1, 2, 7);
+        assert_eq!(cluster.client_send(7, b"p2"), PacketDisposition::Buffered);
+
+        // Offer -> Ready
+        assert_eq!(cluster.step(), Some(MIGRATION_OFFER_CMD));
+        assert!(!cluster.node(2).migrator.is_authoritative(7));
+        assert_eq!(cluster.client_send(7, b"p3"), PacketDisposition::Buffered);
+
+        // Ready -> source 不再处理但保留副本，通知 gate 切换路由
+        assert_eq!(cluster.step(), Some(MIGRATION_READY_CMD));
+        assert!(!cluster.node(1).migrator.is_authoritative(7));
+        assert!(cluster.node(1).migrator.has_session(7));
+        assert_eq!(cluster.registry.owner(7), Some(1));
+        assert_eq!(cluster.client_send(7, b"p4"), PacketDisposition::Buffered);
+
+        // gate 切换路由，之后的包进入 target 暂存
+        assert_eq!(cluster.step(), Some(MIGRATION_ROUTE_CMD));
+        assert_eq!(cluster.registry.owner(7), Some(2));
+        assert_eq!(cluster.client_send(7, b"p5"), PacketDisposition::Buffered);
+
+        // RouteAck -> Commit
+        assert_eq!(cluster.step(), Some(MIGRATION_ROUTE_ACK_CMD));
+
+        // Commit -> target 权威，按序处理
+        assert_eq!(cluster.step(), Some(MIGRATION_COMMIT_CMD));
+        assert!(cluster.node(2).migrator.is_authoritative(7));
+        assert!(cluster.node(1).migrator.has_session(7));
+        assert_eq!(cluster.client_send(7, b"p6"), PacketDisposition::Handle);
+
+        // Committed -> source 释放副本
+        assert_eq!(cluster.step(), Some(MIGRATION_COMMITTED_CMD));
+        assert!(!cluster.node(1).migrator.has_session(7));
+        assert!(!cluster.node(1).migrator.is_migrating(7));
+        assert_eq!(cluster.step(), None);
+
+        assert_eq!(cluster.node(1).handled, vec![b"p1".to_vec()]);
+        assert_eq!(
+            cluster.node(2).handled,
+            vec![
+                b"p2".to_vec(),
+                b"p3".to_vec(),
+                b"p4".to_vec(),
+                b"p5".to_vec(),
+                b"p6".to_vec()
+            ]
+        );
+        assert_eq!(cluster.node(2).migrator.get(7), Some(&session("a")));
+    }
+
+    #[test]
+    fn lost_commit_is_resent() {
+        let mut cluster = Cluster::new();
+        cluster.login(1, 7, session("b"));
+        cluster.begin(1, 2, 7);
+        cluster.drop_cmds.push(MIGRATION_COMMIT_CMD);
+        cluster.run();
+
+        // Commit 丢失：没有权威节点，source 保留副本，路由已切到 target
+        assert!(!cluster.node(1).migrator.is_authoritative(7));
+        assert!(!cluster.node(2).migrator.is_authoritative(7));
+        assert!(cluster.node(1).migrator.has_session(7));
+        assert_eq!(cluster.client_send(7, b"p"), PacketDisposition::Buffered);
+
+        // source 超时重发 Commit
+        cluster.advance(1, Duration::from_millis(50));
+        assert!(cluster.wire.is_empty());
+        cluster.advance(1, Duration::from_millis(60));
+        cluster.run();
+        assert!(cluster.node(2).migrator.is_authoritative(7));
+        assert!(!cluster.node(1).migrator.has_session(7));
+        assert_eq!(cluster.node(2).handled, vec![b"p".to_vec()]);
+
+        // 重复的 Commit 只回复 Committed
+        let token = OwnershipToken { pid: 7, epoch: 1 };
+        let now = cluster.now;
+        let (reply, replay) = cluster.node(2).migrator.handle_msg(
+            MigrationMsg::Commit {
+                token,
+                packets: vec![b"p".to_vec()],
+            },
+            now,
+        );
+        assert_eq!(reply, Some(MigrationMsg::Committed { token }));
+        assert!(replay.is_empty());
+    }
+
+    #[test]
+    fn lost_route_ack_is_resent() {
+        let mut cluster = Cluster::new();
+        cluster.login(1, 7, session("r"));
+        cluster.begin(1, 2, 7);
+        cluster.drop_cmds.push(MIGRATION_ROUTE_ACK_CMD);
+        cluster.run();
+
+        // gate 已切换但 source 没收到确认，不发送 Commit
+        assert_eq!(cluster.registry.owner(7), Some(2));
+        assert!(!cluster.node(1).migrator.is_authoritative(7));
+        assert_eq!(cluster.client_send(7, b"p"), PacketDisposition::Buffered);
+
+        // source 超时重发 RouteUpdate，重复的路由不改变路由表但仍然确认
+        cluster.advance(1, Duration::from_millis(110));
+        assert_eq!(cluster.step(), Some(MIGRATION_ROUTE_CMD));
+        assert_eq!(cluster.registry.owner(7), Some(2));
+        cluster.run();
+        assert!(cluster.node(2).migrator.is_authoritative(7));
+        assert!(!cluster.node(1).migrator.has_session(7));
+        assert_eq!(cluster.node(2).handled, vec![b"p".to_vec()]);
+    }
+
+    #[test]
+    fn target_timeout_rolls_back() {
+        let mut cluster = Cluster::new();
+        cluster.login(1, 7, session("c"));
+        cluster.begin(1, 2, 7);
+        assert_eq!(cluster.client_send(7, b"p1"), PacketDisposition::Buffered);
+        cluster.drop_cmds.push(MIGRATION_COMMIT_CMD);
+        cluster.run();
+        assert_eq!(cluster.client_send(7, b"p2"), PacketDisposition::Buffered);
+
+        // target 等不到 Commit，放弃并交还包，source 重新成为权威节点并切回路由
+        cluster.advance(2, Duration::from_millis(150));
+        cluster.run();
+        assert!(cluster.node(1).migrator.is_authoritative(7));
+        assert!(!cluster.node(2).migrator.is_authoritative(7));
+        assert_eq!(cluster.registry.owner(7), Some(1));
+        assert_eq!(cluster.client_send(7, b"p3"), PacketDisposition::Handle);
+        assert_eq!(
+            cluster.node(1).handled,
+            vec![b"p1".to_vec(), b"p2".to_vec(), b"p3".to_vec()]
+        );
+
+        // 迟到的 Commit 不能让 target 成为权威节点
+        let now = cluster.now;
+        let (reply, _) = cluster.node(2).migrator.handle_msg(
+            MigrationMsg::Commit {
+                token: OwnershipToken { pid: 7, epoch: 1 },
+                packets: Vec::new(),
+            },
+            now,
+        );
+        assert!(matches!(reply, Some(MigrationMsg::Abort { .. })));
+        assert!(!cluster.node(2).migrator.is_authoritative(7));
+        assert_eq!(cluster.node(1).migrator.get(7), Some(&session("c")));
+    }
+
+    #[test]
+    fn source_timeout_before_ready_rolls_back() {
+        let mut cluster = Cluster::new();
+        cluster.login(1, 7, session("d"));
+        cluster.begin(1, 2, 7);
+        assert_eq!(cluster.client_send(7, b"p1"), PacketDisposition::Buffered);
+        assert_eq!(cluster.step(), Some(MIGRATION_OFFER_CMD));
+
+        // Ready 迟到
+        cluster.advance(1, Duration::from_millis(150));
+        assert!(cluster.node(1).migrator.is_authoritative(7));
+        assert_eq!(cluster.node(1).handled, vec![b"p1".to_vec()]);
+        cluster.run();
+
+        assert!(cluster.node(1).migrator.is_authoritative(7));
+        assert!(!cluster.node(2).migrator.is_authoritative(7));
+        assert_eq!(cluster.registry.owner(7), Some(1));
+        assert_eq!(cluster.client_send(7, b"p2"), PacketDisposition::Handle);
+    }
+
+    #[test]
+    fn ownership_token_prevents_double_authority() {
+        let mut cluster = Cluster::new();
+        cluster.login(1, 7, session("e"));
+        cluster.begin(1, 2, 7);
+        assert_eq!(cluster.step(), Some(MIGRATION_OFFER_CMD));
+
+        // 源节点超时回滚后再次迁移，旧 epoch 的 Commit 不能生效
+        cluster.advance(1, Duration::from_millis(150));
+        cluster.wire.clear();
+        cluster.begin(1, 2, 7);
+        let stale = MigrationMsg::Commit {
+            token: OwnershipToken { pid: 7, epoch: 1 },
+            packets: Vec::new(),
+        };
+        cluster.post(1, 2, stale);
+        assert_eq!(cluster.step(), Some(MIGRATION_OFFER_CMD));
+        assert_eq!(cluster.step(), Some(MIGRATION_COMMIT_CMD));
+        assert!(!cluster.node(2).migrator.is_authoritative(7));
+        cluster.assert_single_owner(7);
+
+        // 新 epoch 的迁移正常完成
+        cluster.run();
+        assert!(cluster.node(2).migrator.is_authoritative(7));
+        assert!(!cluster.node(1).migrator.has_session(7));
+        assert_eq!(cluster.registry.owner(7), Some(2));
+    }
+
+    #[test]
+    fn partial_component_failure_aborts() {
+        let mut cluster = Cluster::new();
+        cluster.login(1, 7, session("f"));
+
+        // source: 任一组件序列化失败则不发起迁移，epoch 不变
+        let now = cluster.now;
+        let node = cluster.node(1);
+        node.migrator
+            .sessions
+            .get_mut(&7)
+            .unwrap()
+            .session
+            .level
+            .broken = true;
+        assert!(matches!(
+            node.migrator.begin_migration(7, 2, now),
+            Err(MigrationError::Serialize(_))
+        ));
+        assert!(!node.migrator.is_migrating(7));
+        assert!(node.migrator.is_authoritative(7));
+        assert_eq!(node.migrator.sessions[&7].epoch, 0);
+
+        // target: 任一组件恢复失败则不暂存副本，回复 Abort，source 回滚并处理缓存的包
+        let owned = node.migrator.sessions.get_mut(&7).unwrap();
+        owned.session.level.broken = false;
+        owned.session.name.0 = "unrestorable".to_owned();
+        cluster.begin(1, 2, 7);
+        assert_eq!(cluster.client_send(7, b"p1"), PacketDisposition::Buffered);
+        cluster.run();
+
+        assert!(cluster.node(1).migrator.is_authoritative(7));
+        assert!(!cluster.node(1).migrator.is_migrating(7));
+        assert!(cluster.node(2).migrator.staged.is_empty());
+        assert!(!cluster.node(2).migrator.has_session(7));
+        assert_eq!(cluster.registry.owner(7), Some(1));
+        assert_eq!(cluster.client_send(7, b"p2"), PacketDisposition::Handle);
+        assert_eq!(
+            cluster.node(1).handled,
+            vec![b"p1".to_vec(), b"p2".to_vec()]
+        );
+    }
+
+    #[test]
+    fn restore_session_all_or_nothing() {
+        let data = serialize_session(&session("g")).unwrap();
+        assert_eq!(
+            restore_session::<TestSession>(7, TestSession::VERSION, &data),
+            Ok(session("g"))
+        );
+        assert!(restore_session::<TestSession>(7, 2, &data).is_err());
+        assert!(restore_session::<TestSession>(7, 1, &data[..data.len() - 1]).is_err());
+
+        let encode = |parts: &[(&str, &[u8])]| {
+            let mut buf = (parts.len() as u32).to_le_bytes().to_vec();
+            for (name, part) in parts {
+                put_bytes(&mut buf, name.as_bytes());
+                put_bytes(&mut buf, part);
+            }
+            buf
+        };
+        let level = 3_u32.to_le_bytes();
+
+        // 缺少组件
+        let missing = encode(&[("level", &level)]);
+        let err = restore_session::<TestSession>(7, 1, &missing).unwrap_err();
+        assert!(err.contains("name missing"), "{}", err);
+
+        // 多余、重复的组件
+        let unknown = encode(&[("level", &level), ("name", b"g"), ("bag", b"")]);
+        let err = restore_session::<TestSession>(7, 1, &unknown).unwrap_err();
+        assert!(err.contains("unknown component bag"), "{}", err);
+        let duplicate = encode(&[("level", &level), ("level", &level), ("name", b"g")]);
+        assert!(restore_session::<TestSession>(7, 1, &duplicate).is_err());
+
+        // 前面的组件恢复成功、后面的失败，整体失败
+        let bad = encode(&[("level", &level), ("name", b"unrestorable")]);
+        let err = restore_session::<TestSession>(7, 1, &bad).unwrap_err();
+        assert!(err.contains("component name restore failed"), "{}", err);
+    }
+
+    #[test]
+    fn registry_accepts_only_newer_routes() {
+        let mut registry = SessionRegistry::new();
+        registry.register(7, 1);
+        let route = |epoch: u64, node: NodeId, rollback: bool| RouteUpdate {
+            token: OwnershipToken { pid: 7, epoch },
+            node,
+            rollback,
+        };
+
+        assert!(registry.update(&route(1, 2, false)));
+        assert!(!registry.update(&route(1, 2, false)));
+        assert_eq!(registry.owner(7), Some(2));
+
+        // 同一 epoch 回滚优先，迟到的切换不再生效
+        assert!(registry.update(&route(1, 1, true)));
+        assert!(!registry.update(&route(1, 2, false)));
+        assert_eq!(registry.owner(7), Some(1));
+
+        assert!(registry.update(&route(2, 2, false)));
+        assert_eq!(registry.owner(7), Some(2));
+
+        // 未登记的会话
+        let mut other = route(1, 2, false);
+        other.token.pid = 8;
+        assert!(!registry.update(&other));
+        assert_eq!(registry.owner(8), None);
+        assert_eq!(registry.len(), 1);
+    }
+
+    #[test]
+    fn wire_encoding_round_trip() {
+        let token = OwnershipToken { pid: 7, epoch: 3 };
+        let msgs = vec![
+            MigrationMsg::Offer {
+                token,
+                source: 1,
+                version: 2,
+                data: b"data".to_vec(),
+            },
+            MigrationMsg::Ready { token },
+            MigrationMsg::Commit {
+                token,
+                packets: vec![b"p1".to_vec(), Vec::new()],
+            },
+            MigrationMsg::Committed { token },
+            MigrationMsg::Abort {
+                token,
+                reason: "timeout".to_owned(),
+                packets: vec![b"p2".to_vec()],
+            },
+        ];
+        for msg in msgs {
+            assert!(is_migration_cmd(msg.cmd()));
+            let body = msg.encode();
+            assert_eq!(MigrationMsg::decode(msg.cmd(), &body), Ok(msg.clone()));
+            assert!(MigrationMsg::decode(msg.cmd(), &body[..body.len() - 1]).is_err());
+        }
+        assert!(!is_migration_cmd(0xFFFB));
+        assert!(MigrationMsg::decode(0xFFFB, &[0; 16]).is_err());
+
+        let update = RouteUpdate {
+            token,
+            node: 2,
+            rollback: true,
+        };
+        let body = update.encode();
+        assert_eq!(RouteUpdate::decode(&body), Ok(update));
+        assert!(RouteUpdate::decode(&body[..body.len() - 1]).is_err());
+        assert!(is_migration_cmd(MIGRATION_ROUTE_CMD));
+        assert!(is_migration_cmd(MIGRATION_ROUTE_ACK_CMD));
+        assert!(!is_migration_cmd(MIGRATION_ROUTE_ACK_CMD - 1));
+
+        let forwarded = encode_forwarded(7, b"payload");
+        assert_eq!(decode_forwarded(&forwarded), Ok((7, &b"payload"[..])));
+        assert!(decode_forwarded(&forwarded[..7]).is_err());
+    }
+
+    const E2E_PID: PlayerId = 7;
+    const CLIENT_CMD: CmdId = 100;
+    const HELLO_CMD: CmdId = 101; // 连接建立后发送本端节点 id
+
+    struct E2eNode {
+        migrator: SessionMigrator<TestSession>,
+        routes: Rc<RefCell<Vec<RouteUpdate>>>,
+        peers: hashbrown::HashMap<NodeId, ConnId>, // 对端节点 id（gate 为 0）-> 连接
+        handled: Arc<parking_lot::Mutex<Vec<Vec<u8>>>>,
+    }
+
+    thread_local! {
+        static G_PROXIES: RefCell<hashbrown::HashMap<NodeId, NetProxy>> =
+            RefCell::new(hashbrown::HashMap::new());
+        static G_NODES: RefCell<hashbrown::HashMap<NodeId, E2eNode>> =
+            RefCell::new(hashbrown::HashMap::new());
+        static G_GATE: RefCell<SessionGate> = RefCell::new(SessionGate::new());
+    }
+
+    fn with_proxy<R>(id: NodeId, f: impl FnOnce(&mut NetProxy) -> R) -> R {
+        G_PROXIES.with(|g| f(g.borrow_mut().get_mut(&id).unwrap()))
+    }
+
+    fn with_node<R>(id: NodeId, f: impl FnOnce(&mut E2eNode) -> R) -> R {
+        G_NODES.with(|g| f(g.borrow_mut().get_mut(&id).unwrap()))
+    }
+
+    // 在 srv_net 线程中执行并等待结果
+    fn in_service<R, F>(srv_net: &Arc<ServiceNetRs>, f: F) -> R
+    where
+        R: Send + 'static,
+        F: FnOnce() -> R + Send + Sync + 'static,
+    {
+        let (tx, rx) = std::sync::mpsc::channel();
+        srv_net.run_in_service(Box::new(move || tx.send(f()).unwrap()));
+        rx.recv().unwrap()
+    }
+
+    // 节点：迁移消息在节点间的连接上收发，RouteUpdate 经 gate 连接发出
+    fn node_proxy(srv_net: &Arc<ServiceNetRs>, id: NodeId) -> NetProxy {
+        let mut proxy = NetProxy::new(PacketType::Server, srv_net);
+        proxy.set_packet_handler(HELLO_CMD, move |_, hd, _, body| {
+            let peer = NodeId::from_le_bytes(body.try_into().unwrap());
+            with_node(id, |node| node.peers.insert(peer, hd));
+        });
+        proxy.set_packet_handler(CLIENT_CMD, move |_, _, _, body| {
+            let (pid, payload) = decode_forwarded(body).unwrap();
+            with_node(id, |node| {
+                if PacketDisposition::Handle == node.migrator.on_packet(pid, payload) {
+                    node.handled.lock().push(payload.to_vec());
+                }
+            });
+        });
+        proxy.set_packet_handler(MIGRATION_ROUTE_ACK_CMD, move |proxy, _, _, body| {
+            let update = RouteUpdate::decode(body).unwrap();
+            with_node(id, |node| {
+                if let Some((target, commit)) = node.migrator.on_route_ack(update, Instant::now()) {
+                    commit.send(proxy, node.peers[&target]).unwrap();
+                }
+            });
+        });
+        for cmd in MIGRATION_ABORT_CMD..=MIGRATION_OFFER_CMD {
+            proxy.set_packet_handler(cmd, move |proxy, hd, cmd, body| {
+                let msg = MigrationMsg::decode(cmd, body).unwrap();
+                with_node(id, |node| {
+                    let (reply, replay) = node.migrator.handle_msg(msg, Instant::now());
+                    node.handled.lock().extend(replay);
+                    if let Some(reply) = reply {
+                        reply.send(proxy, hd).unwrap();
+                    }
+                    for update in node.routes.borrow_mut().drain(..) {
+                        update.send(proxy, node.peers[&GATE]).unwrap();
+                    }
+                });
+            });
+        }
+        proxy
+    }
+
+    // gate：客户端连接登录为 E2E_PID，包按路由表转发
+    fn gate_proxy(srv_net: &Arc<ServiceNetRs>) -> NetProxy {
+        let mut proxy = NetProxy::new(PacketType::Server, srv_net);
+        proxy.set_packet_handler(CLIENT_CMD, |proxy, _, cmd, body| {
+            G_GATE.with(|g| g.borrow().forward(proxy, E2E_PID, cmd, body).unwrap());
+        });
+        proxy.set_packet_handler(MIGRATION_ROUTE_CMD, |proxy, hd, _, body| {
+            G_GATE.with(|g| g.borrow_mut().on_route_update(proxy, hd, body).unwrap());
+        });
+        proxy
+    }
+
+    fn listen(srv_net: &'static Arc<ServiceNetRs>, id: NodeId) -> std::net::SocketAddr {
+        use crate::{listen_tcp_addr, ListenerHandle};
+
+        let listener_id = listen_tcp_addr(
+            srv_net,
+            "127.0.0.1".to_owned(),
+            0,
+            PacketType::Server,
+            move |conn: Arc<TcpConn>| {
+                with_proxy(id, |proxy| proxy.on_incomming_conn(conn.hd, false))
+            },
+            move |hd: ConnId, pkt: NetPacketGuard| {
+                with_proxy(id, |proxy| proxy.on_net_packet(hd, pkt))
+            },
+            move |hd: ConnId| with_proxy(id, |proxy| proxy.on_hd_lost(hd)),
+            srv_net,
+        );
+        ListenerHandle::new(srv_net, listener_id)
+            .local_addr()
+            .unwrap()
+    }
+
+    fn connect(
+        srv_net: &'static Arc<ServiceNetRs>,
+        id: NodeId,
+        addr: std::net::SocketAddr,
+    ) -> ConnId {
+        crate::connect_to_tcp_server(
+            srv_net,
+            "migration",
+            addr.to_string().as_str(),
+            move |conn: Arc<TcpConn>| {
+                with_proxy(id, |proxy| {
+                    proxy.on_outgoing_conn(conn.hd, false);
+                    proxy
+                        .send_raw(conn.hd, HELLO_CMD, &id.to_le_bytes())
+                        .unwrap();
+                })
+            },
+            move |hd: ConnId, pkt: NetPacketGuard| {
+                with_proxy(id, |proxy| proxy.on_net_packet(hd, pkt))
+            },
+            move |hd: ConnId| with_proxy(id, |proxy| proxy.on_hd_lost(hd)),
+            srv_net,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn migrate_over_tcp_through_gate() {
+        use crate::{proc_service_ready, start_network, start_service};
+        use std::io::Write;
+
+        let srv_net: &'static Arc<ServiceNetRs> =
+            Box::leak(Box::new(Arc::new(ServiceNetRs::new(4007))));
+        let ready_pair = start_service(srv_net.as_ref(), "test_net", || {});
+        assert!(proc_service_ready(srv_net.as_ref(), ready_pair));
+        start_network(srv_net);
+
+        let handled: Vec<_> = (0..2)
+            .map(|_| Arc::new(parking_lot::Mutex::new(Vec::<Vec<u8>>::new())))
+            .collect();
+        let handled2 = handled.clone();
+        in_service(srv_net, move || {
+            G_PROXIES.with(|g| g.borrow_mut().insert(GATE, gate_proxy(srv_net)));
+            for id in 1..=2 {
+                G_PROXIES.with(|g| g.borrow_mut().insert(id, node_proxy(srv_net, id)));
+                let routes = Rc::new(RefCell::new(Vec::new()));
+                let routes2 = routes.clone();
+                let node = E2eNode {
+                    migrator: SessionMigrator::new(id, Duration::from_secs(5), move |update| {
+                        routes2.borrow_mut().push(update)
+                    }),
+                    routes,
+                    peers: hashbrown::HashMap::new(),
+                    handled: handled2[id as usize - 1].clone(),
+                };
+                G_NODES.with(|g| g.borrow_mut().insert(id, node));
+            }
+        });
+
+        let wait_until = |f: &dyn Fn() -> bool| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while !f() {
+                assert!(Instant::now() < deadline, "wait timeout");
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        };
+
+        // gate -> 各节点，节点间 mesh 连接
+        let gate_addr = listen(srv_net, GATE);
+        let node_addrs = [listen(srv_net, 1), listen(srv_net, 2)];
+        for id in 1..=2 {
+            let hd = connect(srv_net, GATE, node_addrs[id as usize - 1]);
+            in_service(srv_net, move || {
+                G_GATE.with(|g| g.borrow_mut().add_node(id, hd))
+            });
+        }
+        let mesh_hd = connect(srv_net, 1, node_addrs[1]);
+        in_service(srv_net, move || {
+            with_node(1, |node| node.peers.insert(2, mesh_hd))
+        });
+        wait_until(&|| {
+            in_service(srv_net, || {
+                with_node(1, |node| node.peers.contains_key(&GATE))
+                    && with_node(2, |node| node.peers.len() == 2)
+            })
+        });
+
+        // 登录到节点 1
+        in_service(srv_net, || {
+            G_GATE.with(|g| g.borrow_mut().login(E2E_PID, 1));
+            with_node(1, |node| node.migrator.register(E2E_PID, session("e2e")));
+        });
+
+        let wire = |seq: u32| {
+            let mut pkt = take_packet(4);
+            pkt.set_type(PacketType::Server);
+            pkt.set_cmd(CLIENT_CMD);
+            pkt.set_body(&seq.to_le_bytes());
+            assert!(pkt.encode_packet(ConnId::from(0), &hashbrown::HashMap::new()));
+            pkt.consume().to_vec()
+        };
+        let mut client = std::net::TcpStream::connect(gate_addr).unwrap();
+        for seq in 0..10_u32 {
+            client.write_all(&wire(seq)).unwrap();
+        }
+        wait_until(&|| handled[0].lock().len() == 10);
+
+        // 迁移过程中客户端持续发包
+        in_service(srv_net, || {
+            let offer = with_node(1, |node| {
+                node.migrator
+                    .begin_migration(E2E_PID, 2, Instant::now())
+                    .unwrap()
+            });
+            let hd = with_node(1, |node| node.peers[&2]);
+            with_proxy(1, |proxy| offer.send(proxy, hd).unwrap());
+        });
+        for seq in 10..200_u32 {
+            client.write_all(&wire(seq)).unwrap();
+            if 0 == seq % 10 {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+        wait_until(&|| {
+            in_service(srv_net, || {
+                with_node(2, |node| node.migrator.is_authoritative(E2E_PID))
+                    && !with_node(1, |node| node.migrator.has_session(E2E_PID))
+            })
+        });
+        wait_until(&|| handled[0].lock().len() + handled[1].lock().len() == 200);
+
+        // 切换前后的包都只处理一次，且按发送顺序
+        let seqs: Vec<u32> = handled[0]
+            .lock()
+            .iter()
+            .chain(handled[1].lock().iter())
+            .map(|pkt| u32::from_le_bytes(pkt.as_slice().try_into().unwrap()))
+            .collect();
+        assert_eq!(seqs, (0..200).collect::<Vec<_>>());
+        assert!(!handled[1].lock().is_empty());
+        let (owner, restored) = in_service(srv_net, || {
+            (
+                G_GATE.with(|g| g.borrow().registry().owner(E2E_PID)),
+                with_node(2, |node| {
+                    node.migrator.get(E2E_PID) == Some(&session("e2e"))
+                }),
+            )
+        });
+        assert_eq!(owner, Some(2));
+        assert!(restored);
+
+        // 迁回节点 1 时组件恢复失败：整体放弃，节点 2 继续服务
+        in_service(srv_net, || {
+            let offer = with_node(2, |node| {
+                let owned = node.migrator.sessions.get_mut(&E2E_PID).unwrap();
+                owned.session.name.0 = "unrestorable".to_owned();
+                node.migrator
+                    .begin_migration(E2E_PID, 1, Instant::now())
+                    .unwrap()
+            });
+            let hd = with_node(2, |node| node.peers[&1]);
+            with_proxy(2, |proxy| offer.send(proxy, hd).unwrap());
+        });
+        for seq in 200..210_u32 {
+            client.write_all(&wire(seq)).unwrap();
+        }
+        wait_until(&|| {
+            in_service(srv_net, || {
+                !with_node(2, |node| node.migrator.is_migrating(E2E_PID))
+            })
+        });
+        wait_until(&|| handled[1].lock().len() + handled[0].lock().len() == 210);
+        let (owner, authoritative, staged) = in_service(srv_net, || {
+            (
+                G_GATE.with(|g| g.borrow().registry().owner(E2E_PID)),
+                with_node(2, |node| node.migrator.is_authoritative(E2E_PID)),
+                with_node(1, |node| {
+                    node.migrator.has_session(E2E_PID) || !node.migrator.staged.is_empty()
+                }),
+            )
+        });
+        assert_eq!(owner, Some(2));
+        assert!(authoritative);
+        assert!(!staged);
+        let node2_handled = handled[1].lock();
+        let tail: Vec<u32> = node2_handled[node2_handled.len() - 10..]
+            .iter()
+            .map(|pkt| u32::from_le_bytes(pkt.as_slice().try_into().unwrap()))
+            .collect();
+        assert_eq!(tail, (200..210).collect::<Vec<_>>());
+    }
+}
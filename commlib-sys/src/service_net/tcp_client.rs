@@ -11,15 +11,18 @@
 
 use atomic::{Atomic, Ordering};
 use parking_lot::RwLock;
+use std::net::SocketAddr;
 use std::sync::Arc;
 
 use message_io::network::Endpoint;
 
-use crate::service_net::take_small_packet;
+use crate::service_net::{start_heartbeat, take_small_packet};
 use crate::{Clock, ServiceNetRs, ServiceRs};
 
 use super::{
-    ClientStatus, ConnId, MessageIoNetwork, NetPacketGuard, PacketReceiver, PacketType, TcpConn,
+    ClientStatus, CloseReason, ConnId, HeartbeatConfig, MessageIoNetwork, NetPacketGuard,
+    PacketReceiver, PacketType, RateLimit, RateLimitState, ReconnectPolicy, SendQueueLimit,
+    SendQueueState, TcpConn, MAX_PACKET_SIZE,
 };
 
 ///
@@ -38,11 +41,19 @@ pub struct TcpClient {
     pub mi_network: Arc<MessageIoNetwork>,
     pub srv_net: Arc<ServiceNetRs>,
     pub auto_reconnect: bool,
+    pub reconnect_policy: ReconnectPolicy,
+    reconnect_attempts: Atomic<u32>,
+    reconnect_gen: Atomic<u32>,
+    reconnect_cancelled: Atomic<bool>,
 
     //
-    pub conn_fn: Arc<dyn Fn(ConnId) + Send + Sync>,
+    pub conn_fn: Arc<dyn Fn(Arc<TcpConn>) + Send + Sync>,
     pub pkt_fn: Arc<dyn Fn(ConnId, NetPacketGuard) + Send + Sync>,
-    pub close_fn: Arc<dyn Fn(ConnId) + Send + Sync>,
+    pub close_fn: Arc<dyn Fn(Arc<TcpConn>) + Send + Sync>,
+    pub exhausted_fn: Arc<dyn Fn(ConnId) + Send + Sync>,
+    pub send_limit: SendQueueLimit,
+    pub max_packet_size: Atomic<usize>,
+    pub heartbeat: RwLock<HeartbeatConfig>,
 
     //
     pub inner_hd: Atomic<ConnId>,
@@ -62,9 +73,9 @@ impl TcpClient {
     ) -> TcpClient
     where
         T: ServiceRs + 'static,
-        C: Fn(ConnId) + Send + Sync + 'static,
+        C: Fn(Arc<TcpConn>) + Send + Sync + 'static,
         P: Fn(ConnId, NetPacketGuard) + Send + Sync + 'static,
-        S: Fn(ConnId) + Send + Sync + 'static,
+        S: Fn(Arc<TcpConn>) + Send + Sync + 'static,
     {
         Self {
             start: std::time::Instant::now(),
@@ -79,12 +90,20 @@ impl TcpClient {
             mi_network: mi_network.clone(),
             srv_net: srv_net.clone(),
             auto_reconnect: true,
+            reconnect_policy: ReconnectPolicy::default(),
+            reconnect_attempts: Atomic::new(0),
+            reconnect_gen: Atomic::new(0),
+            reconnect_cancelled: Atomic::new(false),
 
             conn_fn: Arc::new(conn_fn),
             pkt_fn: Arc::new(pkt_fn),
             close_fn: Arc::new(close_fn),
+            exhausted_fn: Arc::new(|_hd| {}),
+            send_limit: SendQueueLimit::default(),
+            max_packet_size: Atomic::new(MAX_PACKET_SIZE),
+            heartbeat: RwLock::new(HeartbeatConfig::default()),
 
-            inner_hd: Atomic::new(ConnId::from(0)),
+            inner_hd: Atomic::new(ConnId::from_raw(0)),
         }
     }
 
@@ -111,6 +130,7 @@ impl TcpClient {
 
         // status: Connecting
         self.set_status(ClientStatus::Connecting);
+        self.reconnect_cancelled.store(false, Ordering::Relaxed);
 
         // inner connect
         let mi_network = self.mi_network.clone();
@@ -153,29 +173,61 @@ impl TcpClient {
             return Err(errmsg);
         }
 
+        // 重连次数用尽，通知上层不再重连
+        let attempts = self.reconnect_attempts();
+        if self.reconnect_policy.is_exhausted(attempts) {
+            let errmsg = format!("reconnect attempts exhausted ({})", attempts);
+            log::error!(
+                "[hd={}]({}) give up reconnect to raddr: {} -- id<{}>!!! {}!!!",
+                self.inner_hd(),
+                self.name,
+                self.raddr,
+                self.id,
+                errmsg,
+            );
+
+            let hd = self.inner_hd();
+            let exhausted_fn = self.exhausted_fn.clone();
+            self.srv.run_in_service(Box::new(move || {
+                (exhausted_fn)(hd);
+            }));
+            return Err(errmsg);
+        }
+
         //
-        const DELAY_MS: u64 = 5000_u64; // ms
+        let attempt = attempts + 1;
+        self.reconnect_attempts.store(attempt, Ordering::Relaxed);
+        let delay_ms = self.reconnect_policy.delay_for(attempt).as_millis() as u64;
         log::info!(
-            "[hd={}]({}) try to reconnect after {}ms -- id<{}> ...",
+            "[hd={}]({}) try to reconnect after {}ms (attempt {}) -- id<{}> ...",
             self.inner_hd(),
             self.name,
-            DELAY_MS,
+            delay_ms,
+            attempt,
             self.id
         );
 
         let hd = self.inner_hd();
         let name = self.name.clone();
         let cli_id = self.id.clone();
+        let gen = self.reconnect_gen.load(Ordering::Relaxed);
 
         let srv_net = self.srv_net.clone();
 
         //
-        Clock::set_timeout(self.srv_net.as_ref(), DELAY_MS, move || {
+        Clock::set_timeout(self.srv_net.as_ref(), delay_ms, move || {
             log::info!("[hd={}]({}) reconnect -- id<{}> ...", hd, name, cli_id);
             {
                 let client_opt = srv_net.get_client(&cli_id);
                 if let Some(cli) = client_opt {
-                    if cli.status().is_connected() {
+                    if cli.reconnect_gen.load(Ordering::Relaxed) != gen {
+                        log::info!(
+                            "[hd={}]({}) reconnect cancelled -- id<{}>.",
+                            hd,
+                            name,
+                            cli_id
+                        );
+                    } else if cli.status().is_connected() {
                         log::error!(
                             "[hd={}]({}) reconnect failed -- id<{}>!!! already connected!!!",
                             hd,
@@ -234,6 +286,22 @@ impl TcpClient {
             self.id,
         );
 
+        // 手动关闭：取消尚未触发的重连
+        self.cancel_reconnect();
+
+        let cb = Arc::new(move |hd: ConnId| {
+            log::info!("[hd={}] disconnect over.", hd);
+            disconneced_cb(hd);
+        });
+
+        // 等待重连中，没有可关闭的连接，立即回调
+        if self.status().is_idle() {
+            self.srv.run_in_service(Box::new(move || {
+                (cb)(inner_hd);
+            }));
+            return Ok(());
+        }
+
         // client 必须处于连接状态
         if !self.status().is_connected() {
             let errmsg = "wrong status".to_owned();
@@ -260,11 +328,6 @@ impl TcpClient {
             self.id,
         );
 
-        let cb = Arc::new(move |hd| {
-            log::info!("[hd={}] disconnect over.", hd);
-            disconneced_cb(hd);
-        });
-
         // 在当前线程中加 write 锁
         let mut is_conn_closed = false;
         if let Some(conn) = self.srv_net.get_conn(inner_hd) {
@@ -272,8 +335,9 @@ impl TcpClient {
                 is_conn_closed = true;
             } else {
                 // 修改 close_fn，运行 disconneced_cb
+                let cb2 = cb.clone();
                 let mut close_fn_mut = conn.close_fn.write();
-                (*close_fn_mut) = cb.clone();
+                (*close_fn_mut) = Arc::new(move |conn: Arc<TcpConn>| (cb2)(conn.hd));
 
                 // low level close
                 conn.close();
@@ -300,7 +364,13 @@ impl TcpClient {
     }
 
     /// Make new tcp conn with callbacks from tcp client
-    pub fn make_new_conn(&self, packet_type: PacketType, hd: ConnId, endpoint: Endpoint) {
+    pub fn make_new_conn(
+        &self,
+        packet_type: PacketType,
+        hd: ConnId,
+        endpoint: Endpoint,
+        local_addr: SocketAddr,
+    ) {
         //
         let cli_id = self.id.clone();
         let netctrl = self.mi_network.node_handler.clone();
@@ -309,22 +379,26 @@ impl TcpClient {
         let cli_conn_fn = self.conn_fn.clone();
         let cli_pkt_fn = self.pkt_fn.clone();
         let cli_close_fn = self.close_fn.clone();
+        let send_limit = self.send_limit.clone();
+        let max_packet_size = self.max_packet_size();
+        let heartbeat = self.heartbeat();
 
         let srv_net = self.srv_net.clone();
         let srv = self.srv.clone();
 
         // insert tcp conn in srv net(同一线程便于观察 conn 生命周期)
         let cb = move || {
-            let conn_fn = Arc::new(move |hd| {
-                (*cli_conn_fn)(hd);
+            let conn_fn = Arc::new(move |conn: Arc<TcpConn>| {
+                (*cli_conn_fn)(conn);
             });
             let pkt_fn = Arc::new(move |hd, pkt| {
                 (*cli_pkt_fn)(hd, pkt);
             });
 
             let srv_net2 = srv_net.clone();
-            let close_fn = Arc::new(move |hd| {
-                (*cli_close_fn)(hd);
+            let close_fn = Arc::new(move |conn: Arc<TcpConn>| {
+                let hd = conn.hd;
+                (*cli_close_fn)(conn);
 
                 // close tcp client
                 let cli_opt = srv_net2.get_client(&cli_id);
@@ -351,13 +425,20 @@ impl TcpClient {
                 //
                 packet_type: Atomic::new(PacketType::Server),
                 hd,
+                listener: None,
 
                 //
                 endpoint,
                 netctrl: netctrl.clone(),
 
+                //
+                remote_addr: endpoint.addr(),
+                local_addr,
+                connected_at: std::time::Instant::now(),
+
                 //
                 closed: Atomic::new(false),
+                close_reason: Atomic::new(CloseReason::None),
 
                 //
                 srv: srv.clone(),
@@ -369,16 +450,36 @@ impl TcpClient {
                 close_fn: RwLock::new(close_fn),
 
                 //
-                pkt_receiver: PacketReceiver::new(pkt),
+                pkt_receiver: PacketReceiver::with_max_packet_size(pkt, max_packet_size),
+
+                //
+                send_limit,
+                send_queue: SendQueueState::new(),
+
+                //
+                heartbeat,
+                idle: heartbeat.idle_tracker(),
+
+                //
+                rate_limit: RateLimit::default(),
+                rate_state: RateLimitState::new(),
+
+                //
+                ws_framer: None,
+
+                //
+                user_data: RwLock::new(None),
             });
 
             //
             srv_net.insert_conn(conn.hd, &conn);
+            start_heartbeat(&srv_net, &conn);
 
             // update inner hd for TcpClient
             let cli_opt = srv_net.get_client(&cli_id);
             if let Some(cli) = cli_opt {
                 cli.set_inner_hd(hd);
+                cli.reconnect_attempts.store(0, Ordering::Relaxed);
             }
 
             // trigger conn_fn
@@ -390,7 +491,7 @@ impl TcpClient {
     ///
     pub fn set_connection_callback<F>(&mut self, cb: F)
     where
-        F: Fn(ConnId) + Send + Sync + 'static,
+        F: Fn(Arc<TcpConn>) + Send + Sync + 'static,
     {
         self.conn_fn = Arc::new(cb);
     }
@@ -407,10 +508,66 @@ impl TcpClient {
     pub fn set_close_callback<F>(&mut self, cb: F)
     where
         F: Fn(ConnId) + Send + Sync + 'static,
+    {
+        self.close_fn = Arc::new(move |conn: Arc<TcpConn>| cb(conn.hd));
+    }
+
+    /// close 回调参数为已关闭的 conn，仍可读取连接信息
+    pub fn set_close_callback_ex<F>(&mut self, cb: F)
+    where
+        F: Fn(Arc<TcpConn>) + Send + Sync + 'static,
     {
         self.close_fn = Arc::new(cb);
     }
 
+    /// 新建 conn 使用的发送队列限制
+    pub fn set_send_queue_limit(&mut self, limit: SendQueueLimit) {
+        self.send_limit = limit;
+    }
+
+    /// 新建 conn 接收包长度上限（含包头），超过时以 CloseReason::PacketTooLarge 关闭连接
+    pub fn set_max_packet_size(&self, max_packet_size: usize) {
+        self.max_packet_size
+            .store(max_packet_size, Ordering::Relaxed);
+    }
+
+    ///
+    #[inline(always)]
+    pub fn max_packet_size(&self) -> usize {
+        self.max_packet_size.load(Ordering::Relaxed)
+    }
+
+    /// 新建 conn 使用的心跳配置，只影响之后新建的 conn（包括重连）
+    pub fn set_heartbeat(&self, heartbeat: HeartbeatConfig) {
+        *self.heartbeat.write() = heartbeat;
+    }
+
+    ///
+    #[inline(always)]
+    pub fn heartbeat(&self) -> HeartbeatConfig {
+        *self.heartbeat.read()
+    }
+
+    /// 重连次数用尽时回调，参数为最后一次连接的 hd
+    pub fn set_reconnect_exhausted_callback<F>(&mut self, cb: F)
+    where
+        F: Fn(ConnId) + Send + Sync + 'static,
+    {
+        self.exhausted_fn = Arc::new(cb);
+    }
+
+    /// 当前连续重连次数，连接成功后清零
+    #[inline(always)]
+    pub fn reconnect_attempts(&self) -> u32 {
+        self.reconnect_attempts.load(Ordering::Relaxed)
+    }
+
+    /// 取消尚未触发的重连定时器，并且不再自动重连，直到下一次 connect
+    pub fn cancel_reconnect(&self) {
+        self.reconnect_cancelled.store(true, Ordering::Relaxed);
+        self.reconnect_gen.fetch_add(1, Ordering::Relaxed);
+    }
+
     ///
     #[inline(always)]
     pub fn status(&self) -> ClientStatus {
@@ -423,7 +580,7 @@ impl TcpClient {
     }
 
     fn check_auto_reconnect(&self) {
-        if self.auto_reconnect {
+        if self.auto_reconnect && !self.reconnect_cancelled.load(Ordering::Relaxed) {
             //
             match self.reconnect() {
                 Ok(_) => {
@@ -1,6 +1,9 @@
 use atomic::{Atomic, Ordering};
 use parking_lot::RwLock;
+use std::any::Any;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use message_io::network::Endpoint;
 use message_io::node::NodeHandler;
@@ -8,32 +11,63 @@ use message_io::node::NodeHandler;
 use crate::ServiceRs;
 
 use super::packet_receiver::PacketResult;
-use super::{ConnId, NetPacketGuard, PacketReceiver, PacketType, ServiceNetRs};
+use super::udp_conn::IdleTracker;
+use super::{handle_close_conn_event, SendQueueEvent, SendQueueLimit, SendQueueState};
+use super::{
+    CloseReason, ConnId, HeartbeatConfig, NetPacketGuard, PacketReceiver, PacketType, ServiceNetRs,
+    TcpListenerId,
+};
+use super::{RateLimit, RateLimitState, RateLimitStats, WsFramer};
 
 /// Tcp connection: all fields are public for easy construct
 pub struct TcpConn {
     //
     pub packet_type: Atomic<PacketType>,
     pub hd: ConnId,
+    pub listener: Option<TcpListenerId>, // accept 该连接的 listener，client 连接为 None
 
     //
     pub endpoint: Endpoint,
     pub netctrl: NodeHandler<()>,
 
+    // 对端和本端地址，连接建立（accept/connect）时记录
+    pub remote_addr: SocketAddr,
+    pub local_addr: SocketAddr, // accept 的连接为 listener 绑定的地址
+    pub connected_at: Instant,
+
     //
     pub closed: Atomic<bool>,
+    pub close_reason: Atomic<CloseReason>,
 
     //
     pub srv: Arc<dyn ServiceRs>,
     pub srv_net: Arc<ServiceNetRs>,
 
     //
-    pub conn_fn: Arc<dyn Fn(ConnId) + Send + Sync>,
+    pub conn_fn: Arc<dyn Fn(Arc<TcpConn>) + Send + Sync>,
     pub pkt_fn: Arc<dyn Fn(ConnId, NetPacketGuard) + Send + Sync>,
-    pub close_fn: RwLock<Arc<dyn Fn(ConnId) + Send + Sync>>,
+    pub close_fn: RwLock<Arc<dyn Fn(Arc<TcpConn>) + Send + Sync>>,
 
     //
     pub pkt_receiver: PacketReceiver,
+
+    // 发送队列，不限制时直接发送
+    pub send_limit: SendQueueLimit,
+    pub send_queue: SendQueueState,
+
+    // 心跳配置，idle 记录最后收到数据的时间
+    pub heartbeat: HeartbeatConfig,
+    pub idle: IdleTracker,
+
+    // 收包限流，不限制时直接交给 pkt_fn
+    pub rate_limit: RateLimit,
+    pub rate_state: RateLimitState,
+
+    // websocket 分帧（PacketType::Websocket），None 表示直接发送
+    pub ws_framer: Option<WsFramer>,
+
+    // 上层关联的数据（如玩家 session），close_fn 执行之后自动清除
+    pub user_data: RwLock<Option<Arc<dyn Any + Send + Sync>>>,
 }
 
 impl TcpConn {
@@ -43,6 +77,64 @@ impl TcpConn {
         self.pkt_receiver.read(data, len)
     }
 
+    /// 对端地址
+    #[inline(always)]
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.remote_addr
+    }
+
+    /// 本端地址，accept 的连接为 listener 绑定的地址（如 0.0.0.0:port）
+    #[inline(always)]
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// 连接建立的时间
+    #[inline(always)]
+    pub fn connected_at(&self) -> Instant {
+        self.connected_at
+    }
+
+    /// 设置关联数据，覆盖之前的数据
+    pub fn set_user_data<T>(&self, v: Arc<T>)
+    where
+        T: Any + Send + Sync,
+    {
+        *self.user_data.write() = Some(v);
+    }
+
+    /// 取得关联数据，没有设置或类型不符时返回 None
+    pub fn user_data<T>(&self) -> Option<Arc<T>>
+    where
+        T: Any + Send + Sync,
+    {
+        let data = self.user_data.read().clone()?;
+        data.downcast::<T>().ok()
+    }
+
+    /// 取出并清除关联数据
+    pub fn take_user_data(&self) -> Option<Arc<dyn Any + Send + Sync>> {
+        self.user_data.write().take()
+    }
+
+    /// 最后一次收到数据（包括心跳）的时间
+    #[inline(always)]
+    pub fn last_recv_time(&self) -> Instant {
+        self.idle.last_active()
+    }
+
+    /// 距离最后一次收到数据的时间
+    #[inline(always)]
+    pub fn idle_duration(&self) -> Duration {
+        Instant::now().saturating_duration_since(self.last_recv_time())
+    }
+
+    /// 收包限流统计
+    #[inline(always)]
+    pub fn rate_limit_stats(&self) -> RateLimitStats {
+        self.rate_state.stats()
+    }
+
     /// low level close
     #[inline(always)]
     pub fn close(&self) {
@@ -50,22 +142,120 @@ impl TcpConn {
         self.netctrl.network().remove(self.endpoint.resource_id());
     }
 
+    /// low level close with reason
+    pub fn close_with_reason(&self, reason: CloseReason) {
+        log::info!("[hd={}] close with reason: {:?}", self.hd, reason);
+        self.close_reason.store(reason, Ordering::Relaxed);
+        self.close();
+    }
+
     ///
     #[inline(always)]
+    pub fn close_reason(&self) -> CloseReason {
+        self.close_reason.load(Ordering::Relaxed)
+    }
+
+    /// 发送已编码的包，websocket 连接自动加上帧头（握手完成前缓存）
+    #[inline(always)]
     pub fn send(&self, data: &[u8]) {
+        match &self.ws_framer {
+            Some(framer) => {
+                if let Some(frame) = framer.frame(data) {
+                    self.send_unframed(&frame);
+                }
+            }
+            None => self.send_unframed(data),
+        }
+    }
+
+    /// 原样发送，不经过 websocket 分帧
+    pub fn send_unframed(&self, data: &[u8]) {
+        if self.closed.load(Ordering::Relaxed) {
+            log::error!("[hd={}] send data failed!!! conn is closed!!!", self.hd);
+            return;
+        }
         log::debug!("[hd={}] send data ...", self.hd);
 
-        self.netctrl.network().send(self.endpoint, data);
+        if self.send_limit.is_unlimited() {
+            self.netctrl.network().send(self.endpoint, data);
+        } else {
+            self.send_queued(data);
+        }
+    }
+
+    /// 尚未写入 socket 的字节数
+    #[inline(always)]
+    pub fn send_bytes_pending(&self) -> usize {
+        self.send_queue.pending()
+    }
+
+    /// 待发送字节数低于 high watermark（没有设置时为 hard limit）即为可写，不限制时总是可写
+    pub fn is_writable(&self) -> bool {
+        let threshold = if self.send_limit.high_watermark > 0 {
+            self.send_limit.high_watermark
+        } else {
+            self.send_limit.hard_limit
+        };
+        0 == threshold || self.send_bytes_pending() < threshold
     }
 
-    /// call conn_fn
-    pub fn run_conn_fn(&self) {
+    /// 经 srv_net 队列发送，统计待发送字节数
+    fn send_queued(&self, data: &[u8]) {
         let hd = self.hd;
+        match self.send_queue.push(data.len(), &self.send_limit) {
+            SendQueueEvent::Queued => {}
+            SendQueueEvent::HighWatermark(pending) => {
+                log::warn!("[hd={}] send queue high watermark: {} bytes", hd, pending);
+                if let Some(f) = self.send_limit.high_watermark_fn.clone() {
+                    let srv_net = self.srv_net.clone();
+                    self.srv.run_in_service(Box::new(move || {
+                        if let Some(conn) = srv_net.get_conn(hd) {
+                            (f)(conn, pending);
+                        }
+                    }));
+                }
+            }
+            SendQueueEvent::Overflow(pending) => {
+                log::error!(
+                    "[hd={}] send queue overflow: {} bytes > hard limit {}!!!",
+                    hd,
+                    pending,
+                    self.send_limit.hard_limit
+                );
+                self.close_with_reason(CloseReason::SendQueueOverflow);
+
+                // 触发 close_fn
+                let srv_net = self.srv_net.clone();
+                self.srv_net.run_in_service(Box::new(move || {
+                    if let Some(conn) = srv_net.get_conn(hd) {
+                        handle_close_conn_event(srv_net.as_ref(), &conn);
+                    }
+                }));
+                return;
+            }
+        }
+
+        //
+        let data = data.to_vec();
+        let srv_net = self.srv_net.clone();
+        self.srv_net.run_in_service(Box::new(move || {
+            if let Some(conn) = srv_net.get_conn(hd) {
+                if !conn.closed.load(Ordering::Relaxed) {
+                    conn.netctrl.network().send(conn.endpoint, &data);
+                }
+                conn.send_queue.pop(data.len());
+            }
+        }));
+    }
+
+    /// call conn_fn，参数为新建立的 conn，可读取对端地址等连接信息
+    pub fn run_conn_fn(self: &Arc<Self>) {
+        let conn = self.clone();
         let f = self.conn_fn.clone();
 
         //
         self.srv.run_in_service(Box::new(move || {
-            (f)(hd);
+            (f)(conn);
         }));
     }
 
@@ -80,22 +270,34 @@ impl TcpConn {
         }));
     }
 
-    /// call close_fn
-    pub fn run_close_fn(&self) {
-        let hd = self.hd;
+    /// call close_fn, at most once
+    ///
+    /// close_fn 与 conn_fn 投递到同一个 service 队列，即使 conn_fn 尚未执行，close_fn 也会排在其后执行
+    pub fn run_close_fn(self: &Arc<Self>) {
+        // 标记关闭，之后只读，不能再发送
+        if self.closed.swap(true, Ordering::Relaxed) {
+            log::info!("[hd={}] close_fn already triggered", self.hd);
+            return;
+        }
 
-        let f: Arc<dyn Fn(ConnId) + Send + Sync>;
+        // 释放 listener 的连接名额
+        if let Some(listener) = self.listener {
+            self.srv_net.release_listener_conn(listener);
+        }
+
+        let f: Arc<dyn Fn(Arc<TcpConn>) + Send + Sync>;
         {
             let close_fn = self.close_fn.read();
             f = (*close_fn).clone();
         }
 
-        // 标记关闭
-        self.closed.store(true, Ordering::Relaxed);
-
         //
+        let conn = self.clone();
         self.srv.run_in_service(Box::new(move || {
-            (f)(hd);
+            (f)(conn.clone());
+
+            // close_fn 中仍可读取，之后清除，避免 conn 被引用时 session 无法释放
+            conn.take_user_data();
         }));
     }
 
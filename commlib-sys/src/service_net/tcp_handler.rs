@@ -21,8 +21,8 @@ pub type OnAcceptFuncType = extern "C" fn(
     OsSocketAddr,
 );
 
-///
-pub type OnConnectedFuncType = extern "C" fn(*const TcpClient, ConnId, OsSocketAddr);
+/// (tcp_client, hd, remote addr, local addr)
+pub type OnConnectedFuncType = extern "C" fn(*const TcpClient, ConnId, OsSocketAddr, OsSocketAddr);
 
 ///
 pub type OnMessageFuncType = extern "C" fn(*const Arc<ServiceNetRs>, ConnId, *const u8, usize);
@@ -81,23 +81,41 @@ extern "C" fn on_accept_cb(
     let srv_net = unsafe { &*srv_net_ptr };
     let netctrl = unsafe { &*netctrl_ptr };
 
-    let id = ResourceId::from(hd.id);
+    let id = match srv_net.conn_resource_id(hd) {
+        Ok(raw_id) => ResourceId::from(raw_id),
+        Err(err) => {
+            log::error!("[on_accept_cb] make new conn failed!!! error: {}", err);
+            return;
+        }
+    };
     let sock_addr = os_addr.into_addr().unwrap();
     let endpoint = Endpoint::new(id, sock_addr);
 
     // make new conn
-    listener_id.make_new_conn(PacketType::Server, hd, endpoint, netctrl, srv_net);
+    listener_id.make_new_conn(hd, endpoint, netctrl, srv_net);
 }
 
-extern "C" fn on_connected_cb(tcp_client_ptr: *const TcpClient, hd: ConnId, os_addr: OsSocketAddr) {
+extern "C" fn on_connected_cb(
+    tcp_client_ptr: *const TcpClient,
+    hd: ConnId,
+    os_addr: OsSocketAddr,
+    local_os_addr: OsSocketAddr,
+) {
     let cli = unsafe { &mut *(tcp_client_ptr as *mut TcpClient) };
 
-    let id = ResourceId::from(hd.id);
+    let id = match cli.srv_net.conn_resource_id(hd) {
+        Ok(raw_id) => ResourceId::from(raw_id),
+        Err(err) => {
+            log::error!("[on_connected_cb] make new conn failed!!! error: {}", err);
+            return;
+        }
+    };
     let sock_addr = os_addr.into_addr().unwrap();
+    let local_addr = local_os_addr.into_addr().unwrap();
     let endpoint = Endpoint::new(id, sock_addr);
 
     // make new conn
-    cli.make_new_conn(PacketType::Server, hd, endpoint);
+    cli.make_new_conn(PacketType::Server, hd, endpoint, local_addr);
 }
 
 extern "C" fn on_message_cb(
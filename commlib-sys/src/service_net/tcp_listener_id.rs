@@ -7,10 +7,11 @@ use message_io::network::Endpoint;
 use message_io::node::NodeHandler;
 
 use crate::service_net::net_packet::get_packet_leading_field_size;
-use crate::service_net::take_small_packet;
+use crate::service_net::{start_heartbeat, take_small_packet};
 use crate::{ServiceNetRs, ServiceRs};
 
-use super::{ConnId, PacketReceiver, PacketType, ServerStatus, TcpConn, TcpServer};
+use super::{CloseReason, ConnId, PacketReceiver, PacketType, ServerStatus, TcpConn, TcpServer};
+use super::{RateLimitState, SendQueueLimit, SendQueueState, WsFramer};
 
 /// Tcp server id
 #[derive(Copy, Clone, PartialEq, Eq, std::hash::Hash)]
@@ -24,7 +25,6 @@ impl TcpListenerId {
     /// Make new tcp conn with callbacks from tcp server
     pub fn make_new_conn(
         &self,
-        packet_type: PacketType,
         hd: ConnId,
         endpoint: Endpoint,
         netctrl: &NodeHandler<()>,
@@ -49,53 +49,129 @@ impl TcpListenerId {
                     }
                 }
 
-                // 根据 tcp server 创建 tcp conn
-                if let Some(tcp_server) = tcp_server_opt {
-                    //
-                    let srv = tcp_server.srv.clone();
-
-                    //
-                    let conn_fn = tcp_server.conn_fn.clone();
-                    let pkt_fn = tcp_server.pkt_fn.clone();
-                    let close_fn = tcp_server.close_fn.clone();
-
-                    // 设置初始 packet
-                    let mut pkt = take_small_packet();
-                    pkt.set_type(packet_type);
-
-                    let conn = Arc::new(TcpConn {
-                        //
-                        packet_type: Atomic::new(PacketType::Server),
-                        hd,
-
-                        //
-                        endpoint,
-                        netctrl: netctrl2.clone(),
-
-                        //
-                        closed: Atomic::new(false),
+                match tcp_server_opt {
+                    // 连接数达到上限：触发 reject_fn 后立即关闭
+                    Some(tcp_server) if !tcp_server.try_acquire_conn() => {
+                        let remote_addr = endpoint.addr();
+                        log::warn!(
+                            "[hd={}] listener_id={} reach max conns({}), reject conn from {}!!!",
+                            hd,
+                            listener_id,
+                            tcp_server.connection_limit(),
+                            remote_addr
+                        );
+                        netctrl2.network().remove(endpoint.resource_id());
+                        srv_net2.remove_conn(hd);
+
+                        let reject_fn = tcp_server.reject_fn.clone();
+                        tcp_server.srv.run_in_service(Box::new(move || {
+                            reject_fn(remote_addr);
+                        }));
+                    }
 
+                    // 根据 tcp server 创建 tcp conn
+                    Some(tcp_server) => {
                         //
-                        srv: srv.clone(),
-                        srv_net: srv_net2.clone(),
+                        let srv = tcp_server.srv.clone();
 
                         //
-                        conn_fn,
-                        pkt_fn,
-                        close_fn: RwLock::new(close_fn),
+                        let conn_fn = tcp_server.conn_fn.clone();
+                        let pkt_fn = tcp_server.pkt_fn.clone();
+                        let close_fn = tcp_server.close_fn.clone();
+                        let send_limit = tcp_server.send_limit.clone();
+                        let max_packet_size = tcp_server.max_packet_size();
+                        let heartbeat = tcp_server.heartbeat();
+                        let rate_limit = tcp_server.rate_limit();
+                        let framing = tcp_server.framing;
+                        let listener = tcp_server.id;
+                        let local_addr = tcp_server
+                            .local_addr
+                            .unwrap_or_else(|| SocketAddr::from(([0, 0, 0, 0], 0)));
+
+                        // 设置初始 packet
+                        let mut pkt = take_small_packet();
+                        pkt.set_type(framing);
+
+                        // websocket 连接先握手，之后按帧收发
+                        let ws_framer = if framing == PacketType::Websocket {
+                            Some(WsFramer::new(max_packet_size))
+                        } else {
+                            None
+                        };
+
+                        let conn = Arc::new(TcpConn {
+                            //
+                            packet_type: Atomic::new(framing),
+                            hd,
+                            listener: Some(listener),
+
+                            //
+                            endpoint,
+                            netctrl: netctrl2.clone(),
+
+                            //
+                            remote_addr: endpoint.addr(),
+                            local_addr,
+                            connected_at: std::time::Instant::now(),
+
+                            //
+                            closed: Atomic::new(false),
+                            close_reason: Atomic::new(CloseReason::None),
+
+                            //
+                            srv: srv.clone(),
+                            srv_net: srv_net2.clone(),
+
+                            //
+                            conn_fn,
+                            pkt_fn,
+                            close_fn: RwLock::new(close_fn),
+
+                            //
+                            pkt_receiver: PacketReceiver::with_max_packet_size(
+                                pkt,
+                                max_packet_size,
+                            ),
+
+                            //
+                            send_limit,
+                            send_queue: SendQueueState::new(),
+
+                            //
+                            heartbeat,
+                            idle: heartbeat.idle_tracker(),
+
+                            //
+                            rate_limit,
+                            rate_state: RateLimitState::new(),
+
+                            //
+                            ws_framer,
+
+                            //
+                            user_data: RwLock::new(None),
+                        });
 
                         //
-                        pkt_receiver: PacketReceiver::new(pkt),
-                    });
+                        conn_opt = Some(conn);
+                    }
 
-                    //
-                    conn_opt = Some(conn);
+                    None => {
+                        // 已停止 accept 的 listen socket 上尚未处理的连接
+                        log::error!(
+                            "[hd={}] listener_id={} not found, close conn!!!",
+                            hd,
+                            listener_id
+                        );
+                        netctrl2.network().remove(endpoint.resource_id());
+                    }
                 }
             };
 
             if let Some(conn) = conn_opt {
                 // add conn to service net
                 srv_net2.insert_conn(conn.hd, &conn);
+                start_heartbeat(&srv_net2, &conn);
 
                 // trigger conn_fn
                 conn.run_conn_fn();
@@ -111,6 +187,7 @@ impl TcpListenerId {
 
         // update listener id
         tcp_server.listener_id = listener_id;
+        tcp_server.local_addr = Some(sock_addr);
 
         // 状态：Running
         tcp_server.set_status(ServerStatus::Running);
@@ -36,11 +36,16 @@
 //!
 
 use atomic::{Atomic, Ordering};
+use parking_lot::RwLock;
 use std::net::SocketAddr;
 use std::sync::Arc;
 
+use message_io::network::ResourceId;
+
 use super::MessageIoNetwork;
-use super::{ConnId, NetPacketGuard, ServerStatus, TcpListenerId};
+use super::MAX_PACKET_SIZE;
+use super::{ConnId, HeartbeatConfig, NetPacketGuard, RateLimit, SendQueueLimit, ServerStatus};
+use super::{PacketType, TcpConn, TcpListenerId};
 
 use crate::{ServiceNetRs, ServiceRs};
 
@@ -55,7 +60,9 @@ pub struct TcpServer {
 
     //
     pub addr: String,
-    pub listener_id: TcpListenerId,
+    pub id: TcpListenerId, // listen 返回的 id，暂停/恢复 accept 之后不变
+    pub listener_id: TcpListenerId, // 当前 listen socket
+    pub local_addr: Option<SocketAddr>,
     pub listen_fn: Arc<dyn Fn(SocketAddr, ServerStatus) + Send + Sync>,
 
     //
@@ -64,9 +71,17 @@ pub struct TcpServer {
     pub srv_net: Arc<ServiceNetRs>,
 
     //
-    pub conn_fn: Arc<dyn Fn(ConnId) + Send + Sync>,
+    pub conn_fn: Arc<dyn Fn(Arc<TcpConn>) + Send + Sync>,
     pub pkt_fn: Arc<dyn Fn(ConnId, NetPacketGuard) + Send + Sync>,
-    pub close_fn: Arc<dyn Fn(ConnId) + Send + Sync>,
+    pub close_fn: Arc<dyn Fn(Arc<TcpConn>) + Send + Sync>,
+    pub reject_fn: Arc<dyn Fn(SocketAddr) + Send + Sync>,
+
+    //
+    pub send_limit: SendQueueLimit,
+    pub max_packet_size: Atomic<usize>,
+    pub heartbeat: RwLock<HeartbeatConfig>,
+    pub rate_limit: RwLock<RateLimit>,
+    pub framing: PacketType, // accept 的 conn 的分帧方式，PacketType::Websocket 先握手再按帧收发
 }
 
 impl TcpServer {
@@ -88,16 +103,25 @@ impl TcpServer {
             connection_num: Atomic::new(0_usize),
 
             addr: addr.to_owned(),
+            id: TcpListenerId::from(0),
             listener_id: TcpListenerId::from(0),
+            local_addr: None,
             listen_fn: Arc::new(|_sock_addr, _status| {}),
 
             srv: srv.clone(),
             mi_network: mi_network.clone(),
             srv_net: srv_net.clone(),
 
-            conn_fn: Arc::new(|_hd| {}),
+            conn_fn: Arc::new(|_conn| {}),
             pkt_fn: Arc::new(|_hd, _pkt| {}),
-            close_fn: Arc::new(|_hd| {}),
+            close_fn: Arc::new(|_conn| {}),
+            reject_fn: Arc::new(|_addr| {}),
+
+            send_limit: SendQueueLimit::default(),
+            max_packet_size: Atomic::new(MAX_PACKET_SIZE),
+            heartbeat: RwLock::new(HeartbeatConfig::default()),
+            rate_limit: RwLock::new(RateLimit::default()),
+            framing: PacketType::Server,
         }
     }
 
@@ -138,10 +162,49 @@ impl TcpServer {
         // TODO:
     }
 
+    /// 停止 accept：关闭 listen socket，新的连接被拒绝，已建立的连接不受影响
+    pub fn stop_accept(&mut self) -> bool {
+        if !self.status().is_running() {
+            log::error!(
+                "[listener_id={}] stop accept failed!!! status: {}",
+                self.id,
+                self.status().to_string()
+            );
+            return false;
+        }
+
+        log::info!("[listener_id={}] stop accept at {}", self.id, self.addr);
+        self.mi_network
+            .node_handler
+            .network()
+            .remove(ResourceId::from(self.listener_id.id));
+        self.set_status(ServerStatus::Stopped);
+        true
+    }
+
+    /// 恢复 accept：在之前实际绑定的地址上重新 listen
+    pub fn resume_accept(&mut self) -> bool {
+        if !self.status().is_stopped() {
+            log::error!(
+                "[listener_id={}] resume accept failed!!! status: {}",
+                self.id,
+                self.status().to_string()
+            );
+            return false;
+        }
+
+        // 绑定的是端口 0 时使用实际分配的端口
+        if let Some(local_addr) = self.local_addr {
+            self.addr = local_addr.to_string();
+        }
+        self.listen();
+        self.status().is_running()
+    }
+
     ///
     pub fn set_connection_callback<F>(&mut self, cb: F)
     where
-        F: Fn(ConnId) + Send + Sync + 'static,
+        F: Fn(Arc<TcpConn>) + Send + Sync + 'static,
     {
         self.conn_fn = Arc::new(cb);
     }
@@ -158,10 +221,110 @@ impl TcpServer {
     pub fn set_close_callback<F>(&mut self, cb: F)
     where
         F: Fn(ConnId) + Send + Sync + 'static,
+    {
+        self.close_fn = Arc::new(move |conn: Arc<TcpConn>| cb(conn.hd));
+    }
+
+    /// close 回调参数为已关闭的 conn，仍可读取连接信息
+    pub fn set_close_callback_ex<F>(&mut self, cb: F)
+    where
+        F: Fn(Arc<TcpConn>) + Send + Sync + 'static,
     {
         self.close_fn = Arc::new(cb);
     }
 
+    /// 连接数达到上限时，新连接 accept 后立即关闭，关闭前以对端地址触发 cb（在 srv 中运行）
+    pub fn set_reject_callback<F>(&mut self, cb: F)
+    where
+        F: Fn(SocketAddr) + Send + Sync + 'static,
+    {
+        self.reject_fn = Arc::new(cb);
+    }
+
+    /// 同时存在的 accept 连接数上限，0 表示不限制
+    pub fn set_connection_limit(&self, max_conns: usize) {
+        self.connection_limit.store(max_conns, Ordering::Relaxed);
+    }
+
+    ///
+    #[inline(always)]
+    pub fn connection_limit(&self) -> usize {
+        self.connection_limit.load(Ordering::Relaxed)
+    }
+
+    /// 当前 accept 的连接数（包括 conn_fn 尚未执行的连接）
+    #[inline(always)]
+    pub fn connection_num(&self) -> usize {
+        self.connection_num.load(Ordering::Relaxed)
+    }
+
+    /// 新连接占用一个名额，达到上限时返回 false（在 srv_net 中运行）
+    pub fn try_acquire_conn(&self) -> bool {
+        let limit = self.connection_limit();
+        self.connection_num
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |num| {
+                if limit > 0 && num >= limit {
+                    None
+                } else {
+                    Some(num + 1)
+                }
+            })
+            .is_ok()
+    }
+
+    /// 连接关闭，释放名额
+    pub fn release_conn(&self) {
+        let _ = self
+            .connection_num
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |num| {
+                num.checked_sub(1)
+            });
+    }
+
+    /// accept 的 conn 使用的发送队列限制
+    pub fn set_send_queue_limit(&mut self, limit: SendQueueLimit) {
+        self.send_limit = limit;
+    }
+
+    /// accept 的 conn 接收包长度上限（含包头），超过时以 CloseReason::PacketTooLarge 关闭连接
+    pub fn set_max_packet_size(&self, max_packet_size: usize) {
+        self.max_packet_size
+            .store(max_packet_size, Ordering::Relaxed);
+    }
+
+    ///
+    #[inline(always)]
+    pub fn max_packet_size(&self) -> usize {
+        self.max_packet_size.load(Ordering::Relaxed)
+    }
+
+    /// accept 的 conn 使用的心跳配置，只影响之后 accept 的 conn
+    pub fn set_heartbeat(&self, heartbeat: HeartbeatConfig) {
+        *self.heartbeat.write() = heartbeat;
+    }
+
+    ///
+    #[inline(always)]
+    pub fn heartbeat(&self) -> HeartbeatConfig {
+        *self.heartbeat.read()
+    }
+
+    /// accept 的 conn 使用的收包限流配置，只影响之后 accept 的 conn
+    pub fn set_rate_limit(&self, rate_limit: RateLimit) {
+        *self.rate_limit.write() = rate_limit;
+    }
+
+    ///
+    #[inline(always)]
+    pub fn rate_limit(&self) -> RateLimit {
+        *self.rate_limit.read()
+    }
+
+    /// accept 的 conn 使用的分帧方式，需要在 listen 之前设置
+    pub fn set_framing(&mut self, framing: PacketType) {
+        self.framing = framing;
+    }
+
     ///
     #[inline(always)]
     pub fn status(&self) -> ServerStatus {
@@ -0,0 +1,734 @@
+//! Commlib: UdpConn
+//! UDP 没有连接概念，按远端地址划分 session，每个 session 从 ServiceNetRs 的 ConnId 分配表分配 ConnId
+//! （与 tcp 连接共用，关闭后 generation 递增），超过 idle timeout 没有收到数据则视为断开，触发 close_fn.
+//!
+//! 原始 UDP（listen_udp_addr / connect_to_udp_server）每个数据报携带完整的包，没有确认、重传和排序，
+//! 包可能丢失、重复或乱序.
+//! KCP 模式（listen_kcp_addr / connect_to_kcp_server）在 session 上叠加一个 Kcp 控制块：
+//! 收到的数据报先经 Kcp::input，按序重组出的消息再交给 PacketReceiver 切包；发送时进入 Kcp 发送队列，
+//! 由 srv_net 的 service 定时器按 interval 驱动 Kcp::update 发出数据报和重传.
+//! server 端以第一个数据报中的 conv 建立 session，重传次数达到上限时以 CloseReason::DeadLink 关闭
+
+use atomic::{Atomic, Ordering};
+use parking_lot::{Mutex, RwLock};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use message_io::network::{Endpoint, ResourceId};
+use message_io::node::NodeHandler;
+
+use crate::{Clock, ServiceRs, ServiceTimerId};
+
+use super::kcp::{kcp_clock_ms, kcp_peek_conv, Kcp, KcpConfig};
+use super::packet_receiver::PacketResult;
+use super::{take_packet, take_small_packet};
+use super::{CloseReason, ConnId, NetPacketGuard, PacketReceiver, PacketType, ServiceNetRs};
+
+/// server 端 session 共享 listener 的 resource id，分配 ConnId 时使用最高位置位的序号作为 key，
+/// 避免与 message io 的 resource id 冲突
+const UDP_SESSION_KEY_BASE: usize = 1 << (usize::BITS - 1);
+static NEXT_UDP_SESSION_KEY: AtomicUsize = AtomicUsize::new(1);
+
+#[inline(always)]
+fn next_udp_session_key() -> usize {
+    UDP_SESSION_KEY_BASE | NEXT_UDP_SESSION_KEY.fetch_add(1, Ordering::Relaxed)
+}
+
+/// 空闲计时
+pub struct IdleTracker {
+    start: Instant,
+    last_active_ms: AtomicU64,
+    timeout: Duration,
+}
+
+impl IdleTracker {
+    ///
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            start: Instant::now(),
+            last_active_ms: AtomicU64::new(0),
+            timeout,
+        }
+    }
+
+    ///
+    #[inline(always)]
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// 收到数据时刷新
+    #[inline(always)]
+    pub fn touch(&self, now: Instant) {
+        let ms = now.saturating_duration_since(self.start).as_millis() as u64;
+        self.last_active_ms.fetch_max(ms, Ordering::Relaxed);
+    }
+
+    /// 最后一次收到数据的时间（没有收到过数据时为创建时间）
+    #[inline(always)]
+    pub fn last_active(&self) -> Instant {
+        self.start + Duration::from_millis(self.last_active_ms.load(Ordering::Relaxed))
+    }
+
+    /// 距离超时的剩余时间，已超时返回 None
+    pub fn remaining(&self, now: Instant) -> Option<Duration> {
+        let last_active = Duration::from_millis(self.last_active_ms.load(Ordering::Relaxed));
+        let idle = now.saturating_duration_since(self.start + last_active);
+        if idle >= self.timeout {
+            None
+        } else {
+            Some(self.timeout - idle)
+        }
+    }
+}
+
+/// 远端地址 -> session
+#[derive(Default)]
+pub struct UdpSessions {
+    by_addr: hashbrown::HashMap<SocketAddr, ConnId>,
+}
+
+impl UdpSessions {
+    ///
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 查找远端地址对应的 session, 不存在则从 srv_net 分配新的 ConnId, bool 表示是否新建
+    pub fn get_or_insert(&mut self, srv_net: &ServiceNetRs, addr: SocketAddr) -> (ConnId, bool) {
+        if let Some(hd) = self.by_addr.get(&addr) {
+            (*hd, false)
+        } else {
+            let hd = srv_net.alloc_conn_id(next_udp_session_key());
+            self.by_addr.insert(addr, hd);
+            (hd, true)
+        }
+    }
+
+    ///
+    pub fn remove(&mut self, addr: &SocketAddr) -> Option<ConnId> {
+        self.by_addr.remove(addr)
+    }
+
+    ///
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.by_addr.len()
+    }
+
+    ///
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.by_addr.is_empty()
+    }
+}
+
+/// Udp server: 一个 udp socket 上的所有 session
+pub struct UdpServer {
+    pub addr: SocketAddr,
+    pub listener: ResourceId,
+    pub idle_timeout: Duration,
+    pub netctrl: NodeHandler<()>,
+    pub sessions: RwLock<UdpSessions>,
+    pub kcp: Option<KcpConfig>, // None 表示原始 UDP
+
+    //
+    pub srv: Arc<dyn ServiceRs>,
+    pub conn_fn: Arc<dyn Fn(ConnId) + Send + Sync>,
+    pub pkt_fn: Arc<dyn Fn(ConnId, NetPacketGuard) + Send + Sync>,
+    pub close_fn: Arc<dyn Fn(ConnId) + Send + Sync>,
+}
+
+impl UdpServer {
+    /// 取出远端地址对应的 session，新 session 触发 conn_fn （在 srv_net 中运行）.
+    /// KCP 模式下新 session 使用数据报中的 conv，conv 为 None（不足一个 kcp 报文头）时丢弃
+    pub fn session_conn(
+        &self,
+        srv_net: &Arc<ServiceNetRs>,
+        raddr: SocketAddr,
+        conv: Option<u32>,
+    ) -> Option<Arc<UdpConn>> {
+        assert!(srv_net.is_in_service_thread());
+
+        let kcp = match (self.kcp, conv) {
+            (Some(config), Some(conv)) => Some((conv, config)),
+            (Some(_), None) => {
+                log::error!("[{}] kcp datagram too short, drop it!!!", raddr);
+                return None;
+            }
+            (None, _) => None,
+        };
+
+        let (hd, is_new) = self.sessions.write().get_or_insert(srv_net, raddr);
+        if !is_new {
+            if let Some(conn) = srv_net.get_udp_conn(hd) {
+                return Some(conn);
+            }
+        }
+
+        //
+        let conn = Arc::new(UdpConn::new(
+            hd,
+            Endpoint::new(self.listener, raddr),
+            Some(self.listener),
+            self.netctrl.clone(),
+            self.idle_timeout,
+            &self.srv,
+            srv_net,
+            self.conn_fn.clone(),
+            self.pkt_fn.clone(),
+            self.close_fn.clone(),
+            kcp,
+        ));
+        log::info!(
+            "[hd={}] udp session {} accepted at {}",
+            hd,
+            raddr,
+            self.addr
+        );
+        srv_net.insert_udp_conn(hd, &conn);
+        schedule_udp_idle_check(srv_net, hd, self.idle_timeout);
+        schedule_udp_kcp_update(srv_net, &conn);
+
+        // trigger conn_fn
+        conn.run_conn_fn();
+        Some(conn)
+    }
+}
+
+/// Udp connection (session)
+pub struct UdpConn {
+    pub hd: ConnId,
+    pub endpoint: Endpoint,
+    pub listener: Option<ResourceId>, // server 端 session 共享 listener socket
+    pub netctrl: NodeHandler<()>,
+
+    //
+    pub closed: Atomic<bool>,
+    pub close_reason: Atomic<CloseReason>,
+    pub idle: IdleTracker,
+
+    //
+    pub srv: Arc<dyn ServiceRs>,
+    pub srv_net: Arc<ServiceNetRs>,
+
+    //
+    pub conn_fn: Arc<dyn Fn(ConnId) + Send + Sync>,
+    pub pkt_fn: Arc<dyn Fn(ConnId, NetPacketGuard) + Send + Sync>,
+    pub close_fn: Arc<dyn Fn(ConnId) + Send + Sync>,
+
+    //
+    pub pkt_receiver: PacketReceiver,
+
+    //
+    pub kcp: Option<Mutex<Kcp>>, // None 表示原始 UDP
+    pub kcp_timer: Mutex<Option<ServiceTimerId>>,
+}
+
+impl UdpConn {
+    ///
+    pub fn new(
+        hd: ConnId,
+        endpoint: Endpoint,
+        listener: Option<ResourceId>,
+        netctrl: NodeHandler<()>,
+        idle_timeout: Duration,
+        srv: &Arc<dyn ServiceRs>,
+        srv_net: &Arc<ServiceNetRs>,
+        conn_fn: Arc<dyn Fn(ConnId) + Send + Sync>,
+        pkt_fn: Arc<dyn Fn(ConnId, NetPacketGuard) + Send + Sync>,
+        close_fn: Arc<dyn Fn(ConnId) + Send + Sync>,
+        kcp: Option<(u32, KcpConfig)>,
+    ) -> Self {
+        // 设置初始 packet
+        let mut pkt = take_small_packet();
+        pkt.set_type(PacketType::Server);
+
+        // kcp 输出的数据报直接写 socket
+        let kcp = kcp.map(|(conv, config)| {
+            let netctrl = netctrl.clone();
+            let output = move |data: &[u8]| {
+                netctrl.network().send(endpoint, data);
+            };
+            Mutex::new(Kcp::new(conv, &config, Box::new(output)))
+        });
+
+        Self {
+            hd,
+            endpoint,
+            listener,
+            netctrl,
+
+            closed: Atomic::new(false),
+            close_reason: Atomic::new(CloseReason::None),
+            idle: IdleTracker::new(idle_timeout),
+
+            srv: srv.clone(),
+            srv_net: srv_net.clone(),
+
+            conn_fn,
+            pkt_fn,
+            close_fn,
+
+            pkt_receiver: PacketReceiver::new(pkt),
+
+            kcp,
+            kcp_timer: Mutex::new(None),
+        }
+    }
+
+    /// KCP 模式下进入 kcp 发送队列，由下一次 update 发出
+    #[inline(always)]
+    pub fn send(&self, data: &[u8]) {
+        if self.closed.load(Ordering::Relaxed) {
+            log::error!("[hd={}] udp send data failed!!! conn is closed!!!", self.hd);
+            return;
+        }
+        match &self.kcp {
+            Some(kcp) => {
+                if let Err(err) = kcp.lock().send(data) {
+                    log::error!("[hd={}] kcp send failed!!! error: {}", self.hd, err);
+                }
+            }
+            None => {
+                self.netctrl.network().send(self.endpoint, data);
+            }
+        }
+    }
+
+    ///
+    #[inline(always)]
+    pub fn close_reason(&self) -> CloseReason {
+        self.close_reason.load(Ordering::Relaxed)
+    }
+
+    /// call conn_fn
+    pub fn run_conn_fn(&self) {
+        let hd = self.hd;
+        let f = self.conn_fn.clone();
+
+        //
+        self.srv.run_in_service(Box::new(move || {
+            (f)(hd);
+        }));
+    }
+
+    /// call pkt_fn
+    pub fn run_pkt_fn(&self, pkt: NetPacketGuard) {
+        let hd = self.hd;
+        let f = self.pkt_fn.clone();
+
+        //
+        self.srv.run_in_service(Box::new(move || {
+            (f)(hd, pkt);
+        }));
+    }
+
+    /// call close_fn, at most once
+    pub fn run_close_fn(&self) {
+        if self.closed.swap(true, Ordering::Relaxed) {
+            log::info!("[hd={}] udp close_fn already triggered", self.hd);
+            return;
+        }
+
+        let hd = self.hd;
+        let f = self.close_fn.clone();
+
+        //
+        self.srv.run_in_service(Box::new(move || {
+            (f)(hd);
+        }));
+    }
+}
+
+/// 收到 udp 数据报（在 message io 线程中运行）
+pub fn on_udp_message(srv_net: &Arc<ServiceNetRs>, endpoint: Endpoint, input: &[u8]) {
+    // 利用 buffer pkt 作为跨线程传递的数据缓存
+    let mut buffer_pkt = take_packet(input.len());
+    buffer_pkt.append_slice(input);
+    let conv = kcp_peek_conv(input);
+
+    // 在 srv_net 中运行
+    let srv_net2 = srv_net.clone();
+    let cb = move || {
+        let raw_id = endpoint.resource_id().raw();
+        let conn_opt = if let Some(udp_server) = srv_net2.get_udp_server(raw_id) {
+            udp_server.session_conn(&srv_net2, endpoint.addr(), conv)
+        } else {
+            srv_net2
+                .resolve_conn_id(raw_id)
+                .and_then(|hd| srv_net2.get_udp_conn(hd))
+        };
+
+        if let Some(conn) = conn_opt {
+            conn.idle.touch(Instant::now());
+            handle_udp_message_event(&srv_net2, &conn, buffer_pkt);
+        } else {
+            log::error!("[on_udp_message][{}] udp conn not found!!!", endpoint);
+        }
+    };
+    srv_net.run_in_service(Box::new(cb));
+}
+
+/// 处理 udp message 事件 （在 srv_net 中运行）
+pub fn handle_udp_message_event(
+    srv_net: &ServiceNetRs,
+    conn: &Arc<UdpConn>,
+    mut buffer_pkt: NetPacketGuard,
+) {
+    assert!(srv_net.is_in_service_thread());
+
+    let input = buffer_pkt.consume();
+    match &conn.kcp {
+        Some(kcp) => {
+            // 先交给 kcp，按序重组出的消息再切包
+            let msgs = {
+                let mut kcp = kcp.lock();
+                if let Err(err) = kcp.input(input) {
+                    log::error!("[hd={}] kcp input failed!!! error: {}", conn.hd, err);
+                    return;
+                }
+                std::iter::from_fn(|| kcp.recv()).collect::<Vec<_>>()
+            };
+            for msg in msgs {
+                if !read_udp_packets(srv_net, conn, &msg) {
+                    break;
+                }
+            }
+        }
+        None => {
+            read_udp_packets(srv_net, conn, input);
+        }
+    }
+}
+
+// PacketReceiver 循环切包并派发，出错时关闭 conn 并返回 false
+fn read_udp_packets(srv_net: &ServiceNetRs, conn: &Arc<UdpConn>, input: &[u8]) -> bool {
+    let input_data = input.as_ptr();
+    let input_len: usize = input.len();
+
+    // conn 循环处理 input
+    let mut pos = 0_usize;
+    while pos < input_len {
+        let ptr = unsafe { input_data.add(pos) };
+        match conn.pkt_receiver.read(ptr, input_len - pos) {
+            PacketResult::Ready((pkt, consumed)) => {
+                conn.run_pkt_fn(pkt);
+                pos += consumed;
+            }
+            PacketResult::Suspend(consumed) => {
+                pos += consumed;
+            }
+            PacketResult::Overflow(pkt_full_len) => {
+                log::error!(
+                    "[hd={}] udp packet too large!!! pkt_full_len={}",
+                    conn.hd,
+                    pkt_full_len
+                );
+                close_udp_conn(srv_net, conn, CloseReason::PacketTooLarge);
+                return false;
+            }
+            PacketResult::Abort(err) => {
+                log::error!("[hd={}] udp handle_read failed!!! error: {}", conn.hd, err);
+                close_udp_conn(srv_net, conn, CloseReason::Normal);
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// 关闭 udp conn: 移除 session 并触发 close_fn （在 srv_net 中运行）
+pub fn close_udp_conn(srv_net: &ServiceNetRs, conn: &Arc<UdpConn>, reason: CloseReason) {
+    assert!(srv_net.is_in_service_thread());
+
+    log::info!("[hd={}] udp close with reason: {:?}", conn.hd, reason);
+    conn.close_reason.store(reason, Ordering::Relaxed);
+    srv_net.remove_udp_conn(conn.hd);
+    if let Some(timer_id) = conn.kcp_timer.lock().take() {
+        srv_net.get_handle().cancel_timer(timer_id);
+    }
+
+    match conn.listener {
+        Some(listener) => {
+            // server 端 session 只移除地址映射，socket 继续监听
+            if let Some(udp_server) = srv_net.get_udp_server(listener.raw()) {
+                udp_server.sessions.write().remove(&conn.endpoint.addr());
+            }
+        }
+        None => {
+            conn.netctrl.network().remove(conn.endpoint.resource_id());
+        }
+    }
+
+    // trigger close_fn
+    conn.run_close_fn();
+}
+
+/// 空闲检查：到期时若期间没有收到数据则关闭，否则按剩余时间重新计时
+pub fn schedule_udp_idle_check(srv_net: &Arc<ServiceNetRs>, hd: ConnId, delay: Duration) {
+    let srv_net2 = srv_net.clone();
+    let delay_ms = std::cmp::max(1, delay.as_millis() as u64);
+    Clock::set_timeout(srv_net.as_ref(), delay_ms, move || {
+        if let Some(conn) = srv_net2.get_udp_conn(hd) {
+            match conn.idle.remaining(Instant::now()) {
+                Some(remaining) => {
+                    schedule_udp_idle_check(&srv_net2, hd, remaining);
+                }
+                None => {
+                    log::info!(
+                        "[hd={}] udp conn idle for {:?}, close it",
+                        hd,
+                        conn.idle.timeout()
+                    );
+                    close_udp_conn(srv_net2.as_ref(), &conn, CloseReason::IdleTimeout);
+                }
+            }
+        }
+    });
+}
+
+/// KCP 模式：在 srv_net 的 service 定时器上按 kcp interval 驱动 update，conn 关闭时取消
+pub fn schedule_udp_kcp_update(srv_net: &Arc<ServiceNetRs>, conn: &Arc<UdpConn>) {
+    let interval = match &conn.kcp {
+        Some(kcp) => Duration::from_millis(kcp.lock().interval() as u64),
+        None => return,
+    };
+
+    let hd = conn.hd;
+    let srv_net2 = srv_net.clone();
+    let timer_id = srv_net
+        .get_handle()
+        .schedule_periodic(interval, interval, move || {
+            if let Some(conn) = srv_net2.get_udp_conn(hd) {
+                update_udp_kcp(srv_net2.as_ref(), &conn);
+            }
+        });
+    *conn.kcp_timer.lock() = Some(timer_id);
+}
+
+/// 推进 kcp：发出 ack、新分片和重传，重传次数达到上限时关闭 conn （在 srv_net 中运行）
+pub fn update_udp_kcp(srv_net: &ServiceNetRs, conn: &Arc<UdpConn>) {
+    assert!(srv_net.is_in_service_thread());
+
+    let dead = match &conn.kcp {
+        Some(kcp) => {
+            let mut kcp = kcp.lock();
+            kcp.update(kcp_clock_ms());
+            kcp.is_dead()
+        }
+        None => false,
+    };
+    if dead {
+        log::error!("[hd={}] kcp dead link, close it", conn.hd);
+        close_udp_conn(srv_net, conn, CloseReason::DeadLink);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idle_tracker_expires() {
+        let idle = IdleTracker::new(Duration::from_millis(100));
+        let now = Instant::now();
+        assert!(idle.remaining(now).is_some());
+
+        idle.touch(now + Duration::from_millis(80));
+        assert!(idle.remaining(now + Duration::from_millis(150)).is_some());
+        assert!(idle.remaining(now + Duration::from_millis(200)).is_none());
+
+        // 乱序的旧时间不会回退
+        idle.touch(now);
+        assert!(idle.remaining(now + Duration::from_millis(150)).is_some());
+    }
+
+    #[test]
+    fn loopback_listen_and_idle_close() {
+        use crate::{connect_to_udp_server, listen_udp_addr};
+        use crate::{proc_service_ready, start_network, start_service};
+
+        let srv_net: &'static Arc<ServiceNetRs> =
+            Box::leak(Box::new(Arc::new(ServiceNetRs::new(4005))));
+        let ready_pair = start_service(srv_net.as_ref(), "test_net", || {});
+        assert!(proc_service_ready(srv_net.as_ref(), ready_pair));
+        start_network(srv_net);
+
+        let sessions = Arc::new(parking_lot::Mutex::new(Vec::<Arc<UdpConn>>::new()));
+        let received = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let closed = Arc::new(parking_lot::Mutex::new(Vec::new()));
+
+        // 回调在 srv_net 线程中执行
+        let sessions2 = sessions.clone();
+        let conn_fn = move |hd: ConnId| {
+            sessions2.lock().push(srv_net.get_udp_conn(hd).unwrap());
+        };
+        let received2 = received.clone();
+        let pkt_fn = move |hd: ConnId, pkt: NetPacketGuard| {
+            let seq = u32::from_le_bytes(pkt.body().try_into().unwrap());
+            received2.lock().push((hd, seq));
+        };
+        let (sessions3, closed2) = (sessions.clone(), closed.clone());
+        let close_fn = move |hd: ConnId| {
+            let sessions = sessions3.lock();
+            let conn = sessions.iter().find(|conn| conn.hd == hd).unwrap();
+            closed2.lock().push((hd, conn.close_reason()));
+        };
+        let addr = listen_udp_addr(
+            srv_net,
+            "127.0.0.1".to_owned(),
+            0,
+            conn_fn,
+            pkt_fn,
+            close_fn,
+            Duration::from_millis(200),
+            srv_net,
+        )
+        .unwrap();
+
+        let client_hd = connect_to_udp_server(
+            srv_net,
+            addr.to_string().as_str(),
+            |_| {},
+            |_, _| {},
+            |_| {},
+            Duration::from_secs(60),
+            srv_net,
+        )
+        .unwrap();
+
+        let wait_until = |f: &dyn Fn() -> bool| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while !f() {
+                assert!(Instant::now() < deadline, "wait timeout");
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        };
+        let wire = |seq: u32| {
+            let mut pkt = take_packet(4);
+            pkt.set_type(PacketType::Server);
+            pkt.set_cmd(1);
+            pkt.set_body(&seq.to_le_bytes());
+            assert!(pkt.encode_packet(client_hd, &hashbrown::HashMap::new()));
+            pkt.consume().to_vec()
+        };
+
+        for seq in 0..100_u32 {
+            srv_net.send_udp(client_hd, &wire(seq));
+        }
+        wait_until(&|| received.lock().len() == 100);
+
+        // 同一个远端地址只有一个 session，ConnId 来自分配表
+        let server_hd = sessions.lock()[0].hd;
+        assert_eq!(sessions.lock().len(), 1);
+        assert_ne!(server_hd, client_hd);
+        assert!(server_hd.generation() >= 1);
+        let mut seqs: Vec<u32> = received.lock().iter().map(|(_, seq)| *seq).collect();
+        seqs.sort();
+        assert_eq!(seqs, (0..100).collect::<Vec<_>>());
+        assert!(received.lock().iter().all(|(hd, _)| *hd == server_hd));
+
+        // 对端不再发送，超时后 close_fn 触发
+        wait_until(&|| !closed.lock().is_empty());
+        assert_eq!(*closed.lock(), vec![(server_hd, CloseReason::IdleTimeout)]);
+        assert!(srv_net.get_udp_conn(server_hd).is_none());
+
+        // 再次发送得到新的 session，旧 hd 失效
+        srv_net.send_udp(client_hd, &wire(100));
+        wait_until(&|| sessions.lock().len() == 2);
+        let new_hd = sessions.lock()[1].hd;
+        assert_ne!(new_hd, server_hd);
+        assert!(srv_net.conn_resource_id(server_hd).is_err());
+    }
+
+    #[test]
+    fn loopback_kcp_in_order_both_ways() {
+        use crate::{connect_to_kcp_server, listen_kcp_addr};
+        use crate::{proc_service_ready, start_network, start_service};
+
+        let srv_net: &'static Arc<ServiceNetRs> =
+            Box::leak(Box::new(Arc::new(ServiceNetRs::new(4006))));
+        let ready_pair = start_service(srv_net.as_ref(), "test_net_kcp", || {});
+        assert!(proc_service_ready(srv_net.as_ref(), ready_pair));
+        start_network(srv_net);
+
+        let wire = |hd: ConnId, seq: u32| {
+            let mut pkt = take_packet(4);
+            pkt.set_type(PacketType::Server);
+            pkt.set_cmd(1);
+            pkt.set_body(&seq.to_le_bytes());
+            assert!(pkt.encode_packet(hd, &hashbrown::HashMap::new()));
+            pkt.consume().to_vec()
+        };
+
+        // server 收到的包原样回发
+        let server_received = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let server_received2 = server_received.clone();
+        let server_pkt_fn = move |hd: ConnId, pkt: NetPacketGuard| {
+            let seq = u32::from_le_bytes(pkt.body().try_into().unwrap());
+            server_received2.lock().push(seq);
+            srv_net.send_udp(hd, &wire(hd, seq));
+        };
+        let addr = listen_kcp_addr(
+            srv_net,
+            "127.0.0.1".to_owned(),
+            0,
+            |_| {},
+            server_pkt_fn,
+            |_| {},
+            Duration::from_secs(60),
+            KcpConfig::fast(),
+            srv_net,
+        )
+        .unwrap();
+
+        let client_received = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let client_received2 = client_received.clone();
+        let client_pkt_fn = move |_hd: ConnId, pkt: NetPacketGuard| {
+            let seq = u32::from_le_bytes(pkt.body().try_into().unwrap());
+            client_received2.lock().push(seq);
+        };
+        let client_hd = connect_to_kcp_server(
+            srv_net,
+            addr.to_string().as_str(),
+            |_| {},
+            client_pkt_fn,
+            |_| {},
+            Duration::from_secs(60),
+            KcpConfig::fast(),
+            srv_net,
+        )
+        .unwrap();
+        assert!(srv_net.get_udp_conn(client_hd).unwrap().kcp.is_some());
+
+        let wait_until = |f: &dyn Fn() -> bool| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while !f() {
+                assert!(Instant::now() < deadline, "wait timeout");
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        };
+
+        for seq in 0..500_u32 {
+            srv_net.send_udp(client_hd, &wire(client_hd, seq));
+        }
+        wait_until(&|| client_received.lock().len() == 500);
+
+        // kcp 保证两个方向都按发送顺序到达，不需要排序
+        assert_eq!(*server_received.lock(), (0..500).collect::<Vec<_>>());
+        assert_eq!(*client_received.lock(), (0..500).collect::<Vec<_>>());
+
+        // 关闭后 kcp 定时器取消
+        let conn = srv_net.get_udp_conn(client_hd).unwrap();
+        assert!(conn.kcp_timer.lock().is_some());
+        let conn2 = conn.clone();
+        srv_net.run_in_service(Box::new(move || {
+            close_udp_conn(srv_net.as_ref(), &conn2, CloseReason::Normal);
+        }));
+        wait_until(&|| srv_net.get_udp_conn(client_hd).is_none());
+        assert!(conn.kcp_timer.lock().is_none());
+    }
+}
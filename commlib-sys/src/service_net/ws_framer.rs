@@ -0,0 +1,682 @@
+//! Commlib: WsFramer
+//! tcp 上的 websocket（RFC 6455）分帧：连接建立后先解析 http upgrade 握手，回复 101 之后切换到二进制分帧.
+//! 每个 websocket 消息对应一个 PacketType::Websocket 包：cmd(2) + 包体.
+//! 只接受 masked 的客户端帧，发送的帧不加 mask（服务端）
+
+use base64::{engine::general_purpose, Engine as _};
+use parking_lot::Mutex;
+use sha1::{Digest, Sha1};
+use std::sync::Arc;
+
+use crate::ServiceNetRs;
+
+use super::{dispatch_rate_limited, handle_close_conn_event, take_packet};
+use super::{CloseReason, NetPacketGuard, PacketType, TcpConn};
+
+/// RFC 6455 握手使用的 GUID
+const WS_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// 握手请求长度上限
+const MAX_HANDSHAKE_SIZE: usize = 8192;
+
+/// 控制帧 payload 长度上限
+const MAX_CONTROL_PAYLOAD: usize = 125;
+
+/// websocket 帧类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum WsOpcode {
+    Continuation = 0x0,
+    Text = 0x1,
+    Binary = 0x2,
+    Close = 0x8,
+    Ping = 0x9,
+    Pong = 0xA,
+}
+
+impl WsOpcode {
+    ///
+    pub fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0x0 => Some(WsOpcode::Continuation),
+            0x1 => Some(WsOpcode::Text),
+            0x2 => Some(WsOpcode::Binary),
+            0x8 => Some(WsOpcode::Close),
+            0x9 => Some(WsOpcode::Ping),
+            0xA => Some(WsOpcode::Pong),
+            _ => None,
+        }
+    }
+
+    /// 控制帧：close/ping/pong
+    #[inline(always)]
+    pub fn is_control(&self) -> bool {
+        (*self as u8) & 0x8 != 0
+    }
+}
+
+/// deframe 的结果，按收到的顺序处理
+pub enum WsDeframe {
+    Handshake(Vec<u8>), // 握手完成，需要原样发送的 101 回复（含握手前 frame 的数据）
+    Packet(NetPacketGuard), // 一个完整的消息
+    Reply(Vec<u8>),     // 需要原样发送的控制帧（回复 ping 的 pong）
+    Close(Vec<u8>),     // 对端关闭，原样发送回复的 close 帧后关闭连接
+    Overflow(usize),    // 消息长度超过 max_packet_size
+    Abort(String),      // 握手或帧格式错误
+}
+
+// 解出的一个帧，payload 已去掉 mask
+struct WsFrame {
+    fin: bool,
+    opcode: WsOpcode,
+    payload: Vec<u8>,
+}
+
+struct WsFramerInner {
+    open: bool,       // 握手是否完成
+    buf: Vec<u8>,     // 尚未处理的输入
+    message: Vec<u8>, // 分片消息已收到的部分
+    fragmented: bool, // 是否正在接收分片消息
+    pending: Vec<u8>, // 握手完成前 frame 的数据
+}
+
+/// websocket 分帧：deframe 处理收到的数据，frame 生成发送的帧
+pub struct WsFramer {
+    max_packet_size: usize,
+    inner: Mutex<WsFramerInner>,
+}
+
+impl WsFramer {
+    /// max_packet_size: 消息长度上限（cmd + 包体）
+    pub fn new(max_packet_size: usize) -> Self {
+        Self {
+            max_packet_size,
+            inner: Mutex::new(WsFramerInner {
+                open: false,
+                buf: Vec::new(),
+                message: Vec::new(),
+                fragmented: false,
+                pending: Vec::new(),
+            }),
+        }
+    }
+
+    /// 握手是否完成
+    pub fn is_open(&self) -> bool {
+        self.inner.lock().open
+    }
+
+    /// 处理收到的数据，数据不完整时缓存到下一次. 返回 Close/Overflow/Abort 之后不再处理
+    pub fn deframe(&self, data: &[u8]) -> Vec<WsDeframe> {
+        let mut inner = self.inner.lock();
+        inner.buf.extend_from_slice(data);
+
+        let mut out = Vec::new();
+
+        // 握手
+        if !inner.open {
+            match parse_upgrade_request(&inner.buf) {
+                Ok(Some((consumed, key))) => {
+                    inner.buf.drain(..consumed);
+                    inner.open = true;
+
+                    let mut response = make_upgrade_response(&key);
+                    response.append(&mut inner.pending);
+                    out.push(WsDeframe::Handshake(response));
+                }
+                Ok(None) => {
+                    return out;
+                }
+                Err(err) => {
+                    out.push(WsDeframe::Abort(err));
+                    return out;
+                }
+            }
+        }
+
+        // 二进制分帧
+        loop {
+            let frame = match decode_frame(&inner.buf, self.max_packet_size) {
+                Ok(Some((frame, consumed))) => {
+                    inner.buf.drain(..consumed);
+                    frame
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    out.push(err);
+                    break;
+                }
+            };
+
+            match frame.opcode {
+                WsOpcode::Continuation => {
+                    if !inner.fragmented {
+                        out.push(WsDeframe::Abort("unexpected continuation frame".to_owned()));
+                        break;
+                    }
+
+                    let len = inner.message.len() + frame.payload.len();
+                    if len > self.max_packet_size {
+                        out.push(WsDeframe::Overflow(len));
+                        break;
+                    }
+                    inner.message.extend_from_slice(&frame.payload);
+
+                    if frame.fin {
+                        inner.fragmented = false;
+                        let message = std::mem::take(&mut inner.message);
+                        match make_message_packet(&message) {
+                            Ok(pkt) => out.push(WsDeframe::Packet(pkt)),
+                            Err(err) => {
+                                out.push(err);
+                                break;
+                            }
+                        }
+                    }
+                }
+                WsOpcode::Text | WsOpcode::Binary => {
+                    if inner.fragmented {
+                        out.push(WsDeframe::Abort(
+                            "new message before fragmented message finished".to_owned(),
+                        ));
+                        break;
+                    }
+
+                    if frame.fin {
+                        match make_message_packet(&frame.payload) {
+                            Ok(pkt) => out.push(WsDeframe::Packet(pkt)),
+                            Err(err) => {
+                                out.push(err);
+                                break;
+                            }
+                        }
+                    } else {
+                        inner.fragmented = true;
+                        inner.message = frame.payload;
+                    }
+                }
+                WsOpcode::Ping => {
+                    out.push(WsDeframe::Reply(encode_frame(
+                        WsOpcode::Pong,
+                        &frame.payload,
+                        None,
+                    )));
+                }
+                WsOpcode::Pong => {
+                    // 只用于刷新活跃时间
+                }
+                WsOpcode::Close => {
+                    // 回复 close 帧：带上对端的状态码
+                    let code_len = std::cmp::min(2, frame.payload.len());
+                    out.push(WsDeframe::Close(encode_frame(
+                        WsOpcode::Close,
+                        &frame.payload[..code_len],
+                        None,
+                    )));
+                    break;
+                }
+            }
+        }
+        out
+    }
+
+    /// 生成一个 binary 帧. 握手完成前返回 None，数据缓存到握手回复之后发送
+    pub fn frame(&self, payload: &[u8]) -> Option<Vec<u8>> {
+        let bytes = encode_frame(WsOpcode::Binary, payload, None);
+
+        let mut inner = self.inner.lock();
+        if inner.open {
+            Some(bytes)
+        } else {
+            inner.pending.extend_from_slice(&bytes);
+            None
+        }
+    }
+}
+
+/// 编码一个 fin 帧，mask 为 None 时不加 mask（服务端发送的帧）
+pub fn encode_frame(opcode: WsOpcode, payload: &[u8], mask: Option<[u8; 4]>) -> Vec<u8> {
+    let len = payload.len();
+    let mut bytes = Vec::with_capacity(len + 14);
+    bytes.push(0x80 | opcode as u8);
+
+    let mask_bit = if mask.is_some() { 0x80_u8 } else { 0 };
+    if len <= MAX_CONTROL_PAYLOAD {
+        bytes.push(mask_bit | len as u8);
+    } else if len <= u16::MAX as usize {
+        bytes.push(mask_bit | 126);
+        bytes.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        bytes.push(mask_bit | 127);
+        bytes.extend_from_slice(&(len as u64).to_be_bytes());
+    }
+
+    match mask {
+        Some(mask) => {
+            bytes.extend_from_slice(&mask);
+            bytes.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        }
+        None => {
+            bytes.extend_from_slice(payload);
+        }
+    }
+    bytes
+}
+
+/// 解析 http upgrade 请求：Ok(None) 表示请求尚不完整，Ok(Some) 返回 (请求长度, Sec-WebSocket-Key)
+pub fn parse_upgrade_request(buf: &[u8]) -> Result<Option<(usize, String)>, String> {
+    let end = match buf.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(end) => end,
+        None => {
+            if buf.len() > MAX_HANDSHAKE_SIZE {
+                return Err(format!("handshake too large: {} bytes", buf.len()));
+            }
+            return Ok(None);
+        }
+    };
+    if end > MAX_HANDSHAKE_SIZE {
+        return Err(format!("handshake too large: {} bytes", end));
+    }
+
+    let request = std::str::from_utf8(&buf[..end]).map_err(|_| "handshake not utf8".to_owned())?;
+    let mut lines = request.split("\r\n");
+
+    // 请求行: GET /path HTTP/1.1
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+    if parts.next() != Some("GET") || parts.next().is_none() || parts.next() != Some("HTTP/1.1") {
+        return Err(format!("invalid request line: {}", request_line));
+    }
+
+    let mut upgrade = false;
+    let mut connection = false;
+    let mut version = false;
+    let mut key = None;
+    for line in lines {
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => return Err(format!("invalid header: {}", line)),
+        };
+
+        if name.eq_ignore_ascii_case("Upgrade") {
+            upgrade = value.eq_ignore_ascii_case("websocket");
+        } else if name.eq_ignore_ascii_case("Connection") {
+            connection = value
+                .split(',')
+                .any(|token| token.trim().eq_ignore_ascii_case("upgrade"));
+        } else if name.eq_ignore_ascii_case("Sec-WebSocket-Version") {
+            version = value == "13";
+        } else if name.eq_ignore_ascii_case("Sec-WebSocket-Key") && !value.is_empty() {
+            key = Some(value.to_owned());
+        }
+    }
+
+    if !upgrade || !connection {
+        return Err("not a websocket upgrade request".to_owned());
+    }
+    if !version {
+        return Err("unsupported websocket version".to_owned());
+    }
+    match key {
+        Some(key) => Ok(Some((end + 4, key))),
+        None => Err("missing Sec-WebSocket-Key".to_owned()),
+    }
+}
+
+/// Sec-WebSocket-Accept = base64(sha1(key + GUID))
+pub fn ws_accept_key(key: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(key.as_bytes());
+    hasher.update(WS_GUID.as_bytes());
+    general_purpose::STANDARD.encode(hasher.finalize())
+}
+
+fn make_upgrade_response(key: &str) -> Vec<u8> {
+    std::format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        ws_accept_key(key)
+    )
+    .into_bytes()
+}
+
+// 解出一个帧：Ok(None) 表示数据不完整
+fn decode_frame(buf: &[u8], max_payload: usize) -> Result<Option<(WsFrame, usize)>, WsDeframe> {
+    if buf.len() < 2 {
+        return Ok(None);
+    }
+
+    let fin = buf[0] & 0x80 != 0;
+    if buf[0] & 0x70 != 0 {
+        return Err(WsDeframe::Abort("reserved bits set".to_owned()));
+    }
+    let opcode = match WsOpcode::from_u8(buf[0] & 0x0F) {
+        Some(opcode) => opcode,
+        None => {
+            return Err(WsDeframe::Abort(format!(
+                "unknown opcode: {:#x}",
+                buf[0] & 0x0F
+            )))
+        }
+    };
+    if buf[1] & 0x80 == 0 {
+        return Err(WsDeframe::Abort("client frame not masked".to_owned()));
+    }
+
+    // payload 长度: 7 bit / 16 bit / 64 bit
+    let (len, mut pos) = match buf[1] & 0x7F {
+        126 => {
+            if buf.len() < 4 {
+                return Ok(None);
+            }
+            (u16::from_be_bytes([buf[2], buf[3]]) as u64, 4)
+        }
+        127 => {
+            if buf.len() < 10 {
+                return Ok(None);
+            }
+            let mut len_bytes = [0_u8; 8];
+            len_bytes.copy_from_slice(&buf[2..10]);
+            (u64::from_be_bytes(len_bytes), 10)
+        }
+        len => (len as u64, 2),
+    };
+
+    if opcode.is_control() && (!fin || len > MAX_CONTROL_PAYLOAD as u64) {
+        return Err(WsDeframe::Abort("invalid control frame".to_owned()));
+    }
+    if len > max_payload as u64 {
+        return Err(WsDeframe::Overflow(len as usize));
+    }
+    let len = len as usize;
+
+    if buf.len() < pos + 4 + len {
+        return Ok(None);
+    }
+    let mask = [buf[pos], buf[pos + 1], buf[pos + 2], buf[pos + 3]];
+    pos += 4;
+
+    let payload = buf[pos..pos + len]
+        .iter()
+        .enumerate()
+        .map(|(i, b)| b ^ mask[i % 4])
+        .collect();
+    Ok(Some((
+        WsFrame {
+            fin,
+            opcode,
+            payload,
+        },
+        pos + len,
+    )))
+}
+
+// 完整的消息作为一个 PacketType::Websocket 包，decode 时读取 cmd
+fn make_message_packet(message: &[u8]) -> Result<NetPacketGuard, WsDeframe> {
+    if message.len() < 2 {
+        return Err(WsDeframe::Abort(format!(
+            "message too short: {} bytes",
+            message.len()
+        )));
+    }
+
+    let mut pkt = take_packet(message.len());
+    pkt.set_type(PacketType::Websocket);
+    pkt.append_slice(message);
+    Ok(pkt)
+}
+
+/// 处理 websocket conn 收到的数据：握手回复和控制帧直接发送，消息交给 pkt_fn（在 srv_net 中运行）
+pub fn handle_ws_message(
+    srv_net: &ServiceNetRs,
+    conn: &Arc<TcpConn>,
+    framer: &WsFramer,
+    input: &[u8],
+) {
+    for event in framer.deframe(input) {
+        match event {
+            WsDeframe::Handshake(response) => {
+                log::info!("[hd={}] websocket handshake ok", conn.hd);
+                conn.send_unframed(&response);
+            }
+            WsDeframe::Packet(pkt) => {
+                if !dispatch_rate_limited(srv_net, conn, pkt) {
+                    // 超过限流已关闭
+                    break;
+                }
+            }
+            WsDeframe::Reply(bytes) => {
+                conn.send_unframed(&bytes);
+            }
+            WsDeframe::Close(bytes) => {
+                log::info!("[hd={}] websocket closed by peer", conn.hd);
+                conn.send_unframed(&bytes);
+                conn.close_with_reason(CloseReason::Normal);
+                handle_close_conn_event(srv_net, conn);
+                break;
+            }
+            WsDeframe::Overflow(len) => {
+                log::error!(
+                    "[hd={}][handle_ws_message] message too large!!! len={}",
+                    conn.hd,
+                    len
+                );
+                conn.close_with_reason(CloseReason::PacketTooLarge);
+                handle_close_conn_event(srv_net, conn);
+                break;
+            }
+            WsDeframe::Abort(err) => {
+                log::error!(
+                    "[hd={}][handle_ws_message] websocket error!!! error: {}",
+                    conn.hd,
+                    err
+                );
+                conn.close();
+                handle_close_conn_event(srv_net, conn);
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service_net::ConnId;
+
+    const REQUEST: &[u8] = b"GET /chat HTTP/1.1\r\nHost: server.example.com\r\nUpgrade: websocket\r\nConnection: keep-alive, Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n";
+
+    const MASK: [u8; 4] = [0x37, 0xfa, 0x21, 0x3d];
+
+    fn open_framer() -> WsFramer {
+        let framer = WsFramer::new(1024 * 1024);
+        let events = framer.deframe(REQUEST);
+        assert!(matches!(events.as_slice(), [WsDeframe::Handshake(_)]));
+        assert!(framer.is_open());
+        framer
+    }
+
+    fn message(cmd: u16, body: &[u8]) -> Vec<u8> {
+        let mut message = cmd.to_be_bytes().to_vec();
+        message.extend_from_slice(body);
+        message
+    }
+
+    #[test]
+    fn handshake() {
+        // RFC 6455 1.3 的示例
+        assert_eq!(
+            ws_accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+
+        // 请求分两次到达
+        let framer = WsFramer::new(1024);
+        assert!(framer.deframe(&REQUEST[..20]).is_empty());
+        assert!(!framer.is_open());
+        match framer.deframe(&REQUEST[20..]).as_slice() {
+            [WsDeframe::Handshake(response)] => {
+                let response = std::str::from_utf8(response).unwrap();
+                assert!(response.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+                assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+                assert!(response.ends_with("\r\n\r\n"));
+            }
+            _ => panic!("handshake expected"),
+        }
+
+        // 非 websocket 请求
+        let request = b"GET / HTTP/1.1\r\nHost: a\r\n\r\n";
+        assert!(parse_upgrade_request(request).is_err());
+        let request = b"POST / HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: a\r\nSec-WebSocket-Version: 13\r\n\r\n";
+        assert!(parse_upgrade_request(request).is_err());
+        let request = b"GET / HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: a\r\nSec-WebSocket-Version: 8\r\n\r\n";
+        assert!(parse_upgrade_request(request).is_err());
+        assert!(parse_upgrade_request(&vec![b'a'; MAX_HANDSHAKE_SIZE + 1]).is_err());
+
+        let framer = WsFramer::new(1024);
+        assert!(matches!(
+            framer.deframe(b"GET / HTTP/1.1\r\n\r\n").as_slice(),
+            [WsDeframe::Abort(_)]
+        ));
+    }
+
+    #[test]
+    fn binary_message() {
+        let framer = open_framer();
+
+        // 一次收到两个帧，第二个帧不完整
+        let first = encode_frame(WsOpcode::Binary, &message(1001, b"hello"), Some(MASK));
+        let second = encode_frame(WsOpcode::Binary, &message(1002, b"world"), Some(MASK));
+        let mut data = first.clone();
+        data.extend_from_slice(&second[..3]);
+
+        let mut events = framer.deframe(&data);
+        assert_eq!(events.len(), 1);
+        match events.pop().unwrap() {
+            WsDeframe::Packet(mut pkt) => {
+                assert_eq!(pkt.packet_type(), PacketType::Websocket);
+                assert!(pkt.decode_packet(ConnId::from_raw(0), &hashbrown::HashMap::new()));
+                assert_eq!(pkt.cmd(), 1001);
+                assert_eq!(pkt.body(), b"hello");
+            }
+            _ => panic!("packet expected"),
+        }
+
+        let mut events = framer.deframe(&second[3..]);
+        match events.pop().unwrap() {
+            WsDeframe::Packet(mut pkt) => {
+                assert!(pkt.decode_packet(ConnId::from_raw(0), &hashbrown::HashMap::new()));
+                assert_eq!(pkt.cmd(), 1002);
+                assert_eq!(pkt.body(), b"world");
+            }
+            _ => panic!("packet expected"),
+        }
+
+        // 没有 mask 的客户端帧
+        let unmasked = encode_frame(WsOpcode::Binary, &message(1001, b""), None);
+        assert!(matches!(
+            framer.deframe(&unmasked).as_slice(),
+            [WsDeframe::Abort(_)]
+        ));
+    }
+
+    #[test]
+    fn fragmented_and_control() {
+        let framer = open_framer();
+        let payload = message(2001, b"fragmented message");
+
+        // 分片之间插入 ping
+        let mut first = encode_frame(WsOpcode::Binary, &payload[..5], Some(MASK));
+        first[0] &= 0x7F;
+        let mut data = first;
+        data.extend(encode_frame(WsOpcode::Ping, b"ping", Some(MASK)));
+        data.extend(encode_frame(
+            WsOpcode::Continuation,
+            &payload[5..],
+            Some(MASK),
+        ));
+
+        let mut events = framer.deframe(&data).into_iter();
+        match events.next().unwrap() {
+            WsDeframe::Reply(bytes) => {
+                assert_eq!(bytes, encode_frame(WsOpcode::Pong, b"ping", None));
+            }
+            _ => panic!("pong expected"),
+        }
+        match events.next().unwrap() {
+            WsDeframe::Packet(mut pkt) => {
+                assert!(pkt.decode_packet(ConnId::from_raw(0), &hashbrown::HashMap::new()));
+                assert_eq!(pkt.cmd(), 2001);
+                assert_eq!(pkt.body(), b"fragmented message");
+            }
+            _ => panic!("packet expected"),
+        }
+        assert!(events.next().is_none());
+
+        // 不足 cmd 长度的消息
+        let short = encode_frame(WsOpcode::Binary, b"x", Some(MASK));
+        assert!(matches!(
+            open_framer().deframe(&short).as_slice(),
+            [WsDeframe::Abort(_)]
+        ));
+
+        // close 回复对端的状态码，之后的数据不再处理
+        let mut data = encode_frame(WsOpcode::Close, &[0x03, 0xE8, b'b', b'y', b'e'], Some(MASK));
+        data.extend(encode_frame(WsOpcode::Binary, &message(1, b""), Some(MASK)));
+        match framer.deframe(&data).as_slice() {
+            [WsDeframe::Close(bytes)] => {
+                assert_eq!(*bytes, vec![0x88, 0x02, 0x03, 0xE8]);
+            }
+            _ => panic!("close expected"),
+        }
+    }
+
+    #[test]
+    fn frame_lengths() {
+        for len in [0_usize, 125, 126, 65535, 65536] {
+            let payload = vec![0x5A_u8; len];
+            let bytes = encode_frame(WsOpcode::Binary, &payload, None);
+            let header_len = match len {
+                0..=125 => 2,
+                126..=65535 => 4,
+                _ => 10,
+            };
+            assert_eq!(bytes.len(), header_len + len);
+            assert_eq!(bytes[0], 0x82);
+            assert_eq!(&bytes[header_len..], payload.as_slice());
+
+            // 客户端发送的帧解出相同的 payload
+            let masked = encode_frame(WsOpcode::Binary, &payload, Some(MASK));
+            let (frame, consumed) = decode_frame(&masked, 1024 * 1024).ok().unwrap().unwrap();
+            assert_eq!(consumed, masked.len());
+            assert!(frame.fin);
+            assert_eq!(frame.payload, payload);
+        }
+
+        // 超过 max_packet_size
+        let framer = WsFramer::new(1024);
+        framer.deframe(REQUEST);
+        let masked = encode_frame(WsOpcode::Binary, &vec![0_u8; 1025], Some(MASK));
+        assert!(matches!(
+            framer.deframe(&masked[..4]).as_slice(),
+            [WsDeframe::Overflow(1025)]
+        ));
+    }
+
+    #[test]
+    fn frame_before_handshake() {
+        let framer = WsFramer::new(1024);
+        assert!(framer.frame(b"early").is_none());
+
+        match framer.deframe(REQUEST).as_slice() {
+            [WsDeframe::Handshake(response)] => {
+                let frame = encode_frame(WsOpcode::Binary, b"early", None);
+                assert!(response.ends_with(&frame));
+            }
+            _ => panic!("handshake expected"),
+        }
+        assert_eq!(
+            framer.frame(b"late"),
+            Some(encode_frame(WsOpcode::Binary, b"late", None))
+        );
+    }
+}
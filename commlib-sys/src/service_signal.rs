@@ -1,3 +1,5 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use crate::ffi_sig::init_signal_handlers;
 use crate::impl_event_for;
 use crate::G_SERVICE_SIGNAL;
@@ -9,6 +11,12 @@ use crate::{
 pub struct EventSignalInt();
 impl_event_for!(ServiceSignalRs, EventSignalInt);
 
+pub struct EventSignalTerm();
+impl_event_for!(ServiceSignalRs, EventSignalTerm);
+
+pub struct EventSignalHup();
+impl_event_for!(ServiceSignalRs, EventSignalHup);
+
 pub struct EventSignalUsr1();
 impl_event_for!(ServiceSignalRs, EventSignalUsr1);
 
@@ -18,6 +26,7 @@ impl_event_for!(ServiceSignalRs, EventSignalUsr2);
 /// ServiceSignal
 pub struct ServiceSignalRs {
     pub handle: ServiceHandle,
+    has_term_listener: AtomicBool, // 没有 sig_term 监听时，SIGTERM 按 sig_int 处理
 }
 
 impl ServiceSignalRs {
@@ -25,6 +34,7 @@ impl ServiceSignalRs {
     pub fn new(id: u64) -> ServiceSignalRs {
         Self {
             handle: ServiceHandle::new(id, NodeState::Idle),
+            has_term_listener: AtomicBool::new(false),
         }
     }
 
@@ -35,6 +45,26 @@ impl ServiceSignalRs {
         e.trigger();
     }
 
+    /// Event: sig_term, 没有 sig_term 监听时触发 sig_int（只监听 sig_int 的程序收到 SIGTERM 也能关闭）
+    pub fn on_sig_term(&self) {
+        if !self.has_term_listener.load(Ordering::Relaxed) {
+            log::info!("no sig_term listener, handle as sig_int");
+            self.on_sig_int();
+            return;
+        }
+
+        // Trigger event
+        let mut e = EventSignalTerm {};
+        e.trigger();
+    }
+
+    /// Event: sig_hup
+    pub fn on_sig_hup(&self) {
+        // Trigger event
+        let mut e = EventSignalHup {};
+        e.trigger();
+    }
+
     /// Event: sig_usr1
     pub fn on_sig_usr1(&self) {
         // Trigger event
@@ -72,6 +102,46 @@ impl ServiceSignalRs {
             });
         }));
     }
+
+    /// Listen signal: sig_term, 每次收到信号都会执行 f
+    pub fn listen_sig_term<F>(&self, srv: &'static dyn ServiceRs, f: F)
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.has_term_listener.store(true, Ordering::Relaxed);
+        self.listen_repeatable::<EventSignalTerm, F>(srv, f);
+    }
+
+    /// Listen signal: sig_hup, 每次收到信号都会执行 f (windows 下没有 SIGHUP, 只记录警告)
+    pub fn listen_sig_hup<F>(&self, srv: &'static dyn ServiceRs, f: F)
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        #[cfg(windows)]
+        {
+            let _ = (srv, f);
+            log::warn!("listen_sig_hup: SIGHUP is not supported on windows, ignored.");
+        }
+
+        #[cfg(not(windows))]
+        self.listen_repeatable::<EventSignalHup, F>(srv, f);
+    }
+
+    fn listen_repeatable<E, F>(&self, srv: &'static dyn ServiceRs, f: F)
+    where
+        E: Event + 'static,
+        F: Fn() + Send + Sync + 'static,
+    {
+        let f = std::sync::Arc::new(f);
+        self.run_in_service(Box::new(move || {
+            // 在 Service thread 中注册事件回调，多个回调按注册顺序执行
+            E::add_callback(move |_e| {
+                // 事件触发时，将 f post 到工作线程执行
+                let f = f.clone();
+                srv.run_in_service(Box::new(move || f()));
+            });
+        }));
+    }
 }
 
 impl ServiceRs for ServiceSignalRs {
@@ -97,6 +167,22 @@ impl ServiceRs for ServiceSignalRs {
             G_SERVICE_SIGNAL.run_in_service(cb);
         }
 
+        extern "C" fn on_signal_term(sig: i32) {
+            log::info!("Recive term signal in Rust! Value={}", sig);
+
+            // Post event callback to service thread: sig_term
+            let cb = Box::new(|| G_SERVICE_SIGNAL.on_sig_term());
+            G_SERVICE_SIGNAL.run_in_service(cb);
+        }
+
+        extern "C" fn on_signal_hup(sig: i32) {
+            log::info!("Recive hup signal in Rust! Value={}", sig);
+
+            // Post event callback to service thread: sig_hup
+            let cb = Box::new(|| G_SERVICE_SIGNAL.on_sig_hup());
+            G_SERVICE_SIGNAL.run_in_service(cb);
+        }
+
         extern "C" fn on_signal_usr1(sig: i32) {
             log::info!("Recive usr1 signal in Rust! Value={}", sig);
 
@@ -113,11 +199,13 @@ impl ServiceRs for ServiceSignalRs {
             G_SERVICE_SIGNAL.run_in_service(cb);
         }
 
-        let cb1 = SignalCallback(on_signal_int);
-        let cb2 = SignalCallback(on_signal_usr1);
-        let cb3 = SignalCallback(on_signal_usr2);
+        let cb_int = SignalCallback(on_signal_int);
+        let cb_term = SignalCallback(on_signal_term);
+        let cb_hup = SignalCallback(on_signal_hup);
+        let cb_usr1 = SignalCallback(on_signal_usr1);
+        let cb_usr2 = SignalCallback(on_signal_usr2);
 
-        init_signal_handlers(cb1, cb2, cb3);
+        init_signal_handlers(cb_int, cb_term, cb_hup, cb_usr1, cb_usr2);
     }
 
     /// 在 service 线程中执行回调任务
@@ -136,3 +224,74 @@ impl ServiceRs for ServiceSignalRs {
         self.get_handle().join_service();
     }
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::{proc_service_ready, start_service};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    fn start(srv: &'static dyn ServiceRs, name: &str) {
+        let ready_pair = start_service(srv, name, || {});
+        assert!(proc_service_ready(srv, ready_pair));
+    }
+
+    #[test]
+    fn sig_term_and_hup_fire_on_service_thread() {
+        let sig_srv: &'static dyn ServiceRs = G_SERVICE_SIGNAL.as_ref();
+        start(sig_srv, "service_signal");
+        sig_srv.conf();
+
+        let target: &'static ServiceSignalRs = Box::leak(Box::new(ServiceSignalRs::new(9999)));
+        start(target, "signal_target");
+
+        let (tx, rx) = mpsc::channel::<(&'static str, u64)>();
+
+        // 只监听 sig_int：SIGTERM 同样触发
+        {
+            let tx = std::sync::Mutex::new(tx.clone());
+            G_SERVICE_SIGNAL.listen_sig_int(target, move || {
+                tx.lock()
+                    .unwrap()
+                    .send(("int", spdlog::get_current_tid()))
+                    .unwrap();
+            });
+            std::thread::sleep(Duration::from_millis(50));
+            assert_eq!(0, unsafe { libc::raise(libc::SIGTERM) });
+
+            let (tag, tid) = rx.recv_timeout(Duration::from_secs(2)).unwrap();
+            assert_eq!(tag, "int");
+            assert_eq!(tid, target.get_handle().tid());
+        }
+
+        for (sig, name) in [(libc::SIGTERM, "term"), (libc::SIGHUP, "hup")] {
+            for tag in ["first", "second"] {
+                let tx = std::sync::Mutex::new(tx.clone());
+                let f = move || {
+                    tx.lock()
+                        .unwrap()
+                        .send((tag, spdlog::get_current_tid()))
+                        .unwrap();
+                };
+                if libc::SIGTERM == sig {
+                    G_SERVICE_SIGNAL.listen_sig_term(target, f);
+                } else {
+                    G_SERVICE_SIGNAL.listen_sig_hup(target, f);
+                }
+            }
+
+            // 不是一次性的：重复触发
+            for _ in 0..2 {
+                std::thread::sleep(Duration::from_millis(50));
+                assert_eq!(0, unsafe { libc::raise(sig) }, "raise {}", name);
+
+                for expected in ["first", "second"] {
+                    let (tag, tid) = rx.recv_timeout(Duration::from_secs(2)).unwrap();
+                    assert_eq!(tag, expected);
+                    assert_eq!(tid, target.get_handle().tid());
+                }
+            }
+        }
+    }
+}
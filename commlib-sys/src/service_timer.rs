@@ -0,0 +1,323 @@
+//! Commlib: ServiceTimers
+//! service 内置定时器：内嵌一个可取消的 QuadWheelWithOverflow，由 service 主循环推进，
+//! 回调总是在 service 线程中执行. 空闲时用 can_skip 计算等待时间，不会空转.
+//! 定时器 id 为 service 内唯一的 u64
+
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use super::hash_wheel_timer::wheels::cancellable::{CancellableTimerEntry, QuadWheelWithOverflow};
+use super::hash_wheel_timer::wheels::{Skip, TimerEntryWithDelay};
+
+/// service 内的定时器 id
+pub type ServiceTimerId = u64;
+
+/// 定时器回调
+pub type ServiceTimerFn = Box<dyn FnMut() + Send + Sync>;
+
+#[derive(Debug)]
+struct ServiceTimerEntry {
+    id: ServiceTimerId,
+    delay: Duration,
+    period: Option<Duration>,
+}
+
+impl CancellableTimerEntry for ServiceTimerEntry {
+    type Id = ServiceTimerId;
+
+    fn id(&self) -> &Self::Id {
+        &self.id
+    }
+
+    fn period(&self) -> Option<Duration> {
+        self.period
+    }
+}
+
+impl TimerEntryWithDelay for ServiceTimerEntry {
+    fn delay(&self) -> Duration {
+        self.delay
+    }
+}
+
+struct TimerSlot {
+    cb: Option<ServiceTimerFn>, // None 表示回调正在执行
+    periodic: bool,
+}
+
+struct TimerWheel {
+    wheel: QuadWheelWithOverflow<ServiceTimerEntry>,
+    slots: hashbrown::HashMap<ServiceTimerId, TimerSlot>,
+    last_update: Instant, // wheel 当前位置对应的时间
+}
+
+/// service 内置定时器
+pub struct ServiceTimers {
+    inner: Mutex<TimerWheel>,
+    next_id: AtomicU64,
+}
+
+impl ServiceTimers {
+    ///
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(TimerWheel {
+                wheel: QuadWheelWithOverflow::new(),
+                slots: hashbrown::HashMap::new(),
+                last_update: Instant::now(),
+            }),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// delay 之后执行一次 cb
+    pub fn schedule<F>(&self, delay: Duration, cb: F) -> ServiceTimerId
+    where
+        F: FnMut() + Send + Sync + 'static,
+    {
+        self.insert(delay, None, Box::new(cb))
+    }
+
+    /// initial 之后第一次执行 cb，之后每隔 interval 执行一次，直到 cancel
+    pub fn schedule_periodic<F>(
+        &self,
+        initial: Duration,
+        interval: Duration,
+        cb: F,
+    ) -> ServiceTimerId
+    where
+        F: FnMut() + Send + Sync + 'static,
+    {
+        self.insert(initial, Some(interval), Box::new(cb))
+    }
+
+    /// 取消定时器，可以在回调（包括自身的回调）中调用，返回定时器是否存在
+    pub fn cancel(&self, id: ServiceTimerId) -> bool {
+        let mut inner = self.inner.lock();
+        let _ = inner.wheel.cancel(&id);
+        inner.slots.remove(&id).is_some()
+    }
+
+    /// 尚未到期（或周期性）的定时器数量
+    pub fn len(&self) -> usize {
+        self.inner.lock().slots.len()
+    }
+
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 距离下一次需要推进的时间，None 表示没有定时器
+    pub fn next_timeout(&self) -> Option<Duration> {
+        let inner = self.inner.lock();
+        match inner.wheel.can_skip() {
+            Skip::Empty => None,
+            Skip::None => Some(Duration::ZERO),
+            Skip::Millis(ms) => {
+                let due = inner.last_update + Duration::from_millis(ms as u64 + 1);
+                Some(due.saturating_duration_since(Instant::now()))
+            }
+        }
+    }
+
+    /// 推进到当前时间并执行到期的回调（在 service 线程中调用），返回执行的回调数
+    pub fn update(&self) -> usize {
+        self.update_at(Instant::now())
+    }
+
+    pub(crate) fn update_at(&self, now: Instant) -> usize {
+        let expired = self.collect_expired(now);
+
+        // 回调执行时不持有锁，回调中可以 schedule/cancel
+        let mut count = 0_usize;
+        for id in expired {
+            let cb_opt = {
+                let mut inner = self.inner.lock();
+                inner.slots.get_mut(&id).and_then(|slot| slot.cb.take())
+            };
+
+            if let Some(mut cb) = cb_opt {
+                cb();
+                count += 1;
+
+                let mut inner = self.inner.lock();
+                let periodic = inner.slots.get(&id).map(|slot| slot.periodic);
+                match periodic {
+                    Some(true) => {
+                        inner.slots.get_mut(&id).unwrap().cb = Some(cb);
+                    }
+                    Some(false) => {
+                        inner.slots.remove(&id);
+                    }
+                    None => {
+                        // 已在回调中取消
+                    }
+                }
+            }
+        }
+        count
+    }
+
+    fn collect_expired(&self, now: Instant) -> Vec<ServiceTimerId> {
+        let mut inner = self.inner.lock();
+        let elapsed_ms = now.saturating_duration_since(inner.last_update).as_millis() as u64;
+        inner.last_update += Duration::from_millis(elapsed_ms);
+
+        let mut expired = Vec::new();
+        let mut delta = std::cmp::min(elapsed_ms, u32::MAX as u64) as u32;
+        while delta > 0 {
+            match inner.wheel.can_skip() {
+                Skip::Empty => break,
+                Skip::None => {
+                    delta -= 1;
+                    for e in inner.wheel.tick() {
+                        expired.push(e.id);
+                    }
+                }
+                Skip::Millis(ms) => {
+                    let n = std::cmp::min(ms, delta);
+                    delta -= n;
+                    inner.wheel.skip(n);
+                }
+            }
+        }
+        expired
+    }
+
+    fn insert(
+        &self,
+        delay: Duration,
+        period: Option<Duration>,
+        cb: ServiceTimerFn,
+    ) -> ServiceTimerId {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        let mut inner = self.inner.lock();
+
+        // wheel 停留在 last_update，补上之后经过的时间；至少 1 tick
+        let behind = Instant::now().saturating_duration_since(inner.last_update);
+        let ms = (delay + behind).as_micros().div_ceil(1000);
+        let wheel_delay = Duration::from_millis(std::cmp::max(1, ms as u64));
+
+        let e = ServiceTimerEntry {
+            id,
+            delay: wheel_delay,
+            period,
+        };
+        if let Err(err) = inner.wheel.insert(e) {
+            log::error!("service timer id={} insert failed: {:?}!!!", id, err);
+        }
+        inner.slots.insert(
+            id,
+            TimerSlot {
+                cb: Some(cb),
+                periodic: period.is_some(),
+            },
+        );
+        id
+    }
+}
+
+impl Default for ServiceTimers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn once_and_periodic() {
+        let timers = ServiceTimers::new();
+        let start = Instant::now();
+        assert_eq!(timers.next_timeout(), None);
+
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let fired2 = fired.clone();
+        let once = timers.schedule(Duration::from_millis(10), move || {
+            fired2.lock().push("once");
+        });
+        let fired2 = fired.clone();
+        let tick = timers.schedule_periodic(
+            Duration::from_millis(5),
+            Duration::from_millis(20),
+            move || {
+                fired2.lock().push("tick");
+            },
+        );
+        assert_ne!(once, tick);
+        assert_eq!(timers.len(), 2);
+        assert!(timers.next_timeout().unwrap() <= Duration::from_millis(10));
+
+        assert_eq!(timers.update_at(start + Duration::from_millis(3)), 0);
+        assert_eq!(timers.update_at(start + Duration::from_millis(15)), 2);
+        assert_eq!(*fired.lock(), vec!["tick", "once"]);
+        assert_eq!(timers.len(), 1);
+
+        // 周期性定时器: 5ms 之后每 20ms 一次
+        assert_eq!(timers.update_at(start + Duration::from_millis(70)), 3);
+        assert!(timers.cancel(tick));
+        assert!(!timers.cancel(tick));
+        assert!(!timers.cancel(once));
+        assert_eq!(timers.update_at(start + Duration::from_millis(200)), 0);
+        assert!(timers.is_empty());
+        assert_eq!(timers.next_timeout(), None);
+    }
+
+    #[test]
+    fn cancel_from_callback() {
+        let timers: &'static ServiceTimers = Box::leak(Box::new(ServiceTimers::new()));
+        let start = Instant::now();
+
+        let fired = Arc::new(Mutex::new(Vec::new()));
+
+        // b 比 a 晚到期，在 a 的回调中取消 b
+        let fired2 = fired.clone();
+        let b = timers.schedule(Duration::from_millis(20), move || {
+            fired2.lock().push("b");
+        });
+        let fired2 = fired.clone();
+        timers.schedule(Duration::from_millis(10), move || {
+            fired2.lock().push("a");
+            assert!(timers.cancel(b));
+        });
+
+        // 周期性定时器在一次性定时器的回调中取消
+        let fired2 = fired.clone();
+        let p = timers.schedule_periodic(
+            Duration::from_millis(30),
+            Duration::from_millis(10),
+            move || {
+                fired2.lock().push("p");
+            },
+        );
+        let fired2 = fired.clone();
+        timers.schedule(Duration::from_millis(45), move || {
+            fired2.lock().push("q");
+            assert!(timers.cancel(p));
+        });
+
+        // 周期性定时器取消自身
+        let fired2 = fired.clone();
+        let e_id = Arc::new(AtomicU64::new(0));
+        let e_id2 = e_id.clone();
+        let e = timers.schedule_periodic(
+            Duration::from_millis(60),
+            Duration::from_millis(5),
+            move || {
+                fired2.lock().push("e");
+                timers.cancel(e_id2.load(Ordering::Relaxed));
+            },
+        );
+        e_id.store(e, Ordering::Relaxed);
+
+        timers.update_at(start + Duration::from_millis(100));
+        assert_eq!(*fired.lock(), vec!["a", "p", "p", "q", "e"]);
+        assert!(timers.is_empty());
+    }
+}
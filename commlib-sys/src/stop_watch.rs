@@ -23,6 +23,11 @@ impl StopWatch {
         self.start.elapsed().as_millis()
     }
 
+    ///
+    pub fn elapsed_us(&self) -> u64 {
+        self.start.elapsed().as_micros() as u64
+    }
+
     ///
     pub fn elapsed_and_reset(&mut self) -> u128 {
         let now = std::time::Instant::now();
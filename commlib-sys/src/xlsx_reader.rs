@@ -0,0 +1,187 @@
+//! Commlib: DataTable xlsx
+//! 第一行为字段名，其余为数据行；跳过全空的行，数值单元格按整数/小数转为字符串
+
+use calamine::{open_workbook, Data, Dimensions, Reader, Xlsx};
+use std::path::Path;
+
+use crate::data_schema::DataTable;
+
+/// 读取 xlsx 文件中的 sheet（None 时取第一个 sheet），表名为不含扩展名的文件名
+pub fn read_data_table(path: &Path, sheet: Option<&str>) -> Result<DataTable, String> {
+    let name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut workbook: Xlsx<_> =
+        open_workbook(path).map_err(|err| format!("open xlsx file({:?}) error: {}.", path, err))?;
+    let sheet_name = match sheet {
+        Some(sheet) => sheet.to_owned(),
+        None => workbook
+            .sheet_names()
+            .first()
+            .cloned()
+            .ok_or_else(|| format!("xlsx file({:?}): no sheet found", path))?,
+    };
+    let range = workbook
+        .worksheet_range(&sheet_name)
+        .map_err(|err| format!("xlsx file({:?}) sheet({}): {}", path, sheet_name, err))?;
+
+    let (header_row, first_col) = match range.start() {
+        Some(start) => start,
+        None => {
+            return Err(format!(
+                "xlsx file({:?}) sheet({}): empty sheet, header not found",
+                path, sheet_name
+            ))
+        }
+    };
+
+    // 字段名不能来自合并单元格
+    workbook
+        .load_merged_regions()
+        .map_err(|err| format!("xlsx file({:?}) sheet({}): {}", path, sheet_name, err))?;
+    if let Some((_, _, region)) = workbook
+        .merged_regions_by_sheet(&sheet_name)
+        .into_iter()
+        .find(|(_, _, region)| region.start.0 <= header_row && header_row <= region.end.0)
+    {
+        return Err(format!(
+            "xlsx file({:?}) sheet({}): merged header cells({}) not supported",
+            path,
+            sheet_name,
+            region_name(region)
+        ));
+    }
+
+    let mut rows = Vec::new();
+    for (i, row) in range.rows().enumerate() {
+        let mut cells = Vec::with_capacity(row.len());
+        for (j, cell) in row.iter().enumerate() {
+            let value = cell_to_string(cell).map_err(|err| {
+                format!(
+                    "xlsx file({:?}) sheet({}) cell({}): {}",
+                    path,
+                    sheet_name,
+                    cell_name(header_row + i as u32, first_col + j as u32),
+                    err
+                )
+            })?;
+            cells.push(value);
+        }
+        rows.push(cells);
+    }
+
+    let mut rows = rows.into_iter();
+    let fields: Vec<String> = match rows.next() {
+        Some(header) => header.into_iter().map(|s| s.trim().to_owned()).collect(),
+        None => return Err(format!("xlsx file({:?}): header not found", path)),
+    };
+    if fields.iter().any(|field| field.is_empty()) {
+        return Err(format!(
+            "xlsx file({:?}) sheet({}): empty field name in header",
+            path, sheet_name
+        ));
+    }
+
+    let mut dt = DataTable::new(name, fields);
+    dt.set_data(
+        rows.filter(|row| row.iter().any(|cell| !cell.trim().is_empty()))
+            .collect(),
+    );
+    Ok(dt)
+}
+
+/// 单元格转为字符串，整数值的浮点数不带 ".0"
+fn cell_to_string(cell: &Data) -> Result<String, String> {
+    match cell {
+        Data::Empty => Ok(String::new()),
+        Data::String(s) | Data::DateTimeIso(s) | Data::DurationIso(s) => Ok(s.clone()),
+        Data::Int(n) => Ok(n.to_string()),
+        Data::Float(f) => Ok(float_to_string(*f)),
+        Data::Bool(b) => Ok(b.to_string()),
+        Data::DateTime(dt) => Ok(float_to_string(dt.as_f64())),
+        Data::Error(err) => Err(format!("error value {}", err)),
+    }
+}
+
+fn float_to_string(f: f64) -> String {
+    // 2^53 以内的整数可以精确表示
+    if f.fract() == 0.0 && f.abs() < 9007199254740992.0 {
+        (f as i64).to_string()
+    } else {
+        f.to_string()
+    }
+}
+
+/// 单元格名称，如 (0, 1) -> "B1"
+fn cell_name(row: u32, col: u32) -> String {
+    let mut letters = Vec::new();
+    let mut n = col + 1;
+    while n > 0 {
+        letters.push((b'A' + ((n - 1) % 26) as u8) as char);
+        n = (n - 1) / 26;
+    }
+    letters.iter().rev().collect::<String>() + &(row + 1).to_string()
+}
+
+fn region_name(region: &Dimensions) -> String {
+    std::format!(
+        "{}:{}",
+        cell_name(region.start.0, region.start.1),
+        cell_name(region.end.0, region.end.1)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn mixed_columns() {
+        let dt = read_data_table(&fixture("role.xlsx"), None).unwrap();
+        assert_eq!(dt.name, "role");
+        assert_eq!(dt.fields, vec!["id", "name", "lv", "rate"]);
+
+        // 空行跳过
+        assert_eq!(dt.rows.len(), 3);
+        assert_eq!(dt.get(0, "id"), "1");
+        assert_eq!(dt.get(0, "lv"), "5");
+        assert_eq!(dt.get(0, "rate"), "0.5");
+        assert_eq!(dt.get(1, "name"), "1001");
+        assert_eq!(dt.get(1, "rate"), "1.25");
+        assert_eq!(dt.get(2, "name"), "mage");
+        assert_eq!(dt.get(2, "lv"), "07");
+        assert_eq!(dt.get(2, "rate"), "2");
+        assert_eq!(dt.rows_by_pk.get("3"), Some(&2));
+
+        let dt = read_data_table(&fixture("role.xlsx"), Some("item")).unwrap();
+        assert_eq!(dt.fields, vec!["id", "count"]);
+        assert_eq!(dt.get(0, "count"), "1000");
+
+        assert!(read_data_table(&fixture("role.xlsx"), Some("missing")).is_err());
+        assert!(read_data_table(&fixture("missing.xlsx"), None).is_err());
+    }
+
+    #[test]
+    fn merged_header_rejected() {
+        let err = read_data_table(&fixture("merged_header.xlsx"), None).unwrap_err();
+        assert!(err.contains("merged header cells(B1:C1)"), "{}", err);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(float_to_string(3.0), "3");
+        assert_eq!(float_to_string(-42.0), "-42");
+        assert_eq!(float_to_string(0.1), "0.1");
+        assert_eq!(float_to_string(1e20), "100000000000000000000");
+        assert_eq!(cell_name(0, 0), "A1");
+        assert_eq!(cell_name(9, 27), "AB10");
+    }
+}
@@ -1,24 +1,38 @@
-use crate::DataTable;
+use crate::data_schema::{ColumnType, DataTable};
 
 #[derive(Default, Debug, Clone)]
 pub struct XmlReader {
     pub key: String,
     pub value: String,
     children: hashbrown::HashMap<String, Vec<XmlReader>>,
+    /// 元素子节点的文档顺序: (key, children[key] 中的下标), 不含属性
+    order: Vec<(String, usize)>,
+    is_attribute: bool, // 属性节点, 序列化时写为属性
+}
+
+/// 流式读取时的根节点信息
+#[derive(Default, Debug, Clone)]
+pub struct XmlStreamContext {
+    pub root_key: String,
+    pub attributes: Vec<(String, String)>,
 }
 
 static XML_READER_EMPTY_LIST: Vec<XmlReader> = Vec::<XmlReader>::new();
 
-/// 如果 Vec 存在则直接插入，如果 Vec 不存在则新建并插入
-fn insert_child_reader(node_reader: &mut XmlReader, child_reader: XmlReader) {
+/// 如果 Vec 存在则直接插入，如果 Vec 不存在则新建并插入, 返回插入位置下标
+fn insert_child_reader(node_reader: &mut XmlReader, child_reader: XmlReader) -> usize {
     let checkopt = node_reader.children.get_mut(&child_reader.key);
     match checkopt {
-        Some(v) => v.push(child_reader),
+        Some(v) => {
+            v.push(child_reader);
+            v.len() - 1
+        }
         None => {
             let key = child_reader.key.clone();
             let mut new_vec = Vec::<XmlReader>::with_capacity(16);
             new_vec.push(child_reader);
             node_reader.children.insert(key, new_vec);
+            0
         }
     }
 }
@@ -39,6 +53,7 @@ impl XmlReader {
 
             attr_reader.key = attr.name().to_string();
             attr_reader.value = attr.value().to_string();
+            attr_reader.is_attribute = true;
 
             insert_child_reader(&mut node_reader, attr_reader);
         }
@@ -47,7 +62,9 @@ impl XmlReader {
         for child_node in node.children() {
             if child_node.is_element() {
                 let child_reader = Self::do_parse(&child_node);
-                insert_child_reader(&mut node_reader, child_reader);
+                let key = child_reader.key.clone();
+                let idx = insert_child_reader(&mut node_reader, child_reader);
+                node_reader.order.push((key, idx));
             }
         }
 
@@ -74,6 +91,53 @@ impl XmlReader {
         }
     }
 
+    /// 逐个读取根节点下的元素子节点, 每次只构造一个子节点的 XmlReader, 不保留整棵树.
+    /// roxmltree 仍需要完整的文件内容, 节省的是 XmlReader 树的内存
+    pub fn read_file_streaming<F>(path: &std::path::Path, mut chunk_fn: F) -> Result<(), String>
+    where
+        F: FnMut(&XmlStreamContext, XmlReader),
+    {
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            let errmsg = format!("parse xml file({:?}) error: {}.", path, e);
+            log::error!("{errmsg}");
+            errmsg
+        })?;
+        Self::read_content_streaming(&content, &mut chunk_fn)
+    }
+
+    /// 同 read_file_streaming, 从字符串读取
+    pub fn read_content_streaming<F>(content: &str, mut chunk_fn: F) -> Result<(), String>
+    where
+        F: FnMut(&XmlStreamContext, XmlReader),
+    {
+        let opt = roxmltree::ParsingOptions {
+            allow_dtd: true,
+            ..roxmltree::ParsingOptions::default()
+        };
+        let doc = roxmltree::Document::parse_with_options(content, opt).map_err(|e| {
+            let errmsg = format!(
+                "parse xml content failed!!! error: {}, len: {}.",
+                e,
+                content.len()
+            );
+            log::error!("{errmsg}");
+            errmsg
+        })?;
+
+        let root = doc.root_element();
+        let ctx = XmlStreamContext {
+            root_key: root.tag_name().name().to_string(),
+            attributes: root
+                .attributes()
+                .map(|attr| (attr.name().to_string(), attr.value().to_string()))
+                .collect(),
+        };
+        for child_node in root.children().filter(|n| n.is_element()) {
+            chunk_fn(&ctx, Self::do_parse(&child_node));
+        }
+        Ok(())
+    }
+
     // 从字符串构造 XmlReader 对象
     pub fn read_content(content: &str) -> Result<Self, String> {
         let opt = roxmltree::ParsingOptions {
@@ -120,13 +184,42 @@ impl XmlReader {
         }
     }
 
+    /// 根据 键值路径(keys) 读取 节点 bool值, 支持 true/false/1/0/yes/no（忽略大小写）
+    pub fn get_bool(&self, keys: Vec<&str>, default_value: bool) -> bool {
+        if let Some(reader) = self.get_child(keys) {
+            match parse_bool(&reader.value) {
+                Some(b) => b,
+                None => {
+                    log::error!(
+                        "xml key({}) bool parse failed: {}",
+                        reader.key,
+                        reader.value
+                    );
+                    default_value
+                }
+            }
+        } else {
+            default_value
+        }
+    }
+
+    /// 根据 键值路径(keys) 读取 节点 i64值
+    pub fn get_i64(&self, keys: Vec<&str>, default_value: i64) -> i64 {
+        self.get(keys, default_value)
+    }
+
+    /// 根据 键值路径(keys) 读取 节点 f64值
+    pub fn get_f64(&self, keys: Vec<&str>, default_value: f64) -> f64 {
+        self.get(keys, default_value)
+    }
+
     /// 根据 键值路径(keys) 读取 节点 字符串值，然后转换成目标类型 T
     pub fn get<T>(&self, keys: Vec<&str>, default_value: T) -> T
     where
         T: std::str::FromStr + ToOwned<Owned = T>,
     {
         if let Some(reader) = self.get_child(keys) {
-            if let Ok(v) = reader.value.parse::<T>() {
+            if let Ok(v) = reader.value.trim().parse::<T>() {
                 v
             } else {
                 default_value
@@ -136,6 +229,57 @@ impl XmlReader {
         }
     }
 
+    /// 根据 键值路径(keys) 读取 节点 列表值, 如 "1,2,3", 解析失败的元素跳过并记录日志
+    pub fn get_list<T>(&self, keys: Vec<&str>, sep: char) -> Vec<T>
+    where
+        T: std::str::FromStr,
+    {
+        let mut list = Vec::new();
+        if let Some(reader) = self.get_child(keys) {
+            for item in reader.value.split(sep).map(str::trim) {
+                if item.is_empty() {
+                    continue;
+                }
+                match item.parse::<T>() {
+                    Ok(v) => list.push(v),
+                    Err(_) => {
+                        log::error!("xml key({}) list item parse failed: {}", reader.key, item);
+                    }
+                }
+            }
+        }
+        list
+    }
+
+    /// 根据 键值路径(keys) 读取 节点 键值对列表, 如 "1:10,2:20", 解析失败的元素跳过并记录日志
+    pub fn get_pairs<K, V>(&self, keys: Vec<&str>, pair_sep: char, kv_sep: char) -> Vec<(K, V)>
+    where
+        K: std::str::FromStr,
+        V: std::str::FromStr,
+    {
+        let mut pairs = Vec::new();
+        if let Some(reader) = self.get_child(keys) {
+            for item in reader.value.split(pair_sep).map(str::trim) {
+                if item.is_empty() {
+                    continue;
+                }
+                let parsed = item.split_once(kv_sep).and_then(|(k, v)| {
+                    match (k.trim().parse::<K>(), v.trim().parse::<V>()) {
+                        (Ok(k), Ok(v)) => Some((k, v)),
+                        _ => None,
+                    }
+                });
+                match parsed {
+                    Some(pair) => pairs.push(pair),
+                    None => {
+                        log::error!("xml key({}) pair item parse failed: {}", reader.key, item);
+                    }
+                }
+            }
+        }
+        pairs
+    }
+
     /// 根据 键值路径(keys) 查找 节点, 遇到多 children 直接选取第一个child
     pub fn get_child(&self, keys: Vec<&str>) -> Option<&Self> {
         if 0 == keys.len() {
@@ -162,6 +306,51 @@ impl XmlReader {
         Some(cur)
     }
 
+    /// 根据 点分路径(如 "server.net.port") 读取 节点 字符串值, "\." 表示键名中的 '.'
+    pub fn get_by_path(&self, path: &str, default_value: &str) -> String {
+        let keys = split_key_path(path);
+        self.get_string(keys.iter().map(String::as_str).collect(), default_value)
+    }
+
+    /// 根据 点分路径 读取 节点 bool值
+    pub fn get_bool_by_path(&self, path: &str, default_value: bool) -> bool {
+        let keys = split_key_path(path);
+        self.get_bool(keys.iter().map(String::as_str).collect(), default_value)
+    }
+
+    /// 根据 点分路径 读取 节点 u64值
+    pub fn get_u64_by_path(&self, path: &str, default_value: u64) -> u64 {
+        let keys = split_key_path(path);
+        self.get_u64(keys.iter().map(String::as_str).collect(), default_value)
+    }
+
+    /// 根据 点分路径 读取 节点 f64值
+    pub fn get_f64_by_path(&self, path: &str, default_value: f64) -> f64 {
+        let keys = split_key_path(path);
+        self.get_f64(keys.iter().map(String::as_str).collect(), default_value)
+    }
+
+    /// 根据 点分路径 读取 节点 字符串值，然后转换成目标类型 T
+    pub fn get_typed_by_path<T>(&self, path: &str, default_value: T) -> T
+    where
+        T: std::str::FromStr + ToOwned<Owned = T>,
+    {
+        let keys = split_key_path(path);
+        self.get(keys.iter().map(String::as_str).collect(), default_value)
+    }
+
+    /// 根据 点分路径 查找 节点
+    pub fn get_child_by_path(&self, path: &str) -> Option<&Self> {
+        let keys = split_key_path(path);
+        self.get_child(keys.iter().map(String::as_str).collect())
+    }
+
+    /// 根据 点分路径 读取 节点 列表
+    pub fn get_children_by_path(&self, path: &str) -> Option<&Vec<Self>> {
+        let keys = split_key_path(path);
+        self.get_children(keys.iter().map(String::as_str).collect())
+    }
+
     /// 根据 键值路径(keys) 读取 节点 列表
     pub fn get_children(&self, keys: Vec<&str>) -> Option<&Vec<Self>> {
         if 0 == keys.len() {
@@ -189,9 +378,188 @@ impl XmlReader {
         }
         Some(list)
     }
+
+    /// 按文档顺序遍历所有元素子节点(不含属性), 不同 tag 交错时仍保持原始顺序
+    pub fn children_ordered(&self) -> impl Iterator<Item = &XmlReader> {
+        self.order
+            .iter()
+            .filter_map(|(key, idx)| self.children.get(key).and_then(|v| v.get(*idx)))
+    }
+
+    /// 根据 键值路径(keys) 查找 节点, 按文档顺序返回其所有元素子节点; keys 为空时取自身
+    pub fn get_children_ordered(&self, keys: Vec<&str>) -> Option<Vec<&Self>> {
+        let node = if keys.is_empty() {
+            self
+        } else {
+            self.get_child(keys)?
+        };
+        Some(node.children_ordered().collect())
+    }
+
+    /// 所有子节点(含属性)的键名
+    pub fn child_keys(&self) -> impl Iterator<Item = &String> {
+        self.children.keys()
+    }
+
+    /// 根据 键值路径(keys) 查找 可修改的节点, 遇到多 children 直接选取第一个child; keys 为空时取自身
+    pub fn get_child_mut(&mut self, keys: Vec<&str>) -> Option<&mut Self> {
+        let mut cur = self;
+        for key in keys {
+            cur = cur.children.get_mut(key)?.first_mut()?;
+        }
+        Some(cur)
+    }
+
+    /// 是否属性节点
+    #[inline(always)]
+    pub fn is_attribute(&self) -> bool {
+        self.is_attribute
+    }
+
+    /// 根据 键值路径(keys) 修改 节点(或属性) 值, keys 为空时修改自身; 节点不存在返回 false
+    pub fn set_value(&mut self, keys: Vec<&str>, value: &str) -> bool {
+        match self.get_child_mut(keys) {
+            Some(node) => {
+                node.value = value.to_owned();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 在 键值路径(keys) 对应的节点下追加元素子节点, 子节点名取 child.key; 节点不存在返回 false
+    pub fn add_child(&mut self, keys: Vec<&str>, mut child: XmlReader) -> bool {
+        let node = match self.get_child_mut(keys) {
+            Some(node) => node,
+            None => return false,
+        };
+        child.is_attribute = false;
+        let key = child.key.clone();
+        let idx = insert_child_reader(node, child);
+        node.order.push((key, idx));
+        true
+    }
+
+    /// 删除 键值路径(keys) 对应的节点列表中第 index 个节点(元素或属性), 返回被删除的节点
+    pub fn remove_child(&mut self, keys: Vec<&str>, index: usize) -> Option<XmlReader> {
+        let (key, parent_keys) = keys.split_last()?;
+        let parent = self.get_child_mut(parent_keys.to_vec())?;
+        let list = parent.children.get_mut(*key)?;
+        if index >= list.len() {
+            return None;
+        }
+        let removed = list.remove(index);
+        if list.is_empty() {
+            parent.children.remove(*key);
+        }
+
+        // 同名子节点的下标前移
+        parent
+            .order
+            .retain(|(k, i)| !(k.as_str() == *key && *i == index));
+        for (k, i) in parent.order.iter_mut() {
+            if k.as_str() == *key && *i > index {
+                *i -= 1;
+            }
+        }
+        Some(removed)
+    }
+
+    /// 设置元素子节点: 替换 key 下已有的所有子节点(含属性), 保持原有文档位置
+    pub fn set_child(&mut self, key: &str, mut value: XmlReader) {
+        value.key = key.to_owned();
+        value.is_attribute = false;
+
+        let pos = self.order.iter().position(|(k, _)| k == key);
+        self.order.retain(|(k, _)| k != key);
+        self.children.insert(key.to_owned(), vec![value]);
+        match pos {
+            Some(pos) => self.order.insert(pos, (key.to_owned(), 0)),
+            None => self.order.push((key.to_owned(), 0)),
+        }
+    }
+
+    /// 删除 key 下所有子节点(含属性), 返回是否存在
+    pub fn remove_children(&mut self, key: &str) -> bool {
+        self.order.retain(|(k, _)| k != key);
+        self.children.remove(key).is_some()
+    }
+
+    /// 序列化为 xml 字符串, pretty 为 true 时换行缩进, 属性按名字排序
+    pub fn to_xml_string(&self, pretty: bool) -> String {
+        let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
+        if pretty {
+            out.push('\n');
+        }
+        self.write_xml(&mut out, pretty, 0);
+        out
+    }
+
+    /// 序列化并写入文件
+    pub fn write_file(&self, path: &std::path::Path) -> Result<(), String> {
+        std::fs::write(path, self.to_xml_string(true)).map_err(|e| {
+            let errmsg = format!("write xml file({:?}) error: {}.", path, e);
+            log::error!("{errmsg}");
+            errmsg
+        })
+    }
+
+    fn write_xml(&self, out: &mut String, pretty: bool, depth: usize) {
+        let indent = if pretty {
+            "  ".repeat(depth)
+        } else {
+            String::new()
+        };
+        let newline = if pretty { "\n" } else { "" };
+        out.push_str(&indent);
+        out.push('<');
+        out.push_str(&self.key);
+
+        let mut attrs: Vec<&XmlReader> = self
+            .children
+            .values()
+            .flat_map(|v| v.iter().filter(|c| c.is_attribute))
+            .collect();
+        attrs.sort_by(|a, b| a.key.cmp(&b.key));
+        for attr in attrs {
+            out.push(' ');
+            out.push_str(&attr.key);
+            out.push_str("=\"");
+            escape_xml(&attr.value, out);
+            out.push('"');
+        }
+
+        if self.order.is_empty() {
+            if self.value.is_empty() {
+                out.push_str("/>");
+            } else {
+                out.push('>');
+                escape_xml(&self.value, out);
+                out.push_str("</");
+                out.push_str(&self.key);
+                out.push('>');
+            }
+            out.push_str(newline);
+        } else {
+            // 有子元素时文本只保留有效内容, 缩进空白不计入
+            out.push('>');
+            escape_xml(self.value.trim(), out);
+            out.push_str(newline);
+            for child in self.children_ordered() {
+                child.write_xml(out, pretty, depth + 1);
+            }
+            out.push_str(&indent);
+            out.push_str("</");
+            out.push_str(&self.key);
+            out.push('>');
+            out.push_str(newline);
+        }
+    }
+
     //读取xml配置表
-    pub fn read_data_table(path: &String) -> Result<DataTable, String> {
+    pub fn read_data_table(path: impl AsRef<std::path::Path>) -> Result<DataTable, String> {
         // 读取文件到内存并解析
+        let path = path.as_ref();
         let content_r = std::fs::read_to_string(path);
         match content_r {
             Ok(content) => Self::read_data_string(&content),
@@ -257,6 +625,27 @@ impl XmlReader {
         if dt.fields.is_empty() {
             return Err("dt.fields.is_empty()".to_string());
         }
+
+        // 可选的列类型声明: <cell name="lv" type="int">
+        let mut column_types: Vec<(String, ColumnType)> = Vec::new();
+        for cell_node in doc
+            .root_element()
+            .descendants()
+            .filter(|node| node.is_element() && node.tag_name().name() == "cell")
+        {
+            if let (Some(name), Some(type_name)) =
+                (cell_node.attribute("name"), cell_node.attribute("type"))
+            {
+                if column_types.iter().any(|(n, _)| n == name) {
+                    continue;
+                }
+                match ColumnType::from_name(type_name) {
+                    Some(column_type) => column_types.push((name.to_owned(), column_type)),
+                    None => log::error!("column({}) unknown type: {}", name, type_name),
+                }
+            }
+        }
+        dt.set_column_types(column_types);
         let mut row_datas: Vec<Vec<String>> = Vec::new();
         // 遍历 cell 节点
         for data_node in doc
@@ -281,3 +670,333 @@ impl XmlReader {
         Ok(dt)
     }
 }
+
+/// 按 '.' 拆分键值路径, "\." 转义为键名中的 '.', "\\" 转义为 '\'
+fn split_key_path(path: &str) -> Vec<String> {
+    let mut keys = Vec::new();
+    let mut key = String::new();
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(next @ ('.' | '\\')) => key.push(next),
+                Some(next) => {
+                    key.push(c);
+                    key.push(next);
+                }
+                None => key.push(c),
+            },
+            '.' => keys.push(std::mem::take(&mut key)),
+            _ => key.push(c),
+        }
+    }
+    keys.push(key);
+    keys
+}
+
+fn escape_xml(value: &str, out: &mut String) {
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    let value = value.trim();
+    if value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("yes") || value == "1" {
+        Some(true)
+    } else if value.eq_ignore_ascii_case("false")
+        || value.eq_ignore_ascii_case("no")
+        || value == "0"
+    {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUEST_XML: &str = r#"<quest id="7">
+        <step>a</step>
+        <branch>b</branch>
+        <step>c</step>
+        <script><branch>x</branch><step>y</step></script>
+    </quest>"#;
+
+    #[test]
+    fn children_in_document_order() {
+        let reader = XmlReader::read_content(QUEST_XML).unwrap();
+
+        let keys: Vec<(&str, &str)> = reader
+            .children_ordered()
+            .map(|c| (c.key.as_str(), c.value.as_str()))
+            .filter(|(k, _)| *k != "script")
+            .collect();
+        assert_eq!(keys, vec![("step", "a"), ("branch", "b"), ("step", "c")]);
+
+        // 属性不参与有序遍历
+        assert!(reader.children_ordered().all(|c| c.key != "id"));
+        assert_eq!(reader.get_string(vec!["id"], ""), "7");
+
+        let script: Vec<&str> = reader
+            .get_children_ordered(vec!["script"])
+            .unwrap()
+            .iter()
+            .map(|c| c.value.as_str())
+            .collect();
+        assert_eq!(script, vec!["x", "y"]);
+
+        // 原有接口不变
+        assert_eq!(reader.get_children(vec!["step"]).unwrap().len(), 2);
+        assert_eq!(reader.get_string(vec!["step"], ""), "a");
+    }
+    #[test]
+    fn list_values() {
+        let reader = XmlReader::read_content(
+            r#"<cfg ids=" 101; 102 ;103; " empty="">
+                <lv>1, 2,x, 3,</lv>
+                <reward>1:10, 2 : 20 ,bad, 3:30,</reward>
+            </cfg>"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            reader.get_list::<u32>(vec!["ids"], ';'),
+            vec![101, 102, 103]
+        );
+        assert_eq!(reader.get_list::<u32>(vec!["lv"], ','), vec![1, 2, 3]);
+        assert!(reader.get_list::<u32>(vec!["empty"], ',').is_empty());
+        assert!(reader.get_list::<u32>(vec!["missing"], ',').is_empty());
+
+        assert_eq!(
+            reader.get_pairs::<u32, u64>(vec!["reward"], ',', ':'),
+            vec![(1, 10), (2, 20), (3, 30)]
+        );
+        assert!(reader
+            .get_pairs::<u32, u64>(vec!["empty"], ',', ':')
+            .is_empty());
+    }
+
+    #[test]
+    fn typed_values() {
+        let reader = XmlReader::read_content(
+            r#"<cfg a="true" b="FALSE" c="1" d="0" e="Yes" f="no" g=" TRUE " bad="maybe">
+                <rate>0.25</rate>
+                <offset>-42</offset>
+                <big>1e3</big>
+            </cfg>"#,
+        )
+        .unwrap();
+
+        assert!(reader.get_bool(vec!["a"], false));
+        assert!(!reader.get_bool(vec!["b"], true));
+        assert!(reader.get_bool(vec!["c"], false));
+        assert!(!reader.get_bool(vec!["d"], true));
+        assert!(reader.get_bool(vec!["e"], false));
+        assert!(!reader.get_bool(vec!["f"], true));
+        assert!(reader.get_bool(vec!["g"], false));
+        assert!(reader.get_bool(vec!["bad"], true));
+        assert!(!reader.get_bool(vec!["bad"], false));
+        assert!(reader.get_bool(vec!["missing"], true));
+
+        assert_eq!(reader.get_f64(vec!["rate"], 0.0), 0.25);
+        assert_eq!(reader.get_f64(vec!["big"], 0.0), 1000.0);
+        assert_eq!(reader.get_f64(vec!["offset"], 0.0), -42.0);
+        assert_eq!(reader.get_f64(vec!["bad"], 1.5), 1.5);
+        assert_eq!(reader.get_f64(vec!["missing"], 2.5), 2.5);
+
+        assert_eq!(reader.get_i64(vec!["offset"], 0), -42);
+        assert_eq!(reader.get_i64(vec!["rate"], 7), 7);
+        assert_eq!(reader.get_i64(vec!["missing"], -1), -1);
+    }
+
+    #[test]
+    fn key_path() {
+        assert_eq!(
+            split_key_path("server.net.port"),
+            vec!["server", "net", "port"]
+        );
+        assert_eq!(split_key_path("port"), vec!["port"]);
+        assert_eq!(split_key_path(r"a\.b.c"), vec!["a.b", "c"]);
+        assert_eq!(split_key_path(r"a\\.b"), vec![r"a\", "b"]);
+        assert_eq!(split_key_path(r"a\n"), vec![r"a\n"]);
+
+        let reader = XmlReader::read_content(
+            r#"<root>
+                <server><net port="8000" debug="yes" rate="0.5"/></server>
+                <node id="1"/>
+                <node id="2"/>
+            </root>"#,
+        )
+        .unwrap();
+
+        assert_eq!(reader.get_by_path("server.net.port", ""), "8000");
+        assert_eq!(reader.get_u64_by_path("server.net.port", 0), 8000);
+        assert!(reader.get_bool_by_path("server.net.debug", false));
+        assert_eq!(reader.get_f64_by_path("server.net.rate", 0.0), 0.5);
+        assert_eq!(reader.get_typed_by_path::<u16>("server.net.port", 0), 8000);
+        assert_eq!(reader.get_by_path("server.missing.port", "none"), "none");
+        assert_eq!(reader.get_children_by_path("node").unwrap().len(), 2);
+        assert!(reader.get_child_by_path("server.net").is_some());
+        assert!(reader.get_children_by_path("").is_none());
+    }
+
+    fn same_tree(a: &XmlReader, b: &XmlReader) -> bool {
+        let keys_a: hashbrown::HashSet<&String> = a.children.keys().collect();
+        let keys_b: hashbrown::HashSet<&String> = b.children.keys().collect();
+        a.key == b.key
+            && a.value.trim() == b.value.trim()
+            && a.order == b.order
+            && keys_a == keys_b
+            && a.children.iter().all(|(k, v)| {
+                let w = &b.children[k];
+                v.len() == w.len() && v.iter().zip(w).all(|(x, y)| same_tree(x, y))
+            })
+    }
+
+    #[test]
+    fn xml_string_round_trip() {
+        let reader = XmlReader::read_content(QUEST_XML).unwrap();
+        for pretty in [true, false] {
+            let xml = reader.to_xml_string(pretty);
+            let reread = XmlReader::read_content(&xml).unwrap();
+            assert!(same_tree(&reader, &reread), "{}", xml);
+        }
+
+        let reader = XmlReader::read_content(
+            r#"<cfg name="a &amp; b" quote='say "hi"'><text>1 &lt; 2</text><empty/></cfg>"#,
+        )
+        .unwrap();
+        let xml = reader.to_xml_string(false);
+        assert!(xml.contains(r#"name="a &amp; b""#), "{}", xml);
+        assert!(xml.contains("<text>1 &lt; 2</text>"), "{}", xml);
+        let reread = XmlReader::read_content(&xml).unwrap();
+        assert!(same_tree(&reader, &reread));
+        assert_eq!(reread.get_string(vec!["name"], ""), "a & b");
+        assert_eq!(reread.get_string(vec!["quote"], ""), "say \"hi\"");
+        assert_eq!(reread.get_string(vec!["text"], ""), "1 < 2");
+    }
+
+    #[test]
+    fn modify_and_write() {
+        let mut reader = XmlReader::read_content(QUEST_XML).unwrap();
+
+        let mut port = XmlReader::new();
+        assert!(port.set_value(vec![], "9000"));
+        reader.set_child("port", port);
+        reader.set_child("branch", XmlReader::new());
+        assert!(reader.remove_children("script"));
+        assert!(!reader.remove_children("missing"));
+
+        let keys: Vec<&str> = reader.children_ordered().map(|c| c.key.as_str()).collect();
+        assert_eq!(keys, vec!["step", "branch", "step", "port"]);
+
+        let path = std::env::temp_dir().join(format!("xmlreader_{}.xml", std::process::id()));
+        reader.write_file(&path).unwrap();
+        let reread = XmlReader::read_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(same_tree(&reader, &reread));
+        assert_eq!(reread.get_u64(vec!["port"], 0), 9000);
+        assert_eq!(reread.get_string(vec!["id"], ""), "7");
+        assert!(reread.get_child(vec!["script"]).is_none());
+    }
+
+    #[test]
+    fn mutate_by_keys() {
+        let mut reader = XmlReader::read_content(
+            r#"<root>
+                <server port="8000"><name>gs</name></server>
+                <node id="1"/>
+                <node id="2"/>
+                <node id="3"/>
+            </root>"#,
+        )
+        .unwrap();
+
+        assert!(reader.set_value(vec!["server", "port"], "9000"));
+        assert!(reader.set_value(vec!["server", "name"], "a<b>&c"));
+        assert!(!reader.set_value(vec!["server", "missing"], "x"));
+
+        let mut node = XmlReader::new();
+        node.key = "node".to_owned();
+        assert!(node.add_child(vec![], XmlReader::read_content("<tag>t</tag>").unwrap()));
+        assert!(reader.add_child(vec![], node));
+        assert!(!reader.add_child(vec!["missing"], XmlReader::new()));
+
+        let removed = reader.remove_child(vec!["node"], 1).unwrap();
+        assert_eq!(removed.get_string(vec!["id"], ""), "2");
+        assert!(reader.remove_child(vec!["node"], 3).is_none());
+        assert!(reader.remove_child(vec![], 0).is_none());
+        assert!(reader
+            .get_child(vec!["server", "port"])
+            .unwrap()
+            .is_attribute());
+        assert!(!reader
+            .get_child(vec!["server", "name"])
+            .unwrap()
+            .is_attribute());
+
+        let xml = reader.to_xml_string(false);
+        assert!(xml.contains(r#"<server port="9000"><name>a&lt;b&gt;&amp;c</name></server>"#));
+        let reread = XmlReader::read_content(&xml).unwrap();
+        assert!(same_tree(&reader, &reread), "{}", xml);
+        assert_eq!(reread.get_u64(vec!["server", "port"], 0), 9000);
+        assert_eq!(reread.get_string(vec!["server", "name"], ""), "a<b>&c");
+
+        let ids: Vec<String> = reread
+            .children_ordered()
+            .filter(|c| c.key == "node")
+            .map(|c| c.get_string(vec!["id"], "-"))
+            .collect();
+        assert_eq!(ids, vec!["1", "3", "-"]);
+        assert_eq!(
+            reread.get_children(vec!["node"]).unwrap()[2].get_string(vec!["tag"], ""),
+            "t"
+        );
+    }
+
+    #[test]
+    fn streaming_rows() {
+        let mut rows = Vec::new();
+        let mut root = XmlStreamContext::default();
+        XmlReader::read_content_streaming(
+            r#"<table name="item" version="3">
+                <row id="1" name="sword"/>
+                <row id="2" name="shield"><tag>def</tag></row>
+                <meta>ignored by caller</meta>
+            </table>"#,
+            |ctx, row| {
+                root = ctx.clone();
+                if row.key == "row" {
+                    rows.push((row.get_u64(vec!["id"], 0), row.get_string(vec!["name"], "")));
+                }
+            },
+        )
+        .unwrap();
+
+        assert_eq!(root.root_key, "table");
+        assert_eq!(
+            root.attributes,
+            vec![
+                ("name".to_owned(), "item".to_owned()),
+                ("version".to_owned(), "3".to_owned())
+            ]
+        );
+        assert_eq!(
+            rows,
+            vec![(1, "sword".to_owned()), (2, "shield".to_owned())]
+        );
+
+        assert!(XmlReader::read_content_streaming("<broken>", |_, _| {}).is_err());
+    }
+}
@@ -3,17 +3,20 @@
 
 namespace commlib
 {
-	void init_signal_handlers(SignalCallback cb_ctrl_c, SignalCallback cb_usr1, SignalCallback cb_usr2)
+	void init_signal_handlers(SignalCallback cb_ctrl_c, SignalCallback cb_term, SignalCallback cb_hup, SignalCallback cb_usr1, SignalCallback cb_usr2)
 	{
 		signal(SIGINT, cb_ctrl_c);
-		signal(SIGTERM, cb_ctrl_c);
+		signal(SIGTERM, cb_term); // 没有 sig_term 监听时按 sig_int 处理
 		signal(SIGABRT, cb_ctrl_c);
 
 #ifdef _WIN32
+		(void)cb_hup; // windows 没有 SIGHUP
 		signal(SIGBREAK, cb_ctrl_c);
 #else
 		signal(SIGQUIT, cb_ctrl_c);
 
+		signal(SIGHUP, cb_hup); // 重新加载配置
+
 		signal(SIGPIPE, SIG_IGN); // ignore signal
 
 		signal(SIGUSR1, cb_usr1); // 关服
@@ -7,7 +7,7 @@ using SignalCallback = void(*)(int32_t);
 
 namespace commlib
 {
-    void init_signal_handlers(SignalCallback cb_ctrl_c, SignalCallback cb_usr1, SignalCallback cb_usr2);
+    void init_signal_handlers(SignalCallback cb_ctrl_c, SignalCallback cb_term, SignalCallback cb_hup, SignalCallback cb_usr1, SignalCallback cb_usr2);
     //void init_signal_handlers();
 
     void new_abc();